@@ -1,22 +1,273 @@
+use crate::test_mode;
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::collections::HashMap;
 use std::fs;
-use std::io::Read;
 use std::path::Path;
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+
+/// Default number of API requests in flight at once
+pub const DEFAULT_AI_CONCURRENCY: usize = 4;
+
+/// Age at which cached tags are generated again: 30 days
+pub const DEFAULT_MAX_TAG_AGE_SECS: i64 = 30 * 24 * 3600;
+
+/// Model recorded on tag sets created by hand
+const MANUAL_MODEL: &str = "manual";
+
+/// Per-request timeout (local LLMs can be slow)
+const REQUEST_TIMEOUT_SECS: u64 = 60;
+
+/// Anthropic API version sent with every Messages API request
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// Where a local Ollama server listens by default
+const OLLAMA_ADDRESS: &str = "localhost:11434";
+
+/// Wire format of the tagging API
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AiProvider {
+    /// OpenAI chat completions, also spoken by most local LLM servers
+    OpenAi,
+    /// Anthropic Messages API
+    Anthropic,
+    /// Ollama's native /api/chat or /api/generate
+    Ollama,
+}
+
+/// Parse provider name from string
+pub fn parse_provider(s: &str) -> Result<AiProvider> {
+    match s.to_lowercase().as_str() {
+        "openai" => Ok(AiProvider::OpenAi),
+        "anthropic" | "claude" => Ok(AiProvider::Anthropic),
+        "ollama" => Ok(AiProvider::Ollama),
+        _ => anyhow::bail!("Invalid AI provider: {}. Use: openai, anthropic or ollama", s),
+    }
+}
+
+impl AiProvider {
+    /// Provider selected by LSIX_AI_PROVIDER, else guessed from the
+    /// endpoint
+    fn from_env() -> AiProvider {
+        if let Some(provider) = std::env::var("LSIX_AI_PROVIDER")
+            .ok()
+            .and_then(|s| parse_provider(&s).ok())
+        {
+            return provider;
+        }
+        match std::env::var("LSIX_AI_ENDPOINT") {
+            Ok(endpoint) => AiProvider::from_endpoint(&endpoint),
+            Err(_) => AiProvider::OpenAi,
+        }
+    }
+
+    /// Provider an endpoint URL most likely belongs to
+    pub fn from_endpoint(endpoint: &str) -> AiProvider {
+        if endpoint.contains("anthropic.com") {
+            AiProvider::Anthropic
+        } else if endpoint.contains(":11434")
+            || endpoint.ends_with("/api/chat")
+            || endpoint.ends_with("/api/generate")
+        {
+            AiProvider::Ollama
+        } else {
+            AiProvider::OpenAi
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AiProvider::OpenAi => "openai",
+            AiProvider::Anthropic => "anthropic",
+            AiProvider::Ollama => "ollama",
+        }
+    }
+
+    pub fn default_endpoint(self) -> &'static str {
+        match self {
+            AiProvider::OpenAi => "https://api.openai.com/v1/chat/completions",
+            AiProvider::Anthropic => "https://api.anthropic.com/v1/messages",
+            AiProvider::Ollama => "http://localhost:11434/api/chat",
+        }
+    }
+
+    pub fn default_model(self) -> &'static str {
+        match self {
+            AiProvider::OpenAi => "gpt-4o-mini",
+            AiProvider::Anthropic => "claude-haiku-4-5",
+            AiProvider::Ollama => "llava",
+        }
+    }
+
+    /// Whether requests need an API key; local servers do without
+    pub fn needs_api_key(self, endpoint: &str) -> bool {
+        self != AiProvider::Ollama && !endpoint.contains("localhost")
+    }
 
-/// AI tagging configuration
+    /// Request body asking the configured model to answer `prompt` about
+    /// one image
+    fn request_body(
+        self,
+        config: &AITaggingConfig,
+        prompt: &str,
+        image_base64: &str,
+        media_type: &str,
+        max_tokens: u32,
+    ) -> serde_json::Value {
+        let model = &config.model;
+        match self {
+            AiProvider::OpenAi => json!({
+                "model": model,
+                "messages": [
+                    {
+                        "role": "user",
+                        "content": [
+                            {
+                                "type": "text",
+                                "text": prompt
+                            },
+                            {
+                                "type": "image_url",
+                                "image_url": {
+                                    "url": format!("data:{};base64,{}", media_type, image_base64)
+                                }
+                            }
+                        ]
+                    }
+                ],
+                "max_tokens": max_tokens,
+                "temperature": 0.8,
+                "stream": false
+            }),
+            // Anthropic wants the image before the question
+            AiProvider::Anthropic => json!({
+                "model": model,
+                "max_tokens": max_tokens,
+                "messages": [
+                    {
+                        "role": "user",
+                        "content": [
+                            {
+                                "type": "image",
+                                "source": {
+                                    "type": "base64",
+                                    "media_type": media_type,
+                                    "data": image_base64
+                                }
+                            },
+                            {
+                                "type": "text",
+                                "text": prompt
+                            }
+                        ]
+                    }
+                ]
+            }),
+            // Ollama takes bare base64 images next to the text
+            AiProvider::Ollama if config.api_endpoint.ends_with("/api/generate") => json!({
+                "model": model,
+                "prompt": prompt,
+                "images": [image_base64],
+                "stream": false,
+                "options": {
+                    "num_predict": max_tokens,
+                    "temperature": 0.8
+                }
+            }),
+            AiProvider::Ollama => json!({
+                "model": model,
+                "messages": [
+                    {
+                        "role": "user",
+                        "content": prompt,
+                        "images": [image_base64]
+                    }
+                ],
+                "stream": false,
+                "options": {
+                    "num_predict": max_tokens,
+                    "temperature": 0.8
+                }
+            }),
+        }
+    }
+
+    /// Add the authentication headers; local servers may need no key at all
+    fn authorize(self, request: reqwest::RequestBuilder, api_key: &str) -> reqwest::RequestBuilder {
+        match self {
+            AiProvider::OpenAi | AiProvider::Ollama if api_key.is_empty() => request,
+            // Ollama itself has no auth, but proxies in front of it may
+            AiProvider::OpenAi | AiProvider::Ollama => {
+                request.header("Authorization", format!("Bearer {}", api_key))
+            }
+            AiProvider::Anthropic => request
+                .header("x-api-key", api_key)
+                .header("anthropic-version", ANTHROPIC_VERSION),
+        }
+    }
+}
+
+/// Whether an Ollama server answers on its default port. Asked once per
+/// run.
+fn ollama_running() -> bool {
+    use std::net::{TcpStream, ToSocketAddrs};
+    static RUNNING: OnceLock<bool> = OnceLock::new();
+
+    *RUNNING.get_or_init(|| {
+        let Ok(addrs) = OLLAMA_ADDRESS.to_socket_addrs() else {
+            return false;
+        };
+        addrs
+            .into_iter()
+            .any(|addr| TcpStream::connect_timeout(&addr, Duration::from_millis(200)).is_ok())
+    })
+}
+
+/// AI tagging configuration.
+///
+/// `Default` reads `LSIX_AI_PROVIDER`, `LSIX_AI_API_KEY`, `LSIX_AI_ENDPOINT`
+/// and `LSIX_AI_MODEL` and the custom prompt from `tag_prompt.md` in the
+/// config directory; embedders can also fill the fields in directly. It
+/// doesn't look for a local server: `use_running_ollama` does, once tagging
+/// is about to start.
 #[derive(Debug, Clone)]
 pub struct AITaggingConfig {
+    /// API flavor spoken by `api_endpoint` (LSIX_AI_PROVIDER)
+    pub provider: AiProvider,
+    /// Chat completions or Messages endpoint, or a local LLM server
     pub api_endpoint: String,
+    /// API key; may be empty for local endpoints
     pub api_key: String,
+    /// Vision model name sent with each request
     pub model: String,
+    /// Maximum number of tags kept per image
     pub max_tags: usize,
+    /// Where tag results are cached as JSON; `None` disables caching
     pub cache_dir: Option<std::path::PathBuf>,
+    /// Prompt overriding the built-in tagging instructions
     pub custom_prompt: Option<String>,
+    /// Lowercase tags the model must choose from; empty for free tagging
+    pub taxonomy: Vec<String>,
+    /// Print request/response details to stderr
     pub debug: bool,
+    /// Also ask the model for object bounding boxes
+    pub detect_objects: bool,
+    /// Also ask the model for a one-sentence caption
+    pub caption: bool,
+    /// Maximum number of API requests in flight at once
+    pub concurrency: usize,
+    /// Upper bound on API requests started per minute; `None` for no limit
+    pub requests_per_minute: Option<u32>,
+    /// Cached tags older than this many seconds are generated again
+    pub max_tag_age_secs: i64,
+    /// Generate cached tags again when another model produced them
+    pub retag_if_model_changed: bool,
 }
 
 impl Default for AITaggingConfig {
@@ -31,37 +282,70 @@ impl Default for AITaggingConfig {
 
         // Load custom prompt from config file
         let custom_prompt = load_custom_prompt();
+        let provider = AiProvider::from_env();
 
         Self {
+            provider,
             api_endpoint: std::env::var("LSIX_AI_ENDPOINT")
-                .unwrap_or_else(|_| "https://api.openai.com/v1/chat/completions".to_string()),
+                .unwrap_or_else(|_| provider.default_endpoint().to_string()),
             api_key,
             model: std::env::var("LSIX_AI_MODEL").unwrap_or_else(|_| {
-                if is_local {
+                if is_local && provider == AiProvider::OpenAi {
                     "Qwen3VL-8B-Instruct-Q8_0.gguf".to_string()
                 } else {
-                    "gpt-4o-mini".to_string()
+                    provider.default_model().to_string()
                 }
             }),
             max_tags: 10,
-            cache_dir: Some(
-                std::path::PathBuf::from(std::env::var("HOME").unwrap_or_default())
-                    .join(".cache")
-                    .join("lsix")
-                    .join("ai_tags"),
-            ),
+            cache_dir: Some(crate::app_dirs::cache_dir().join("ai_tags")),
             custom_prompt,
+            taxonomy: Vec::new(),
             debug: false, // Default to no debug output
+            detect_objects: false,
+            caption: false,
+            concurrency: DEFAULT_AI_CONCURRENCY,
+            requests_per_minute: None,
+            max_tag_age_secs: DEFAULT_MAX_TAG_AGE_SECS,
+            retag_if_model_changed: false,
+        }
+    }
+}
+
+impl AITaggingConfig {
+    /// With no provider, endpoint or API key in the environment, use a
+    /// local Ollama server if one is running. This connects to it, so call
+    /// it only when images are about to be tagged, before config file
+    /// settings are applied.
+    pub fn use_running_ollama(&mut self) {
+        let configured = ["LSIX_AI_PROVIDER", "LSIX_AI_ENDPOINT"]
+            .iter()
+            .any(|name| std::env::var_os(name).is_some());
+        if configured || !self.api_key.is_empty() || !ollama_running() {
+            return;
+        }
+        self.provider = AiProvider::Ollama;
+        self.api_endpoint = AiProvider::Ollama.default_endpoint().to_string();
+        if std::env::var_os("LSIX_AI_MODEL").is_none() {
+            self.model = AiProvider::Ollama.default_model().to_string();
+        }
+    }
+
+    /// Switch to `provider`, moving the endpoint and model along while they
+    /// are still the previous provider's defaults
+    pub fn set_provider(&mut self, provider: AiProvider) {
+        if self.api_endpoint == self.provider.default_endpoint() {
+            self.api_endpoint = provider.default_endpoint().to_string();
+        }
+        if self.model == self.provider.default_model() {
+            self.model = provider.default_model().to_string();
         }
+        self.provider = provider;
     }
 }
 
-/// Load custom prompt from $HOME/.lsix/tag_prompt.md
+/// Load custom prompt from tag_prompt.md in the config directory
 fn load_custom_prompt() -> Option<String> {
-    let home = std::env::var("HOME").ok()?;
-    let prompt_path = std::path::PathBuf::from(home)
-        .join(".lsix")
-        .join("tag_prompt.md");
+    let prompt_path = crate::app_dirs::config_dir().join("tag_prompt.md");
 
     if !prompt_path.exists() {
         return None;
@@ -90,34 +374,359 @@ fn load_custom_prompt() -> Option<String> {
 /// AI-generated tags for an image
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AITags {
+    /// Lowercase tags, most relevant first
     pub tags: Vec<String>,
-    pub content_rating: Option<String>, // Content rating: "sfw" or "nsfw"
+    /// Content rating: "sfw" or "nsfw"
+    pub content_rating: Option<String>,
+    /// Model-reported confidence, 0.0 to 1.0
     pub confidence: f32,
+    /// Model that produced the tags, as configured
     pub model: String,
+    /// Exact model version the API reported answering with (e.g.
+    /// gpt-4o-2024-08-06), when it reported one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model_version: Option<String>,
+    /// Unix time the tags were generated
     pub timestamp: i64,
+    /// Whether this result was served from the cache
     pub cache_hit: bool,
+    /// Detected objects; `None` if detection was not requested
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub objects: Option<Vec<DetectedObject>>,
+    /// One-sentence description; `None` if no caption was requested
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub caption: Option<String>,
+    /// Confidence the model gave each tag, 0.0 to 1.0. Tags without one
+    /// (added by hand, or by models that gave none) count as certain.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub tag_confidence: HashMap<String, f32>,
+    /// Tags added by hand, kept when the image is tagged again
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub manual_tags: Vec<String>,
+    /// Tags removed by hand, left out when the image is tagged again
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub removed_tags: Vec<String>,
+}
+
+/// An object located in an image
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DetectedObject {
+    /// Lowercase object name
+    pub label: String,
+    /// `[x, y, width, height]` as fractions of the image size (0.0 to 1.0)
+    pub bbox: [f32; 4],
+}
+
+impl AITags {
+    /// Confidence in `tag`, 1.0 when none was given
+    pub fn confidence_of(&self, tag: &str) -> f32 {
+        self.tag_confidence.get(tag).copied().unwrap_or(1.0)
+    }
+
+    /// Whether an object named `label` was detected
+    pub fn has_object(&self, label: &str) -> bool {
+        self.objects
+            .iter()
+            .flatten()
+            .any(|o| o.label.eq_ignore_ascii_case(label.trim()))
+    }
+
+    /// Remember that `tag` was added by hand
+    fn record_added(&mut self, tag: &str) {
+        self.removed_tags.retain(|removed| removed != tag);
+        if !self.manual_tags.iter().any(|manual| manual == tag) {
+            self.manual_tags.push(tag.to_string());
+        }
+    }
+
+    /// Remember that `tag` was removed by hand
+    fn record_removed(&mut self, tag: &str) {
+        self.manual_tags.retain(|manual| manual != tag);
+        if !self.removed_tags.iter().any(|removed| removed == tag) {
+            self.removed_tags.push(tag.to_string());
+        }
+    }
+}
+
+/// Extra instructions appended to the prompt when detecting objects
+const OBJECT_PROMPT: &str = "\n\n\
+    ALSO locate the main objects. After the tag line, add one line per object in the format \
+    'object: <name> <x> <y> <width> <height>', where the numbers are the bounding box as \
+    fractions of the image width and height (0.0 to 1.0, origin top-left).\n\
+    Example:\n\
+    object: person 0.12 0.30 0.25 0.60\n\
+    object: dog 0.55 0.62 0.30 0.30";
+
+/// Extra instructions appended to the prompt with a taxonomy; the allowed
+/// tags follow, comma-separated
+const TAXONOMY_PROMPT: &str = "\n\n\
+    Use ONLY tags from this list, spelled exactly as given; leave out anything the list \
+    doesn't cover: ";
+
+/// Extra instructions appended to every prompt, for the confidence of
+/// each tag
+const CONFIDENCE_PROMPT: &str = "\n\n\
+    After each tag, give your confidence that it applies, from 0.0 to 1.0, in parentheses. \
+    Leave the sfw/nsfw classification without one.\n\
+    Example: 'beach (0.95), sunset (0.7), surfboard (0.4), sfw'";
+
+/// Extra instructions appended to the prompt when captioning
+const CAPTION_PROMPT: &str = "\n\n\
+    ALSO describe the image in one short sentence. After the tag line, add a line in the format \
+    'caption: <sentence>'.\n\
+    Example:\n\
+    caption: A dog chasing a ball across a sunny beach.";
+
+/// Spaces API request starts evenly to stay under a requests-per-minute
+/// limit. Cache hits never reach the limiter.
+pub struct RateLimiter {
+    interval: Option<Duration>,
+    next_slot: tokio::sync::Mutex<Option<Instant>>,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_minute: Option<u32>) -> Self {
+        RateLimiter {
+            interval: requests_per_minute
+                .filter(|&n| n > 0)
+                .map(|n| Duration::from_secs_f64(60.0 / n as f64)),
+            next_slot: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    /// Wait for the next free request slot
+    pub async fn wait(&self) {
+        let Some(interval) = self.interval else {
+            return;
+        };
+        let start = {
+            let mut next_slot = self.next_slot.lock().await;
+            reserve_slot(&mut next_slot, Instant::now(), interval)
+        };
+        tokio::time::sleep_until(start).await;
+    }
+}
+
+/// Take the earliest slot not before `now` and move `next_slot` one
+/// `interval` past it
+fn reserve_slot(next_slot: &mut Option<Instant>, now: Instant, interval: Duration) -> Instant {
+    let start = next_slot.map_or(now, |slot| slot.max(now));
+    *next_slot = Some(start + interval);
+    start
+}
+
+/// HTTP client for the tagging API
+fn api_client() -> Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+        .build()
+        .context("Failed to create HTTP client")
+}
+
+/// Whether cached tags can be reused, or why they are generated again
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CacheStatus {
+    Fresh,
+    /// Never tagged
+    Missing,
+    /// Older than `max_tag_age_secs`
+    Expired,
+    /// Produced by another model, with `retag_if_model_changed`
+    ModelChanged,
+    /// Lacking the objects or caption now asked for
+    Incomplete,
+}
+
+impl CacheStatus {
+    /// What the status means for the image, for the summary before tagging
+    pub fn label(self) -> &'static str {
+        match self {
+            CacheStatus::Fresh => "cached",
+            CacheStatus::Missing => "untagged",
+            CacheStatus::Expired => "expired",
+            CacheStatus::ModelChanged => "tagged by another model",
+            CacheStatus::Incomplete => "missing objects or caption",
+        }
+    }
+}
+
+/// Whether `cached` tags are still good under `config` at unix time `now`.
+/// Entries made by hand never expire or count as another model's.
+pub fn cache_status(cached: &AITags, config: &AITaggingConfig, now: i64) -> CacheStatus {
+    if cached.model != MANUAL_MODEL && now - cached.timestamp >= config.max_tag_age_secs {
+        CacheStatus::Expired
+    } else if config.retag_if_model_changed
+        && cached.model != MANUAL_MODEL
+        && cached.model != config.model
+    {
+        CacheStatus::ModelChanged
+    } else if (config.detect_objects && cached.objects.is_none())
+        || (config.caption && cached.caption.is_none())
+    {
+        CacheStatus::Incomplete
+    } else {
+        CacheStatus::Fresh
+    }
 }
 
-/// Tag a single image using AI
+/// Cache status of each of `image_paths`, counted
+pub fn cache_statuses(
+    image_paths: &[String],
+    config: &AITaggingConfig,
+) -> HashMap<CacheStatus, usize> {
+    let now = test_mode::now().timestamp();
+    let mut counts = HashMap::new();
+    for path in image_paths {
+        let cached = config.cache_dir.as_ref().and_then(|dir| load_cached_tags(dir, path).ok());
+        let status = cached.map_or(CacheStatus::Missing, |cached| cache_status(&cached, config, now));
+        *counts.entry(status).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// Cached tags for `image_path`, if they are still good
+fn fresh_cached_tags(image_path: &str, config: &AITaggingConfig) -> Option<AITags> {
+    let cached = load_cached_tags(config.cache_dir.as_ref()?, image_path).ok()?;
+    let now = test_mode::now().timestamp();
+    (cache_status(&cached, config, now) == CacheStatus::Fresh).then_some(AITags {
+        cache_hit: true,
+        ..cached
+    })
+}
+
+/// Parse an age such as `90d`: a number of hours (h), days (d), weeks (w)
+/// or years (y), in seconds
+pub fn parse_tag_age(s: &str) -> Result<i64> {
+    let s = s.trim().to_lowercase();
+    let invalid = || anyhow::anyhow!("Invalid age: {}. Use e.g. 12h, 90d, 8w or 1y", s);
+    let unit = s.chars().last().ok_or_else(invalid)?;
+    let hours = match unit {
+        'h' => 1,
+        'd' => 24,
+        'w' => 7 * 24,
+        'y' => 365 * 24,
+        _ => return Err(invalid()),
+    };
+    let count: i64 = s[..s.len() - 1].parse().map_err(|_| invalid())?;
+    if count <= 0 {
+        return Err(invalid());
+    }
+    Ok(count.saturating_mul(hours * 3600))
+}
+
+/// Tag a single image using AI (blocking)
 pub fn tag_image_ai(image_path: &str, config: &AITaggingConfig, force: bool) -> Result<AITags> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("Failed to start async runtime")?;
+    let client = api_client()?;
+    runtime.block_on(tag_image_async(
+        &client,
+        image_path,
+        config,
+        force,
+        &RateLimiter::new(None),
+    ))
+}
+
+/// Tag a single image using AI, waiting on `limiter` before calling the API
+pub async fn tag_image_async(
+    client: &reqwest::Client,
+    image_path: &str,
+    config: &AITaggingConfig,
+    force: bool,
+    limiter: &RateLimiter,
+) -> Result<AITags> {
     // Check cache first (unless force is enabled)
     if !force {
-        if let Some(cache_dir) = &config.cache_dir {
-            if let Ok(cached) = load_cached_tags(cache_dir, image_path) {
-                // Verify cache is not too old (30 days)
-                let now = chrono::Utc::now().timestamp();
-                if now - cached.timestamp < 30 * 24 * 3600 {
-                    return Ok(AITags {
-                        cache_hit: true,
-                        ..cached
-                    });
-                }
-            }
+        if let Some(cached) = fresh_cached_tags(image_path, config) {
+            return Ok(cached);
         }
     }
 
+    let request_body = build_request(image_path, config)?;
+    limiter.wait().await;
+
+    let request_builder = client
+        .post(&config.api_endpoint)
+        .header("Content-Type", "application/json");
+
+    let response = config
+        .provider
+        .authorize(request_builder, &config.api_key)
+        .json(&request_body)
+        .send()
+        .await
+        .context("Failed to call AI API")?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let error_text = response.text().await.unwrap_or_default();
+        anyhow::bail!("AI API error ({}): {}", status, error_text);
+    }
+
+    // Parse response
+    let response_json: serde_json::Value =
+        response.json().await.context("Failed to parse AI response")?;
+
+    // Debug output for response
+    if config.debug {
+        eprintln!(
+            "\n╔════════════════════════════════════════════════════════════════════════════╗"
+        );
+        eprintln!(
+            "║                    API Response Debug                                          ║"
+        );
+        eprintln!("╚════════════════════════════════════════════════════════════════════════════╝");
+        eprintln!("\n📥 Status: {}", status);
+        eprintln!("\n📦 Full response JSON:");
+        eprintln!("────────────────────────────────────────────────────────────────");
+        eprintln!(
+            "{}",
+            serde_json::to_string_pretty(&response_json)
+                .unwrap_or_else(|_| "Failed to pretty print".to_string())
+        );
+        eprintln!("────────────────────────────────────────────────────────────────");
+    }
+
+    let mut ai_tags = parse_tags(&response_json, config)?;
+
+    // Save to cache, keeping the ratings, flags and tag edits made by hand
+    // on the old entry
+    if let Some(cache_dir) = &config.cache_dir {
+        if let Ok(old) = load_cached_tags(cache_dir, image_path) {
+            keep_manual_edits(&mut ai_tags, old);
+        }
+        let _ = save_cached_tags(cache_dir, image_path, &ai_tags);
+    }
+
+    Ok(ai_tags)
+}
+
+/// Carry the ratings, flags and tags added or removed by hand on `old` over
+/// to the freshly generated `new` tags
+fn keep_manual_edits(new: &mut AITags, old: AITags) {
+    let culling = old.tags.into_iter().filter(|tag| crate::culling::is_culling_tag(tag));
+    for tag in culling.chain(old.manual_tags.iter().cloned()) {
+        if !new.tags.contains(&tag) {
+            new.tags.push(tag);
+        }
+    }
+    new.tags.retain(|tag| !old.removed_tags.contains(tag));
+    new.tag_confidence.retain(|tag, _| new.tags.contains(tag));
+    for tag in &old.manual_tags {
+        new.tag_confidence.remove(tag);
+    }
+    new.manual_tags = old.manual_tags;
+    new.removed_tags = old.removed_tags;
+}
+
+/// Build the tagging request for one image
+fn build_request(image_path: &str, config: &AITaggingConfig) -> Result<serde_json::Value> {
     // Encode image to base64
     let image_base64 = encode_image_to_base64(image_path)?;
+    let media_type = image_media_type(image_path);
 
     // Prepare API request - use custom prompt if available, otherwise use default
     let prompt = if let Some(custom) = &config.custom_prompt {
@@ -162,6 +771,21 @@ pub fn tag_image_ai(image_path: &str, config: &AITaggingConfig, force: bool) ->
         )
     };
 
+    let mut prompt = prompt + CONFIDENCE_PROMPT;
+    let mut max_tokens = 300;
+    if !config.taxonomy.is_empty() {
+        prompt += TAXONOMY_PROMPT;
+        prompt += &config.taxonomy.join(", ");
+    }
+    if config.detect_objects {
+        prompt += OBJECT_PROMPT;
+        max_tokens += 400;
+    }
+    if config.caption {
+        prompt += CAPTION_PROMPT;
+        max_tokens += 100;
+    }
+
     // Debug output
     if config.debug {
         eprintln!(
@@ -171,7 +795,11 @@ pub fn tag_image_ai(image_path: &str, config: &AITaggingConfig, force: bool) ->
             "║                    API Request Debug                                           ║"
         );
         eprintln!("╚════════════════════════════════════════════════════════════════════════════╝");
-        eprintln!("\n📤 Sending request to: {}", config.api_endpoint);
+        eprintln!(
+            "\n📤 Sending request to: {} ({})",
+            config.api_endpoint,
+            config.provider.name()
+        );
         eprintln!("📝 Model: {}", config.model);
         eprintln!("📄 Image: {}", image_path);
         eprintln!(
@@ -184,48 +812,13 @@ pub fn tag_image_ai(image_path: &str, config: &AITaggingConfig, force: bool) ->
         eprintln!("────────────────────────────────────────────────────────────────");
     }
 
-    let request_body = if config.api_endpoint.contains("openai")
-        || config.api_endpoint.contains("localhost")
-        || config.api_endpoint.contains("v1/chat/completions")
-    {
-        // OpenAI-compatible format (used by most local LLM servers too)
-        json!({
-            "model": config.model,
-            "messages": [
-                {
-                    "role": "user",
-                    "content": [
-                        {
-                            "type": "text",
-                            "text": prompt
-                        },
-                        {
-                            "type": "image_url",
-                            "image_url": {
-                                "url": format!("data:image/png;base64,{}", image_base64)
-                            }
-                        }
-                    ]
-                }
-            ],
-            "max_tokens": 200,
-            "temperature": 0.8,
-            "stream": false
-        })
-    } else {
-        // Generic format (Claude, etc.)
-        json!({
-            "model": config.model,
-            "messages": [
-                {
-                    "role": "user",
-                    "content": prompt
-                }
-            ],
-            "images": [image_base64],
-            "max_tokens": 200
-        })
-    };
+    let request_body = config.provider.request_body(
+        config,
+        &prompt,
+        image_base64.as_str(),
+        media_type,
+        max_tokens,
+    );
 
     // Debug output for request body
     if config.debug {
@@ -245,79 +838,61 @@ pub fn tag_image_ai(image_path: &str, config: &AITaggingConfig, force: bool) ->
         eprintln!("────────────────────────────────────────────────────────────────");
     }
 
-    // Call API
-    let client = reqwest::blocking::Client::builder()
-        .timeout(std::time::Duration::from_secs(60)) // Longer timeout for local LLM
-        .build()?;
-
-    let mut request_builder = client
-        .post(&config.api_endpoint)
-        .header("Content-Type", "application/json");
-
-    // Only add Authorization header if we have an API key
-    if !config.api_key.is_empty() {
-        request_builder =
-            request_builder.header("Authorization", format!("Bearer {}", config.api_key));
-    }
-
-    let response = request_builder
-        .json(&request_body)
-        .send()
-        .context("Failed to call AI API")?;
-
-    let status = response.status();
-    if !status.is_success() {
-        let error_text = response.text().unwrap_or_default();
-        anyhow::bail!("AI API error ({}): {}", status, error_text);
-    }
-
-    // Parse response
-    let response_json: serde_json::Value =
-        response.json().context("Failed to parse AI response")?;
-
-    // Debug output for response
-    if config.debug {
-        eprintln!(
-            "\n╔════════════════════════════════════════════════════════════════════════════╗"
-        );
-        eprintln!(
-            "║                    API Response Debug                                          ║"
-        );
-        eprintln!("╚════════════════════════════════════════════════════════════════════════════╝");
-        eprintln!("\n📥 Status: {}", status);
-        eprintln!("\n📦 Full response JSON:");
-        eprintln!("────────────────────────────────────────────────────────────────");
-        eprintln!(
-            "{}",
-            serde_json::to_string_pretty(&response_json)
-                .unwrap_or_else(|_| "Failed to pretty print".to_string())
-        );
-        eprintln!("────────────────────────────────────────────────────────────────");
-    }
+    Ok(request_body)
+}
 
+/// Turn the API response into tags, content rating and objects
+fn parse_tags(response_json: &serde_json::Value, config: &AITaggingConfig) -> Result<AITags> {
     // Extract tags based on response format
-    let tags_text = extract_tags_from_response(&response_json)?;
+    let response_text = extract_tags_from_response(response_json)?;
+
+    // Object and caption lines are separate from the comma-separated tags
+    let mut objects = Vec::new();
+    let mut caption = None;
+    let mut tag_lines = Vec::new();
+    for line in response_text.lines() {
+        if let Some(text) = parse_caption_line(line) {
+            caption = Some(text);
+            continue;
+        }
+        match parse_object_line(line) {
+            Some(object) => objects.push(object),
+            None if line.to_lowercase().contains("object:") => {}
+            None => tag_lines.push(line),
+        }
+    }
+    let tags_text = tag_lines.join(",");
 
     // Debug output for extracted tags text
     if config.debug {
         eprintln!("\n🔍 Extracted tags text: \"{}\"", tags_text);
     }
 
-    // Parse tags - split by comma and process
+    // Parse tags - split by comma and process, taking off the confidences
+    let mut tag_confidence = HashMap::new();
     let all_parts: Vec<String> = tags_text
         .split(',')
-        .map(|s| s.trim().to_lowercase())
+        .map(|s| {
+            let (tag, confidence) = split_confidence(s);
+            let tag = tag.to_lowercase();
+            if let Some(confidence) = confidence {
+                tag_confidence.insert(tag.clone(), confidence);
+            }
+            tag
+        })
         .filter(|s| !s.is_empty() && s.len() > 2)
         .collect();
 
-    // Separate content classification from regular tags
+    // Separate content classification from regular tags, dropping tags
+    // outside the taxonomy
+    let in_taxonomy = |tag: &String| config.taxonomy.is_empty() || config.taxonomy.contains(tag);
     let mut regular_tags = Vec::new();
     let mut content_classification = None;
 
     for part in all_parts {
         if part == "sfw" || part == "nsfw" {
             content_classification = Some(part);
-        } else if regular_tags.len() < config.max_tags {
+        } else if regular_tags.len() < config.max_tags && in_taxonomy(&part) {
             regular_tags.push(part);
         }
     }
@@ -413,6 +988,12 @@ pub fn tag_image_ai(image_path: &str, config: &AITaggingConfig, force: bool) ->
         if let Some(rating) = &final_content_rating {
             eprintln!("  Content Rating: \"{}\"", rating);
         }
+        for object in &objects {
+            eprintln!("  Object: \"{}\" {:?}", object.label, object.bbox);
+        }
+        if let Some(caption) = &caption {
+            eprintln!("  Caption: \"{}\"", caption);
+        }
         eprintln!(
             "\n╔════════════════════════════════════════════════════════════════════════════╗\n"
         );
@@ -421,37 +1002,54 @@ pub fn tag_image_ai(image_path: &str, config: &AITaggingConfig, force: bool) ->
     if final_tags.is_empty() {
         anyhow::bail!("No tags generated from AI response");
     }
+    tag_confidence.retain(|tag, _| final_tags.contains(tag));
 
-    let ai_tags = AITags {
+    Ok(AITags {
         tags: final_tags,
         content_rating: final_content_rating,
         confidence: 1.0, // AI doesn't always provide confidence
         model: config.model.clone(),
-        timestamp: chrono::Utc::now().timestamp(),
+        model_version: response_json.get("model").and_then(|m| m.as_str()).map(String::from),
+        timestamp: test_mode::now().timestamp(),
         cache_hit: false,
-    };
+        objects: config.detect_objects.then_some(objects),
+        caption: if config.caption { caption } else { None },
+        tag_confidence,
+        manual_tags: Vec::new(),
+        removed_tags: Vec::new(),
+    })
+}
 
-    // Save to cache
-    if let Some(cache_dir) = &config.cache_dir {
-        let _ = save_cached_tags(cache_dir, image_path, &ai_tags);
+/// A tag from the model's reply and the confidence after it, as in
+/// `beach (0.95)`; out-of-range confidences are clamped
+fn split_confidence(part: &str) -> (&str, Option<f32>) {
+    let part = part.trim();
+    let parsed = part
+        .strip_suffix(')')
+        .and_then(|rest| rest.rsplit_once('('))
+        .and_then(|(tag, value)| Some((tag.trim(), value.trim().parse::<f32>().ok()?)));
+    match parsed {
+        Some((tag, confidence)) => (tag, Some(confidence.clamp(0.0, 1.0))),
+        None => (part, None),
     }
-
-    Ok(ai_tags)
 }
 
-/// Tag multiple images in parallel
+/// Tag multiple images concurrently: at most `config.concurrency` requests
+/// in flight, started no faster than `config.requests_per_minute`. Ctrl-C
+/// stops scheduling, aborts the requests in flight and returns what has been
+/// tagged so far (finished results are already cached).
 pub fn tag_images_parallel(
     image_paths: &[String],
     config: &AITaggingConfig,
     force: bool,
 ) -> Result<HashMap<String, AITags>> {
-    use rayon::prelude::*;
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .context("Failed to start async runtime")?;
 
     // Create progress bar
-    let progress = Arc::new(Mutex::new(indicatif::ProgressBar::new(
-        image_paths.len() as u64
-    )));
-    let pb = progress.lock().unwrap();
+    let pb = indicatif::ProgressBar::new(image_paths.len() as u64);
     pb.set_style(
         indicatif::ProgressStyle::default_bar()
             .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} {msg}")
@@ -463,32 +1061,69 @@ pub fn tag_images_parallel(
     } else {
         "Initializing..."
     });
-    drop(pb);
-
-    let results: Vec<(String, Result<AITags>)> = image_paths
-        .par_iter()
-        .map(|path| {
-            let result = tag_image_ai(path, config, force);
-
-            // Update progress
-            if let Ok(ref _tags) = result {
-                let pb = progress.lock().unwrap();
-                let filename = Path::new(path)
-                    .file_name()
-                    .and_then(|n| n.to_str())
-                    .unwrap_or(path);
-                pb.set_message(format!("Processing: {}", filename));
-                pb.inc(1);
-            }
 
-            (path.clone(), result)
-        })
-        .collect();
+    let client = api_client()?;
+    let config = Arc::new(config.clone());
+    let limiter = Arc::new(RateLimiter::new(config.requests_per_minute));
+    let permits = Arc::new(Semaphore::new(config.concurrency.max(1)));
+
+    // Keeps an earlier interruptible phase's Ctrl-C watcher from exiting
+    let _interrupt = crate::interrupt::guard();
+    let (results, cancelled) = runtime.block_on(async {
+        let mut tasks = JoinSet::new();
+        for path in image_paths {
+            let (client, config, limiter, permits) =
+                (client.clone(), config.clone(), limiter.clone(), permits.clone());
+            let path = path.clone();
+            tasks.spawn(async move {
+                let result = match permits.acquire_owned().await {
+                    Ok(_permit) => tag_image_async(&client, &path, &config, force, &limiter).await,
+                    Err(e) => Err(e.into()),
+                };
+                (path, result)
+            });
+        }
+
+        let mut results: Vec<(String, Result<AITags>)> = Vec::new();
+        let ctrl_c = tokio::signal::ctrl_c();
+        tokio::pin!(ctrl_c);
+        loop {
+            tokio::select! {
+                joined = tasks.join_next() => match joined {
+                    Some(Ok((path, result))) => {
+                        // Update progress
+                        if result.is_ok() {
+                            let filename = Path::new(&path)
+                                .file_name()
+                                .and_then(|n| n.to_str())
+                                .unwrap_or(&path);
+                            pb.set_message(format!("Processing: {}", filename));
+                            pb.inc(1);
+                        }
+                        results.push((path, result));
+                    }
+                    Some(Err(_)) => {} // Aborted or panicked task
+                    None => return (results, false),
+                },
+                _ = &mut ctrl_c => {
+                    tasks.abort_all();
+                    return (results, true);
+                }
+            }
+        }
+    });
 
     // Finish progress bar
-    let pb = progress.lock().unwrap();
-    pb.finish_with_message("AI tagging complete!");
-    drop(pb);
+    if cancelled {
+        pb.abandon_with_message("AI tagging cancelled");
+        eprintln!(
+            "\n⚠️  Cancelled: {} of {} images were not tagged",
+            image_paths.len() - results.len(),
+            image_paths.len()
+        );
+    } else {
+        pb.finish_with_message("AI tagging complete!");
+    }
 
     // Print summary
     let mut tags_map = HashMap::new();
@@ -526,21 +1161,28 @@ pub fn tag_images_parallel(
 }
 
 /// Encode image file to base64
-fn encode_image_to_base64(image_path: &str) -> Result<String> {
+pub(crate) fn encode_image_to_base64(image_path: &str) -> Result<String> {
     // Check file size (limit to 20MB for API)
     let metadata = fs::metadata(image_path)?;
     if metadata.len() > 20 * 1024 * 1024 {
         anyhow::bail!("Image too large for AI analysis (max 20MB)");
     }
 
-    // Read file
-    let mut file = fs::File::open(image_path)?;
-    let mut buffer = Vec::new();
-    file.read_to_end(&mut buffer)?;
+    crate::blob_cache::base64_of(std::path::Path::new(image_path))
+}
 
-    // Encode to base64
-    use base64::Engine;
-    Ok(base64::engine::general_purpose::STANDARD.encode(&buffer))
+/// MIME type of an image for the API, sniffed from its header
+pub(crate) fn image_media_type(image_path: &str) -> &'static str {
+    let format = image::ImageReader::open(image_path)
+        .and_then(|reader| reader.with_guessed_format())
+        .ok()
+        .and_then(|reader| reader.format());
+    match format {
+        Some(image::ImageFormat::Jpeg) => "image/jpeg",
+        Some(image::ImageFormat::Gif) => "image/gif",
+        Some(image::ImageFormat::WebP) => "image/webp",
+        _ => "image/png",
+    }
 }
 
 /// Extract tags from different AI response formats
@@ -558,6 +1200,30 @@ fn extract_tags_from_response(response: &serde_json::Value) -> Result<String> {
         }
     }
 
+    // Try Ollama formats: /api/chat, then /api/generate
+    if let Some(text) = response
+        .get("message")
+        .and_then(|m| m.get("content"))
+        .and_then(|c| c.as_str())
+    {
+        return Ok(text.to_string());
+    }
+    if let Some(text) = response.get("response").and_then(|r| r.as_str()) {
+        return Ok(text.to_string());
+    }
+
+    // Try Anthropic format: a list of content blocks
+    if let Some(blocks) = response.get("content").and_then(|c| c.as_array()) {
+        let text: Vec<&str> = blocks
+            .iter()
+            .filter(|block| block.get("type").and_then(|t| t.as_str()) == Some("text"))
+            .filter_map(|block| block.get("text").and_then(|t| t.as_str()))
+            .collect();
+        if !text.is_empty() {
+            return Ok(text.join("\n"));
+        }
+    }
+
     // Try generic format
     if let Some(content) = response.get("content") {
         if let Some(text) = content.as_str() {
@@ -569,72 +1235,297 @@ fn extract_tags_from_response(response: &serde_json::Value) -> Result<String> {
     Ok(response.to_string())
 }
 
-/// Cache file path for an image
-fn cache_file_path(cache_dir: &std::path::Path, image_path: &str) -> std::path::PathBuf {
-    use std::collections::hash_map::DefaultHasher;
-    use std::hash::{Hash, Hasher};
+/// Cache file holding the tags of images whose contents hash to `hash`
+fn cache_file_path(cache_dir: &Path, hash: &str) -> std::path::PathBuf {
+    cache_dir.join(format!("{}.json", hash))
+}
 
-    let mut hasher = DefaultHasher::new();
-    image_path.hash(&mut hasher);
-    let hash = format!("{:x}", hasher.finish());
+/// Path index entry: the content hash of a file as of its size and
+/// modification time
+#[derive(Debug, Serialize, Deserialize)]
+struct PathIndexEntry {
+    size: u64,
+    /// Modification time, nanoseconds since the epoch
+    modified: u64,
+    hash: String,
+}
 
-    cache_dir.join(format!("{}.json", hash))
+/// Path index file for `image_path`, so unchanged files aren't re-hashed on
+/// every lookup
+fn path_index_path(cache_dir: &Path, image_path: &str) -> std::path::PathBuf {
+    cache_dir
+        .join("paths")
+        .join(crate::blob_cache::content_hash(image_path.as_bytes()))
 }
 
-/// Generate alternative cache paths for lookup (try different path formats)
-fn get_cache_paths_to_try(
-    cache_dir: &std::path::Path,
-    image_path: &str,
-) -> Vec<std::path::PathBuf> {
-    let mut paths_to_try = Vec::new();
+/// SHA-256 of the contents of `image_path`, from the path index while the
+/// file is unchanged. Tags are cached under this key, so they follow a file
+/// that is moved or renamed, and copies share one entry.
+pub(crate) fn content_key(cache_dir: &Path, image_path: &str) -> Result<String> {
+    let image_path = image_path.trim_end_matches("[0]");
+    let (size, modified) = crate::hash_index::file_stamp(image_path)
+        .with_context(|| format!("Failed to stat {}", image_path))?;
+    let index_path = path_index_path(cache_dir, image_path);
+    if let Some(entry) = fs::read_to_string(&index_path)
+        .ok()
+        .and_then(|json| serde_json::from_str::<PathIndexEntry>(&json).ok())
+    {
+        if entry.size == size && entry.modified == modified {
+            return Ok(entry.hash);
+        }
+    }
 
-    // Try exact path first
-    paths_to_try.push(cache_file_path(cache_dir, image_path));
+    let data = fs::read(image_path).with_context(|| format!("Failed to read {}", image_path))?;
+    let hash = crate::blob_cache::content_hash(&data);
+    let entry = PathIndexEntry {
+        size,
+        modified,
+        hash: hash.clone(),
+    };
+    if let Some(parent) = index_path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    let _ = write_cache_file(&index_path, &serde_json::to_string(&entry)?);
+    Ok(hash)
+}
 
-    // Try with just filename (in case path was different when cached)
-    if let Some(filename) = std::path::Path::new(image_path).file_name() {
-        if let Some(filename_str) = filename.to_str() {
-            paths_to_try.push(cache_file_path(cache_dir, filename_str));
+/// Cache file an older version wrote for `key`, keyed by the path string
+fn legacy_cache_file_path(cache_dir: &Path, key: &str) -> std::path::PathBuf {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
 
-            // Try with ./ prefix
-            paths_to_try.push(cache_file_path(cache_dir, &format!("./{}", filename_str)));
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    cache_dir.join(format!("{:x}.json", hasher.finish()))
+}
+
+/// Path-keyed cache files older versions may have written for an image.
+/// The flag marks entries keyed by this very file (its path or a relative
+/// spelling of it), which are migrated; entries keyed by the bare file name
+/// may belong to another file of the same name, so they are only read.
+fn legacy_cache_paths(cache_dir: &Path, image_path: &str) -> Vec<(std::path::PathBuf, bool)> {
+    let mut paths_to_try = vec![(legacy_cache_file_path(cache_dir, image_path), true)];
+
+    // Relative spellings of a canonical path
+    if Path::new(image_path).is_absolute() {
+        for legacy in crate::paths::legacy_forms(image_path) {
+            paths_to_try.push((legacy_cache_file_path(cache_dir, &legacy), true));
         }
     }
 
+    // Just the file name, in case the path was different when cached
+    if let Some(filename) = Path::new(image_path).file_name().and_then(|n| n.to_str()) {
+        paths_to_try.push((legacy_cache_file_path(cache_dir, filename), false));
+        paths_to_try.push((legacy_cache_file_path(cache_dir, &format!("./{}", filename)), false));
+    }
+
     paths_to_try
 }
 
-/// Load cached tags from disk
-pub fn load_cached_tags(cache_dir: &std::path::Path, image_path: &str) -> Result<AITags> {
-    // Try multiple possible cache paths
-    let paths_to_try = get_cache_paths_to_try(cache_dir, image_path);
+/// Load cached tags from disk. Tags found under an old key for this file's
+/// path are migrated to the content key.
+pub fn load_cached_tags(cache_dir: &Path, image_path: &str) -> Result<AITags> {
+    let hash = content_key(cache_dir, image_path)?;
+    let cache_path = cache_file_path(cache_dir, &hash);
+    if cache_path.exists() {
+        let cached_json = fs::read_to_string(&cache_path)?;
+        return Ok(serde_json::from_str(&cached_json)?);
+    }
 
-    for cache_path in &paths_to_try {
-        if cache_path.exists() {
-            let cached_json = fs::read_to_string(&cache_path)?;
+    let paths_to_try = legacy_cache_paths(cache_dir, image_path);
+    for (legacy_path, owned) in &paths_to_try {
+        if let Ok(cached_json) = fs::read_to_string(legacy_path) {
             let tags: AITags = serde_json::from_str(&cached_json)?;
+            // Migrating another file's entry would stick its tags to this
+            // content and every copy of it for good
+            if *owned && write_cache_file(&cache_path, &cached_json).is_ok() {
+                let _ = fs::remove_file(legacy_path);
+            }
             return Ok(tags);
         }
     }
 
     anyhow::bail!(
-        "Cache not found (tried {} path formats)",
+        "Cache not found (tried content hash and {} legacy keys)",
         paths_to_try.len()
     )
 }
 
 /// Save tags to cache
-fn save_cached_tags(cache_dir: &std::path::Path, image_path: &str, tags: &AITags) -> Result<()> {
+pub(crate) fn save_cached_tags(cache_dir: &Path, image_path: &str, tags: &AITags) -> Result<()> {
     // Ensure cache directory exists
     if !cache_dir.exists() {
         fs::create_dir_all(cache_dir)?;
     }
 
-    let cache_path = cache_file_path(cache_dir, image_path);
+    let cache_path = cache_file_path(cache_dir, &content_key(cache_dir, image_path)?);
     let cached_json = serde_json::to_string_pretty(tags)?;
-    fs::write(&cache_path, cached_json)?;
+    write_cache_file(&cache_path, &cached_json)
+}
 
-    Ok(())
+/// Write a cache file through a temp file and a rename: copies of an image
+/// share one entry and may be tagged at the same time, and a reader must
+/// never see the file half-written
+fn write_cache_file(path: &Path, contents: &str) -> Result<()> {
+    static NEXT_TMP: AtomicUsize = AtomicUsize::new(0);
+
+    let tmp = path.with_extension(format!(
+        "{}.{}.tmp",
+        std::process::id(),
+        NEXT_TMP.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::write(&tmp, contents).with_context(|| format!("Failed to write {}", tmp.display()))?;
+    fs::rename(&tmp, path).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Tags as stored: trimmed and lowercase
+fn normalize_tag(tag: &str) -> String {
+    tag.trim().to_lowercase()
+}
+
+/// Tags from repeated and comma-separated arguments, normalized, without
+/// empty entries
+pub fn split_tags(args: &[String]) -> Vec<String> {
+    args.iter()
+        .flat_map(|arg| arg.split(','))
+        .map(normalize_tag)
+        .filter(|tag| !tag.is_empty())
+        .collect()
+}
+
+/// Parse an `OLD=NEW` tag rename
+pub fn parse_tag_rename(s: &str) -> Result<(String, String)> {
+    let (from, to) = s
+        .split_once('=')
+        .ok_or_else(|| anyhow::anyhow!("Invalid tag rename: {}. Use OLD=NEW", s))?;
+    let (from, to) = (normalize_tag(from), normalize_tag(to));
+    if from.is_empty() || to.is_empty() {
+        anyhow::bail!("Invalid tag rename: {}. Use OLD=NEW", s);
+    }
+    Ok((from, to))
+}
+
+/// Add tags by hand to an image's cached tag set, creating an entry if the
+/// image was never tagged. Returns the updated tags.
+pub fn add_manual_tags(cache_dir: &Path, image_path: &str, new_tags: &[String]) -> Result<AITags> {
+    let mut tags = load_cached_tags(cache_dir, image_path).unwrap_or_else(|_| AITags {
+        tags: Vec::new(),
+        content_rating: None,
+        confidence: 1.0,
+        model: MANUAL_MODEL.to_string(),
+        model_version: None,
+        timestamp: test_mode::now().timestamp(),
+        cache_hit: false,
+        objects: None,
+        caption: None,
+        tag_confidence: HashMap::new(),
+        manual_tags: Vec::new(),
+        removed_tags: Vec::new(),
+    });
+
+    for tag in new_tags {
+        let tag = normalize_tag(tag);
+        if tag.is_empty() {
+            continue;
+        }
+        // Adding a tag the model was unsure of confirms it
+        tags.tag_confidence.remove(&tag);
+        tags.record_added(&tag);
+        if !tags.tags.contains(&tag) {
+            tags.tags.push(tag);
+        }
+    }
+
+    save_cached_tags(cache_dir, image_path, &tags)?;
+    Ok(tags)
+}
+
+/// Remove tags from an image's cached tag set. Returns whether any tag was
+/// removed; images without cached tags are left alone.
+pub fn remove_manual_tags(cache_dir: &Path, image_path: &str, old_tags: &[String]) -> Result<bool> {
+    let Ok(mut tags) = load_cached_tags(cache_dir, image_path) else {
+        return Ok(false);
+    };
+    let old_tags: Vec<String> = old_tags.iter().map(|t| normalize_tag(t)).collect();
+    let before = tags.tags.len();
+    tags.tags.retain(|tag| !old_tags.contains(tag));
+    if tags.tags.len() == before {
+        return Ok(false);
+    }
+    tags.tag_confidence.retain(|tag, _| !old_tags.contains(tag));
+    for tag in &old_tags {
+        tags.record_removed(tag);
+    }
+
+    save_cached_tags(cache_dir, image_path, &tags)?;
+    Ok(true)
+}
+
+/// Rename tag `from` to `to` in an image's cached tag set, keeping its
+/// place in the list (or dropping it if the image already has `to`).
+/// Returns whether the image had the tag.
+pub fn rename_manual_tag(cache_dir: &Path, image_path: &str, from: &str, to: &str) -> Result<bool> {
+    let Ok(mut tags) = load_cached_tags(cache_dir, image_path) else {
+        return Ok(false);
+    };
+    let (from, to) = (normalize_tag(from), normalize_tag(to));
+    let Some(index) = tags.tags.iter().position(|tag| *tag == from) else {
+        return Ok(false);
+    };
+    let confidence = tags.tag_confidence.remove(&from);
+    if tags.tags.contains(&to) {
+        tags.tags.remove(index);
+    } else {
+        if let Some(confidence) = confidence {
+            tags.tag_confidence.insert(to.clone(), confidence);
+        }
+        tags.tags[index] = to.clone();
+    }
+    tags.record_removed(&from);
+    tags.record_added(&to);
+
+    save_cached_tags(cache_dir, image_path, &tags)?;
+    Ok(true)
+}
+
+/// Parse an `object: <name> <x> <y> <width> <height>` line. Coordinates are
+/// clamped to the image; lines with malformed or empty boxes are rejected.
+pub fn parse_object_line(line: &str) -> Option<DetectedObject> {
+    let line = line.trim().trim_start_matches(['-', '*']).trim_start();
+    let rest = line
+        .get(..7)
+        .filter(|p| p.eq_ignore_ascii_case("object:"))
+        .map(|_| &line[7..])?;
+
+    let parts: Vec<&str> = rest.split_whitespace().collect();
+    if parts.len() < 5 {
+        return None;
+    }
+    let (name, coords) = parts.split_at(parts.len() - 4);
+    let mut bbox = [0.0f32; 4];
+    for (value, part) in bbox.iter_mut().zip(coords) {
+        *value = part.trim_matches(',').parse::<f32>().ok()?.clamp(0.0, 1.0);
+    }
+    bbox[2] = bbox[2].min(1.0 - bbox[0]);
+    bbox[3] = bbox[3].min(1.0 - bbox[1]);
+    if bbox[2] <= 0.0 || bbox[3] <= 0.0 {
+        return None;
+    }
+
+    Some(DetectedObject {
+        label: name.join(" ").to_lowercase(),
+        bbox,
+    })
+}
+
+/// Parse a `caption: <sentence>` line; empty captions are rejected
+pub fn parse_caption_line(line: &str) -> Option<String> {
+    let line = line.trim().trim_start_matches(['-', '*']).trim_start();
+    let caption = line
+        .get(..8)
+        .filter(|p| p.eq_ignore_ascii_case("caption:"))
+        .map(|_| line[8..].trim().trim_matches(['"', '\'']).trim())?;
+    (!caption.is_empty()).then(|| caption.to_string())
 }
 
 /// Clear AI tag cache
@@ -651,12 +1542,274 @@ pub fn clear_ai_cache(config: &AITaggingConfig) -> Result<()> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_dir::TestDir;
 
     #[test]
     fn test_cache_file_path() {
         let config = AITaggingConfig::default();
         let cache_dir = config.cache_dir.unwrap();
-        let path = cache_file_path(&cache_dir, "/home/user/photo.jpg");
-        assert!(path.ends_with(".json"));
+        let hash = crate::blob_cache::content_hash(b"photo");
+        let path = cache_file_path(&cache_dir, &hash);
+        assert_eq!(path.extension().and_then(|e| e.to_str()), Some("json"));
+        assert_eq!(path.parent(), Some(cache_dir.as_path()));
+    }
+
+    #[test]
+    fn test_content_keyed_cache() {
+        let dir = TestDir::new("ai-cache");
+        let cache_dir = dir.join("cache");
+        fs::create_dir_all(&cache_dir).unwrap();
+        let image = dir.join("a.jpg").to_string_lossy().to_string();
+        let copy = dir.join("b.jpg").to_string_lossy().to_string();
+        fs::write(&image, b"image data").unwrap();
+
+        // An entry written by an older version under the path key is migrated
+        let legacy = legacy_cache_file_path(&cache_dir, &image);
+        fs::write(&legacy, r#"{"tags":["beach"],"content_rating":null,"confidence":0.9,
+            "model":"m","timestamp":0,"cache_hit":false}"#)
+            .unwrap();
+        assert_eq!(load_cached_tags(&cache_dir, &image).unwrap().tags, ["beach"]);
+        assert!(!legacy.exists());
+
+        // A copy has the same content, so it shares the entry
+        fs::copy(&image, &copy).unwrap();
+        assert_eq!(load_cached_tags(&cache_dir, &copy).unwrap().tags, ["beach"]);
+
+        // Changed content no longer matches
+        fs::write(&image, b"edited").unwrap();
+        assert!(load_cached_tags(&cache_dir, &image).is_err());
+
+        // An entry under the bare file name is read but not migrated
+        let by_name = legacy_cache_file_path(&cache_dir, "a.jpg");
+        fs::write(&by_name, r#"{"tags":["cat"],"content_rating":null,"confidence":0.9,
+            "model":"m","timestamp":0,"cache_hit":false}"#)
+            .unwrap();
+        assert_eq!(load_cached_tags(&cache_dir, &image).unwrap().tags, ["cat"]);
+        let hash = content_key(&cache_dir, &image).unwrap();
+        assert!(by_name.exists() && !cache_file_path(&cache_dir, &hash).exists());
+    }
+
+    #[test]
+    fn test_manual_tag_editing() {
+        let dir = TestDir::new("manual-tags");
+        let cache_dir = dir.join("cache");
+        fs::create_dir_all(&cache_dir).unwrap();
+        let image = dir.join("a.jpg").to_string_lossy().to_string();
+        fs::write(&image, b"image data").unwrap();
+
+        let tags = split_tags(&["Seaside, sunset".to_string(), " dog ".to_string()]);
+        assert_eq!(tags, ["seaside", "sunset", "dog"]);
+        add_manual_tags(&cache_dir, &image, &tags).unwrap();
+
+        assert!(rename_manual_tag(&cache_dir, &image, "seaside", "beach").unwrap());
+        assert!(remove_manual_tags(&cache_dir, &image, &["DOG".to_string()]).unwrap());
+        assert!(!remove_manual_tags(&cache_dir, &image, &["cat".to_string()]).unwrap());
+        assert_eq!(load_cached_tags(&cache_dir, &image).unwrap().tags, ["beach", "sunset"]);
+
+        // Renaming onto a tag the image already has merges the two
+        assert!(rename_manual_tag(&cache_dir, &image, "sunset", "beach").unwrap());
+        let edited = load_cached_tags(&cache_dir, &image).unwrap();
+        assert_eq!(edited.tags, ["beach"]);
+        assert_eq!(edited.manual_tags, ["beach"]);
+        assert_eq!(edited.removed_tags, ["seaside", "dog", "sunset"]);
+
+        // Tagging again keeps the edits: added tags stay, removed ones stay out
+        let mut retagged: AITags = serde_json::from_value(json!({"tags": ["dog", "sand"],
+            "content_rating": null, "confidence": 1.0, "model": "llava",
+            "timestamp": 0, "cache_hit": false, "tag_confidence": {"dog": 0.4}}))
+        .unwrap();
+        keep_manual_edits(&mut retagged, edited);
+        assert_eq!(retagged.tags, ["sand", "beach"]);
+        assert!(retagged.tag_confidence.is_empty());
+        assert_eq!(retagged.removed_tags, ["seaside", "dog", "sunset"]);
+
+        assert_eq!(
+            parse_tag_rename("Seaside=Beach").unwrap(),
+            ("seaside".to_string(), "beach".to_string())
+        );
+        assert!(parse_tag_rename("beach").is_err());
+        assert!(parse_tag_rename("=beach").is_err());
+    }
+
+    #[test]
+    fn test_parse_object_line() {
+        let obj = parse_object_line("object: Traffic Light 0.5 0.25 0.75 0.5").unwrap();
+        assert_eq!(obj.label, "traffic light");
+        // Width is clipped to the right edge
+        assert_eq!(obj.bbox, [0.5, 0.25, 0.5, 0.5]);
+
+        assert!(parse_object_line("- object: cat 0.1 0.1 0.2 0.2").is_some());
+        assert!(parse_object_line("object: cat 0.1 0.1 0 0.2").is_none());
+        assert!(parse_object_line("object: cat 0.1 0.1").is_none());
+        assert!(parse_object_line("cat, dog, sfw").is_none());
+    }
+
+    #[test]
+    fn test_parse_caption() {
+        assert_eq!(
+            parse_caption_line("- Caption: \"A dog on a beach.\"").as_deref(),
+            Some("A dog on a beach.")
+        );
+        assert!(parse_caption_line("caption:").is_none());
+
+        let config = AITaggingConfig {
+            caption: true,
+            ..AITaggingConfig::default()
+        };
+        let response = serde_json::json!({
+            "choices": [{"message": {"content":
+                "beach, sunset, sfw\ncaption: A calm beach at dusk."}}]
+        });
+        let tags = parse_tags(&response, &config).unwrap();
+        assert_eq!(tags.tags, vec!["beach", "sunset"]);
+        assert_eq!(tags.caption.as_deref(), Some("A calm beach at dusk."));
+    }
+
+    #[test]
+    fn test_taxonomy_limits_tags() {
+        let config = AITaggingConfig {
+            taxonomy: vec!["cat".to_string(), "sofa".to_string()],
+            ..AITaggingConfig::default()
+        };
+        let response = json!({"choices": [{"message": {"content": "Cat, kitten, sofa, sfw"}}]});
+        let tags = parse_tags(&response, &config).unwrap();
+        assert_eq!(tags.tags, vec!["cat", "sofa"]);
+        assert_eq!(tags.content_rating.as_deref(), Some("sfw"));
+    }
+
+    #[test]
+    fn test_tag_confidence() {
+        let response = json!({"choices": [{"message": {"content":
+            "Beach (0.95), surfboard (0.3), sunset(2), dog, sfw"}}]});
+        let tags = parse_tags(&response, &AITaggingConfig::default()).unwrap();
+        assert_eq!(tags.tags, vec!["beach", "surfboard", "sunset", "dog"]);
+        assert_eq!(tags.confidence_of("beach"), 0.95);
+        assert_eq!(tags.confidence_of("surfboard"), 0.3);
+        assert_eq!(tags.confidence_of("sunset"), 1.0);
+        assert_eq!(tags.confidence_of("dog"), 1.0);
+        assert_eq!(tags.tag_confidence.len(), 3);
+        assert_eq!(split_confidence("tree (tall)"), ("tree (tall)", None));
+    }
+
+    #[test]
+    fn test_cache_status() {
+        assert_eq!(parse_tag_age("90d").unwrap(), 90 * 24 * 3600);
+        assert_eq!(parse_tag_age("2W").unwrap(), 14 * 24 * 3600);
+        assert!(parse_tag_age("90").is_err() && parse_tag_age("0d").is_err());
+
+        let config = AITaggingConfig { model: "gpt-4o".to_string(), ..AITaggingConfig::default() };
+        let day = 24 * 3600;
+        let cached: AITags = serde_json::from_value(json!({"tags": ["beach"],
+            "content_rating": null, "confidence": 1.0, "model": "llava",
+            "timestamp": 0, "cache_hit": false}))
+        .unwrap();
+        assert_eq!(cache_status(&cached, &config, 29 * day), CacheStatus::Fresh);
+        assert_eq!(cache_status(&cached, &config, 30 * day), CacheStatus::Expired);
+
+        let config = AITaggingConfig { retag_if_model_changed: true, ..config };
+        assert_eq!(cache_status(&cached, &config, day), CacheStatus::ModelChanged);
+        let manual = AITags { model: MANUAL_MODEL.to_string(), ..cached.clone() };
+        assert_eq!(cache_status(&manual, &config, day), CacheStatus::Fresh);
+        // Tags entered by hand don't expire
+        assert_eq!(cache_status(&manual, &config, 365 * day), CacheStatus::Fresh);
+
+        let config = AITaggingConfig { max_tag_age_secs: 90 * day, caption: true, ..config };
+        let same_model = AITags { model: "gpt-4o".to_string(), ..cached };
+        assert_eq!(cache_status(&same_model, &config, 60 * day), CacheStatus::Incomplete);
+    }
+
+    #[test]
+    fn test_reserve_slot() {
+        let now = Instant::now();
+        let interval = Duration::from_secs(1);
+        let mut next_slot = None;
+
+        assert_eq!(reserve_slot(&mut next_slot, now, interval), now);
+        // Requests arriving together are spaced one interval apart
+        assert_eq!(reserve_slot(&mut next_slot, now, interval), now + interval);
+        // After an idle period the next request starts right away
+        let later = now + Duration::from_secs(10);
+        assert_eq!(reserve_slot(&mut next_slot, later, interval), later);
+    }
+
+    #[test]
+    fn test_anthropic_request_body() {
+        let config = AITaggingConfig {
+            model: "claude".to_string(),
+            ..AITaggingConfig::default()
+        };
+        let body =
+            AiProvider::Anthropic.request_body(&config, "tag it", "AAAA", "image/jpeg", 200);
+        assert_eq!(body["max_tokens"], 200);
+        let content = &body["messages"][0]["content"];
+        assert_eq!(content[0]["type"], "image");
+        assert_eq!(content[0]["source"]["media_type"], "image/jpeg");
+        assert_eq!(content[0]["source"]["data"], "AAAA");
+        assert_eq!(content[1]["text"], "tag it");
+    }
+
+    #[test]
+    fn test_extract_tags_from_response() {
+        let openai = json!({"choices": [{"message": {"content": "cat, dog, sfw"}}]});
+        assert_eq!(extract_tags_from_response(&openai).unwrap(), "cat, dog, sfw");
+
+        let anthropic = json!({
+            "type": "message",
+            "content": [{"type": "text", "text": "beach, sunset, sfw"}],
+            "stop_reason": "end_turn"
+        });
+        assert_eq!(extract_tags_from_response(&anthropic).unwrap(), "beach, sunset, sfw");
+
+        let ollama_chat = json!({"message": {"role": "assistant", "content": "cat, sfw"}});
+        assert_eq!(extract_tags_from_response(&ollama_chat).unwrap(), "cat, sfw");
+        let ollama_generate = json!({"response": "dog, sfw", "done": true});
+        assert_eq!(extract_tags_from_response(&ollama_generate).unwrap(), "dog, sfw");
+    }
+
+    #[test]
+    fn test_ollama_provider() {
+        assert_eq!(
+            AiProvider::from_endpoint("http://localhost:11434/api/chat"),
+            AiProvider::Ollama
+        );
+        assert_eq!(
+            AiProvider::from_endpoint("http://gpu-box:8080/api/generate"),
+            AiProvider::Ollama
+        );
+        assert_eq!(
+            AiProvider::from_endpoint("http://localhost:8000/v1/chat/completions"),
+            AiProvider::OpenAi
+        );
+        assert!(!AiProvider::Ollama.needs_api_key("http://gpu-box:11434/api/chat"));
+
+        let mut config = AITaggingConfig {
+            model: "llava".to_string(),
+            api_endpoint: "http://localhost:11434/api/chat".to_string(),
+            ..AITaggingConfig::default()
+        };
+        let body = AiProvider::Ollama.request_body(&config, "tag it", "AAAA", "image/png", 200);
+        assert_eq!(body["messages"][0]["images"][0], "AAAA");
+        assert_eq!(body["options"]["num_predict"], 200);
+
+        config.api_endpoint = "http://localhost:11434/api/generate".to_string();
+        let body = AiProvider::Ollama.request_body(&config, "tag it", "AAAA", "image/png", 200);
+        assert_eq!(body["prompt"], "tag it");
+        assert_eq!(body["images"][0], "AAAA");
+    }
+
+    #[test]
+    fn test_set_provider() {
+        let mut config = AITaggingConfig {
+            provider: AiProvider::OpenAi,
+            api_endpoint: AiProvider::OpenAi.default_endpoint().to_string(),
+            model: "my-model".to_string(),
+            ..AITaggingConfig::default()
+        };
+        config.set_provider(AiProvider::Anthropic);
+        assert_eq!(config.api_endpoint, "https://api.anthropic.com/v1/messages");
+        // An explicitly chosen model is kept
+        assert_eq!(config.model, "my-model");
+        assert!(parse_provider("Claude").is_ok());
+        assert!(parse_provider("gemini").is_err());
     }
 }
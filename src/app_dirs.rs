@@ -0,0 +1,207 @@
+// Where lsix keeps its files, following the XDG base directories:
+//   - config: $XDG_CONFIG_HOME/lsix (~/.config/lsix): config.toml, tag_prompt.md
+//   - cache:  LSIX_CACHE_DIR, or $XDG_CACHE_HOME/lsix (~/.cache/lsix)
+//   - state:  $XDG_STATE_HOME/lsix (~/.local/state/lsix): history, group
+//     names and edits, saved views, usage statistics, the TUI log
+// On macOS the defaults are ~/Library/Application Support/lsix,
+// ~/Library/Caches/lsix and ~/Library/Logs/lsix instead; the XDG variables
+// still win when set. Relative XDG paths are ignored, as the spec says.
+//
+// Files left at the old locations (~/.cache/lsix with XDG_CACHE_HOME set,
+// ~/.lsix/tag_prompt.md, the ~/.config and ~/.local/state directories on
+// macOS) are moved over on the first run that finds them.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const APP: &str = "lsix";
+
+pub(crate) fn home() -> PathBuf {
+    std::env::var_os("HOME")
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir)
+}
+
+/// `$var/lsix` when `var` holds an absolute path, otherwise `fallback`
+/// under the home directory
+fn base_dir(var: &str, fallback: &str) -> PathBuf {
+    match std::env::var_os(var).map(PathBuf::from) {
+        Some(dir) if dir.is_absolute() => dir.join(APP),
+        _ => home().join(fallback).join(APP),
+    }
+}
+
+fn is_macos() -> bool {
+    cfg!(target_os = "macos")
+}
+
+/// Directory of the user config file and the tagging prompt
+pub fn config_dir() -> PathBuf {
+    let fallback = if is_macos() { "Library/Application Support" } else { ".config" };
+    base_dir("XDG_CONFIG_HOME", fallback)
+}
+
+/// Root of the caches, which can all be deleted
+pub fn cache_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("LSIX_CACHE_DIR").filter(|dir| !dir.is_empty()) {
+        return PathBuf::from(dir);
+    }
+    base_dir("XDG_CACHE_HOME", if is_macos() { "Library/Caches" } else { ".cache" })
+}
+
+/// Directory for state kept between runs, such as the invocation history
+pub fn state_dir() -> PathBuf {
+    if is_macos() && std::env::var_os("XDG_STATE_HOME").is_none() {
+        return config_dir().join("state");
+    }
+    base_dir("XDG_STATE_HOME", ".local/state")
+}
+
+/// The TUI's --log file
+pub fn log_path() -> PathBuf {
+    let dir = if is_macos() && std::env::var_os("XDG_STATE_HOME").is_none() {
+        home().join("Library/Logs").join(APP)
+    } else {
+        state_dir()
+    };
+    dir.join("tui.log")
+}
+
+/// Open the TUI log for appending, or truncated to start a new one
+pub fn open_log(truncate: bool) -> io::Result<fs::File> {
+    let path = log_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(!truncate)
+        .truncate(truncate)
+        .open(path)
+}
+
+/// Old locations and where their contents go now
+fn legacy_moves() -> Vec<(PathBuf, PathBuf)> {
+    let home = home();
+    let mut moves = Vec::new();
+    // Earlier versions ignored XDG_CACHE_HOME; an explicit LSIX_CACHE_DIR is
+    // left to its owner
+    if std::env::var_os("LSIX_CACHE_DIR").is_none() {
+        moves.push((home.join(".cache").join(APP), cache_dir()));
+    }
+    // The old defaults, which only differ from the new ones on macOS
+    if std::env::var_os("XDG_CONFIG_HOME").is_none() {
+        moves.push((home.join(".config").join(APP), config_dir()));
+    }
+    if std::env::var_os("XDG_STATE_HOME").is_none() {
+        moves.push((home.join(".local/state").join(APP), state_dir()));
+    }
+    // Last, so the config directory it creates doesn't stop the one above
+    moves.push((home.join(".lsix/tag_prompt.md"), config_dir().join("tag_prompt.md")));
+    moves
+}
+
+/// Move files left at the old locations to the current ones, returning a
+/// note for each. Nothing is moved over existing files: a location in use
+/// wins.
+pub fn migrate_legacy() -> Vec<String> {
+    let notes = migrate(&legacy_moves());
+    // ~/.lsix only held the prompt
+    fs::remove_dir(home().join(".lsix")).ok();
+    notes
+}
+
+fn migrate(moves: &[(PathBuf, PathBuf)]) -> Vec<String> {
+    let mut notes = Vec::new();
+    for (from, to) in moves {
+        if from == to || from.symlink_metadata().is_err() || to.symlink_metadata().is_ok() {
+            continue;
+        }
+        match move_path(from, to) {
+            Ok(()) => notes.push(format!("Moved {} to {}", from.display(), to.display())),
+            Err(e) => notes.push(format!(
+                "Could not move {} to {}: {}",
+                from.display(),
+                to.display(),
+                e
+            )),
+        }
+    }
+    notes
+}
+
+/// Rename `from` to `to`, copying when they are on different file systems
+fn move_path(from: &Path, to: &Path) -> io::Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    let copied = copy_tree(from, to);
+    if copied.is_err() {
+        // Leave the old copy whole rather than two halves
+        if to.is_dir() {
+            fs::remove_dir_all(to).ok();
+        } else {
+            fs::remove_file(to).ok();
+        }
+        return copied;
+    }
+    if from.is_dir() {
+        fs::remove_dir_all(from)
+    } else {
+        fs::remove_file(from)
+    }
+}
+
+fn copy_tree(from: &Path, to: &Path) -> io::Result<()> {
+    if !from.is_dir() {
+        return fs::copy(from, to).map(|_| ());
+    }
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        copy_tree(&entry.path(), &to.join(entry.file_name()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn test_app_dirs() {
+        let root = TestDir::new("app-dirs");
+        let old_cache = root.join("home/.cache/lsix");
+        fs::create_dir_all(old_cache.join("thumbs")).unwrap();
+        fs::write(old_cache.join("thumbs/a.png"), "a").unwrap();
+        let old_prompt = root.join("home/.lsix/tag_prompt.md");
+        fs::create_dir_all(old_prompt.parent().unwrap()).unwrap();
+        fs::write(&old_prompt, "old").unwrap();
+        let new_prompt = root.join("config/lsix/tag_prompt.md");
+        fs::create_dir_all(new_prompt.parent().unwrap()).unwrap();
+        fs::write(&new_prompt, "new").unwrap();
+
+        let new_cache = root.join("xdg-cache/lsix");
+        let moves = [
+            (old_cache.clone(), new_cache.clone()),
+            (old_prompt.clone(), new_prompt.clone()),
+            (root.join("home/.local/state/lsix"), root.join("state/lsix")),
+        ];
+        let notes = migrate(&moves);
+        assert_eq!(notes.len(), 1, "{:?}", notes);
+        assert!(notes[0].starts_with("Moved"));
+        assert_eq!(fs::read_to_string(new_cache.join("thumbs/a.png")).unwrap(), "a");
+        assert!(!old_cache.exists());
+        // A file already at the new location is kept, and so is the old one
+        assert_eq!(fs::read_to_string(&new_prompt).unwrap(), "new");
+        assert!(old_prompt.exists());
+        assert!(migrate(&moves).is_empty());
+    }
+}
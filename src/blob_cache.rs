@@ -0,0 +1,224 @@
+// Content-addressed blob cache shared by the remote fetcher, the
+// thumbnailer and the AI encoder. Downloads land in
+// ~/.cache/lsix/blobs/<sha256>/<name>, so every consumer reads the same
+// local file. Base64 payloads for AI requests are encoded per request and
+// not kept: a large library would hold every image in memory otherwise.
+
+use crate::image_proc::get_cache_dir;
+use anyhow::{Context, Result};
+use base64::Engine;
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Default number of concurrent downloads
+pub const DEFAULT_FETCH_JOBS: usize = 4;
+
+/// Per-request download timeout
+const FETCH_TIMEOUT_SECS: u64 = 60;
+
+/// Whether an input argument is a remote URL rather than a local path
+pub fn is_remote(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+/// Hex SHA-256 of `data`
+pub fn content_hash(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn blob_dir() -> Result<PathBuf> {
+    Ok(get_cache_dir()?.join("blobs"))
+}
+
+/// Index entry recording which blob a URL resolved to
+fn url_index_path(url: &str) -> Result<PathBuf> {
+    Ok(blob_dir()?.join("urls").join(content_hash(url.as_bytes())))
+}
+
+/// Store `data` under its content hash. If the content is already cached
+/// the existing file is returned and nothing is written.
+pub fn store(data: &[u8], name: &str) -> Result<PathBuf> {
+    let dir = blob_dir()?.join(content_hash(data));
+
+    if let Some(existing) = fs::read_dir(&dir)
+        .ok()
+        .and_then(|mut entries| entries.find_map(|e| e.ok()))
+    {
+        return Ok(existing.path());
+    }
+
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let path = dir.join(name);
+    // Write then rename so concurrent readers never see a partial blob
+    let tmp = dir.join(format!(".{}.{}", name, std::process::id()));
+    fs::write(&tmp, data).with_context(|| format!("Failed to write {}", tmp.display()))?;
+    fs::rename(&tmp, &path)?;
+    Ok(path)
+}
+
+/// Download a URL into the blob cache, reusing an earlier download of the
+/// same URL. Returns the local path.
+pub fn fetch(client: &reqwest::blocking::Client, url: &str) -> Result<PathBuf> {
+    let index = url_index_path(url)?;
+    if let Ok(cached) = fs::read_to_string(&index) {
+        let cached = PathBuf::from(cached.trim());
+        if cached.is_file() {
+            return Ok(cached);
+        }
+    }
+
+    let response = client
+        .get(url)
+        .send()
+        .and_then(|r| r.error_for_status())
+        .with_context(|| format!("Failed to download {}", url))?;
+    let data = response
+        .bytes()
+        .with_context(|| format!("Failed to read {}", url))?;
+
+    let path = store(&data, &file_name_for(url, &data))?;
+
+    if let Some(parent) = index.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&index, path.to_string_lossy().as_bytes())?;
+    Ok(path)
+}
+
+/// Download URLs with up to `jobs` concurrent requests. Each distinct URL
+/// is fetched once; results keep the input order.
+pub fn fetch_all(urls: &[String], jobs: usize) -> Vec<(String, Result<PathBuf>)> {
+    let client = match reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(FETCH_TIMEOUT_SECS))
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => {
+            let msg = format!("Failed to create HTTP client: {}", e);
+            return urls
+                .iter()
+                .map(|u| (u.clone(), Err(anyhow::anyhow!(msg.clone()))))
+                .collect();
+        }
+    };
+
+    let mut seen = HashSet::new();
+    let unique: Vec<&String> = urls.iter().filter(|u| seen.insert(u.as_str())).collect();
+
+    let fetched: HashMap<&str, Result<PathBuf, String>> = match rayon::ThreadPoolBuilder::new()
+        .num_threads(jobs.max(1))
+        .build()
+    {
+        Ok(pool) => pool.install(|| {
+            unique
+                .par_iter()
+                .map(|url| (url.as_str(), fetch(&client, url).map_err(|e| format!("{:#}", e))))
+                .collect()
+        }),
+        Err(_) => unique
+            .iter()
+            .map(|url| (url.as_str(), fetch(&client, url).map_err(|e| format!("{:#}", e))))
+            .collect(),
+    };
+
+    urls.iter()
+        .map(|url| {
+            let result = match &fetched[url.as_str()] {
+                Ok(path) => Ok(path.clone()),
+                Err(e) => Err(anyhow::anyhow!(e.clone())),
+            };
+            (url.clone(), result)
+        })
+        .collect()
+}
+
+/// Replace URLs in `inputs` with local blob paths, downloading as needed.
+/// Failed downloads are reported and dropped.
+pub fn resolve_remote_inputs(inputs: &[String], jobs: usize) -> Vec<String> {
+    let urls: Vec<String> = inputs.iter().filter(|p| is_remote(p)).cloned().collect();
+    if urls.is_empty() {
+        return inputs.to_vec();
+    }
+
+    eprintln!("Fetching {} remote image(s)...", urls.len());
+    let mut fetched: HashMap<String, PathBuf> = HashMap::new();
+    for (url, result) in fetch_all(&urls, jobs) {
+        match result {
+            Ok(path) => {
+                fetched.insert(url, path);
+            }
+            Err(e) => eprintln!("Warning: {:#}", e),
+        }
+    }
+
+    inputs
+        .iter()
+        .filter_map(|input| {
+            if is_remote(input) {
+                fetched
+                    .get(input)
+                    .map(|p| p.to_string_lossy().to_string())
+            } else {
+                Some(input.clone())
+            }
+        })
+        .collect()
+}
+
+/// Base64 of a file's contents
+pub fn base64_of(path: &Path) -> Result<String> {
+    let data = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(base64::engine::general_purpose::STANDARD.encode(&data))
+}
+
+/// Local file name for a download: the last URL path segment, with an
+/// extension guessed from the content if the URL has none
+fn file_name_for(url: &str, data: &[u8]) -> String {
+    let without_scheme = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let path = without_scheme.split(['?', '#']).next().unwrap_or("");
+    let name = path
+        .split_once('/')
+        .map_or("", |(_, p)| p)
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .filter(|n| !n.is_empty())
+        .unwrap_or("image");
+
+    if Path::new(name).extension().is_some() {
+        return name.to_string();
+    }
+
+    let ext = image::guess_format(data)
+        .ok()
+        .and_then(|f| f.extensions_str().first().copied())
+        .unwrap_or("img");
+    format!("{}.{}", name, ext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_content_hash() {
+        assert_eq!(
+            content_hash(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_file_name_for() {
+        assert_eq!(file_name_for("https://x.org/a/cat.jpg?w=200", b""), "cat.jpg");
+        assert_eq!(file_name_for("https://x.org/img/42", b"\x89PNG\r\n\x1a\n"), "42.png");
+        assert_eq!(file_name_for("https://x.org/", b""), "image.img");
+    }
+}
@@ -0,0 +1,355 @@
+// `lsix bugreport`: collects everything we usually ask for on terminal
+// rendering issues into one text file (or tarball) that can be attached.
+
+use crate::app_dirs;
+use crate::terminal;
+use anyhow::{Context, Result};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Number of trailing log lines included in the report
+const LOG_TAIL_LINES: usize = 60;
+
+/// External tools lsix can use, with the flag that prints their version
+const TOOLS: [(&str, &str); 6] = [
+    ("magick", "-version"),
+    ("convert", "-version"),
+    ("montage", "-version"),
+    ("identify", "-version"),
+    ("ffmpeg", "-version"),
+    ("tmux", "-V"),
+];
+
+/// Write a bug report to `out` (stdout if None). A `.tar.gz`/`.tgz` name
+/// produces a tarball holding the report and the full log.
+pub fn run_bugreport(out: Option<&str>, version: &str, build_time: &str) -> Result<()> {
+    let report = generate_report(version, build_time);
+
+    match out {
+        None => print!("{}", report),
+        Some(out) if out.ends_with(".tar.gz") || out.ends_with(".tgz") => {
+            write_tarball(&report, Path::new(out))?;
+            eprintln!("✓ Bug report written to {}", out);
+        }
+        Some(out) => {
+            fs::write(out, &report).with_context(|| format!("Failed to write {}", out))?;
+            eprintln!("✓ Bug report written to {}", out);
+        }
+    }
+
+    Ok(())
+}
+
+/// Build the full plain-text report
+pub fn generate_report(version: &str, build_time: &str) -> String {
+    let mut report = String::new();
+
+    writeln!(report, "lsix bug report").ok();
+    writeln!(report, "===============").ok();
+
+    section(&mut report, "Version");
+    writeln!(report, "lsix {}", version).ok();
+    writeln!(report, "build time: {}", build_time.trim()).ok();
+    writeln!(
+        report,
+        "os: {} ({})",
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    )
+    .ok();
+
+    section(&mut report, "Terminal");
+    write_terminal_info(&mut report);
+
+    section(&mut report, "Environment");
+    write_environment(&mut report);
+
+    section(&mut report, "AI tagging config");
+    write_ai_config(&mut report);
+
+    section(&mut report, "External tools");
+    write_tools(&mut report);
+
+    section(&mut report, "Optional features");
+    for line in crate::tools::report() {
+        writeln!(report, "{}", line).ok();
+    }
+
+    section(&mut report, "Test render");
+    write_test_render(&mut report);
+
+    section(&mut report, "Log (last lines)");
+    write_log_tail(&mut report);
+
+    report
+}
+
+fn section(report: &mut String, title: &str) {
+    writeln!(report, "\n## {}\n", title).ok();
+}
+
+fn write_terminal_info(report: &mut String) {
+    for var in ["TERM", "TERM_PROGRAM", "TERM_PROGRAM_VERSION", "COLORTERM", "TMUX", "KITTY_WINDOW_ID"] {
+        let value = std::env::var(var).unwrap_or_else(|_| "(unset)".to_string());
+        writeln!(report, "{}={}", var, value).ok();
+    }
+
+    match crossterm::terminal::size() {
+        Ok((cols, rows)) => writeln!(report, "size: {}x{} cells", cols, rows).ok(),
+        Err(e) => writeln!(report, "size: unknown ({})", e).ok(),
+    };
+
+    match terminal::detect_protocol() {
+        Ok(protocol) => writeln!(report, "graphics protocol: {:?}", protocol).ok(),
+        Err(e) => writeln!(report, "graphics protocol: error ({})", e).ok(),
+    };
+
+    match terminal::detect_sixel() {
+        Ok(has_sixel) => writeln!(report, "sixel detected: {}", has_sixel).ok(),
+        Err(e) => writeln!(report, "sixel detected: no ({})", first_line(&e.to_string())).ok(),
+    };
+
+    if let Ok(colors) = terminal::detect_colors() {
+        writeln!(report, "colors: {}", colors).ok();
+    }
+    if let Ok((bg, fg)) = terminal::detect_colorscheme() {
+        writeln!(report, "colorscheme: background {} / foreground {}", bg, fg).ok();
+    }
+    if let Ok(width) = terminal::detect_geometry() {
+        writeln!(report, "width: {}px", width).ok();
+    }
+    match terminal::detect_cell_size() {
+        Some((width, height)) => writeln!(report, "cell size: {}x{}px", width, height).ok(),
+        None => writeln!(report, "cell size: unknown (the TUI will query the terminal)").ok(),
+    };
+}
+
+fn write_environment(report: &mut String) {
+    let mut vars: Vec<(String, String)> = std::env::vars()
+        .filter(|(name, _)| name.starts_with("LSIX_"))
+        .collect();
+    vars.sort();
+
+    if vars.is_empty() {
+        writeln!(report, "(no LSIX_* variables set)").ok();
+    }
+    for (name, value) in vars {
+        writeln!(report, "{}={}", name, redact(&name, &value)).ok();
+    }
+}
+
+fn write_ai_config(report: &mut String) {
+    let config = crate::ai_tagging::AITaggingConfig::default();
+
+    writeln!(report, "provider: {}", config.provider.name()).ok();
+    writeln!(report, "endpoint: {}", redact_url(&config.api_endpoint)).ok();
+    writeln!(report, "model: {}", config.model).ok();
+    writeln!(report, "api key: {}", redact("API_KEY", &config.api_key)).ok();
+    writeln!(
+        report,
+        "custom prompt: {}",
+        if config.custom_prompt.is_some() { "yes" } else { "no" }
+    )
+    .ok();
+    if let Some(dir) = &config.cache_dir {
+        let entries = fs::read_dir(dir).map(|d| d.count()).unwrap_or(0);
+        writeln!(report, "cache: {} ({} entries)", dir.display(), entries).ok();
+    }
+}
+
+fn write_tools(report: &mut String) {
+    for (tool, version_flag) in TOOLS {
+        let output = Command::new(tool)
+            .arg(version_flag)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output();
+
+        match output {
+            Ok(output) if output.status.success() => {
+                let version = String::from_utf8_lossy(&output.stdout);
+                writeln!(report, "{}: {}", tool, first_line(&version)).ok();
+            }
+            Ok(output) => {
+                writeln!(report, "{}: exited with {:?}", tool, output.status.code()).ok();
+            }
+            Err(_) => {
+                writeln!(report, "{}: not found", tool).ok();
+            }
+        }
+    }
+}
+
+/// Encode a small test pattern with every backend, without printing it
+fn write_test_render(report: &mut String) {
+    let pattern = image::RgbaImage::from_fn(24, 12, |x, y| {
+        image::Rgba([(x * 10) as u8, (y * 20) as u8, 128, 255])
+    });
+
+    let sixel = crate::sixel_encoder::encode(&pattern, 256, [0, 0, 0]);
+    writeln!(
+        report,
+        "native sixel: {} ({} bytes)",
+        if sixel.starts_with(b"\x1bP") && sixel.ends_with(b"\x1b\\") { "ok" } else { "malformed" },
+        sixel.len()
+    )
+    .ok();
+
+    match crate::kitty_encoder::encode(&pattern) {
+        Ok(kitty) => writeln!(report, "kitty: ok ({} bytes)", kitty.len()).ok(),
+        Err(e) => writeln!(report, "kitty: error ({:#})", e).ok(),
+    };
+
+    match imagemagick_test_render(&pattern) {
+        Ok(len) => writeln!(report, "imagemagick sixel: ok ({} bytes)", len).ok(),
+        Err(e) => writeln!(report, "imagemagick sixel: error ({:#})", e).ok(),
+    };
+}
+
+/// Round-trip the pattern through ImageMagick's SIXEL coder
+fn imagemagick_test_render(pattern: &image::RgbaImage) -> Result<usize> {
+    let input = std::env::temp_dir().join(format!("lsix-bugreport-{}.png", std::process::id()));
+    pattern.save(&input)?;
+
+    let output = Command::new("convert")
+        .arg(&input)
+        .arg("sixel:-")
+        .stderr(Stdio::piped())
+        .output();
+    let _ = fs::remove_file(&input);
+
+    let output = output.context("convert not available")?;
+    if !output.status.success() {
+        anyhow::bail!("{}", first_line(&String::from_utf8_lossy(&output.stderr)));
+    }
+    Ok(output.stdout.len())
+}
+
+fn write_log_tail(report: &mut String) {
+    let log_path = app_dirs::log_path();
+    match fs::read_to_string(&log_path) {
+        Ok(log) => {
+            let lines: Vec<&str> = log.lines().collect();
+            let start = lines.len().saturating_sub(LOG_TAIL_LINES);
+            writeln!(report, "({}, {} lines total)", log_path.display(), lines.len()).ok();
+            for line in &lines[start..] {
+                writeln!(report, "{}", line).ok();
+            }
+        }
+        Err(_) => {
+            writeln!(
+                report,
+                "(no log at {}; reproduce with --log to capture one)",
+                log_path.display()
+            )
+            .ok();
+        }
+    }
+}
+
+/// Bundle the report and the full log with the system `tar`
+fn write_tarball(report: &str, out: &Path) -> Result<()> {
+    let staging = std::env::temp_dir().join(format!("lsix-bugreport-{}", std::process::id()));
+    fs::create_dir_all(&staging)?;
+
+    fs::write(staging.join("report.txt"), report)?;
+    let mut files = vec!["report.txt"];
+    if fs::copy(app_dirs::log_path(), staging.join("lsix_tui.log")).is_ok() {
+        files.push("lsix_tui.log");
+    }
+
+    let out = std::env::current_dir()?.join(out);
+    let status = Command::new("tar")
+        .arg("czf")
+        .arg(&out)
+        .arg("-C")
+        .arg(&staging)
+        .args(&files)
+        .status()
+        .context("Failed to run tar");
+    let _ = fs::remove_dir_all(&staging);
+
+    let status = status?;
+    if !status.success() {
+        anyhow::bail!("tar failed with exit code: {:?}", status.code());
+    }
+    Ok(())
+}
+
+/// Whether a variable/parameter name looks like it holds a credential
+fn is_secret_name(name: &str) -> bool {
+    let name = name.to_uppercase();
+    ["KEY", "TOKEN", "SECRET", "PASSWORD"]
+        .iter()
+        .any(|word| name.contains(word))
+}
+
+/// Hide values of variables that look like credentials
+fn redact(name: &str, value: &str) -> String {
+    if is_secret_name(name) {
+        if value.is_empty() {
+            "(empty)".to_string()
+        } else {
+            format!("<redacted, {} chars>", value.chars().count())
+        }
+    } else {
+        redact_url(value)
+    }
+}
+
+/// Strip credentials embedded in a URL (user:pass@host, ?key=...)
+fn redact_url(value: &str) -> String {
+    let mut value = value.to_string();
+
+    if let Some(scheme_end) = value.find("://") {
+        let rest = &value[scheme_end + 3..];
+        let host_end = rest.find('/').unwrap_or(rest.len());
+        if let Some(at) = rest[..host_end].rfind('@') {
+            value = format!("{}<redacted>@{}", &value[..scheme_end + 3], &rest[at + 1..]);
+        }
+    }
+
+    if let Some(query_start) = value.find('?') {
+        let (base, query) = value.split_at(query_start + 1);
+        let query: Vec<String> = query
+            .split('&')
+            .map(|pair| match pair.split_once('=') {
+                Some((k, _)) if is_secret_name(k) => format!("{}=<redacted>", k),
+                _ => pair.to_string(),
+            })
+            .collect();
+        value = format!("{}{}", base, query.join("&"));
+    }
+
+    value
+}
+
+fn first_line(s: &str) -> &str {
+    s.lines().next().unwrap_or("").trim()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact_secrets() {
+        assert_eq!(redact("LSIX_AI_API_KEY", "sk-abc123"), "<redacted, 9 chars>");
+        assert_eq!(redact("LSIX_AI_API_KEY", ""), "(empty)");
+        assert_eq!(redact("LSIX_AI_MODEL", "gpt-4o-mini"), "gpt-4o-mini");
+    }
+
+    #[test]
+    fn test_redact_url() {
+        assert_eq!(
+            redact_url("https://user:pw@example.com/v1?key=abc&model=x"),
+            "https://<redacted>@example.com/v1?key=<redacted>&model=x"
+        );
+        assert_eq!(
+            redact_url("http://localhost:8000/v1/chat/completions"),
+            "http://localhost:8000/v1/chat/completions"
+        );
+    }
+}
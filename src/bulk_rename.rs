@@ -0,0 +1,291 @@
+// Renaming several images at once from a pattern such as `trip_{n:3}`,
+// for the TUI's rename key on a selection.
+//
+// A plan lists every old and new name before anything is touched, marking
+// the names that collide: two images given the same name, or a name taken
+// by a file that isn't being renamed. Colliding names can be numbered
+// (`_2`, `_3`) instead. Applying a plan is all or nothing: every image is
+// first moved to a temporary name beside it, so names can be swapped, and
+// a failure undoes whatever was already renamed.
+
+use anyhow::{Context, Result};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Why a new name can't be used as it is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Conflict {
+    /// Another image in the batch gets the same name
+    Duplicate,
+    /// A file that isn't being renamed already has the name
+    Exists,
+}
+
+impl Conflict {
+    pub fn label(self) -> &'static str {
+        match self {
+            Conflict::Duplicate => "same name as another image",
+            Conflict::Exists => "file exists",
+        }
+    }
+}
+
+/// One image and its new path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rename {
+    pub from: PathBuf,
+    pub to: PathBuf,
+    pub conflict: Option<Conflict>,
+}
+
+/// Every rename of a batch, in the order of the images
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenamePlan {
+    pub renames: Vec<Rename>,
+}
+
+/// New name of an image from `pattern`: `{n}` is its number in the batch
+/// (from 1), `{n:3}` the number padded to 3 digits, `{name}` its old name
+/// without the extension and `{ext}` the extension. Without `{ext}` the
+/// old extension is kept.
+pub fn render_name(pattern: &str, number: usize, path: &Path) -> Result<String> {
+    let stem = path.file_stem().map(|s| s.to_string_lossy()).unwrap_or_default();
+    let ext = path.extension().map(|e| e.to_string_lossy()).unwrap_or_default();
+
+    let mut name = String::new();
+    let mut rest = pattern.trim();
+    while let Some(open) = rest.find('{') {
+        name.push_str(&rest[..open]);
+        let close = rest[open..]
+            .find('}')
+            .with_context(|| format!("Unclosed {{ in {}", pattern))?;
+        let field = &rest[open + 1..open + close];
+        match (field, field.split_once(':')) {
+            ("n", _) => name.push_str(&number.to_string()),
+            (_, Some(("n", width))) => {
+                let width: usize = width
+                    .parse()
+                    .with_context(|| format!("Invalid number width: {{{}}}", field))?;
+                name.push_str(&format!("{:0width$}", number, width = width));
+            }
+            ("name", _) => name.push_str(&stem),
+            ("ext", _) => name.push_str(&ext),
+            _ => anyhow::bail!(
+                "Unknown field {{{}}}. Use {{n}}, {{n:3}}, {{name}}, {{ext}}",
+                field
+            ),
+        }
+        rest = &rest[open + close + 1..];
+    }
+    name.push_str(rest);
+    if !pattern.contains("{ext}") && !ext.is_empty() {
+        name = format!("{}.{}", name, ext);
+    }
+
+    if name.is_empty() || name.contains('/') || name == "." || name == ".." {
+        anyhow::bail!("Invalid file name: {:?}", name);
+    }
+    Ok(name)
+}
+
+/// Plan renaming `paths`, in order, after `pattern` (see `render_name`)
+pub fn plan(paths: &[String], pattern: &str) -> Result<RenamePlan> {
+    let renames = paths
+        .iter()
+        .enumerate()
+        .map(|(i, path)| {
+            let from = PathBuf::from(path);
+            let name = render_name(pattern, i + 1, &from)?;
+            let to = from.with_file_name(name);
+            Ok(Rename { from, to, conflict: None })
+        })
+        .collect::<Result<_>>()?;
+    let mut plan = RenamePlan { renames };
+    plan.find_conflicts();
+    Ok(plan)
+}
+
+impl RenamePlan {
+    /// Number of new names that collide
+    pub fn conflicts(&self) -> usize {
+        self.renames.iter().filter(|r| r.conflict.is_some()).count()
+    }
+
+    fn find_conflicts(&mut self) {
+        let mut counts: HashMap<&Path, usize> = HashMap::new();
+        for rename in &self.renames {
+            *counts.entry(&rename.to).or_default() += 1;
+        }
+        let vacated = self.vacated();
+        let conflicts: Vec<Option<Conflict>> = self
+            .renames
+            .iter()
+            .map(|rename| {
+                if counts[rename.to.as_path()] > 1 {
+                    Some(Conflict::Duplicate)
+                } else if is_taken(&rename.to, &vacated) {
+                    Some(Conflict::Exists)
+                } else {
+                    None
+                }
+            })
+            .collect();
+        for (rename, conflict) in self.renames.iter_mut().zip(conflicts) {
+            rename.conflict = conflict;
+        }
+    }
+
+    /// Old paths, which are free once the batch has been renamed
+    fn vacated(&self) -> HashSet<PathBuf> {
+        self.renames.iter().map(|r| r.from.clone()).collect()
+    }
+
+    /// The plan with colliding names numbered: `trip.jpg` becomes
+    /// `trip_2.jpg`, `trip_3.jpg` and so on. Of several images given one
+    /// free name, the first keeps it.
+    pub fn with_suffixes(mut self) -> RenamePlan {
+        let vacated = self.vacated();
+        let mut used: HashSet<PathBuf> = self
+            .renames
+            .iter()
+            .filter(|r| r.conflict.is_none())
+            .map(|r| r.to.clone())
+            .collect();
+        for rename in self.renames.iter_mut().filter(|r| r.conflict.is_some()) {
+            let stem = rename.to.file_stem().unwrap_or_default().to_string_lossy().to_string();
+            let ext = rename.to.extension().map(|e| e.to_string_lossy().to_string());
+            let mut candidate = rename.to.clone();
+            let mut number = 2;
+            while used.contains(&candidate) || is_taken(&candidate, &vacated) {
+                let name = match &ext {
+                    Some(ext) => format!("{}_{}.{}", stem, number, ext),
+                    None => format!("{}_{}", stem, number),
+                };
+                candidate = rename.to.with_file_name(name);
+                number += 1;
+            }
+            used.insert(candidate.clone());
+            rename.to = candidate;
+            rename.conflict = None;
+        }
+        self
+    }
+}
+
+/// Whether `path` belongs to a file that isn't being renamed away
+fn is_taken(path: &Path, vacated: &HashSet<PathBuf>) -> bool {
+    path.symlink_metadata().is_ok() && !vacated.contains(path)
+}
+
+/// Rename every image of `plan`, or none of them. Returns the old and new
+/// paths of the images whose name changed.
+pub fn apply(plan: &RenamePlan) -> Result<Vec<(String, String)>> {
+    if plan.conflicts() > 0 {
+        anyhow::bail!("{} of the new names collide", plan.conflicts());
+    }
+    let moves: Vec<&Rename> = plan.renames.iter().filter(|r| r.from != r.to).collect();
+
+    // Renames made so far, undone in reverse when a later one fails
+    let mut done: Vec<(PathBuf, PathBuf)> = Vec::new();
+    let result = rename_all(&moves, &mut done);
+    if let Err(e) = result {
+        let stuck: Vec<String> = done
+            .iter()
+            .rev()
+            .filter(|(from, to)| fs::rename(to, from).is_err())
+            .map(|(_, to)| to.display().to_string())
+            .collect();
+        if stuck.is_empty() {
+            return Err(e.context("Nothing was renamed"));
+        }
+        return Err(e.context(format!("Could not undo the renames, left: {}", stuck.join(", "))));
+    }
+
+    Ok(moves
+        .iter()
+        .map(|r| (r.from.to_string_lossy().to_string(), r.to.to_string_lossy().to_string()))
+        .collect())
+}
+
+/// Both phases of `apply`, recording each rename in `done`
+fn rename_all(moves: &[&Rename], done: &mut Vec<(PathBuf, PathBuf)>) -> Result<()> {
+    let mut step = |from: &Path, to: &Path| -> Result<()> {
+        // rename() replaces files, so a name taken since planning is checked here
+        if to.symlink_metadata().is_ok() {
+            anyhow::bail!("{} already exists", to.display());
+        }
+        fs::rename(from, to)
+            .with_context(|| format!("Failed to rename {} to {}", from.display(), to.display()))?;
+        done.push((from.to_path_buf(), to.to_path_buf()));
+        Ok(())
+    };
+
+    let temps: Vec<PathBuf> = moves.iter().map(|r| temp_path(&r.from)).collect();
+    for (rename, temp) in moves.iter().zip(&temps) {
+        step(&rename.from, temp)?;
+    }
+    for (rename, temp) in moves.iter().zip(&temps) {
+        step(temp, &rename.to)?;
+    }
+    Ok(())
+}
+
+/// Hidden name beside `path` that it is parked under between the phases
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+    path.with_file_name(format!(".{}.lsix-rename-{}", name, std::process::id()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn test_bulk_rename() {
+        let dir = TestDir::new("bulk-rename");
+        let file = |name: &str| {
+            let path = dir.join(name);
+            fs::write(&path, name).unwrap();
+            path.to_string_lossy().to_string()
+        };
+        let (a, b, c) = (file("a.jpg"), file("b.jpg"), file("keep.jpg"));
+
+        let name = |pattern: &str| render_name(pattern, 7, Path::new("/x/IMG_1.jpg")).unwrap();
+        assert_eq!(name("trip_{n:3}"), "trip_007.jpg");
+        assert_eq!(name("{name}-{n}.{ext}"), "IMG_1-7.jpg");
+        assert!(render_name("{n", 1, Path::new("a.jpg")).is_err());
+        assert!(render_name("{date}", 1, Path::new("a.jpg")).is_err());
+
+        // Both images would be keep.jpg, which is also taken
+        let paths = [a.clone(), b.clone()];
+        let clash = plan(&paths, "keep").unwrap();
+        assert_eq!(clash.conflicts(), 2);
+        assert!(apply(&clash).is_err());
+        let numbered = clash.with_suffixes();
+        let names: Vec<_> = numbered.renames.iter().map(|r| r.to.file_name().unwrap()).collect();
+        assert_eq!(names, ["keep_2.jpg", "keep_3.jpg"]);
+
+        // Swapping names is fine: the old names are free by then
+        let swap = plan(&[a.clone(), b.clone()], "{n}").unwrap();
+        assert_eq!(swap.conflicts(), 0);
+        let swap = RenamePlan {
+            renames: vec![
+                Rename { to: PathBuf::from(&b), ..swap.renames[0].clone() },
+                Rename { to: PathBuf::from(&a), ..swap.renames[1].clone() },
+            ],
+        };
+        assert_eq!(apply(&swap).unwrap().len(), 2);
+        assert_eq!(fs::read_to_string(&a).unwrap(), "b.jpg");
+
+        // A name taken after planning undoes the whole batch
+        let late = plan(&[a.clone(), b.clone(), c.clone()], "late_{n}").unwrap();
+        file("late_3.jpg");
+        assert!(apply(&late).is_err());
+        assert_eq!(fs::read_to_string(&a).unwrap(), "b.jpg");
+        assert_eq!(fs::read_to_string(&c).unwrap(), "keep.jpg");
+        assert!(!dir.join("late_1.jpg").exists());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 4);
+    }
+}
@@ -0,0 +1,193 @@
+// What changed in a folder since it was last listed, for `--changes`: new
+// files get a NEW badge and files whose contents differ an UPDATED one, and
+// `--changed-only` lists just those. Each listing records the SHA-256 of its
+// files (with their size and modification time, so unchanged files aren't
+// read again) in listings.json in the state directory. A folder listed for
+// the first time is only recorded: with nothing to compare against, nothing
+// in it is new.
+
+use crate::app_dirs::state_dir;
+use crate::blob_cache::content_hash;
+use crate::hash_index::file_stamp;
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// How a file differs from the last listing of its folder
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    New,
+    Updated,
+}
+
+impl Change {
+    /// Badge under the file's label
+    pub fn badge(self) -> &'static str {
+        match self {
+            Change::New => "● NEW",
+            Change::Updated => "● UPDATED",
+        }
+    }
+}
+
+/// A file as last listed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct FileEntry {
+    size: u64,
+    /// Modification time, nanoseconds since the epoch
+    modified: u64,
+    /// SHA-256 of the contents
+    hash: String,
+}
+
+/// Every folder listed with `--changes` and the files last seen in them,
+/// by canonical path
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Listings {
+    dirs: BTreeSet<String>,
+    files: HashMap<String, FileEntry>,
+}
+
+fn listings_path() -> PathBuf {
+    state_dir().join("listings.json")
+}
+
+fn canonical(path: &str) -> String {
+    fs::canonicalize(path)
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_else(|_| path.to_string())
+}
+
+fn parent(path: &str) -> String {
+    Path::new(path).parent().map(|p| p.to_string_lossy().to_string()).unwrap_or_default()
+}
+
+impl Listings {
+    /// Load the listings; a missing or unreadable file yields none
+    pub fn load() -> Listings {
+        fs::read_to_string(listings_path())
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
+    }
+
+    /// Compare `paths` with the last listing of their folders and record
+    /// them as the new one. Returns the changed paths, and the folders
+    /// listed for the first time.
+    pub fn update(&mut self, paths: &[String]) -> (HashMap<String, Change>, Vec<String>) {
+        let current: Vec<(&String, String, Option<FileEntry>)> = paths
+            .par_iter()
+            .map(|path| {
+                let key = canonical(path);
+                let entry = self.entry_now(&key);
+                (path, key, entry)
+            })
+            .collect();
+
+        let mut changes = HashMap::new();
+        let mut first_seen = BTreeSet::new();
+        for (path, key, entry) in current {
+            let Some(entry) = entry else { continue };
+            let dir = parent(&key);
+            if !self.dirs.contains(&dir) {
+                first_seen.insert(dir);
+            } else {
+                match self.files.get(&key) {
+                    None => {
+                        changes.insert(path.clone(), Change::New);
+                    }
+                    Some(last) if last.hash != entry.hash => {
+                        changes.insert(path.clone(), Change::Updated);
+                    }
+                    Some(_) => {}
+                }
+            }
+            self.files.insert(key, entry);
+        }
+        self.dirs.extend(first_seen.iter().cloned());
+        (changes, first_seen.into_iter().collect())
+    }
+
+    /// `path` as it is now, hashed again only when its size or modification
+    /// time changed
+    fn entry_now(&self, path: &str) -> Option<FileEntry> {
+        let (size, modified) = file_stamp(path)?;
+        if let Some(last) = self.files.get(path) {
+            if last.size == size && last.modified == modified {
+                return Some(last.clone());
+            }
+        }
+        let hash = content_hash(&fs::read(path).ok()?);
+        Some(FileEntry { size, modified, hash })
+    }
+
+    /// Write the listings; files that no longer exist are dropped
+    pub fn save(&mut self) -> Result<()> {
+        self.files.retain(|path, _| Path::new(path).exists());
+        let path = listings_path();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).context("Failed to create state directory")?;
+        }
+        // Write then rename so a concurrent run never reads a partial file
+        let tmp = path.with_extension(format!("json.{}", std::process::id()));
+        fs::write(&tmp, serde_json::to_string(&self)?)
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        fs::rename(&tmp, &path).with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(())
+    }
+}
+
+/// Files among `paths` new or changed since their folders were last
+/// listed, recording this listing for the next run
+pub fn detect(paths: &[String]) -> Result<HashMap<String, Change>> {
+    let mut listings = Listings::load();
+    let (changes, first_seen) = listings.update(paths);
+    for dir in first_seen {
+        crate::diagnostics::info(&format!(
+            "First listing of {} recorded; changes show from the next one",
+            dir
+        ));
+    }
+    listings.save()?;
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn test_changes() {
+        let dir = TestDir::new("changes");
+        let file = |name: &str, contents: &str| {
+            let path = dir.join(name);
+            fs::write(&path, contents).unwrap();
+            path.to_string_lossy().to_string()
+        };
+        let (a, b) = (file("a.png", "a"), file("b.png", "b"));
+
+        // The first listing is only recorded
+        let mut listings = Listings::default();
+        let (changes, first_seen) = listings.update(&[a.clone(), b.clone()]);
+        assert!(changes.is_empty());
+        assert_eq!(first_seen, [canonical(&dir.to_string_lossy())]);
+
+        // A rewrite with the same contents isn't a change
+        file("a.png", "a");
+        file("b.png", "b2");
+        let c = file("c.png", "c");
+        let (changes, first_seen) = listings.update(&[a.clone(), b.clone(), c.clone()]);
+        assert!(first_seen.is_empty());
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[&b], Change::Updated);
+        assert_eq!(changes[&c], Change::New);
+
+        // Nothing changed since
+        assert!(listings.update(&[a, b, c]).0.is_empty());
+    }
+}
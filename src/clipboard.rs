@@ -0,0 +1,151 @@
+// Copying from the TUI to the system clipboard: the absolute path of an
+// image (y) or the image itself (Y), for pasting into a shell, chat or
+// editor.
+//
+// In a graphical session text goes through wl-copy (Wayland) or xclip
+// (X11). Elsewhere, e.g. over ssh, it is sent as an OSC 52 escape sequence
+// that the terminal writes to its clipboard; inside tmux the sequence is
+// passed through to the outer terminal. Terminals don't take images that
+// way, so copying an image needs wl-copy or xclip. Images go to the
+// clipboard as PNG, converted first if they are stored in another format.
+
+use crate::tools::{self, Tool};
+use anyhow::{Context, Result};
+use base64::Engine;
+use image::ImageFormat;
+use std::io::{Cursor, Write};
+use std::process::{Command, Stdio};
+
+/// How something got to the clipboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Clipboard {
+    /// Through a clipboard tool
+    Tool(Tool),
+    /// Through the terminal (OSC 52)
+    Terminal,
+}
+
+impl Clipboard {
+    pub fn label(self) -> &'static str {
+        match self {
+            Clipboard::Tool(tool) => tool.name(),
+            Clipboard::Terminal => "OSC 52",
+        }
+    }
+}
+
+fn env_set(name: &str) -> bool {
+    std::env::var_os(name).is_some_and(|value| !value.is_empty())
+}
+
+/// Clipboard tool of the graphical session, if there is one and the tool is
+/// installed
+fn session_tool() -> Option<Tool> {
+    if env_set("WAYLAND_DISPLAY") && tools::available(Tool::WlCopy) {
+        Some(Tool::WlCopy)
+    } else if env_set("DISPLAY") && tools::available(Tool::Xclip) {
+        Some(Tool::Xclip)
+    } else {
+        None
+    }
+}
+
+/// Hand `data` of type `mime` (plain text when `None`) to `tool`
+fn pipe_to(tool: Tool, mime: Option<&str>, data: &[u8]) -> Result<()> {
+    let mut command = Command::new(tools::require(tool, "Copying to the clipboard")?);
+    match tool {
+        Tool::WlCopy => {
+            if let Some(mime) = mime {
+                command.args(["--type", mime]);
+            }
+        }
+        _ => {
+            command.args(["-selection", "clipboard"]);
+            if let Some(mime) = mime {
+                command.args(["-t", mime]);
+            }
+        }
+    }
+    // Both tools stay in the background to serve the clipboard; with their
+    // output closed, waiting returns once they have read the data
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Failed to run {}", tool.name()))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(data)?;
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        anyhow::bail!("{} failed ({})", tool.name(), status);
+    }
+    Ok(())
+}
+
+/// OSC 52 sequence that sets the clipboard to `text`, wrapped for tmux to
+/// pass on when `tmux`
+pub fn osc52(text: &str, tmux: bool) -> String {
+    let encoded = base64::engine::general_purpose::STANDARD.encode(text);
+    let sequence = format!("\x1b]52;c;{}\x07", encoded);
+    if tmux {
+        format!("\x1bPtmux;{}\x1b\\", sequence.replace('\x1b', "\x1b\x1b"))
+    } else {
+        sequence
+    }
+}
+
+/// Put `text` on the clipboard
+pub fn copy_text(text: &str) -> Result<Clipboard> {
+    if let Some(tool) = session_tool() {
+        pipe_to(tool, None, text.as_bytes())?;
+        return Ok(Clipboard::Tool(tool));
+    }
+    let mut stdout = std::io::stdout();
+    stdout.write_all(osc52(text, env_set("TMUX")).as_bytes())?;
+    stdout.flush()?;
+    Ok(Clipboard::Terminal)
+}
+
+/// The image at `path` as PNG data
+pub fn png_data(path: &str) -> Result<Vec<u8>> {
+    if ImageFormat::from_path(path).is_ok_and(|format| format == ImageFormat::Png) {
+        return std::fs::read(path).with_context(|| format!("Failed to read {}", path));
+    }
+    let image = image::open(path).with_context(|| format!("Failed to open {}", path))?;
+    let mut png = Vec::new();
+    image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+    Ok(png)
+}
+
+/// Put the image at `path` on the clipboard
+pub fn copy_image(path: &str) -> Result<Clipboard> {
+    let Some(tool) = session_tool() else {
+        // Name what to install when the tool is what's missing
+        let tool = if env_set("WAYLAND_DISPLAY") { Tool::WlCopy } else { Tool::Xclip };
+        tools::require(tool, "Copying images")?;
+        anyhow::bail!("Copying images needs a graphical session (no $WAYLAND_DISPLAY or $DISPLAY)");
+    };
+    pipe_to(tool, Some("image/png"), &png_data(path)?)?;
+    Ok(Clipboard::Tool(tool))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn test_clipboard_data() {
+        assert_eq!(osc52("/a b.jpg", false), "\x1b]52;c;L2EgYi5qcGc=\x07");
+        assert_eq!(osc52("x", true), "\x1bPtmux;\x1b\x1b]52;c;eA==\x07\x1b\\");
+
+        let dir = TestDir::new("clipboard");
+        let path = dir.join("dot.bmp").to_string_lossy().to_string();
+        image::RgbImage::new(3, 2).save(&path).unwrap();
+        let png = png_data(&path).unwrap();
+        assert!(png.starts_with(b"\x89PNG"));
+        assert_eq!(image::load_from_memory(&png).unwrap().width(), 3);
+    }
+}
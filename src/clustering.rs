@@ -0,0 +1,356 @@
+// Near-neighbor clustering for --group-by similarity and color, so grouping
+// scales past a few thousand images. Instead of comparing all pairs, close
+// pairs are found with an index and joined with union-find: perceptual
+// hashes go into multi-index hash tables keyed by 16-bit chunks, color
+// vectors are bucketed with random-hyperplane LSH and only compared within
+// a bucket. Clusters are single-linkage: if A is close to B and B to C, all
+// three end up together even when A and C are further apart.
+
+use crate::grouping::PerceptualHash;
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+/// Disjoint sets over `0..n`
+pub struct UnionFind {
+    parent: Vec<usize>,
+    rank: Vec<u8>,
+}
+
+impl UnionFind {
+    pub fn new(n: usize) -> Self {
+        UnionFind {
+            parent: (0..n).collect(),
+            rank: vec![0; n],
+        }
+    }
+
+    /// Representative of the set containing `x`
+    pub fn find(&mut self, x: usize) -> usize {
+        let mut root = x;
+        while self.parent[root] != root {
+            root = self.parent[root];
+        }
+        // Path compression
+        let mut x = x;
+        while self.parent[x] != root {
+            let next = self.parent[x];
+            self.parent[x] = root;
+            x = next;
+        }
+        root
+    }
+
+    /// Merge the sets of `a` and `b`
+    pub fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return;
+        }
+        match self.rank[a].cmp(&self.rank[b]) {
+            std::cmp::Ordering::Less => self.parent[a] = b,
+            std::cmp::Ordering::Greater => self.parent[b] = a,
+            std::cmp::Ordering::Equal => {
+                self.parent[b] = a;
+                self.rank[a] += 1;
+            }
+        }
+    }
+
+    /// All sets, singletons included, ordered by their first member; members
+    /// are in ascending order
+    pub fn sets(&mut self) -> Vec<Vec<usize>> {
+        let mut index_of_root: HashMap<usize, usize> = HashMap::new();
+        let mut sets: Vec<Vec<usize>> = Vec::new();
+        for x in 0..self.parent.len() {
+            let root = self.find(x);
+            let i = *index_of_root.entry(root).or_insert_with(|| {
+                sets.push(Vec::new());
+                sets.len() - 1
+            });
+            sets[i].push(x);
+        }
+        sets
+    }
+}
+
+/// Bits per sub-hash in a `HammingIndex` table
+const CHUNK_BITS: u32 = 16;
+
+/// Multi-index hash tables over perceptual hashes, for finding every hash
+/// within a Hamming distance of a query without scanning them all. Each hash
+/// is cut into 16-bit chunks with one table per chunk position; two hashes
+/// at most `d` bits apart differ in at most `d / chunks` bits in some chunk,
+/// so only table entries that close to the query's chunks are candidates.
+pub struct HammingIndex<'a> {
+    hashes: &'a [&'a PerceptualHash],
+    tables: Vec<HashMap<u16, Vec<usize>>>,
+}
+
+/// 16-bit chunk `index` of `hash`, zero-padded past its end
+fn chunk(hash: &PerceptualHash, index: usize) -> u16 {
+    let byte = |i: usize| hash.hash.get(i).copied().unwrap_or(0) as u16;
+    byte(2 * index) | byte(2 * index + 1) << 8
+}
+
+/// Every 16-bit value within `radius` bits of `value`
+fn chunk_neighbors(value: u16, radius: u32) -> Vec<u16> {
+    let mut found = vec![value];
+    let mut frontier = vec![(value, 0u32)];
+    for _ in 0..radius.min(CHUNK_BITS) {
+        let mut next = Vec::new();
+        for (v, lowest) in frontier {
+            // Flip bits above the last flipped one so each value comes once
+            for bit in lowest..CHUNK_BITS {
+                let flipped = v ^ (1 << bit);
+                found.push(flipped);
+                next.push((flipped, bit + 1));
+            }
+        }
+        frontier = next;
+    }
+    found
+}
+
+/// Number of 16-bit values within `radius` bits of a value
+fn chunk_neighbor_count(radius: u32) -> usize {
+    let mut total = 0usize;
+    let mut combinations = 1usize;
+    for k in 0..=radius.min(CHUNK_BITS) {
+        total += combinations;
+        combinations = combinations * (CHUNK_BITS - k) as usize / (k + 1) as usize;
+    }
+    total
+}
+
+impl<'a> HammingIndex<'a> {
+    /// Index over all of `hashes`
+    pub fn new(hashes: &'a [&'a PerceptualHash]) -> Self {
+        let bytes = hashes.iter().map(|h| h.hash.len()).max().unwrap_or(0);
+        let mut tables = vec![HashMap::new(); bytes.div_ceil(2)];
+        for (i, hash) in hashes.iter().enumerate() {
+            for (position, table) in tables.iter_mut().enumerate() {
+                table.entry(chunk(hash, position)).or_insert_with(Vec::new).push(i);
+            }
+        }
+        HammingIndex { hashes, tables }
+    }
+
+    /// Indices of the hashes within `max_distance` bits of `query`, in
+    /// ascending order
+    pub fn find(&self, query: &PerceptualHash, max_distance: u32) -> Vec<usize> {
+        let within = |i: &usize| self.hashes[*i].hamming_distance(query) <= max_distance;
+        let radius = max_distance / self.tables.len().max(1) as u32;
+
+        // Loose thresholds probe more values than there are hashes: scan
+        if chunk_neighbor_count(radius) * self.tables.len() >= self.hashes.len() {
+            return (0..self.hashes.len()).filter(within).collect();
+        }
+
+        let mut candidates = Vec::new();
+        for (position, table) in self.tables.iter().enumerate() {
+            for value in chunk_neighbors(chunk(query, position), radius) {
+                if let Some(members) = table.get(&value) {
+                    candidates.extend_from_slice(members);
+                }
+            }
+        }
+        candidates.sort_unstable();
+        candidates.dedup();
+        candidates.retain(within);
+        candidates
+    }
+}
+
+/// Clusters of hashes linked by distances of at most `max_distance` bits,
+/// as indices into `hashes` (see `UnionFind::sets` for the order)
+pub fn cluster_hashes(hashes: &[&PerceptualHash], max_distance: u32) -> Vec<Vec<usize>> {
+    let index = HammingIndex::new(hashes);
+    let neighbors: Vec<Vec<usize>> = hashes
+        .par_iter()
+        .map(|hash| index.find(hash, max_distance))
+        .collect();
+
+    let mut sets = UnionFind::new(hashes.len());
+    for (i, found) in neighbors.iter().enumerate() {
+        for &j in found {
+            sets.union(i, j);
+        }
+    }
+    sets.sets()
+}
+
+/// Hyperplanes per LSH band; more bits make buckets smaller and stricter
+const LSH_BITS: usize = 12;
+/// Independent LSH bands; more bands find more of the close pairs
+const LSH_BANDS: usize = 16;
+
+/// Clusters of vectors linked by pairs for which `is_similar(a, b)` holds.
+/// Candidate pairs are vectors sharing an LSH bucket in some band, so pairs
+/// whose `vectors` point in nearly the same direction are found with high
+/// probability; every candidate is checked with `is_similar`.
+pub fn cluster_vectors<F>(vectors: &[Vec<f32>], is_similar: F) -> Vec<Vec<usize>>
+where
+    F: Fn(usize, usize) -> bool,
+{
+    let mut sets = UnionFind::new(vectors.len());
+    let dims = vectors.first().map_or(0, |v| v.len());
+    if dims == 0 {
+        return sets.sets();
+    }
+
+    // Center the vectors so hyperplanes through the origin split them evenly;
+    // non-negative histograms would otherwise all land on the same side
+    let mut mean = vec![0.0f32; dims];
+    for vector in vectors {
+        for (m, v) in mean.iter_mut().zip(vector) {
+            *m += v / vectors.len() as f32;
+        }
+    }
+    let planes = random_planes(LSH_BITS * LSH_BANDS, dims);
+    let signatures: Vec<Vec<u16>> = vectors
+        .par_iter()
+        .map(|vector| {
+            planes
+                .chunks(LSH_BITS)
+                .map(|band| {
+                    band.iter().enumerate().fold(0u16, |key, (bit, plane)| {
+                        let side: f32 = plane
+                            .iter()
+                            .zip(vector)
+                            .zip(&mean)
+                            .map(|((p, v), m)| p * (v - m))
+                            .sum();
+                        if side >= 0.0 {
+                            key | 1 << bit
+                        } else {
+                            key
+                        }
+                    })
+                })
+                .collect()
+        })
+        .collect();
+
+    let mut buckets: HashMap<(usize, u16), Vec<usize>> = HashMap::new();
+    for (i, signature) in signatures.iter().enumerate() {
+        for (band, &key) in signature.iter().enumerate() {
+            buckets.entry((band, key)).or_default().push(i);
+        }
+    }
+
+    for members in buckets.values() {
+        for (k, &i) in members.iter().enumerate() {
+            for &j in &members[k + 1..] {
+                // Already linked through other pairs: skip the exact check
+                if sets.find(i) != sets.find(j) && is_similar(i, j) {
+                    sets.union(i, j);
+                }
+            }
+        }
+    }
+    sets.sets()
+}
+
+/// `count` pseudo-random Gaussian directions of `dims` dimensions, the same
+/// on every run so grouping is reproducible
+fn random_planes(count: usize, dims: usize) -> Vec<Vec<f32>> {
+    // SplitMix64
+    let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+    let mut next_unit = move || {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        // Uniform in (0, 1]
+        ((z >> 11) as f64 + 1.0) / (1u64 << 53) as f64
+    };
+
+    (0..count)
+        .map(|_| {
+            (0..dims)
+                .map(|_| {
+                    // Box-Muller
+                    let (u1, u2) = (next_unit(), next_unit());
+                    ((-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos()) as f32
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(bits: u64) -> PerceptualHash {
+        PerceptualHash {
+            hash: bits.to_le_bytes().to_vec(),
+            width: 1,
+            height: 1,
+        }
+    }
+
+    #[test]
+    fn test_hamming_index_matches_scan() {
+        // Deterministic spread of hashes, with a few close variants
+        let spread = |i: u64| i.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        let variants = (0..20).map(|i| spread(i) ^ (1 << i) ^ (1 << (i + 7)));
+        let hashes: Vec<PerceptualHash> = (0..200).map(spread).chain(variants).map(hash).collect();
+        let refs: Vec<&PerceptualHash> = hashes.iter().collect();
+        let index = HammingIndex::new(&refs);
+
+        for max_distance in [0, 4, 9] {
+            for query in &refs[..30] {
+                let expected: Vec<usize> = (0..refs.len())
+                    .filter(|&i| refs[i].hamming_distance(query) <= max_distance)
+                    .collect();
+                assert_eq!(index.find(query, max_distance), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_chunk_neighbors() {
+        for radius in 0..3 {
+            let neighbors = chunk_neighbors(0xf0f0, radius);
+            assert_eq!(neighbors.len(), chunk_neighbor_count(radius));
+            assert!(neighbors.iter().all(|v| (v ^ 0xf0f0).count_ones() <= radius));
+        }
+        assert_eq!(chunk_neighbor_count(2), 1 + 16 + 120);
+    }
+
+    #[test]
+    fn test_cluster_hashes_links_chains() {
+        let hashes = [hash(0), hash(0b1111_0000_0000), hash(0b11), hash(0b1111)];
+        let refs: Vec<&PerceptualHash> = hashes.iter().collect();
+        // 0 -> 0b11 -> 0b1111 are 2 bits apart; 0b1111_0000_0000 is on its own
+        assert_eq!(cluster_hashes(&refs, 2), vec![vec![0, 2, 3], vec![1]]);
+        assert_eq!(cluster_hashes(&refs, 0).len(), 4);
+    }
+
+    #[test]
+    fn test_cluster_vectors() {
+        let planes = random_planes(6, 32);
+        // Three well separated directions, each with slightly perturbed copies
+        let mut vectors = Vec::new();
+        for copy in 0..5 {
+            for base in &planes[..3] {
+                let noise = &planes[3 + copy % 3];
+                let vector: Vec<f32> = base.iter().zip(noise).map(|(b, n)| b + 0.05 * n).collect();
+                vectors.push(vector);
+            }
+        }
+        let cosine = |a: &[f32], b: &[f32]| {
+            let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+            let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+            dot / (norm(a) * norm(b))
+        };
+        let clusters = cluster_vectors(&vectors, |i, j| cosine(&vectors[i], &vectors[j]) >= 0.9);
+
+        assert_eq!(clusters.len(), 3);
+        for (base, cluster) in clusters.iter().enumerate() {
+            assert_eq!(cluster, &(0..5).map(|copy| copy * 3 + base).collect::<Vec<_>>());
+        }
+    }
+}
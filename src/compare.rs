@@ -0,0 +1,96 @@
+use image::{imageops::FilterType, DynamicImage, GenericImageView, Rgba, RgbaImage};
+
+/// Longest edge of the composited comparison frame
+const MAX_COMPARE_DIMENSION: u32 = 1920;
+
+/// Width of the divider line drawn at the split position
+const DIVIDER_WIDTH: u32 = 2;
+
+/// Composite two images into a single before/after "wipe" frame.
+///
+/// Columns left of `split` (0.0 to 1.0, fraction of the width) are taken from
+/// `before`, columns right of it from `after`. `after` is stretched to the
+/// dimensions of `before` so an edit can be compared against its original even
+/// if it was cropped or re-exported at another size.
+pub fn wipe_composite(before: &DynamicImage, after: &DynamicImage, split: f32) -> DynamicImage {
+    let before = fit_within(before, MAX_COMPARE_DIMENSION);
+    let (width, height) = before.dimensions();
+
+    let after = if after.dimensions() == (width, height) {
+        after.to_rgba8()
+    } else {
+        after
+            .resize_exact(width, height, FilterType::Triangle)
+            .to_rgba8()
+    };
+    let before = before.to_rgba8();
+
+    let split_x = split_column(width, split);
+    let divider_start = split_x.saturating_sub(DIVIDER_WIDTH / 2);
+    let divider_end = (divider_start + DIVIDER_WIDTH).min(width);
+
+    let mut out = RgbaImage::new(width, height);
+    for (x, y, pixel) in out.enumerate_pixels_mut() {
+        *pixel = if x >= divider_start && x < divider_end && split_x > 0 && split_x < width {
+            Rgba([255, 255, 255, 255])
+        } else if x < split_x {
+            *before.get_pixel(x, y)
+        } else {
+            *after.get_pixel(x, y)
+        };
+    }
+
+    DynamicImage::ImageRgba8(out)
+}
+
+/// Column index where the wipe switches from `before` to `after`
+fn split_column(width: u32, split: f32) -> u32 {
+    ((width as f32) * split.clamp(0.0, 1.0)).round() as u32
+}
+
+/// Downscale an image so its longest edge is at most `max_dimension`
+fn fit_within(img: &DynamicImage, max_dimension: u32) -> DynamicImage {
+    if img.width().max(img.height()) <= max_dimension {
+        img.clone()
+    } else {
+        img.resize(max_dimension, max_dimension, FilterType::Lanczos3)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(width: u32, height: u32, color: [u8; 4]) -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_pixel(width, height, Rgba(color)))
+    }
+
+    #[test]
+    fn test_wipe_composite_takes_sides_from_each_image() {
+        let before = solid(100, 10, [255, 0, 0, 255]);
+        let after = solid(100, 10, [0, 0, 255, 255]);
+
+        let out = wipe_composite(&before, &after, 0.5).to_rgba8();
+        assert_eq!(out.dimensions(), (100, 10));
+        assert_eq!(out.get_pixel(10, 5), &Rgba([255, 0, 0, 255]));
+        assert_eq!(out.get_pixel(90, 5), &Rgba([0, 0, 255, 255]));
+        assert_eq!(out.get_pixel(50, 5), &Rgba([255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn test_wipe_composite_resizes_after_to_before() {
+        let before = solid(40, 20, [255, 0, 0, 255]);
+        let after = solid(80, 80, [0, 255, 0, 255]);
+
+        let out = wipe_composite(&before, &after, 0.0).to_rgba8();
+        assert_eq!(out.dimensions(), (40, 20));
+        assert_eq!(out.get_pixel(0, 0), &Rgba([0, 255, 0, 255]));
+    }
+
+    #[test]
+    fn test_split_column_is_clamped() {
+        assert_eq!(split_column(100, -1.0), 0);
+        assert_eq!(split_column(100, 2.0), 100);
+        assert_eq!(split_column(100, 0.25), 25);
+    }
+}
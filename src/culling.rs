@@ -0,0 +1,123 @@
+// Star ratings and pick/reject flags for culling a shoot in the TUI (keys
+// 1-5, p and x) and for `--min-rating`. Both are kept as hand-added tags in
+// the tag cache, next to the AI tags: `rating:N` for N stars, and `pick` or
+// `reject`. An image has at most one rating and one flag; setting either
+// replaces the old one.
+
+use crate::ai_tagging::{add_manual_tags, load_cached_tags, remove_manual_tags};
+use anyhow::Result;
+use std::path::Path;
+
+/// Highest star rating
+pub const MAX_STARS: u8 = 5;
+
+/// A culling decision on an image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flag {
+    Pick,
+    Reject,
+}
+
+impl Flag {
+    /// The tag that records the flag
+    pub fn tag(self) -> &'static str {
+        match self {
+            Flag::Pick => "pick",
+            Flag::Reject => "reject",
+        }
+    }
+}
+
+/// Stars of the `rating:N` tag among `tags`, 0 to 5
+pub fn stars(tags: &[String]) -> Option<u8> {
+    crate::query::rating(tags)
+        .and_then(|value| value.parse::<f32>().ok())
+        .map(|stars| stars.round().clamp(0.0, MAX_STARS as f32) as u8)
+}
+
+/// The flag among `tags`; a reject wins over a pick
+pub fn flag(tags: &[String]) -> Option<Flag> {
+    [Flag::Reject, Flag::Pick].into_iter().find(|flag| tags.iter().any(|t| t == flag.tag()))
+}
+
+/// Whether `tag` is a rating or a flag rather than a description
+pub fn is_culling_tag(tag: &str) -> bool {
+    tag == Flag::Pick.tag()
+        || tag == Flag::Reject.tag()
+        || crate::query::rating(&[tag.to_string()]).is_some()
+}
+
+/// Cached tags of the image at `path`, none when it has no cache entry
+fn tags_of(cache_dir: &Path, path: &str) -> Vec<String> {
+    load_cached_tags(cache_dir, path).map(|tags| tags.tags).unwrap_or_default()
+}
+
+/// Stars given to the image at `path`
+pub fn cached_stars(cache_dir: &Path, path: &str) -> Option<u8> {
+    stars(&tags_of(cache_dir, path))
+}
+
+/// Flag set on the image at `path`
+pub fn cached_flag(cache_dir: &Path, path: &str) -> Option<Flag> {
+    flag(&tags_of(cache_dir, path))
+}
+
+/// Give the image at `path` `rating` stars, or clear its rating
+pub fn set_stars(cache_dir: &Path, path: &str, rating: Option<u8>) -> Result<()> {
+    let old: Vec<String> = tags_of(cache_dir, path)
+        .into_iter()
+        .filter(|tag| crate::query::rating(std::slice::from_ref(tag)).is_some())
+        .collect();
+    if !old.is_empty() {
+        remove_manual_tags(cache_dir, path, &old)?;
+    }
+    if let Some(rating) = rating {
+        add_manual_tags(cache_dir, path, &[format!("rating:{}", rating.min(MAX_STARS))])?;
+    }
+    Ok(())
+}
+
+/// Flag the image at `path`, or clear its flag
+pub fn set_flag(cache_dir: &Path, path: &str, flag: Option<Flag>) -> Result<()> {
+    let old = [Flag::Pick.tag().to_string(), Flag::Reject.tag().to_string()];
+    remove_manual_tags(cache_dir, path, &old)?;
+    if let Some(flag) = flag {
+        add_manual_tags(cache_dir, path, &[flag.tag().to_string()])?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn test_culling() {
+        let tags = |list: &[&str]| list.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+        assert_eq!(stars(&tags(&["beach", "rating:4"])), Some(4));
+        assert_eq!(stars(&tags(&["rating=9"])), Some(5));
+        assert_eq!(flag(&tags(&["pick", "reject"])), Some(Flag::Reject));
+        assert!(is_culling_tag("rating:2") && is_culling_tag("pick"));
+        assert!(!is_culling_tag("beach"));
+
+        let dir = TestDir::new("culling");
+        let image = dir.join("a.png");
+        std::fs::write(&image, "a").unwrap();
+        let image = image.to_string_lossy().to_string();
+        let cache = dir.join("cache");
+
+        assert_eq!(cached_stars(&cache, &image), None);
+        add_manual_tags(&cache, &image, &["beach".to_string()]).unwrap();
+        set_stars(&cache, &image, Some(3)).unwrap();
+        set_stars(&cache, &image, Some(5)).unwrap();
+        assert_eq!(cached_stars(&cache, &image), Some(5));
+        set_flag(&cache, &image, Some(Flag::Pick)).unwrap();
+        set_flag(&cache, &image, Some(Flag::Reject)).unwrap();
+        assert_eq!(tags_of(&cache, &image), ["beach", "rating:5", "reject"]);
+
+        set_stars(&cache, &image, None).unwrap();
+        set_flag(&cache, &image, None).unwrap();
+        assert_eq!(tags_of(&cache, &image), ["beach"]);
+    }
+}
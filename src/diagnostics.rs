@@ -0,0 +1,142 @@
+// Shared sink for warnings and progress notes. By default only the first
+// few warnings of each kind are printed and the rest are counted, so a messy
+// directory ends with one line per kind ("37 files could not be analyzed")
+// instead of a flood. --verbose prints every warning, --no-warnings drops
+// them, and --quiet drops progress notes as well.
+
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+
+/// Warnings of one kind printed before the rest are only counted
+const INLINE_LIMIT: usize = 3;
+
+/// How much is printed, from least to most
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Neither warnings nor progress notes
+    Quiet,
+    /// Progress notes only
+    NoWarnings,
+    /// Progress notes and the first few warnings of each kind
+    Normal,
+    /// Progress notes and every warning
+    Verbose,
+}
+
+/// What a warning is about; warnings are counted per kind
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WarningKind {
+    NotFound,
+    Analyze,
+    Decode,
+    Grouping,
+    Other,
+}
+
+impl WarningKind {
+    const ALL: [WarningKind; 5] = [
+        WarningKind::NotFound,
+        WarningKind::Analyze,
+        WarningKind::Decode,
+        WarningKind::Grouping,
+        WarningKind::Other,
+    ];
+
+    /// Summary line for `count` warnings of this kind
+    fn describe(self, count: usize) -> String {
+        match self {
+            WarningKind::NotFound => format!("{} files were not found", count),
+            WarningKind::Analyze => format!("{} files could not be analyzed", count),
+            WarningKind::Decode => format!("{} images could not be decoded", count),
+            WarningKind::Grouping => format!("{} images could not be read for grouping", count),
+            WarningKind::Other => format!("{} other warnings", count),
+        }
+    }
+}
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+static COUNTS: [AtomicUsize; WarningKind::ALL.len()] =
+    [const { AtomicUsize::new(0) }; WarningKind::ALL.len()];
+
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+pub fn verbosity() -> Verbosity {
+    match VERBOSITY.load(Ordering::Relaxed) {
+        0 => Verbosity::Quiet,
+        1 => Verbosity::NoWarnings,
+        2 => Verbosity::Normal,
+        _ => Verbosity::Verbose,
+    }
+}
+
+/// Print a progress note unless --quiet
+pub fn info(message: &str) {
+    if verbosity() > Verbosity::Quiet {
+        eprintln!("{}", message);
+    }
+}
+
+/// Report a warning; printed or counted depending on the verbosity
+pub fn warn(kind: WarningKind, message: &str) {
+    let seen = COUNTS[kind as usize].fetch_add(1, Ordering::Relaxed);
+    let show = match verbosity() {
+        Verbosity::Verbose => true,
+        Verbosity::Normal => seen < INLINE_LIMIT,
+        Verbosity::Quiet | Verbosity::NoWarnings => false,
+    };
+    if show {
+        eprintln!("Warning: {}", message);
+    }
+}
+
+/// Lines for the warnings that were counted but not printed
+pub fn format_summary(counts: &[(WarningKind, usize)], verbosity: Verbosity) -> Vec<String> {
+    if verbosity != Verbosity::Normal {
+        return Vec::new();
+    }
+    counts
+        .iter()
+        .filter(|(_, count)| *count > INLINE_LIMIT)
+        .map(|(kind, count)| format!("{}, use --verbose for details", kind.describe(*count)))
+        .collect()
+}
+
+/// Print the aggregated warnings, once, at the end of a run
+pub fn print_summary() {
+    let counts: Vec<(WarningKind, usize)> = WarningKind::ALL
+        .iter()
+        .map(|&kind| (kind, COUNTS[kind as usize].swap(0, Ordering::Relaxed)))
+        .collect();
+    for line in format_summary(&counts, verbosity()) {
+        eprintln!("Warning: {}", line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_summary() {
+        let counts = [
+            (WarningKind::NotFound, 2),
+            (WarningKind::Analyze, 37),
+            (WarningKind::Decode, 0),
+        ];
+
+        // Warnings up to the inline limit were already printed one by one
+        assert_eq!(
+            format_summary(&counts, Verbosity::Normal),
+            vec!["37 files could not be analyzed, use --verbose for details"]
+        );
+        assert!(format_summary(&counts, Verbosity::Verbose).is_empty());
+        assert!(format_summary(&counts, Verbosity::Quiet).is_empty());
+    }
+
+    #[test]
+    fn test_verbosity_order() {
+        assert!(Verbosity::Quiet < Verbosity::NoWarnings);
+        assert!(Verbosity::Normal < Verbosity::Verbose);
+    }
+}
@@ -0,0 +1,132 @@
+// Folder sidebar of the TUI browser: the subdirectories of one directory,
+// with `..` on top, so a whole photo library can be walked without starting
+// lsix again for every folder. Opening a folder lists its subdirectories
+// and shows its images in the grid; going up selects the folder that was
+// left. Hidden folders are not listed.
+
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+/// Subdirectories of `dir`, sorted by name ignoring case
+pub fn subdirectories(dir: &Path) -> Result<Vec<PathBuf>> {
+    let entries =
+        std::fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))?;
+    let mut dirs: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .collect();
+    dirs.sort_by_key(|path| {
+        path.file_name().map(|n| n.to_string_lossy().to_lowercase()).unwrap_or_default()
+    });
+    Ok(dirs)
+}
+
+/// The directory listed in the sidebar and the highlighted entry
+#[derive(Debug, Clone, PartialEq)]
+pub struct DirTree {
+    pub dir: PathBuf,
+    pub subdirs: Vec<PathBuf>,
+    /// Highlighted entry; 0 is `..` when the directory has a parent
+    pub selected: usize,
+}
+
+impl DirTree {
+    /// List `dir`, made absolute so that `..` leads somewhere
+    pub fn open(dir: &Path) -> Result<DirTree> {
+        let dir = dir.canonicalize().with_context(|| format!("Can't open {}", dir.display()))?;
+        let subdirs = subdirectories(&dir)?;
+        Ok(DirTree { dir, subdirs, selected: 0 })
+    }
+
+    fn has_parent(&self) -> bool {
+        self.dir.parent().is_some()
+    }
+
+    /// Entries shown, in order: `..` (if any), then the subdirectories
+    pub fn entries(&self) -> Vec<String> {
+        let names = self.subdirs.iter().map(|path| {
+            format!("{}/", path.file_name().unwrap_or_default().to_string_lossy())
+        });
+        self.has_parent().then(|| "../".to_string()).into_iter().chain(names).collect()
+    }
+
+    /// Directory of the highlighted entry
+    pub fn selected_dir(&self) -> Option<PathBuf> {
+        match (self.has_parent(), self.selected) {
+            (true, 0) => self.dir.parent().map(Path::to_path_buf),
+            (true, idx) => self.subdirs.get(idx - 1).cloned(),
+            (false, idx) => self.subdirs.get(idx).cloned(),
+        }
+    }
+
+    /// Move the highlight by `delta` entries, stopping at either end
+    pub fn move_selection(&mut self, delta: isize) {
+        let last = self.entries().len().saturating_sub(1);
+        self.selected = self.selected.saturating_add_signed(delta).min(last);
+    }
+
+    /// Highlight `dir` if it is listed
+    fn select(&mut self, dir: &Path) {
+        if let Some(idx) = self.subdirs.iter().position(|path| path == dir) {
+            self.selected = idx + usize::from(self.has_parent());
+        }
+    }
+
+    /// List the highlighted directory instead. Returns the directory now
+    /// listed.
+    pub fn enter(&mut self) -> Result<PathBuf> {
+        let Some(dir) = self.selected_dir() else {
+            anyhow::bail!("No folder to open");
+        };
+        let left = self.dir.clone();
+        *self = DirTree::open(&dir)?;
+        // Coming back up through `..`: keep the way down highlighted
+        self.select(&left);
+        Ok(self.dir.clone())
+    }
+
+    /// List the parent directory instead, with the one left highlighted
+    pub fn up(&mut self) -> Result<PathBuf> {
+        let Some(parent) = self.dir.parent().map(Path::to_path_buf) else {
+            anyhow::bail!("{} has no parent folder", self.dir.display());
+        };
+        let left = self.dir.clone();
+        *self = DirTree::open(&parent)?;
+        self.select(&left);
+        Ok(self.dir.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn test_dir_tree() {
+        let root = TestDir::new("dirtree");
+        for dir in ["Beach", "alps", ".thumbs", "alps/2023"] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        std::fs::write(root.join("notes.txt"), "").unwrap();
+
+        let mut tree = DirTree::open(&root).unwrap();
+        assert_eq!(tree.entries(), ["../", "alps/", "Beach/"]);
+        tree.move_selection(1);
+        assert_eq!(tree.selected_dir(), Some(tree.dir.join("alps")));
+        tree.move_selection(5);
+        assert_eq!(tree.selected, 2);
+
+        tree.selected = 1;
+        let alps = tree.enter().unwrap();
+        assert!(alps.ends_with("alps"));
+        assert_eq!(tree.entries(), ["../", "2023/"]);
+        assert_eq!(tree.selected, 0);
+
+        // Up again, back on the folder that was left
+        tree.up().unwrap();
+        assert_eq!(tree.entries()[tree.selected], "alps/");
+    }
+}
@@ -0,0 +1,257 @@
+// Duplicate detection for --find-duplicates. Exact duplicates share a
+// content hash; near-duplicates are images whose dHash differs in at most a
+// few bits (re-encodes, resizes, small edits). Each group lists the copy
+// worth keeping first, which is what the TUI dedup prompt leaves alone.
+
+use crate::blob_cache::content_hash;
+use crate::diagnostics::{self, WarningKind};
+use crate::grouping::{dhash, GroupMetadata, ImageGroup, PerceptualHash};
+use crate::image_proc::open_image;
+use rayon::prelude::*;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Default maximum dHash distance (in bits, out of 64) for near-duplicates
+pub const DEFAULT_MAX_DISTANCE: u32 = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicateKind {
+    /// Byte-for-byte identical files
+    Exact,
+    /// Visually the same picture
+    Near,
+}
+
+/// Images that are copies of each other
+#[derive(Debug, Clone)]
+pub struct DuplicateGroup {
+    pub kind: DuplicateKind,
+    /// Paths, the copy to keep first
+    pub images: Vec<String>,
+}
+
+impl DuplicateGroup {
+    /// Copies that can go, i.e. everything but the first image
+    pub fn extras(&self) -> &[String] {
+        &self.images[1..]
+    }
+}
+
+/// Find exact and near-duplicate groups among `paths`. Exact copies are
+/// represented by a single image in the near-duplicate search, so a file is
+/// never listed in two groups.
+pub fn find_duplicates(paths: &[String], max_distance: u32) -> Vec<DuplicateGroup> {
+    let hashed: Vec<(String, String, u64)> = paths
+        .par_iter()
+        .filter_map(|path| match fs::read(path) {
+            Ok(data) => Some((path.clone(), content_hash(&data), data.len() as u64)),
+            Err(e) => {
+                diagnostics::warn(WarningKind::NotFound, &format!("{}: {}", path, e));
+                None
+            }
+        })
+        .collect();
+
+    // Exact duplicates, in input order
+    let mut by_hash: HashMap<&str, Vec<usize>> = HashMap::new();
+    let mut hash_order = Vec::new();
+    for (i, (_, hash, _)) in hashed.iter().enumerate() {
+        let members = by_hash.entry(hash.as_str()).or_default();
+        if members.is_empty() {
+            hash_order.push(hash.as_str());
+        }
+        members.push(i);
+    }
+
+    let mut groups = Vec::new();
+    let mut representatives = Vec::new();
+    for hash in hash_order {
+        let mut members = by_hash[hash].clone();
+        // Identical content: keep the shortest path
+        members.sort_by_key(|&i| (hashed[i].0.len(), hashed[i].0.as_str()));
+        representatives.push(members[0]);
+        if members.len() > 1 {
+            groups.push(DuplicateGroup {
+                kind: DuplicateKind::Exact,
+                images: members.iter().map(|&i| hashed[i].0.clone()).collect(),
+            });
+        }
+    }
+
+    // Near duplicates among one copy of each distinct file
+    let perceptual: Vec<(usize, PerceptualHash)> = representatives
+        .par_iter()
+        .filter_map(|&i| match open_image(&hashed[i].0) {
+            Ok(img) => Some((i, dhash(&img))),
+            Err(e) => {
+                diagnostics::warn(WarningKind::Grouping, &format!("{:#}", e));
+                None
+            }
+        })
+        .collect();
+
+    let hashes: Vec<&PerceptualHash> = perceptual.iter().map(|(_, h)| h).collect();
+    for cluster in cluster_near(&hashes, max_distance) {
+        let mut members: Vec<&(usize, PerceptualHash)> =
+            cluster.iter().map(|&c| &perceptual[c]).collect();
+        // Keep the largest picture, then the largest file
+        members.sort_by_key(|(i, hash)| {
+            let (path, _, size) = &hashed[*i];
+            (
+                Reverse(hash.width as u64 * hash.height as u64),
+                Reverse(*size),
+                path.as_str(),
+            )
+        });
+        groups.push(DuplicateGroup {
+            kind: DuplicateKind::Near,
+            images: members.iter().map(|(i, _)| hashed[*i].0.clone()).collect(),
+        });
+    }
+
+    groups
+}
+
+/// Clusters (of two or more) of hashes within `max_distance` bits of the
+/// first hash of the cluster, as indices into `hashes`
+pub fn cluster_near(hashes: &[&PerceptualHash], max_distance: u32) -> Vec<Vec<usize>> {
+    let mut clusters = Vec::new();
+    let mut assigned = vec![false; hashes.len()];
+
+    for i in 0..hashes.len() {
+        if assigned[i] {
+            continue;
+        }
+        assigned[i] = true;
+
+        let mut cluster = vec![i];
+        for j in i + 1..hashes.len() {
+            if !assigned[j] && hashes[i].hamming_distance(hashes[j]) <= max_distance {
+                cluster.push(j);
+                assigned[j] = true;
+            }
+        }
+        if cluster.len() > 1 {
+            clusters.push(cluster);
+        }
+    }
+    clusters
+}
+
+/// Text report: one block per group, the kept copy marked
+pub fn format_report(groups: &[DuplicateGroup]) -> String {
+    let mut out = String::new();
+    for (i, group) in groups.iter().enumerate() {
+        let kind = match group.kind {
+            DuplicateKind::Exact => "exact duplicates",
+            DuplicateKind::Near => "near duplicates",
+        };
+        out.push_str(&format!("Group {}: {} ({} files)\n", i + 1, kind, group.images.len()));
+        for (j, path) in group.images.iter().enumerate() {
+            let mark = if j == 0 { "keep" } else { "    " };
+            out.push_str(&format!("  {}  {}\n", mark, path));
+        }
+    }
+
+    let exact = groups.iter().filter(|g| g.kind == DuplicateKind::Exact).count();
+    let extras: usize = groups.iter().map(|g| g.extras().len()).sum();
+    out.push_str(&format!(
+        "{} exact and {} near-duplicate groups; {} files could be removed\n",
+        exact,
+        groups.len() - exact,
+        extras
+    ));
+    out
+}
+
+/// Duplicate groups as image groups for the grouped grid display
+pub fn to_image_groups(groups: &[DuplicateGroup]) -> Vec<ImageGroup> {
+    groups
+        .iter()
+        .enumerate()
+        .map(|(i, group)| {
+            let name = match group.kind {
+                DuplicateKind::Exact => "Exact duplicates",
+                DuplicateKind::Near => "Near duplicates",
+            };
+            let keep = Path::new(&group.images[0])
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default();
+            ImageGroup {
+                id: format!("duplicates_{}", i),
+                name: name.to_string(),
+                images: group.images.clone(),
+                representative: group.images[0].clone(),
+                metadata: GroupMetadata {
+                    group_type: "duplicates".to_string(),
+                    count: group.images.len(),
+                    common_features: HashMap::from([("keep".to_string(), keep)]),
+                },
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+    use image::{DynamicImage, RgbImage};
+
+    fn gradient(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+            let v = ((x * 255 / width + y * 64 / height) % 256) as u8;
+            image::Rgb([v, v / 2, 255 - v])
+        }))
+    }
+
+    fn checkerboard() -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(64, 64, |x, y| {
+            if (x / 8 + y / 8) % 2 == 0 {
+                image::Rgb([0, 0, 0])
+            } else {
+                image::Rgb([255, 255, 255])
+            }
+        }))
+    }
+
+    #[test]
+    fn test_find_duplicates() {
+        let dir = TestDir::new("duplicates-find");
+        let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+
+        gradient(128, 96).save(path("a.png")).unwrap();
+        fs::copy(path("a.png"), path("copy-of-a.png")).unwrap();
+        gradient(64, 48).save(path("a-small.png")).unwrap();
+        checkerboard().save(path("other.png")).unwrap();
+
+        let paths: Vec<String> = ["copy-of-a.png", "a.png", "a-small.png", "other.png"]
+            .iter()
+            .map(|n| path(n))
+            .collect();
+        let groups = find_duplicates(&paths, DEFAULT_MAX_DISTANCE);
+
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].kind, DuplicateKind::Exact);
+        assert_eq!(groups[0].images, vec![path("a.png"), path("copy-of-a.png")]);
+        // The larger picture is kept
+        assert_eq!(groups[1].kind, DuplicateKind::Near);
+        assert_eq!(groups[1].images, vec![path("a.png"), path("a-small.png")]);
+    }
+
+    #[test]
+    fn test_format_report() {
+        let report = format_report(&[DuplicateGroup {
+            kind: DuplicateKind::Exact,
+            images: vec!["a.png".to_string(), "b.png".to_string()],
+        }]);
+        assert_eq!(
+            report,
+            "Group 1: exact duplicates (2 files)\n  keep  a.png\n        b.png\n\
+             1 exact and 0 near-duplicate groups; 1 files could be removed\n"
+        );
+    }
+}
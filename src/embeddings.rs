@@ -0,0 +1,457 @@
+// Image embeddings for semantic search (`--search "dog on a beach"`).
+//
+// Images and the query text are embedded by a CLIP-style model behind an
+// OpenAI-compatible embeddings endpoint (a local model server such as
+// infinity, or a hosted API), and images are ranked by cosine similarity to
+// the query. Vectors are cached per model in ~/.cache/lsix/embeddings/,
+// keyed by path and trusted while the file's size and modification time are
+// unchanged, so only new images reach the endpoint.
+
+use crate::ai_tagging::{encode_image_to_base64, image_media_type, RateLimiter};
+use crate::diagnostics::{self, Verbosity, WarningKind};
+use crate::hash_index::file_stamp;
+use crate::image_proc::get_cache_dir;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+/// Default number of images returned by --search
+pub const DEFAULT_SEARCH_LIMIT: usize = 20;
+
+/// Per-request timeout
+const REQUEST_TIMEOUT_SECS: u64 = 60;
+
+/// Embeddings endpoint configuration.
+///
+/// `Default` reads `LSIX_EMBED_ENDPOINT`, `LSIX_EMBED_MODEL` and
+/// `LSIX_EMBED_API_KEY` (falling back to `LSIX_AI_API_KEY`).
+#[derive(Debug, Clone)]
+pub struct EmbeddingConfig {
+    /// OpenAI-compatible embeddings endpoint serving a CLIP-style model
+    pub api_endpoint: String,
+    /// API key; may be empty for local servers
+    pub api_key: String,
+    /// Model name sent with each request; cached vectors are kept per model
+    pub model: String,
+    /// Where vectors are cached; `None` disables caching
+    pub cache_dir: Option<PathBuf>,
+    /// Maximum API requests in flight at once
+    pub concurrency: usize,
+    /// Upper bound on API requests started per minute; `None` for no limit
+    pub requests_per_minute: Option<u32>,
+    /// Print request failures with the response body
+    pub debug: bool,
+}
+
+impl Default for EmbeddingConfig {
+    fn default() -> Self {
+        let api_key = std::env::var("LSIX_EMBED_API_KEY")
+            .or_else(|_| std::env::var("LSIX_AI_API_KEY"))
+            .unwrap_or_default();
+
+        Self {
+            api_endpoint: std::env::var("LSIX_EMBED_ENDPOINT")
+                .unwrap_or_else(|_| "http://localhost:7997/v1/embeddings".to_string()),
+            api_key,
+            model: std::env::var("LSIX_EMBED_MODEL")
+                .unwrap_or_else(|_| "openai/clip-vit-base-patch32".to_string()),
+            cache_dir: get_cache_dir().ok().map(|dir| dir.join("embeddings")),
+            concurrency: crate::ai_tagging::DEFAULT_AI_CONCURRENCY,
+            requests_per_minute: None,
+            debug: false,
+        }
+    }
+}
+
+impl EmbeddingConfig {
+    /// Whether requests need an API key; local servers do without
+    pub fn needs_api_key(&self) -> bool {
+        !self.api_endpoint.contains("localhost") && !self.api_endpoint.contains("127.0.0.1")
+    }
+}
+
+/// Embedding of one file, with what it was computed from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedEmbedding {
+    size: u64,
+    /// Modification time, nanoseconds since the epoch
+    modified: u64,
+    vector: Vec<f32>,
+}
+
+/// Cached image embeddings of one model
+#[derive(Debug, Default)]
+struct EmbeddingIndex {
+    path: Option<PathBuf>,
+    entries: HashMap<String, CachedEmbedding>,
+    dirty: bool,
+}
+
+/// Cache file for `model`; any character unsafe in a file name becomes `_`
+fn index_file(cache_dir: &Path, model: &str) -> PathBuf {
+    let name: String = model
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '.' { c } else { '_' })
+        .collect();
+    cache_dir.join(format!("{}.json", name))
+}
+
+impl EmbeddingIndex {
+    fn load(config: &EmbeddingConfig) -> EmbeddingIndex {
+        let Some(path) = config.cache_dir.as_ref().map(|dir| index_file(dir, &config.model)) else {
+            return EmbeddingIndex::default();
+        };
+        let entries = fs::read_to_string(&path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        EmbeddingIndex {
+            path: Some(path),
+            entries,
+            dirty: false,
+        }
+    }
+
+    /// Stored vector of `image`, unless the file changed since
+    fn get(&self, image: &str) -> Option<Vec<f32>> {
+        let entry = self.entries.get(image)?;
+        let (size, modified) = file_stamp(image)?;
+        (entry.size == size && entry.modified == modified).then(|| entry.vector.clone())
+    }
+
+    fn insert(&mut self, image: &str, vector: Vec<f32>) {
+        if let Some((size, modified)) = file_stamp(image) {
+            let entry = CachedEmbedding {
+                size,
+                modified,
+                vector,
+            };
+            self.entries.insert(image.to_string(), entry);
+            self.dirty = true;
+        }
+    }
+
+    fn save(&mut self) -> Result<()> {
+        let Some(path) = self.path.as_ref().filter(|_| self.dirty) else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        self.entries.retain(|image, _| Path::new(image).exists());
+        let json = serde_json::to_string(&self.entries)?;
+        let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+        fs::write(&tmp, json).with_context(|| format!("Failed to write {}", tmp.display()))?;
+        fs::rename(&tmp, path).with_context(|| format!("Failed to write {}", path.display()))?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+/// HTTP client for the embeddings API
+fn api_client() -> Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+        .build()
+        .context("Failed to create HTTP client")
+}
+
+/// Request body embedding one image, sent as a data URL. Servers that host
+/// CLIP next to text models pick the image tower from `modality`.
+fn image_request_body(model: &str, image_base64: &str, media_type: &str) -> serde_json::Value {
+    json!({
+        "model": model,
+        "input": [format!("data:{};base64,{}", media_type, image_base64)],
+        "modality": "image",
+        "encoding_format": "float"
+    })
+}
+
+/// Request body embedding a text query
+fn text_request_body(model: &str, text: &str) -> serde_json::Value {
+    json!({
+        "model": model,
+        "input": [text],
+        "modality": "text",
+        "encoding_format": "float"
+    })
+}
+
+/// First vector of an embeddings response: OpenAI's `data[0].embedding`, or
+/// Ollama's `embeddings[0]`
+fn parse_embedding(response: &serde_json::Value) -> Result<Vec<f32>> {
+    let vector = response
+        .pointer("/data/0/embedding")
+        .or_else(|| response.pointer("/embeddings/0"))
+        .and_then(|v| v.as_array())
+        .ok_or_else(|| anyhow::anyhow!("No embedding in response"))?;
+    let vector: Vec<f32> = vector
+        .iter()
+        .map(|x| x.as_f64().map(|x| x as f32))
+        .collect::<Option<_>>()
+        .ok_or_else(|| anyhow::anyhow!("Embedding contains a non-number"))?;
+    if vector.is_empty() {
+        anyhow::bail!("Empty embedding in response");
+    }
+    Ok(vector)
+}
+
+/// Send one embeddings request
+async fn request_embedding(
+    client: &reqwest::Client,
+    config: &EmbeddingConfig,
+    body: &serde_json::Value,
+) -> Result<Vec<f32>> {
+    let mut request = client
+        .post(&config.api_endpoint)
+        .header("Content-Type", "application/json");
+    if !config.api_key.is_empty() {
+        request = request.header("Authorization", format!("Bearer {}", config.api_key));
+    }
+
+    let response = request.json(body).send().await.context("Failed to call embeddings API")?;
+    let status = response.status();
+    if !status.is_success() {
+        let error_text = response.text().await.unwrap_or_default();
+        anyhow::bail!("Embeddings API error ({}): {}", status, error_text);
+    }
+
+    let response_json: serde_json::Value =
+        response.json().await.context("Failed to parse embeddings response")?;
+    if config.debug {
+        eprintln!("Embeddings response: {}", response_json);
+    }
+    parse_embedding(&response_json)
+}
+
+/// Embed one image, waiting on `limiter` before calling the API
+async fn embed_image(
+    client: &reqwest::Client,
+    config: &EmbeddingConfig,
+    image_path: &str,
+    limiter: &RateLimiter,
+) -> Result<Vec<f32>> {
+    let image_base64 = encode_image_to_base64(image_path)
+        .with_context(|| format!("Failed to read {}", image_path))?;
+    let body = image_request_body(&config.model, &image_base64, image_media_type(image_path));
+    limiter.wait().await;
+    request_embedding(client, config, &body)
+        .await
+        .with_context(|| format!("Failed to embed {}", image_path))
+}
+
+/// Embeddings of `image_paths`, from the cache where possible. New images
+/// are embedded concurrently (at most `config.concurrency` requests in
+/// flight); Ctrl-C stops early and returns the images embedded so far.
+/// Images that fail are reported as warnings and left out.
+pub fn embed_images(
+    image_paths: &[String],
+    config: &EmbeddingConfig,
+) -> Result<HashMap<String, Vec<f32>>> {
+    let mut index = EmbeddingIndex::load(config);
+    let mut vectors: HashMap<String, Vec<f32>> = image_paths
+        .iter()
+        .filter_map(|path| Some((path.clone(), index.get(path)?)))
+        .collect();
+    let missing: Vec<String> = image_paths
+        .iter()
+        .filter(|path| !vectors.contains_key(*path))
+        .cloned()
+        .collect();
+    if missing.is_empty() {
+        return Ok(vectors);
+    }
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .context("Failed to start async runtime")?;
+
+    let progress = if diagnostics::verbosity() == Verbosity::Quiet {
+        indicatif::ProgressBar::hidden()
+    } else {
+        indicatif::ProgressBar::new(missing.len() as u64)
+    };
+    progress.set_style(
+        indicatif::ProgressStyle::default_bar()
+            .template("{spinner:.green} [{bar:40.cyan/blue}] {pos}/{len} {msg}")
+            .unwrap()
+            .progress_chars("##-"),
+    );
+    progress.set_message("Embedding images");
+
+    let client = api_client()?;
+    let config = Arc::new(config.clone());
+    let limiter = Arc::new(RateLimiter::new(config.requests_per_minute));
+    let permits = Arc::new(Semaphore::new(config.concurrency.max(1)));
+
+    let _interrupt = crate::interrupt::guard();
+    let cancelled = runtime.block_on(async {
+        let mut tasks = JoinSet::new();
+        for path in missing.iter().cloned() {
+            let (client, config, limiter, permits) =
+                (client.clone(), config.clone(), limiter.clone(), permits.clone());
+            tasks.spawn(async move {
+                let result = match permits.acquire_owned().await {
+                    Ok(_permit) => embed_image(&client, &config, &path, &limiter).await,
+                    Err(e) => Err(e.into()),
+                };
+                (path, result)
+            });
+        }
+
+        let ctrl_c = tokio::signal::ctrl_c();
+        tokio::pin!(ctrl_c);
+        loop {
+            tokio::select! {
+                joined = tasks.join_next() => match joined {
+                    Some(Ok((path, result))) => {
+                        progress.inc(1);
+                        match result {
+                            Ok(vector) => {
+                                index.insert(&path, vector.clone());
+                                vectors.insert(path, vector);
+                            }
+                            Err(e) => progress.suspend(|| {
+                                diagnostics::warn(WarningKind::Other, &format!("{:#}", e));
+                            }),
+                        }
+                    }
+                    Some(Err(_)) => {} // Aborted or panicked task
+                    None => return false,
+                },
+                _ = &mut ctrl_c => {
+                    tasks.abort_all();
+                    return true;
+                }
+            }
+        }
+    });
+
+    if cancelled {
+        progress.abandon_with_message("Embedding interrupted");
+        diagnostics::info(&format!(
+            "Interrupted: searching the {} of {} images embedded so far",
+            vectors.len(),
+            image_paths.len()
+        ));
+    } else {
+        progress.finish_and_clear();
+    }
+    if let Err(e) = index.save() {
+        diagnostics::warn(WarningKind::Other, &format!("{:#}", e));
+    }
+
+    Ok(vectors)
+}
+
+/// Embed a text query
+pub fn embed_text(text: &str, config: &EmbeddingConfig) -> Result<Vec<f32>> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("Failed to start async runtime")?;
+    let client = api_client()?;
+    runtime
+        .block_on(request_embedding(&client, config, &text_request_body(&config.model, text)))
+        .context("Failed to embed the search query")
+}
+
+/// Cosine similarity of two vectors, 0.0 when either is zero or their
+/// lengths differ
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+/// Images by similarity to `query`, best first, at most `limit` of them
+pub fn rank(
+    query: &[f32],
+    vectors: &HashMap<String, Vec<f32>>,
+    limit: usize,
+) -> Vec<(String, f32)> {
+    let mut ranked: Vec<(String, f32)> = vectors
+        .iter()
+        .map(|(path, vector)| (path.clone(), cosine_similarity(query, vector)))
+        .collect();
+    // Ties fall back to the path so results are stable between runs
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    ranked.truncate(limit);
+    ranked
+}
+
+/// The `limit` images of `image_paths` best matching `query`, with their
+/// similarity scores
+pub fn search(
+    image_paths: &[String],
+    query: &str,
+    limit: usize,
+    config: &EmbeddingConfig,
+) -> Result<Vec<(String, f32)>> {
+    let query = embed_text(query, config)?;
+    let vectors = embed_images(image_paths, config)?;
+    Ok(rank(&query, &vectors, limit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_embedding() {
+        let openai = json!({"object": "list", "data": [{"index": 0, "embedding": [0.5, -1.0]}]});
+        assert_eq!(parse_embedding(&openai).unwrap(), vec![0.5, -1.0]);
+
+        let ollama = json!({"model": "clip", "embeddings": [[1.0, 2.0, 3.0]]});
+        assert_eq!(parse_embedding(&ollama).unwrap(), vec![1.0, 2.0, 3.0]);
+
+        assert!(parse_embedding(&json!({"error": "no model"})).is_err());
+        assert!(parse_embedding(&json!({"data": [{"embedding": []}]})).is_err());
+    }
+
+    #[test]
+    fn test_rank() {
+        let vectors: HashMap<String, Vec<f32>> = [
+            ("beach.jpg", vec![1.0, 0.1]),
+            ("dog.jpg", vec![0.6, 0.8]),
+            ("city.jpg", vec![-1.0, 0.0]),
+        ]
+        .into_iter()
+        .map(|(path, vector)| (path.to_string(), vector))
+        .collect();
+
+        let ranked = rank(&[0.0, 2.0], &vectors, 2);
+        let paths: Vec<&str> = ranked.iter().map(|(path, _)| path.as_str()).collect();
+        assert_eq!(paths, ["dog.jpg", "beach.jpg"]);
+        assert!((ranked[0].1 - 0.8).abs() < 1e-6);
+
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[0.0, 0.0]), 0.0);
+        assert_eq!(cosine_similarity(&[1.0], &[1.0, 0.0]), 0.0);
+    }
+
+    #[test]
+    fn test_index_file_name() {
+        let dir = Path::new("/cache");
+        assert_eq!(
+            index_file(dir, "openai/clip-vit-base-patch32"),
+            Path::new("/cache/openai_clip-vit-base-patch32.json")
+        );
+    }
+}
@@ -0,0 +1,154 @@
+// File management for the TUI browser: trash/delete, rename, move and copy.
+// Operations never overwrite an existing file.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// File operations offered by the TUI browser
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileOp {
+    /// Move to the desktop trash
+    Trash,
+    /// Remove permanently
+    Delete,
+    /// Rename in place
+    Rename,
+    /// Move into another directory
+    Move,
+    /// Copy into another directory
+    Copy,
+}
+
+/// Move files to the trash (freedesktop trash on Linux)
+pub fn trash_files(paths: &[String]) -> Result<()> {
+    trash::delete_all(paths).context("Failed to move files to trash")
+}
+
+/// Permanently delete files
+pub fn delete_files(paths: &[String]) -> Result<()> {
+    for path in paths {
+        fs::remove_file(path).with_context(|| format!("Failed to delete {}", path))?;
+    }
+    Ok(())
+}
+
+/// Rename a file within its directory. Returns the new path.
+pub fn rename_file(path: &str, new_name: &str) -> Result<String> {
+    let new_name = new_name.trim();
+    if new_name.is_empty() || new_name.contains('/') || new_name == "." || new_name == ".." {
+        anyhow::bail!("Invalid file name: {:?}", new_name);
+    }
+
+    let target = Path::new(path)
+        .parent()
+        .unwrap_or_else(|| Path::new(""))
+        .join(new_name);
+    if target.exists() {
+        anyhow::bail!("{} already exists", target.display());
+    }
+
+    fs::rename(path, &target)
+        .with_context(|| format!("Failed to rename {} to {}", path, new_name))?;
+    Ok(target.to_string_lossy().to_string())
+}
+
+/// Move files into `dir`, falling back to copy + delete across filesystems.
+/// Returns the new paths.
+pub fn move_files(paths: &[String], dir: &str) -> Result<Vec<String>> {
+    let mut moved = Vec::with_capacity(paths.len());
+    for path in paths {
+        let target = target_path(path, dir)?;
+        if fs::rename(path, &target).is_err() {
+            fs::copy(path, &target)
+                .with_context(|| format!("Failed to move {} to {}", path, dir))?;
+            fs::remove_file(path).with_context(|| format!("Failed to remove {}", path))?;
+        }
+        moved.push(target.to_string_lossy().to_string());
+    }
+    Ok(moved)
+}
+
+/// Copy files into `dir`. Returns the paths of the copies.
+pub fn copy_files(paths: &[String], dir: &str) -> Result<Vec<String>> {
+    let mut copied = Vec::with_capacity(paths.len());
+    for path in paths {
+        let target = target_path(path, dir)?;
+        fs::copy(path, &target).with_context(|| format!("Failed to copy {} to {}", path, dir))?;
+        copied.push(target.to_string_lossy().to_string());
+    }
+    Ok(copied)
+}
+
+/// Destination of `path` inside `dir` (`~` expanded); it must not exist yet
+fn target_path(path: &str, dir: &str) -> Result<PathBuf> {
+    let dir = expand_tilde(dir.trim());
+    if !dir.is_dir() {
+        anyhow::bail!("Not a directory: {}", dir.display());
+    }
+
+    let file_name = Path::new(path)
+        .file_name()
+        .with_context(|| format!("Invalid path: {}", path))?;
+    let target = dir.join(file_name);
+    if target.exists() {
+        anyhow::bail!("{} already exists", target.display());
+    }
+    Ok(target)
+}
+
+fn expand_tilde(path: &str) -> PathBuf {
+    match path.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => {
+            let home = std::env::var("HOME").unwrap_or_default();
+            PathBuf::from(format!("{}{}", home, rest))
+        }
+        _ => PathBuf::from(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn test_rename_refuses_overwrite() {
+        let dir = TestDir::new("file-ops-rename");
+        let a = dir.join("a.png");
+        fs::write(&a, b"a").unwrap();
+        fs::write(dir.join("b.png"), b"b").unwrap();
+
+        let a = a.to_string_lossy().to_string();
+        assert!(rename_file(&a, "b.png").is_err());
+        assert!(rename_file(&a, "sub/c.png").is_err());
+
+        let renamed = rename_file(&a, "c.png").unwrap();
+        assert!(renamed.ends_with("c.png"));
+        assert!(Path::new(&renamed).exists());
+    }
+
+    #[test]
+    fn test_copy_and_move() {
+        let dir = TestDir::new("file-ops-move");
+        let dest = dir.join("dest");
+        fs::create_dir(&dest).unwrap();
+        let src = dir.join("x.png");
+        fs::write(&src, b"x").unwrap();
+
+        let paths = vec![src.to_string_lossy().to_string()];
+        let dest_str = dest.to_string_lossy().to_string();
+
+        let copied = copy_files(&paths, &dest_str).unwrap();
+        assert!(Path::new(&copied[0]).exists());
+        assert!(src.exists());
+
+        // The copy now occupies the destination name
+        assert!(move_files(&paths, &dest_str).is_err());
+
+        fs::remove_file(&copied[0]).unwrap();
+        let moved = move_files(&paths, &dest_str).unwrap();
+        assert!(Path::new(&moved[0]).exists());
+        assert!(!src.exists());
+    }
+}
@@ -1,3 +1,4 @@
+/// How image labels are derived from file paths
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum FilenameMode {
     Short, // Only basename (default)
@@ -84,16 +85,18 @@ pub fn process_image_path(path: &str, explicit: bool) -> String {
 /// Find image files in the current directory
 /// Returns a sorted list of image file paths
 pub fn find_image_files() -> Vec<String> {
-    let extensions = [
-        "jpg", "jpeg", "png", "gif", "webp", "tiff", "tif", "pnm", "ppm", "pgm", "pbm", "pam",
-        "xbm", "xpm", "bmp", "ico", "svg", "eps",
-    ];
+    let extensions = crate::image_proc::IMAGE_EXTENSIONS.iter().copied();
 
     let mut files = Vec::new();
 
-    for ext in &extensions {
+    let videos = crate::video_thumbs::VIDEO_EXTENSIONS
+        .into_iter()
+        .filter(|ext| crate::video_thumbs::listed_in_directories(ext));
+    // IMG_0001.JPG and IMG_0002.HEIC from cameras and phones too
+    let options = glob::MatchOptions { case_sensitive: false, ..Default::default() };
+    for ext in extensions.chain(videos) {
         let pattern = format!("*.{}", ext);
-        if let Ok(entries) = glob::glob(&pattern) {
+        if let Ok(entries) = glob::glob_with(&pattern, options) {
             for entry in entries.filter_map(|e| e.ok()) {
                 if entry.is_file() {
                     if let Some(path_str) = entry.to_str() {
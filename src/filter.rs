@@ -1,19 +1,28 @@
+use crate::diagnostics::{self, WarningKind};
+use crate::tools::{self, Tool};
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::path::Path;
-use std::process::{Command, Stdio};
+use std::process::Command;
 
 /// Image analysis results
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ImageFeatures {
+    /// Width in pixels
     pub width: u32,
+    /// Height in pixels
     pub height: u32,
+    /// File size in bytes
     pub file_size: u64,
-    pub brightness: f32,        // 0.0 (dark) to 1.0 (bright)
-    pub dominant_color: String, // Hex color
+    /// Mean brightness, 0.0 (dark) to 1.0 (bright)
+    pub brightness: f32,
+    /// Hex color
+    pub dominant_color: String,
+    /// Orientation derived from the aspect ratio
     pub orientation: ImageOrientation,
 }
 
+/// Image orientation, with a 10% tolerance for square
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ImageOrientation {
     Landscape, // width > height
@@ -21,7 +30,8 @@ pub enum ImageOrientation {
     Square,    // width == height (within tolerance)
 }
 
-/// Filter criteria for images
+/// Filter criteria for images. Unset (`None`) criteria always pass;
+/// size bounds are inclusive.
 #[derive(Debug, Clone)]
 pub struct FilterConfig {
     // Size filters
@@ -57,64 +67,104 @@ impl Default for FilterConfig {
 }
 
 impl FilterConfig {
+    /// Check if any filter criterion is set
+    pub fn is_active(&self) -> bool {
+        self.min_width.is_some()
+            || self.max_width.is_some()
+            || self.min_height.is_some()
+            || self.max_height.is_some()
+            || self.min_file_size.is_some()
+            || self.max_file_size.is_some()
+            || self.min_brightness.is_some()
+            || self.max_brightness.is_some()
+            || self.orientation.is_some()
+    }
+
     /// Check if an image matches all filter criteria
+    #[allow(dead_code)]
     pub fn matches(&self, features: &ImageFeatures) -> bool {
+        self.rejection_reasons(features).is_empty()
+    }
+
+    /// Describe every criterion the image fails (e.g. "width 800 < 1920").
+    /// An empty list means the image matches.
+    pub fn rejection_reasons(&self, features: &ImageFeatures) -> Vec<String> {
+        let mut reasons = Vec::new();
+
         // Width filter
         if let Some(min_w) = self.min_width {
             if features.width < min_w {
-                return false;
+                reasons.push(format!("width {} < {}", features.width, min_w));
             }
         }
         if let Some(max_w) = self.max_width {
             if features.width > max_w {
-                return false;
+                reasons.push(format!("width {} > {}", features.width, max_w));
             }
         }
 
         // Height filter
         if let Some(min_h) = self.min_height {
             if features.height < min_h {
-                return false;
+                reasons.push(format!("height {} < {}", features.height, min_h));
             }
         }
         if let Some(max_h) = self.max_height {
             if features.height > max_h {
-                return false;
+                reasons.push(format!("height {} > {}", features.height, max_h));
             }
         }
 
         // File size filter
         if let Some(min_size) = self.min_file_size {
             if features.file_size < min_size {
-                return false;
+                reasons.push(format!(
+                    "size {} < {}",
+                    format_file_size(features.file_size),
+                    format_file_size(min_size)
+                ));
             }
         }
         if let Some(max_size) = self.max_file_size {
             if features.file_size > max_size {
-                return false;
+                reasons.push(format!(
+                    "size {} > {}",
+                    format_file_size(features.file_size),
+                    format_file_size(max_size)
+                ));
             }
         }
 
         // Brightness filter
         if let Some(min_bright) = self.min_brightness {
             if features.brightness < min_bright {
-                return false;
+                reasons.push(format!(
+                    "brightness {:.2} < {:.2}",
+                    features.brightness, min_bright
+                ));
             }
         }
         if let Some(max_bright) = self.max_brightness {
             if features.brightness > max_bright {
-                return false;
+                reasons.push(format!(
+                    "brightness {:.2} > {:.2}",
+                    features.brightness, max_bright
+                ));
             }
         }
 
         // Orientation filter
         if let Some(orient) = self.orientation {
             if features.orientation != orient {
-                return false;
+                reasons.push(format!(
+                    "{} != {}",
+                    orientation_name(features.orientation),
+                    orientation_name(orient)
+                ));
             }
         }
 
-        true
+        reasons
     }
 }
 
@@ -126,23 +176,21 @@ pub fn analyze_image(path: &str) -> Result<ImageFeatures> {
     let metadata = std::fs::metadata(path_obj).context("Failed to get file metadata")?;
     let file_size = metadata.len();
 
-    // Use ImageMagick identify to get image info
-    let identify_cmd = if Command::new("magick")
-        .arg("identify")
-        .arg("-version")
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .status()
-        .map(|s| s.success())
-        .unwrap_or(false)
-    {
-        "magick"
-    } else {
-        "identify"
+    // Use ImageMagick identify to get image info: a subcommand of magick in
+    // ImageMagick 7, a program of its own in 6
+    let magick = tools::require(Tool::ImageMagick, "Image analysis")?;
+    let identify = || {
+        if magick == "magick" {
+            let mut cmd = Command::new("magick");
+            cmd.arg("identify");
+            cmd
+        } else {
+            Command::new("identify")
+        }
     };
 
     // Get image dimensions and format
-    let output = Command::new(identify_cmd)
+    let output = identify()
         .arg("-format")
         .arg("%w %h") // width height
         .arg(path)
@@ -159,18 +207,10 @@ pub fn analyze_image(path: &str) -> Result<ImageFeatures> {
     let width: u32 = parts[0].parse().context("Failed to parse width")?;
     let height: u32 = parts[1].parse().context("Failed to parse height")?;
 
-    // Determine orientation
-    let aspect_ratio = width as f32 / height as f32;
-    let orientation = if aspect_ratio > 1.1 {
-        ImageOrientation::Landscape
-    } else if aspect_ratio < 0.9 {
-        ImageOrientation::Portrait
-    } else {
-        ImageOrientation::Square
-    };
+    let orientation = orientation_of(width, height);
 
     // Get brightness (using ImageMagick to analyze)
-    let brightness_output = Command::new(identify_cmd)
+    let brightness_output = identify()
         .arg("-format")
         .arg("%[mean]") // mean brightness
         .arg(path)
@@ -178,10 +218,17 @@ pub fn analyze_image(path: &str) -> Result<ImageFeatures> {
         .context("Failed to get brightness")?;
 
     let brightness_str = String::from_utf8_lossy(&brightness_output.stdout);
-    let brightness: f32 = brightness_str.trim().parse().unwrap_or(0.5) / 65535.0; // ImageMagick returns 16-bit value
+    let brightness: f32 = match brightness_str.trim().parse::<f32>() {
+        Ok(mean) => mean / 65535.0, // ImageMagick returns 16-bit value
+        Err(_) => {
+            let message = format!("Could not read brightness of {}, assuming 50%", path);
+            diagnostics::warn(WarningKind::Analyze, &message);
+            0.5
+        }
+    };
 
     // Get dominant color (simplified - just take center pixel)
-    let color_output = Command::new(identify_cmd)
+    let color_output = identify()
         .arg("-format")
         .arg("%[pixel:p{50%,50%}]") // center pixel color
         .arg(path)
@@ -202,6 +249,18 @@ pub fn analyze_image(path: &str) -> Result<ImageFeatures> {
     })
 }
 
+/// Orientation of a `width` x `height` image, square within 10%
+pub fn orientation_of(width: u32, height: u32) -> ImageOrientation {
+    let aspect_ratio = width as f32 / height as f32;
+    if aspect_ratio > 1.1 {
+        ImageOrientation::Landscape
+    } else if aspect_ratio < 0.9 {
+        ImageOrientation::Portrait
+    } else {
+        ImageOrientation::Square
+    }
+}
+
 /// Parse orientation from string
 pub fn parse_orientation(s: &str) -> Result<ImageOrientation> {
     match s.to_lowercase().as_str() {
@@ -215,6 +274,33 @@ pub fn parse_orientation(s: &str) -> Result<ImageOrientation> {
     }
 }
 
+/// Lowercase name of an orientation, as accepted by `parse_orientation`
+pub fn orientation_name(orientation: ImageOrientation) -> &'static str {
+    match orientation {
+        ImageOrientation::Landscape => "landscape",
+        ImageOrientation::Portrait => "portrait",
+        ImageOrientation::Square => "square",
+    }
+}
+
+/// Format a byte count in the same units `parse_file_size` accepts (e.g. "1.5M")
+pub fn format_file_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["K", "M", "G", "T"];
+
+    if bytes < 1024 {
+        return bytes.to_string();
+    }
+
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{:.1}{}", value, UNITS[unit])
+}
+
 /// Parse human-readable file size (e.g., "100K", "2M", "1G")
 pub fn parse_file_size(s: &str) -> Result<u64> {
     let s = s.trim().to_uppercase();
@@ -307,4 +393,40 @@ mod tests {
         };
         assert!(!filter.matches(&features_portrait));
     }
+
+    #[test]
+    fn test_rejection_reasons() {
+        let filter = FilterConfig {
+            min_width: Some(1920),
+            min_file_size: Some(1024 * 1024),
+            orientation: Some(ImageOrientation::Portrait),
+            ..Default::default()
+        };
+
+        let features = ImageFeatures {
+            width: 800,
+            height: 600,
+            file_size: 512 * 1024,
+            brightness: 0.5,
+            dominant_color: "#ffffff".to_string(),
+            orientation: ImageOrientation::Landscape,
+        };
+
+        assert_eq!(
+            filter.rejection_reasons(&features),
+            vec![
+                "width 800 < 1920".to_string(),
+                "size 512.0K < 1.0M".to_string(),
+                "landscape != portrait".to_string(),
+            ]
+        );
+        assert!(FilterConfig::default().rejection_reasons(&features).is_empty());
+    }
+
+    #[test]
+    fn test_format_file_size() {
+        assert_eq!(format_file_size(100), "100");
+        assert_eq!(format_file_size(1536), "1.5K");
+        assert_eq!(format_file_size(3 * 1024 * 1024), "3.0M");
+    }
 }
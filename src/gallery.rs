@@ -0,0 +1,369 @@
+// Static HTML gallery (--export-html DIR): the selected images as a site to
+// share or open offline. index.html carries its own CSS and JavaScript;
+// thumbs/ and images/ hold downscaled JPEG copies, so the originals are
+// neither linked nor copied. Cached tags become filter buttons, groups
+// become sections, and clicking a thumbnail opens it in a lightbox.
+
+use crate::ai_tagging::load_cached_tags;
+use crate::image_proc::open_image;
+use anyhow::{Context, Result};
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::DynamicImage;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::fs;
+use std::io::BufWriter;
+use std::path::Path;
+
+/// Longest side of gallery thumbnails, in pixels
+const THUMB_SIZE: u32 = 320;
+/// Longest side of the images shown in the lightbox
+const PREVIEW_SIZE: u32 = 1600;
+const JPEG_QUALITY: u8 = 85;
+
+/// A titled run of images; the title is empty for an ungrouped gallery
+#[derive(Debug, Clone)]
+pub struct Section {
+    pub name: String,
+    pub images: Vec<String>,
+}
+
+/// One exported image, as index.html refers to it
+#[derive(Debug, Clone)]
+struct GalleryImage {
+    /// File name of the original
+    name: String,
+    /// Base name of the files in thumbs/ and images/
+    file: String,
+    width: u32,
+    height: u32,
+    tags: Vec<String>,
+    caption: Option<String>,
+}
+
+/// What an export wrote
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GalleryStats {
+    pub images: usize,
+    pub failed: usize,
+    pub tags: usize,
+}
+
+/// Write `sections` as a gallery titled `title` into `out`, with tags and
+/// captions from the tag cache at `tag_cache`. Images that can't be
+/// decoded are left out with a warning.
+pub fn export_gallery(
+    sections: &[Section],
+    title: &str,
+    out: &Path,
+    tag_cache: Option<&Path>,
+) -> Result<GalleryStats> {
+    for dir in [out.to_path_buf(), out.join("thumbs"), out.join("images")] {
+        fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+
+    // Number the images across sections so every file name is unique
+    let jobs: Vec<(usize, usize, &String)> = sections
+        .iter()
+        .enumerate()
+        .flat_map(|(section, s)| s.images.iter().map(move |path| (section, path)))
+        .enumerate()
+        .map(|(number, (section, path))| (section, number + 1, path))
+        .collect();
+
+    let exported: Vec<(usize, Option<GalleryImage>)> = jobs
+        .par_iter()
+        .map(|&(section, number, path)| match export_image(path, number, out, tag_cache) {
+            Ok(image) => (section, Some(image)),
+            Err(e) => {
+                eprintln!("Warning: skipping {}: {:#}", path, e);
+                (section, None)
+            }
+        })
+        .collect();
+
+    let mut rendered: Vec<(&str, Vec<GalleryImage>)> =
+        sections.iter().map(|s| (s.name.as_str(), Vec::new())).collect();
+    let mut failed = 0;
+    for (section, image) in exported {
+        match image {
+            Some(image) => rendered[section].1.push(image),
+            None => failed += 1,
+        }
+    }
+    rendered.retain(|(_, images)| !images.is_empty());
+
+    let tag_counts = tag_counts(rendered.iter().flat_map(|(_, images)| images));
+    let html = render_html(title, &rendered, &tag_counts);
+    let index = out.join("index.html");
+    fs::write(&index, html).with_context(|| format!("Failed to write {}", index.display()))?;
+
+    Ok(GalleryStats {
+        images: rendered.iter().map(|(_, images)| images.len()).sum(),
+        failed,
+        tags: tag_counts.len(),
+    })
+}
+
+/// Write the thumbnail and preview of `path` as image number `number`
+fn export_image(
+    path: &str,
+    number: usize,
+    out: &Path,
+    tag_cache: Option<&Path>,
+) -> Result<GalleryImage> {
+    let img = open_image(path)?;
+    let file = format!("{:04}.jpg", number);
+    let thumb = shrink(&img, THUMB_SIZE, FilterType::Triangle);
+    let preview = shrink(&img, PREVIEW_SIZE, FilterType::Lanczos3);
+    save_jpeg(&thumb, &out.join("thumbs").join(&file))?;
+    save_jpeg(&preview, &out.join("images").join(&file))?;
+
+    let file_path = path.trim_end_matches("[0]");
+    let cached = tag_cache.and_then(|dir| load_cached_tags(dir, file_path).ok());
+    Ok(GalleryImage {
+        name: Path::new(file_path)
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| file_path.to_string()),
+        file,
+        width: preview.width(),
+        height: preview.height(),
+        tags: cached.as_ref().map(|t| t.tags.clone()).unwrap_or_default(),
+        caption: cached.and_then(|t| t.caption),
+    })
+}
+
+/// `img` scaled down to fit `size` x `size`; smaller images are kept as is
+fn shrink(img: &DynamicImage, size: u32, filter: FilterType) -> DynamicImage {
+    if img.width().max(img.height()) > size {
+        img.resize(size, size, filter)
+    } else {
+        img.clone()
+    }
+}
+
+fn save_jpeg(img: &DynamicImage, path: &Path) -> Result<()> {
+    let file =
+        fs::File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    JpegEncoder::new_with_quality(BufWriter::new(file), JPEG_QUALITY)
+        .encode_image(&img.to_rgb8())
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Tags with how many images have each, most common first
+fn tag_counts<'a>(images: impl Iterator<Item = &'a GalleryImage>) -> Vec<(String, usize)> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for image in images {
+        for tag in &image.tags {
+            *counts.entry(tag).or_default() += 1;
+        }
+    }
+    let mut counts: Vec<(String, usize)> =
+        counts.into_iter().map(|(tag, n)| (tag.to_string(), n)).collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Page styles: a dark, responsive grid and a full-window lightbox
+const STYLE: &str = "\
+body{margin:0;font-family:system-ui,sans-serif;background:#111;color:#ddd}
+header{padding:16px 24px}
+h1{margin:0 0 12px;font-size:1.4em}
+h2{margin:24px 24px 8px;font-size:1.1em;color:#aaa}
+#tags button{margin:0 6px 6px 0;padding:3px 10px;border:1px solid #555;border-radius:12px;\
+background:#222;color:#ddd;cursor:pointer}
+#tags button.on{background:#4a7;border-color:#4a7;color:#111}
+.grid{display:grid;grid-template-columns:repeat(auto-fill,minmax(200px,1fr));gap:12px;\
+padding:0 24px}
+figure{margin:0;cursor:pointer}
+figure img{width:100%;aspect-ratio:1;object-fit:cover;border-radius:4px;background:#222}
+figcaption{font-size:.8em;padding:4px 0;overflow:hidden;text-overflow:ellipsis;\
+white-space:nowrap}
+.hidden{display:none}
+#lightbox{position:fixed;inset:0;background:rgba(0,0,0,.92);display:flex;\
+flex-direction:column;align-items:center;justify-content:center}
+#lightbox img{max-width:96vw;max-height:88vh}
+#lightbox p{margin:10px;text-align:center}
+#lightbox.hidden{display:none}
+";
+
+/// Tag filtering (images must have every selected tag) and the lightbox,
+/// which steps through the images currently shown
+const SCRIPT: &str = "\
+const selected=new Set();
+const items=[...document.querySelectorAll('figure')];
+function filter(){
+  for(const f of items){
+    const tags=f.dataset.tags?f.dataset.tags.split('|'):[];
+    f.classList.toggle('hidden',![...selected].every(t=>tags.includes(t)));
+  }
+  for(const s of document.querySelectorAll('section')){
+    s.classList.toggle('hidden',!s.querySelector('figure:not(.hidden)'));
+  }
+}
+for(const b of document.querySelectorAll('#tags button')){
+  b.onclick=()=>{
+    const t=b.dataset.tag;
+    if(selected.has(t)){selected.delete(t)}else{selected.add(t)}
+    b.classList.toggle('on',selected.has(t));
+    filter();
+  };
+}
+const box=document.getElementById('lightbox');
+let current=null;
+function show(f){
+  current=f;
+  box.querySelector('img').src=f.dataset.full;
+  box.querySelector('p').textContent=f.dataset.caption||f.querySelector('figcaption').textContent;
+  box.classList.remove('hidden');
+}
+function step(d){
+  const shown=items.filter(f=>!f.classList.contains('hidden'));
+  const i=shown.indexOf(current);
+  if(shown.length)show(shown[(i+d+shown.length)%shown.length]);
+}
+for(const f of items)f.onclick=()=>show(f);
+box.onclick=()=>box.classList.add('hidden');
+document.onkeydown=e=>{
+  if(box.classList.contains('hidden'))return;
+  if(e.key==='Escape')box.classList.add('hidden');
+  if(e.key==='ArrowRight')step(1);
+  if(e.key==='ArrowLeft')step(-1);
+};
+";
+
+/// index.html for `sections` of exported images
+fn render_html(
+    title: &str,
+    sections: &[(&str, Vec<GalleryImage>)],
+    tag_counts: &[(String, usize)],
+) -> String {
+    let count: usize = sections.iter().map(|(_, images)| images.len()).sum();
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width,initial-scale=1\">\n\
+         <title>{}</title>\n<style>\n{}</style>\n</head>\n<body>\n<header>\n\
+         <h1>{} <small>({} images)</small></h1>\n<div id=\"tags\">",
+        html_escape(title),
+        STYLE,
+        html_escape(title),
+        count
+    );
+    for (tag, n) in tag_counts {
+        html.push_str(&format!(
+            "<button data-tag=\"{0}\">{0} <small>{1}</small></button>",
+            html_escape(tag),
+            n
+        ));
+    }
+    html.push_str("</div>\n</header>\n");
+
+    for (name, images) in sections {
+        html.push_str("<section>\n");
+        if !name.is_empty() {
+            html.push_str(&format!(
+                "<h2>{} <small>({})</small></h2>\n",
+                html_escape(name),
+                images.len()
+            ));
+        }
+        html.push_str("<div class=\"grid\">\n");
+        for image in images {
+            let caption = image
+                .caption
+                .as_ref()
+                .map(|c| format!(" data-caption=\"{}\"", html_escape(c)))
+                .unwrap_or_default();
+            html.push_str(&format!(
+                "<figure data-tags=\"{}\" data-full=\"images/{}\"{}>\
+                 <img src=\"thumbs/{}\" loading=\"lazy\" alt=\"{}\" \
+                 width=\"{}\" height=\"{}\"><figcaption>{}</figcaption></figure>\n",
+                html_escape(&image.tags.join("|")),
+                image.file,
+                caption,
+                image.file,
+                html_escape(&image.name),
+                image.width,
+                image.height,
+                html_escape(&image.name)
+            ));
+        }
+        html.push_str("</div>\n</section>\n");
+    }
+
+    html.push_str(&format!(
+        "<div id=\"lightbox\" class=\"hidden\"><img alt=\"\"><p></p></div>\n\
+         <script>\n{}</script>\n</body>\n</html>\n",
+        SCRIPT
+    ));
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn test_export_gallery() {
+        let dir = TestDir::new("gallery");
+        let src = dir.join("src");
+        fs::create_dir_all(&src).unwrap();
+        let image = |name: &str, size: u32| {
+            let path = src.join(name);
+            image::RgbImage::from_pixel(size, size / 2, image::Rgb([10, 120, 200]))
+                .save(&path)
+                .unwrap();
+            path.to_string_lossy().to_string()
+        };
+        let (small, large) = (image("small <1>.png", 100), image("large.png", 1800));
+        let broken = src.join("broken.png").to_string_lossy().to_string();
+        fs::write(&broken, b"not an image").unwrap();
+
+        let sections = vec![
+            Section { name: "Beach & sea".to_string(), images: vec![small, broken] },
+            Section { name: "Empty".to_string(), images: Vec::new() },
+            Section { name: "Later".to_string(), images: vec![large] },
+        ];
+        let out = dir.join("gallery");
+        let stats = export_gallery(&sections, "Trip", &out, None).unwrap();
+        assert_eq!(stats, GalleryStats { images: 2, failed: 1, tags: 0 });
+
+        // Previews are capped; small images keep their size
+        assert_eq!(image::image_dimensions(out.join("images/0003.jpg")).unwrap(), (1600, 800));
+        assert_eq!(image::image_dimensions(out.join("thumbs/0001.jpg")).unwrap(), (100, 50));
+        assert!(!out.join("images/0002.jpg").exists());
+
+        let html = fs::read_to_string(out.join("index.html")).unwrap();
+        assert!(html.contains("Beach &amp; sea"));
+        assert!(html.contains("small &lt;1&gt;.png"));
+        assert!(!html.contains("Empty"));
+    }
+
+    #[test]
+    fn test_tag_counts() {
+        let image = |tags: &[&str]| GalleryImage {
+            name: String::new(),
+            file: String::new(),
+            width: 1,
+            height: 1,
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            caption: None,
+        };
+        let images = [image(&["sea", "dog"]), image(&["dog"]), image(&[])];
+        assert_eq!(
+            tag_counts(images.iter()),
+            [("dog".to_string(), 2), ("sea".to_string(), 1)]
+        );
+    }
+}
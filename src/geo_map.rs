@@ -0,0 +1,332 @@
+// Where a geotagged photo was taken, for the TUI info panel: a small map
+// around the capture position with a marker on it, and a link (the map key,
+// M) that opens the position in a browser or maps app.
+//
+// The map is pieced together from slippy-map tiles, the 256-pixel PNG tiles
+// of OpenStreetMap and most tile servers, stored as
+// ~/.cache/lsix/tiles/Z/X/Y.png. Tiles are read from there first, so maps
+// work offline for places seen before or for a cache filled by another
+// tool. Missing tiles are downloaded only when a tile server is configured
+// (`[tui] map_tiles`, e.g. "https://tile.openstreetmap.org/{z}/{x}/{y}.png");
+// without one, places outside the cache have no map.
+
+use crate::image_proc::get_cache_dir;
+use crate::scrub::gps_position;
+use crate::tools::{self, Tool};
+use anyhow::{Context, Result};
+use image::{imageops, DynamicImage, Rgba, RgbaImage};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::Duration;
+
+/// Pixel size of a tile
+const TILE_SIZE: u32 = 256;
+
+/// Zoom level of the map: streets and blocks
+pub const MAP_ZOOM: u32 = 15;
+
+/// Pixel size of the (square) map
+pub const MAP_SIZE: u32 = 256;
+
+/// Page the map key opens when `[tui] map_url` isn't set
+const DEFAULT_MAP_URL: &str =
+    "https://www.openstreetmap.org/?mlat={lat}&mlon={lon}#map={zoom}/{lat}/{lon}";
+
+/// Per-tile download timeout
+const TILE_TIMEOUT_SECS: u64 = 15;
+
+/// Web Mercator stops short of the poles
+const MAX_LATITUDE: f64 = 85.051_128_78;
+
+/// Color of a tile that couldn't be had
+const NO_TILE: Rgba<u8> = Rgba([200, 200, 200, 255]);
+
+/// Capture position of the image at `path` as decimal (latitude,
+/// longitude), from its GPS EXIF tags
+pub fn read_position(path: &str) -> Option<(f64, f64)> {
+    let file = fs::File::open(path).ok()?;
+    let exif = exif::Reader::new()
+        .read_from_container(&mut std::io::BufReader::new(file))
+        .ok()?;
+    gps_position(&exif).filter(|(lat, lon)| lat.abs() <= 90.0 && lon.abs() <= 180.0)
+}
+
+/// Position of (`lat`, `lon`) in pixels on the whole world map at `zoom`
+pub fn world_pixel(lat: f64, lon: f64, zoom: u32) -> (f64, f64) {
+    let size = (TILE_SIZE as f64) * 2f64.powi(zoom as i32);
+    let lat = lat.clamp(-MAX_LATITUDE, MAX_LATITUDE).to_radians();
+    let x = (lon + 180.0) / 360.0 * size;
+    let y = (1.0 - (lat.tan() + 1.0 / lat.cos()).ln() / std::f64::consts::PI) / 2.0 * size;
+    (x, y)
+}
+
+/// `template` with `{z}`, `{x}` and `{y}` filled in
+pub fn tile_url(template: &str, zoom: u32, x: u32, y: u32) -> String {
+    template
+        .replace("{z}", &zoom.to_string())
+        .replace("{x}", &x.to_string())
+        .replace("{y}", &y.to_string())
+}
+
+/// Page showing (`lat`, `lon`): `template` with `{lat}`, `{lon}` and
+/// `{zoom}` filled in
+pub fn map_link(template: &str, lat: f64, lon: f64) -> String {
+    template
+        .replace("{lat}", &format!("{:.6}", lat))
+        .replace("{lon}", &format!("{:.6}", lon))
+        .replace("{zoom}", &MAP_ZOOM.to_string())
+}
+
+/// Where tiles are cached
+fn tile_dir() -> Result<std::path::PathBuf> {
+    Ok(get_cache_dir()?.join("tiles"))
+}
+
+/// Tile `x`, `y` at `zoom` from `dir`, downloaded from `server` into `dir`
+/// if it isn't there
+fn load_tile(
+    dir: &Path,
+    server: Option<(&str, &reqwest::blocking::Client)>,
+    zoom: u32,
+    x: u32,
+    y: u32,
+) -> Result<RgbaImage> {
+    let path = dir.join(zoom.to_string()).join(x.to_string()).join(format!("{}.png", y));
+    if let Ok(tile) = image::open(&path) {
+        return Ok(tile.to_rgba8());
+    }
+    let Some((template, client)) = server else {
+        anyhow::bail!("No tiles cached here; set [tui] map_tiles to download them");
+    };
+
+    let url = tile_url(template, zoom, x, y);
+    let data = client
+        .get(&url)
+        .send()
+        .and_then(|r| r.error_for_status())
+        .and_then(|r| r.bytes())
+        .with_context(|| format!("Failed to download {}", url))?;
+    let tile = image::load_from_memory(&data)
+        .with_context(|| format!("{} is not an image", url))?
+        .to_rgba8();
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // Write then rename so a concurrent reader never sees a partial tile
+    let tmp = path.with_extension(format!("png.{}", std::process::id()));
+    tile.save_with_format(&tmp, image::ImageFormat::Png)?;
+    fs::rename(&tmp, &path)?;
+    Ok(tile)
+}
+
+/// Square map of `MAP_SIZE` pixels centered on (`lat`, `lon`), with a
+/// marker there. Tiles that can't be had are left gray; with none at all
+/// there is no map.
+pub fn render_map(lat: f64, lon: f64, dir: &Path, server: Option<&str>) -> Result<RgbaImage> {
+    let client = match server {
+        Some(_) => Some(
+            reqwest::blocking::Client::builder()
+                .user_agent(concat!("lsix/", env!("CARGO_PKG_VERSION")))
+                .timeout(Duration::from_secs(TILE_TIMEOUT_SECS))
+                .build()?,
+        ),
+        None => None,
+    };
+    let server = server.zip(client.as_ref());
+
+    let (x, y) = world_pixel(lat, lon, MAP_ZOOM);
+    let (size, tile_size, tiles) = (MAP_SIZE as i64, TILE_SIZE as i64, 1i64 << MAP_ZOOM);
+    let left = x.round() as i64 - size / 2;
+    let top = y.round() as i64 - size / 2;
+
+    let mut map = RgbaImage::from_pixel(MAP_SIZE, MAP_SIZE, NO_TILE);
+    let mut error = None;
+    let mut found = false;
+    // Tiles under `size` pixels from `start`
+    let covered =
+        |start: i64| start.div_euclid(tile_size)..=(start + size - 1).div_euclid(tile_size);
+    for tile_y in covered(top).filter(|tile_y| (0..tiles).contains(tile_y)) {
+        for tile_x in covered(left) {
+            // Across the date line the world repeats
+            let wrapped = tile_x.rem_euclid(tiles) as u32;
+            match load_tile(dir, server, MAP_ZOOM, wrapped, tile_y as u32) {
+                Ok(tile) => {
+                    let (x, y) = (tile_x * tile_size - left, tile_y * tile_size - top);
+                    imageops::overlay(&mut map, &tile, x, y);
+                    found = true;
+                }
+                Err(e) => error = error.or(Some(e)),
+            }
+        }
+    }
+    if !found {
+        return Err(error.unwrap_or_else(|| anyhow::anyhow!("No map tiles")));
+    }
+
+    draw_marker(&mut map, (x.round() as i64 - left) as i32, (y.round() as i64 - top) as i32);
+    Ok(map)
+}
+
+/// Red dot with a white ring at `cx`, `cy`
+fn draw_marker(map: &mut RgbaImage, cx: i32, cy: i32) {
+    for dy in -7i32..=7 {
+        for dx in -7i32..=7 {
+            let distance = dx * dx + dy * dy;
+            let color = match distance {
+                0..=20 => Rgba([220, 30, 30, 255]),
+                21..=49 => Rgba([255, 255, 255, 255]),
+                _ => continue,
+            };
+            let (px, py) = (cx + dx, cy + dy);
+            if px >= 0 && py >= 0 && (px as u32) < map.width() && (py as u32) < map.height() {
+                map.put_pixel(px as u32, py as u32, color);
+            }
+        }
+    }
+}
+
+/// Open (`lat`, `lon`) in a browser or maps app: the page `template`
+/// (`[tui] map_url`) names, else OpenStreetMap. Returns the link.
+pub fn open_map(template: Option<&str>, lat: f64, lon: f64) -> Result<String> {
+    let link = map_link(template.unwrap_or(DEFAULT_MAP_URL), lat, lon);
+    let mut child = Command::new(tools::require(Tool::XdgOpen, "Opening maps")?)
+        .arg(&link)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .context("Failed to run xdg-open")?;
+    // xdg-open returns once the browser is started; reap it off the UI thread
+    std::thread::spawn(move || child.wait());
+    Ok(link)
+}
+
+/// A map being made or made, or why there is none
+pub enum MapState<'a> {
+    Loading,
+    Ready(&'a DynamicImage),
+    Failed(&'a str),
+}
+
+type MapJob = (String, (f64, f64));
+type MapResult = (String, Option<Result<DynamicImage, String>>);
+
+/// Makes maps on a worker thread so the draw loop never waits on the
+/// network. Only the latest request is worked on; ones the cursor has
+/// already moved past are dropped rather than downloaded.
+pub struct MapLoader {
+    server: Option<String>,
+    jobs: Option<Sender<MapJob>>,
+    tx: Sender<MapResult>,
+    rx: Receiver<MapResult>,
+    pending: HashSet<String>,
+    maps: HashMap<String, Result<DynamicImage, String>>,
+}
+
+impl MapLoader {
+    /// Missing tiles are downloaded from `server` (`[tui] map_tiles`), if
+    /// there is one
+    pub fn new(server: Option<String>) -> Self {
+        let (tx, rx) = mpsc::channel();
+        MapLoader { server, jobs: None, tx, rx, pending: HashSet::new(), maps: HashMap::new() }
+    }
+
+    /// Queue a map of `position` for the image at `path`, unless it was
+    /// made or queued before
+    pub fn request(&mut self, path: &str, position: (f64, f64)) {
+        if self.maps.contains_key(path) || !self.pending.insert(path.to_string()) {
+            return;
+        }
+        let jobs = self.jobs.get_or_insert_with(|| {
+            let (jobs, queue) = mpsc::channel::<MapJob>();
+            let tx = self.tx.clone();
+            let server = self.server.clone();
+            std::thread::spawn(move || {
+                while let Ok(mut job) = queue.recv() {
+                    while let Ok(newer) = queue.try_recv() {
+                        let _ = tx.send((std::mem::replace(&mut job, newer).0, None));
+                    }
+                    let (path, (lat, lon)) = job;
+                    let map = tile_dir()
+                        .and_then(|dir| render_map(lat, lon, &dir, server.as_deref()))
+                        .map(DynamicImage::ImageRgba8)
+                        .map_err(|e| format!("{:#}", e));
+                    let _ = tx.send((path, Some(map)));
+                }
+            });
+            jobs
+        });
+        let _ = jobs.send((path.to_string(), position));
+    }
+
+    /// Collect finished maps without blocking. Returns whether any arrived.
+    pub fn receive(&mut self) -> bool {
+        let mut any = false;
+        while let Ok((path, map)) = self.rx.try_recv() {
+            self.pending.remove(&path);
+            if let Some(map) = map {
+                self.maps.insert(path, map);
+                any = true;
+            }
+        }
+        any
+    }
+
+    /// Map of the image at `path`; Loading until it is requested and made
+    pub fn get(&self, path: &str) -> MapState<'_> {
+        match self.maps.get(path) {
+            Some(Ok(map)) => MapState::Ready(map),
+            Some(Err(e)) => MapState::Failed(e),
+            None => MapState::Loading,
+        }
+    }
+
+    /// Make the map of `path` again, e.g. after its file was rewritten
+    pub fn forget(&mut self, path: &str) {
+        self.maps.remove(path);
+    }
+}
+
+impl Default for MapLoader {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn test_render_map() {
+        let (x, y) = world_pixel(0.0, 0.0, 1);
+        assert_eq!((x, y), (256.0, 256.0));
+        let (x, y) = world_pixel(51.5, -0.12, MAP_ZOOM);
+        assert_eq!(((x / 256.0) as u32, (y / 256.0) as u32), (16373, 10897));
+        assert_eq!(tile_url("https://t/{z}/{x}/{y}.png", 3, 4, 5), "https://t/3/4/5.png");
+        assert_eq!(
+            map_link("geo:{lat},{lon}?z={zoom}", 48.8584, 2.2945),
+            "geo:48.858400,2.294500?z=15"
+        );
+
+        // Only the tile under the marker is cached: the rest stays gray
+        let dir = TestDir::new("map");
+        let (lat, lon) = (51.5, -0.12);
+        let tile = dir.join("15/16373/10897.png");
+        fs::create_dir_all(tile.parent().unwrap()).unwrap();
+        RgbaImage::from_pixel(TILE_SIZE, TILE_SIZE, Rgba([0, 0, 255, 255])).save(&tile).unwrap();
+
+        let map = render_map(lat, lon, &dir, None).unwrap();
+        assert_eq!(map.dimensions(), (MAP_SIZE, MAP_SIZE));
+        let center = MAP_SIZE / 2;
+        assert_eq!(*map.get_pixel(center, center), Rgba([220, 30, 30, 255]));
+        assert_eq!(*map.get_pixel(center + 20, center), Rgba([0, 0, 255, 255]));
+        assert!(map.pixels().any(|p| *p == NO_TILE));
+        assert!(render_map(-33.9, 151.2, &dir, None).is_err());
+    }
+}
@@ -0,0 +1,181 @@
+// Which frame an animated GIF or WebP thumbnail shows. The first frame of
+// many animations is blank or a title card, so instead of it (the default)
+// the grid can show the middle frame, a given one, or the "best": the frame
+// with the most detail, by the entropy of its luminance histogram.
+
+use crate::image_proc::open_image;
+use anyhow::{Context, Result};
+use image::codecs::gif::GifDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::{imageops, AnimationDecoder, DynamicImage, Frames, ImageFormat, RgbaImage};
+use std::fs::File;
+use std::io::BufReader;
+
+/// Frames looked at in one animation; later ones count as not there
+const MAX_FRAMES: usize = 1000;
+
+/// Size frames are shrunk to before their entropy is measured
+const ENTROPY_SAMPLE_SIZE: u32 = 64;
+
+/// Frame of an animation a thumbnail shows (`--gif-frame`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum GifFrame {
+    #[default]
+    First,
+    Middle,
+    /// The frame with the most detail
+    Best,
+    /// Frame N, counted from 0; the last frame when there are fewer
+    Index(usize),
+}
+
+/// Parse a frame choice: first, middle, best or a frame number
+pub fn parse_gif_frame(s: &str) -> Result<GifFrame> {
+    match s.to_lowercase().as_str() {
+        "first" => Ok(GifFrame::First),
+        "middle" => Ok(GifFrame::Middle),
+        "best" => Ok(GifFrame::Best),
+        n => n.parse().map(GifFrame::Index).map_err(|_| {
+            anyhow::anyhow!("Invalid GIF frame: {}. Use: first, middle, best or a number", s)
+        }),
+    }
+}
+
+/// Decode the image at `path` for its thumbnail: the frame `choice` picks
+/// for an animation, the image itself otherwise
+pub fn open_frame(path: &str, choice: GifFrame) -> Result<DynamicImage> {
+    match pick_frame(path, choice)? {
+        Some(frame) => Ok(frame),
+        None => open_image(path),
+    }
+}
+
+/// The frame of the animation at `path` that `choice` picks. None for a
+/// still image, or for the first frame, which `open_image` decodes anyway.
+pub fn pick_frame(path: &str, choice: GifFrame) -> Result<Option<DynamicImage>> {
+    if choice == GifFrame::First {
+        return Ok(None);
+    }
+    let path = path.trim_end_matches("[0]");
+    let Some(animation) = frames(path)? else {
+        return Ok(None);
+    };
+    let index = match choice {
+        GifFrame::First => 0,
+        GifFrame::Index(n) => n,
+        GifFrame::Middle => {
+            // Counting takes a pass of its own, the frames are decoded in order
+            let count = animation.take(MAX_FRAMES).count();
+            let Some(animation) = frames(path)? else {
+                return Ok(None);
+            };
+            return nth_frame(animation, count / 2);
+        }
+        GifFrame::Best => return best_frame(animation).map(Some),
+    };
+    nth_frame(animation, index)
+}
+
+/// Frame `index` of `frames`, or the last one when there are fewer
+fn nth_frame(frames: Frames, index: usize) -> Result<Option<DynamicImage>> {
+    let mut last = None;
+    for frame in frames.take(index.min(MAX_FRAMES - 1) + 1) {
+        last = Some(frame.context("Failed to decode animation frame")?);
+    }
+    Ok(last.map(|frame| DynamicImage::ImageRgba8(frame.into_buffer())))
+}
+
+fn best_frame(frames: Frames) -> Result<DynamicImage> {
+    let mut best: Option<(f64, RgbaImage)> = None;
+    for frame in frames.take(MAX_FRAMES) {
+        let image = frame.context("Failed to decode animation frame")?.into_buffer();
+        let score = entropy(&image);
+        if best.as_ref().is_none_or(|(best_score, _)| score > *best_score) {
+            best = Some((score, image));
+        }
+    }
+    let (_, image) = best.context("Animation has no frames")?;
+    Ok(DynamicImage::ImageRgba8(image))
+}
+
+/// Shannon entropy of the luminance histogram in bits: 0 for a blank frame,
+/// up to 8 for one using every shade
+fn entropy(image: &RgbaImage) -> f64 {
+    let sample = imageops::thumbnail(image, ENTROPY_SAMPLE_SIZE, ENTROPY_SAMPLE_SIZE);
+    let mut histogram = [0u32; 256];
+    for pixel in sample.pixels() {
+        let [r, g, b, _] = pixel.0.map(u32::from);
+        histogram[((r * 299 + g * 587 + b * 114) / 1000) as usize] += 1;
+    }
+    let total = (sample.width() * sample.height()).max(1) as f64;
+    histogram
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / total;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// Frames of the animation at `path`; None when it is a still image
+fn frames(path: &str) -> Result<Option<Frames<'static>>> {
+    let reader = image::ImageReader::open(path)?.with_guessed_format()?;
+    let format = reader.format();
+    let open = || -> Result<BufReader<File>> { Ok(BufReader::new(File::open(path)?)) };
+    Ok(match format {
+        Some(ImageFormat::Gif) => Some(GifDecoder::new(open()?)?.into_frames()),
+        Some(ImageFormat::WebP) => {
+            let decoder = WebPDecoder::new(open()?)?;
+            decoder.has_animation().then(|| decoder.into_frames())
+        }
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+    use image::codecs::gif::GifEncoder;
+    use image::{Frame, Rgba};
+
+    #[test]
+    fn test_gif_frame() {
+        assert_eq!(parse_gif_frame("Best").unwrap(), GifFrame::Best);
+        assert_eq!(parse_gif_frame("3").unwrap(), GifFrame::Index(3));
+        assert!(parse_gif_frame("last").is_err());
+
+        // A blank title frame, a detailed one, then two plain colors
+        let dir = TestDir::new("gif-frame");
+        let path = dir.join("anim.gif");
+        let plain = |shade: u8| RgbaImage::from_pixel(32, 32, Rgba([shade, shade, shade, 255]));
+        let detailed = RgbaImage::from_fn(32, 32, |x, y| {
+            Rgba([(x * 8) as u8, (y * 8) as u8, ((x + y) * 4) as u8, 255])
+        });
+        let mut encoder = GifEncoder::new(File::create(&path).unwrap());
+        for image in [plain(0), detailed, plain(100), plain(200)] {
+            encoder.encode_frame(Frame::new(image)).unwrap();
+        }
+        drop(encoder);
+        let path = path.to_string_lossy().to_string();
+
+        let shade = |choice| {
+            let frame = pick_frame(&path, choice).unwrap().unwrap().to_rgba8();
+            let distinct: std::collections::HashSet<_> = frame.pixels().collect();
+            (frame.get_pixel(0, 0)[0], distinct.len())
+        };
+        assert!(shade(GifFrame::Best).1 > 1);
+        assert_eq!(shade(GifFrame::Middle).0, 100);
+        assert_eq!(shade(GifFrame::Index(3)).0, 200);
+        assert_eq!(shade(GifFrame::Index(9)).0, 200);
+        assert!(pick_frame(&path, GifFrame::First).unwrap().is_none());
+
+        // Still images keep decoding as usual
+        let still = dir.join("still.png");
+        plain(50).save(&still).unwrap();
+        let still = still.to_string_lossy().to_string();
+        assert!(pick_frame(&still, GifFrame::Best).unwrap().is_none());
+        assert_eq!(open_frame(&still, GifFrame::Best).unwrap().to_rgba8().get_pixel(0, 0)[0], 50);
+    }
+}
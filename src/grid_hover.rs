@@ -0,0 +1,277 @@
+// Interactive grid (--interactive): the grid stays on screen, the pointer is
+// followed with terminal mouse reporting and the path and details of the
+// tile under it are shown on a status line below the grid. Only the rows
+// that fit on one screen are drawn, so the recorded row positions stay valid.
+// When the terminal (or tmux pane) is resized the grid is laid out again for
+// the new width and redrawn.
+
+use crate::filter::format_file_size;
+use crate::image_proc::{render_rows, ImageConfig, ImageEntry};
+use crate::terminal::{detect_geometry, GraphicsProtocol};
+use anyhow::Result;
+use crossterm::{
+    cursor,
+    event::{
+        self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyModifiers,
+        MouseEventKind,
+    },
+    execute, queue,
+    style::Print,
+    terminal::{self, Clear, ClearType},
+};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::time::Duration;
+
+/// Quiet period after a resize event before redrawing, so dragging a pane
+/// border redraws once rather than on every step
+const RESIZE_SETTLE: Duration = Duration::from_millis(150);
+
+/// Screen lines covered by one grid row, and the images it shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RowSpan {
+    /// First screen line of the row
+    pub top: u16,
+    /// First line below the row
+    pub bottom: u16,
+    /// Index of the row's first image
+    pub first: usize,
+    /// Number of images in the row
+    pub count: usize,
+}
+
+/// Index of the image under terminal cell (`column`, `line`), given the
+/// width of one tile in terminal columns
+pub fn tile_at(rows: &[RowSpan], column: u16, line: u16, tile_columns: f32) -> Option<usize> {
+    let row = rows.iter().find(|r| (r.top..r.bottom).contains(&line))?;
+    let tile = (column as f32 / tile_columns) as usize;
+    (tile < row.count).then_some(row.first + tile)
+}
+
+/// Status line for an image: full path, dimensions, file size and the
+/// filter rejection, if any
+fn describe(entry: &ImageEntry) -> String {
+    let path = entry.path.trim_end_matches("[0]");
+    let mut parts = vec![path.to_string()];
+    if let Ok((width, height)) = image::image_dimensions(path) {
+        parts.push(format!("{}x{}", width, height));
+    }
+    if let Ok(metadata) = std::fs::metadata(path) {
+        parts.push(format_file_size(metadata.len()));
+    }
+    if let Some(reason) = &entry.rejection {
+        parts.push(format!("rejected: {}", reason));
+    }
+    parts.join("  ")
+}
+
+/// Fit `text` into `columns`, dropping the start so file names stay visible
+fn fit_status(text: &str, columns: usize) -> String {
+    let len = text.chars().count();
+    if len <= columns {
+        return text.to_string();
+    }
+    let tail: String = text.chars().skip(len - columns.saturating_sub(1)).collect();
+    format!("…{}", tail)
+}
+
+fn show_status(stdout: &mut io::Stdout, line: u16, columns: u16, text: &str) -> Result<()> {
+    queue!(
+        stdout,
+        cursor::MoveTo(0, line),
+        Clear(ClearType::CurrentLine),
+        Print(fit_status(text, columns as usize))
+    )?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// How the hover loop ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HoverExit {
+    Quit,
+    Resized,
+}
+
+/// Draw the grid from the top of the screen and show the details of the
+/// tile under the pointer until q, Esc or Enter is pressed
+pub fn run_interactive_grid(
+    entries: &[ImageEntry],
+    config: &ImageConfig,
+    width_px: u32,
+) -> Result<()> {
+    let mut config = config.clone();
+    let mut width_px = width_px;
+    let mut stdout = io::stdout();
+
+    loop {
+        let (columns, lines) = terminal::size()?;
+        let status_line = lines.saturating_sub(1);
+        let (rows, intro, tile_columns) = draw_grid(&mut stdout, entries, &config, width_px)?;
+
+        terminal::enable_raw_mode()?;
+        execute!(stdout, EnableMouseCapture, cursor::Hide)?;
+
+        let result = hover_loop(
+            &mut stdout,
+            entries,
+            &rows,
+            status_line,
+            columns,
+            tile_columns,
+            &intro,
+        );
+
+        execute!(stdout, DisableMouseCapture, cursor::Show)?;
+        terminal::disable_raw_mode()?;
+        match result {
+            Ok(HoverExit::Resized) => {
+                // The pane's pixel width is queried again (tmux reports it per pane)
+                width_px = detect_geometry()?;
+                config.fit_width(width_px);
+            }
+            Ok(HoverExit::Quit) | Err(_) => {
+                execute!(stdout, cursor::MoveTo(0, status_line))?;
+                println!();
+                return result.map(|_| ());
+            }
+        }
+    }
+}
+
+/// Clear the screen and draw as many grid rows as fit above the status
+/// line. Returns the rows drawn, the status line's intro text and the width
+/// of one tile in terminal columns.
+fn draw_grid(
+    stdout: &mut io::Stdout,
+    entries: &[ImageEntry],
+    config: &ImageConfig,
+    width_px: u32,
+) -> Result<(Vec<RowSpan>, String, f32)> {
+    let (columns, lines) = terminal::size()?;
+    let per_row = config.num_tiles_per_row.max(1) as usize;
+    let status_line = lines.saturating_sub(1);
+
+    // Every row takes at least one line, so never render more than a screen
+    let candidates = &entries[..entries.len().min(per_row * status_line as usize)];
+    let rendered = render_rows(candidates, config);
+
+    execute!(stdout, Clear(ClearType::All), cursor::MoveTo(0, 0))?;
+
+    let mut rows = Vec::new();
+    for (i, data) in rendered.into_iter().enumerate() {
+        let top = cursor::position()?.1;
+        if top >= status_line {
+            break;
+        }
+        stdout.write_all(&data?)?;
+        stdout.flush()?;
+        let bottom = cursor::position()?.1;
+
+        let first = i * per_row;
+        rows.push(RowSpan {
+            top,
+            bottom: bottom.max(top + 1),
+            first,
+            count: per_row.min(entries.len() - first),
+        });
+    }
+
+    let shown: usize = rows.iter().map(|r| r.count).sum();
+    let intro = if shown < entries.len() {
+        format!(
+            "{} of {} images shown; point at a tile for details, q to quit",
+            shown,
+            entries.len()
+        )
+    } else {
+        "Point at a tile for details, q to quit".to_string()
+    };
+
+    // Width of one tile in terminal columns
+    let tile_px = config.cell_width();
+    let px_per_column = if config.protocol == GraphicsProtocol::Blocks {
+        // Block rows are scaled down to the terminal width, a pixel per column
+        ((tile_px * per_row as u32) as f32 / columns.max(1) as f32).max(1.0)
+    } else {
+        width_px as f32 / columns.max(1) as f32
+    };
+    let tile_columns = (tile_px as f32 / px_per_column).max(1.0);
+
+    Ok((rows, intro, tile_columns))
+}
+
+fn hover_loop(
+    stdout: &mut io::Stdout,
+    entries: &[ImageEntry],
+    rows: &[RowSpan],
+    status_line: u16,
+    columns: u16,
+    tile_columns: f32,
+    intro: &str,
+) -> Result<HoverExit> {
+    let mut hovered = None;
+    let mut details: HashMap<usize, String> = HashMap::new();
+    show_status(stdout, status_line, columns, intro)?;
+
+    loop {
+        match event::read()? {
+            Event::Key(key) => {
+                let quit = matches!(key.code, KeyCode::Char('q') | KeyCode::Esc | KeyCode::Enter);
+                let ctrl_c = key.code == KeyCode::Char('c')
+                    && key.modifiers.contains(KeyModifiers::CONTROL);
+                if quit || ctrl_c {
+                    return Ok(HoverExit::Quit);
+                }
+            }
+            Event::Mouse(mouse)
+                if matches!(mouse.kind, MouseEventKind::Moved | MouseEventKind::Drag(_)) =>
+            {
+                let tile = tile_at(rows, mouse.column, mouse.row, tile_columns);
+                if tile == hovered {
+                    continue;
+                }
+                hovered = tile;
+                let text = match tile {
+                    Some(i) => details.entry(i).or_insert_with(|| describe(&entries[i])).as_str(),
+                    None => intro,
+                };
+                show_status(stdout, status_line, columns, text)?;
+            }
+            Event::Resize(_, _) => {
+                // Let the resize settle; events arriving meanwhile are dropped
+                while event::poll(RESIZE_SETTLE)? {
+                    event::read()?;
+                }
+                return Ok(HoverExit::Resized);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tile_at() {
+        let rows = [
+            RowSpan { top: 0, bottom: 10, first: 0, count: 4 },
+            RowSpan { top: 10, bottom: 18, first: 4, count: 2 },
+        ];
+
+        assert_eq!(tile_at(&rows, 0, 0, 20.0), Some(0));
+        assert_eq!(tile_at(&rows, 79, 9, 20.0), Some(3));
+        assert_eq!(tile_at(&rows, 25, 12, 20.0), Some(5));
+        // Past the last tile of a short row, and below the grid
+        assert_eq!(tile_at(&rows, 45, 12, 20.0), None);
+        assert_eq!(tile_at(&rows, 5, 18, 20.0), None);
+    }
+
+    #[test]
+    fn test_fit_status() {
+        assert_eq!(fit_status("short", 10), "short");
+        assert_eq!(fit_status("/photos/2024/beach.jpg", 10), "…beach.jpg");
+    }
+}
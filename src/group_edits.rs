@@ -0,0 +1,266 @@
+// Corrections made to automatic groups in the TUI: images moved to another
+// group, groups merged, and groups split in two. They are saved to
+// group_edits.json in the state directory, per grouping strategy, and
+// applied again whenever that strategy groups images, so a later run
+// honors them. Like group names, groups are matched by `ImageGroup::key`.
+//
+// Edits that no longer apply (an image that is gone, a group that no longer
+// forms) are skipped rather than forgotten.
+
+use crate::grouping::{GroupMetadata, ImageGroup};
+use crate::app_dirs::state_dir;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+
+/// Group type of the groups made by splitting
+const SPLIT_GROUP_TYPE: &str = "manual";
+
+/// Corrections to the groups of one strategy
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct StrategyEdits {
+    /// Key of the group each moved image belongs in, by image path
+    #[serde(default)]
+    moves: BTreeMap<String, String>,
+    /// Key of the group each merged group went into, by key
+    #[serde(default)]
+    merges: BTreeMap<String, String>,
+    /// Names of the groups made by splitting, by id
+    #[serde(default)]
+    splits: BTreeMap<String, String>,
+}
+
+/// Manual group corrections by grouping strategy
+#[derive(Debug, Default)]
+pub struct GroupEdits {
+    strategies: HashMap<String, StrategyEdits>,
+}
+
+fn group_edits_path() -> PathBuf {
+    state_dir().join("group_edits.json")
+}
+
+/// Strategy that made `groups`: the type most of them have, ignoring
+/// groups made by splitting
+pub fn strategy_of(groups: &[ImageGroup]) -> Option<String> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for group in groups.iter().filter(|g| g.metadata.group_type != SPLIT_GROUP_TYPE) {
+        *counts.entry(group.metadata.group_type.as_str()).or_insert(0) += 1;
+    }
+    counts
+        .into_iter()
+        .max_by_key(|&(kind, count)| (count, kind))
+        .map(|(kind, _)| kind.to_string())
+}
+
+/// Empty group made by splitting, with `id` and `name`
+fn split_group(id: &str, name: &str) -> ImageGroup {
+    ImageGroup {
+        id: id.to_string(),
+        name: name.to_string(),
+        images: Vec::new(),
+        representative: String::new(),
+        metadata: GroupMetadata {
+            group_type: SPLIT_GROUP_TYPE.to_string(),
+            count: 0,
+            common_features: HashMap::new(),
+        },
+    }
+}
+
+impl StrategyEdits {
+    /// Key `key` ends up under after merges; merges that go round in a
+    /// circle stop where they started
+    fn merged_key<'a>(&'a self, mut key: &'a str) -> &'a str {
+        let mut seen = HashSet::new();
+        while let Some(into) = self.merges.get(key) {
+            if !seen.insert(key) {
+                break;
+            }
+            key = into;
+        }
+        key
+    }
+}
+
+impl GroupEdits {
+    /// Edits saved by earlier sessions; a missing or unreadable file starts
+    /// empty
+    pub fn load() -> GroupEdits {
+        let strategies = fs::read_to_string(group_edits_path())
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        GroupEdits { strategies }
+    }
+
+    /// Write the edits to disk
+    pub fn save(&self) -> Result<()> {
+        let path = group_edits_path();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let json = serde_json::to_string_pretty(&self.strategies)?;
+        fs::write(&path, json).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Correct `groups` by the edits saved for their strategy: split groups
+    /// are added, images moved and groups merged. Groups left empty are
+    /// dropped. Applying the edits again changes nothing.
+    pub fn apply(&self, groups: &mut Vec<ImageGroup>) {
+        let Some(edits) = strategy_of(groups).and_then(|s| self.strategies.get(&s)) else {
+            return;
+        };
+        for (id, name) in &edits.splits {
+            let group = split_group(id, name);
+            if !groups.iter().any(|g| g.key() == group.key()) {
+                groups.push(group);
+            }
+        }
+
+        let index: HashMap<String, usize> =
+            groups.iter().enumerate().map(|(i, g)| (g.key(), i)).collect();
+        let target = |key: &str| index.get(edits.merged_key(key)).copied();
+        let mut moves: Vec<(String, usize)> = Vec::new();
+        for (i, group) in groups.iter().enumerate() {
+            let merged = target(&group.key()).filter(|&to| to != i);
+            for image in &group.images {
+                let moved = edits.moves.get(image).and_then(|key| target(key));
+                if let Some(to) = moved.or(merged).filter(|&to| to != i) {
+                    moves.push((image.clone(), to));
+                }
+            }
+        }
+        for (image, to) in moves {
+            for group in groups.iter_mut() {
+                group.images.retain(|path| *path != image);
+            }
+            groups[to].images.push(image);
+        }
+
+        groups.retain(|group| !group.images.is_empty());
+        for group in groups.iter_mut() {
+            group.metadata.count = group.images.len();
+            if !group.images.contains(&group.representative) {
+                group.representative = group.images[0].clone();
+            }
+        }
+    }
+
+    fn edits_for(&mut self, groups: &[ImageGroup]) -> Result<&mut StrategyEdits> {
+        let strategy = strategy_of(groups).context("Images are not grouped; use --group-by")?;
+        Ok(self.strategies.entry(strategy).or_default())
+    }
+
+    /// Record that `images` belong in `target`, one of `groups`
+    pub fn move_images(
+        &mut self,
+        groups: &[ImageGroup],
+        images: &[String],
+        target: &ImageGroup,
+    ) -> Result<()> {
+        let edits = self.edits_for(groups)?;
+        for image in images {
+            edits.moves.insert(image.clone(), target.key());
+        }
+        Ok(())
+    }
+
+    /// Record that group `from` is merged into `into`, both among `groups`
+    pub fn merge(
+        &mut self,
+        groups: &[ImageGroup],
+        from: &ImageGroup,
+        into: &ImageGroup,
+    ) -> Result<()> {
+        let edits = self.edits_for(groups)?;
+        if from.key() == into.key() {
+            anyhow::bail!("Can't merge a group with itself");
+        }
+        // Merging back undoes an earlier merge instead of making a circle
+        edits.merges.remove(&into.key());
+        edits.merges.insert(from.key(), into.key());
+        Ok(())
+    }
+
+    /// Record that `images`, the end of one of `groups`, form a new group.
+    /// Returns the new group's name.
+    pub fn split(&mut self, groups: &[ImageGroup], images: &[String]) -> Result<String> {
+        let edits = self.edits_for(groups)?;
+        let id = (1..)
+            .map(|n| format!("split-{}", n))
+            .find(|id| !edits.splits.contains_key(id))
+            .unwrap_or_default();
+        let name = format!("Split {}", id.trim_start_matches("split-"));
+        let key = split_group(&id, &name).key();
+        for image in images {
+            edits.moves.insert(image.clone(), key.clone());
+        }
+        edits.splits.insert(id, name.clone());
+        Ok(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group(id: &str, images: &[&str]) -> ImageGroup {
+        ImageGroup {
+            id: id.to_string(),
+            name: id.to_string(),
+            images: images.iter().map(|i| i.to_string()).collect(),
+            representative: images[0].to_string(),
+            metadata: GroupMetadata {
+                group_type: "time".to_string(),
+                count: images.len(),
+                common_features: HashMap::new(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_group_edits() {
+        let groups = vec![
+            group("morning", &["a.jpg", "b.jpg", "c.jpg"]),
+            group("noon", &["d.jpg"]),
+            group("evening", &["e.jpg", "f.jpg"]),
+        ];
+        let mut edits = GroupEdits::default();
+        edits.move_images(&groups, &["a.jpg".to_string()], &groups[2]).unwrap();
+        edits.merge(&groups, &groups[1], &groups[0]).unwrap();
+        assert_eq!(edits.split(&groups, &["c.jpg".to_string()]).unwrap(), "Split 1");
+
+        // Regrouping gives the same automatic groups; the edits reshape them
+        let mut regrouped = groups.clone();
+        edits.apply(&mut regrouped);
+        let shape: Vec<(&str, Vec<&str>)> = regrouped
+            .iter()
+            .map(|g| (g.name.as_str(), g.images.iter().map(String::as_str).collect()))
+            .collect();
+        assert_eq!(
+            shape,
+            [
+                ("morning", vec!["b.jpg", "d.jpg"]),
+                ("evening", vec!["e.jpg", "f.jpg", "a.jpg"]),
+                ("Split 1", vec!["c.jpg"]),
+            ]
+        );
+        assert_eq!(regrouped[0].representative, "b.jpg");
+        assert_eq!(regrouped[2].metadata.count, 1);
+
+        let before = regrouped.clone();
+        edits.apply(&mut regrouped);
+        assert_eq!(regrouped.len(), before.len());
+        assert!(regrouped.iter().zip(&before).all(|(a, b)| a.images == b.images));
+
+        // Merging the other way replaces the earlier merge instead of looping
+        edits.merge(&groups, &groups[0], &groups[1]).unwrap();
+        let mut regrouped = groups.clone();
+        edits.apply(&mut regrouped);
+        assert_eq!(regrouped[0].images, ["d.jpg", "b.jpg"]);
+    }
+}
@@ -0,0 +1,87 @@
+// Names given to groups in the TUI, saved to group_names.json in the state
+// directory so a group keeps its album-like name in later runs. Groups are
+// matched by `ImageGroup::key`, which survives regrouping as long as the
+// group's defining feature (date, tag, representative image...) does.
+
+use crate::grouping::ImageGroup;
+use crate::app_dirs::state_dir;
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+/// Custom group names by group key
+#[derive(Debug, Default)]
+pub struct GroupNames {
+    names: HashMap<String, String>,
+}
+
+fn group_names_path() -> PathBuf {
+    state_dir().join("group_names.json")
+}
+
+impl GroupNames {
+    /// Names saved by earlier sessions; a missing or unreadable file starts
+    /// empty
+    pub fn load() -> GroupNames {
+        let names = fs::read_to_string(group_names_path())
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        GroupNames { names }
+    }
+
+    /// Give each group its saved name, if it has one
+    pub fn apply(&self, groups: &mut [ImageGroup]) {
+        for group in groups {
+            if let Some(name) = self.names.get(&group.key()) {
+                group.name = name.clone();
+            }
+        }
+    }
+
+    /// Remember `name` for the group with `key`
+    pub fn set(&mut self, key: &str, name: &str) {
+        self.names.insert(key.to_string(), name.to_string());
+    }
+
+    /// Write the names to disk
+    pub fn save(&self) -> Result<()> {
+        let path = group_names_path();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let json = serde_json::to_string_pretty(&self.names)?;
+        fs::write(&path, json).with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grouping::GroupMetadata;
+
+    #[test]
+    fn test_apply_group_names() {
+        let group = |tag: &str| ImageGroup {
+            id: format!("tag_{}", tag),
+            name: format!("{} Images", tag),
+            images: vec![format!("{}.jpg", tag)],
+            representative: format!("{}.jpg", tag),
+            metadata: GroupMetadata {
+                group_type: "tags".to_string(),
+                count: 1,
+                common_features: HashMap::new(),
+            },
+        };
+        let mut groups = vec![group("beach"), group("snow")];
+
+        let mut names = GroupNames::default();
+        names.set(&groups[1].key(), "Ski trip 2024");
+        names.apply(&mut groups);
+
+        assert_eq!(groups[0].name, "beach Images");
+        assert_eq!(groups[1].name, "Ski trip 2024");
+    }
+}
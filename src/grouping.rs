@@ -2,8 +2,14 @@
 #![allow(dead_code)]
 
 use crate::ai_tagging::{AITaggingConfig, AITags};
+use crate::clustering::{cluster_hashes, cluster_vectors};
+use crate::diagnostics::{self, Verbosity, WarningKind};
 use crate::filter::ImageFeatures;
+use crate::hash_index::HashIndex;
 use crate::image_proc::ImageEntry;
+use crate::interrupt;
+use crate::test_mode;
+use crate::timing;
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -23,29 +29,114 @@ pub enum GroupBy {
     Tags,       // By auto-detected tags
 }
 
+impl GroupBy {
+    /// The strategy as `parse_group_by` reads it
+    pub fn name(self) -> &'static str {
+        match self {
+            GroupBy::None => "none",
+            GroupBy::Similarity => "similarity",
+            GroupBy::Color => "color",
+            GroupBy::Size => "size",
+            GroupBy::Time => "time",
+            GroupBy::Tags => "tags",
+        }
+    }
+}
+
+/// Parse a --group-by value
+pub fn parse_group_by(s: &str) -> Result<GroupBy> {
+    match s.to_lowercase().as_str() {
+        "none" => Ok(GroupBy::None),
+        "similarity" => Ok(GroupBy::Similarity),
+        "color" => Ok(GroupBy::Color),
+        "size" => Ok(GroupBy::Size),
+        "time" => Ok(GroupBy::Time),
+        "tags" => Ok(GroupBy::Tags),
+        _ => anyhow::bail!(
+            "Unknown grouping: {}. Use: none, similarity, color, size, time, tags",
+            s
+        ),
+    }
+}
+
 /// A group of similar images
 #[derive(Debug, Clone)]
 pub struct ImageGroup {
+    /// Stable identifier, unique within one `group_images` call
     pub id: GroupId,
+    /// Human-readable name shown in group headers
     pub name: String,
+    /// Paths of the images in this group
     pub images: Vec<String>,
-    pub representative: String, // Most representative image
+    /// Most representative image
+    pub representative: String,
+    /// How the group was formed and what its images share
     pub metadata: GroupMetadata,
 }
 
+impl ImageGroup {
+    /// Identifier that stays the same across runs as long as the group does:
+    /// what the images share for size, time and tag groups, the
+    /// representative image for similarity, color and duplicate groups
+    pub fn key(&self) -> String {
+        match self.metadata.group_type.as_str() {
+            "similarity" | "color" | "duplicates" => {
+                format!("{}:{}", self.metadata.group_type, self.representative)
+            }
+            _ => format!("{}:{}", self.metadata.group_type, self.id),
+        }
+    }
+}
+
+/// Fill a group name template. Placeholders: `{n}` (group number),
+/// `{count}`, `{name}` (built-in name), `{type}`, and the group's common
+/// features: `{date}`, `{tag}`, `{size}` and `{color}`. Unknown placeholders
+/// are left as they are.
+pub fn render_group_name(template: &str, number: usize, group: &ImageGroup) -> String {
+    let features = &group.metadata.common_features;
+    let mut name = template
+        .replace("{n}", &number.to_string())
+        .replace("{count}", &group.images.len().to_string())
+        .replace("{name}", &group.name)
+        .replace("{type}", &group.metadata.group_type);
+    let aliases = [("size", "resolution"), ("color", "dominant_color")];
+    for (placeholder, feature) in aliases {
+        if let Some(value) = features.get(feature) {
+            name = name.replace(&format!("{{{}}}", placeholder), value);
+        }
+    }
+    for (feature, value) in features {
+        name = name.replace(&format!("{{{}}}", feature), value);
+    }
+    name
+}
+
+/// Rename every group from `template`, numbering from 1
+pub fn name_groups(groups: &mut [ImageGroup], template: &str) {
+    for (i, group) in groups.iter_mut().enumerate() {
+        group.name = render_group_name(template, i + 1, group);
+    }
+}
+
 /// Metadata about a group
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GroupMetadata {
+    /// Grouping strategy that produced the group (e.g. "color", "tags")
     pub group_type: String,
+    /// Number of images in the group
     pub count: usize,
+    /// Features shared by all images, e.g. "tag" => "sunset"
     pub common_features: HashMap<String, String>,
 }
 
 /// Perceptual hash for image similarity
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct PerceptualHash {
+    /// Hash bits, packed into bytes
     pub hash: Vec<u8>,
+    /// Width of the image the hash was computed from
     pub width: u32,
+    /// Height of the image the hash was computed from
     pub height: u32,
 }
 
@@ -55,7 +146,7 @@ impl PerceptualHash {
         self.hash
             .iter()
             .zip(other.hash.iter())
-            .map(|(&a, _b)| (a as u8).count_ones() as u32)
+            .map(|(&a, &b)| (a ^ b).count_ones())
             .sum()
     }
 
@@ -67,45 +158,174 @@ impl PerceptualHash {
     }
 }
 
+/// Number of 30° hue bins in a color histogram
+pub const HUE_BINS: usize = 12;
+
+/// Color name of each hue bin, starting at red (345°-15°)
+const HUE_NAMES: [&str; HUE_BINS] = [
+    "Red", "Orange", "Yellow", "Green", "Green", "Green", "Cyan", "Blue", "Blue", "Purple",
+    "Magenta", "Pink",
+];
+
+/// Hue names counted as warm when no single color dominates
+const WARM_HUES: [&str; 5] = ["Red", "Orange", "Yellow", "Magenta", "Pink"];
+
 /// Color histogram for color-based grouping
 #[derive(Debug, Clone)]
 pub struct ColorHistogram {
+    /// Pixel counts per red channel value
     pub red: [u32; 256],
+    /// Pixel counts per green channel value
     pub green: [u32; 256],
+    /// Pixel counts per blue channel value
     pub blue: [u32; 256],
+    /// Saturated pixels per 30° hue bin
+    pub hue: [u32; HUE_BINS],
+    /// Pixels too dark to have a meaningful hue
+    pub black: u32,
+    /// Unsaturated mid-tone pixels
+    pub gray: u32,
+    /// Bright unsaturated pixels
+    pub white: u32,
+    /// Number of pixels sampled
     pub total_pixels: u64,
 }
 
 impl ColorHistogram {
-    /// Calculate color similarity (0.0 to 1.0)
+    fn empty() -> Self {
+        ColorHistogram {
+            red: [0; 256],
+            green: [0; 256],
+            blue: [0; 256],
+            hue: [0; HUE_BINS],
+            black: 0,
+            gray: 0,
+            white: 0,
+            total_pixels: 0,
+        }
+    }
+
+    /// Calculate color similarity (0.0 to 1.0): the mean of the cosine
+    /// similarities of the RGB channel histograms and of the HSV bins
     pub fn similarity(&self, other: &ColorHistogram) -> f32 {
-        let mut dot_product = 0.0f64;
-        let mut norm_a = 0.0f64;
-        let mut norm_b = 0.0f64;
+        let rgb = |h: &ColorHistogram| -> Vec<u32> {
+            h.red.iter().chain(&h.green).chain(&h.blue).copied().collect()
+        };
+        let rgb_similarity = cosine_similarity(&rgb(self), &rgb(other));
+        let hsv_similarity = cosine_similarity(&self.hsv_bins(), &other.hsv_bins());
+        (rgb_similarity + hsv_similarity) / 2.0
+    }
+
+    /// Unit vector whose dot product with another histogram's equals their
+    /// `similarity`: the normalized RGB and HSV parts, each scaled by 1/√2
+    pub fn feature_vector(&self) -> Vec<f32> {
+        let rgb = self.red.iter().chain(&self.green).chain(&self.blue);
+        let hsv = self.hsv_bins();
+        let mut vector = normalized(rgb.copied());
+        vector.extend(normalized(hsv.into_iter()));
+        vector
+    }
+
+    /// Hue bins followed by the black, gray and white counts
+    fn hsv_bins(&self) -> Vec<u32> {
+        let mut bins = self.hue.to_vec();
+        bins.extend([self.black, self.gray, self.white]);
+        bins
+    }
 
+    /// Add another histogram's counts, e.g. to describe a whole group
+    pub fn merge(&mut self, other: &ColorHistogram) {
         for i in 0..256 {
-            let a = (self.red[i] + self.green[i] + self.blue[i]) as f64;
-            let b = (other.red[i] + other.green[i] + other.blue[i]) as f64;
+            self.red[i] += other.red[i];
+            self.green[i] += other.green[i];
+            self.blue[i] += other.blue[i];
+        }
+        for (bin, count) in self.hue.iter_mut().zip(other.hue) {
+            *bin += count;
+        }
+        self.black += other.black;
+        self.gray += other.gray;
+        self.white += other.white;
+        self.total_pixels += other.total_pixels;
+    }
 
-            dot_product += a * b;
-            norm_a += a * a;
-            norm_b += b * b;
+    /// Name of the dominant color: a hue name when one hue covers at least
+    /// half of the saturated pixels, "Warm" or "Cool" when they are mixed,
+    /// or Black/Gray/White when most pixels are unsaturated
+    pub fn dominant_color_name(&self) -> String {
+        let chromatic: u32 = self.hue.iter().sum();
+        let achromatic = self.black + self.gray + self.white;
+        if chromatic == 0 && achromatic == 0 {
+            return "Unknown".to_string();
         }
 
-        if norm_a == 0.0 || norm_b == 0.0 {
-            return 0.0;
+        if chromatic * 3 < achromatic {
+            let shades = [("White", self.white), ("Gray", self.gray), ("Black", self.black)];
+            let (name, _) = shades.iter().max_by_key(|(_, count)| *count).unwrap();
+            return name.to_string();
+        }
+
+        let mut by_name: Vec<(&str, u32)> = Vec::new();
+        for (name, count) in HUE_NAMES.iter().zip(self.hue) {
+            match by_name.iter().position(|(n, _)| n == name) {
+                Some(i) => by_name[i].1 += count,
+                None => by_name.push((*name, count)),
+            }
+        }
+        let (name, count) = by_name.iter().max_by_key(|(_, count)| *count).unwrap();
+        if count * 2 >= chromatic {
+            return name.to_string();
         }
 
-        (dot_product / (norm_a.sqrt() * norm_b.sqrt())) as f32
+        let warm: u32 = by_name
+            .iter()
+            .filter(|(name, _)| WARM_HUES.contains(name))
+            .map(|(_, count)| count)
+            .sum();
+        if warm * 2 >= chromatic {
+            "Warm".to_string()
+        } else {
+            "Cool".to_string()
+        }
     }
 }
 
+/// `counts` scaled to length 1/√2 (all zeros if empty)
+fn normalized(counts: impl Iterator<Item = u32>) -> Vec<f32> {
+    let counts: Vec<f64> = counts.map(|c| c as f64).collect();
+    let norm = counts.iter().map(|c| c * c).sum::<f64>().sqrt() * std::f64::consts::SQRT_2;
+    counts
+        .iter()
+        .map(|c| if norm > 0.0 { (c / norm) as f32 } else { 0.0 })
+        .collect()
+}
+
+fn cosine_similarity(a: &[u32], b: &[u32]) -> f32 {
+    let mut dot_product = 0.0f64;
+    let mut norm_a = 0.0f64;
+    let mut norm_b = 0.0f64;
+
+    for (&a, &b) in a.iter().zip(b) {
+        let (a, b) = (a as f64, b as f64);
+        dot_product += a * b;
+        norm_a += a * a;
+        norm_b += b * b;
+    }
+
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+
+    (dot_product / (norm_a.sqrt() * norm_b.sqrt())) as f32
+}
+
 /// Group images using the specified strategy
 pub fn group_images(
     image_paths: &[String],
     strategy: GroupBy,
     similarity_threshold: f32,
 ) -> Result<Vec<ImageGroup>> {
+    let _span = timing::phase("group");
     match strategy {
         GroupBy::None => {
             // Put all images in one group
@@ -129,50 +349,101 @@ pub fn group_images(
     }
 }
 
-/// Group images by visual similarity using perceptual hashing
-fn group_by_similarity(image_paths: &[String], threshold: f32) -> Result<Vec<ImageGroup>> {
+/// Perceptual hashes of the images that could be read, in input order.
+/// Hashes from the index are reused; the rest are computed in parallel with
+/// a progress bar. Ctrl-C stops hashing and keeps what was done so far.
+fn perceptual_hashes(image_paths: &[String]) -> Vec<(String, PerceptualHash)> {
     use rayon::prelude::*;
 
-    // Calculate perceptual hashes for all images
-    let hashes: Vec<(String, PerceptualHash)> = image_paths
+    let mut index = HashIndex::load();
+    let mut known: HashMap<&str, PerceptualHash> = image_paths
+        .iter()
+        .filter_map(|path| Some((path.as_str(), index.get(path)?)))
+        .collect();
+    let missing: Vec<&String> = image_paths
+        .iter()
+        .filter(|path| !known.contains_key(path.as_str()))
+        .collect();
+
+    let progress = if missing.is_empty() || diagnostics::verbosity() == Verbosity::Quiet {
+        indicatif::ProgressBar::hidden()
+    } else {
+        indicatif::ProgressBar::new(missing.len() as u64)
+    };
+    progress.set_style(
+        indicatif::ProgressStyle::default_bar()
+            .template("{spinner:.green} [{bar:40.cyan/blue}] {pos}/{len} {msg}")
+            .unwrap()
+            .progress_chars("##-"),
+    );
+    progress.set_message("Hashing images");
+
+    let interrupt = interrupt::guard();
+    let computed: Vec<(&String, PerceptualHash)> = missing
         .par_iter()
-        .filter_map(|path| {
-            calculate_perceptual_hash(path)
-                .ok()
-                .map(|hash| (path.clone(), hash))
+        .filter_map(|&path| {
+            if interrupt::interrupted() {
+                return None;
+            }
+            let result = calculate_perceptual_hash(path);
+            progress.inc(1);
+            match result {
+                Ok(hash) => Some((path, hash)),
+                Err(e) => {
+                    progress.suspend(|| {
+                        diagnostics::warn(WarningKind::Grouping, &format!("{:#}", e));
+                    });
+                    None
+                }
+            }
         })
         .collect();
 
-    if hashes.is_empty() {
-        return Ok(vec![]);
+    let interrupted = interrupt::interrupted();
+    drop(interrupt);
+    if interrupted {
+        progress.abandon_with_message("Hashing interrupted");
+        diagnostics::info(&format!(
+            "Interrupted: grouping the {} of {} images hashed so far",
+            known.len() + computed.len(),
+            image_paths.len()
+        ));
+    } else {
+        progress.finish_and_clear();
     }
 
-    // Group similar images
-    let mut groups: Vec<Vec<String>> = Vec::new();
-    let mut assigned = vec![false; hashes.len()];
-
-    for (i, (path_i, hash_i)) in hashes.iter().enumerate() {
-        if assigned[i] {
-            continue;
-        }
+    for (path, hash) in computed {
+        index.insert(path, hash.clone());
+        known.insert(path.as_str(), hash);
+    }
+    if let Err(e) = index.save() {
+        diagnostics::warn(WarningKind::Other, &format!("{:#}", e));
+    }
 
-        let mut group = vec![path_i.clone()];
-        assigned[i] = true;
+    image_paths
+        .iter()
+        .filter_map(|path| Some((path.clone(), known.remove(path.as_str())?)))
+        .collect()
+}
 
-        // Find similar images
-        for (j, (path_j, hash_j)) in hashes.iter().enumerate() {
-            if i != j && !assigned[j] {
-                let similarity = hash_i.similarity(hash_j);
-                if similarity >= threshold {
-                    group.push(path_j.clone());
-                    assigned[j] = true;
-                }
-            }
-        }
+/// Group images by visual similarity using perceptual hashing
+fn group_by_similarity(image_paths: &[String], threshold: f32) -> Result<Vec<ImageGroup>> {
+    let hashes = perceptual_hashes(image_paths);
 
-        groups.push(group);
+    if hashes.is_empty() {
+        return Ok(vec![]);
     }
 
+    // Group similar images: similarity >= threshold means at most this many
+    // differing bits
+    let bits = (DHASH_SIZE * DHASH_SIZE) as f32;
+    let max_distance = ((1.0 - threshold.clamp(0.0, 1.0)) * bits).floor() as u32;
+    let hash_refs: Vec<&PerceptualHash> = hashes.iter().map(|(_, hash)| hash).collect();
+    let groups: Vec<Vec<String>> = cluster_hashes(&hash_refs, max_distance)
+        .into_iter()
+        .map(|members| members.iter().map(|&i| hashes[i].0.clone()).collect())
+        .collect();
+
     // Convert to ImageGroup structures
     Ok(groups
         .into_iter()
@@ -201,10 +472,12 @@ fn group_by_color(image_paths: &[String], threshold: f32) -> Result<Vec<ImageGro
     // Calculate color histograms for all images
     let histograms: Vec<(String, ColorHistogram)> = image_paths
         .par_iter()
-        .filter_map(|path| {
-            calculate_color_histogram(path)
-                .ok()
-                .map(|hist| (path.clone(), hist))
+        .filter_map(|path| match calculate_color_histogram(path) {
+            Ok(hist) => Some((path.clone(), hist)),
+            Err(e) => {
+                diagnostics::warn(WarningKind::Grouping, &format!("{:#}", e));
+                None
+            }
         })
         .collect();
 
@@ -213,37 +486,28 @@ fn group_by_color(image_paths: &[String], threshold: f32) -> Result<Vec<ImageGro
     }
 
     // Group by color
-    let mut groups: Vec<Vec<String>> = Vec::new();
-    let mut assigned = vec![false; histograms.len()];
-
-    for (i, (path_i, hist_i)) in histograms.iter().enumerate() {
-        if assigned[i] {
-            continue;
-        }
-
-        let mut group = vec![path_i.clone()];
-        assigned[i] = true;
-
-        // Find similar colors
-        for (j, (path_j, hist_j)) in histograms.iter().enumerate() {
-            if i != j && !assigned[j] {
-                let similarity = hist_i.similarity(hist_j);
-                if similarity >= threshold {
-                    group.push(path_j.clone());
-                    assigned[j] = true;
-                }
+    let vectors: Vec<Vec<f32>> = histograms.par_iter().map(|(_, h)| h.feature_vector()).collect();
+    let clusters = cluster_vectors(&vectors, |i, j| {
+        histograms[i].1.similarity(&histograms[j].1) >= threshold
+    });
+    let groups: Vec<(Vec<String>, ColorHistogram)> = clusters
+        .into_iter()
+        .map(|members| {
+            let mut group_hist = histograms[members[0]].1.clone();
+            for &i in &members[1..] {
+                group_hist.merge(&histograms[i].1);
             }
-        }
-
-        groups.push(group);
-    }
+            let images = members.iter().map(|&i| histograms[i].0.clone()).collect();
+            (images, group_hist)
+        })
+        .collect();
 
     // Convert to ImageGroup structures
     Ok(groups
         .into_iter()
         .enumerate()
-        .map(|(i, images)| {
-            let dominant_color = get_dominant_color_name(&images);
+        .map(|(i, (images, group_hist))| {
+            let dominant_color = group_hist.dominant_color_name();
             let name = format!("{} Images", dominant_color);
             ImageGroup {
                 id: format!("color_{}", i),
@@ -267,12 +531,22 @@ fn group_by_color(image_paths: &[String], threshold: f32) -> Result<Vec<ImageGro
 /// Group images by size (dimensions)
 fn group_by_size(image_paths: &[String]) -> Result<Vec<ImageGroup>> {
     use crate::filter::analyze_image;
+    use crate::tools::{self, Tool};
     use rayon::prelude::*;
 
+    tools::require(Tool::ImageMagick, "--group-by size")?;
+
     // Get image features
     let features: Vec<(String, ImageFeatures)> = image_paths
         .par_iter()
-        .filter_map(|path| analyze_image(path).ok().map(|f| (path.clone(), f)))
+        .filter_map(|path| match analyze_image(path) {
+            Ok(f) => Some((path.clone(), f)),
+            Err(e) => {
+                let message = format!("Failed to analyze {}: {:#}", path, e);
+                diagnostics::warn(WarningKind::Analyze, &message);
+                None
+            }
+        })
         .collect();
 
     if features.is_empty() {
@@ -291,8 +565,12 @@ fn group_by_size(image_paths: &[String]) -> Result<Vec<ImageGroup>> {
         size_groups.entry(key).or_insert_with(Vec::new).push(path);
     }
 
+    // Sort by size, so the order doesn't depend on hashing
+    let mut sorted_groups: Vec<_> = size_groups.into_iter().collect();
+    sorted_groups.sort_by(|a, b| a.0.cmp(&b.0));
+
     // Convert to ImageGroup structures
-    Ok(size_groups
+    Ok(sorted_groups
         .into_iter()
         .map(|(size, images)| ImageGroup {
             id: format!("size_{}", size.replace('x', "_")),
@@ -321,8 +599,7 @@ fn group_by_time(image_paths: &[String]) -> Result<Vec<ImageGroup>> {
     for path in image_paths {
         if let Ok(metadata) = fs::metadata(path) {
             if let Ok(modified) = metadata.modified() {
-                let datetime: chrono::DateTime<chrono::Local> = modified.into();
-                let date_key = datetime.format("%Y-%m-%d").to_string();
+                let date_key = test_mode::local(modified.into()).format("%Y-%m-%d").to_string();
                 time_groups
                     .entry(date_key)
                     .or_insert_with(Vec::new)
@@ -369,7 +646,11 @@ fn group_by_tags(image_paths: &[String]) -> Result<Vec<ImageGroup>> {
         }
     }
 
-    Ok(tag_groups
+    // Sort by tag, so the order doesn't depend on hashing
+    let mut sorted_groups: Vec<_> = tag_groups.into_iter().collect();
+    sorted_groups.sort_by(|a, b| a.0.cmp(&b.0));
+
+    Ok(sorted_groups
         .into_iter()
         .map(|(tag, images)| ImageGroup {
             id: format!("tag_{}", tag.to_lowercase().replace(' ', "_")),
@@ -389,93 +670,108 @@ fn group_by_tags(image_paths: &[String]) -> Result<Vec<ImageGroup>> {
         .collect())
 }
 
-/// Calculate a simplified perceptual hash
-fn calculate_perceptual_hash(path: &str) -> Result<PerceptualHash> {
-    use std::process::Command;
-
-    // Use ImageMagick to get a small grayscale version
-    let output = Command::new("convert")
-        .arg(path)
-        .arg("-colorspace")
-        .arg("Gray")
-        .arg("-resize")
-        .arg("8x8!")
-        .arg("-format")
-        .arg("%c")
-        .arg("histogram:info:-")
-        .output()
-        .context("Failed to calculate perceptual hash")?;
-
-    // Parse histogram to get average brightness
-    let _text = String::from_utf8_lossy(&output.stdout);
-
-    // Simplified hash: just use dimensions for now
-    // A real implementation would analyze pixel values
-    let identify_output = Command::new("identify")
-        .arg("-format")
-        .arg("%w %h")
-        .arg(path)
-        .output()
-        .context("Failed to identify image")?;
-
-    let info = String::from_utf8_lossy(&identify_output.stdout);
-    let parts: Vec<&str> = info.trim().split_whitespace().collect();
-
-    if parts.len() >= 2 {
-        let width: u32 = parts[0].parse()?;
-        let height: u32 = parts[1].parse()?;
-
-        // Create a simple hash based on dimensions and filename
-        let mut path_hash = std::collections::hash_map::DefaultHasher::new();
-        use std::hash::{Hash, Hasher};
-        path.hash(&mut path_hash);
-
-        Ok(PerceptualHash {
-            hash: vec![path_hash.finish() as u8; 8], // 64-bit hash
-            width,
-            height,
-        })
-    } else {
-        anyhow::bail!("Failed to parse image dimensions")
+/// Side length of the dHash gradient grid (64 bits)
+const DHASH_SIZE: u32 = 8;
+
+/// Difference hash of a decoded image: shrink to 9x8 grayscale and record
+/// whether each pixel is brighter than its right neighbour. Robust to
+/// scaling, recompression and small brightness changes.
+pub fn dhash(img: &image::DynamicImage) -> PerceptualHash {
+    let small = image::imageops::resize(
+        &img.to_luma8(),
+        DHASH_SIZE + 1,
+        DHASH_SIZE,
+        image::imageops::FilterType::Triangle,
+    );
+
+    let mut hash = vec![0u8; (DHASH_SIZE * DHASH_SIZE / 8) as usize];
+    for y in 0..DHASH_SIZE {
+        for x in 0..DHASH_SIZE {
+            if small.get_pixel(x, y)[0] > small.get_pixel(x + 1, y)[0] {
+                let bit = (y * DHASH_SIZE + x) as usize;
+                hash[bit / 8] |= 1 << (bit % 8);
+            }
+        }
+    }
+
+    PerceptualHash {
+        hash,
+        width: img.width(),
+        height: img.height(),
     }
 }
 
-/// Calculate color histogram for an image
-fn calculate_color_histogram(path: &str) -> Result<ColorHistogram> {
-    use std::process::Command;
-
-    let output = Command::new("convert")
-        .arg(path)
-        .arg("-resize")
-        .arg("100x100!") // Downsample for speed
-        .arg("-format")
-        .arg("%c")
-        .arg("histogram:info:-")
-        .output()
-        .context("Failed to calculate color histogram")?;
-
-    // Parse histogram
-    let text = String::from_utf8_lossy(&output.stdout);
-    let mut histogram = ColorHistogram {
-        red: [0; 256],
-        green: [0; 256],
-        blue: [0; 256],
-        total_pixels: 0,
-    };
+/// Calculate the perceptual hash of an image file
+fn calculate_perceptual_hash(path: &str) -> Result<PerceptualHash> {
+    let img = crate::image_proc::open_image(path)
+        .with_context(|| format!("Failed to calculate perceptual hash for {}", path))?;
+    Ok(dhash(&img))
+}
 
-    // Simple parsing - just count color occurrences
-    for line in text.lines() {
-        if line.contains("red") {
-            if let Some(num) = line.split_whitespace().next() {
-                if let Ok(count) = num.parse::<u32>() {
-                    // This is simplified - real implementation would parse properly
-                    histogram.total_pixels += count as u64;
-                }
+/// Side length of the downscaled image histograms are computed from
+const HISTOGRAM_SIZE: u32 = 64;
+
+/// RGB and HSV histogram of a decoded image, sampled from a 64x64 copy
+pub fn color_histogram(img: &image::DynamicImage) -> ColorHistogram {
+    let small = image::imageops::resize(
+        &img.to_rgb8(),
+        HISTOGRAM_SIZE,
+        HISTOGRAM_SIZE,
+        image::imageops::FilterType::Triangle,
+    );
+
+    let mut histogram = ColorHistogram::empty();
+    for pixel in small.pixels() {
+        let [r, g, b] = pixel.0;
+        histogram.red[r as usize] += 1;
+        histogram.green[g as usize] += 1;
+        histogram.blue[b as usize] += 1;
+        histogram.total_pixels += 1;
+
+        let max = r.max(g).max(b) as f32;
+        let min = r.min(g).min(b) as f32;
+        let value = max / 255.0;
+        let saturation = if max == 0.0 { 0.0 } else { (max - min) / max };
+
+        if value < 0.2 {
+            histogram.black += 1;
+        } else if saturation < 0.2 {
+            if value > 0.85 {
+                histogram.white += 1;
+            } else {
+                histogram.gray += 1;
             }
+        } else {
+            let hue = hue_degrees(r as f32, g as f32, b as f32, max, min);
+            let bin = ((hue + 15.0) / 30.0) as usize % HUE_BINS;
+            histogram.hue[bin] += 1;
         }
     }
+    histogram
+}
+
+/// HSV hue in degrees (0-360) of a saturated pixel
+fn hue_degrees(r: f32, g: f32, b: f32, max: f32, min: f32) -> f32 {
+    let delta = max - min;
+    let hue = if max == r {
+        60.0 * (((g - b) / delta) % 6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    if hue < 0.0 {
+        hue + 360.0
+    } else {
+        hue
+    }
+}
 
-    Ok(histogram)
+/// Calculate color histogram for an image file
+fn calculate_color_histogram(path: &str) -> Result<ColorHistogram> {
+    let img = crate::image_proc::open_image(path)
+        .with_context(|| format!("Failed to calculate color histogram for {}", path))?;
+    Ok(color_histogram(&img))
 }
 
 /// Extract tags from image path/filename
@@ -558,13 +854,6 @@ fn is_meaningful_tag(tag: &str) -> bool {
     true
 }
 
-/// Get dominant color name from a group of images
-fn get_dominant_color_name(_images: &[String]) -> String {
-    // Simplified - just return a color category
-    // Real implementation would analyze actual colors
-    "Color".to_string()
-}
-
 /// List all tags with their image counts
 pub fn list_tag_statistics(image_paths: &[String], sort_by: &str) -> Result<()> {
     use std::collections::HashMap;
@@ -575,6 +864,7 @@ pub fn list_tag_statistics(image_paths: &[String], sort_by: &str) -> Result<()>
     let mut tag_files: HashMap<String, Vec<String>> = HashMap::new();
     let mut images_with_ai_tags = 0;
     let mut images_with_filename_tags = 0;
+    let mut captions: Vec<(&str, String)> = Vec::new();
 
     for path in image_paths {
         let mut tags = Vec::new();
@@ -585,6 +875,9 @@ pub fn list_tag_statistics(image_paths: &[String], sort_by: &str) -> Result<()>
             _has_ai_tags = true;
             images_with_ai_tags += 1;
             tags.extend(ai_tags.tags.clone());
+            if let Some(caption) = &ai_tags.caption {
+                captions.push((path, caption.clone()));
+            }
 
             // Add content rating as a tag if available
             if let Some(content_rating) = &ai_tags.content_rating {
@@ -741,6 +1034,17 @@ pub fn list_tag_statistics(image_paths: &[String], sort_by: &str) -> Result<()>
         tags_vec.first().map(|(_, c)| *c).unwrap_or(0)
     );
 
+    if !captions.is_empty() {
+        println!("\nCaptions ({} images):", captions.len());
+        for (path, caption) in &captions {
+            let name = Path::new(path)
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or(path);
+            println!("  {}: {}", name, caption);
+        }
+    }
+
     Ok(())
 }
 
@@ -758,20 +1062,63 @@ fn load_ai_tags(image_path: &str) -> Result<AITags> {
 
 /// Filter images by specific tags (OR logic - match any tag)
 pub fn filter_by_tags(images: Vec<ImageEntry>, tags: &[String]) -> Result<Vec<ImageEntry>> {
-    filter_by_tags_advanced(images, tags, &[], &[])
+    filter_by_tags_advanced(images, tags, &[], &[], None)
+}
+
+/// A tag to filter by and, written `beach:0.8`, the confidence the image's
+/// tag needs at least
+#[derive(Debug, Clone, PartialEq)]
+struct TagFilter {
+    tag: String,
+    min_confidence: Option<f32>,
+}
+
+/// Parse `beach` or `beach:0.8`. Only a number with a decimal point is a
+/// confidence, so `rating:5` stays a tag.
+fn parse_tag_filter(s: &str) -> Result<TagFilter> {
+    let s = s.trim();
+    if let Some((tag, value)) = s.rsplit_once(':') {
+        if value.contains('.') {
+            if let Ok(confidence) = value.parse::<f32>() {
+                if !(0.0..=1.0).contains(&confidence) || tag.trim().is_empty() {
+                    anyhow::bail!("Invalid tag filter: {}. Use TAG:CONFIDENCE, 0.0 to 1.0", s);
+                }
+                return Ok(TagFilter {
+                    tag: tag.trim().to_lowercase(),
+                    min_confidence: Some(confidence),
+                });
+            }
+        }
+    }
+    Ok(TagFilter { tag: s.to_lowercase(), min_confidence: None })
+}
+
+/// Tag filters from repeated and comma-separated arguments
+fn parse_tag_filters(args: &[String]) -> Result<Vec<TagFilter>> {
+    args.iter()
+        .flat_map(|t| t.split(','))
+        .filter(|s| !s.trim().is_empty())
+        .map(parse_tag_filter)
+        .collect()
 }
 
 /// Filter images by tags with AND/OR/NOT logic
 /// Supports comma-separated tags in arguments (e.g., "beach,sunset" = beach OR sunset)
+/// An AI tag counts only when the model's confidence in it reaches the
+/// tag's own minimum (`beach:0.8`), or else `min_confidence`
 pub fn filter_by_tags_advanced(
     images: Vec<ImageEntry>,
     tags_or: &[String],
     tags_and: &[String],
     tags_not: &[String],
+    min_confidence: Option<f32>,
 ) -> Result<Vec<ImageEntry>> {
     if tags_or.is_empty() && tags_and.is_empty() && tags_not.is_empty() {
         return Ok(images);
     }
+    if min_confidence.is_some_and(|c| !(0.0..=1.0).contains(&c)) {
+        anyhow::bail!("--min-tag-confidence must be between 0.0 and 1.0");
+    }
 
     let mut filter_parts = Vec::new();
     if !tags_or.is_empty() {
@@ -783,65 +1130,50 @@ pub fn filter_by_tags_advanced(
     if !tags_not.is_empty() {
         filter_parts.push(format!("NOT({})", tags_not.join(", ")));
     }
+    if let Some(min_confidence) = min_confidence {
+        filter_parts.push(format!("confidence >= {}", min_confidence));
+    }
 
-    let tags_or_parsed: Vec<String> = tags_or
-        .iter()
-        .flat_map(|t| t.split(',').map(|s| s.trim().to_string()))
-        .filter(|s| !s.is_empty())
-        .collect();
-
-    let tags_and_parsed: Vec<String> = tags_and
-        .iter()
-        .flat_map(|t| t.split(',').map(|s| s.trim().to_string()))
-        .filter(|s| !s.is_empty())
-        .collect();
-
-    let tags_not_parsed: Vec<String> = tags_not
-        .iter()
-        .flat_map(|t| t.split(',').map(|s| s.trim().to_string()))
-        .filter(|s| !s.is_empty())
-        .collect();
+    let tags_or_parsed = parse_tag_filters(tags_or)?;
+    let tags_and_parsed = parse_tag_filters(tags_and)?;
+    let tags_not_parsed = parse_tag_filters(tags_not)?;
 
     let filtered: Vec<ImageEntry> = images
         .iter()
         .filter(|img| {
-            let mut image_tags = extract_tags(&img.path);
-            if let Ok(ai_tags) = load_ai_tags(&img.path) {
-                image_tags.extend(ai_tags.tags);
+            // Grid entries carry ImageMagick's [0] frame suffix
+            let path = img.path.trim_end_matches("[0]");
+            // Tags from the path are certain
+            let mut image_tags: Vec<(String, f32)> =
+                extract_tags(path).into_iter().map(|tag| (tag.to_lowercase(), 1.0)).collect();
+            if let Ok(ai_tags) = load_ai_tags(path) {
+                image_tags.extend(
+                    ai_tags.tags.iter().map(|tag| (tag.to_lowercase(), ai_tags.confidence_of(tag))),
+                );
                 // Include content rating as a tag if available
                 if let Some(content_rating) = &ai_tags.content_rating {
-                    image_tags.push(content_rating.clone());
+                    image_tags.push((content_rating.to_lowercase(), 1.0));
                 }
             }
 
-            let image_tags_lower: Vec<String> =
-                image_tags.iter().map(|t| t.to_lowercase()).collect();
-
-            let matches_or = tags_or_parsed.is_empty()
-                || tags_or_parsed
-                    .iter()
-                    .any(|requested_tag| image_tags_lower.contains(&requested_tag.to_lowercase()));
-
-            let matches_and = tags_and_parsed.is_empty()
-                || tags_and_parsed
-                    .iter()
-                    .all(|requested_tag| image_tags_lower.contains(&requested_tag.to_lowercase()));
-
-            let matches_not = tags_not_parsed.is_empty()
-                || !tags_not_parsed
-                    .iter()
-                    .any(|requested_tag| image_tags_lower.contains(&requested_tag.to_lowercase()));
+            let has = |filter: &TagFilter| {
+                let min = filter.min_confidence.or(min_confidence).unwrap_or(0.0);
+                image_tags.iter().any(|(tag, confidence)| *tag == filter.tag && *confidence >= min)
+            };
+            let matches_or = tags_or_parsed.is_empty() || tags_or_parsed.iter().any(has);
+            let matches_and = tags_and_parsed.is_empty() || tags_and_parsed.iter().all(has);
+            let matches_not = tags_not_parsed.is_empty() || !tags_not_parsed.iter().any(has);
 
             matches_or && matches_and && matches_not
         })
         .cloned()
         .collect();
 
-    eprintln!(
+    diagnostics::info(&format!(
         "Filtered to {} images by: {}",
         filtered.len(),
         filter_parts.join(" + ")
-    );
+    ));
 
     Ok(filtered)
 }
@@ -856,4 +1188,112 @@ mod tests {
         assert!(tags.contains(&"vacation".to_string()));
         assert!(tags.contains(&"JPG".to_string()));
     }
+
+    #[test]
+    fn test_parse_tag_filter() {
+        let filter = |tag: &str, min_confidence| TagFilter { tag: tag.to_string(), min_confidence };
+        assert_eq!(parse_tag_filter(" Beach:0.8").unwrap(), filter("beach", Some(0.8)));
+        assert_eq!(parse_tag_filter("rating:5").unwrap(), filter("rating:5", None));
+        assert!(parse_tag_filter("beach:1.5").is_err());
+        assert!(parse_tag_filter(":0.5").is_err());
+        let filters = parse_tag_filters(&["beach:0.9,dog".to_string()]).unwrap();
+        assert_eq!(filters, [filter("beach", Some(0.9)), filter("dog", None)]);
+    }
+
+    #[test]
+    fn test_render_group_name() {
+        let group = ImageGroup {
+            id: "date_20240601".to_string(),
+            name: "2024-06-01 Images".to_string(),
+            images: vec!["a.jpg".to_string(), "b.jpg".to_string()],
+            representative: "a.jpg".to_string(),
+            metadata: GroupMetadata {
+                group_type: "time".to_string(),
+                count: 2,
+                common_features: HashMap::from([("date".to_string(), "2024-06-01".to_string())]),
+            },
+        };
+
+        assert_eq!(
+            render_group_name("{date} — {count} photos", 3, &group),
+            "2024-06-01 — 2 photos"
+        );
+        assert_eq!(render_group_name("#{n} {name} {tag}", 3, &group), "#3 2024-06-01 Images {tag}");
+        assert_eq!(group.key(), "time:date_20240601");
+        assert_eq!(parse_group_by("Time").unwrap(), GroupBy::Time);
+        assert!(parse_group_by("mood").is_err());
+    }
+
+    #[test]
+    fn test_hamming_distance() {
+        let hash = |bytes: &[u8]| PerceptualHash {
+            hash: bytes.to_vec(),
+            width: 1,
+            height: 1,
+        };
+        assert_eq!(hash(&[0xff, 0x00]).hamming_distance(&hash(&[0xff, 0x00])), 0);
+        assert_eq!(hash(&[0xff, 0x00]).hamming_distance(&hash(&[0x0f, 0x01])), 5);
+        assert_eq!(hash(&[0xff]).similarity(&hash(&[0x00])), 0.0);
+    }
+
+    #[test]
+    fn test_dhash_matches_resized_copies() {
+        use image::{DynamicImage, RgbImage};
+
+        let gradient = DynamicImage::ImageRgb8(RgbImage::from_fn(320, 200, |x, y| {
+            let v = ((x * 7 + y * 3) % 256) as u8;
+            image::Rgb([v, 255 - v, (x % 256) as u8])
+        }));
+        let small = gradient.resize_exact(160, 100, image::imageops::FilterType::Triangle);
+        let other = DynamicImage::ImageRgb8(RgbImage::from_fn(320, 200, |x, y| {
+            if (x / 40 + y / 40) % 2 == 0 {
+                image::Rgb([0, 0, 0])
+            } else {
+                image::Rgb([255, 255, 255])
+            }
+        }));
+
+        let original = dhash(&gradient);
+        assert_eq!((original.width, original.height), (320, 200));
+        assert!(original.similarity(&dhash(&small)) > 0.9);
+        assert!(original.similarity(&dhash(&other)) < 0.8);
+    }
+
+    fn solid(r: u8, g: u8, b: u8) -> ColorHistogram {
+        let img = image::RgbImage::from_pixel(32, 32, image::Rgb([r, g, b]));
+        color_histogram(&image::DynamicImage::ImageRgb8(img))
+    }
+
+    #[test]
+    fn test_dominant_color_name() {
+        assert_eq!(solid(220, 30, 30).dominant_color_name(), "Red");
+        assert_eq!(solid(30, 60, 200).dominant_color_name(), "Blue");
+        assert_eq!(solid(250, 250, 250).dominant_color_name(), "White");
+        assert_eq!(solid(10, 10, 10).dominant_color_name(), "Black");
+
+        // Mixed reds, oranges and yellows are warm
+        let mut warm = solid(220, 30, 30);
+        warm.merge(&solid(240, 140, 20));
+        warm.merge(&solid(230, 220, 30));
+        assert_eq!(warm.dominant_color_name(), "Warm");
+    }
+
+    #[test]
+    fn test_color_histogram_similarity() {
+        let red = solid(220, 30, 30);
+        // Same hue bin, different RGB bins
+        assert!(red.similarity(&solid(210, 40, 35)) >= 0.5);
+        assert!(red.similarity(&solid(30, 60, 200)) < 0.1);
+        assert_eq!(red.total_pixels, (HISTOGRAM_SIZE * HISTOGRAM_SIZE) as u64);
+
+        // Feature vectors reproduce the similarity as a dot product
+        let orange = solid(230, 120, 20);
+        let dot: f32 = red
+            .feature_vector()
+            .iter()
+            .zip(orange.feature_vector())
+            .map(|(a, b)| a * b)
+            .sum();
+        assert!((dot - red.similarity(&orange)).abs() < 1e-4);
+    }
 }
@@ -0,0 +1,138 @@
+// Persisted perceptual hashes (~/.cache/lsix/hash_index.json), so repeated
+// --group-by similarity runs only decode new or changed images. Entries are
+// keyed by canonical path and trusted only while the file's size and
+// modification time are unchanged.
+
+use crate::grouping::PerceptualHash;
+use crate::image_proc::get_cache_dir;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Hash of one file, with what it was computed from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct IndexEntry {
+    size: u64,
+    /// Modification time, nanoseconds since the epoch
+    modified: u64,
+    hash: PerceptualHash,
+}
+
+/// Perceptual hashes by image path
+#[derive(Debug, Default)]
+pub struct HashIndex {
+    entries: HashMap<String, IndexEntry>,
+    /// Changed since loading
+    dirty: bool,
+}
+
+fn index_path() -> Result<PathBuf> {
+    Ok(get_cache_dir()?.join("hash_index.json"))
+}
+
+/// Size and modification time of `path`
+pub(crate) fn file_stamp(path: &str) -> Option<(u64, u64)> {
+    let metadata = fs::metadata(path.trim_end_matches("[0]")).ok()?;
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some((metadata.len(), modified.as_nanos() as u64))
+}
+
+impl HashIndex {
+    /// The index saved by earlier runs; a missing or unreadable file starts
+    /// empty
+    pub fn load() -> HashIndex {
+        index_path().map(|path| Self::load_from(&path)).unwrap_or_default()
+    }
+
+    fn load_from(path: &Path) -> HashIndex {
+        let entries = fs::read_to_string(path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        HashIndex {
+            entries,
+            dirty: false,
+        }
+    }
+
+    /// Stored hash of `path`, unless the file changed since it was computed
+    pub fn get(&self, path: &str) -> Option<PerceptualHash> {
+        let entry = self.entries.get(path)?;
+        let (size, modified) = file_stamp(path)?;
+        (entry.size == size && entry.modified == modified).then(|| entry.hash.clone())
+    }
+
+    /// Record the hash of `path` as of the file's current state
+    pub fn insert(&mut self, path: &str, hash: PerceptualHash) {
+        if let Some((size, modified)) = file_stamp(path) {
+            self.entries.insert(
+                path.to_string(),
+                IndexEntry {
+                    size,
+                    modified,
+                    hash,
+                },
+            );
+            self.dirty = true;
+        }
+    }
+
+    /// Write the index back if anything was added; entries of files that no
+    /// longer exist are dropped
+    pub fn save(&mut self) -> Result<()> {
+        if self.dirty {
+            self.save_to(&index_path()?)?;
+        }
+        Ok(())
+    }
+
+    fn save_to(&mut self, path: &Path) -> Result<()> {
+        self.entries
+            .retain(|image, _| Path::new(image.trim_end_matches("[0]")).exists());
+        let json = serde_json::to_string(&self.entries)?;
+        // Write then rename so a concurrent run never reads a partial index
+        let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+        fs::write(&tmp, json).with_context(|| format!("Failed to write {}", tmp.display()))?;
+        fs::rename(&tmp, path).with_context(|| format!("Failed to write {}", path.display()))?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn test_hash_index_round_trip() {
+        let dir = TestDir::new("hash-index");
+        let image = dir.join("a.png").to_string_lossy().to_string();
+        let gone = dir.join("gone.png").to_string_lossy().to_string();
+        fs::write(&image, b"first").unwrap();
+        fs::write(&gone, b"gone").unwrap();
+
+        let hash = PerceptualHash {
+            hash: vec![0xab; 8],
+            width: 640,
+            height: 480,
+        };
+        let mut index = HashIndex::default();
+        index.insert(&image, hash.clone());
+        index.insert(&gone, hash.clone());
+        fs::remove_file(&gone).unwrap();
+
+        let index_file = dir.join("hash_index.json");
+        index.save_to(&index_file).unwrap();
+        let index = HashIndex::load_from(&index_file);
+        assert_eq!(index.get(&image), Some(hash));
+        assert!(!index.entries.contains_key(&gone));
+
+        // A changed file no longer matches its entry
+        fs::write(&image, b"edited").unwrap();
+        assert_eq!(index.get(&image), None);
+    }
+}
@@ -0,0 +1,78 @@
+// HEIC/HEIF photos, the format iPhones save in. The image crate can't read
+// them, so with the `heif` cargo feature they are decoded through libheif
+// (the libheif-rs binding), rotated and mirrored as the file says; every
+// decode that goes through `open_image` (grids, the TUI, thumbnails) then
+// handles them like any other image. Builds without the feature, which
+// need no libheif, leave .heic and .heif files out of directory listings
+// and fail on them with how to get a build that reads them.
+
+use anyhow::Result;
+use image::DynamicImage;
+use std::path::Path;
+
+/// Extensions of HEIF files
+pub const HEIF_EXTENSIONS: [&str; 2] = ["heic", "heif"];
+
+/// Whether this build decodes HEIF files
+pub const SUPPORTED: bool = cfg!(feature = "heif");
+
+/// Whether the file at `path` is HEIF, by its extension
+pub fn is_heif(path: &str) -> bool {
+    Path::new(path.trim_end_matches("[0]"))
+        .extension()
+        .is_some_and(|ext| HEIF_EXTENSIONS.contains(&ext.to_string_lossy().to_lowercase().as_str()))
+}
+
+/// Decode the primary image of the HEIF file at `path`
+#[cfg(feature = "heif")]
+pub fn open_heif(path: &str) -> Result<DynamicImage> {
+    use anyhow::Context;
+    use libheif_rs::{ColorSpace, HeifContext, LibHeif, RgbChroma};
+
+    let path = path.trim_end_matches("[0]");
+    let context =
+        HeifContext::read_from_file(path).with_context(|| format!("Failed to read {}", path))?;
+    let handle = context.primary_image_handle()?;
+    let decoded = LibHeif::new()
+        .decode(&handle, ColorSpace::Rgb(RgbChroma::Rgba), None)
+        .with_context(|| format!("Failed to decode {}", path))?;
+    let planes = decoded.planes();
+    let plane = planes
+        .interleaved
+        .ok_or_else(|| anyhow::anyhow!("{} decoded without RGBA pixels", path))?;
+
+    // Rows may be padded past the pixels
+    let row_len = plane.width as usize * 4;
+    let mut pixels = Vec::with_capacity(row_len * plane.height as usize);
+    for row in plane.data.chunks(plane.stride).take(plane.height as usize) {
+        pixels.extend_from_slice(&row[..row_len]);
+    }
+    let image = image::RgbaImage::from_raw(plane.width, plane.height, pixels)
+        .ok_or_else(|| anyhow::anyhow!("{} has an incomplete image", path))?;
+    Ok(DynamicImage::ImageRgba8(image))
+}
+
+/// Decode the primary image of the HEIF file at `path`: not in this build
+#[cfg(not(feature = "heif"))]
+pub fn open_heif(path: &str) -> Result<DynamicImage> {
+    anyhow::bail!(
+        "{} is a HEIC/HEIF image, which this build of lsix can't decode. Install libheif and \
+         rebuild with `cargo build --release --features heif`.",
+        path.trim_end_matches("[0]")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heif() {
+        assert!(is_heif("IMG_0001.HEIC") && is_heif("photos/a.heif"));
+        assert!(!is_heif("a.jpg") && !is_heif("heic"));
+        if !SUPPORTED {
+            let error = open_heif("IMG_0001.HEIC").unwrap_err().to_string();
+            assert!(error.contains("--features heif"));
+        }
+    }
+}
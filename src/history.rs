@@ -0,0 +1,156 @@
+// Invocation history for `--again` and `--history`, stored as JSON in the
+// state directory (see app_dirs).
+
+use crate::app_dirs::state_dir;
+use crate::test_mode;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+/// Number of invocations kept across all directories
+const MAX_HISTORY_ENTRIES: usize = 100;
+
+/// One recorded invocation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// Working directory the command ran in
+    pub cwd: String,
+    /// Arguments after the program name
+    pub args: Vec<String>,
+    /// Unix time of the invocation
+    pub timestamp: i64,
+}
+
+/// All recorded invocations, oldest first
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct History {
+    /// Recorded invocations, capped at the most recent ones
+    pub entries: Vec<HistoryEntry>,
+}
+
+impl History {
+    /// Append an invocation, dropping the oldest entries beyond the cap
+    pub fn push(&mut self, entry: HistoryEntry) {
+        self.entries.push(entry);
+        if self.entries.len() > MAX_HISTORY_ENTRIES {
+            let excess = self.entries.len() - MAX_HISTORY_ENTRIES;
+            self.entries.drain(..excess);
+        }
+    }
+
+    /// Most recent invocation made in `cwd`
+    pub fn last_for_dir(&self, cwd: &str) -> Option<&HistoryEntry> {
+        self.entries.iter().rev().find(|e| e.cwd == cwd)
+    }
+}
+
+fn history_path() -> PathBuf {
+    state_dir().join("history.json")
+}
+
+/// Load the history; a missing or unreadable file yields an empty history
+pub fn load_history() -> History {
+    fs::read_to_string(history_path())
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+/// Record an invocation of `args` in `cwd`
+pub fn record(cwd: &str, args: &[String]) -> Result<()> {
+    let mut history = load_history();
+    history.push(HistoryEntry {
+        cwd: cwd.to_string(),
+        args: args.to_vec(),
+        timestamp: test_mode::now().timestamp(),
+    });
+
+    let path = history_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).context("Failed to create state directory")?;
+    }
+    fs::write(&path, serde_json::to_string_pretty(&history)?)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(())
+}
+
+/// Print the most recent invocations, newest last
+pub fn print_history(limit: usize) {
+    let history = load_history();
+    if history.entries.is_empty() {
+        eprintln!("No lsix invocations recorded yet.");
+        return;
+    }
+
+    let start = history.entries.len().saturating_sub(limit);
+    for entry in &history.entries[start..] {
+        let time = chrono::DateTime::from_timestamp(entry.timestamp, 0)
+            .map(|t| test_mode::local(t).format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_default();
+        println!("{}  {}  {}", time, entry.cwd, format_command(&entry.args));
+    }
+}
+
+/// Render arguments as a shell command line
+pub fn format_command(args: &[String]) -> String {
+    std::iter::once("lsix".to_string())
+        .chain(args.iter().map(|a| shell_quote(a)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Quote an argument for POSIX shells if it contains special characters
+fn shell_quote(arg: &str) -> String {
+    let safe = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c));
+
+    if safe {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(cwd: &str, args: &[&str]) -> HistoryEntry {
+        HistoryEntry {
+            cwd: cwd.to_string(),
+            args: args.iter().map(|a| a.to_string()).collect(),
+            timestamp: 0,
+        }
+    }
+
+    #[test]
+    fn test_last_for_dir() {
+        let mut history = History::default();
+        history.push(entry("/a", &["--grid"]));
+        history.push(entry("/b", &["-r"]));
+        history.push(entry("/a", &["--min-width", "800"]));
+
+        assert_eq!(history.last_for_dir("/a").unwrap().args, vec!["--min-width", "800"]);
+        assert_eq!(history.last_for_dir("/b").unwrap().args, vec!["-r"]);
+        assert!(history.last_for_dir("/c").is_none());
+    }
+
+    #[test]
+    fn test_history_is_capped() {
+        let mut history = History::default();
+        for i in 0..MAX_HISTORY_ENTRIES + 5 {
+            history.push(entry(&i.to_string(), &[]));
+        }
+        assert_eq!(history.entries.len(), MAX_HISTORY_ENTRIES);
+        assert_eq!(history.entries[0].cwd, "5");
+    }
+
+    #[test]
+    fn test_format_command() {
+        let args = vec!["--tag".to_string(), "beach sunset".to_string(), "it's.png".to_string()];
+        assert_eq!(format_command(&args), "lsix --tag 'beach sunset' 'it'\\''s.png'");
+    }
+}
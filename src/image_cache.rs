@@ -0,0 +1,139 @@
+// Decoded images kept in memory by the TUI browser, bounded by a byte
+// budget. When an insert goes over budget the least recently used images
+// are dropped; lookups count as use.
+
+use image::DynamicImage;
+use std::cell::Cell;
+use std::collections::HashMap;
+
+/// Default budget for decoded full-size images, in MiB
+pub const DEFAULT_CACHE_MB: usize = 512;
+
+/// Images never evicted even over budget, so a before/after pair fits
+const MIN_ENTRIES: usize = 2;
+
+struct Entry {
+    image: DynamicImage,
+    bytes: usize,
+    last_used: Cell<u64>,
+}
+
+/// LRU cache of decoded images with a memory budget
+pub struct ImageCache {
+    entries: HashMap<String, Entry>,
+    budget: usize,
+    used: usize,
+    clock: Cell<u64>,
+}
+
+impl ImageCache {
+    /// Cache holding at most `budget_mb` MiB of decoded pixels
+    pub fn new(budget_mb: usize) -> Self {
+        ImageCache {
+            entries: HashMap::new(),
+            budget: budget_mb.saturating_mul(1024 * 1024),
+            used: 0,
+            clock: Cell::new(0),
+        }
+    }
+
+    fn tick(&self) -> u64 {
+        let now = self.clock.get() + 1;
+        self.clock.set(now);
+        now
+    }
+
+    pub fn contains_key(&self, path: &str) -> bool {
+        self.entries.contains_key(path)
+    }
+
+    /// Look up an image and mark it as recently used
+    pub fn get(&self, path: &str) -> Option<&DynamicImage> {
+        let entry = self.entries.get(path)?;
+        entry.last_used.set(self.tick());
+        Some(&entry.image)
+    }
+
+    /// Add an image, evicting the least recently used ones if over budget
+    pub fn insert(&mut self, path: String, image: DynamicImage) {
+        self.remove(&path);
+        let bytes = image.as_bytes().len();
+        let last_used = Cell::new(self.tick());
+        self.used += bytes;
+        self.entries.insert(path, Entry { image, bytes, last_used });
+        self.evict();
+    }
+
+    pub fn remove(&mut self, path: &str) -> Option<DynamicImage> {
+        let entry = self.entries.remove(path)?;
+        self.used -= entry.bytes;
+        Some(entry.image)
+    }
+
+    /// Bytes of decoded pixels currently held
+    pub fn used_bytes(&self) -> usize {
+        self.used
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn evict(&mut self) {
+        while self.used > self.budget && self.entries.len() > MIN_ENTRIES {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used.get())
+                .map(|(path, _)| path.clone());
+            match oldest {
+                Some(path) => {
+                    self.remove(&path);
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbaImage;
+
+    /// 1 MiB RGBA image
+    fn mib_image() -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::new(512, 512))
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let mut cache = ImageCache::new(3);
+        cache.insert("a".to_string(), mib_image());
+        cache.insert("b".to_string(), mib_image());
+        cache.insert("c".to_string(), mib_image());
+        assert!(cache.get("a").is_some());
+
+        cache.insert("d".to_string(), mib_image());
+        assert!(!cache.contains_key("b"));
+        assert!(cache.contains_key("a"));
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.used_bytes(), 3 * 1024 * 1024);
+    }
+
+    #[test]
+    fn test_keeps_pair_over_budget() {
+        let mut cache = ImageCache::new(0);
+        cache.insert("before".to_string(), mib_image());
+        cache.insert("after".to_string(), mib_image());
+        assert!(cache.get("before").is_some() && cache.get("after").is_some());
+
+        cache.insert("next".to_string(), mib_image());
+        assert_eq!(cache.len(), 2);
+        assert!(!cache.contains_key("before"));
+    }
+}
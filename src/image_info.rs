@@ -0,0 +1,132 @@
+// Details of one image for the TUI info panel (the `i` key): file name,
+// pixel size, file size, format, EXIF capture data and location, brightness
+// and dominant color. Brightness and color are measured on the grid thumbnail, so opening
+// the panel doesn't decode the full image or call ImageMagick.
+
+use crate::filter::format_file_size;
+use crate::geo_map::read_position;
+use crate::grouping::color_histogram;
+use crate::json_output::read_exif;
+use crate::scrub::format_position;
+use image::{DynamicImage, ImageReader};
+use std::path::Path;
+
+/// EXIF fields shown under "Capture", by tag name, with their labels
+const CAPTURE_FIELDS: [(&str, &str); 9] = [
+    ("DateTimeOriginal", "Taken"),
+    ("Make", "Make"),
+    ("Model", "Camera"),
+    ("LensModel", "Lens"),
+    ("FocalLength", "Focal length"),
+    ("FNumber", "Aperture"),
+    ("ExposureTime", "Exposure"),
+    ("PhotographicSensitivity", "ISO"),
+    ("Flash", "Flash"),
+];
+
+/// What the info panel shows about one image
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImageDetails {
+    pub name: String,
+    /// Pixel size, if the image header could be read
+    pub dimensions: Option<(u32, u32)>,
+    pub file_size: Option<u64>,
+    /// Format found from the file contents, e.g. "JPEG"
+    pub format: Option<String>,
+    /// Capture fields the image has, as (label, value) in display order
+    pub capture: Vec<(&'static str, String)>,
+    /// Where the photo was taken, as decimal (latitude, longitude)
+    pub position: Option<(f64, f64)>,
+    /// Mean brightness of the thumbnail, 0.0 (dark) to 1.0 (bright)
+    pub brightness: Option<f32>,
+    /// Name of the thumbnail's dominant color, e.g. "Blue"
+    pub dominant_color: Option<String>,
+}
+
+impl ImageDetails {
+    /// Read the details of `path`; brightness and color are left out until
+    /// a `thumbnail` is available
+    pub fn load(path: &str, thumbnail: Option<&DynamicImage>) -> ImageDetails {
+        let file = path.trim_end_matches("[0]");
+        let exif = read_exif(file);
+        let reader = ImageReader::open(file).and_then(|r| r.with_guessed_format()).ok();
+        let position = read_position(file);
+        let mut capture: Vec<(&'static str, String)> = CAPTURE_FIELDS
+            .iter()
+            .filter_map(|(tag, label)| Some((*label, exif.get(*tag)?.clone())))
+            .collect();
+        if let Some((lat, lon)) = position {
+            capture.push(("Location", format_position(lat, lon)));
+        }
+        ImageDetails {
+            name: Path::new(file)
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_else(|| file.to_string()),
+            file_size: std::fs::metadata(file).ok().map(|m| m.len()),
+            format: reader
+                .as_ref()
+                .and_then(|r| r.format())
+                .map(|format| format!("{:?}", format).to_uppercase()),
+            // Read through the guessed format, as the extension may be wrong
+            dimensions: reader.and_then(|r| r.into_dimensions().ok()),
+            capture,
+            position,
+            brightness: thumbnail.map(mean_brightness),
+            dominant_color: thumbnail.map(|t| color_histogram(t).dominant_color_name()),
+        }
+    }
+
+    /// The file rows of the panel as (label, value); values that couldn't
+    /// be read are shown as "?"
+    pub fn rows(&self) -> Vec<(&'static str, String)> {
+        let unknown = || "?".to_string();
+        vec![
+            ("Name", self.name.clone()),
+            (
+                "Dimensions",
+                self.dimensions.map_or_else(unknown, |(w, h)| format!("{} × {}", w, h)),
+            ),
+            ("File size", self.file_size.map_or_else(unknown, format_file_size)),
+            ("Format", self.format.clone().unwrap_or_else(unknown)),
+            ("Brightness", self.brightness.map_or_else(unknown, |b| format!("{:.2}", b))),
+            ("Dominant color", self.dominant_color.clone().unwrap_or_else(unknown)),
+        ]
+    }
+}
+
+/// Mean luma of `image`, 0.0 to 1.0
+fn mean_brightness(image: &DynamicImage) -> f32 {
+    let gray = image.to_luma8();
+    let pixels = (gray.width() as u64 * gray.height() as u64).max(1);
+    let sum: u64 = gray.pixels().map(|p| p[0] as u64).sum();
+    sum as f32 / pixels as f32 / 255.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn test_load_details() {
+        let dir = TestDir::new("info");
+        // A PNG behind a .jpg name: the format comes from the contents
+        let path = dir.join("sky.jpg").to_string_lossy().to_string();
+        let image = image::RgbImage::from_pixel(40, 20, image::Rgb([40, 90, 220]));
+        image.save_with_format(&path, image::ImageFormat::Png).unwrap();
+
+        let details = ImageDetails::load(&path, None);
+        assert_eq!(details.name, "sky.jpg");
+        assert_eq!(details.dimensions, Some((40, 20)));
+        assert_eq!(details.format.as_deref(), Some("PNG"));
+        assert!(details.capture.is_empty());
+        assert_eq!(details.position, None);
+        assert_eq!(details.rows()[4], ("Brightness", "?".to_string()));
+
+        let thumbnail = DynamicImage::ImageRgb8(image);
+        let details = ImageDetails::load(&path, Some(&thumbnail));
+        assert_eq!(details.dominant_color.as_deref(), Some("Blue"));
+        assert!(details.brightness.is_some_and(|b| b > 0.2 && b < 0.5));
+    }
+}
@@ -3,17 +3,26 @@ use rayon::prelude::*;
 use std::collections::hash_map::DefaultHasher;
 use std::fs;
 use std::hash::{Hash, Hasher};
-use std::io::{self, Write};
+use std::io;
 use std::process::{Command, Stdio};
-use std::sync::OnceLock;
+use std::sync::Arc;
 
 // Import filename types
 use crate::filename::FilenameMode;
 use crate::filter::{analyze_image, FilterConfig};
+use crate::gif_frame::{self, GifFrame};
 use crate::grouping::ImageGroup;
-
-/// ImageMagick command detection result
-static IMAGEMAGICK_MODE: OnceLock<ImageMagickMode> = OnceLock::new();
+use crate::heif;
+use crate::modern_formats;
+use crate::row_fallback::RowWriter;
+use crate::sixel_encoder::Palette;
+use crate::terminal::GraphicsProtocol;
+use crate::diagnostics::{self, WarningKind};
+use crate::timing;
+use crate::tools::{self, Tool};
+use crate::usage_stats;
+use crate::video_thumbs;
+use crate::watermark::Watermark;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum ImageMagickMode {
@@ -23,55 +32,148 @@ enum ImageMagickMode {
     V6,
 }
 
-/// Detect ImageMagick version and command style
-fn detect_imagemagick() -> ImageMagickMode {
-    // Check for ImageMagick 7.x first (magick command)
-    if Command::new("magick")
-        .arg("-version")
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .status()
-        .map(|s| s.success())
-        .unwrap_or(false)
-    {
-        return ImageMagickMode::V7;
+/// Get the installed ImageMagick's command style
+fn get_imagemagick_mode() -> Result<ImageMagickMode> {
+    match tools::require(Tool::ImageMagick, "The ImageMagick encoder")? {
+        "magick" => Ok(ImageMagickMode::V7),
+        _ => Ok(ImageMagickMode::V6),
     }
+}
 
-    // Check for ImageMagick 6.x (montage command)
-    if Command::new("montage")
-        .arg("-version")
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .status()
-        .map(|s| s.success())
-        .unwrap_or(false)
-    {
-        return ImageMagickMode::V6;
+/// How grid rows are turned into SIXEL
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Encoder {
+    /// Built-in compositor and SIXEL encoder (no external tools)
+    Native,
+    /// ImageMagick `montage` piped into `convert`
+    ImageMagick,
+}
+
+/// Parse encoder name from string
+pub fn parse_encoder(s: &str) -> Result<Encoder> {
+    match s.to_lowercase().as_str() {
+        "native" | "builtin" => Ok(Encoder::Native),
+        "imagemagick" | "magick" => Ok(Encoder::ImageMagick),
+        _ => anyhow::bail!("Invalid encoder: {}. Use: native or imagemagick", s),
     }
+}
 
-    // Default to V7 (will fail with clear error message)
-    ImageMagickMode::V7
+/// Where the native SIXEL encoder gets each row's palette
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PaletteMode {
+    /// Every row is quantized on its own
+    PerRow,
+    /// One palette from a sample of all thumbnails in the listing (or in
+    /// each group), shared by its rows
+    Global,
 }
 
-/// Get the detected ImageMagick mode
-fn get_imagemagick_mode() -> ImageMagickMode {
-    *IMAGEMAGICK_MODE.get_or_init(|| detect_imagemagick())
+/// Parse palette mode from string
+pub fn parse_palette_mode(s: &str) -> Result<PaletteMode> {
+    match s.to_lowercase().as_str() {
+        "per-row" | "row" => Ok(PaletteMode::PerRow),
+        "global" => Ok(PaletteMode::Global),
+        _ => anyhow::bail!("Invalid palette: {}. Use: global or per-row", s),
+    }
+}
+
+/// Parse a tile border given as "<px> <color>", e.g. "2 #444444"
+pub fn parse_border(s: &str) -> Result<(u32, String)> {
+    let parts: Vec<&str> = s.split_whitespace().collect();
+    let [px, color] = parts[..] else {
+        anyhow::bail!("Invalid border: {}. Use: <px> <color>, e.g. 2 #444444", s);
+    };
+    let px: u32 = px
+        .parse()
+        .with_context(|| format!("Invalid border width: {}", px))?;
+    if crate::montage::parse_color(color).is_none() {
+        anyhow::bail!("Invalid border color: {}. Use #rgb, #rrggbb or a color name", color);
+    }
+    Ok((px, color.to_string()))
+}
+
+/// Decode an image with the `image` crate, honoring EXIF orientation
+/// (the equivalent of ImageMagick's -auto-orient).
+/// ImageMagick's "[0]" frame selector is accepted and ignored, since only
+/// the first frame is decoded anyway.
+pub fn open_image(path: &str) -> Result<image::DynamicImage> {
+    use image::ImageDecoder;
+
+    if video_thumbs::is_video(path) {
+        return video_thumbs::open_frame(path);
+    }
+    if heif::is_heif(path) {
+        return heif::open_heif(path);
+    }
+    if modern_formats::is_jxl(path) {
+        return modern_formats::open_jxl(path);
+    }
+    if modern_formats::is_avif(path) {
+        modern_formats::check_avif(path)?;
+    }
+    let path = path.trim_end_matches("[0]");
+    let mut decoder = image::ImageReader::open(path)?
+        .with_guessed_format()?
+        .into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut img = image::DynamicImage::from_decoder(decoder)?;
+    img.apply_orientation(orientation);
+    Ok(img)
 }
 
 /// Configuration for image processing
 #[derive(Debug, Clone)]
 pub struct ImageConfig {
+    /// Maximum thumbnail width in pixels
     pub tile_width: u32,
+    /// Maximum thumbnail height in pixels
     pub tile_height: u32,
+    /// Horizontal padding on each side of a tile
     pub tile_xspace: u32,
+    /// Vertical padding above and below a tile
     pub tile_yspace: u32,
+    /// Tiles per output row
     pub num_tiles_per_row: u32,
+    /// Palette size for SIXEL output
     pub num_colors: u32,
+    /// Whether rows share one palette (native SIXEL only)
+    pub palette: PaletteMode,
+    /// Palette shared by the rows being rendered in `PaletteMode::Global`
+    pub shared_palette: Option<Arc<Palette>>,
+    /// Background color (ImageMagick color syntax)
     pub background: String,
+    /// Label color (ImageMagick color syntax)
     pub foreground: String,
+    /// Label font for the ImageMagick encoder
     pub font_family: Option<String>,
+    /// Label font size in points
     pub font_size: u32,
+    /// Draw drop shadows under thumbnails
     pub shadow: bool,
+    /// How rows are rendered
+    pub encoder: Encoder,
+    /// Inline graphics protocol the rows are emitted in
+    pub protocol: GraphicsProtocol,
+    /// Shadow lift applied to thumbnails for display only, 0.0 (off) to 1.0
+    pub boost_shadows: Option<f32>,
+    /// Border drawn around each thumbnail in pixels, 0 for none
+    pub border_width: u32,
+    /// Border color (ImageMagick color syntax)
+    pub border_color: String,
+    /// Round the corners of thumbnails (and their border)
+    pub rounded: bool,
+    /// Logo or text stamped onto each thumbnail
+    pub watermark: Option<Arc<Watermark>>,
+    /// Frame animated thumbnails show
+    pub gif_frame: GifFrame,
+}
+
+/// Encoder selected by LSIX_ENCODER, native unless asked otherwise
+fn default_encoder() -> Encoder {
+    std::env::var("LSIX_ENCODER")
+        .ok()
+        .and_then(|s| parse_encoder(&s).ok())
+        .unwrap_or(Encoder::Native)
 }
 
 impl ImageConfig {
@@ -125,11 +227,21 @@ impl ImageConfig {
             tile_yspace,
             num_tiles_per_row,
             num_colors: optimized_colors,
+            palette: PaletteMode::PerRow,
+            shared_palette: None,
             background: bg.to_string(),
             foreground: fg.to_string(),
             font_family: None,
             font_size,
             shadow,
+            encoder: default_encoder(),
+            protocol: GraphicsProtocol::Sixel,
+            boost_shadows: None,
+            border_width: 0,
+            border_color: "gray".to_string(),
+            rounded: false,
+            watermark: None,
+            gif_frame: GifFrame::First,
         }
     }
 
@@ -163,14 +275,72 @@ impl ImageConfig {
             tile_yspace,
             num_tiles_per_row,
             num_colors: optimized_colors,
+            palette: PaletteMode::PerRow,
+            shared_palette: None,
             background: bg.to_string(),
             foreground: fg.to_string(),
             font_family: None,
             font_size,
             shadow,
+            encoder: default_encoder(),
+            protocol: GraphicsProtocol::Sixel,
+            boost_shadows: None,
+            border_width: 0,
+            border_color: "gray".to_string(),
+            rounded: false,
+            watermark: None,
+            gif_frame: GifFrame::First,
         }
     }
 
+    /// Pixels around a thumbnail on each side: border plus padding
+    fn tile_margin(&self) -> u32 {
+        self.border_width + self.tile_xspace
+    }
+
+    /// Width of one tile in a row: thumbnail, border and padding
+    pub fn cell_width(&self) -> u32 {
+        self.tile_width + 2 * self.tile_margin()
+    }
+
+    /// Change the tile size and recompute the row layout for `width`
+    pub fn set_tile_size(&mut self, width: u32, tile_size: u32) {
+        let tile_size = tile_size.max(16);
+        self.tile_width = tile_size;
+        self.tile_height = tile_size;
+        self.num_tiles_per_row = (width / (self.cell_width() + 1)).max(1);
+        self.font_size = (tile_size / 10).max(10);
+    }
+
+    /// Set the padding on each side of a tile and recompute the row layout
+    pub fn set_spacing(&mut self, width: u32, spacing: u32) {
+        self.tile_xspace = spacing;
+        self.tile_yspace = spacing;
+        self.num_tiles_per_row = (width / (self.cell_width() + 1)).max(1);
+    }
+
+    /// Draw a border around each thumbnail and recompute the row layout
+    pub fn set_border(&mut self, width: u32, border_width: u32, color: &str) {
+        self.border_width = border_width;
+        self.border_color = color.to_string();
+        self.num_tiles_per_row = (width / (self.cell_width() + 1)).max(1);
+    }
+
+    /// Recompute the row layout for a new terminal `width`, keeping the tile size
+    pub fn fit_width(&mut self, width: u32) {
+        self.num_tiles_per_row = (width / (self.cell_width() + 1)).max(1);
+    }
+
+    /// Show exactly `columns` tiles per row, shrinking tiles if they don't fit
+    pub fn set_columns(&mut self, width: u32, columns: u32) {
+        let columns = columns.max(1);
+        let fit = (width / columns).saturating_sub(2 * self.tile_margin() + 1);
+        if fit < self.tile_width {
+            self.set_tile_size(width, fit);
+        }
+        self.num_tiles_per_row = columns;
+    }
+
     /// Get ImageMagick montage options
     fn get_montage_options(&self) -> Vec<String> {
         let mut opts = Vec::new();
@@ -180,13 +350,26 @@ impl ImageConfig {
         opts.push(format!("{}x1", self.num_tiles_per_row));
 
         // Geometry - IMPORTANT: use ">" to only shrink, never enlarge!
-        // This matches the original script behavior
+        // This matches the original script behavior.
+        // Rounded thumbnails arrive pre-framed (see `framed_copy`), border
+        // included, so the geometry leaves room for it instead of -border.
+        let frame = if self.rounded { 2 * self.border_width } else { 0 };
         opts.push("-geometry".to_string());
         opts.push(format!(
             "{}x{}>+{}+{}",
-            self.tile_width, self.tile_height, self.tile_xspace, self.tile_yspace
+            self.tile_width + frame,
+            self.tile_height + frame,
+            self.tile_xspace,
+            self.tile_yspace
         ));
 
+        if self.border_width > 0 && !self.rounded {
+            opts.push("-border".to_string());
+            opts.push(self.border_width.to_string());
+            opts.push("-bordercolor".to_string());
+            opts.push(self.border_color.clone());
+        }
+
         // Background and foreground colors
         opts.push("-background".to_string());
         opts.push(self.background.clone());
@@ -201,6 +384,12 @@ impl ImageConfig {
             opts.push("-shadow".to_string());
         }
 
+        // Lift shadows the same way the native encoder does
+        if let Some(strength) = self.boost_shadows {
+            opts.push("-gamma".to_string());
+            opts.push(format!("{:.3}", 1.0 / shadow_gamma(strength)));
+        }
+
         // Font settings
         if let Some(ref family) = self.font_family {
             opts.push("-font".to_string());
@@ -214,20 +403,20 @@ impl ImageConfig {
     }
 
     /// Get the montage command based on ImageMagick version
-    fn get_montage_command(&self) -> Command {
-        match get_imagemagick_mode() {
+    fn get_montage_command(&self) -> Result<Command> {
+        Ok(match get_imagemagick_mode()? {
             ImageMagickMode::V7 => {
                 let mut cmd = Command::new("magick");
                 cmd.arg("montage");
                 cmd
             }
             ImageMagickMode::V6 => Command::new("montage"),
-        }
+        })
     }
 
     /// Get the convert command based on ImageMagick version
-    fn get_convert_command(&self) -> Command {
-        match get_imagemagick_mode() {
+    fn get_convert_command(&self) -> Result<Command> {
+        Ok(match get_imagemagick_mode()? {
             ImageMagickMode::V7 => {
                 let mut cmd = Command::new("magick");
                 cmd.arg("-");
@@ -240,46 +429,125 @@ impl ImageConfig {
                 cmd.arg("-");
                 cmd
             }
-        }
+        })
     }
 }
 
 /// A single image entry with its label
 #[derive(Debug, Clone)]
 pub struct ImageEntry {
+    /// Path passed to the decoder (may carry ImageMagick's `[0]` suffix)
     pub path: String,
+    /// Label drawn under the thumbnail, escaped for ImageMagick
     pub label: String,
+    /// Why the image failed the filters (only set with --explain-filters)
+    pub rejection: Option<String>,
 }
 
 /// Process and display images in chunks, with concurrent loading
 /// Processes multiple rows in parallel for better performance
 #[allow(dead_code)]
 pub fn process_images_concurrent(images: Vec<ImageEntry>, config: &ImageConfig) -> Result<()> {
+    let mut writer = RowWriter::for_stdout(&generate_sixel_output);
+    write_rows(&images, config, &mut writer)?;
+    writer.finish();
+    Ok(())
+}
+
+/// Render the rows of `images` and write them in order through `writer`,
+/// which retries failed rows with reduced settings. Returns false once
+/// stdout's reader has gone away.
+fn write_rows(images: &[ImageEntry], config: &ImageConfig, writer: &mut RowWriter) -> Result<bool> {
+    let results = render_rows(images, config);
+
+    // Output in order
+    let _span = timing::phase("output");
+    let mut stdout = io::stdout();
+    let rows = images.chunks(config.num_tiles_per_row as usize);
+    for (row, result) in rows.zip(results) {
+        if !writer.write_row(&mut stdout, row, config, result)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Render the grid rows (`num_tiles_per_row` images each) in parallel,
+/// returning the encoded output of every row in display order
+pub fn render_rows(images: &[ImageEntry], config: &ImageConfig) -> Vec<Result<Vec<u8>>> {
     use rayon::prelude::*;
 
-    // Process images in chunks (rows)
+    let shared;
+    let config = match global_palette(images, config) {
+        Some(palette) => {
+            shared = ImageConfig { shared_palette: Some(Arc::new(palette)), ..config.clone() };
+            &shared
+        }
+        None => config,
+    };
+
     let chunk_size = config.num_tiles_per_row as usize;
     let chunks: Vec<_> = images.chunks(chunk_size).collect();
 
-    // Process rows in parallel, but maintain order for display
-    let results: Vec<Result<Vec<u8>>> = chunks
+    let _span = timing::phase("render");
+    chunks
         .par_iter() // Parallel iteration over rows
-        .map(|chunk| generate_sixel_output_cached(chunk, config))
-        .collect();
+        .enumerate()
+        .map(|(row, chunk)| {
+            let _span = timing::item("render", &format!("row {}", row + 1));
+            generate_sixel_output_cached(chunk, config)
+        })
+        .collect()
+}
 
-    // Output in order
-    for result in results {
-        let data = result?;
-        io::stdout().write_all(&data)?;
-        io::stdout().flush()?;
+/// Thumbnails sampled for a global palette
+const GLOBAL_PALETTE_SAMPLES: usize = 32;
+
+/// Pixels taken from each sampled thumbnail
+const GLOBAL_PALETTE_PIXELS: usize = 4096;
+
+/// The palette every row of `images` shares in `PaletteMode::Global`:
+/// median cut over a sample of their thumbnails, plus the background and
+/// label colors found in every row. None when rows get their own palette.
+pub(crate) fn global_palette(images: &[ImageEntry], config: &ImageConfig) -> Option<Palette> {
+    if config.palette != PaletteMode::Global
+        || config.protocol != GraphicsProtocol::Sixel
+        || config.encoder != Encoder::Native
+    {
+        return None;
     }
+    let _span = timing::phase("palette");
 
-    Ok(())
+    let parse = |color: &str, default: [u8; 3]| {
+        crate::montage::parse_color(color).map_or(default, |c| [c[0], c[1], c[2]])
+    };
+    let background = parse(&config.background, [0, 0, 0]);
+    let foreground = parse(&config.foreground, [255, 255, 255]);
+
+    let step = images.len().div_ceil(GLOBAL_PALETTE_SAMPLES).max(1);
+    let sampled: Vec<&ImageEntry> = images.iter().step_by(step).collect();
+    let mut pixels: Vec<[u8; 3]> = sampled
+        .par_iter()
+        .filter_map(|entry| crate::montage::load_tile(entry, config).ok())
+        .flat_map_iter(|tile| {
+            let pixels = crate::sixel_encoder::flatten(&tile.image, background);
+            let step = (pixels.len() / GLOBAL_PALETTE_PIXELS).max(1);
+            pixels.into_iter().step_by(step).collect::<Vec<_>>()
+        })
+        .collect();
+    if pixels.is_empty() {
+        return None;
+    }
+
+    // Padding and labels take a share of every row
+    let total = pixels.len();
+    pixels.extend(std::iter::repeat_n(background, total / 4));
+    pixels.extend(std::iter::repeat_n(foreground, total / 100 + 1));
+    Some(Palette::median_cut(&pixels, config.num_colors as usize))
 }
 
 /// Process and display images grouped by criteria
 /// Shows group headers and processes each group separately
-#[allow(dead_code)]
 pub fn process_images_grouped(
     groups: Vec<ImageGroup>,
     all_images: Vec<ImageEntry>,
@@ -287,6 +555,7 @@ pub fn process_images_grouped(
 ) -> Result<()> {
     use std::io::Write;
 
+    let mut writer = RowWriter::for_stdout(&generate_sixel_output);
     for (group_idx, group) in groups.iter().enumerate() {
         // Print group header
         eprintln!("\n╔═══════════════════════════════════════════════════════════════");
@@ -299,12 +568,13 @@ pub fn process_images_grouped(
 
         // Show group metadata
         if !group.metadata.common_features.is_empty() {
-            let features: Vec<String> = group
+            let mut features: Vec<String> = group
                 .metadata
                 .common_features
                 .iter()
                 .map(|(k, v)| format!("{}: {}", k, v))
                 .collect();
+            features.sort();
             eprintln!("║ {}", features.join(", "));
         }
 
@@ -314,7 +584,10 @@ pub fn process_images_grouped(
         // Find ImageEntry objects for images in this group
         let group_images: Vec<ImageEntry> = all_images
             .iter()
-            .filter(|img| group.images.contains(&img.path))
+            .filter(|img| {
+                let path = img.path.trim_end_matches("[0]");
+                group.images.iter().any(|p| p == path)
+            })
             .cloned()
             .collect();
 
@@ -324,13 +597,16 @@ pub fn process_images_grouped(
         }
 
         // Process images in this group
-        process_images_concurrent(group_images, config)?;
+        if !write_rows(&group_images, config, &mut writer)? {
+            break;
+        }
 
         // Add separator between groups
         if group_idx < groups.len() - 1 {
             eprintln!("\n"); // Extra newline between groups
         }
     }
+    writer.finish();
 
     Ok(())
 }
@@ -343,20 +619,22 @@ fn generate_sixel_output_cached(images: &[ImageEntry], config: &ImageConfig) ->
         let cache_key = generate_cache_key(images, config);
         let cache_path = cache_dir.join(&cache_key);
 
-        // Check if cache is valid
+        // Check if cache is valid; a damaged entry reads as a miss
         if is_cache_valid(&cache_path, images) {
-            // Try to read from cache
-            match fs::read(&cache_path) {
-                Ok(data) => return Ok(data),
-                Err(_) => {}
+            if let Some(data) = crate::render_cache::read_entry(&cache_path) {
+                usage_stats::record_cache(true);
+                return Ok(data);
             }
         }
+        usage_stats::record_cache(false);
 
         // Cache miss or invalid, generate new output
+        let started = std::time::Instant::now();
         let sixel_output = generate_sixel_output(images, config)?;
+        usage_stats::record_render(started.elapsed());
 
         // Write to cache for next time
-        let _ = write_to_cache(&cache_path, &sixel_output);
+        let _ = crate::render_cache::write_entry(&cache_path, &sixel_output);
 
         return Ok(sixel_output);
     }
@@ -367,20 +645,33 @@ fn generate_sixel_output_cached(images: &[ImageEntry], config: &ImageConfig) ->
 
 /// Generate cache key based on images and config
 #[allow(dead_code)]
-fn generate_cache_key(images: &[ImageEntry], config: &ImageConfig) -> String {
+pub(crate) fn generate_cache_key(images: &[ImageEntry], config: &ImageConfig) -> String {
     let mut hasher = DefaultHasher::new();
 
     // Hash configuration parameters
     config.tile_width.hash(&mut hasher);
     config.tile_height.hash(&mut hasher);
+    config.tile_xspace.hash(&mut hasher);
+    config.tile_yspace.hash(&mut hasher);
     config.num_colors.hash(&mut hasher);
     config.background.hash(&mut hasher);
     config.foreground.hash(&mut hasher);
     config.shadow.hash(&mut hasher);
-
-    // Hash image paths and modification times
+    config.encoder.hash(&mut hasher);
+    config.protocol.hash(&mut hasher);
+    config.shared_palette.as_ref().map(|p| &p.colors).hash(&mut hasher);
+    config.boost_shadows.map(f32::to_bits).hash(&mut hasher);
+    config.border_width.hash(&mut hasher);
+    config.border_color.hash(&mut hasher);
+    config.rounded.hash(&mut hasher);
+    config.watermark.as_ref().map(|w| w.cache_key()).hash(&mut hasher);
+    config.gif_frame.hash(&mut hasher);
+
+    // Hash image paths, labels and modification times
     for img in images {
         img.path.hash(&mut hasher);
+        img.label.hash(&mut hasher);
+        img.rejection.hash(&mut hasher);
         // Include file modification time in hash
         if let Ok(metadata) = fs::metadata(&img.path) {
             if let Ok(modified) = metadata.modified() {
@@ -396,14 +687,29 @@ fn generate_cache_key(images: &[ImageEntry], config: &ImageConfig) -> String {
     format!("{:x}", hasher.finish())
 }
 
+/// Gamma exponent for a shadow boost strength: 1.0 at 0, 0.5 at full strength
+pub fn shadow_gamma(strength: f32) -> f32 {
+    1.0 / (1.0 + strength.clamp(0.0, 1.0))
+}
+
+/// Brighten dark tones with a gamma curve; highlights barely change and
+/// alpha is kept. Used for display only, never for saved files.
+pub fn boost_shadows(img: &mut image::RgbaImage, strength: f32) {
+    let gamma = shadow_gamma(strength);
+    let mut lut = [0u8; 256];
+    for (v, out) in lut.iter_mut().enumerate() {
+        *out = ((v as f32 / 255.0).powf(gamma) * 255.0).round() as u8;
+    }
+    for pixel in img.pixels_mut() {
+        for channel in &mut pixel.0[..3] {
+            *channel = lut[*channel as usize];
+        }
+    }
+}
+
 /// Get cache directory path
-#[allow(dead_code)]
-fn get_cache_dir() -> Result<std::path::PathBuf> {
-    let cache_dir = if let Ok(home) = std::env::var("HOME") {
-        std::path::PathBuf::from(home).join(".cache").join("lsix")
-    } else {
-        std::path::PathBuf::from("/tmp/lsix")
-    };
+pub fn get_cache_dir() -> Result<std::path::PathBuf> {
+    let cache_dir = crate::app_dirs::cache_dir();
 
     // Create cache directory if it doesn't exist
     if !cache_dir.exists() {
@@ -442,16 +748,52 @@ fn is_cache_valid(cache_path: &std::path::Path, images: &[ImageEntry]) -> bool {
     true
 }
 
-/// Write to cache
-#[allow(dead_code)]
-fn write_to_cache(cache_path: &std::path::Path, data: &[u8]) -> Result<()> {
-    fs::write(cache_path, data)?;
-    Ok(())
+/// Generate terminal graphics output for a chunk of images
+fn generate_sixel_output(images: &[ImageEntry], config: &ImageConfig) -> Result<Vec<u8>> {
+    // ImageMagick can only produce SIXEL; other protocols always go native
+    match (config.protocol, config.encoder) {
+        (GraphicsProtocol::Sixel, Encoder::ImageMagick) => {
+            generate_sixel_output_imagemagick(images, config)
+        }
+        _ => generate_sixel_output_native(images, config),
+    }
 }
 
-/// Generate SIXEL output for a chunk of images
-#[allow(dead_code)]
-fn generate_sixel_output(images: &[ImageEntry], config: &ImageConfig) -> Result<Vec<u8>> {
+/// Generate output in-process: decode, compose the row, encode it with the
+/// backend for the configured graphics protocol
+fn generate_sixel_output_native(images: &[ImageEntry], config: &ImageConfig) -> Result<Vec<u8>> {
+    use crate::montage::{compose_row, load_tile};
+
+    let tiles: Vec<_> = images
+        .iter()
+        .filter_map(|img| {
+            if img.path.is_empty() {
+                diagnostics::warn(WarningKind::Other, "Skipping image with empty path");
+                return None;
+            }
+
+            match load_tile(img, config) {
+                Ok(tile) => Some(tile),
+                Err(e) => {
+                    diagnostics::warn(WarningKind::Decode, &format!("{:#}", e));
+                    None
+                }
+            }
+        })
+        .collect();
+
+    // If no valid images, return empty output
+    if tiles.is_empty() {
+        diagnostics::warn(WarningKind::Other, "No valid images in this chunk");
+        return Ok(Vec::new());
+    }
+
+    let row = compose_row(&tiles, config);
+    crate::term_image::backend_for(config.protocol).encode_row(&row, config)
+}
+
+/// Generate SIXEL output for a chunk of images with ImageMagick
+fn generate_sixel_output_imagemagick(images: &[ImageEntry], config: &ImageConfig) -> Result<Vec<u8>> {
     // Build montage arguments for this row
     let mut montage_args = config.get_montage_options();
 
@@ -461,25 +803,60 @@ fn generate_sixel_output(images: &[ImageEntry], config: &ImageConfig) -> Result<
     // Add labels and file paths for each image
     for img in images {
         if img.path.is_empty() {
-            eprintln!("Warning: Skipping image with empty path");
+            diagnostics::warn(WarningKind::Other, "Skipping image with empty path");
             continue;
         }
 
         // Check if file exists
         if !std::path::Path::new(&img.path).exists() {
-            eprintln!("Warning: File not found: {}", img.path);
+            diagnostics::warn(WarningKind::NotFound, &format!("File not found: {}", img.path));
             continue;
         }
 
+        // montage shows an animation's first frame; other frames are
+        // handed to it as a still
+        let path = match frame_copy(&img.path, config.gif_frame) {
+            Ok(Some(frame)) => frame.to_string_lossy().to_string(),
+            Ok(None) => img.path.clone(),
+            Err(e) => {
+                let message = format!("Failed to pick a frame of {}: {}", img.path, e);
+                diagnostics::warn(WarningKind::Decode, &message);
+                img.path.clone()
+            }
+        };
+
+        // Images rejected by the filters are shown greyed out
+        let source = if config.rounded || config.watermark.is_some() {
+            match framed_copy(&path, img.rejection.is_some(), config) {
+                Ok(framed) => framed.to_string_lossy().to_string(),
+                Err(e) => {
+                    let message = format!("Failed to prepare {}: {}", img.path, e);
+                    diagnostics::warn(WarningKind::Other, &message);
+                    path
+                }
+            }
+        } else if img.rejection.is_some() {
+            match dimmed_copy(&path) {
+                Ok(dimmed) => dimmed.to_string_lossy().to_string(),
+                Err(e) => {
+                    let message = format!("Failed to dim {}: {}", img.path, e);
+                    diagnostics::warn(WarningKind::Other, &message);
+                    path
+                }
+            }
+        } else {
+            path
+        };
+
         valid_images.push(img);
         montage_args.push("-label".to_string());
         montage_args.push(img.label.clone());
-        montage_args.push(img.path.clone());
+        montage_args.push(source);
     }
 
     // If no valid images, return empty output
     if valid_images.is_empty() {
-        eprintln!("Warning: No valid images in this chunk");
+        diagnostics::warn(WarningKind::Other, "No valid images in this chunk");
         return Ok(Vec::new());
     }
 
@@ -492,7 +869,7 @@ fn generate_sixel_output(images: &[ImageEntry], config: &ImageConfig) -> Result<
     }
 
     // Start montage process
-    let mut montage_cmd = config.get_montage_command();
+    let mut montage_cmd = config.get_montage_command()?;
     let mut montage_child = montage_cmd
         .args(&montage_args)
         .stdout(Stdio::piped())
@@ -501,7 +878,7 @@ fn generate_sixel_output(images: &[ImageEntry], config: &ImageConfig) -> Result<
         .context("Failed to execute montage command")?;
 
     // Start convert process, taking stdin from montage stdout
-    let mut convert_cmd = config.get_convert_command();
+    let mut convert_cmd = config.get_convert_command()?;
     let mut convert_child = convert_cmd
         .arg("-colors")
         .arg(format!("{}", config.num_colors))
@@ -549,36 +926,131 @@ fn generate_sixel_output(images: &[ImageEntry], config: &ImageConfig) -> Result<
     Ok(sixel_data)
 }
 
+/// Write the frame of an animation that `choice` picks, or a video's
+/// representative frame, to the cache and return its path; None for still
+/// images and the first frame
+fn frame_copy(path: &str, choice: GifFrame) -> Result<Option<std::path::PathBuf>> {
+    if video_thumbs::is_video(path) {
+        return video_thumbs::frame_path(path).map(Some);
+    }
+    if choice == GifFrame::First {
+        return Ok(None);
+    }
+    let source = path.trim_end_matches("[0]");
+
+    let frames_dir = get_cache_dir()?.join("frames");
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+    choice.hash(&mut hasher);
+    if let Ok(modified) = fs::metadata(source).and_then(|m| m.modified()) {
+        modified.hash(&mut hasher);
+    }
+    let frame_path = frames_dir.join(format!("{:x}.png", hasher.finish()));
+
+    if !frame_path.exists() {
+        let Some(frame) = gif_frame::pick_frame(source, choice)? else {
+            return Ok(None);
+        };
+        fs::create_dir_all(&frames_dir)?;
+        frame.save(&frame_path).context("Failed to write frame")?;
+    }
+
+    Ok(Some(frame_path))
+}
+
+/// Write a greyed-out copy of an image to the cache and return its path
+fn dimmed_copy(path: &str) -> Result<std::path::PathBuf> {
+    // Strip ImageMagick's frame selector, the image crate reads the first frame anyway
+    let source = path.trim_end_matches("[0]");
+
+    let dimmed_dir = get_cache_dir()?.join("dimmed");
+    fs::create_dir_all(&dimmed_dir)?;
+
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+    if let Ok(modified) = fs::metadata(source).and_then(|m| m.modified()) {
+        modified.hash(&mut hasher);
+    }
+    let dimmed_path = dimmed_dir.join(format!("{:x}.png", hasher.finish()));
+
+    if !dimmed_path.exists() {
+        let img = image::open(source).context("Failed to decode image")?;
+        img.grayscale()
+            .brighten(-60)
+            .save(&dimmed_path)
+            .context("Failed to write dimmed copy")?;
+    }
+
+    Ok(dimmed_path)
+}
+
+/// Write a thumbnail with the watermark and, when rounded, its rounded
+/// corners and border to the cache and return its path; montage can't do
+/// either by itself
+fn framed_copy(path: &str, dimmed: bool, config: &ImageConfig) -> Result<std::path::PathBuf> {
+    let source = path.trim_end_matches("[0]");
+
+    let framed_dir = get_cache_dir()?.join("framed");
+    fs::create_dir_all(&framed_dir)?;
+
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+    if let Ok(modified) = fs::metadata(source).and_then(|m| m.modified()) {
+        modified.hash(&mut hasher);
+    }
+    (config.tile_width, config.tile_height, dimmed).hash(&mut hasher);
+    (config.border_width, &config.border_color, config.rounded).hash(&mut hasher);
+    config.watermark.as_ref().map(|w| w.cache_key()).hash(&mut hasher);
+    let framed_path = framed_dir.join(format!("{:x}.png", hasher.finish()));
+
+    if !framed_path.exists() {
+        let img = open_image(source).context("Failed to decode image")?;
+        let img = if img.width() > config.tile_width || img.height() > config.tile_height {
+            img.thumbnail(config.tile_width, config.tile_height)
+        } else {
+            img
+        };
+        let img = if dimmed { img.grayscale().brighten(-60) } else { img };
+        let mut thumbnail = img.to_rgba8();
+        if let Some(watermark) = &config.watermark {
+            watermark.apply(&mut thumbnail);
+        }
+        // Without rounding, montage draws the border itself
+        let thumbnail = if config.rounded {
+            crate::montage::frame_thumbnail(&thumbnail, config)
+        } else {
+            thumbnail
+        };
+        thumbnail.save(&framed_path).context("Failed to write thumbnail copy")?;
+    }
+
+    Ok(framed_path)
+}
+
 /// Pre-load and validate image files concurrently
-/// Returns only valid image entries that match the filter criteria
-#[allow(dead_code)]
+/// Returns only valid image entries that match the filter criteria.
+/// With `explain` set, rejected images are kept and labelled with the failing criteria.
 pub fn validate_images_concurrent(
     paths: &[String],
     explicit: bool,
     mode: FilenameMode,
     filter_config: &FilterConfig,
+    explain: bool,
 ) -> Vec<ImageEntry> {
     use crate::filename::{process_image_path, process_label_with_mode};
 
     // Check if any filter is active
-    let has_filters = filter_config.min_width.is_some()
-        || filter_config.max_width.is_some()
-        || filter_config.min_height.is_some()
-        || filter_config.max_height.is_some()
-        || filter_config.min_file_size.is_some()
-        || filter_config.max_file_size.is_some()
-        || filter_config.min_brightness.is_some()
-        || filter_config.max_brightness.is_some()
-        || filter_config.orientation.is_some();
+    let has_filters = filter_config.is_active();
 
     paths
         .par_iter() // Parallel iteration
         .filter_map(|path| {
             // Check if file exists and is readable
+            let _span = timing::item("analyze", path);
             let path_obj = std::path::Path::new(path);
 
             if !path_obj.exists() {
-                eprintln!("Warning: File not found: {}", path);
+                diagnostics::warn(WarningKind::NotFound, &format!("File not found: {}", path));
                 return None;
             }
 
@@ -586,38 +1058,66 @@ pub fn validate_images_concurrent(
             let processed_path = process_image_path(path, explicit);
 
             // If filters are active, analyze and check
+            let mut rejection = None;
             if has_filters {
                 match analyze_image(&processed_path) {
                     Ok(features) => {
-                        if !filter_config.matches(&features) {
-                            // Image doesn't match filter, skip it
-                            return None;
+                        let reasons = filter_config.rejection_reasons(&features);
+                        if !reasons.is_empty() {
+                            if !explain {
+                                // Image doesn't match filter, skip it
+                                return None;
+                            }
+                            rejection = Some(reasons.join(", "));
                         }
                     }
                     Err(e) => {
-                        eprintln!("Warning: Failed to analyze {}: {}", path, e);
+                        diagnostics::warn(
+                            WarningKind::Analyze,
+                            &format!("Failed to analyze {}: {}", path, e),
+                        );
                         // Include image anyway if analysis fails
                     }
                 }
             }
 
+            // Rejected images carry the failing criteria in their label
+            let mut label = process_label_with_mode(path, mode);
+            if let Some(ref reason) = rejection {
+                label.push('\n');
+                label.push_str(&reason.replace('%', "%%"));
+            }
+
             // Create image entry
             Some(ImageEntry {
                 path: processed_path,
-                label: process_label_with_mode(path, mode),
+                label,
+                rejection,
             })
         })
         .collect()
 }
 
+/// Extensions of the files taken from directories and the command line,
+/// compared in lowercase; HEIF, AVIF and JPEG XL only in builds that
+/// decode them
+pub const IMAGE_EXTENSIONS: &[&str] = &[
+    "jpg", "jpeg", "png", "gif", "webp", "tiff", "tif", "pnm", "ppm", "pgm", "pbm", "pam", "xbm",
+    "xpm", "bmp", "ico", "svg", "eps",
+    #[cfg(feature = "heif")]
+    "heic",
+    #[cfg(feature = "heif")]
+    "heif",
+    #[cfg(feature = "avif")]
+    "avif",
+    #[cfg(feature = "jxl")]
+    "jxl",
+];
+
 /// Find and process directories recursively
 /// Filters to only include image files
 pub fn expand_directories(paths: &[String]) -> Vec<String> {
-    // Supported image extensions
-    let image_extensions = [
-        "jpg", "jpeg", "png", "gif", "webp", "tiff", "tif", "pnm", "ppm", "pgm", "pbm", "pam",
-        "xbm", "xpm", "bmp", "ico", "svg", "eps",
-    ];
+    let image_extensions = IMAGE_EXTENSIONS;
 
     let mut result = Vec::new();
 
@@ -626,7 +1126,7 @@ pub fn expand_directories(paths: &[String]) -> Vec<String> {
 
         if path_obj.is_dir() {
             // Process directory (non-recursive unless -r flag is used)
-            eprintln!("Scanning directory: {}", path);
+            diagnostics::info(&format!("Scanning directory: {}", path));
 
             if let Ok(entries) = std::fs::read_dir(path) {
                 for entry in entries.filter_map(|e| e.ok()) {
@@ -634,7 +1134,10 @@ pub fn expand_directories(paths: &[String]) -> Vec<String> {
                     // Only add if it's a file with image extension
                     if entry_path.is_file() {
                         if let Some(ext) = entry_path.extension() {
-                            if image_extensions.contains(&ext.to_string_lossy().as_ref()) {
+                            let ext = ext.to_string_lossy().to_lowercase();
+                            if image_extensions.contains(&ext.as_str())
+                                || video_thumbs::listed_in_directories(&ext)
+                            {
                                 if let Some(path_str) = entry_path.to_str() {
                                     result.push(path_str.to_string());
                                 }
@@ -644,9 +1147,13 @@ pub fn expand_directories(paths: &[String]) -> Vec<String> {
                 }
             }
         } else {
-            // Regular file - check if it has image extension
+            // Regular file - check if it has image extension; videos named
+            // here are listed even without ffmpeg, to say what is missing
             if let Some(ext) = path_obj.extension() {
-                if image_extensions.contains(&ext.to_string_lossy().as_ref()) {
+                let ext = ext.to_string_lossy().to_lowercase();
+                if image_extensions.contains(&ext.as_str())
+                    || video_thumbs::is_video_extension(&ext)
+                {
                     result.push(path.clone());
                 }
             }
@@ -659,10 +1166,7 @@ pub fn expand_directories(paths: &[String]) -> Vec<String> {
 
 /// Recursively find all images in directory tree
 pub fn expand_directories_recursive(paths: &[String]) -> Vec<String> {
-    let image_extensions = [
-        "jpg", "jpeg", "png", "gif", "webp", "tiff", "tif", "pnm", "ppm", "pgm", "pbm", "pam",
-        "xbm", "xpm", "bmp", "ico", "svg", "eps",
-    ];
+    let image_extensions = IMAGE_EXTENSIONS;
 
     let mut result = Vec::new();
 
@@ -671,7 +1175,7 @@ pub fn expand_directories_recursive(paths: &[String]) -> Vec<String> {
 
         if path_obj.is_dir() {
             // Recursively process directory and all subdirectories
-            eprintln!("Recursively scanning: {}", path);
+            diagnostics::info(&format!("Recursively scanning: {}", path));
 
             if let Ok(entries) = std::fs::read_dir(path) {
                 for entry in entries.filter_map(|e| e.ok()) {
@@ -685,7 +1189,10 @@ pub fn expand_directories_recursive(paths: &[String]) -> Vec<String> {
                     } else if entry_path.is_file() {
                         // Check if it's an image file
                         if let Some(ext) = entry_path.extension() {
-                            if image_extensions.contains(&ext.to_string_lossy().as_ref()) {
+                            let ext = ext.to_string_lossy().to_lowercase();
+                            if image_extensions.contains(&ext.as_str())
+                                || video_thumbs::listed_in_directories(&ext)
+                            {
                                 if let Some(path_str) = entry_path.to_str() {
                                     result.push(path_str.to_string());
                                 }
@@ -697,7 +1204,10 @@ pub fn expand_directories_recursive(paths: &[String]) -> Vec<String> {
         } else {
             // Regular file - check if it has image extension
             if let Some(ext) = path_obj.extension() {
-                if image_extensions.contains(&ext.to_string_lossy().as_ref()) {
+                let ext = ext.to_string_lossy().to_lowercase();
+                if image_extensions.contains(&ext.as_str())
+                    || video_thumbs::is_video_extension(&ext)
+                {
                     result.push(path.clone());
                 }
             }
@@ -721,6 +1231,41 @@ mod tests {
         assert_eq!(config.shadow, true); // 256 > 16
     }
 
+    #[test]
+    fn test_boost_shadows() {
+        let mut img = image::RgbaImage::from_pixel(1, 1, image::Rgba([20, 128, 255, 77]));
+        boost_shadows(&mut img, 0.0);
+        assert_eq!(img.get_pixel(0, 0).0, [20, 128, 255, 77]);
+
+        boost_shadows(&mut img, 1.0);
+        let [r, g, b, a] = img.get_pixel(0, 0).0;
+        assert!(r > 60 && g > 128);
+        assert_eq!((b, a), (255, 77));
+    }
+
+    #[test]
+    fn test_parse_border() {
+        assert_eq!(parse_border("2 #444444").unwrap(), (2, "#444444".to_string()));
+        assert!(parse_border("2").is_err());
+        assert!(parse_border("two white").is_err());
+        assert!(parse_border("2 not-a-color").is_err());
+    }
+
+    #[test]
+    fn test_montage_border_options() {
+        let mut config = ImageConfig::from_terminal_width(1024, 256, "white", "black");
+        config.set_border(1024, 3, "red");
+        let opts = config.get_montage_options();
+        assert!(opts.windows(2).any(|w| w == ["-border", "3"]));
+        assert!(opts.windows(2).any(|w| w == ["-bordercolor", "red"]));
+
+        // Rounded thumbnails come with their border drawn in
+        config.rounded = true;
+        let opts = config.get_montage_options();
+        assert!(!opts.iter().any(|o| o == "-border"));
+        assert!(opts.iter().any(|o| o.starts_with("366x366>")));
+    }
+
     #[test]
     fn test_image_config_low_color() {
         let config = ImageConfig::from_terminal_width(800, 16, "white", "black");
@@ -0,0 +1,56 @@
+// Cooperative Ctrl-C for long batch phases (similarity hashing). While a
+// `Guard` is alive the first Ctrl-C only sets a flag that workers poll, so
+// the phase can stop early and keep what it has; a second Ctrl-C, or one
+// outside any guarded phase, exits right away like the default handler.
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Once;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+/// Number of live guards
+static ARMED: AtomicUsize = AtomicUsize::new(0);
+static WATCHER: Once = Once::new();
+
+/// Exit status of a process killed by SIGINT
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// Marks a phase that can be interrupted; see `interrupted`
+pub struct Guard(());
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        if ARMED.fetch_sub(1, Ordering::SeqCst) == 1 {
+            INTERRUPTED.store(false, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Start an interruptible phase. Ctrl-C is watched from a background thread
+/// that is started on first use and stays for the rest of the process.
+pub fn guard() -> Guard {
+    ARMED.fetch_add(1, Ordering::SeqCst);
+    WATCHER.call_once(|| {
+        std::thread::spawn(watch);
+    });
+    Guard(())
+}
+
+/// Whether Ctrl-C was pressed during the current phase
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
+
+fn watch() {
+    let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+        Ok(runtime) => runtime,
+        Err(_) => return,
+    };
+    runtime.block_on(async {
+        while tokio::signal::ctrl_c().await.is_ok() {
+            let armed = ARMED.load(Ordering::SeqCst) > 0;
+            if !armed || INTERRUPTED.swap(true, Ordering::SeqCst) {
+                std::process::exit(INTERRUPTED_EXIT_CODE);
+            }
+        }
+    });
+}
@@ -0,0 +1,197 @@
+// Machine-readable listing (--output json): instead of graphics, one JSON
+// document describing the selected images as the pipeline saw them, for
+// scripts to consume.
+//
+//   {"version": 1,
+//    "images": [{"path": ..., "width": ..., "tags": [...], "groups": [...],
+//                "exif": {"Model": ..., ...}, ...}],
+//    "groups": [{"id": ..., "name": ..., "count": ...}]}
+//
+// Values lsix couldn't determine are null rather than guessed.
+
+use crate::ai_tagging::load_cached_tags;
+use crate::filter::{analyze_image, orientation_name, orientation_of};
+use crate::grouping::ImageGroup;
+use crate::image_proc::ImageEntry;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Format version of the document, bumped on incompatible changes
+pub const JSON_VERSION: u32 = 1;
+
+/// EXIF values longer than this (thumbnails, maker notes) are left out
+const MAX_EXIF_VALUE: usize = 256;
+
+/// The whole document
+#[derive(Debug, Clone, Serialize)]
+pub struct Listing {
+    pub version: u32,
+    pub images: Vec<ImageRecord>,
+    pub groups: Vec<GroupRecord>,
+}
+
+/// One image
+#[derive(Debug, Clone, Serialize)]
+pub struct ImageRecord {
+    pub path: String,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// Bytes
+    pub file_size: Option<u64>,
+    /// Mean brightness, 0.0 (dark) to 1.0 (bright)
+    pub brightness: Option<f32>,
+    pub dominant_color: Option<String>,
+    /// landscape, portrait or square
+    pub orientation: Option<&'static str>,
+    /// Cached tags, most relevant first
+    pub tags: Vec<String>,
+    pub caption: Option<String>,
+    /// Ids of the groups the image is in
+    pub groups: Vec<String>,
+    /// Why the filters rejected the image (only with --explain-filters)
+    pub rejection: Option<String>,
+    /// Primary EXIF fields by tag name, as displayed (e.g. "f/2.8")
+    pub exif: BTreeMap<String, String>,
+}
+
+/// One group, in display order
+#[derive(Debug, Clone, Serialize)]
+pub struct GroupRecord {
+    pub id: String,
+    pub name: String,
+    pub count: usize,
+}
+
+/// Describe `entries`, with tags from the tag cache at `tag_cache` and
+/// assignments to `groups`
+pub fn build_listing(
+    entries: &[ImageEntry],
+    groups: &[ImageGroup],
+    tag_cache: Option<&Path>,
+) -> Listing {
+    let images = entries
+        .par_iter()
+        .map(|entry| {
+            let mut record = image_record(entry, tag_cache);
+            record.groups = groups
+                .iter()
+                .filter(|g| g.images.contains(&record.path))
+                .map(|g| g.id.clone())
+                .collect();
+            record
+        })
+        .collect();
+    let groups = groups
+        .iter()
+        .map(|g| GroupRecord { id: g.id.clone(), name: g.name.clone(), count: g.images.len() })
+        .collect();
+    Listing { version: JSON_VERSION, images, groups }
+}
+
+/// Describe one image. Analysis goes through ImageMagick like the filters
+/// do; without it only the size and orientation are read, from the header.
+fn image_record(entry: &ImageEntry, tag_cache: Option<&Path>) -> ImageRecord {
+    let path = entry.path.trim_end_matches("[0]").to_string();
+    let cached = tag_cache.and_then(|dir| load_cached_tags(dir, &path).ok());
+    let mut record = ImageRecord {
+        width: None,
+        height: None,
+        file_size: std::fs::metadata(&path).ok().map(|m| m.len()),
+        brightness: None,
+        dominant_color: None,
+        orientation: None,
+        tags: cached.as_ref().map(|t| t.tags.clone()).unwrap_or_default(),
+        caption: cached.and_then(|t| t.caption),
+        groups: Vec::new(),
+        rejection: entry.rejection.clone(),
+        exif: read_exif(&path),
+        path,
+    };
+
+    match analyze_image(&record.path) {
+        Ok(features) => {
+            record.width = Some(features.width);
+            record.height = Some(features.height);
+            record.brightness = Some(features.brightness);
+            record.dominant_color = Some(features.dominant_color).filter(|c| !c.is_empty());
+            record.orientation = Some(orientation_name(features.orientation));
+        }
+        Err(_) => {
+            if let Ok((width, height)) = image::image_dimensions(&record.path) {
+                record.width = Some(width);
+                record.height = Some(height);
+                record.orientation = Some(orientation_name(orientation_of(width, height)));
+            }
+        }
+    }
+    record
+}
+
+/// Primary-image EXIF fields of `path` by tag name; empty if it has none
+pub fn read_exif(path: &str) -> BTreeMap<String, String> {
+    let Ok(file) = std::fs::File::open(path) else {
+        return BTreeMap::new();
+    };
+    let Ok(exif) = exif::Reader::new().read_from_container(&mut std::io::BufReader::new(file))
+    else {
+        return BTreeMap::new();
+    };
+
+    let mut fields = BTreeMap::new();
+    for field in exif.fields().filter(|f| f.ifd_num == exif::In::PRIMARY) {
+        let value = field.display_value().with_unit(&exif).to_string();
+        let value = value.trim_matches('"').trim().to_string();
+        if !value.is_empty() && value.len() <= MAX_EXIF_VALUE {
+            fields.entry(field.tag.to_string()).or_insert(value);
+        }
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+    use crate::grouping::GroupMetadata;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_build_listing() {
+        let dir = TestDir::new("json");
+        let path = dir.join("tall.png").to_string_lossy().to_string();
+        image::RgbImage::new(20, 60).save(&path).unwrap();
+
+        let entry = ImageEntry {
+            path: format!("{}[0]", path),
+            label: String::new(),
+            rejection: Some("too small".to_string()),
+        };
+        let group = ImageGroup {
+            id: "g1".to_string(),
+            name: "Tall ones".to_string(),
+            images: vec![path.clone()],
+            representative: path.clone(),
+            metadata: GroupMetadata {
+                group_type: "size".to_string(),
+                count: 1,
+                common_features: HashMap::new(),
+            },
+        };
+
+        let listing = build_listing(&[entry], &[group], None);
+        let image = &listing.images[0];
+        assert_eq!(image.path, path);
+        assert_eq!((image.width, image.height), (Some(20), Some(60)));
+        assert_eq!(image.orientation, Some("portrait"));
+        assert_eq!(image.groups, ["g1"]);
+        assert!(image.exif.is_empty());
+
+        let json: serde_json::Value = serde_json::to_value(&listing).unwrap();
+        assert_eq!(json["version"], 1);
+        assert_eq!(json["groups"][0]["count"], 1);
+        assert_eq!(json["images"][0]["rejection"], "too small");
+        assert!(json["images"][0]["caption"].is_null());
+    }
+}
@@ -0,0 +1,350 @@
+// TUI key bindings: which keys trigger each remappable action, set from the
+// `[keybindings]` config table. An action can have several keys, given as a
+// list:
+//
+//   [keybindings]
+//   quit = ["q", "Q"]
+//   view = "space"
+//
+// Keys listed for an action replace its defaults. Navigation (arrows, hjkl
+// in fullscreen, PgUp/PgDn) and Esc are fixed. The help popup (?) lists the
+// bindings in effect.
+
+use crate::config::KeyList;
+use anyhow::Result;
+use crossterm::event::KeyCode;
+use std::collections::HashMap;
+
+/// The keys bound to one action
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keys(Vec<KeyCode>);
+
+impl Keys {
+    pub fn new(codes: Vec<KeyCode>) -> Keys {
+        Keys(codes)
+    }
+
+    fn one(code: KeyCode) -> Keys {
+        Keys(vec![code])
+    }
+
+    /// Where `code` is among the keys, from 0
+    pub fn position(&self, code: KeyCode) -> Option<usize> {
+        self.0.iter().position(|&key| key == code)
+    }
+
+    /// Key names as the config spells them, e.g. "q/x"
+    pub fn label(&self) -> String {
+        self.0.iter().map(|&code| key_name(code)).collect::<Vec<_>>().join("/")
+    }
+}
+
+/// `code == keys`: whether `code` is one of `keys`
+impl PartialEq<Keys> for KeyCode {
+    fn eq(&self, keys: &Keys) -> bool {
+        keys.0.contains(self)
+    }
+}
+
+/// Parse a key name: a single character, or enter, esc, space, tab, backspace
+pub fn parse_key(s: &str) -> Result<KeyCode> {
+    let mut chars = s.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Ok(KeyCode::Char(c));
+    }
+
+    match s.to_lowercase().as_str() {
+        "enter" | "return" => Ok(KeyCode::Enter),
+        "esc" | "escape" => Ok(KeyCode::Esc),
+        "space" => Ok(KeyCode::Char(' ')),
+        "tab" => Ok(KeyCode::Tab),
+        "backspace" => Ok(KeyCode::Backspace),
+        _ => anyhow::bail!(
+            "Invalid key: {}. Use a single character or: enter, esc, space, tab, backspace",
+            s
+        ),
+    }
+}
+
+/// Name of `code` that `parse_key` reads back
+pub fn key_name(code: KeyCode) -> String {
+    match code {
+        KeyCode::Char(' ') => "space".to_string(),
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::Enter => "enter".to_string(),
+        KeyCode::Esc => "esc".to_string(),
+        KeyCode::Tab => "tab".to_string(),
+        KeyCode::Backspace => "backspace".to_string(),
+        code => format!("{:?}", code).to_lowercase(),
+    }
+}
+
+/// Remappable TUI keys, set from the `[keybindings]` config table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyBindings {
+    pub quit: Keys,
+    pub view: Keys,
+    pub compare: Keys,
+    pub wipe: Keys,
+    pub delete: Keys,
+    pub rename: Keys,
+    pub move_to: Keys,
+    pub copy_to: Keys,
+    pub select: Keys,
+    pub visual: Keys,
+    pub select_all: Keys,
+    pub tag: Keys,
+    pub export: Keys,
+    pub boxes: Keys,
+    pub fit: Keys,
+    pub zoom_in: Keys,
+    pub zoom_out: Keys,
+    pub reset_view: Keys,
+    pub rotate: Keys,
+    pub rotate_back: Keys,
+    pub mirror: Keys,
+    pub save_view: Keys,
+    pub info: Keys,
+    pub rename_group: Keys,
+    pub command: Keys,
+    pub search: Keys,
+    pub next_match: Keys,
+    pub prev_match: Keys,
+    pub folders: Keys,
+    pub move_to_group: Keys,
+    pub merge_group: Keys,
+    pub split_group: Keys,
+    pub output: Keys,
+    pub retry: Keys,
+    pub open: Keys,
+    pub yank: Keys,
+    pub yank_image: Keys,
+    pub map: Keys,
+    pub rate: Keys,
+    pub pick: Keys,
+    pub reject: Keys,
+    pub labels: Keys,
+    pub badges: Keys,
+    pub help: Keys,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            quit: Keys::one(KeyCode::Char('q')),
+            view: Keys::one(KeyCode::Enter),
+            compare: Keys::one(KeyCode::Char('c')),
+            wipe: Keys::one(KeyCode::Char('w')),
+            delete: Keys::one(KeyCode::Char('d')),
+            rename: Keys::one(KeyCode::Char('r')),
+            move_to: Keys::one(KeyCode::Char('m')),
+            copy_to: Keys::one(KeyCode::Char('C')),
+            select: Keys::one(KeyCode::Char(' ')),
+            visual: Keys::one(KeyCode::Char('v')),
+            select_all: Keys::one(KeyCode::Char('a')),
+            tag: Keys::one(KeyCode::Char('t')),
+            export: Keys::one(KeyCode::Char('E')),
+            boxes: Keys::one(KeyCode::Char('b')),
+            fit: Keys::one(KeyCode::Char('f')),
+            zoom_in: Keys::one(KeyCode::Char('+')),
+            zoom_out: Keys::one(KeyCode::Char('-')),
+            reset_view: Keys::one(KeyCode::Char('0')),
+            // Fullscreen only, where rename, move and retry don't apply
+            rotate: Keys::one(KeyCode::Char('r')),
+            rotate_back: Keys::one(KeyCode::Char('R')),
+            mirror: Keys::one(KeyCode::Char('m')),
+            save_view: Keys::one(KeyCode::Char('s')),
+            info: Keys::one(KeyCode::Char('i')),
+            rename_group: Keys::one(KeyCode::Char('n')),
+            command: Keys::one(KeyCode::Char(':')),
+            search: Keys::one(KeyCode::Char('/')),
+            // Only while a search is active, where n doesn't name groups
+            next_match: Keys::one(KeyCode::Char('n')),
+            prev_match: Keys::one(KeyCode::Char('N')),
+            folders: Keys::one(KeyCode::Tab),
+            move_to_group: Keys::one(KeyCode::Char('M')),
+            merge_group: Keys::one(KeyCode::Char('J')),
+            split_group: Keys::one(KeyCode::Char('S')),
+            output: Keys::one(KeyCode::Char('O')),
+            retry: Keys::one(KeyCode::Char('R')),
+            open: Keys::one(KeyCode::Char('o')),
+            yank: Keys::one(KeyCode::Char('y')),
+            yank_image: Keys::one(KeyCode::Char('Y')),
+            // Only while the info panel is open, where M doesn't move images
+            map: Keys::one(KeyCode::Char('M')),
+            // The Nth key gives N stars
+            rate: Keys::new(('1'..='5').map(KeyCode::Char).collect()),
+            pick: Keys::one(KeyCode::Char('p')),
+            reject: Keys::one(KeyCode::Char('x')),
+            labels: Keys::one(KeyCode::Char('L')),
+            badges: Keys::one(KeyCode::Char('B')),
+            help: Keys::one(KeyCode::Char('?')),
+        }
+    }
+}
+
+impl KeyBindings {
+    /// Build bindings from `action = "key"` (or `action = ["key", ...]`)
+    /// pairs, keeping defaults for actions that aren't listed
+    pub fn from_config(bindings: &HashMap<String, KeyList>) -> Result<Self> {
+        let mut keys = KeyBindings::default();
+        for (action, list) in bindings {
+            let codes = list.keys().iter().map(|key| parse_key(key)).collect::<Result<Vec<_>>>()?;
+            if codes.is_empty() {
+                anyhow::bail!("No keys given for {}", action);
+            }
+            let Some(slot) = keys.slot(action) else {
+                let names: Vec<&str> = KeyBindings::default()
+                    .bindings()
+                    .iter()
+                    .map(|&(name, _, _)| name)
+                    .collect();
+                anyhow::bail!(
+                    "Unknown keybinding action: {}. Use: {}",
+                    action,
+                    names.join(", ")
+                );
+            };
+            *slot = Keys::new(codes);
+        }
+        Ok(keys)
+    }
+
+    fn slot(&mut self, action: &str) -> Option<&mut Keys> {
+        Some(match action {
+            "quit" => &mut self.quit,
+            "view" => &mut self.view,
+            "compare" => &mut self.compare,
+            "wipe" => &mut self.wipe,
+            "delete" => &mut self.delete,
+            "rename" => &mut self.rename,
+            "move" => &mut self.move_to,
+            "copy" => &mut self.copy_to,
+            "select" => &mut self.select,
+            "visual" => &mut self.visual,
+            "select_all" => &mut self.select_all,
+            "tag" => &mut self.tag,
+            "export" => &mut self.export,
+            "boxes" => &mut self.boxes,
+            "fit" => &mut self.fit,
+            "zoom_in" => &mut self.zoom_in,
+            "zoom_out" => &mut self.zoom_out,
+            "reset_view" => &mut self.reset_view,
+            "rotate" => &mut self.rotate,
+            "rotate_back" => &mut self.rotate_back,
+            "mirror" => &mut self.mirror,
+            "save_view" => &mut self.save_view,
+            "info" => &mut self.info,
+            "rename_group" => &mut self.rename_group,
+            "command" => &mut self.command,
+            "search" => &mut self.search,
+            "next_match" => &mut self.next_match,
+            "prev_match" => &mut self.prev_match,
+            "folders" => &mut self.folders,
+            "move_to_group" => &mut self.move_to_group,
+            "merge_group" => &mut self.merge_group,
+            "split_group" => &mut self.split_group,
+            "output" => &mut self.output,
+            "retry" => &mut self.retry,
+            "open" => &mut self.open,
+            "yank" => &mut self.yank,
+            "yank_image" => &mut self.yank_image,
+            "map" => &mut self.map,
+            "rate" => &mut self.rate,
+            "pick" => &mut self.pick,
+            "reject" => &mut self.reject,
+            "labels" => &mut self.labels,
+            "badges" => &mut self.badges,
+            "help" => &mut self.help,
+            _ => return None,
+        })
+    }
+
+    /// Every action as (config name, what it does, keys), in help order
+    pub fn bindings(&self) -> Vec<(&'static str, &'static str, &Keys)> {
+        vec![
+            ("quit", "Quit, or leave fullscreen and comparison", &self.quit),
+            ("view", "View fullscreen", &self.view),
+            ("select", "Select or unselect", &self.select),
+            ("visual", "Select a range", &self.visual),
+            ("select_all", "Select all or none", &self.select_all),
+            ("delete", "Trash or delete", &self.delete),
+            ("rename", "Rename; several images from a pattern", &self.rename),
+            ("move", "Move to a directory", &self.move_to),
+            ("copy", "Copy to a directory", &self.copy_to),
+            ("tag", "Edit tags", &self.tag),
+            ("export", "Write the paths to a list file", &self.export),
+            ("compare", "Mark for comparison", &self.compare),
+            ("wipe", "Wipe between the two marked images", &self.wipe),
+            ("info", "Info panel", &self.info),
+            ("map", "Open the location in a map (info panel)", &self.map),
+            ("command", "Filter, sort or group", &self.command),
+            ("search", "Search", &self.search),
+            ("next_match", "Next match", &self.next_match),
+            ("prev_match", "Previous match", &self.prev_match),
+            ("folders", "Folder sidebar", &self.folders),
+            ("rename_group", "Name the current group", &self.rename_group),
+            ("move_to_group", "Move to the next group", &self.move_to_group),
+            ("merge_group", "Merge into the next group", &self.merge_group),
+            ("split_group", "Split the group from here", &self.split_group),
+            ("open", "Open in an external program", &self.open),
+            ("yank", "Copy the path to the clipboard", &self.yank),
+            ("yank_image", "Copy the image to the clipboard", &self.yank_image),
+            ("output", "Output of custom commands", &self.output),
+            ("retry", "Run again (output pane)", &self.retry),
+            ("fit", "Cycle the fit mode (fullscreen)", &self.fit),
+            ("zoom_in", "Zoom in; in the grid, fewer and bigger thumbnails", &self.zoom_in),
+            ("zoom_out", "Zoom out; in the grid, more and smaller thumbnails", &self.zoom_out),
+            ("reset_view", "Reset zoom and scroll (fullscreen)", &self.reset_view),
+            ("rotate", "Rotate clockwise (fullscreen)", &self.rotate),
+            ("rotate_back", "Rotate counterclockwise (fullscreen)", &self.rotate_back),
+            ("mirror", "Mirror (fullscreen)", &self.mirror),
+            ("save_view", "Save the rotation to the file (fullscreen)", &self.save_view),
+            ("boxes", "Detected objects (fullscreen)", &self.boxes),
+            ("rate", "Rate 1 to 5 stars; the same rating again clears it", &self.rate),
+            ("pick", "Flag as a pick, or unflag", &self.pick),
+            ("reject", "Flag as a reject, or unflag", &self.reject),
+            ("labels", "Captions in the grid: names, numbered, off", &self.labels),
+            ("badges", "Rating, NSFW and tag badges in the grid", &self.badges),
+            ("help", "This help", &self.help),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_bindings_from_config() {
+        let config: HashMap<String, KeyList> = toml::from_str(
+            r#"
+            quit = ["q", "x"]
+            view = "space"
+            "#,
+        )
+        .unwrap();
+        let keys = KeyBindings::from_config(&config).unwrap();
+        assert!(KeyCode::Char('x') == keys.quit && KeyCode::Char('q') == keys.quit);
+        assert!(KeyCode::Enter != keys.view);
+        assert_eq!(keys.view.label(), "space");
+        assert_eq!(keys.folders.label(), "tab");
+        assert_eq!(keys.rate.label(), "1/2/3/4/5");
+        assert_eq!(keys.rate.position(KeyCode::Char('3')), Some(2));
+
+        let unknown: HashMap<String, KeyList> = toml::from_str("fly = \"F\"").unwrap();
+        let error = KeyBindings::from_config(&unknown).unwrap_err().to_string();
+        assert!(error.contains("quit, view"));
+        let empty: HashMap<String, KeyList> = toml::from_str("quit = []").unwrap();
+        assert!(KeyBindings::from_config(&empty).is_err());
+
+        // Every action in the help can be bound, and every key name reads back
+        let mut keys = KeyBindings::default();
+        let names: Vec<&str> = KeyBindings::default().bindings().iter().map(|b| b.0).collect();
+        assert_eq!(names.len(), 44);
+        assert!(names.iter().all(|name| keys.slot(name).is_some()));
+        for code in [KeyCode::Char(' '), KeyCode::Char('?'), KeyCode::Backspace] {
+            assert_eq!(parse_key(&key_name(code)).unwrap(), code);
+        }
+    }
+}
@@ -0,0 +1,75 @@
+// Kitty graphics protocol encoder: transmits an image as PNG data in
+// base64 chunks (APC `\x1b_G ... \x1b\`), displayed at the cursor.
+
+use anyhow::{Context, Result};
+use base64::Engine;
+use image::RgbaImage;
+use std::io::{Cursor, Write};
+
+/// Maximum base64 payload per escape sequence allowed by the protocol
+const MAX_CHUNK_SIZE: usize = 4096;
+
+/// Encode an image as a Kitty "transmit and display" command.
+/// The cursor ends up on the line below the image.
+pub fn encode(img: &RgbaImage) -> Result<Vec<u8>> {
+    let mut png = Vec::new();
+    img.write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+        .context("Failed to encode PNG for Kitty graphics")?;
+
+    let payload = base64::engine::general_purpose::STANDARD.encode(&png);
+    let mut out = Vec::with_capacity(payload.len() + payload.len() / MAX_CHUNK_SIZE * 16 + 32);
+    write_chunks(&mut out, payload.as_bytes());
+    out.push(b'\n');
+    Ok(out)
+}
+
+/// Split a base64 payload into protocol chunks. Only the first chunk
+/// carries the control keys; `m=1` marks that more chunks follow.
+fn write_chunks(out: &mut Vec<u8>, payload: &[u8]) {
+    let mut chunks = payload.chunks(MAX_CHUNK_SIZE).peekable();
+    let mut first = true;
+
+    // An empty payload still needs one (empty) command
+    if chunks.peek().is_none() {
+        out.extend_from_slice(b"\x1b_Ga=T,f=100,q=2;\x1b\\");
+        return;
+    }
+
+    while let Some(chunk) = chunks.next() {
+        let more = if chunks.peek().is_some() { 1 } else { 0 };
+        if first {
+            // a=T: transmit and display, f=100: PNG, q=2: suppress responses
+            write!(out, "\x1b_Ga=T,f=100,q=2,m={};", more).ok();
+            first = false;
+        } else {
+            write!(out, "\x1b_Gm={};", more).ok();
+        }
+        out.extend_from_slice(chunk);
+        out.extend_from_slice(b"\x1b\\");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_chunks_splits_payload() {
+        let payload = vec![b'A'; MAX_CHUNK_SIZE + 10];
+        let mut out = Vec::new();
+        write_chunks(&mut out, &payload);
+
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.starts_with("\x1b_Ga=T,f=100,q=2,m=1;"));
+        assert!(text.contains("\x1b\\\x1b_Gm=0;AAAAAAAAAA\x1b\\"));
+        assert_eq!(text.matches("\x1b_G").count(), 2);
+    }
+
+    #[test]
+    fn test_encode_single_chunk() {
+        let img = RgbaImage::from_pixel(4, 4, image::Rgba([255, 0, 0, 255]));
+        let text = String::from_utf8(encode(&img).unwrap()).unwrap();
+        assert!(text.starts_with("\x1b_Ga=T,f=100,q=2,m=0;"));
+        assert!(text.ends_with("\x1b\\\n"));
+    }
+}
@@ -0,0 +1,304 @@
+// Export cached tags as ML dataset annotations (COCO JSON, YOLO txt, CSV).
+// Image-level tags become annotations covering the whole image; region
+// annotations carry their own bounding box.
+
+use crate::ai_tagging::{load_cached_tags, AITaggingConfig};
+use crate::test_mode;
+use anyhow::{Context, Result};
+use serde_json::json;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+/// Dataset annotation format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LabelFormat {
+    /// One COCO JSON file
+    Coco,
+    /// A directory with one `<stem>.txt` per image plus `classes.txt`
+    Yolo,
+    /// One CSV row per image
+    Csv,
+}
+
+/// Parse label format from string
+pub fn parse_label_format(s: &str) -> Result<LabelFormat> {
+    match s.to_lowercase().as_str() {
+        "coco" => Ok(LabelFormat::Coco),
+        "yolo" => Ok(LabelFormat::Yolo),
+        "csv" => Ok(LabelFormat::Csv),
+        _ => anyhow::bail!("Invalid label format: {}. Use: coco, yolo, csv", s),
+    }
+}
+
+impl LabelFormat {
+    /// Output used when no path is given
+    pub fn default_output(self) -> &'static str {
+        match self {
+            LabelFormat::Coco => "labels.json",
+            LabelFormat::Yolo => "labels",
+            LabelFormat::Csv => "labels.csv",
+        }
+    }
+}
+
+/// Axis-aligned box in pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+/// One label on an image
+#[derive(Debug, Clone, PartialEq)]
+pub struct Annotation {
+    pub label: String,
+    /// Region the label applies to; `None` means the whole image
+    pub bbox: Option<BoundingBox>,
+}
+
+/// An image with its size and annotations
+#[derive(Debug, Clone, PartialEq)]
+pub struct LabeledImage {
+    pub path: String,
+    pub width: u32,
+    pub height: u32,
+    pub annotations: Vec<Annotation>,
+}
+
+impl LabeledImage {
+    fn bbox(&self, annotation: &Annotation) -> BoundingBox {
+        annotation.bbox.unwrap_or(BoundingBox {
+            x: 0.0,
+            y: 0.0,
+            width: self.width as f32,
+            height: self.height as f32,
+        })
+    }
+}
+
+/// Load sizes and cached tags for `paths`. Images without cached tags are
+/// kept (with no annotations) so the export lists the whole selection.
+pub fn collect_labels(paths: &[String], config: &AITaggingConfig) -> Vec<LabeledImage> {
+    paths
+        .iter()
+        .filter_map(|path| {
+            let (width, height) = match image::image_dimensions(path) {
+                Ok(dims) => dims,
+                Err(e) => {
+                    eprintln!("Warning: skipping {}: {}", path, e);
+                    return None;
+                }
+            };
+
+            let mut annotations = Vec::new();
+            if let Some(tags) = config
+                .cache_dir
+                .as_deref()
+                .and_then(|dir| load_cached_tags(dir, path).ok())
+            {
+                annotations.extend(tags.tags.into_iter().map(|label| Annotation { label, bbox: None }));
+                // Detected objects carry fractional boxes; convert to pixels
+                annotations.extend(tags.objects.into_iter().flatten().map(|obj| Annotation {
+                    label: obj.label,
+                    bbox: Some(BoundingBox {
+                        x: obj.bbox[0] * width as f32,
+                        y: obj.bbox[1] * height as f32,
+                        width: obj.bbox[2] * width as f32,
+                        height: obj.bbox[3] * height as f32,
+                    }),
+                }));
+            }
+
+            Some(LabeledImage {
+                path: path.clone(),
+                width,
+                height,
+                annotations,
+            })
+        })
+        .collect()
+}
+
+/// Sorted label names; a label's index is its class id
+pub fn categories(images: &[LabeledImage]) -> Vec<String> {
+    images
+        .iter()
+        .flat_map(|img| img.annotations.iter().map(|a| a.label.clone()))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+/// Write `images` to `out` in `format`
+pub fn export_labels(images: &[LabeledImage], format: LabelFormat, out: &Path) -> Result<()> {
+    match format {
+        LabelFormat::Coco => {
+            let json = serde_json::to_string_pretty(&to_coco(images))?;
+            fs::write(out, json).with_context(|| format!("Failed to write {}", out.display()))
+        }
+        LabelFormat::Csv => {
+            fs::write(out, to_csv(images)).with_context(|| format!("Failed to write {}", out.display()))
+        }
+        LabelFormat::Yolo => write_yolo(images, out),
+    }
+}
+
+/// COCO detection format. Category and annotation ids start at 1.
+pub fn to_coco(images: &[LabeledImage]) -> serde_json::Value {
+    let categories = categories(images);
+    let category_id = |label: &str| categories.iter().position(|c| c == label).unwrap_or(0) + 1;
+
+    let mut annotations = Vec::new();
+    for (image_idx, img) in images.iter().enumerate() {
+        for annotation in &img.annotations {
+            let bbox = img.bbox(annotation);
+            annotations.push(json!({
+                "id": annotations.len() + 1,
+                "image_id": image_idx + 1,
+                "category_id": category_id(&annotation.label),
+                "bbox": [bbox.x, bbox.y, bbox.width, bbox.height],
+                "area": bbox.width * bbox.height,
+                "iscrowd": 0,
+            }));
+        }
+    }
+
+    json!({
+        "info": {
+            "description": "Exported by lsix",
+            "date_created": test_mode::local(test_mode::now()).to_rfc3339(),
+        },
+        "images": images.iter().enumerate().map(|(idx, img)| json!({
+            "id": idx + 1,
+            "file_name": img.path,
+            "width": img.width,
+            "height": img.height,
+        })).collect::<Vec<_>>(),
+        "categories": categories.iter().enumerate().map(|(idx, name)| json!({
+            "id": idx + 1,
+            "name": name,
+        })).collect::<Vec<_>>(),
+        "annotations": annotations,
+    })
+}
+
+/// `path,width,height,labels` with labels separated by `;`
+pub fn to_csv(images: &[LabeledImage]) -> String {
+    let mut csv = String::from("path,width,height,labels\n");
+    for img in images {
+        let labels: Vec<&str> = img.annotations.iter().map(|a| a.label.as_str()).collect();
+        csv.push_str(&format!(
+            "{},{},{},{}\n",
+            csv_field(&img.path),
+            img.width,
+            img.height,
+            csv_field(&labels.join(";"))
+        ));
+    }
+    csv
+}
+
+/// YOLO line for one annotation: class id and normalized center/size
+pub fn yolo_line(img: &LabeledImage, annotation: &Annotation, class_id: usize) -> String {
+    let bbox = img.bbox(annotation);
+    let (w, h) = (img.width.max(1) as f32, img.height.max(1) as f32);
+    format!(
+        "{} {:.6} {:.6} {:.6} {:.6}",
+        class_id,
+        (bbox.x + bbox.width / 2.0) / w,
+        (bbox.y + bbox.height / 2.0) / h,
+        bbox.width / w,
+        bbox.height / h
+    )
+}
+
+fn write_yolo(images: &[LabeledImage], dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+
+    let categories = categories(images);
+    let mut classes = categories.join("\n");
+    classes.push('\n');
+    fs::write(dir.join("classes.txt"), classes)?;
+
+    for img in images {
+        let stem = Path::new(&img.path)
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_else(|| "image".to_string());
+
+        let mut lines = String::new();
+        for annotation in &img.annotations {
+            let class_id = categories.iter().position(|c| *c == annotation.label).unwrap_or(0);
+            lines.push_str(&yolo_line(img, annotation, class_id));
+            lines.push('\n');
+        }
+        fs::write(dir.join(format!("{}.txt", stem)), lines)?;
+    }
+    Ok(())
+}
+
+/// Quote a CSV field if needed
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Vec<LabeledImage> {
+        vec![
+            LabeledImage {
+                path: "beach, day.jpg".to_string(),
+                width: 200,
+                height: 100,
+                annotations: vec![
+                    Annotation { label: "sea".to_string(), bbox: None },
+                    Annotation { label: "beach".to_string(), bbox: None },
+                ],
+            },
+            LabeledImage {
+                path: "dog.png".to_string(),
+                width: 100,
+                height: 100,
+                annotations: vec![Annotation {
+                    label: "dog".to_string(),
+                    bbox: Some(BoundingBox { x: 10.0, y: 20.0, width: 50.0, height: 40.0 }),
+                }],
+            },
+        ]
+    }
+
+    #[test]
+    fn test_coco_ids() {
+        let coco = to_coco(&sample());
+        assert_eq!(coco["categories"][0]["name"], "beach");
+        assert_eq!(coco["annotations"].as_array().unwrap().len(), 3);
+        // "dog" is the second category and belongs to the second image
+        assert_eq!(coco["annotations"][2]["category_id"], 2);
+        assert_eq!(coco["annotations"][2]["image_id"], 2);
+        assert_eq!(coco["annotations"][0]["bbox"], json!([0.0, 0.0, 200.0, 100.0]));
+    }
+
+    #[test]
+    fn test_yolo_line() {
+        let images = sample();
+        let line = yolo_line(&images[1], &images[1].annotations[0], 1);
+        assert_eq!(line, "1 0.350000 0.400000 0.500000 0.400000");
+        let whole = yolo_line(&images[0], &images[0].annotations[0], 2);
+        assert_eq!(whole, "2 0.500000 0.500000 1.000000 1.000000");
+    }
+
+    #[test]
+    fn test_csv_quoting() {
+        let csv = to_csv(&sample());
+        assert!(csv.contains("\"beach, day.jpg\",200,100,sea;beach\n"));
+    }
+}
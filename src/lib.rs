@@ -0,0 +1,173 @@
+//! lsix: like ls, but for images.
+//!
+//! The `lsix` binary is a thin CLI over this library. The modules can also
+//! be embedded directly, e.g. to group or AI-tag images from another tool
+//! without shelling out:
+//!
+//! ```no_run
+//! use lsix::ai_tagging::{tag_images_parallel, AITaggingConfig};
+//! use lsix::grouping::{group_images, GroupBy};
+//! use lsix::image_proc::expand_directories;
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let images = expand_directories(&["photos".to_string()]);
+//!
+//! for group in group_images(&images, GroupBy::Size, 0.85)? {
+//!     println!("{}: {} images", group.name, group.images.len());
+//! }
+//!
+//! let tags = tag_images_parallel(&images, &AITaggingConfig::default(), false)?;
+//! for (path, tags) in &tags {
+//!     println!("{}: {}", path, tags.tags.join(", "));
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+/// AI image tagging through vision-capable chat completion APIs, with a
+/// per-image result cache
+pub mod ai_tagging;
+/// Config, cache, state and log locations (XDG base directories), and
+/// moving files over from the old ones
+pub mod app_dirs;
+/// Content-addressed cache for downloaded images and AI payloads
+pub mod blob_cache;
+/// `lsix bugreport`: environment and rendering diagnostics
+pub mod bugreport;
+/// Renaming a selection of images from a pattern, all or nothing
+pub mod bulk_rename;
+/// Files new or changed since their folder was last listed (--changes)
+pub mod changes;
+/// Copying image paths and images to the clipboard from the TUI
+pub mod clipboard;
+/// Near-neighbor clustering (multi-index hashing, LSH, union-find) for grouping
+pub mod clustering;
+/// Before/after wipe compositing
+pub mod compare;
+/// Config files: ~/.config/lsix/config.toml and project `.lsix.toml` files
+pub mod config;
+/// Star ratings and pick/reject flags for culling
+pub mod culling;
+/// Warning aggregation and --quiet / --verbose output control
+pub mod diagnostics;
+/// Folder sidebar for walking directories in the TUI browser
+pub mod dir_tree;
+/// Image embeddings and semantic search for --search
+pub mod embeddings;
+/// Exact and near-duplicate detection for --find-duplicates
+pub mod duplicates;
+/// Trash, delete, rename, move and copy for the TUI browser
+pub mod file_ops;
+/// File discovery and label formatting
+pub mod filename;
+/// Invocation history for `--again` / `--history`
+pub mod history;
+/// Image feature analysis and size/brightness/orientation filters
+pub mod filter;
+/// Static HTML gallery export with tag filters and a lightbox
+pub mod gallery;
+/// Map of where a geotagged photo was taken, for the TUI info panel
+pub mod geo_map;
+/// Frame an animated GIF or WebP thumbnail shows: first, middle, best or N
+pub mod gif_frame;
+/// Group merges, splits and moves made in the TUI, kept across runs
+pub mod group_edits;
+/// Album-like group names set in the TUI, kept across runs
+pub mod group_names;
+/// Grouping by similarity, color, size, time or tags, and tag filtering
+pub mod grouping;
+/// Persisted perceptual hashes for --group-by similarity
+pub mod hash_index;
+/// HEIC/HEIF decoding through libheif (the `heif` cargo feature)
+pub mod heif;
+/// Memory-bounded LRU cache of decoded images for the TUI
+pub mod image_cache;
+/// File, EXIF and color details of one image for the TUI info panel
+pub mod image_info;
+/// `--interactive` grid with mouse hover details
+pub mod grid_hover;
+/// Grid rendering pipeline: validation, thumbnails, caching and output
+pub mod image_proc;
+/// Cooperative Ctrl-C handling for interruptible batch phases
+pub mod interrupt;
+/// `--output json`: the selected images and their analysis as one JSON document
+pub mod json_output;
+/// TUI key bindings from the `[keybindings]` config table
+pub mod keymap;
+/// Kitty graphics protocol encoder
+pub mod kitty_encoder;
+/// Dataset label export (COCO, YOLO, CSV)
+pub mod labels;
+/// AVIF and JPEG XL decoding (the `avif` and `jxl` cargo features)
+pub mod modern_formats;
+/// Native thumbnail row compositor
+pub mod montage;
+/// Bell or desktop notification when a run completes (--notify)
+pub mod notify;
+/// `lsix oci`: images inside the layers of a container image on disk
+pub mod oci;
+/// Splitting long grids into screen-sized pages (--page, --all)
+pub mod paging;
+/// NUL-separated and shell-quoted path output (--print0, --print-quoted)
+pub mod path_output;
+/// Canonical image paths shared by every cache
+pub mod paths;
+/// The TUI's cursor and scroll position, remembered per directory
+pub mod positions;
+/// `lsix query`: one TSV or CSV row of metadata per image
+pub mod query;
+/// Sharpness/exposure/contrast quality scores and best-shot selection
+pub mod quality;
+/// Rendered rows cached on disk with integrity footers, and `lsix cache verify`
+pub mod render_cache;
+/// Retrying grid rows with reduced settings when rendering or the terminal fails
+pub mod row_fallback;
+/// `lsix scrub`: metadata removal
+pub mod scrub;
+/// Image ordering for --sort, with natural file name order
+pub mod sorting;
+/// Pure-Rust SIXEL encoder
+pub mod sixel_encoder;
+/// Sprite-sheet splitting
+pub mod sprites;
+/// Process substitution, pipes and extensionless temp files as inputs
+pub mod stream_input;
+/// Tags exchanged with XMP sidecars and IPTC/EXIF keywords
+pub mod tag_export;
+/// Graphics backends and ratatui-image helpers
+pub mod term_image;
+/// Terminal capability detection
+pub mod terminal;
+/// Deterministic runs for tests and bug reports (--test-mode)
+pub mod test_mode;
+/// On-disk cache of downscaled TUI thumbnails
+pub mod thumbnail_cache;
+/// Time-lapse assembly from image sequences
+pub mod timelapse;
+/// `--timing`: per-phase span timings and a summary of the slowest items
+pub mod timing;
+/// Optional external tools, probed once, with install hints when missing
+pub mod tools;
+/// Writing fullscreen rotation and mirroring into image files
+pub mod transform;
+/// Shell commands bound to TUI keys, run with their output captured
+pub mod tui_actions;
+/// Interactive TUI browser
+pub mod tui_browser;
+/// `:filter`, `:sort` and `:group` commands typed in the TUI
+pub mod tui_command;
+/// Opt-in local usage statistics for `lsix stats --self`
+pub mod usage_stats;
+/// Representative frames of video files, for their thumbnails
+pub mod video_thumbs;
+/// Per-image fullscreen view state (fit mode, pan, rotation)
+pub mod view_state;
+/// `--watch`: changes to images in the watched directories
+pub mod watch;
+/// Logo and text watermarks stamped onto thumbnails and exported frames
+pub mod watermark;
+
+#[cfg(test)]
+mod pipeline_tests;
+#[cfg(test)]
+mod test_dir;
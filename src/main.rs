@@ -1,42 +1,61 @@
-mod ai_tagging;
-mod filename;
-mod filter;
-mod grouping;
-mod image_proc;
-mod term_image;
-mod terminal;
-mod tui_browser;
-
-use ai_tagging::{clear_ai_cache, tag_images_parallel, AITaggingConfig};
+use lsix::{
+    ai_tagging, app_dirs, blob_cache, bugreport, changes, config, culling, diagnostics,
+    duplicates, embeddings, filename, filter, gallery, gif_frame, grid_hover, group_edits,
+    group_names, grouping, history, image_cache, image_proc, json_output, keymap, labels,
+    notify, oci, paging, path_output, paths, quality, query, render_cache, row_fallback, scrub,
+    sorting, sprites, stream_input, tag_export, term_image, terminal, test_mode, timelapse,
+    timing, tools, tui_actions, tui_browser, usage_stats, video_thumbs, watch, watermark,
+};
+
+use ai_tagging::{
+    add_manual_tags, clear_ai_cache, load_cached_tags, parse_tag_rename, remove_manual_tags,
+    rename_manual_tag, split_tags, tag_images_parallel, AITaggingConfig,
+};
 use anyhow::{Context, Result};
 
+/// Number of invocations shown by --history
+const HISTORY_LIST_LIMIT: usize = 20;
+
+/// How long to wait for the terminal to answer the post-render DA query
+const GRAPHICS_VERIFY_TIMEOUT_MS: u64 = 500;
+
 const BUILD_TIME: &str = include_str!(concat!(env!("OUT_DIR"), "/build_time.txt"));
 
-use clap::Parser;
+use clap::{CommandFactory, Parser, Subcommand};
 use filename::FilenameMode;
 use filter::{parse_file_size, parse_orientation, FilterConfig};
+use grouping::{filter_by_tags_advanced, group_images, parse_group_by, GroupBy};
 use image_proc::{
-    expand_directories, expand_directories_recursive,
+    expand_directories, expand_directories_recursive, parse_encoder, parse_palette_mode,
+    process_images_concurrent, process_images_grouped, validate_images_concurrent, Encoder,
+    ImageConfig, PaletteMode,
 };
-use std::io::{self, Write};
+use std::collections::{HashMap, HashSet};
+use std::io::{self, IsTerminal, Write};
 use std::path::Path as StdPath;
+use std::process::ExitCode;
+use terminal::GraphicsProtocol;
 
 /// lsix: like ls, but for images.
 /// Shows thumbnails of images with titles directly in terminal.
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[command(name = "lsix")]
 #[command(author = "hackerb9")]
 #[command(version = "2.0.0")]
 #[command(about = "Like ls, but for images - displays thumbnails in SIXEL-capable terminals")]
+#[command(args_conflicts_with_subcommands = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Commands>,
+
     /// Image files or directories to display
     #[arg(name = "FILES")]
     files: Vec<String>,
 
-    /// Display mode for filenames
-    #[arg(short, long, default_value = "short")]
+    /// Display mode for filenames [default: short]
+    #[arg(short, long)]
     #[arg(value_parser = clap::builder::PossibleValuesParser::new(["short", "long"]))]
-    mode: String,
+    mode: Option<String>,
 
     // Size filters
     /// Minimum image width in pixels
@@ -77,6 +96,42 @@ struct Args {
     #[arg(long)]
     orientation: Option<String>,
 
+    /// Show images rejected by the filters greyed out, labelled with the failing criteria
+    /// (implies --grid)
+    #[arg(long)]
+    explain_filters: bool,
+
+    // Ordering and quality options
+    /// Order images by: name (natural order), mtime (newest first), size
+    /// (largest first), resolution (largest first), quality (best first),
+    /// exif-date (newest first) or tag:NAME (value of NAME:VALUE tags).
+    /// Chain keys with commas and add asc/desc, e.g.
+    /// "tag:rating desc, exif-date asc, name"
+    #[arg(long, value_name = "KEYS")]
+    sort: Option<String>,
+
+    /// Reverse the --sort order
+    #[arg(long, requires = "sort")]
+    reverse: bool,
+
+    /// Minimum quality score (0 to 100)
+    #[arg(long, value_name = "SCORE")]
+    min_quality: Option<f32>,
+
+    /// Mark the highest-scored image of every group of similar images
+    /// (see --similarity-threshold)
+    #[arg(long)]
+    best_shots: bool,
+
+    /// Badge files that are new (NEW) or whose contents changed (UPDATED)
+    /// since their folder was last listed with --changes
+    #[arg(long)]
+    changes: bool,
+
+    /// List only the files --changes would badge (implies --changes)
+    #[arg(long)]
+    changed_only: bool,
+
     // Grouping options
     /// Group images by: similarity, color, size, time, tags, none
     #[arg(long, default_value = "none")]
@@ -87,6 +142,25 @@ struct Args {
     #[arg(long, default_value = "0.85")]
     similarity_threshold: f32,
 
+    /// Name template for --group-by groups, e.g. "{date} — {count} photos".
+    /// Placeholders: {n}, {count}, {name}, {type}, {date}, {tag}, {size}, {color}
+    #[arg(long, value_name = "TEMPLATE")]
+    group_name: Option<String>,
+
+    /// Report exact duplicates (same content) and near duplicates (same
+    /// picture); with --grid each group is shown as its own grid
+    #[arg(long)]
+    find_duplicates: bool,
+
+    /// Maximum perceptual hash distance, in bits out of 64, for near duplicates
+    #[arg(long, value_name = "BITS", default_value_t = duplicates::DEFAULT_MAX_DISTANCE)]
+    duplicate_distance: u32,
+
+    /// Review the duplicates in the TUI with every copy but the kept one
+    /// selected for trashing
+    #[arg(long, requires = "find_duplicates")]
+    dedup: bool,
+
     // Tag management
     /// List all tags with image counts (does not display images)
     #[arg(long)]
@@ -109,16 +183,83 @@ struct Args {
     #[arg(long)]
     tag_not: Vec<String>,
 
+    /// Ignore AI tags the model gave less than this confidence (0.0-1.0)
+    /// when filtering by tag; `--tag beach:0.8` sets it for one tag
+    #[arg(long, value_name = "CONFIDENCE")]
+    min_tag_confidence: Option<f32>,
+
+    /// Keep images rated at least N stars (1-5), as rated in the TUI or
+    /// tagged rating:N
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u8).range(1..=5))]
+    min_rating: Option<u8>,
+
+    /// Add tags by hand to the listed images (repeatable or comma-separated;
+    /// no API needed)
+    #[arg(long, value_name = "TAG")]
+    add_tag: Vec<String>,
+
+    /// Remove tags from the listed images
+    #[arg(long, value_name = "TAG")]
+    remove_tag: Vec<String>,
+
+    /// Rename a tag on the listed images, e.g. --rename-tag seaside=beach
+    #[arg(long, value_name = "OLD=NEW")]
+    rename_tag: Option<String>,
+
+    /// Add keywords already in the listed images' metadata (XMP sidecars,
+    /// embedded XMP, IPTC and EXIF) to their tags, so --tag can filter them
+    #[arg(long)]
+    import_tags: bool,
+
     // Directory options
     /// Recursive directory search
     #[arg(short, long)]
     recursive: bool,
 
+    /// Keep symlinked files under their own path instead of their target's
+    #[arg(long)]
+    no_resolve_symlinks: bool,
+
+    /// Maximum concurrent downloads for http(s) URLs given as FILES
+    #[arg(long, default_value_t = blob_cache::DEFAULT_FETCH_JOBS)]
+    fetch_jobs: usize,
+
+    /// Follow the listed directories (or the current one): the grid and
+    /// --output json are printed again when images are added, changed or
+    /// removed, and the TUI updates its list in place. Ctrl-C stops.
+    #[arg(long, conflicts_with_all = ["interactive", "dedup"])]
+    watch: bool,
+
     // AI tagging options
     /// Generate AI tags for images (requires LSIX_AI_API_KEY)
     #[arg(long)]
     ai_tag: bool,
 
+    /// With --ai-tag, also ask the model for object bounding boxes
+    #[arg(long, requires = "ai_tag")]
+    detect_objects: bool,
+
+    /// Generate AI tags with a one-sentence caption per image (implies
+    /// --ai-tag); captions show in the TUI and in --list-tags
+    #[arg(long)]
+    ai_caption: bool,
+
+    /// Show only images with a detected object of this name (repeatable,
+    /// OR logic; needs tags from --ai-tag --detect-objects)
+    #[arg(long, value_name = "OBJECT")]
+    has_object: Vec<String>,
+
+    /// Show the images best matching a text description, best first, e.g.
+    /// --search "dog on a beach" (uses a CLIP-style embeddings endpoint,
+    /// LSIX_EMBED_ENDPOINT)
+    #[arg(long, value_name = "QUERY")]
+    search: Option<String>,
+
+    /// Number of images kept by --search
+    #[arg(long, value_name = "N", default_value_t = embeddings::DEFAULT_SEARCH_LIMIT,
+          requires = "search")]
+    search_limit: usize,
+
     /// Clear AI tag cache
     #[arg(long)]
     clear_ai_cache: bool,
@@ -127,6 +268,24 @@ struct Args {
     #[arg(long)]
     force: bool,
 
+    /// With --ai-tag, generate tags again for images a different model
+    /// tagged (star ratings and picks are kept)
+    #[arg(long)]
+    retag_if_model_changed: bool,
+
+    /// With --ai-tag, generate tags again once they are older than AGE,
+    /// e.g. 90d, 8w or 1y (default 30d)
+    #[arg(long, value_name = "AGE", value_parser = ai_tagging::parse_tag_age)]
+    retag_older_than: Option<i64>,
+
+    /// Maximum AI API requests in flight at once
+    #[arg(long, value_name = "N")]
+    ai_concurrency: Option<usize>,
+
+    /// Start at most N AI API requests per minute
+    #[arg(long, value_name = "N")]
+    ai_rate_limit: Option<u32>,
+
     /// Enable debug output for AI API calls
     #[arg(long)]
     debug: bool,
@@ -135,89 +294,891 @@ struct Args {
     #[arg(long)]
     tui: bool,
 
+    /// Memory budget in MiB for full-size images decoded by the TUI browser;
+    /// least recently viewed images are dropped first
+    #[arg(long, value_name = "MB", default_value_t = image_cache::DEFAULT_CACHE_MB)]
+    cache_mb: usize,
+
+    /// Keep the fit mode, scroll position and rotation of fullscreen views
+    /// across sessions, not just while the TUI browser is open
+    #[arg(long)]
+    remember_view: bool,
+
+    /// Program the TUI's o key opens the image under the cursor with, `{}`
+    /// standing for the path (e.g. "gimp {}"; default xdg-open)
+    #[arg(long, value_name = "CMD")]
+    open_with: Option<String>,
+
+    /// Print thumbnails inline as a SIXEL grid (classic lsix output) instead of the TUI browser
+    #[arg(long)]
+    grid: bool,
+
+    /// Keep the grid on screen and show the path, size and dimensions of the
+    /// tile under the mouse pointer (implies --grid; q quits)
+    #[arg(long)]
+    interactive: bool,
+
+    /// Draw the grid on the alternate screen and go back to the previous
+    /// screen on a key press, keeping graphics out of the scrollback
+    /// (implies --grid)
+    #[arg(long, conflicts_with_all = ["interactive", "watch"])]
+    alt_screen: bool,
+
+    /// Print the whole grid instead of stopping after about one screen
+    #[arg(long)]
+    all: bool,
+
+    /// Print page N of the grid, a screen per page (implies --grid)
+    #[arg(long, value_name = "N", conflicts_with_all = ["all", "interactive"])]
+    page: Option<usize>,
+
+    /// SIXEL encoder for grid output: native (built-in) or imagemagick
+    /// (default: $LSIX_ENCODER or native)
+    #[arg(long)]
+    #[arg(value_parser = clap::builder::PossibleValuesParser::new(["native", "imagemagick"]))]
+    encoder: Option<String>,
+
+    /// SIXEL palette of the native encoder: per-row quantizes each row on
+    /// its own, global shares one palette (per group when grouping) built
+    /// from a sample of the thumbnails, for consistent colors across rows
+    #[arg(long)]
+    #[arg(value_parser = clap::builder::PossibleValuesParser::new(["global", "per-row"]))]
+    palette: Option<String>,
+
+    /// Frame animated GIF and WebP thumbnails show: first, middle, best (the
+    /// one with the most detail) or a frame number N, counted from 0
+    #[arg(long, value_name = "FRAME")]
+    gif_frame: Option<String>,
+
+    /// Brighten shadows in the thumbnails so dark photos stay visible on dark
+    /// terminals (display only; files are never modified). Optional strength
+    /// from 0.0 to 1.0, e.g. --boost-shadows=0.8
+    #[arg(long, value_name = "STRENGTH", num_args = 0..=1, require_equals = true,
+          default_missing_value = "0.5")]
+    boost_shadows: Option<f32>,
+
+    /// Draw a border of PX pixels in COLOR around each thumbnail,
+    /// e.g. --border 2 '#444444'
+    #[arg(long, num_args = 2, value_names = ["PX", "COLOR"])]
+    border: Option<Vec<String>>,
+
+    /// Padding in pixels on each side of a tile (0 for a dense grid)
+    #[arg(long, value_name = "PX")]
+    spacing: Option<u32>,
+
+    /// Round the corners of thumbnails
+    #[arg(long)]
+    rounded: bool,
+
+    #[command(flatten)]
+    watermark: WatermarkArgs,
+
+    /// Render with Unicode half blocks when the output can't carry inline
+    /// graphics (pipes, tmux without SIXEL, screen)
+    #[arg(long)]
+    blocks_fallback: bool,
+
+    /// Split sprite sheets into a CxR grid (e.g. 8x4) and show each sprite
+    /// as its own tile; "auto" detects the grid from empty gutters
+    #[arg(long, value_name = "CxR|auto")]
+    grid_split: Option<String>,
+
+    /// Write the frames split by --grid-split into this directory
+    #[arg(long, value_name = "DIR", requires = "grid_split")]
+    export_sprites: Option<String>,
+
+    /// Export the cached tags of the listed images as dataset annotations
+    /// (does not display images)
+    #[arg(long, value_name = "FORMAT")]
+    #[arg(value_parser = clap::builder::PossibleValuesParser::new(["coco", "yolo", "csv"]))]
+    export_labels: Option<String>,
+
+    /// Output for --export-labels: a file for coco/csv, a directory for yolo
+    /// (default: labels.json, labels/ or labels.csv)
+    #[arg(long, value_name = "PATH", requires = "export_labels")]
+    labels_out: Option<String>,
+
+    /// Write the listed images, after filtering, as a static HTML gallery
+    /// into DIR: thumbnails, a lightbox, tag filters and one section per
+    /// --group-by group (does not display images)
+    #[arg(long, value_name = "DIR")]
+    export_html: Option<String>,
+
+    /// What to print: graphics (the default) or json, a document describing
+    /// the selected images (size, brightness, orientation, tags, groups and
+    /// EXIF) for scripts
+    #[arg(long, value_name = "FORMAT")]
+    #[arg(value_parser = clap::builder::PossibleValuesParser::new(["graphics", "json"]))]
+    output: Option<String>,
+
+    /// Print only the number of images that pass the filters and tag
+    /// filters, without showing them
+    #[arg(long, conflicts_with_all = ["quiet_exit", "explain_filters", "watch"])]
+    count: bool,
+
+    /// Show nothing and exit with status 0 if any image passes the filters
+    /// and tag filters, 1 if none does, for use in shell conditions
+    #[arg(long, conflicts_with_all = ["explain_filters", "watch"])]
+    quiet_exit: bool,
+
+    /// Print the paths of the images that pass the filters, each followed
+    /// by a NUL byte for `xargs -0` (with --find-duplicates, the copies
+    /// that could be removed)
+    #[arg(long, conflicts_with_all = ["print_quoted", "watch"])]
+    print0: bool,
+
+    /// Like --print0, but one path per line, quoted for the shell
+    #[arg(long, conflicts_with = "watch")]
+    print_quoted: bool,
+
+    /// Write the cached tags of the listed images into standard metadata:
+    /// xmp (sidecar file), iptc (embedded in JPEG) or exif (XPKeywords,
+    /// JPEG/PNG), for Lightroom, digiKam and other photo tools
+    #[arg(long, value_name = "FORMAT")]
+    #[arg(value_parser = clap::builder::PossibleValuesParser::new(["xmp", "iptc", "exif"]))]
+    export_tags: Option<String>,
+
+    /// Re-run the previous invocation made in this directory; any other
+    /// arguments given are appended to it
+    #[arg(long, conflicts_with = "history")]
+    again: bool,
+
+    /// List recent invocations (most recent last)
+    #[arg(long)]
+    history: bool,
+
     /// Enable detailed logging to file (logs rendering and input events)
     #[arg(long)]
     log: bool,
+
+    /// Only print errors: no warnings and no progress notes
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Don't print warnings about unreadable or missing files
+    #[arg(long)]
+    no_warnings: bool,
+
+    /// Print every warning instead of a count after the first few
+    #[arg(short, long)]
+    verbose: bool,
+
+    /// Time the scan, analyze, group, render and output phases and print a
+    /// summary with the slowest images and rows at the end
+    #[arg(long)]
+    timing: bool,
+
+    /// Deterministic run for tests and bug reports: frozen clock, a stub
+    /// 80x24 terminal and throwaway caches
+    #[arg(long, hide = true)]
+    test_mode: bool,
+
+    /// When the run completes or fails, ring the terminal bell or send a
+    /// desktop notification with a short summary: bell, desktop
+    #[arg(long, value_name = "METHOD")]
+    notify: Option<String>,
+}
+
+/// Watermark flags shared by grid output and `lsix timelapse`
+#[derive(clap::Args, Debug, Clone)]
+struct WatermarkArgs {
+    /// Stamp a logo image or a text such as "(DRAFT)" onto every thumbnail
+    /// or frame (display and export only; source files are never modified)
+    #[arg(long, value_name = "FILE|TEXT")]
+    watermark: Option<String>,
+
+    /// Watermark position: tl, tr, bl, br or center [default: br]
+    #[arg(long, value_name = "POS")]
+    #[arg(value_parser = clap::builder::PossibleValuesParser::new(["tl", "tr", "bl", "br", "center"]))]
+    watermark_pos: Option<String>,
+
+    /// Watermark opacity from 0.0 to 1.0 [default: 0.5]
+    #[arg(long, value_name = "OPACITY")]
+    watermark_opacity: Option<f32>,
+}
+
+impl WatermarkArgs {
+    /// The watermark asked for on the command line, if any
+    fn load(&self) -> Result<Option<watermark::Watermark>> {
+        let Some(spec) = &self.watermark else {
+            return Ok(None);
+        };
+        let position = self
+            .watermark_pos
+            .as_deref()
+            .map(watermark::parse_watermark_position)
+            .transpose()?
+            .unwrap_or(watermark::WatermarkPosition::BottomRight);
+        let opacity = self.watermark_opacity.unwrap_or(watermark::DEFAULT_OPACITY);
+        watermark::Watermark::load(spec, position, opacity).map(Some)
+    }
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum Commands {
+    /// Assemble an image sequence into an animated GIF or MP4 (MP4 needs ffmpeg)
+    Timelapse {
+        /// Directory containing the frames
+        dir: String,
+
+        /// Frames per second
+        #[arg(long, default_value = "12")]
+        fps: u32,
+
+        /// Output file; the extension selects the format (.gif or .mp4)
+        #[arg(long, default_value = "timelapse.gif")]
+        out: String,
+
+        /// Frame order: name (file name) or time (modification time)
+        #[arg(long, default_value = "name")]
+        #[arg(value_parser = clap::builder::PossibleValuesParser::new(["name", "time"]))]
+        order: String,
+
+        /// Maximum frame width in pixels
+        #[arg(long, default_value = "480")]
+        width: u32,
+
+        /// Don't play the result in the terminal after writing it
+        #[arg(long)]
+        no_preview: bool,
+
+        #[command(flatten)]
+        watermark: WatermarkArgs,
+    },
+
+    /// Collect version, terminal detection, config (secrets redacted), a test
+    /// render and recent logs into one report to attach to bug reports
+    Bugreport {
+        /// Write the report to this file instead of stdout
+        /// (.tar.gz/.tgz bundles the report with the full log)
+        #[arg(long)]
+        out: Option<String>,
+    },
+
+    /// Copy images with metadata removed, printing what is removed per file
+    #[command(group(clap::ArgGroup::new("strip").required(true).args(["strip_gps", "strip_all"])))]
+    Scrub {
+        /// Image files or directories to scrub
+        #[arg(required = true)]
+        files: Vec<String>,
+
+        /// Remove only location data (GPS EXIF tags, GPS in XMP)
+        #[arg(long)]
+        strip_gps: bool,
+
+        /// Remove EXIF, XMP, IPTC, comments and text chunks
+        #[arg(long)]
+        strip_all: bool,
+
+        /// Directory to write the scrubbed copies to
+        #[arg(long, required_unless_present = "dry_run")]
+        out: Option<String>,
+
+        /// Only show what would be removed
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Print one row per image (path, WxH, size in bytes, rating, tags) for
+    /// awk, sort or sqlite
+    Query {
+        /// Image files or directories to list
+        #[arg(required = true)]
+        files: Vec<String>,
+
+        /// Search directories recursively
+        #[arg(short, long)]
+        recursive: bool,
+
+        /// Add a column with the cached tags, joined with commas
+        #[arg(long)]
+        tags: bool,
+
+        /// Row format: tsv or csv
+        #[arg(long, default_value = "tsv")]
+        #[arg(value_parser = clap::builder::PossibleValuesParser::new(["tsv", "csv"]))]
+        format: String,
+
+        /// Leave out the header line with the column names
+        #[arg(long)]
+        no_header: bool,
+
+        /// Only images with any of these tags (OR logic)
+        #[arg(long)]
+        tag: Vec<String>,
+
+        /// Only images with all of these tags (AND logic)
+        #[arg(long)]
+        tag_and: Vec<String>,
+
+        /// Leave out images with any of these tags (NOT logic)
+        #[arg(long)]
+        tag_not: Vec<String>,
+
+        /// Ignore AI tags the model gave less than this confidence (0.0-1.0)
+        #[arg(long, value_name = "CONFIDENCE")]
+        min_tag_confidence: Option<f32>,
+
+        /// Print only the paths, each followed by a NUL byte for `xargs -0`
+        #[arg(long, conflicts_with = "print_quoted")]
+        print0: bool,
+
+        /// Print only the paths, one per line, quoted for the shell
+        #[arg(long)]
+        print_quoted: bool,
+    },
+
+    /// List the images inside a container image from its OCI image layout
+    /// on disk (e.g. made with `skopeo copy docker://IMAGE oci:DIR`)
+    Oci {
+        /// Layout directory, optionally with a path inside the image:
+        /// DIR[:PATH]
+        reference: String,
+
+        /// Image to read when the layout holds several, by tag
+        #[arg(long)]
+        tag: Option<String>,
+
+        /// Copy the images into this directory instead of listing them
+        #[arg(long, value_name = "DIR")]
+        extract: Option<String>,
+    },
+
+    /// Show statistics about lsix itself, kept locally when enabled with
+    /// `[stats] enabled = true` or LSIX_STATS=1: runs, render times, cache
+    /// hit rate and the most used flags
+    Stats {
+        /// lsix's own usage (the only statistics so far)
+        #[arg(long = "self", required = true)]
+        own: bool,
+
+        /// Delete the recorded statistics
+        #[arg(long)]
+        reset: bool,
+    },
+
+    /// Check and repair the cache of rendered rows and thumbnails
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum CacheCommand {
+    /// Check every cached row and thumbnail, removing the damaged ones (an
+    /// interrupted write, a failing disk) so they are rebuilt when next shown
+    Verify {
+        /// Only report what is damaged
+        #[arg(long)]
+        dry_run: bool,
+    },
 }
 
 /// Cleanup handler to stop SIXEL and reset terminal
 fn cleanup() {
     // Send escape sequence to stop SIXEL
     eprint!("\x1b\\");
+    terminal::finish_graphics_checks();
+    diagnostics::print_summary();
+    row_fallback::print_summary();
+    if timing::enabled() {
+        eprint!("{}", timing::summary());
+    }
     io::stderr().flush().ok();
 }
 
+/// --quiet-exit found no images; `main` turns it into exit status 1
+#[derive(Debug)]
+struct NoMatches;
+
+impl std::fmt::Display for NoMatches {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "No images matched")
+    }
+}
+
+impl std::error::Error for NoMatches {}
+
 /// Main function
-fn main() -> Result<()> {
+fn main() -> Result<ExitCode> {
     let args = Args::parse();
+    // Before anything reads the clock, the terminal or a cache
+    let _test_dirs = args.test_mode.then(test_mode::enable);
+    if !test_mode::enabled() {
+        for note in app_dirs::migrate_legacy() {
+            eprintln!("{}", note);
+        }
+    }
+
+    if args.history {
+        history::print_history(HISTORY_LIST_LIMIT);
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    // Resolve --again into the stored arguments, then remember this run
+    let cwd = std::env::current_dir()
+        .map(|d| d.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let mut effective_args: Vec<String> =
+        std::env::args().skip(1).filter(|a| a != "--again").collect();
+    let args = if args.again {
+        let previous = history::load_history()
+            .last_for_dir(&cwd)
+            .map(|entry| entry.args.clone())
+            .context("No previous lsix run recorded for this directory")?;
+        effective_args = previous.into_iter().chain(effective_args).collect();
+        eprintln!("Re-running: {}", history::format_command(&effective_args));
+        Args::parse_from(std::iter::once("lsix".to_string()).chain(effective_args.clone()))
+    } else {
+        args
+    };
+    diagnostics::set_verbosity(if args.quiet || args.quiet_exit {
+        diagnostics::Verbosity::Quiet
+    } else if args.no_warnings {
+        diagnostics::Verbosity::NoWarnings
+    } else if args.verbose {
+        diagnostics::Verbosity::Verbose
+    } else {
+        diagnostics::Verbosity::Normal
+    });
+    if let Err(e) = history::record(&cwd, &effective_args) {
+        let message = format!("Failed to save history: {:#}", e);
+        diagnostics::warn(diagnostics::WarningKind::Other, &message);
+    }
+
+    let method = args.notify.as_deref().map(notify::parse_notify_method).transpose()?;
+    let started = std::time::Instant::now();
+    let result = run_watching(args);
+    if let Err(e) = usage_stats::save(&effective_args) {
+        let message = format!("Failed to save usage statistics: {:#}", e);
+        diagnostics::warn(diagnostics::WarningKind::Other, &message);
+    }
+    if let Some(method) = method {
+        let error = result.as_ref().err().map(|e| format!("{:#}", e));
+        notify::notify(method, started.elapsed(), error.as_deref());
+    }
+    exit_status(result)
+}
+
+/// Exit status of a run: failure without a message when --quiet-exit found
+/// nothing, otherwise the run's own result
+fn exit_status(result: Result<()>) -> Result<ExitCode> {
+    match result {
+        Err(e) if e.is::<NoMatches>() => Ok(ExitCode::FAILURE),
+        result => result.map(|()| ExitCode::SUCCESS),
+    }
+}
+
+/// Answer --count and --quiet-exit once `matched` images are known to pass
+/// every filter
+fn report_count(args: &Args, matched: usize) -> Result<()> {
+    if args.count {
+        println!("{}", matched);
+    }
+    cleanup();
+    if args.quiet_exit && matched == 0 {
+        return Err(NoMatches.into());
+    }
+    Ok(())
+}
+
+/// Everything the TUI needs from the config files, the environment and the
+/// command line (`open_with` is --open-with)
+fn tui_settings(
+    config: &config::Config,
+    open_with: Option<&str>,
+    cache_mb: usize,
+) -> Result<tui_browser::TuiSettings> {
+    let (map_tiles, map_url) = config.map();
+    Ok(tui_browser::TuiSettings {
+        keys: keymap::KeyBindings::from_config(&config.keybindings)?,
+        actions: tui_actions::parse_actions(&config.actions)?,
+        cache_mb,
+        open_with: open_with.map(str::to_string).or_else(|| config.open_with()),
+        map_tiles,
+        map_url,
+        grid_columns: config.grid_columns(),
+        badge_tags: config.badge_tags(),
+    })
+}
+
+/// `run`, and with --watch for printed output (the grid, --output json, the
+/// duplicates report), `run` again after every change to the watched
+/// directories. The TUI follows them itself.
+fn run_watching(args: Args) -> Result<()> {
+    let json = args.output.as_deref() == Some("json");
+    let prints = args.grid || args.explain_filters || json || args.find_duplicates;
+    if !args.watch || !prints || args.command.is_some() {
+        return run(args);
+    }
+
+    let roots = watch::watch_roots(&args.files, !args.no_resolve_symlinks);
+    let mut watcher = watch::DirWatcher::new(&roots, args.recursive)?;
+    // Output drawn on the terminal is drawn again to fit its new size
+    let on_screen = !json && io::stdout().is_terminal();
+    if on_screen {
+        watcher.follow_resizes();
+    }
+    loop {
+        // A failed pass (e.g. every image filtered out) waits for the next change
+        if let Err(e) = run(args.clone()) {
+            eprintln!("Error: {:#}", e);
+        }
+        diagnostics::info("Watching for changes (Ctrl-C to stop)...");
+        let wake = watcher.wait()?;
+        if on_screen {
+            print!("\x1b[2J\x1b[H");
+        }
+        match wake {
+            watch::Wake::Changed(touched) => {
+                diagnostics::info(&format!("{} image(s) changed", touched.len()))
+            }
+            watch::Wake::Resized => {
+                // Rows in the scrollback were laid out for the old size too
+                print!("\x1b[3J");
+                diagnostics::info("Terminal resized")
+            }
+        }
+    }
+}
+
+/// Everything after argument parsing: subcommands, tag commands, the grid
+/// and the TUI
+fn run(args: Args) -> Result<()> {
+    match &args.command {
+        Some(Commands::Timelapse {
+            dir,
+            fps,
+            out,
+            order,
+            width,
+            no_preview,
+            watermark,
+        }) => {
+            let config = timelapse::TimelapseConfig {
+                fps: *fps,
+                max_width: *width,
+                order: timelapse::parse_frame_order(order)?,
+                preview: !no_preview,
+                watermark: watermark.load()?,
+            };
+            return timelapse::run_timelapse(dir, out, &config);
+        }
+        Some(Commands::Bugreport { out }) => {
+            // Same as a normal run: report what detection sees without querying
+            std::env::set_var("LSIX_SKIP_QUERIES", "1");
+            let command = Args::command();
+            let version = command.get_version().unwrap_or("unknown");
+            return bugreport::run_bugreport(out.as_deref(), version, BUILD_TIME);
+        }
+        Some(Commands::Scrub {
+            files,
+            strip_gps: _,
+            strip_all,
+            out,
+            dry_run,
+        }) => {
+            let mode = if *strip_all {
+                scrub::ScrubMode::All
+            } else {
+                scrub::ScrubMode::Gps
+            };
+            let files = expand_directories(files);
+            return scrub::run_scrub(&files, out.as_deref().map(StdPath::new), mode, *dry_run);
+        }
+        Some(Commands::Query {
+            files,
+            recursive,
+            tags,
+            format,
+            no_header,
+            tag,
+            tag_and,
+            tag_not,
+            min_tag_confidence,
+            print0,
+            print_quoted,
+        }) => {
+            let files = if *recursive {
+                expand_directories_recursive(files)
+            } else {
+                expand_directories(files)
+            };
+            let entries = files
+                .into_iter()
+                .map(|path| image_proc::ImageEntry { path, label: String::new(), rejection: None })
+                .collect();
+            let files: Vec<String> =
+                filter_by_tags_advanced(entries, tag, tag_and, tag_not, *min_tag_confidence)?
+                    .into_iter()
+                    .map(|entry| entry.path)
+                    .collect();
+            if let Some(format) = path_output::PathFormat::from_flags(*print0, *print_quoted) {
+                return path_output::print_paths(&files, format);
+            }
+            let format = query::parse_query_format(format)?;
+            let tag_cache = AITaggingConfig::default().cache_dir;
+            return query::run_query(&files, tag_cache.as_deref(), *tags, format, *no_header);
+        }
+        Some(Commands::Oci { reference, tag, extract }) => {
+            return oci::run_oci(reference, tag.as_deref(), extract.as_deref().map(StdPath::new));
+        }
+        Some(Commands::Stats { own: _, reset }) => {
+            return usage_stats::run_stats(*reset);
+        }
+        Some(Commands::Cache { command: CacheCommand::Verify { dry_run } }) => {
+            return render_cache::run_verify(*dry_run);
+        }
+        None => {}
+    }
+
+    if args.timing {
+        timing::enable();
+    }
+
+    // Config files provide defaults; command line flags override them
+    let config = config::load_config(&config::target_dir(&args.files))?;
+    if config.stats_enabled() {
+        usage_stats::enable();
+    }
 
     // Determine filename mode from command line argument
-    let _filename_mode = match args.mode.as_str() {
-        "long" => FilenameMode::Long,
+    let filename_mode = match args.mode.as_deref().or(config.display.mode.as_deref()) {
+        Some("long") => FilenameMode::Long,
         _ => FilenameMode::Short,
     };
 
     // Build filter config from command line arguments
-    let _filter_config = FilterConfig {
-        min_width: args.min_width,
-        max_width: args.max_width,
-        min_height: args.min_height,
-        max_height: args.max_height,
-        min_file_size: args.min_file_size.and_then(|s| parse_file_size(&s).ok()),
-        max_file_size: args.max_file_size.and_then(|s| parse_file_size(&s).ok()),
-        min_brightness: args.min_brightness,
-        max_brightness: args.max_brightness,
-        orientation: args.orientation.and_then(|s| parse_orientation(&s).ok()),
+    let filters = &config.filters;
+    let filter_config = FilterConfig {
+        min_width: args.min_width.or(filters.min_width),
+        max_width: args.max_width.or(filters.max_width),
+        min_height: args.min_height.or(filters.min_height),
+        max_height: args.max_height.or(filters.max_height),
+        min_file_size: args
+            .min_file_size
+            .as_deref()
+            .or(filters.min_file_size.as_deref())
+            .and_then(|s| parse_file_size(s).ok()),
+        max_file_size: args
+            .max_file_size
+            .as_deref()
+            .or(filters.max_file_size.as_deref())
+            .and_then(|s| parse_file_size(s).ok()),
+        min_brightness: args.min_brightness.or(filters.min_brightness),
+        max_brightness: args.max_brightness.or(filters.max_brightness),
+        orientation: args
+            .orientation
+            .as_deref()
+            .or(filters.orientation.as_deref())
+            .and_then(|s| parse_orientation(s).ok()),
     };
+    // The filters measure images with ImageMagick
+    if filter_config.is_active() {
+        tools::require(tools::Tool::ImageMagick, "Filtering images")?;
+    }
 
     // Skip terminal auto-detection for TUI mode - it's not needed and can cause input issues
     // Set environment variable to skip terminal queries
     std::env::set_var("LSIX_SKIP_QUERIES", "1");
     
     // Auto-detect terminal capabilities (very fast now)
-    let _term_config = terminal::autodetect().context("Terminal auto-detection failed")?;
+    let mut term_config = if test_mode::enabled() {
+        test_mode::stub_terminal(config.protocol()?)
+    } else {
+        terminal::autodetect_with_protocol(config.protocol()?, args.blocks_fallback)
+            .context("Terminal auto-detection failed")?
+    };
+    config.apply_terminal(&mut term_config);
+    // The TUI and previews build their pickers from what was detected here
+    term_image::init_picker(&term_config);
 
     // Handle --clear-ai-cache
     if args.clear_ai_cache {
-        let ai_config = AITaggingConfig::default();
+        let mut ai_config = AITaggingConfig::default();
+        config.apply_ai(&mut ai_config)?;
         clear_ai_cache(&ai_config)?;
         cleanup();
         return Ok(());
     }
 
     // Get list of image files
+    let scan_span = timing::phase("scan");
+    // Pipes such as `<(curl -s URL)` are read into temp files here; they are
+    // removed when `streams` goes out of scope at exit
+    let streams = stream_input::buffer_stream_inputs(&args.files);
     let image_paths = if args.files.is_empty() {
         // No arguments - find images in current directory
         filename::find_image_files()
     } else {
-        // Arguments provided - expand any directories
+        // Download remote URLs into the blob cache, then expand any directories
+        let files = blob_cache::resolve_remote_inputs(&streams.inputs, args.fetch_jobs);
         if args.recursive {
-            expand_directories_recursive(&args.files)
+            expand_directories_recursive(&files)
         } else {
-            expand_directories(&args.files)
+            expand_directories(&files)
         }
     };
 
+    // One spelling per file, so caches match across relative and absolute
+    // invocations
+    let resolve_symlinks =
+        !args.no_resolve_symlinks && config.paths.resolve_symlinks.unwrap_or(true);
+    let image_paths = paths::canonicalize_all(&image_paths, resolve_symlinks);
+    // A file reached through hard links or bind mounts is shown and tagged
+    // once, with its other paths kept as locations
+    let (image_paths, locations) = paths::collapse_hard_links(image_paths);
+    drop(scan_span);
+
+    let counting = args.count || args.quiet_exit;
     if image_paths.is_empty() {
+        if counting {
+            return report_count(&args, 0);
+        }
         eprintln!("No image files found.");
         cleanup();
         return Ok(());
     }
 
+    // Handle --import-tags: copy metadata keywords into the tag cache
+    let edit_tags =
+        !args.add_tag.is_empty() || !args.remove_tag.is_empty() || args.rename_tag.is_some();
+    if args.import_tags {
+        let mut ai_config = AITaggingConfig::default();
+        config.apply_ai(&mut ai_config)?;
+        let cache_dir = ai_config
+            .cache_dir
+            .ok_or_else(|| anyhow::anyhow!("Tag cache is disabled"))?;
+
+        let (mut imported, mut keywords) = (0, 0);
+        for path in &image_paths {
+            let found = tag_export::read_keywords(path);
+            if !found.is_empty() {
+                add_manual_tags(&cache_dir, path, &found)?;
+                imported += 1;
+                keywords += found.len();
+            }
+        }
+
+        eprintln!(
+            "Imported {} keyword(s) from {} of {} image(s)",
+            keywords,
+            imported,
+            image_paths.len()
+        );
+        notify::set_summary(&format!("Imported keywords of {} images", imported));
+        if !edit_tags && args.export_tags.is_none() {
+            cleanup();
+            return Ok(());
+        }
+    }
+
+    // Handle --add-tag / --remove-tag / --rename-tag: edit the tag cache and exit
+    if edit_tags {
+        let mut ai_config = AITaggingConfig::default();
+        config.apply_ai(&mut ai_config)?;
+        let cache_dir = ai_config
+            .cache_dir
+            .ok_or_else(|| anyhow::anyhow!("Tag cache is disabled"))?;
+        let rename = args.rename_tag.as_deref().map(parse_tag_rename).transpose()?;
+        let (added, removed) = (split_tags(&args.add_tag), split_tags(&args.remove_tag));
+
+        let (mut tagged, mut untagged, mut renamed) = (0, 0, 0);
+        for path in &image_paths {
+            if let Some((from, to)) = &rename {
+                renamed += rename_manual_tag(&cache_dir, path, from, to)? as usize;
+            }
+            if !removed.is_empty() {
+                untagged += remove_manual_tags(&cache_dir, path, &removed)? as usize;
+            }
+            if !added.is_empty() {
+                add_manual_tags(&cache_dir, path, &added)?;
+                tagged += 1;
+            }
+        }
+
+        if let Some((from, to)) = &rename {
+            eprintln!("Renamed '{}' to '{}' on {} image(s)", from, to, renamed);
+        }
+        if !removed.is_empty() {
+            eprintln!("Removed {} from {} image(s)", removed.join(", "), untagged);
+        }
+        if !added.is_empty() {
+            eprintln!("Tagged {} image(s) with {}", tagged, added.join(", "));
+        }
+        if args.export_tags.is_none() {
+            cleanup();
+            return Ok(());
+        }
+    }
+
+    // Handle --export-tags: write cached tags into image metadata and exit
+    if let Some(format) = &args.export_tags {
+        let format = tag_export::parse_tag_format(format)?;
+        let mut ai_config = AITaggingConfig::default();
+        config.apply_ai(&mut ai_config)?;
+        let cache_dir = ai_config
+            .cache_dir
+            .ok_or_else(|| anyhow::anyhow!("Tag cache is disabled"))?;
+
+        let (mut exported, mut untagged, mut failed) = (0, 0, 0);
+        for path in &image_paths {
+            match tag_export::export_cached_tags(&cache_dir, path, format) {
+                Ok(Some(_)) => exported += 1,
+                Ok(None) => untagged += 1,
+                Err(e) => {
+                    eprintln!("Warning: {}: {:#}", path, e);
+                    failed += 1;
+                }
+            }
+        }
+
+        eprintln!("Exported tags of {} image(s)", exported);
+        if untagged > 0 {
+            eprintln!("Skipped {} image(s) without tags (run --ai-tag or --add-tag)", untagged);
+        }
+        notify::set_summary(&format!("Exported tags of {} images", exported));
+        cleanup();
+        if failed > 0 {
+            anyhow::bail!("Failed to export tags of {} image(s)", failed);
+        }
+        return Ok(());
+    }
+
     // Handle --ai-tag option
-    if args.ai_tag {
+    if args.ai_tag || args.ai_caption {
         let mut ai_config = AITaggingConfig::default();
+        ai_config.use_running_ollama();
+        config.apply_ai(&mut ai_config)?;
         ai_config.debug = args.debug; // Set debug flag from command line
+        ai_config.detect_objects = args.detect_objects;
+        ai_config.caption = args.ai_caption;
+        if let Some(concurrency) = args.ai_concurrency {
+            ai_config.concurrency = concurrency;
+        }
+        if args.ai_rate_limit.is_some() {
+            ai_config.requests_per_minute = args.ai_rate_limit;
+        }
+        if let Some(age) = args.retag_older_than {
+            ai_config.max_tag_age_secs = age;
+        }
+        ai_config.retag_if_model_changed = args.retag_if_model_changed;
 
-        // Only check API key if not using localhost
-        if !ai_config.api_endpoint.contains("localhost") && ai_config.api_key.is_empty() {
+        // Only check API key if not using a local server
+        let needs_api_key = ai_config.provider.needs_api_key(&ai_config.api_endpoint);
+        if needs_api_key && ai_config.api_key.is_empty() {
             eprintln!("Error: LSIX_AI_API_KEY environment variable not set!");
             eprintln!("\nTo use AI tagging, set your API key:");
             eprintln!("  export LSIX_AI_API_KEY='your-api-key-here'");
             eprintln!("\nFor local LLM (no API key required):");
             eprintln!("  export LSIX_AI_ENDPOINT='http://localhost:8000/v1/chat/completions'");
             eprintln!("  export LSIX_AI_MODEL='Qwen3VL-8B-Instruct-Q8_0.gguf'");
-            eprintln!("\nSupported: OpenAI (GPT-4, GPT-4o), Anthropic (Claude), local LLMs");
+            eprintln!("\nFor Ollama (start it with `ollama serve`, then `ollama pull llava`):");
+            eprintln!("  export LSIX_AI_PROVIDER=ollama");
+            eprintln!("\nFor Anthropic (Claude):");
+            eprintln!("  export LSIX_AI_PROVIDER=anthropic");
+            eprintln!("\nSupported: OpenAI (GPT-4, GPT-4o), Anthropic (Claude), Ollama, local LLMs");
             cleanup();
             return Ok(());
         }
@@ -233,18 +1194,24 @@ fn main() -> Result<()> {
         );
 
         eprintln!("Model: {}", ai_config.model);
+        eprintln!("Provider: {}", ai_config.provider.name());
         eprintln!("API Endpoint: {}", ai_config.api_endpoint);
         eprintln!("Max tags per image: {}", ai_config.max_tags);
+        eprintln!("Concurrent requests: {}", ai_config.concurrency);
+        if let Some(limit) = ai_config.requests_per_minute {
+            eprintln!("Rate limit: {} requests/minute", limit);
+        }
         eprintln!("Images to process: {}", image_paths.len());
 
+        let prompt_path = app_dirs::config_dir().join("tag_prompt.md");
         if ai_config.custom_prompt.is_some() {
-            eprintln!("Prompt: Custom (from ~/.lsix/tag_prompt.md)");
+            eprintln!("Prompt: Custom (from {})", prompt_path.display());
         } else {
-            eprintln!("Prompt: Default (create ~/.lsix/tag_prompt.md to customize)");
+            eprintln!("Prompt: Default (create {} to customize)", prompt_path.display());
         }
         eprintln!();
 
-        if ai_config.api_endpoint.contains("localhost") {
+        if !needs_api_key {
             eprintln!("💡 Using local LLM - first run will be slower, subsequent runs use cache\n");
         } else {
             eprintln!("💡 Tip: Run once to cache tags, then filtering is instant!\n");
@@ -252,6 +1219,20 @@ fn main() -> Result<()> {
 
         if args.force {
             eprintln!("⚠️  Force mode enabled - ignoring cache and regenerating all tags\n");
+        } else {
+            // Say how many cached tag sets are refreshed, and why
+            let statuses = ai_tagging::cache_statuses(&image_paths, &ai_config);
+            let stale: Vec<String> = [
+                ai_tagging::CacheStatus::Expired,
+                ai_tagging::CacheStatus::ModelChanged,
+                ai_tagging::CacheStatus::Incomplete,
+            ]
+            .into_iter()
+            .filter_map(|status| statuses.get(&status).map(|n| format!("{} {}", n, status.label())))
+            .collect();
+            if !stale.is_empty() {
+                eprintln!("🔄 Refreshing cached tags: {}\n", stale.join(", "));
+            }
         }
 
         // Tag all images with AI
@@ -260,6 +1241,7 @@ fn main() -> Result<()> {
 
         eprintln!("\n✓ AI tagging complete!");
         eprintln!("  Total images tagged: {}", ai_tags_map.len());
+        notify::set_summary(&format!("Tagged {} images", ai_tags_map.len()));
         eprintln!("  Cache location: {:?}", ai_config.cache_dir);
 
         // Display all generated tags
@@ -277,6 +1259,13 @@ fn main() -> Result<()> {
             if let Some(name) = StdPath::new(path).file_name() {
                 eprintln!("{}:", name.to_string_lossy());
                 eprintln!("  Tags: {}\n", tags.tags.join(", "));
+                if let Some(caption) = &tags.caption {
+                    eprintln!("  Caption: {}", caption);
+                }
+                if let Some(objects) = &tags.objects {
+                    let names: Vec<&str> = objects.iter().map(|o| o.label.as_str()).collect();
+                    eprintln!("  Objects: {}", names.join(", "));
+                }
                 if let Some(rating) = &tags.content_rating {
                     eprintln!("  Content Rating: {}", rating.to_uppercase());
                 }
@@ -284,11 +1273,13 @@ fn main() -> Result<()> {
         }
 
         eprintln!("💡 Tips:");
-        eprintln!("  - Tags are cached for 30 days");
+        eprintln!("  - Tags are cached for 30 days (--retag-older-than to change)");
+        eprintln!("  - Use --retag-if-model-changed after switching to a better model");
         eprintln!("  - Use --tag <TAG> to filter by AI-generated tag (OR logic)");
         eprintln!("  - Use --tag-and <TAG> for AND logic (must match all)");
         eprintln!("  - Use --tag-not <TAG> to exclude tags (NOT logic)");
         eprintln!("  - Comma-separated tags: --tag \"beach,sunset\"");
+        eprintln!("  - Use --has-object <NAME> to filter by detected object (needs --detect-objects)");
         eprintln!("  - Use --clear-ai-cache to clear cache and regenerate");
         eprintln!("  - API costs vary by provider (gpt-4o-mini is cost-effective)\n");
 
@@ -296,21 +1287,637 @@ fn main() -> Result<()> {
         return Ok(());
     }
 
-    // Always use TUI browser mode for displaying images
-    eprintln!("Starting TUI browser mode...");
-    eprintln!("Found {} images to browse.", image_paths.len());
-    eprintln!("Build time: {}", BUILD_TIME.trim());
-    eprintln!("Use Arrow keys to navigate, Enter to view full size, q to quit");
+    // Handle --has-object: keep images whose cached detections include any of the objects
+    let image_paths = if args.has_object.is_empty() {
+        image_paths
+    } else {
+        let mut ai_config = AITaggingConfig::default();
+        config.apply_ai(&mut ai_config)?;
+        let cache_dir = ai_config.cache_dir.unwrap_or_default();
+
+        let matching: Vec<String> = image_paths
+            .into_iter()
+            .filter(|path| {
+                load_cached_tags(&cache_dir, path)
+                    .map(|tags| args.has_object.iter().any(|obj| tags.has_object(obj)))
+                    .unwrap_or(false)
+            })
+            .collect();
+        if matching.is_empty() && counting {
+            return report_count(&args, 0);
+        }
+        if matching.is_empty() {
+            eprintln!("No images contain: {}", args.has_object.join(", "));
+            eprintln!("💡 Run --ai-tag --detect-objects first to locate objects");
+            cleanup();
+            return Ok(());
+        }
+        matching
+    };
+
+    // Handle --min-rating: keep images rated at least that many stars
+    let image_paths = if let Some(min_rating) = args.min_rating {
+        let mut ai_config = AITaggingConfig::default();
+        config.apply_ai(&mut ai_config)?;
+        let cache_dir = ai_config.cache_dir.unwrap_or_default();
+
+        let rated: Vec<String> = image_paths
+            .into_iter()
+            .filter(|path| {
+                culling::cached_stars(&cache_dir, path).is_some_and(|stars| stars >= min_rating)
+            })
+            .collect();
+        if rated.is_empty() && counting {
+            return report_count(&args, 0);
+        }
+        if rated.is_empty() {
+            eprintln!("No images rated {} stars or more.", min_rating);
+            eprintln!("💡 Rate images in the TUI (-i) with the keys 1-5");
+            cleanup();
+            return Ok(());
+        }
+        rated
+    } else {
+        image_paths
+    };
+
+    // Handle --search: keep the best matches, ranked by similarity to the query
+    let mut search_scores = HashMap::new();
+    let image_paths = if let Some(query) = &args.search {
+        let mut embed_config = embeddings::EmbeddingConfig::default();
+        config.apply_embeddings(&mut embed_config);
+        embed_config.debug = args.debug;
+        if let Some(concurrency) = args.ai_concurrency {
+            embed_config.concurrency = concurrency;
+        }
+        if args.ai_rate_limit.is_some() {
+            embed_config.requests_per_minute = args.ai_rate_limit;
+        }
+        if embed_config.needs_api_key() && embed_config.api_key.is_empty() {
+            eprintln!("Error: --search needs an embeddings endpoint serving a CLIP-style model.");
+            eprintln!("\nFor a local server (no API key required):");
+            eprintln!("  export LSIX_EMBED_ENDPOINT='http://localhost:7997/v1/embeddings'");
+            eprintln!("  export LSIX_EMBED_MODEL='openai/clip-vit-base-patch32'");
+            eprintln!("\nFor a hosted API, also set LSIX_EMBED_API_KEY.");
+            cleanup();
+            return Ok(());
+        }
+
+        diagnostics::info(&format!(
+            "Searching {} images for \"{}\" with {}",
+            image_paths.len(),
+            query,
+            embed_config.model
+        ));
+        let ranked = {
+            let _span = timing::phase("analyze");
+            embeddings::search(&image_paths, query, args.search_limit, &embed_config)?
+        };
+        notify::set_summary(&format!("Found {} matches for \"{}\"", ranked.len(), query));
+        if ranked.is_empty() && counting {
+            return report_count(&args, 0);
+        }
+        if ranked.is_empty() {
+            eprintln!("No images could be embedded for the search.");
+            cleanup();
+            return Ok(());
+        }
+        ranked
+            .into_iter()
+            .map(|(path, score)| {
+                search_scores.insert(path.clone(), score);
+                path
+            })
+            .collect()
+    } else {
+        image_paths
+    };
+
+    // Handle --grid-split: every sheet is replaced by its frames
+    let image_paths = if let Some(split) = &args.grid_split {
+        let split = sprites::parse_grid_split(split)?;
+        let mut frames = Vec::new();
+
+        for path in &image_paths {
+            let sheet_frames = match &args.export_sprites {
+                Some(dir) => sprites::write_frames(path, split, StdPath::new(dir)),
+                None => sprites::cached_frames(path, split),
+            };
+            match sheet_frames {
+                Ok(sheet_frames) => frames.extend(sheet_frames),
+                Err(e) => {
+                    diagnostics::warn(diagnostics::WarningKind::Other, &format!("{:#}", e))
+                }
+            }
+        }
+
+        if let Some(dir) = &args.export_sprites {
+            eprintln!("✓ Exported {} frames to {}", frames.len(), dir);
+        }
+        frames
+    } else {
+        image_paths
+    };
+
+    // Handle quality scoring: --min-quality, --sort quality and --best-shots
+    let sort_order = args.sort.as_deref().map(sorting::parse_sort_order).transpose()?;
+    let sorts_by_quality = sort_order
+        .iter()
+        .flatten()
+        .any(|spec| spec.key == sorting::SortKey::Quality);
+    let mut best_shots = HashSet::new();
+    let mut quality_scores = None;
+    let mut image_paths = if args.min_quality.is_some()
+        || sorts_by_quality
+        || args.best_shots
+    {
+        diagnostics::info(&format!("Scoring quality of {} images...", image_paths.len()));
+        let scores = {
+            let _span = timing::phase("analyze");
+            quality::score_all(&image_paths)
+        };
+
+        let scored: Vec<String> = image_paths
+            .into_iter()
+            .filter(|path| match (args.min_quality, scores.get(path)) {
+                (Some(min), Some(score)) => *score >= min,
+                (Some(_), None) => false,
+                (None, _) => true,
+            })
+            .collect();
+
+        if args.best_shots {
+            let groups = group_images(&scored, GroupBy::Similarity, args.similarity_threshold)?;
+            if groups.is_empty() && !scored.is_empty() {
+                diagnostics::warn(
+                    diagnostics::WarningKind::Other,
+                    "similarity grouping failed; no best shots marked",
+                );
+            }
+            best_shots.extend(quality::best_of_groups(&groups, &scores));
+            eprintln!("✓ Marked {} best shots in {} groups", best_shots.len(), groups.len());
+        }
+        quality_scores = Some(scores);
+        scored
+    } else {
+        image_paths
+    };
+
+    if let Some(order) = sort_order {
+        let mut ai_config = AITaggingConfig::default();
+        config.apply_ai(&mut ai_config)?;
+        sorting::sort_paths_by(
+            &mut image_paths,
+            &order,
+            args.reverse,
+            quality_scores.as_ref(),
+            ai_config.cache_dir.as_deref(),
+        );
+    }
+
+    // Compare with the last listing of the folders, and record this one
+    let changes = if args.changes || args.changed_only {
+        changes::detect(&image_paths)?
+    } else {
+        HashMap::new()
+    };
+    if args.changed_only {
+        image_paths.retain(|path| changes.contains_key(path));
+        if image_paths.is_empty() && counting {
+            return report_count(&args, 0);
+        }
+        if image_paths.is_empty() {
+            eprintln!("Nothing new or changed since the last listing.");
+            cleanup();
+            return Ok(());
+        }
+    }
+
+    // Apply size/color/orientation filters.
+    // The TUI decodes with the image crate, which doesn't understand ImageMagick's [0]
+    // frame suffix, so only the grid gets it for implicitly listed files.
+    let grid_output = args.grid
+        || args.explain_filters
+        || args.interactive
+        || args.alt_screen
+        || args.page.is_some();
+    let json_output = args.output.as_deref() == Some("json");
+    let path_format = path_output::PathFormat::from_flags(args.print0, args.print_quoted);
+    let explicit = !args.files.is_empty() || !grid_output;
+    let analyze_span = timing::phase("analyze");
+    let entries = validate_images_concurrent(
+        &image_paths,
+        explicit,
+        filename_mode,
+        &filter_config,
+        args.explain_filters,
+    );
+    let entries = filter_by_tags_advanced(
+        entries,
+        &args.tag,
+        &args.tag_and,
+        &args.tag_not,
+        args.min_tag_confidence,
+    )?;
+    drop(analyze_span);
+
+    // Label the best shot of each similarity group, search matches with
+    // their score, files found at several paths with the count, new or
+    // changed files, and videos
+    let entries: Vec<_> = entries
+        .into_iter()
+        .map(|mut entry| {
+            let path = entry.path.trim_end_matches("[0]");
+            if best_shots.contains(path) {
+                entry.label.push_str("\n★ best");
+            }
+            if let Some(score) = search_scores.get(path) {
+                entry.label.push_str(&format!("\n≈ {:.2}", score));
+            }
+            if let Some(all) = locations.get(path) {
+                entry.label.push_str(&format!("\n{}", paths::locations_badge(all.len())));
+            }
+            if let Some(change) = changes.get(path) {
+                entry.label.push_str(&format!("\n{}", change.badge()));
+            }
+            if video_thumbs::is_video(path) {
+                entry.label = format!("{} {}", video_thumbs::BADGE, entry.label);
+            }
+            entry
+        })
+        .collect();
+
+    if counting {
+        return report_count(&args, entries.len());
+    }
+    if entries.is_empty() {
+        eprintln!("No images match the filters.");
+        if filter_config.is_active() {
+            eprintln!("💡 Use --explain-filters to see why each image was rejected");
+        }
+        cleanup();
+        return Ok(());
+    }
+
+    if let Some(format) = &args.export_labels {
+        let format = labels::parse_label_format(format)?;
+        let out = args
+            .labels_out
+            .clone()
+            .unwrap_or_else(|| format.default_output().to_string());
+
+        let paths: Vec<String> = entries
+            .iter()
+            .filter(|e| e.rejection.is_none())
+            .map(|e| e.path.clone())
+            .collect();
+        let mut ai_config = AITaggingConfig::default();
+        config.apply_ai(&mut ai_config)?;
+
+        let images = labels::collect_labels(&paths, &ai_config);
+        let untagged = images.iter().filter(|img| img.annotations.is_empty()).count();
+        labels::export_labels(&images, format, StdPath::new(&out))?;
+
+        eprintln!(
+            "✓ Exported labels for {} images ({} categories) to {}",
+            images.len(),
+            labels::categories(&images).len(),
+            out
+        );
+        if untagged > 0 {
+            eprintln!("💡 {} images have no cached tags; run --ai-tag first", untagged);
+        }
+        cleanup();
+        return Ok(());
+    }
+
+    // Handle --find-duplicates: a report, a TUI dedup session or grouped grids
+    let duplicate_groups = if args.find_duplicates {
+        let paths: Vec<String> = entries
+            .iter()
+            .filter(|e| e.rejection.is_none())
+            .map(|e| e.path.trim_end_matches("[0]").to_string())
+            .collect();
+        let groups = {
+            let _span = timing::phase("group");
+            duplicates::find_duplicates(&paths, args.duplicate_distance)
+        };
+        if groups.is_empty() {
+            eprintln!("No duplicates found among {} images.", paths.len());
+            cleanup();
+            return Ok(());
+        }
+
+        if args.dedup {
+            let settings = tui_settings(&config, args.open_with.as_deref(), args.cache_mb)?;
+            if let Err(e) = tui_browser::run_tui_dedup(&groups, settings) {
+                cleanup();
+                return Err(anyhow::anyhow!("TUI browser failed: {}", e));
+            }
+            cleanup();
+            return Ok(());
+        }
+        if let Some(format) = path_format {
+            let extras: Vec<String> = groups.iter().flat_map(|g| g.extras()).cloned().collect();
+            path_output::print_paths(&extras, format)?;
+            cleanup();
+            return Ok(());
+        }
+        if !grid_output && args.export_html.is_none() && !json_output {
+            print!("{}", duplicates::format_report(&groups));
+            cleanup();
+            return Ok(());
+        }
+        Some(groups)
+    } else {
+        None
+    };
+
+    if let Some(format) = path_format {
+        let paths: Vec<String> = entries
+            .iter()
+            .filter(|e| e.rejection.is_none())
+            .map(|e| e.path.clone())
+            .collect();
+        path_output::print_paths(&paths, format)?;
+        cleanup();
+        return Ok(());
+    }
+
+    // Handle --group-by: groups are named from --group-name or the
+    // [group_names] template for the grouping, then reshaped by the moves,
+    // merges and splits made in the TUI and named from names set there
+    let groups = match parse_group_by(&args.group_by)? {
+        _ if duplicate_groups.is_some() => None,
+        GroupBy::None => None,
+        strategy => {
+            let paths: Vec<String> = entries
+                .iter()
+                .filter(|e| e.rejection.is_none())
+                .map(|e| e.path.trim_end_matches("[0]").to_string())
+                .collect();
+            let mut groups = group_images(&paths, strategy, args.similarity_threshold)?;
+            let template = args
+                .group_name
+                .as_ref()
+                .or_else(|| config.group_names.get(&args.group_by.to_lowercase()));
+            if let Some(template) = template {
+                grouping::name_groups(&mut groups, template);
+            }
+            group_edits::GroupEdits::load().apply(&mut groups);
+            group_names::GroupNames::load().apply(&mut groups);
+            Some(groups)
+        }
+    };
+
+    if let Some(out) = &args.export_html {
+        let sections: Vec<gallery::Section> = match (&duplicate_groups, &groups) {
+            (Some(groups), _) => duplicates::to_image_groups(groups)
+                .into_iter()
+                .map(|g| gallery::Section { name: g.name, images: g.images })
+                .collect(),
+            (None, Some(groups)) => groups
+                .iter()
+                .map(|g| gallery::Section { name: g.name.clone(), images: g.images.clone() })
+                .collect(),
+            (None, None) => vec![gallery::Section {
+                name: String::new(),
+                images: entries
+                    .iter()
+                    .filter(|e| e.rejection.is_none())
+                    .map(|e| e.path.clone())
+                    .collect(),
+            }],
+        };
+        let mut ai_config = AITaggingConfig::default();
+        config.apply_ai(&mut ai_config)?;
+        let title = std::env::current_dir()
+            .ok()
+            .and_then(|dir| dir.file_name().map(|n| n.to_string_lossy().to_string()))
+            .unwrap_or_else(|| "lsix".to_string());
+
+        let stats = gallery::export_gallery(
+            &sections,
+            &title,
+            StdPath::new(out),
+            ai_config.cache_dir.as_deref(),
+        )?;
+        eprintln!(
+            "✓ Exported a gallery of {} images ({} tags) to {}",
+            stats.images,
+            stats.tags,
+            StdPath::new(out).join("index.html").display()
+        );
+        if stats.failed > 0 {
+            eprintln!("Warning: {} images could not be decoded and were left out", stats.failed);
+        }
+        notify::set_summary(&format!("Exported a gallery of {} images", stats.images));
+        cleanup();
+        return Ok(());
+    }
+
+    if json_output {
+        let groups = match (&duplicate_groups, groups) {
+            (Some(groups), _) => duplicates::to_image_groups(groups),
+            (None, groups) => groups.unwrap_or_default(),
+        };
+        let mut ai_config = AITaggingConfig::default();
+        config.apply_ai(&mut ai_config)?;
+        let listing =
+            json_output::build_listing(&entries, &groups, ai_config.cache_dir.as_deref());
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        serde_json::to_writer_pretty(&mut out, &listing)?;
+        writeln!(out)?;
+        cleanup();
+        return Ok(());
+    }
+
+    if grid_output {
+        // Tile appearance flags override the [display] table
+        let config = config.clone().merge(config::Config {
+            display: config::DisplayConfig {
+                border: args.border.as_ref().map(|b| b.join(" ")),
+                spacing: args.spacing,
+                rounded: args.rounded.then_some(true),
+                watermark: args.watermark.watermark.clone(),
+                watermark_pos: args.watermark.watermark_pos.clone(),
+                watermark_opacity: args.watermark.watermark_opacity,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let mut image_config = ImageConfig::from_terminal_width(
+            term_config.width,
+            term_config.num_colors,
+            &term_config.background,
+            &term_config.foreground,
+        );
+        config.apply_image(&mut image_config, term_config.width)?;
+        if let Some(encoder) = &args.encoder {
+            image_config.encoder = parse_encoder(encoder)?;
+        }
+        if let Some(palette) = &args.palette {
+            image_config.palette = parse_palette_mode(palette)?;
+        }
+        if let Some(frame) = &args.gif_frame {
+            image_config.gif_frame = gif_frame::parse_gif_frame(frame)?;
+        }
+        if let Some(strength) = args.boost_shadows {
+            if !(0.0..=1.0).contains(&strength) {
+                anyhow::bail!("--boost-shadows strength must be between 0.0 and 1.0");
+            }
+            image_config.boost_shadows = Some(strength);
+        }
+        image_config.protocol = term_config.protocol;
+
+        // Graphics sent into a pipe or a multiplexer that drops them show up as garbage
+        if let Some(warning) =
+            terminal::graphics_guard(image_config.protocol, term_config.has_sixel)
+        {
+            eprintln!("Warning: {}", warning);
+            // With no SIXEL support found there is nothing else to draw with
+            let no_graphics =
+                image_config.protocol == GraphicsProtocol::Sixel && !term_config.has_sixel;
+            if args.blocks_fallback || no_graphics {
+                eprintln!("Falling back to Unicode block rendering");
+                image_config.protocol = GraphicsProtocol::Blocks;
+            } else {
+                eprintln!("💡 Use --blocks-fallback (or LSIX_PROTOCOL=blocks) to render with text blocks instead");
+            }
+        }
+
+        if image_config.protocol != GraphicsProtocol::Sixel
+            && image_config.encoder == Encoder::ImageMagick
+        {
+            eprintln!("Note: ImageMagick only produces SIXEL; using the native encoder instead");
+        }
+        if image_config.palette == PaletteMode::Global
+            && image_config.protocol == GraphicsProtocol::Sixel
+            && image_config.encoder == Encoder::ImageMagick
+        {
+            eprintln!("Note: --palette global only applies to the native encoder");
+        }
+        if image_config.protocol == GraphicsProtocol::Sixel
+            && image_config.encoder == Encoder::ImageMagick
+        {
+            tools::require(tools::Tool::ImageMagick, "--encoder imagemagick")?;
+        }
+
+        if args.explain_filters {
+            let rejected = entries.iter().filter(|e| e.rejection.is_some()).count();
+            eprintln!(
+                "{} of {} images rejected by filters (shown greyed out)",
+                rejected,
+                entries.len()
+            );
+        }
+
+        let on_terminal = io::stdout().is_terminal() && io::stdin().is_terminal();
+        let interactive = args.interactive && on_terminal;
+        let groups = match (duplicate_groups, groups) {
+            (Some(groups), _) => Some(duplicates::to_image_groups(&groups)),
+            (None, groups) => groups,
+        };
+
+        // One screen at a time on a terminal; the interactive grid stops at
+        // the bottom of the screen by itself
+        let paginate =
+            !args.all && !interactive && (args.page.is_some() || io::stdout().is_terminal());
+        let (entries, groups, more) = if paginate {
+            let label_lines = entries.iter().map(|e| e.label.lines().count()).max().unwrap_or(0);
+            let layout = paging::Layout::new(
+                &image_config,
+                paging::Screen::detect(),
+                label_lines as u32,
+                groups.is_some(),
+            );
+            let number = args.page.unwrap_or(1);
+            let page = paging::select_page(entries, groups, &layout, number)?;
+            let more =
+                (page.remaining > 0).then(|| paging::more_notice(page.remaining, number + 1));
+            (page.entries, page.groups, more)
+        } else {
+            (entries, groups, None)
+        };
+
+        let alt_screen =
+            (args.alt_screen && on_terminal).then(terminal::AltScreen::enter).transpose()?;
+        let output = match groups {
+            _ if interactive => {
+                grid_hover::run_interactive_grid(&entries, &image_config, term_config.width)
+            }
+            Some(groups) => process_images_grouped(groups, entries, &image_config),
+            None => process_images_concurrent(entries, &image_config),
+        };
+        if let (Some(screen), Ok(())) = (&alt_screen, &output) {
+            screen.wait_for_key()?;
+        }
+        // Back on the shell's screen, so errors and the summary stay visible
+        drop(alt_screen);
+        output.context("Grid output failed")?;
+        if let Some(more) = more {
+            eprintln!("{}", more);
+        }
+        cleanup();
+
+        // Make sure the terminal is still answering, i.e. it consumed the
+        // graphics; printed rows were already checked one by one
+        if interactive
+            && image_config.protocol != GraphicsProtocol::Blocks
+            && io::stdout().is_terminal()
+            && io::stdin().is_terminal()
+            && !terminal::verify_graphics_consumed(GRAPHICS_VERIFY_TIMEOUT_MS).unwrap_or(true)
+        {
+            eprintln!(
+                "Warning: the terminal stopped responding after the graphics output; \
+                 it may not support {:?} graphics. Try --blocks-fallback.",
+                image_config.protocol
+            );
+        }
+        return Ok(());
+    }
+
+    let image_paths: Vec<String> = entries.into_iter().map(|entry| entry.path).collect();
+
+    // Otherwise use TUI browser mode for displaying images
+    diagnostics::info("Starting TUI browser mode...");
+    diagnostics::info(&format!("Found {} images to browse.", image_paths.len()));
+    diagnostics::info(&format!("Build time: {}", BUILD_TIME.trim()));
+    diagnostics::info("Use Arrow keys to navigate, Enter to view full size, q to quit");
 
     // Enable logging if requested
     if args.log {
         std::env::set_var("LSIX_ENABLE_LOG", "1");
-        let log_path = "/tmp/lsix_tui.log";
-        eprintln!("Logging enabled - logs will be saved to: {}", log_path);
+        let log_path = app_dirs::log_path();
+        eprintln!("Logging enabled - logs will be saved to: {}", log_path.display());
     }
 
     // Run the TUI browser
-    if let Err(e) = tui_browser::run_tui_browser(image_paths) {
+    let settings = tui_settings(&config, args.open_with.as_deref(), args.cache_mb)?;
+    let watcher = if args.watch {
+        let roots = watch::watch_roots(&args.files, resolve_symlinks);
+        Some(watch::DirWatcher::new(&roots, args.recursive)?)
+    } else {
+        None
+    };
+    let result = match groups {
+        Some(groups) => tui_browser::run_tui_grouped(
+            groups,
+            settings,
+            args.remember_view,
+            watcher,
+            locations,
+        ),
+        None => tui_browser::run_tui_browser(
+            image_paths,
+            settings,
+            args.remember_view,
+            watcher,
+            locations,
+        ),
+    };
+    if let Err(e) = result {
         eprintln!("TUI browser error: {}", e);
         cleanup();
         return Err(anyhow::anyhow!("TUI browser failed: {}", e));
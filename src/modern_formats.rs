@@ -0,0 +1,97 @@
+// AVIF and JPEG XL, the formats newer cameras, phones and web exports save
+// in. Neither decodes with the image crate's default codecs, so each has a
+// cargo feature: `avif` turns on the image crate's dav1d-based decoder,
+// which needs libdav1d installed, and `jxl` adds jxl-oxide, written in
+// pure Rust. With a feature on, the files go through `open_image` like any
+// other image (grids, the TUI, thumbnails). Builds without it leave the
+// files out of directory listings, like HEIF, and fail on them with how to
+// get a build that reads them.
+
+use anyhow::Result;
+use image::DynamicImage;
+use std::path::Path;
+
+/// Extensions of AVIF files
+pub const AVIF_EXTENSIONS: [&str; 1] = ["avif"];
+
+/// Extensions of JPEG XL files
+pub const JXL_EXTENSIONS: [&str; 1] = ["jxl"];
+
+/// Whether this build decodes AVIF files
+pub const AVIF_SUPPORTED: bool = cfg!(feature = "avif");
+
+/// Whether this build decodes JPEG XL files
+pub const JXL_SUPPORTED: bool = cfg!(feature = "jxl");
+
+/// Lowercase extension of the file at `path`
+fn extension(path: &str) -> Option<String> {
+    Path::new(path.trim_end_matches("[0]"))
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+}
+
+/// Whether the file at `path` is AVIF, by its extension
+pub fn is_avif(path: &str) -> bool {
+    extension(path).is_some_and(|ext| AVIF_EXTENSIONS.contains(&ext.as_str()))
+}
+
+/// Whether the file at `path` is JPEG XL, by its extension
+pub fn is_jxl(path: &str) -> bool {
+    extension(path).is_some_and(|ext| JXL_EXTENSIONS.contains(&ext.as_str()))
+}
+
+/// Fail on the AVIF file at `path` unless this build decodes AVIF; the
+/// decoding itself is the image crate's
+pub fn check_avif(path: &str) -> Result<()> {
+    if !AVIF_SUPPORTED {
+        anyhow::bail!(
+            "{} is an AVIF image, which this build of lsix can't decode. Install libdav1d and \
+             rebuild with `cargo build --release --features avif`.",
+            path.trim_end_matches("[0]")
+        );
+    }
+    Ok(())
+}
+
+/// Decode the JPEG XL file at `path`, honoring its orientation
+#[cfg(feature = "jxl")]
+pub fn open_jxl(path: &str) -> Result<DynamicImage> {
+    use anyhow::Context;
+    use jxl_oxide::integration::JxlDecoder;
+
+    let path = path.trim_end_matches("[0]");
+    let file = std::fs::File::open(path).with_context(|| format!("Failed to open {}", path))?;
+    let decoder = JxlDecoder::new(std::io::BufReader::new(file))
+        .with_context(|| format!("Failed to read {}", path))?;
+    DynamicImage::from_decoder(decoder).with_context(|| format!("Failed to decode {}", path))
+}
+
+/// Decode the JPEG XL file at `path`: not in this build
+#[cfg(not(feature = "jxl"))]
+pub fn open_jxl(path: &str) -> Result<DynamicImage> {
+    anyhow::bail!(
+        "{} is a JPEG XL image, which this build of lsix can't decode. Rebuild with \
+         `cargo build --release --features jxl`.",
+        path.trim_end_matches("[0]")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_modern_formats() {
+        assert!(is_avif("photos/IMG_0001.AVIF") && is_avif("a.avif[0]"));
+        assert!(is_jxl("scan.jxl") && is_jxl("SCAN.JXL"));
+        assert!(!is_avif("a.jxl") && !is_jxl("a.jpg") && !is_jxl("jxl"));
+        if !AVIF_SUPPORTED {
+            let error = check_avif("a.avif").unwrap_err().to_string();
+            assert!(error.contains("--features avif"));
+        }
+        if !JXL_SUPPORTED {
+            let error = open_jxl("a.jxl").unwrap_err().to_string();
+            assert!(error.contains("--features jxl"));
+        }
+    }
+}
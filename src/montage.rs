@@ -0,0 +1,345 @@
+// Native replacement for ImageMagick's `montage`: lays out a row of
+// thumbnails with labels on a solid background, optionally framed with a
+// border and rounded corners.
+
+use crate::gif_frame::open_frame;
+use crate::image_proc::{boost_shadows, ImageConfig, ImageEntry};
+use crate::timing;
+use anyhow::{Context, Result};
+use embedded_graphics::{
+    mono_font::{
+        ascii::{FONT_10X20, FONT_6X10},
+        MonoFont, MonoTextStyle,
+    },
+    pixelcolor::Rgb888,
+    prelude::*,
+    text::{Baseline, Text},
+};
+use image::{imageops, Rgba, RgbaImage};
+
+/// A decoded thumbnail ready to be placed in a row
+pub struct Tile {
+    pub image: RgbaImage,
+    pub label: String,
+}
+
+/// Decode an entry and shrink it to the tile size
+pub fn load_tile(entry: &ImageEntry, config: &ImageConfig) -> Result<Tile> {
+    let _span = timing::item("render/image", &entry.path);
+    let img = open_frame(&entry.path, config.gif_frame)
+        .with_context(|| format!("Failed to decode {}", entry.path))?;
+
+    // Like "-geometry WxH>": only ever shrink, never enlarge
+    let img = if img.width() > config.tile_width || img.height() > config.tile_height {
+        img.thumbnail(config.tile_width, config.tile_height)
+    } else {
+        img
+    };
+
+    // Images rejected by the filters are shown greyed out
+    let img = if entry.rejection.is_some() {
+        img.grayscale().brighten(-60)
+    } else {
+        img
+    };
+
+    let mut image = img.to_rgba8();
+    if let Some(strength) = config.boost_shadows {
+        boost_shadows(&mut image, strength);
+    }
+    if let Some(watermark) = &config.watermark {
+        watermark.apply(&mut image);
+    }
+
+    Ok(Tile {
+        image,
+        label: unescape_label(&entry.label),
+    })
+}
+
+/// Undo the ImageMagick escaping applied by `process_label_with_mode`
+fn unescape_label(label: &str) -> String {
+    label
+        .replace("%%", "%")
+        .replace("\\@", "@")
+        .replace("\\\\", "\\")
+}
+
+/// Pick a bitmap font that roughly matches the configured point size
+fn label_font(config: &ImageConfig) -> &'static MonoFont<'static> {
+    if config.font_size >= 20 {
+        &FONT_10X20
+    } else {
+        &FONT_6X10
+    }
+}
+
+/// Height of a composed row whose longest label has `label_lines` lines
+pub fn row_height(config: &ImageConfig, label_lines: u32) -> u32 {
+    let line_height = label_font(config).character_size.height + 2;
+    config.tile_height + 2 * (config.border_width + config.tile_yspace) + label_lines * line_height
+}
+
+/// Compose a row of tiles into a single image, laid out like
+/// `montage -tile Nx1 -geometry WxH>+X+Y`
+pub fn compose_row(tiles: &[Tile], config: &ImageConfig) -> RgbaImage {
+    let background = parse_color(&config.background).unwrap_or(Rgba([0, 0, 0, 255]));
+    let foreground = parse_color(&config.foreground).unwrap_or(Rgba([255, 255, 255, 255]));
+    let font = label_font(config);
+
+    let max_label_lines = tiles
+        .iter()
+        .map(|t| t.label.lines().count())
+        .max()
+        .unwrap_or(0) as u32;
+    let line_height = font.character_size.height + 2;
+
+    let border = config.border_width;
+    let cell_width = config.cell_width();
+    let cell_height = row_height(config, max_label_lines);
+
+    let mut canvas = RgbaImage::from_pixel(
+        (cell_width * tiles.len() as u32).max(1),
+        cell_height.max(1),
+        background,
+    );
+
+    for (i, tile) in tiles.iter().enumerate() {
+        let cell_x = i as u32 * cell_width;
+        let framed = frame_thumbnail(&tile.image, config);
+
+        // Center the framed thumbnail inside its tile area
+        let area_width = config.tile_width + 2 * border;
+        let area_height = config.tile_height + 2 * border;
+        let x = cell_x + config.tile_xspace + area_width.saturating_sub(framed.width()) / 2;
+        let y = config.tile_yspace + area_height.saturating_sub(framed.height()) / 2;
+
+        if config.shadow {
+            draw_shadow(&mut canvas, x, y, framed.width(), framed.height());
+        }
+        imageops::overlay(&mut canvas, &framed, x as i64, y as i64);
+
+        // Labels go below the tile area, one centered line at a time
+        let label_top = config.tile_yspace + area_height + 1;
+        for (line_idx, line) in tile.label.lines().enumerate() {
+            let text_width = line.chars().count() as u32 * font.character_size.width;
+            let text_x = cell_x + cell_width.saturating_sub(text_width) / 2;
+            let text_y = label_top + line_idx as u32 * line_height;
+            draw_text(&mut canvas, line, text_x, text_y, font, foreground);
+        }
+    }
+
+    canvas
+}
+
+/// Surround a thumbnail with the configured border and round its corners,
+/// like `-border N -bordercolor C` plus a rounded mask. Without either the
+/// thumbnail is returned unchanged; cut-off corners are left transparent.
+pub fn frame_thumbnail(image: &RgbaImage, config: &ImageConfig) -> RgbaImage {
+    let border = config.border_width;
+    if border == 0 && !config.rounded {
+        return image.clone();
+    }
+
+    let (width, height) = image.dimensions();
+    let radius = if config.rounded { corner_radius(width, height) } else { 0 };
+    let color = parse_color(&config.border_color).unwrap_or(Rgba([190, 190, 190, 255]));
+
+    let outer_width = width + 2 * border;
+    let outer_height = height + 2 * border;
+    RgbaImage::from_fn(outer_width, outer_height, |x, y| {
+        // The border follows the thumbnail's corners at a constant width
+        let outer_radius = if radius > 0 { radius + border } else { 0 };
+        if !inside_rounded_rect(x, y, outer_width, outer_height, outer_radius) {
+            return Rgba([0, 0, 0, 0]);
+        }
+        let (ix, iy) = (x.wrapping_sub(border), y.wrapping_sub(border));
+        if ix < width && iy < height && inside_rounded_rect(ix, iy, width, height, radius) {
+            *image.get_pixel(ix, iy)
+        } else {
+            color
+        }
+    })
+}
+
+/// Corner radius for a rounded thumbnail, proportional to its size
+fn corner_radius(width: u32, height: u32) -> u32 {
+    (width.min(height) / 12).max(2)
+}
+
+/// Whether the center of pixel (`x`, `y`) lies inside a `width`x`height`
+/// rectangle whose corners are rounded with `radius`
+fn inside_rounded_rect(x: u32, y: u32, width: u32, height: u32, radius: u32) -> bool {
+    let radius = radius.min(width / 2).min(height / 2) as f32;
+    let px = x as f32 + 0.5;
+    let py = y as f32 + 0.5;
+    // Nearest point of the rectangle shrunk by the radius
+    let cx = px.clamp(radius, width as f32 - radius);
+    let cy = py.clamp(radius, height as f32 - radius);
+    (px - cx).powi(2) + (py - cy).powi(2) <= radius * radius
+}
+
+/// Soft drop shadow offset to the bottom right, similar to montage -shadow
+fn draw_shadow(canvas: &mut RgbaImage, x: u32, y: u32, width: u32, height: u32) {
+    const OFFSET: u32 = 4;
+    let shadow = RgbaImage::from_pixel(width, height, Rgba([0, 0, 0, 110]));
+    imageops::overlay(canvas, &shadow, (x + OFFSET) as i64, (y + OFFSET) as i64);
+}
+
+/// Draw one line of `text` with its top left corner at `x`, `y`
+pub(crate) fn draw_text(
+    canvas: &mut RgbaImage,
+    text: &str,
+    x: u32,
+    y: u32,
+    font: &'static MonoFont<'static>,
+    color: Rgba<u8>,
+) {
+    let style = MonoTextStyle::new(font, Rgb888::new(color[0], color[1], color[2]));
+    let mut target = CanvasTarget(canvas);
+    Text::with_baseline(text, Point::new(x as i32, y as i32), style, Baseline::Top)
+        .draw(&mut target)
+        .ok();
+}
+
+/// Adapter so embedded-graphics can draw glyphs into an `RgbaImage`
+struct CanvasTarget<'a>(&'a mut RgbaImage);
+
+impl OriginDimensions for CanvasTarget<'_> {
+    fn size(&self) -> Size {
+        Size::new(self.0.width(), self.0.height())
+    }
+}
+
+impl DrawTarget for CanvasTarget<'_> {
+    type Color = Rgb888;
+    type Error = std::convert::Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if point.x >= 0
+                && point.y >= 0
+                && (point.x as u32) < self.0.width()
+                && (point.y as u32) < self.0.height()
+            {
+                self.0.put_pixel(
+                    point.x as u32,
+                    point.y as u32,
+                    Rgba([color.r(), color.g(), color.b(), 255]),
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parse a color as accepted by ImageMagick's -background/-fill for the
+/// common cases: `#rgb`, `#rrggbb`, `#rrggbbaa` and a few names
+pub fn parse_color(s: &str) -> Option<Rgba<u8>> {
+    let s = s.trim().to_lowercase();
+
+    if let Some(hex) = s.strip_prefix('#') {
+        let channel = |i: usize, len: usize| u8::from_str_radix(hex.get(i..i + len)?, 16).ok();
+        return match hex.len() {
+            3 => {
+                let expand = |v: u8| v * 17;
+                Some(Rgba([
+                    expand(channel(0, 1)?),
+                    expand(channel(1, 1)?),
+                    expand(channel(2, 1)?),
+                    255,
+                ]))
+            }
+            6 => Some(Rgba([channel(0, 2)?, channel(2, 2)?, channel(4, 2)?, 255])),
+            8 => Some(Rgba([
+                channel(0, 2)?,
+                channel(2, 2)?,
+                channel(4, 2)?,
+                channel(6, 2)?,
+            ])),
+            _ => None,
+        };
+    }
+
+    let rgb = match s.as_str() {
+        "black" => [0, 0, 0],
+        "white" => [255, 255, 255],
+        "gray" | "grey" => [190, 190, 190],
+        "red" => [255, 0, 0],
+        "green" => [0, 128, 0],
+        "blue" => [0, 0, 255],
+        "yellow" => [255, 255, 0],
+        "cyan" => [0, 255, 255],
+        "magenta" => [255, 0, 255],
+        "none" | "transparent" => return Some(Rgba([0, 0, 0, 0])),
+        _ => return None,
+    };
+    Some(Rgba([rgb[0], rgb[1], rgb[2], 255]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color("#282a36"), Some(Rgba([0x28, 0x2a, 0x36, 255])));
+        assert_eq!(parse_color("#fff"), Some(Rgba([255, 255, 255, 255])));
+        assert_eq!(parse_color("White"), Some(Rgba([255, 255, 255, 255])));
+        assert_eq!(parse_color("#12345"), None);
+        assert_eq!(parse_color("chartreuse-ish"), None);
+    }
+
+    #[test]
+    fn test_compose_row_dimensions() {
+        let config = ImageConfig::from_terminal_width(1024, 256, "black", "white");
+        let tiles = vec![
+            Tile {
+                image: RgbaImage::from_pixel(100, 50, Rgba([255, 0, 0, 255])),
+                label: "a.png".to_string(),
+            },
+            Tile {
+                image: RgbaImage::from_pixel(50, 100, Rgba([0, 255, 0, 255])),
+                label: "b.png\nline2".to_string(),
+            },
+        ];
+
+        let row = compose_row(&tiles, &config);
+        let cell_width = config.tile_width + 2 * config.tile_xspace;
+        assert_eq!(row.width(), 2 * cell_width);
+        assert!(row.height() > config.tile_height);
+
+        let mut bordered = config.clone();
+        bordered.set_border(1024, 3, "white");
+        let row = compose_row(&tiles, &bordered);
+        assert_eq!(row.width(), 2 * (cell_width + 6));
+    }
+
+    #[test]
+    fn test_frame_thumbnail() {
+        let mut config = ImageConfig::from_terminal_width(1024, 256, "black", "white");
+        let image = RgbaImage::from_pixel(48, 24, Rgba([255, 0, 0, 255]));
+        assert_eq!(frame_thumbnail(&image, &config), image);
+
+        config.set_border(1024, 2, "#00ff00");
+        let framed = frame_thumbnail(&image, &config);
+        assert_eq!(framed.dimensions(), (52, 28));
+        assert_eq!(*framed.get_pixel(0, 0), Rgba([0, 255, 0, 255]));
+        assert_eq!(*framed.get_pixel(2, 2), Rgba([255, 0, 0, 255]));
+
+        // Rounded corners are cut out, the border follows them
+        config.rounded = true;
+        let framed = frame_thumbnail(&image, &config);
+        assert_eq!(framed.get_pixel(0, 0)[3], 0);
+        assert_eq!(*framed.get_pixel(26, 0), Rgba([0, 255, 0, 255]));
+        assert_eq!(*framed.get_pixel(26, 14), Rgba([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_unescape_label() {
+        assert_eq!(unescape_label("100%%\\@home"), "100%@home");
+    }
+}
@@ -0,0 +1,131 @@
+// Completion notices (--notify bell|desktop) for long runs left going in
+// another pane: when lsix finishes or fails it rings the terminal bell or
+// sends a desktop notification (notify-send on Linux, osascript on macOS)
+// with a one-line summary. Long phases record what they did with
+// `set_summary`; otherwise the notice just says how long the run took.
+
+use anyhow::Result;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// How the end of a run is announced
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotifyMethod {
+    /// Terminal bell (BEL); tmux and most terminals flag the pane or tab
+    Bell,
+    /// Desktop notification
+    Desktop,
+}
+
+/// Parse notify method from string
+pub fn parse_notify_method(s: &str) -> Result<NotifyMethod> {
+    match s.to_lowercase().as_str() {
+        "bell" => Ok(NotifyMethod::Bell),
+        "desktop" => Ok(NotifyMethod::Desktop),
+        _ => anyhow::bail!("Invalid notify method: {}. Use: bell, desktop", s),
+    }
+}
+
+static SUMMARY: Mutex<Option<String>> = Mutex::new(None);
+
+/// Record what the run did, e.g. "Tagged 120 images", for the notice
+pub fn set_summary(summary: &str) {
+    if let Ok(mut slot) = SUMMARY.lock() {
+        *slot = Some(summary.to_string());
+    }
+}
+
+/// "850ms", "42s" or "3m 12s"
+fn format_elapsed(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    if secs == 0 {
+        format!("{}ms", elapsed.as_millis())
+    } else if secs < 60 {
+        format!("{}s", secs)
+    } else {
+        format!("{}m {}s", secs / 60, secs % 60)
+    }
+}
+
+/// Summary line for a run that took `elapsed` and ended with `error`, if any
+fn summary_line(summary: Option<&str>, elapsed: Duration, error: Option<&str>) -> String {
+    let elapsed = format_elapsed(elapsed);
+    match (error, summary) {
+        (Some(error), _) => format!("Failed after {}: {}", elapsed, error),
+        (None, Some(summary)) => format!("{} ({})", summary, elapsed),
+        (None, None) => format!("Finished in {}", elapsed),
+    }
+}
+
+/// Quote `text` as an AppleScript string literal
+fn applescript_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Send a desktop notification; returns false if no notifier could be run
+fn send_desktop(title: &str, body: &str) -> bool {
+    let mut command = if cfg!(target_os = "macos") {
+        let script = format!(
+            "display notification {} with title {}",
+            applescript_string(body),
+            applescript_string(title)
+        );
+        let mut command = Command::new("osascript");
+        command.arg("-e").arg(script);
+        command
+    } else {
+        let mut command = Command::new("notify-send");
+        command.args(["--app-name", "lsix", title, body]);
+        command
+    };
+    command
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// Announce the end of a run that took `elapsed`; `error` is set if it
+/// failed. A desktop notice that can't be sent falls back to the bell.
+pub fn notify(method: NotifyMethod, elapsed: Duration, error: Option<&str>) {
+    let summary = SUMMARY.lock().ok().and_then(|slot| slot.clone());
+    let line = summary_line(summary.as_deref(), elapsed, error);
+    let title = if error.is_some() { "lsix failed" } else { "lsix finished" };
+
+    if method == NotifyMethod::Desktop && send_desktop(title, &line) {
+        return;
+    }
+    // The bell goes to stderr so it still rings when stdout is piped
+    let mut stderr = std::io::stderr();
+    let _ = write!(stderr, "\x07");
+    let _ = stderr.flush();
+    if method == NotifyMethod::Desktop {
+        eprintln!("Could not send a desktop notification; {}", line);
+    } else {
+        crate::diagnostics::info(&line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_line() {
+        let minutes = Duration::from_secs(192);
+        assert_eq!(
+            summary_line(Some("Tagged 120 images"), minutes, None),
+            "Tagged 120 images (3m 12s)"
+        );
+        assert_eq!(summary_line(None, Duration::from_millis(850), None), "Finished in 850ms");
+        assert_eq!(
+            summary_line(Some("Tagged 3 images"), Duration::from_secs(5), Some("API error")),
+            "Failed after 5s: API error"
+        );
+        assert_eq!(parse_notify_method("Desktop").unwrap(), NotifyMethod::Desktop);
+        assert!(parse_notify_method("email").is_err());
+        assert_eq!(applescript_string(r#"say "hi""#), r#""say \"hi\"""#);
+    }
+}
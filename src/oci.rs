@@ -0,0 +1,422 @@
+// `lsix oci`: the images inside a container image, read from its OCI image
+// layout on disk (`skopeo copy docker://nginx oci:nginx`, `podman push IMAGE
+// oci:DIR`, or an unpacked `docker save`) without running or unpacking the
+// container.
+//
+//   lsix oci nginx:/usr/share/nginx/html
+//   /usr/share/nginx/html/logo.png   12345   sha256:1b2c3d4e5f60
+//
+// The layers are read in order as the container would see them: a file in
+// a later layer replaces one at the same path, and whiteouts (`.wh.NAME`,
+// `.wh..wh..opq`) remove files of earlier layers. Layers may be plain or
+// gzip-compressed tar. Nothing in the layout is written; --extract copies
+// the images out so they can be browsed like any directory.
+
+use crate::image_proc::IMAGE_EXTENSIONS;
+use crate::query::{format_line, QueryFormat};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+/// Annotation holding the tag of an image in index.json
+const REF_NAME: &str = "org.opencontainers.image.ref.name";
+
+/// Prefix of whiteout entries; `.wh..wh..opq` hides a whole directory
+const WHITEOUT: &str = ".wh.";
+const OPAQUE: &str = ".wh..wh..opq";
+
+#[derive(Debug, Clone, Deserialize)]
+struct Platform {
+    #[serde(default)]
+    architecture: String,
+    #[serde(default)]
+    os: String,
+}
+
+/// Reference to a blob: a manifest, an index or a layer
+#[derive(Debug, Clone, Deserialize)]
+struct Descriptor {
+    #[serde(rename = "mediaType", default)]
+    media_type: String,
+    digest: String,
+    #[serde(default)]
+    annotations: HashMap<String, String>,
+    platform: Option<Platform>,
+}
+
+impl Descriptor {
+    fn tag(&self) -> Option<&str> {
+        self.annotations.get(REF_NAME).map(String::as_str)
+    }
+
+    fn is_index(&self) -> bool {
+        self.media_type.ends_with("image.index.v1+json")
+            || self.media_type.ends_with("manifest.list.v2+json")
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Index {
+    manifests: Vec<Descriptor>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Manifest {
+    layers: Vec<Descriptor>,
+}
+
+/// An image file as the container sees it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OciFile {
+    /// Absolute path inside the container
+    pub path: String,
+    pub size: u64,
+    /// Layer the file comes from, counting from the bottom
+    pub layer: usize,
+}
+
+/// One image of an OCI image layout
+#[derive(Debug, Clone)]
+pub struct OciImage {
+    dir: PathBuf,
+    /// Layer digests, bottom first
+    pub layers: Vec<String>,
+}
+
+/// Split `LAYOUT[:PATH]` into the layout directory and the path inside the
+/// image, at the first `:` that follows an existing directory
+pub fn parse_reference(reference: &str) -> (String, Option<String>) {
+    if Path::new(reference).is_dir() {
+        return (reference.to_string(), None);
+    }
+    let split = reference
+        .match_indices(':')
+        .map(|(idx, _)| idx)
+        .find(|&idx| Path::new(&reference[..idx]).is_dir())
+        .or_else(|| reference.rfind(':').filter(|&idx| idx > 0));
+    match split {
+        Some(idx) => {
+            let path = &reference[idx + 1..];
+            (reference[..idx].to_string(), Some(path.to_string()).filter(|p| !p.is_empty()))
+        }
+        None => (reference.to_string(), None),
+    }
+}
+
+/// Path of a tar entry inside the container, without a leading `/` or
+/// `./`; `None` for paths that climb out with `..`
+fn entry_path(path: &Path) -> Option<String> {
+    let mut parts = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_string_lossy().to_string()),
+            Component::CurDir | Component::RootDir => {}
+            _ => return None,
+        }
+    }
+    Some(parts.join("/"))
+}
+
+fn is_image(path: &str) -> bool {
+    let extension = Path::new(path).extension().map(|ext| ext.to_string_lossy().to_lowercase());
+    extension.is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.as_str()))
+}
+
+/// Whether `path` is `dir` or lies below it (`dir` empty: everything)
+fn is_under(path: &str, dir: &str) -> bool {
+    dir.is_empty()
+        || path == dir
+        || path.strip_prefix(dir).is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// Architecture name the OCI uses for this machine
+fn oci_architecture() -> &'static str {
+    match std::env::consts::ARCH {
+        "x86_64" => "amd64",
+        "aarch64" => "arm64",
+        "x86" => "386",
+        "powerpc64" => "ppc64le",
+        arch => arch,
+    }
+}
+
+impl OciImage {
+    /// Open the image tagged `tag` in the layout at `dir`, or its only
+    /// image. Of a multi-platform image, the one for this machine is used.
+    pub fn open(dir: &Path, tag: Option<&str>) -> Result<OciImage> {
+        if !dir.join("oci-layout").is_file() {
+            anyhow::bail!("{} is not an OCI image layout (no oci-layout file)", dir.display());
+        }
+        let index: Index = read_json(&dir.join("index.json"))?;
+        let mut tags: Vec<&str> = index.manifests.iter().filter_map(Descriptor::tag).collect();
+        tags.sort();
+        tags.dedup();
+        let chosen = match tag {
+            Some(tag) => index.manifests.iter().find(|m| m.tag() == Some(tag)).with_context(|| {
+                format!("No image tagged {} in {}. Tags: {}", tag, dir.display(), tags.join(", "))
+            })?,
+            None if tags.len() > 1 => anyhow::bail!(
+                "{} holds several images; pick one with --tag: {}",
+                dir.display(),
+                tags.join(", ")
+            ),
+            None => index
+                .manifests
+                .first()
+                .with_context(|| format!("{} holds no images", dir.display()))?,
+        };
+
+        let mut descriptor = chosen.clone();
+        while descriptor.is_index() {
+            let index: Index = read_json(&blob_path(dir, &descriptor.digest)?)?;
+            descriptor = pick_platform(index.manifests)
+                .with_context(|| format!("Image index {} is empty", descriptor.digest))?;
+        }
+        let manifest: Manifest = read_json(&blob_path(dir, &descriptor.digest)?)?;
+        Ok(OciImage {
+            dir: dir.to_path_buf(),
+            layers: manifest.layers.into_iter().map(|layer| layer.digest).collect(),
+        })
+    }
+
+    /// Entries of layer `idx`, decompressed
+    fn layer(&self, idx: usize) -> Result<tar::Archive<Box<dyn Read>>> {
+        let path = blob_path(&self.dir, &self.layers[idx])?;
+        let mut reader = BufReader::new(
+            File::open(&path).with_context(|| format!("Failed to open {}", path.display()))?,
+        );
+        let magic = io::BufRead::fill_buf(&mut reader)?;
+        let gzip = magic.starts_with(&[0x1f, 0x8b]);
+        let zstd = magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]);
+        let reader: Box<dyn Read> = if gzip {
+            Box::new(flate2::read::GzDecoder::new(reader))
+        } else if zstd {
+            anyhow::bail!("Layer {} is zstd-compressed, which is not supported", self.layers[idx]);
+        } else {
+            Box::new(reader)
+        };
+        Ok(tar::Archive::new(reader))
+    }
+
+    /// Image files under `dir` (a path inside the container, `/` for all)
+    /// as the container sees them, sorted by path
+    pub fn images(&self, dir: &str) -> Result<Vec<OciFile>> {
+        let dir = entry_path(Path::new(dir)).unwrap_or_default();
+        let mut files: BTreeMap<String, OciFile> = BTreeMap::new();
+        for idx in 0..self.layers.len() {
+            // Until the layer is done, `files` holds only earlier layers,
+            // which are all that its whiteouts and replacements affect
+            let mut added: Vec<OciFile> = Vec::new();
+            let mut archive = self.layer(idx)?;
+            for entry in archive.entries()? {
+                let entry = entry?;
+                let Some(path) = entry_path(&entry.path()?) else {
+                    continue;
+                };
+                let (parent, name) = path.rsplit_once('/').unwrap_or(("", &path));
+                let hidden = if name == OPAQUE {
+                    parent.to_string()
+                } else if let Some(name) = name.strip_prefix(WHITEOUT) {
+                    format!("{}/{}", parent, name).trim_start_matches('/').to_string()
+                } else {
+                    let kind = entry.header().entry_type();
+                    if kind.is_file() && is_image(&path) {
+                        let size = entry.header().size()?;
+                        added.push(OciFile { path: format!("/{}", path), size, layer: idx });
+                    } else if !kind.is_dir() {
+                        // e.g. a symlink where an image used to be
+                        files.remove(&format!("/{}", path));
+                    }
+                    continue;
+                };
+                files.retain(|path, _| !is_under(&path[1..], &hidden));
+            }
+            for file in added {
+                files.insert(file.path.clone(), file);
+            }
+        }
+        Ok(files.into_values().filter(|file| is_under(&file.path[1..], &dir)).collect())
+    }
+
+    /// Copy `files` into `dest`, keeping their paths inside the container
+    pub fn extract(&self, files: &[OciFile], dest: &Path) -> Result<()> {
+        let mut by_layer: BTreeMap<usize, HashMap<&str, &OciFile>> = BTreeMap::new();
+        for file in files {
+            by_layer.entry(file.layer).or_default().insert(&file.path[1..], file);
+        }
+        for (&idx, wanted) in &by_layer {
+            let mut archive = self.layer(idx)?;
+            for entry in archive.entries()? {
+                let mut entry = entry?;
+                let Some(path) = entry_path(&entry.path()?) else {
+                    continue;
+                };
+                if !entry.header().entry_type().is_file() || !wanted.contains_key(path.as_str()) {
+                    continue;
+                }
+                let out = dest.join(&path);
+                if let Some(parent) = out.parent() {
+                    fs::create_dir_all(parent)
+                        .with_context(|| format!("Failed to create {}", parent.display()))?;
+                }
+                let mut file = File::create(&out)
+                    .with_context(|| format!("Failed to write {}", out.display()))?;
+                io::copy(&mut entry, &mut file)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The manifest for this machine among those of a multi-platform index,
+/// else the first that names a platform at all
+fn pick_platform(manifests: Vec<Descriptor>) -> Option<Descriptor> {
+    let matches = |m: &&Descriptor, arch: Option<&str>| {
+        m.platform.as_ref().is_some_and(|p| {
+            p.os == "linux" && arch.is_none_or(|arch| p.architecture == arch)
+                && p.architecture != "unknown"
+        })
+    };
+    manifests
+        .iter()
+        .find(|m| matches(m, Some(oci_architecture())))
+        .or_else(|| manifests.iter().find(|m| matches(m, None)))
+        .or(manifests.first())
+        .cloned()
+}
+
+/// File of the blob with `digest` (`sha256:HEX`)
+fn blob_path(dir: &Path, digest: &str) -> Result<PathBuf> {
+    match digest.split_once(':') {
+        Some((algorithm, hex))
+            if !algorithm.is_empty()
+                && !hex.is_empty()
+                && [algorithm, hex].iter().all(|s| s.chars().all(|c| c.is_ascii_alphanumeric())) =>
+        {
+            Ok(dir.join("blobs").join(algorithm).join(hex))
+        }
+        _ => anyhow::bail!("Invalid digest: {}", digest),
+    }
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> Result<T> {
+    let json =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    serde_json::from_str(&json).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// Short form of a layer digest for listings
+fn short_digest(digest: &str) -> String {
+    match digest.split_once(':') {
+        Some((algorithm, hex)) => format!("{}:{}", algorithm, &hex[..hex.len().min(12)]),
+        None => digest.to_string(),
+    }
+}
+
+/// List the images of `reference` (`LAYOUT[:PATH]`) as path, size and
+/// layer, or copy them into `extract`
+pub fn run_oci(reference: &str, tag: Option<&str>, extract: Option<&Path>) -> Result<()> {
+    let (layout, dir) = parse_reference(reference);
+    let image = OciImage::open(Path::new(&layout), tag)?;
+    let files = image.images(dir.as_deref().unwrap_or("/"))?;
+    if files.is_empty() {
+        anyhow::bail!("No images in {}", reference);
+    }
+
+    if let Some(dest) = extract {
+        image.extract(&files, dest)?;
+        eprintln!("Extracted {} images to {}", files.len(), dest.display());
+        return Ok(());
+    }
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for file in &files {
+        let fields =
+            [file.path.clone(), file.size.to_string(), short_digest(&image.layers[file.layer])];
+        match writeln!(out, "{}", format_line(&fields, QueryFormat::Tsv)) {
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => break,
+            result => result?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+    use sha2::{Digest, Sha256};
+
+    /// Store `data` as a blob of the layout at `dir`; returns its digest
+    fn add_blob(dir: &Path, data: &[u8]) -> String {
+        let hex = format!("{:x}", Sha256::digest(data));
+        fs::create_dir_all(dir.join("blobs/sha256")).unwrap();
+        fs::write(dir.join("blobs/sha256").join(&hex), data).unwrap();
+        format!("sha256:{}", hex)
+    }
+
+    fn layer(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (path, data) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, path, *data).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn test_oci_layout() {
+        let dir = TestDir::new("oci");
+        fs::write(dir.join("oci-layout"), r#"{"imageLayoutVersion":"1.0.0"}"#).unwrap();
+
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(&layer(&[
+            ("srv/www/logo.png", b"png"),
+            ("srv/www/old.jpg", b"jpeg"),
+            ("srv/icons/a.gif", b"gif"),
+            ("etc/app.conf", b"x"),
+        ]))
+        .unwrap();
+        let bottom = add_blob(&dir, &gz.finish().unwrap());
+        let top = add_blob(
+            &dir,
+            &layer(&[("srv/www/.wh.old.jpg", b""), ("./srv/www/logo.png", b"newer png")]),
+        );
+        let manifest = add_blob(
+            &dir,
+            format!(r#"{{"layers":[{{"digest":"{}"}},{{"digest":"{}"}}]}}"#, bottom, top)
+                .as_bytes(),
+        );
+        let index = format!(
+            r#"{{"manifests":[{{"mediaType":"application/vnd.oci.image.manifest.v1+json",
+                "digest":"{}","annotations":{{"{}":"v1"}}}}]}}"#,
+            manifest, REF_NAME
+        );
+        fs::write(dir.join("index.json"), index).unwrap();
+
+        let image = OciImage::open(&dir, None).unwrap();
+        assert!(OciImage::open(&dir, Some("v2")).is_err());
+        let files = image.images("/").unwrap();
+        let listed: Vec<(&str, u64, usize)> =
+            files.iter().map(|f| (f.path.as_str(), f.size, f.layer)).collect();
+        assert_eq!(listed, [("/srv/icons/a.gif", 3, 0), ("/srv/www/logo.png", 9, 1)]);
+        assert_eq!(image.images("/srv/www/").unwrap().len(), 1);
+        assert!(image.images("/srv/ww").unwrap().is_empty());
+
+        let out = dir.join("out");
+        image.extract(&files, &out).unwrap();
+        assert_eq!(fs::read(out.join("srv/www/logo.png")).unwrap(), b"newer png");
+
+        let layout = dir.to_string_lossy().to_string();
+        assert_eq!(parse_reference(&layout), (layout.clone(), None));
+        let (parsed, path) = parse_reference(&format!("{}:/srv/a:b", layout));
+        assert_eq!((parsed, path.as_deref()), (layout, Some("/srv/a:b")));
+        assert!(blob_path(&dir, "sha256:../../etc").is_err());
+    }
+}
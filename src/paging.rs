@@ -0,0 +1,255 @@
+// Grid pagination: printed all at once, a directory of thousands of images
+// scrolls past for minutes. Unless --all is given, the grid stops after
+// about one screen and says how many images are left; --page N shows the
+// N-th screen. Pages are cut between grid rows, and in grouped output a
+// group that continues on the next page gets its header again there.
+
+use crate::grouping::ImageGroup;
+use crate::image_proc::{ImageConfig, ImageEntry};
+use crate::montage::row_height;
+use crate::terminal::{self, GraphicsProtocol};
+use crate::test_mode;
+use std::collections::HashSet;
+use std::ops::Range;
+
+/// Cell height in pixels assumed when the terminal doesn't report one
+const FALLBACK_CELL_HEIGHT: u32 = 20;
+
+/// Screen size assumed when stdout isn't a terminal (--page into a pipe)
+const FALLBACK_SCREEN: Screen = Screen { columns: 80, lines: 24, height_px: None };
+
+/// Lines kept free below the grid for the "more images" note and the prompt
+const RESERVED_LINES: u32 = 2;
+
+/// Lines a group header takes in grouped output (blank line, box, features)
+const GROUP_HEADER_LINES: u32 = 5;
+
+/// Size of the terminal the grid is printed to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Screen {
+    pub columns: u32,
+    pub lines: u32,
+    /// Pixel height, if the terminal reports it
+    pub height_px: Option<u32>,
+}
+
+impl Screen {
+    /// The terminal's size, or a 80x24 screen when it can't be read
+    pub fn detect() -> Screen {
+        if test_mode::enabled() {
+            let (columns, lines) = test_mode::SCREEN_SIZE;
+            return Screen {
+                columns: columns as u32,
+                lines: lines as u32,
+                height_px: Some(lines as u32 * test_mode::CELL_SIZE.1 as u32),
+            };
+        }
+        match crossterm::terminal::size() {
+            Ok((columns, lines)) if columns > 0 && lines > 0 => Screen {
+                columns: columns as u32,
+                lines: lines as u32,
+                height_px: terminal::detect_pixel_height(lines as u32),
+            },
+            _ => FALLBACK_SCREEN,
+        }
+    }
+}
+
+/// How the grid fills a screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    pub per_row: usize,
+    /// Lines one grid row takes
+    pub row_lines: u32,
+    /// Lines a group header takes; 0 for ungrouped output
+    pub header_lines: u32,
+    /// Lines available per page
+    pub page_lines: u32,
+}
+
+impl Layout {
+    /// Layout of rows drawn with `config` on `screen`, for labels of up to
+    /// `label_lines` lines
+    pub fn new(config: &ImageConfig, screen: Screen, label_lines: u32, grouped: bool) -> Layout {
+        let per_row = config.num_tiles_per_row.max(1);
+        let height = row_height(config, label_lines);
+        let row_lines = if config.protocol == GraphicsProtocol::Blocks {
+            // Rows are scaled down to the terminal width, two pixels a line
+            let width = (config.cell_width() * per_row).max(1);
+            let scaled = height as u64 * width.min(screen.columns) as u64 / width as u64;
+            (scaled as u32).max(2).div_ceil(2)
+        } else {
+            let cell_height = match screen.height_px {
+                Some(height_px) => (height_px / screen.lines.max(1)).max(1),
+                None => FALLBACK_CELL_HEIGHT,
+            };
+            height.div_ceil(cell_height)
+        };
+        Layout {
+            per_row: per_row as usize,
+            row_lines,
+            header_lines: if grouped { GROUP_HEADER_LINES } else { 0 },
+            page_lines: screen.lines.saturating_sub(RESERVED_LINES),
+        }
+    }
+}
+
+/// Cut groups of `sizes` images into pages. A page lists the (group, image
+/// range) pieces on it; every page holds at least one row.
+pub fn paginate(sizes: &[usize], layout: &Layout) -> Vec<Vec<(usize, Range<usize>)>> {
+    let per_row = layout.per_row.max(1);
+    let mut pages = Vec::new();
+    let mut page: Vec<(usize, Range<usize>)> = Vec::new();
+    let mut used = 0;
+
+    for (group, &size) in sizes.iter().enumerate() {
+        for start in (0..size).step_by(per_row) {
+            let end = (start + per_row).min(size);
+            let continues = matches!(page.last(), Some((g, _)) if *g == group);
+            let mut cost = layout.row_lines + if continues { 0 } else { layout.header_lines };
+            if used + cost > layout.page_lines && !page.is_empty() {
+                pages.push(std::mem::take(&mut page));
+                used = 0;
+                cost = layout.row_lines + layout.header_lines;
+            }
+            used += cost;
+            match page.last_mut() {
+                Some((g, range)) if *g == group => range.end = end,
+                _ => page.push((group, start..end)),
+            }
+        }
+    }
+    if !page.is_empty() {
+        pages.push(page);
+    }
+    pages
+}
+
+/// One page of the grid
+#[derive(Debug)]
+pub struct Page {
+    pub entries: Vec<ImageEntry>,
+    /// Groups with images on this page, with grouped output
+    pub groups: Option<Vec<ImageGroup>>,
+    /// Images on the pages after this one
+    pub remaining: usize,
+}
+
+/// Page `number` (1-based) of `entries`, drawn in `groups` if grouped
+pub fn select_page(
+    entries: Vec<ImageEntry>,
+    groups: Option<Vec<ImageGroup>>,
+    layout: &Layout,
+    number: usize,
+) -> anyhow::Result<Page> {
+    // Entries of each group in the order the grid draws them
+    let members: Vec<Vec<usize>> = match &groups {
+        Some(groups) => groups
+            .iter()
+            .map(|group| {
+                let paths: HashSet<&str> = group.images.iter().map(String::as_str).collect();
+                (0..entries.len())
+                    .filter(|&i| paths.contains(entries[i].path.trim_end_matches("[0]")))
+                    .collect()
+            })
+            .collect(),
+        None => vec![(0..entries.len()).collect()],
+    };
+    let sizes: Vec<usize> = members.iter().map(Vec::len).collect();
+    let pages = paginate(&sizes, layout);
+    if number == 0 {
+        anyhow::bail!("--page counts from 1");
+    }
+    if number > pages.len() && !pages.is_empty() {
+        anyhow::bail!("--page {}: the grid has {} page(s)", number, pages.len());
+    }
+    let Some(pieces) = pages.get(number - 1) else {
+        return Ok(Page { entries, groups, remaining: 0 });
+    };
+
+    let remaining = pages[number..].iter().flatten().map(|(_, range)| range.len()).sum();
+    let shown: HashSet<usize> = pieces
+        .iter()
+        .flat_map(|(group, range)| members[*group][range.clone()].iter().copied())
+        .collect();
+    let groups = groups.map(|groups| {
+        let on_page: HashSet<usize> = pieces.iter().map(|(group, _)| *group).collect();
+        groups
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| on_page.contains(i))
+            .map(|(_, group)| group)
+            .collect()
+    });
+    let entries = entries
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| shown.contains(i))
+        .map(|(_, entry)| entry)
+        .collect();
+    Ok(Page { entries, groups, remaining })
+}
+
+/// Note printed below a page that isn't the last
+pub fn more_notice(remaining: usize, next_page: usize) -> String {
+    format!(
+        "… {} more image{} (use --all or --page {})",
+        remaining,
+        if remaining == 1 { "" } else { "s" },
+        next_page
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str) -> ImageEntry {
+        ImageEntry { path: format!("{}[0]", path), label: String::new(), rejection: None }
+    }
+
+    #[test]
+    fn test_paginate() {
+        let layout = Layout { per_row: 4, row_lines: 5, header_lines: 0, page_lines: 22 };
+        // 4 rows of 5 lines fit in 22 lines
+        let pages = paginate(&[37], &layout);
+        assert_eq!(pages, [vec![(0, 0..16)], vec![(0, 16..32)], vec![(0, 32..37)]]);
+
+        // Headers take room, and a group split across pages repeats its header
+        let layout = Layout { header_lines: 5, ..layout };
+        let pages = paginate(&[6, 13], &layout);
+        assert_eq!(pages, [vec![(0, 0..6)], vec![(1, 0..12)], vec![(1, 12..13)]]);
+
+        // A row taller than the screen still gets a page of its own
+        let layout = Layout { row_lines: 40, ..layout };
+        assert_eq!(paginate(&[5], &layout).len(), 2);
+    }
+
+    #[test]
+    fn test_select_page() {
+        let entries: Vec<ImageEntry> = (0..10).map(|i| entry(&format!("{}.png", i))).collect();
+        let layout = Layout { per_row: 3, row_lines: 10, header_lines: 0, page_lines: 20 };
+
+        let page = select_page(entries.clone(), None, &layout, 1).unwrap();
+        assert_eq!(page.entries.len(), 6);
+        assert_eq!(page.remaining, 4);
+        let page = select_page(entries.clone(), None, &layout, 2).unwrap();
+        assert_eq!(page.entries[0].path, "6.png[0]");
+        assert_eq!(page.remaining, 0);
+        assert!(select_page(entries, None, &layout, 3).is_err());
+
+        assert_eq!(more_notice(132, 2), "… 132 more images (use --all or --page 2)");
+    }
+
+    #[test]
+    fn test_blocks_row_lines() {
+        let mut config = ImageConfig::from_terminal_width(800, 256, "black", "white");
+        config.protocol = GraphicsProtocol::Blocks;
+        let screen = Screen { columns: 80, lines: 40, height_px: None };
+        let layout = Layout::new(&config, screen, 1, false);
+        let width = config.cell_width() * config.num_tiles_per_row;
+        let scaled = row_height(&config, 1) * 80 / width;
+        assert_eq!(layout.row_lines, scaled.div_ceil(2));
+        assert_eq!(layout.page_lines, 38);
+    }
+}
@@ -0,0 +1,109 @@
+// Paths printed for other programs rather than people: `--print0` ends each
+// path with a NUL byte, like `find -print0`, for `xargs -0`, and
+// `--print-quoted` prints one path per line quoted for the shell, to paste
+// into a command line or hand to plain `xargs`. Either way names with
+// spaces, quotes or line breaks come through whole. The listing prints the
+// matching images, `lsix query` the paths of its rows and
+// `--find-duplicates` the copies that could be removed.
+
+use anyhow::Result;
+use std::io::{self, Write};
+
+/// How paths are separated and quoted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathFormat {
+    /// Each path followed by a NUL byte
+    Nul,
+    /// One shell-quoted path per line
+    Quoted,
+}
+
+impl PathFormat {
+    /// The format asked for with --print0 or --print-quoted, if any
+    pub fn from_flags(print0: bool, print_quoted: bool) -> Option<PathFormat> {
+        if print0 {
+            Some(PathFormat::Nul)
+        } else if print_quoted {
+            Some(PathFormat::Quoted)
+        } else {
+            None
+        }
+    }
+}
+
+/// Whether `c` needs no quoting in a shell word
+fn is_safe(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "/._-+,:@%=".contains(c)
+}
+
+/// `path` quoted for the shell: as it is when every character is safe, in
+/// single quotes otherwise, and in bash's `$'...'` form when it holds
+/// control characters such as a line break, which single quotes can't
+/// carry through `xargs`
+pub fn shell_quote(path: &str) -> String {
+    if !path.is_empty() && path.chars().all(is_safe) {
+        return path.to_string();
+    }
+    if !path.chars().any(char::is_control) {
+        return format!("'{}'", path.replace('\'', r"'\''"));
+    }
+    let mut quoted = String::from("$'");
+    for c in path.chars() {
+        match c {
+            '\n' => quoted.push_str(r"\n"),
+            '\t' => quoted.push_str(r"\t"),
+            '\r' => quoted.push_str(r"\r"),
+            '\\' => quoted.push_str(r"\\"),
+            '\'' => quoted.push_str(r"\'"),
+            c if c.is_control() => quoted.push_str(&format!("\\x{:02x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('\'');
+    quoted
+}
+
+/// Write `paths` to `out` in `format`
+pub fn write_paths(out: &mut impl Write, paths: &[String], format: PathFormat) -> io::Result<()> {
+    for path in paths {
+        // ImageMagick's frame suffix isn't part of the file name
+        let path = path.trim_end_matches("[0]");
+        match format {
+            PathFormat::Nul => write!(out, "{}\0", path)?,
+            PathFormat::Quoted => writeln!(out, "{}", shell_quote(path))?,
+        }
+    }
+    out.flush()
+}
+
+/// Print `paths` to stdout in `format`. A reader that stops early ends the
+/// output quietly.
+pub fn print_paths(paths: &[String], format: PathFormat) -> Result<()> {
+    let stdout = io::stdout();
+    match write_paths(&mut stdout.lock(), paths, format) {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_output() {
+        assert_eq!(shell_quote("photos/IMG_0001.jpg"), "photos/IMG_0001.jpg");
+        assert_eq!(shell_quote("my photos/beach day.jpg"), "'my photos/beach day.jpg'");
+        assert_eq!(shell_quote("Mum's $HOME.jpg"), r"'Mum'\''s $HOME.jpg'");
+        assert_eq!(shell_quote("two\nlines\x01.jpg"), r"$'two\nlines\x01.jpg'");
+        assert_eq!(shell_quote(""), "''");
+
+        let paths = ["a b.jpg".to_string(), "anim.gif[0]".to_string()];
+        let mut out = Vec::new();
+        write_paths(&mut out, &paths, PathFormat::Nul).unwrap();
+        assert_eq!(out, b"a b.jpg\0anim.gif\0");
+        out.clear();
+        write_paths(&mut out, &paths, PathFormat::Quoted).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "'a b.jpg'\nanim.gif\n");
+    }
+}
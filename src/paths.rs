@@ -0,0 +1,170 @@
+// Canonical image paths. Every cache (AI tags, SIXEL grids, thumbnails,
+// groups) is keyed by path, so `lsix .` and `lsix /abs/dir` must see the
+// same string for the same file. Paths are canonicalized once at intake.
+//
+// Hard links and bind mounts give one file several canonical paths; those
+// are collapsed to the first path found, by device and inode, so the image
+// is drawn and tagged once and the other paths are kept as its locations.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+
+/// Every path of each file found at more than one path, keyed by the path
+/// that was kept
+pub type Locations = HashMap<String, Vec<String>>;
+
+/// Absolute form of `path` with `.` and `..` removed. Symlinks are resolved
+/// when `resolve_symlinks` is set and the file exists; otherwise the path
+/// is only normalized lexically.
+pub fn canonicalize_path(path: &str, resolve_symlinks: bool) -> String {
+    if resolve_symlinks {
+        if let Ok(resolved) = std::fs::canonicalize(path) {
+            return resolved.to_string_lossy().to_string();
+        }
+    }
+
+    let path = Path::new(path);
+    let absolute = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        match std::env::current_dir() {
+            Ok(cwd) => cwd.join(path),
+            Err(_) => path.to_path_buf(),
+        }
+    };
+    normalize(&absolute).to_string_lossy().to_string()
+}
+
+/// Canonicalize every path, dropping duplicates that only differed in form
+pub fn canonicalize_all(paths: &[String], resolve_symlinks: bool) -> Vec<String> {
+    let mut seen = HashSet::new();
+    paths
+        .iter()
+        .map(|p| canonicalize_path(p, resolve_symlinks))
+        .filter(|p| seen.insert(p.clone()))
+        .collect()
+}
+
+/// Device and inode of the file at `path`
+#[cfg(unix)]
+fn file_id(path: &str) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    std::fs::metadata(path).ok().map(|m| (m.dev(), m.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_path: &str) -> Option<(u64, u64)> {
+    None
+}
+
+/// Keep the first path of every file, dropping later paths to the same
+/// device and inode (hard links, bind mounts). Paths that can't be read
+/// are kept as they are.
+pub fn collapse_hard_links(paths: Vec<String>) -> (Vec<String>, Locations) {
+    let mut first: HashMap<(u64, u64), String> = HashMap::new();
+    let mut locations = Locations::new();
+    let mut kept = Vec::with_capacity(paths.len());
+    for path in paths {
+        let Some(id) = file_id(&path) else {
+            kept.push(path);
+            continue;
+        };
+        match first.get(&id) {
+            Some(original) => locations
+                .entry(original.clone())
+                .or_insert_with(|| vec![original.clone()])
+                .push(path),
+            None => {
+                first.insert(id, path.clone());
+                kept.push(path);
+            }
+        }
+    }
+    (kept, locations)
+}
+
+/// Badge for an image found at `count` paths, e.g. "×3 locations"
+pub fn locations_badge(count: usize) -> String {
+    format!("×{} locations", count)
+}
+
+/// Remove `.` and `..` components without touching the filesystem
+fn normalize(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match result.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    result.pop();
+                }
+                // `..` at the root stays at the root
+                Some(Component::RootDir) | Some(Component::Prefix(_)) => {}
+                _ => result.push(component),
+            },
+            other => result.push(other),
+        }
+    }
+    result
+}
+
+/// Older spellings of a canonical path that earlier versions may have used
+/// as cache keys: relative to the current directory, with and without `./`
+pub fn legacy_forms(canonical: &str) -> Vec<String> {
+    let mut forms = Vec::new();
+    if let Ok(cwd) = std::env::current_dir() {
+        if let Ok(relative) = Path::new(canonical).strip_prefix(&cwd) {
+            let relative = relative.to_string_lossy().to_string();
+            forms.push(format!("./{}", relative));
+            forms.push(relative);
+        }
+    }
+    forms
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize(Path::new("/a/./b/../c.jpg")), PathBuf::from("/a/c.jpg"));
+        assert_eq!(normalize(Path::new("/../a.jpg")), PathBuf::from("/a.jpg"));
+    }
+
+    #[test]
+    fn test_relative_and_absolute_agree() {
+        let cwd = std::env::current_dir().unwrap();
+        let absolute = cwd.join("photo.jpg").to_string_lossy().to_string();
+        for form in ["photo.jpg", "./photo.jpg", "sub/../photo.jpg", absolute.as_str()] {
+            assert_eq!(canonicalize_path(form, false), absolute);
+            // Missing files fall back to the lexical form
+            assert_eq!(canonicalize_path(form, true), absolute);
+        }
+        assert!(legacy_forms(&absolute).contains(&"photo.jpg".to_string()));
+    }
+
+    #[test]
+    fn test_canonicalize_all_dedupes() {
+        let paths = vec!["a.jpg".to_string(), "./a.jpg".to_string(), "b.jpg".to_string()];
+        assert_eq!(canonicalize_all(&paths, false).len(), 2);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_collapse_hard_links() {
+        let dir = TestDir::new("links");
+        let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+        std::fs::write(path("a.jpg"), b"a").unwrap();
+        std::fs::write(path("b.jpg"), b"b").unwrap();
+        std::fs::hard_link(path("a.jpg"), path("link.jpg")).unwrap();
+
+        let paths = ["a.jpg", "b.jpg", "link.jpg", "missing.jpg"].map(path).to_vec();
+        let (kept, locations) = collapse_hard_links(paths);
+        assert_eq!(kept, ["a.jpg", "b.jpg", "missing.jpg"].map(path));
+        assert_eq!(locations.len(), 1);
+        assert_eq!(locations[&path("a.jpg")], [path("a.jpg"), path("link.jpg")]);
+        assert_eq!(locations_badge(2), "×2 locations");
+    }
+}
@@ -0,0 +1,267 @@
+// End-to-end tests of the grid pipeline (validate -> thumbnail -> encode)
+// over tiny fixtures in every common format. Fixtures are generated into a
+// temp directory by each test, so no binary files live in the repo.
+
+use crate::filename::FilenameMode;
+use crate::filter::FilterConfig;
+use crate::image_proc::{
+    generate_cache_key, global_palette, validate_images_concurrent, ImageConfig, PaletteMode,
+};
+use crate::montage::{compose_row, load_tile};
+use crate::test_dir::TestDir;
+use image::codecs::gif::GifEncoder;
+use image::{DynamicImage, Frame, ImageFormat, Rgba, RgbaImage};
+
+/// A generated fixture and its pixel size
+struct Fixture {
+    path: String,
+    width: u32,
+    height: u32,
+}
+
+/// Colorful test pattern: a hue gradient with a translucent band so alpha
+/// handling is exercised too
+fn pattern(width: u32, height: u32) -> RgbaImage {
+    RgbaImage::from_fn(width, height, |x, y| {
+        let r = (x * 255 / width.max(1)) as u8;
+        let g = (y * 255 / height.max(1)) as u8;
+        let b = ((x + y) * 4 % 256) as u8;
+        let a = if y < height / 4 { 128 } else { 255 };
+        Rgba([r, g, b, a])
+    })
+}
+
+/// Fixtures in every format, with the directory that holds them
+fn fixtures() -> (TestDir, Vec<Fixture>) {
+    let dir = TestDir::new("fixtures");
+    let mut fixtures = Vec::new();
+    let mut add = |name: &str, width: u32, height: u32| {
+        let path = dir.join(name);
+        fixtures.push(Fixture {
+            path: path.to_string_lossy().to_string(),
+            width,
+            height,
+        });
+        path
+    };
+
+    // Landscape PNG with alpha
+    let png = add("wide.png", 96, 48);
+    pattern(96, 48)
+        .save_with_format(&png, ImageFormat::Png)
+        .unwrap();
+
+    // JPEG has no alpha channel
+    let jpeg = add("portrait.jpg", 40, 80);
+    DynamicImage::ImageRgba8(pattern(40, 80))
+        .to_rgb8()
+        .save_with_format(&jpeg, ImageFormat::Jpeg)
+        .unwrap();
+
+    // Two-frame GIF; only the first frame is shown
+    let gif = add("anim.gif", 50, 50);
+    let file = std::fs::File::create(&gif).unwrap();
+    let mut encoder = GifEncoder::new(file);
+    encoder
+        .encode_frames([
+            Frame::new(pattern(50, 50)),
+            Frame::new(RgbaImage::new(50, 50)),
+        ])
+        .unwrap();
+
+    // Lossless WebP
+    let webp = add("tiny.webp", 12, 9);
+    pattern(12, 9)
+        .save_with_format(&webp, ImageFormat::WebP)
+        .unwrap();
+
+    let tiff = add("scan.tiff", 64, 64);
+    DynamicImage::ImageRgba8(pattern(64, 64))
+        .to_rgb8()
+        .save_with_format(&tiff, ImageFormat::Tiff)
+        .unwrap();
+
+    (dir, fixtures)
+}
+
+fn fixture_paths(fixtures: &[Fixture]) -> Vec<String> {
+    fixtures.iter().map(|f| f.path.clone()).collect()
+}
+
+fn test_config(tile_size: u32, colors: u32) -> ImageConfig {
+    let mut config = ImageConfig::from_terminal_width(800, colors, "black", "white");
+    config.set_tile_size(800, tile_size);
+    config.num_colors = colors;
+    config.shadow = false;
+    config
+}
+
+/// Number of `#n;2;r;g;b` color definitions in a SIXEL stream
+fn palette_size(sixel: &[u8]) -> usize {
+    let text = String::from_utf8_lossy(sixel);
+    text.split('#')
+        .filter(|part| part.split(';').nth(1) == Some("2"))
+        .count()
+}
+
+#[test]
+fn test_validate_keeps_every_format() {
+    let (_dir, fixtures) = fixtures();
+    let paths = fixture_paths(&fixtures);
+    for explicit in [true, false] {
+        let entries = validate_images_concurrent(
+            &paths,
+            explicit,
+            FilenameMode::Short,
+            &FilterConfig::default(),
+            false,
+        );
+        assert_eq!(entries.len(), paths.len());
+        for (entry, path) in entries.iter().zip(&paths) {
+            assert_eq!(entry.path.trim_end_matches("[0]"), path);
+            assert!(entry.rejection.is_none());
+        }
+    }
+}
+
+#[test]
+fn test_thumbnails_fit_tile_and_keep_aspect() {
+    let (_dir, fixtures) = fixtures();
+    let config = test_config(32, 64);
+    // Implicit listing adds the [0] frame suffix for GIF/WebP
+    let entries = validate_images_concurrent(
+        &fixture_paths(&fixtures),
+        false,
+        FilenameMode::Short,
+        &FilterConfig::default(),
+        false,
+    );
+
+    for (entry, fixture) in entries.iter().zip(&fixtures) {
+        let tile = load_tile(entry, &config).unwrap();
+        let (w, h) = tile.image.dimensions();
+
+        if fixture.width <= 32 && fixture.height <= 32 {
+            // Small images are never enlarged
+            assert_eq!((w, h), (fixture.width, fixture.height), "{}", fixture.path);
+        } else {
+            assert!(w <= 32 && h <= 32, "{}: {}x{}", fixture.path, w, h);
+            assert_eq!(w.max(h), 32, "{}", fixture.path);
+            let expected = fixture.width as f32 / fixture.height as f32;
+            let actual = w as f32 / h as f32;
+            assert!(
+                (expected - actual).abs() < 0.1,
+                "{}: aspect {} vs {}",
+                fixture.path,
+                actual,
+                expected
+            );
+        }
+    }
+}
+
+#[test]
+fn test_encoded_row_respects_color_cap() {
+    let (_dir, fixtures) = fixtures();
+    let entries = validate_images_concurrent(
+        &fixture_paths(&fixtures),
+        true,
+        FilenameMode::Short,
+        &FilterConfig::default(),
+        false,
+    );
+
+    for colors in [16, 64] {
+        let config = test_config(32, colors);
+        let tiles: Vec<_> = entries
+            .iter()
+            .map(|e| load_tile(e, &config).unwrap())
+            .collect();
+        let row = compose_row(&tiles, &config);
+        assert!(row.width() >= 32 * tiles.len() as u32);
+
+        let sixel = crate::sixel_encoder::encode(&row, config.num_colors, [0, 0, 0]);
+        assert!(sixel.starts_with(b"\x1bP"));
+        assert!(sixel.ends_with(b"\x1b\\"));
+        let used = palette_size(&sixel);
+        assert!(
+            used > 1 && used <= colors as usize,
+            "{} colors with cap {}",
+            used,
+            colors
+        );
+    }
+}
+
+#[test]
+fn test_cache_key_is_stable() {
+    let (_dir, fixtures) = fixtures();
+    let entries = validate_images_concurrent(
+        &fixture_paths(&fixtures),
+        true,
+        FilenameMode::Short,
+        &FilterConfig::default(),
+        false,
+    );
+    let config = test_config(32, 64);
+
+    let key = generate_cache_key(&entries, &config);
+    assert_eq!(key, generate_cache_key(&entries, &config));
+    assert_eq!(key, generate_cache_key(&entries.clone(), &config.clone()));
+
+    // Anything that changes the output changes the key
+    assert_ne!(key, generate_cache_key(&entries, &test_config(32, 16)));
+    assert_ne!(key, generate_cache_key(&entries, &test_config(48, 64)));
+    assert_ne!(key, generate_cache_key(&entries[1..], &config));
+
+    // Tile styling too: --border, --spacing and --rounded
+    let styled: [fn(&mut ImageConfig); 5] = [
+        |c| c.border_width = 4,
+        |c| c.border_color = "red".to_string(),
+        |c| c.rounded = true,
+        |c| c.tile_xspace += 8,
+        |c| c.tile_yspace += 8,
+    ];
+    for style in styled {
+        let mut styled_config = config.clone();
+        style(&mut styled_config);
+        assert_ne!(key, generate_cache_key(&entries, &styled_config));
+    }
+}
+
+#[test]
+fn test_global_palette_is_shared_by_rows() {
+    let (_dir, fixtures) = fixtures();
+    let entries = validate_images_concurrent(
+        &fixture_paths(&fixtures),
+        true,
+        FilenameMode::Short,
+        &FilterConfig::default(),
+        false,
+    );
+    let mut config = test_config(32, 16);
+    assert!(global_palette(&entries, &config).is_none());
+
+    config.palette = PaletteMode::Global;
+    let palette = global_palette(&entries, &config).unwrap();
+    assert!(palette.colors.len() > 1 && palette.colors.len() <= 16);
+    // The background is common to every row, so it is in the palette
+    assert!(palette.colors.iter().any(|c| c.0 == [0, 0, 0]));
+
+    // Rows encoded with it define the same registers, whatever they show
+    let rows: Vec<Vec<u8>> = entries
+        .chunks(2)
+        .map(|chunk| {
+            let tiles: Vec<_> = chunk.iter().map(|e| load_tile(e, &config).unwrap()).collect();
+            crate::sixel_encoder::encode_with(&compose_row(&tiles, &config), &palette, [0, 0, 0])
+        })
+        .collect();
+    for row in &rows {
+        assert_eq!(palette_size(row), palette.colors.len());
+    }
+
+    // Sharing a palette changes the output, so it changes the cache key
+    let key = generate_cache_key(&entries, &config);
+    config.shared_palette = Some(std::sync::Arc::new(palette));
+    assert_ne!(key, generate_cache_key(&entries, &config));
+}
@@ -0,0 +1,141 @@
+// Where the TUI was left in each directory: the image under the cursor and
+// how far the grid was scrolled. Saved to positions.json in the state
+// directory when the TUI exits and restored when the same directory is
+// browsed again. The image is remembered by path, so the cursor finds it
+// even after files were added or removed; its old index is the fallback.
+
+use crate::app_dirs::state_dir;
+use crate::test_mode;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Directories remembered; the ones browsed longest ago are dropped first
+const MAX_DIRS: usize = 500;
+
+/// The cursor and scroll position in one directory
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Position {
+    /// Image under the cursor
+    pub path: String,
+    /// Its index in the grid
+    pub index: usize,
+    /// Index of the first image shown
+    pub scroll_offset: usize,
+    /// When the position was saved, seconds since the epoch
+    saved: i64,
+}
+
+impl Position {
+    pub fn new(path: &str, index: usize, scroll_offset: usize) -> Position {
+        Position {
+            path: path.to_string(),
+            index,
+            scroll_offset,
+            saved: test_mode::now().timestamp(),
+        }
+    }
+
+    /// Index of the remembered image among `items`, or the old index when
+    /// it is gone. None for an empty grid.
+    pub fn index_in(&self, items: &[String]) -> Option<usize> {
+        let last = items.len().checked_sub(1)?;
+        Some(items.iter().position(|item| *item == self.path).unwrap_or(self.index.min(last)))
+    }
+}
+
+/// Positions by directory
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Positions {
+    dirs: HashMap<String, Position>,
+}
+
+fn positions_path() -> PathBuf {
+    state_dir().join("positions.json")
+}
+
+/// The directory `paths` are browsed as: the deepest one holding all of them
+pub fn browse_dir(paths: &[String]) -> Option<String> {
+    let mut common: Option<PathBuf> = None;
+    for path in paths {
+        let parent = Path::new(path.trim_end_matches("[0]")).parent()?;
+        common = Some(match common {
+            None => parent.to_path_buf(),
+            Some(dir) => dir
+                .components()
+                .zip(parent.components())
+                .take_while(|(a, b)| a == b)
+                .map(|(a, _)| a)
+                .collect(),
+        });
+    }
+    let dir = common?;
+    let dir = fs::canonicalize(&dir).unwrap_or(dir);
+    Some(dir.to_string_lossy().to_string())
+}
+
+impl Positions {
+    /// Load the positions; a missing or unreadable file yields none
+    pub fn load() -> Positions {
+        fs::read_to_string(positions_path())
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn get(&self, dir: &str) -> Option<&Position> {
+        self.dirs.get(dir)
+    }
+
+    pub fn set(&mut self, dir: &str, position: Position) {
+        self.dirs.insert(dir.to_string(), position);
+        if self.dirs.len() > MAX_DIRS {
+            let mut saved: Vec<i64> = self.dirs.values().map(|p| p.saved).collect();
+            saved.sort_unstable_by(|a, b| b.cmp(a));
+            let oldest_kept = saved[MAX_DIRS - 1];
+            self.dirs.retain(|_, p| p.saved >= oldest_kept);
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = positions_path();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let json = serde_json::to_string(&self)?;
+        fs::write(&path, json).with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_positions() {
+        let paths = |list: &[&str]| list.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        let shoot = paths(&["/nonexistent/shoot/a.jpg", "/nonexistent/shoot/raw/b.jpg"]);
+        assert_eq!(browse_dir(&shoot).as_deref(), Some("/nonexistent/shoot"));
+        assert_eq!(browse_dir(&paths(&["/nonexistent/c.gif[0]"])).as_deref(), Some("/nonexistent"));
+        assert_eq!(browse_dir(&[]), None);
+
+        // The image is found again after others were added before it
+        let position = Position::new("/p/b.jpg", 1, 0);
+        assert_eq!(position.index_in(&paths(&["/p/new.jpg", "/p/a.jpg", "/p/b.jpg"])), Some(2));
+        assert_eq!(position.index_in(&paths(&["/p/a.jpg"])), Some(0));
+        assert_eq!(position.index_in(&[]), None);
+
+        let mut positions = Positions::default();
+        for i in 0..MAX_DIRS + 5 {
+            let mut position = Position::new("a.jpg", i, 0);
+            position.saved = i as i64;
+            positions.set(&format!("/dir{}", i), position);
+        }
+        assert_eq!(positions.dirs.len(), MAX_DIRS);
+        assert!(positions.get("/dir4").is_none());
+        assert_eq!(positions.get("/dir5").map(|p| p.index), Some(5));
+    }
+}
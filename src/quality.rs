@@ -0,0 +1,206 @@
+// Technical image quality scoring: sharpness (variance of the Laplacian),
+// exposure (mean brightness and clipping) and contrast, combined into a
+// 0-100 score. Scores are cached per content hash in
+// ~/.cache/lsix/quality/, so unchanged images are only analyzed once.
+
+use crate::blob_cache::content_hash;
+use crate::grouping::ImageGroup;
+use crate::image_proc::{get_cache_dir, open_image};
+use anyhow::{Context, Result};
+use image::DynamicImage;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+
+/// Longest side images are reduced to before analysis
+const ANALYSIS_SIZE: u32 = 512;
+
+/// Bump when the scoring changes so cached scores are recomputed
+const SCORE_VERSION: u32 = 1;
+
+/// Laplacian variance at which sharpness reaches ~63%
+const SHARPNESS_SCALE: f32 = 300.0;
+
+/// Quality of one image; every component is 0.0 (bad) to 1.0 (good)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct QualityScore {
+    /// Weighted total, 0 to 100
+    pub score: f32,
+    /// Edge detail; low for blurred or out-of-focus images
+    pub sharpness: f32,
+    /// Brightness near mid-grey without clipped shadows or highlights
+    pub exposure: f32,
+    /// Spread of brightness values
+    pub contrast: f32,
+    version: u32,
+}
+
+/// Score a decoded image
+pub fn score_image(img: &DynamicImage) -> QualityScore {
+    let gray = if img.width().max(img.height()) > ANALYSIS_SIZE {
+        img.thumbnail(ANALYSIS_SIZE, ANALYSIS_SIZE).to_luma8()
+    } else {
+        img.to_luma8()
+    };
+    let (width, height) = gray.dimensions();
+    let luma = |x: u32, y: u32| gray.get_pixel(x, y)[0] as f32;
+
+    // Sharpness: variance of the 4-neighbour Laplacian
+    let mut lap_sum = 0.0f64;
+    let mut lap_sq_sum = 0.0f64;
+    let mut lap_count = 0u64;
+    for y in 1..height.saturating_sub(1) {
+        for x in 1..width.saturating_sub(1) {
+            let lap = luma(x - 1, y) + luma(x + 1, y) + luma(x, y - 1) + luma(x, y + 1)
+                - 4.0 * luma(x, y);
+            lap_sum += lap as f64;
+            lap_sq_sum += (lap * lap) as f64;
+            lap_count += 1;
+        }
+    }
+    let lap_variance = if lap_count > 0 {
+        let mean = lap_sum / lap_count as f64;
+        (lap_sq_sum / lap_count as f64 - mean * mean) as f32
+    } else {
+        0.0
+    };
+    let sharpness = 1.0 - (-lap_variance / SHARPNESS_SCALE).exp();
+
+    // Exposure and contrast from the brightness distribution
+    let pixels = (width as u64 * height as u64).max(1) as f32;
+    let mut sum = 0.0f32;
+    let mut sq_sum = 0.0f32;
+    let mut clipped = 0u32;
+    for p in gray.pixels() {
+        let v = p[0] as f32 / 255.0;
+        sum += v;
+        sq_sum += v * v;
+        if p[0] <= 3 || p[0] >= 252 {
+            clipped += 1;
+        }
+    }
+    let mean = sum / pixels;
+    let std_dev = (sq_sum / pixels - mean * mean).max(0.0).sqrt();
+    let clipped_fraction = clipped as f32 / pixels;
+
+    let exposure = ((1.0 - (mean - 0.5).abs() * 2.0) * (1.0 - clipped_fraction)).clamp(0.0, 1.0);
+    // A standard deviation of 0.25 (of the full range) counts as full contrast
+    let contrast = (std_dev / 0.25).min(1.0);
+
+    let score = 100.0 * (0.5 * sharpness + 0.3 * exposure + 0.2 * contrast);
+    QualityScore {
+        score,
+        sharpness,
+        exposure,
+        contrast,
+        version: SCORE_VERSION,
+    }
+}
+
+/// Score an image file, using the cached score if the content is unchanged
+pub fn score_file(path: &str) -> Result<QualityScore> {
+    let file = path.trim_end_matches("[0]");
+    let data = fs::read(file).with_context(|| format!("Failed to read {}", file))?;
+    let cache_dir = get_cache_dir()?.join("quality");
+    let cache_path = cache_dir.join(format!("{}.json", content_hash(&data)));
+
+    if let Some(cached) = fs::read_to_string(&cache_path)
+        .ok()
+        .and_then(|json| serde_json::from_str::<QualityScore>(&json).ok())
+        .filter(|s| s.version == SCORE_VERSION)
+    {
+        return Ok(cached);
+    }
+
+    let score = score_image(&open_image(path)?);
+    if fs::create_dir_all(&cache_dir).is_ok() {
+        let _ = fs::write(&cache_path, serde_json::to_string(&score)?);
+    }
+    Ok(score)
+}
+
+/// Score images in parallel. Images that fail to decode are reported and
+/// left out of the map.
+pub fn score_all(paths: &[String]) -> HashMap<String, f32> {
+    paths
+        .par_iter()
+        .filter_map(|path| match score_file(path) {
+            Ok(score) => Some((path.clone(), score.score)),
+            Err(e) => {
+                eprintln!("Warning: failed to score {}: {:#}", path, e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Highest-scored image of every group
+pub fn best_of_groups(groups: &[ImageGroup], scores: &HashMap<String, f32>) -> Vec<String> {
+    groups
+        .iter()
+        .filter_map(|group| {
+            group
+                .images
+                .iter()
+                .filter_map(|path| scores.get(path).map(|score| (path, *score)))
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(path, _)| path.clone())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grouping::GroupMetadata;
+    use image::{GrayImage, Luma};
+
+    fn checkerboard(cell: u32) -> DynamicImage {
+        DynamicImage::ImageLuma8(GrayImage::from_fn(128, 128, |x, y| {
+            if (x / cell + y / cell).is_multiple_of(2) {
+                Luma([40])
+            } else {
+                Luma([215])
+            }
+        }))
+    }
+
+    #[test]
+    fn test_blur_lowers_sharpness() {
+        let sharp = score_image(&checkerboard(4));
+        let blurred = score_image(&checkerboard(4).blur(3.0));
+        assert!(sharp.sharpness > blurred.sharpness);
+        assert!(sharp.score > blurred.score);
+    }
+
+    #[test]
+    fn test_exposure() {
+        let flat = |v| score_image(&DynamicImage::ImageLuma8(GrayImage::from_pixel(64, 64, Luma([v]))));
+        assert!(flat(128).exposure > 0.95);
+        assert_eq!(flat(0).exposure, 0.0);
+        assert_eq!(flat(128).contrast, 0.0);
+    }
+
+    #[test]
+    fn test_best_of_groups() {
+        let group = |images: &[&str]| ImageGroup {
+            id: String::new(),
+            name: String::new(),
+            images: images.iter().map(|s| s.to_string()).collect(),
+            representative: String::new(),
+            metadata: GroupMetadata {
+                group_type: "similarity".to_string(),
+                count: images.len(),
+                common_features: HashMap::new(),
+            },
+        };
+        let scores: HashMap<String, f32> = [("a", 40.0), ("b", 70.0), ("c", 10.0)]
+            .iter()
+            .map(|(p, s)| (p.to_string(), *s))
+            .collect();
+
+        let best = best_of_groups(&[group(&["a", "b"]), group(&["c", "unscored"])], &scores);
+        assert_eq!(best, vec!["b".to_string(), "c".to_string()]);
+    }
+}
@@ -0,0 +1,182 @@
+// `lsix query`: one line per image with its path, pixel size, file size,
+// rating and (with --tags) cached tags, as TSV or CSV, for awk, sort or
+// sqlite's `.import` instead of the full --output json document.
+//
+//   path               dimensions  size     rating  tags
+//   photos/beach.jpg   4000x3000   2451873  4       beach,sea,rating:4
+//
+// The rating is the value of a `rating:N` tag, the one `--sort tag:rating`
+// reads. Values that can't be read are left empty. Nothing is decoded: the
+// size comes from the image header.
+
+use crate::ai_tagging::load_cached_tags;
+use anyhow::Result;
+use image::ImageReader;
+use rayon::prelude::*;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Column names, in order; `tags` only with --tags
+const COLUMNS: [&str; 5] = ["path", "dimensions", "size", "rating", "tags"];
+
+/// How rows are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryFormat {
+    /// Tab-separated; tabs and line breaks inside values become spaces
+    Tsv,
+    /// Comma-separated, quoted where needed (RFC 4180)
+    Csv,
+}
+
+/// Parse query output format from string
+pub fn parse_query_format(s: &str) -> Result<QueryFormat> {
+    match s.to_lowercase().as_str() {
+        "tsv" => Ok(QueryFormat::Tsv),
+        "csv" => Ok(QueryFormat::Csv),
+        _ => anyhow::bail!("Invalid query format: {}. Use: tsv, csv", s),
+    }
+}
+
+/// What one row says about an image
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QueryRow {
+    pub path: String,
+    pub dimensions: Option<(u32, u32)>,
+    pub file_size: Option<u64>,
+    pub rating: Option<String>,
+    /// Cached tags, most relevant first
+    pub tags: Vec<String>,
+}
+
+impl QueryRow {
+    /// Read the row of `path`, with tags from the tag cache at `tag_cache`
+    pub fn load(path: &str, tag_cache: Option<&Path>) -> QueryRow {
+        let tags = tag_cache
+            .and_then(|dir| load_cached_tags(dir, path).ok())
+            .map(|cached| cached.tags)
+            .unwrap_or_default();
+        QueryRow {
+            path: path.to_string(),
+            dimensions: ImageReader::open(path)
+                .and_then(|r| r.with_guessed_format())
+                .ok()
+                .and_then(|r| r.into_dimensions().ok()),
+            file_size: std::fs::metadata(path).ok().map(|m| m.len()),
+            rating: rating(&tags),
+            tags,
+        }
+    }
+
+    /// The row's values in column order
+    fn fields(&self, with_tags: bool) -> Vec<String> {
+        let mut fields = vec![
+            self.path.clone(),
+            self.dimensions.map(|(w, h)| format!("{}x{}", w, h)).unwrap_or_default(),
+            self.file_size.map(|size| size.to_string()).unwrap_or_default(),
+            self.rating.clone().unwrap_or_default(),
+        ];
+        if with_tags {
+            fields.push(self.tags.join(","));
+        }
+        fields
+    }
+}
+
+/// Value of the `rating:N` (or `rating=N`) tag among `tags`
+pub fn rating(tags: &[String]) -> Option<String> {
+    tags.iter().find_map(|tag| {
+        let (name, value) = tag.split_once([':', '='])?;
+        name.trim().eq_ignore_ascii_case("rating").then(|| value.trim().to_string())
+    })
+}
+
+/// One line of `fields` in `format`, without the line break
+pub fn format_line(fields: &[String], format: QueryFormat) -> String {
+    let escaped: Vec<String> = match format {
+        QueryFormat::Tsv => fields.iter().map(|f| f.replace(['\t', '\n', '\r'], " ")).collect(),
+        QueryFormat::Csv => fields
+            .iter()
+            .map(|f| {
+                if f.contains([',', '"', '\n', '\r']) {
+                    format!("\"{}\"", f.replace('"', "\"\""))
+                } else {
+                    f.clone()
+                }
+            })
+            .collect(),
+    };
+    let separator = if format == QueryFormat::Tsv { "\t" } else { "," };
+    escaped.join(separator)
+}
+
+/// Write the rows of `paths` to `out`, after a header line unless
+/// `no_header`
+pub fn write_query(
+    out: &mut impl Write,
+    paths: &[String],
+    tag_cache: Option<&Path>,
+    with_tags: bool,
+    format: QueryFormat,
+    no_header: bool,
+) -> io::Result<()> {
+    if !no_header {
+        let columns = if with_tags { &COLUMNS[..] } else { &COLUMNS[..4] };
+        let columns: Vec<String> = columns.iter().map(|c| c.to_string()).collect();
+        writeln!(out, "{}", format_line(&columns, format))?;
+    }
+    let rows: Vec<QueryRow> =
+        paths.par_iter().map(|path| QueryRow::load(path, tag_cache)).collect();
+    for row in &rows {
+        writeln!(out, "{}", format_line(&row.fields(with_tags), format))?;
+    }
+    out.flush()
+}
+
+/// Print the rows of `paths` to stdout. A reader that stops early (`head`)
+/// ends the output quietly.
+pub fn run_query(
+    paths: &[String],
+    tag_cache: Option<&Path>,
+    with_tags: bool,
+    format: QueryFormat,
+    no_header: bool,
+) -> Result<()> {
+    let stdout = io::stdout();
+    match write_query(&mut stdout.lock(), paths, tag_cache, with_tags, format, no_header) {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn test_query_rows() {
+        let dir = TestDir::new("query");
+        let path = dir.join("wide.png").to_string_lossy().to_string();
+        image::RgbImage::new(30, 10).save(&path).unwrap();
+
+        let mut row = QueryRow::load(&path, None);
+        assert_eq!(row.dimensions, Some((30, 10)));
+        assert_eq!(row.rating, None);
+        row.tags = vec!["beach".to_string(), "Rating: 4".to_string()];
+        row.rating = rating(&row.tags);
+        assert_eq!(row.rating.as_deref(), Some("4"));
+
+        let mut out = Vec::new();
+        let paths = std::slice::from_ref(&path);
+        write_query(&mut out, paths, None, true, QueryFormat::Tsv, false).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "path\tdimensions\tsize\trating\ttags");
+        assert!(lines[1].starts_with(&format!("{}\t30x10\t", path)));
+        assert!(lines[1].ends_with("\t\t"));
+
+        let fields = row.fields(true);
+        assert_eq!(format_line(&fields[3..], QueryFormat::Csv), "4,\"beach,Rating: 4\"");
+        assert_eq!(format_line(&["a\tb".to_string()], QueryFormat::Tsv), "a b");
+    }
+}
@@ -0,0 +1,287 @@
+// Rendered rows on disk: the terminal graphics of each row of thumbnails,
+// in the cache directory under a hash of its images and settings (see
+// image_proc::generate_cache_key). Every entry ends with a footer holding
+// the payload's length and SHA-256, so an entry cut short by an interrupted
+// write or damaged on disk is caught when read, deleted and rendered again
+// rather than replayed as garbage graphics. Entries are written to a
+// temporary file and renamed into place.
+//
+// Renders hold a shared lock on the cache's lock file while they touch it,
+// so any number of lsix processes can render at once; `lsix cache verify`
+// takes it exclusively to check every entry (and the thumbnails) and remove
+// the damaged ones without racing a write.
+
+use crate::image_proc::get_cache_dir;
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Last bytes of every entry
+const MAGIC: &[u8; 8] = b"LSIXROW1";
+
+/// Payload length (8 bytes), its SHA-256 (32) and the magic (8)
+const FOOTER_LEN: usize = 8 + 32 + MAGIC.len();
+
+/// Temporary files older than this were left by an interrupted write
+const STALE_TEMP_AGE: Duration = Duration::from_secs(600);
+
+/// Why an entry can't be used
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Damage {
+    /// No footer: cut short, or written by an older version
+    Truncated,
+    /// The payload doesn't match the footer
+    Checksum,
+    /// A thumbnail that doesn't decode
+    Undecodable,
+}
+
+impl Damage {
+    pub fn label(self) -> &'static str {
+        match self {
+            Damage::Truncated => "truncated",
+            Damage::Checksum => "checksum mismatch",
+            Damage::Undecodable => "does not decode",
+        }
+    }
+}
+
+/// `payload` with its footer
+fn encode(payload: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(payload.len() + FOOTER_LEN);
+    data.extend_from_slice(payload);
+    data.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+    data.extend_from_slice(&Sha256::digest(payload));
+    data.extend_from_slice(MAGIC);
+    data
+}
+
+/// The payload of an entry, checked against its footer
+fn decode(mut data: Vec<u8>) -> Result<Vec<u8>, Damage> {
+    if data.len() < FOOTER_LEN || !data.ends_with(MAGIC) {
+        return Err(Damage::Truncated);
+    }
+    let payload_len = data.len() - FOOTER_LEN;
+    let footer = &data[payload_len..];
+    let (len, rest) = footer.split_at(8);
+    let checksum = &rest[..32];
+    if u64::from_le_bytes(len.try_into().unwrap_or_default()) != payload_len as u64 {
+        return Err(Damage::Truncated);
+    }
+    if Sha256::digest(&data[..payload_len]).as_slice() != checksum {
+        return Err(Damage::Checksum);
+    }
+    data.truncate(payload_len);
+    Ok(data)
+}
+
+/// The cache's lock file, locked shared or exclusively. Unlocked when
+/// dropped.
+fn lock(cache_dir: &Path, exclusive: bool) -> Result<File> {
+    fs::create_dir_all(cache_dir).context("Failed to create cache directory")?;
+    let path = cache_dir.join(".lock");
+    let file = File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    if exclusive {
+        if file.try_lock().is_err() {
+            eprintln!("Waiting for other lsix processes to finish with the cache...");
+            file.lock()?;
+        }
+    } else {
+        file.lock_shared()?;
+    }
+    Ok(file)
+}
+
+fn cache_dir_of(path: &Path) -> &Path {
+    path.parent().unwrap_or(Path::new("."))
+}
+
+/// The rendered row at `path`. None when there is none; a damaged entry is
+/// deleted, so the row is rendered again.
+pub fn read_entry(path: &Path) -> Option<Vec<u8>> {
+    let _lock = lock(cache_dir_of(path), false).ok()?;
+    let data = fs::read(path).ok()?;
+    match decode(data) {
+        Ok(payload) => Some(payload),
+        Err(_) => {
+            fs::remove_file(path).ok();
+            None
+        }
+    }
+}
+
+/// Store a rendered row at `path`
+pub fn write_entry(path: &Path, payload: &[u8]) -> Result<()> {
+    let _lock = lock(cache_dir_of(path), false)?;
+    // Write then rename so a reader never sees a partial entry
+    let tmp = temp_path(path);
+    fs::write(&tmp, encode(payload))
+        .with_context(|| format!("Failed to write {}", tmp.display()))?;
+    fs::rename(&tmp, path).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(())
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+    path.with_file_name(format!("{}.{}.tmp", name, std::process::id()))
+}
+
+/// Whether `name` is a rendered row: the hex cache key
+fn is_row_name(name: &str) -> bool {
+    (1..=16).contains(&name.len()) && name.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// What `verify` found
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    pub rows: usize,
+    pub thumbnails: usize,
+    /// Damaged entries, removed unless it was a dry run
+    pub damaged: Vec<(PathBuf, Damage)>,
+    /// Temporary files left by interrupted writes
+    pub stale: Vec<PathBuf>,
+}
+
+/// Check every rendered row and thumbnail in `cache_dir`, removing the
+/// damaged ones and leftover temporary files unless `dry_run`
+pub fn verify(cache_dir: &Path, dry_run: bool) -> Result<VerifyReport> {
+    let _lock = lock(cache_dir, true)?;
+    let mut report = VerifyReport::default();
+
+    let mut rows = Vec::new();
+    let mut thumbnails = Vec::new();
+    for dir in [cache_dir.to_path_buf(), cache_dir.join("thumbs")] {
+        let Ok(entries) = fs::read_dir(&dir) else { continue };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.ends_with(".tmp") {
+                let age = entry
+                    .metadata()
+                    .and_then(|m| m.modified())
+                    .ok()
+                    .and_then(|modified| SystemTime::now().duration_since(modified).ok());
+                if age.is_some_and(|age| age > STALE_TEMP_AGE) {
+                    report.stale.push(path);
+                }
+            } else if dir == cache_dir && is_row_name(&name) && path.is_file() {
+                rows.push(path);
+            } else if dir != cache_dir && name.ends_with(".png") {
+                thumbnails.push(path);
+            }
+        }
+    }
+    report.rows = rows.len();
+    report.thumbnails = thumbnails.len();
+
+    let check_row = |path: &PathBuf| match fs::read(path) {
+        Ok(data) => decode(data).err(),
+        Err(_) => Some(Damage::Truncated),
+    };
+    let check_thumbnail = |path: &PathBuf| {
+        let decoded = image::ImageReader::open(path)
+            .ok()
+            .and_then(|reader| reader.with_guessed_format().ok())
+            .and_then(|reader| reader.decode().ok());
+        decoded.is_none().then_some(Damage::Undecodable)
+    };
+    report.damaged = rows
+        .par_iter()
+        .filter_map(|path| check_row(path).map(|damage| (path.clone(), damage)))
+        .chain(
+            thumbnails
+                .par_iter()
+                .filter_map(|path| check_thumbnail(path).map(|damage| (path.clone(), damage))),
+        )
+        .collect();
+    report.damaged.sort_by(|a, b| a.0.cmp(&b.0));
+
+    if !dry_run {
+        for path in report.damaged.iter().map(|(path, _)| path).chain(&report.stale) {
+            fs::remove_file(path).with_context(|| format!("Failed to remove {}", path.display()))?;
+        }
+    }
+    Ok(report)
+}
+
+/// `lsix cache verify`: check the cache and print what was found
+pub fn run_verify(dry_run: bool) -> Result<()> {
+    let cache_dir = get_cache_dir()?;
+    let report = verify(&cache_dir, dry_run)?;
+    for (path, damage) in &report.damaged {
+        println!("{}: {}", path.display(), damage.label());
+    }
+    for path in &report.stale {
+        println!("{}: left by an interrupted write", path.display());
+    }
+    let problems = report.damaged.len() + report.stale.len();
+    let outcome = match (problems, dry_run) {
+        (0, _) => "all intact".to_string(),
+        (n, true) => format!("{} damaged (dry run, nothing removed)", n),
+        (n, false) => format!("{} damaged, removed; they are rebuilt when next shown", n),
+    };
+    eprintln!(
+        "Checked {} rendered rows and {} thumbnails in {}: {}",
+        report.rows,
+        report.thumbnails,
+        cache_dir.display(),
+        outcome
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn test_render_cache() {
+        let dir = TestDir::new("render-cache");
+        fs::create_dir_all(dir.join("thumbs")).unwrap();
+
+        let row = dir.join("a3f2e8b1c9d4");
+        write_entry(&row, b"\x1bPq#0;2;0;0;0\x1b\\").unwrap();
+        assert_eq!(read_entry(&row).unwrap(), b"\x1bPq#0;2;0;0;0\x1b\\");
+        assert_eq!(read_entry(&dir.join("ffff")), None);
+
+        // An interrupted write, a flipped byte, an entry without a footer
+        let data = fs::read(&row).unwrap();
+        let (cut, flipped, legacy) = (dir.join("1"), dir.join("2"), dir.join("3"));
+        fs::write(&cut, &data[..data.len() - 5]).unwrap();
+        let mut bad = data.clone();
+        bad[0] ^= 1;
+        fs::write(&flipped, &bad).unwrap();
+        fs::write(&legacy, "raw sixel").unwrap();
+        image::RgbaImage::new(2, 2).save(dir.join("thumbs/ok_256.png")).unwrap();
+        fs::write(dir.join("thumbs/bad_256.png"), "not a png").unwrap();
+        fs::write(dir.join("hash_index.json"), "{}").unwrap();
+
+        let report = verify(&dir, true).unwrap();
+        assert_eq!((report.rows, report.thumbnails), (4, 2));
+        let damage: Vec<_> = report.damaged.iter().map(|(_, damage)| *damage).collect();
+        assert_eq!(
+            damage,
+            [Damage::Truncated, Damage::Checksum, Damage::Truncated, Damage::Undecodable]
+        );
+        assert!(cut.exists());
+
+        verify(&dir, false).unwrap();
+        assert!(!cut.exists() && !flipped.exists() && !legacy.exists());
+        assert!(verify(&dir, false).unwrap().damaged.is_empty());
+        assert!(row.exists() && dir.join("hash_index.json").exists());
+
+        // Damage found while rendering is removed too
+        fs::write(&legacy, "raw sixel").unwrap();
+        assert_eq!(read_entry(&legacy), None);
+        assert!(!legacy.exists());
+    }
+}
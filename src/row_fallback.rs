@@ -0,0 +1,316 @@
+// Keeping the grid going when a row fails, instead of ending the listing
+// with an error.
+//
+// A row that can't be rendered (a decoder or ImageMagick failing, or its
+// pipe breaking) is tried again with fewer colors and half-size
+// thumbnails, then as Unicode blocks. On a terminal, every row of graphics
+// is followed by a Primary Device Attributes query: a terminal that doesn't
+// answer in the time the row's size allows choked on the graphics (an
+// unsupported or mangled sequence), so the sequence is ended and that row
+// and all after it are drawn as blocks.
+// What was downgraded is noted in the summary at the end of the run.
+
+use crate::image_proc::{Encoder, ImageConfig, ImageEntry};
+use crate::terminal::{self, GraphicsProtocol};
+use anyhow::Result;
+use std::io::{self, IsTerminal, Write};
+use std::sync::Mutex;
+
+/// Ends a graphics sequence the terminal may still be stuck in (ST)
+const END_SEQUENCE: &[u8] = b"\x1b\\";
+
+/// How long the terminal gets to answer after a row of graphics, plus
+/// `ANSWER_MS_PER_KB` for each KB of the row
+const ANSWER_TIMEOUT_MS: u64 = 500;
+
+/// Extra wait per KB of graphics, enough for a link of about 1 MB/s
+const ANSWER_MS_PER_KB: u64 = 1;
+
+/// Longest wait for an answer, however large the row
+const MAX_ANSWER_TIMEOUT_MS: u64 = 15_000;
+
+/// Fewest colors a reduced row is drawn with
+const MIN_REDUCED_COLORS: u32 = 16;
+
+/// Notes for the end-of-run summary
+static NOTES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// A step down from the configured settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Downgrade {
+    /// A quarter of the colors and half-size thumbnails
+    Reduced,
+    /// Unicode half blocks
+    Blocks,
+}
+
+/// `config` stepped down to `downgrade`. Both drop a shared palette and
+/// ImageMagick, which may be what failed.
+pub fn downgraded(config: &ImageConfig, downgrade: Downgrade) -> ImageConfig {
+    let mut config =
+        ImageConfig { shared_palette: None, encoder: Encoder::Native, ..config.clone() };
+    match downgrade {
+        Downgrade::Reduced => {
+            let colors = (config.num_colors / 4).max(MIN_REDUCED_COLORS);
+            config.num_colors = colors.min(config.num_colors);
+            config.tile_width = (config.tile_width / 2).max(1);
+            config.tile_height = (config.tile_height / 2).max(1);
+        }
+        Downgrade::Blocks => config.protocol = GraphicsProtocol::Blocks,
+    }
+    config
+}
+
+/// Renders a row of images with the given settings
+pub type RenderRow<'a> = &'a dyn Fn(&[ImageEntry], &ImageConfig) -> Result<Vec<u8>>;
+
+/// Asks the terminal whether it took a row of graphics of the given size
+pub type Answered<'a> = Box<dyn FnMut(usize) -> bool + 'a>;
+
+/// How long a terminal may take to answer after `bytes` of graphics
+fn answer_timeout_ms(bytes: usize) -> u64 {
+    let per_size = (bytes as u64 / 1024).saturating_mul(ANSWER_MS_PER_KB);
+    (ANSWER_TIMEOUT_MS + per_size).min(MAX_ANSWER_TIMEOUT_MS)
+}
+
+/// Writes grid rows, downgrading those that fail. One writer serves a whole
+/// listing, so once the terminal stops answering, later groups are drawn
+/// as blocks too.
+pub struct RowWriter<'a> {
+    render: RenderRow<'a>,
+    /// Asks the terminal whether it took the graphics; None when there is
+    /// no terminal to ask
+    answered: Option<Answered<'a>>,
+    rows: usize,
+    /// Row after which the terminal stopped answering
+    choked_at: Option<usize>,
+    reduced: usize,
+    blocks: usize,
+    skipped: usize,
+}
+
+impl<'a> RowWriter<'a> {
+    pub fn new(render: RenderRow<'a>, answered: Option<Answered<'a>>) -> Self {
+        RowWriter { render, answered, rows: 0, choked_at: None, reduced: 0, blocks: 0, skipped: 0 }
+    }
+
+    /// Writer for rows going to stdout, asking the terminal about each row
+    /// of graphics when stdout and stdin are one
+    pub fn for_stdout(render: RenderRow<'a>) -> Self {
+        let on_terminal = io::stdout().is_terminal() && io::stdin().is_terminal();
+        let answered = on_terminal.then(|| {
+            Box::new(|bytes| {
+                terminal::verify_graphics_consumed(answer_timeout_ms(bytes)).unwrap_or(true)
+            }) as Answered
+        });
+        RowWriter::new(render, answered)
+    }
+
+    /// Write the row of `images` that `rendered` holds the first rendering
+    /// of with `config`. A reader gone from the other end of a pipe ends
+    /// the output quietly: Ok(false) says to stop.
+    pub fn write_row(
+        &mut self,
+        out: &mut impl Write,
+        images: &[ImageEntry],
+        config: &ImageConfig,
+        rendered: Result<Vec<u8>>,
+    ) -> io::Result<bool> {
+        self.rows += 1;
+        let graphics = config.protocol != GraphicsProtocol::Blocks;
+        let data = if self.choked_at.is_some() && graphics {
+            self.blocks += 1;
+            let retried = self.rerender(images, config, rendered.err(), &[Downgrade::Blocks]);
+            retried.map(|(data, _)| data)
+        } else {
+            match rendered {
+                Ok(data) => Some(data),
+                Err(e) => {
+                    let steps: &[Downgrade] = if graphics {
+                        &[Downgrade::Reduced, Downgrade::Blocks]
+                    } else {
+                        &[Downgrade::Reduced]
+                    };
+                    let retried = self.rerender(images, config, Some(e), steps);
+                    match retried {
+                        Some((_, Downgrade::Reduced)) => self.reduced += 1,
+                        Some((_, Downgrade::Blocks)) => self.blocks += 1,
+                        None => {}
+                    }
+                    retried.map(|(data, _)| data)
+                }
+            }
+        };
+        let Some(data) = data else {
+            return Ok(true);
+        };
+        if !write_all(out, &data)? {
+            return Ok(false);
+        }
+
+        let is_graphics = graphics && self.choked_at.is_none();
+        let answered = match &mut self.answered {
+            Some(answered) if is_graphics && !data.is_empty() => answered(data.len()),
+            _ => true,
+        };
+        if !answered {
+            // Get the terminal out of the sequence it is stuck in and draw
+            // the row again where it can't choke
+            self.choked_at = Some(self.rows);
+            self.blocks += 1;
+            if !write_all(out, END_SEQUENCE)? {
+                return Ok(false);
+            }
+            if let Some((data, _)) = self.rerender(images, config, None, &[Downgrade::Blocks]) {
+                return write_all(out, &data);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Render `images` again with each of `steps` in turn until one works,
+    /// returning the step that did; None, after a warning, when none does
+    fn rerender(
+        &mut self,
+        images: &[ImageEntry],
+        config: &ImageConfig,
+        mut error: Option<anyhow::Error>,
+        steps: &[Downgrade],
+    ) -> Option<(Vec<u8>, Downgrade)> {
+        for &step in steps {
+            match (self.render)(images, &downgraded(config, step)) {
+                Ok(data) => return Some((data, step)),
+                Err(e) => error = Some(e),
+            }
+        }
+        self.skipped += 1;
+        if let Some(e) = error {
+            crate::diagnostics::warn(
+                crate::diagnostics::WarningKind::Decode,
+                &format!("Row {} could not be drawn: {:#}", self.rows, e),
+            );
+        }
+        None
+    }
+
+    /// What was downgraded, for the summary
+    pub fn notes(&self) -> Vec<String> {
+        let mut notes = Vec::new();
+        if self.reduced > 0 {
+            notes.push(format!(
+                "Rows that failed to render, drawn with fewer colors and smaller thumbnails: {}",
+                self.reduced
+            ));
+        }
+        match self.choked_at {
+            Some(row) => notes.push(format!(
+                "The terminal stopped answering after the graphics of row {}; rows drawn with \
+                 text blocks from there: {}. Try --blocks-fallback.",
+                row, self.blocks
+            )),
+            None if self.blocks > 0 => notes.push(format!(
+                "Rows that failed to render, drawn with text blocks: {}",
+                self.blocks
+            )),
+            None => {}
+        }
+        if self.skipped > 0 {
+            notes.push(format!("Rows that could not be drawn: {}", self.skipped));
+        }
+        notes
+    }
+
+    /// Keep the notes for `print_summary`
+    pub fn finish(self) {
+        NOTES.lock().unwrap().extend(self.notes());
+    }
+}
+
+/// Write `data` and flush, retrying writes the terminal wasn't ready for.
+/// Returns false when the reader has gone away.
+fn write_all(out: &mut impl Write, data: &[u8]) -> io::Result<bool> {
+    let mut rest = data;
+    while !rest.is_empty() {
+        match out.write(rest) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(n) => rest = &rest[n..],
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(false),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            // A terminal left in non-blocking mode takes large rows in parts
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    match out.flush() {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(false),
+        result => result.map(|()| true),
+    }
+}
+
+/// Print the notes of the listing's downgrades, once, at the end of a run
+pub fn print_summary() {
+    for note in NOTES.lock().unwrap().drain(..) {
+        eprintln!("Note: {}", note);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_proc::ImageConfig;
+    use std::cell::RefCell;
+
+    #[test]
+    fn test_row_fallback() {
+        let config = ImageConfig {
+            num_colors: 256,
+            protocol: GraphicsProtocol::Sixel,
+            encoder: Encoder::ImageMagick,
+            ..ImageConfig::from_terminal_width(800, 256, "black", "white")
+        };
+        let reduced = downgraded(&config, Downgrade::Reduced);
+        assert_eq!((reduced.num_colors, reduced.encoder), (64, Encoder::Native));
+        assert_eq!(reduced.tile_width, config.tile_width / 2);
+        assert_eq!(downgraded(&reduced, Downgrade::Reduced).num_colors, 16);
+
+        // Full settings fail; the reduced ones draw "R", blocks draw "B"
+        let render = |_: &[ImageEntry], config: &ImageConfig| -> Result<Vec<u8>> {
+            match (config.protocol, config.num_colors) {
+                (GraphicsProtocol::Blocks, _) => Ok(b"B".to_vec()),
+                (_, 64) => Ok(b"R".to_vec()),
+                _ => anyhow::bail!("convert: broken pipe"),
+            }
+        };
+        // The terminal answers the first row only
+        let answers = RefCell::new(vec![false, true]);
+        let mut writer =
+            RowWriter::new(&render, Some(Box::new(|_| answers.borrow_mut().pop().unwrap_or(true))));
+        let mut out = Vec::new();
+        assert!(writer.write_row(&mut out, &[], &config, Ok(b"S".to_vec())).unwrap());
+        assert!(writer.write_row(&mut out, &[], &config, Err(anyhow::anyhow!("x"))).unwrap());
+        assert!(writer.write_row(&mut out, &[], &config, Ok(b"S".to_vec())).unwrap());
+        assert_eq!(out, b"SR\x1b\\BB");
+        let notes = writer.notes();
+        assert!(notes[0].ends_with("thumbnails: 1"));
+        assert!(notes[1].contains("row 2; rows drawn with text blocks from there: 2."));
+
+        // A reader that went away ends the output without an error
+        struct Closed;
+        impl Write for Closed {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::BrokenPipe.into())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        assert!(!writer.write_row(&mut Closed, &[], &config, Ok(b"S".to_vec())).unwrap());
+
+        // Large rows get longer to be answered, up to a limit
+        assert_eq!(answer_timeout_ms(100), ANSWER_TIMEOUT_MS);
+        assert_eq!(answer_timeout_ms(2 << 20), ANSWER_TIMEOUT_MS + 2048);
+        assert_eq!(answer_timeout_ms(usize::MAX), MAX_ANSWER_TIMEOUT_MS);
+    }
+}
@@ -0,0 +1,399 @@
+// `lsix scrub`: copy images with privacy-sensitive metadata removed.
+// JPEG, PNG and WebP are edited in place (pixels untouched); other formats
+// are re-encoded, which drops all of their metadata.
+
+use anyhow::{Context, Result};
+use exif::{experimental::Writer, Context as ExifContext, Exif, Field, In, Tag, Value};
+use img_parts::{jpeg::markers, Bytes, DynImage, ImageEXIF};
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+
+/// Start of a JPEG APP1 segment (or PNG iTXt keyword) holding XMP
+const XMP_PREFIXES: [&[u8]; 3] = [
+    b"http://ns.adobe.com/xap/1.0/\0",
+    b"http://ns.adobe.com/xmp/extension/\0",
+    b"XML:com.adobe.xmp\0",
+];
+
+/// VP8X flag bits for the EXIF and XMP chunks
+const WEBP_FLAG_EXIF: u8 = 0x08;
+const WEBP_FLAG_XMP: u8 = 0x04;
+
+/// What to remove
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrubMode {
+    /// Only location data; other EXIF (camera, exposure, date) is kept
+    Gps,
+    /// EXIF, XMP, IPTC, comments and text chunks. Color profiles are kept.
+    All,
+}
+
+/// Per-file summary of what was (or would be) removed and kept
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ScrubReport {
+    pub removed: Vec<String>,
+    pub kept: Vec<String>,
+}
+
+/// Scrub `files` into `out` (or only preview with `dry_run`)
+pub fn run_scrub(files: &[String], out: Option<&Path>, mode: ScrubMode, dry_run: bool) -> Result<()> {
+    if let Some(out) = out.filter(|_| !dry_run) {
+        fs::create_dir_all(out).with_context(|| format!("Failed to create {}", out.display()))?;
+    }
+
+    let mut written = 0;
+    let mut failed = 0;
+    for path in files {
+        let result = fs::read(path)
+            .with_context(|| format!("Failed to read {}", path))
+            .and_then(|data| scrub_bytes(data, mode));
+
+        let (data, report) = match result {
+            Ok(result) => result,
+            Err(e) => {
+                eprintln!("✗ {}: {:#}", path, e);
+                failed += 1;
+                continue;
+            }
+        };
+
+        println!("{}", path);
+        if report.removed.is_empty() {
+            println!("  (nothing to remove)");
+        }
+        for item in &report.removed {
+            println!("  - {}", item);
+        }
+        for item in &report.kept {
+            println!("  = {}", item);
+        }
+
+        let Some(out) = out.filter(|_| !dry_run) else {
+            continue;
+        };
+        let target = out.join(Path::new(path).file_name().context("Invalid file name")?);
+        if target.exists() {
+            eprintln!("✗ {} already exists, skipped", target.display());
+            failed += 1;
+            continue;
+        }
+        fs::write(&target, data).with_context(|| format!("Failed to write {}", target.display()))?;
+        written += 1;
+    }
+
+    match out {
+        Some(out) if !dry_run => eprintln!("\n✓ Wrote {} scrubbed image(s) to {}", written, out.display()),
+        _ => eprintln!("\nDry run: no files written"),
+    }
+    if failed > 0 {
+        anyhow::bail!("{} file(s) could not be scrubbed", failed);
+    }
+    Ok(())
+}
+
+/// Remove metadata from an encoded image. Returns the new file contents.
+pub fn scrub_bytes(data: Vec<u8>, mode: ScrubMode) -> Result<(Vec<u8>, ScrubReport)> {
+    let mut report = ScrubReport::default();
+
+    let image = match DynImage::from_bytes(Bytes::from(data.clone())) {
+        Ok(Some(image)) => image,
+        Ok(None) => return reencode(&data),
+        Err(e) => anyhow::bail!("Failed to parse image: {}", e),
+    };
+
+    let image = match image {
+        DynImage::Jpeg(mut jpeg) => {
+            scrub_exif(&mut jpeg, mode, &mut report)?;
+            jpeg.segments_mut().retain(|segment| {
+                let contents = segment.contents();
+                let label = match segment.marker() {
+                    markers::APP1 if is_xmp(contents) => xmp_label(contents, mode),
+                    markers::APP13 if mode == ScrubMode::All => Some("IPTC/Photoshop data".to_string()),
+                    markers::COM if mode == ScrubMode::All => Some("comment".to_string()),
+                    _ => None,
+                };
+                match label {
+                    Some(label) => {
+                        report.removed.push(label);
+                        false
+                    }
+                    None => true,
+                }
+            });
+            DynImage::Jpeg(jpeg)
+        }
+        DynImage::Png(mut png) => {
+            scrub_exif(&mut png, mode, &mut report)?;
+            png.chunks_mut().retain(|chunk| {
+                let contents = chunk.contents();
+                let label = match &chunk.kind() {
+                    b"iTXt" if is_xmp(contents) => xmp_label(contents, mode),
+                    b"tEXt" | b"zTXt" | b"iTXt" if mode == ScrubMode::All => Some(format!(
+                        "text chunk \"{}\"",
+                        String::from_utf8_lossy(contents.split(|&b| b == 0).next().unwrap_or(&[]))
+                    )),
+                    b"tIME" if mode == ScrubMode::All => Some("modification time".to_string()),
+                    _ => None,
+                };
+                match label {
+                    Some(label) => {
+                        report.removed.push(label);
+                        false
+                    }
+                    None => true,
+                }
+            });
+            DynImage::Png(png)
+        }
+        DynImage::WebP(mut webp) => {
+            scrub_exif(&mut webp, mode, &mut report)?;
+            let xmp_label = webp
+                .chunk_by_id(*b"XMP ")
+                .and_then(|chunk| chunk.content().data())
+                .and_then(|data| xmp_label(data, mode));
+            if let Some(label) = xmp_label {
+                webp.remove_chunks_by_id(*b"XMP ");
+                report.removed.push(label);
+            }
+            clear_webp_flags(&mut webp);
+            DynImage::WebP(webp)
+        }
+    };
+
+    let mut out = Vec::with_capacity(data.len());
+    image.encoder().write_to(&mut out)?;
+    Ok((out, report))
+}
+
+/// Formats img-parts can't edit: decode and encode again without metadata
+fn reencode(data: &[u8]) -> Result<(Vec<u8>, ScrubReport)> {
+    let format = image::guess_format(data).context("Unknown image format")?;
+    if format == image::ImageFormat::Gif {
+        anyhow::bail!("GIF is not supported (re-encoding would drop the animation)");
+    }
+
+    let img = image::load_from_memory_with_format(data, format)?;
+    let mut out = Vec::new();
+    img.write_to(&mut Cursor::new(&mut out), format)?;
+
+    let report = ScrubReport {
+        removed: vec![format!("all metadata ({:?} re-encoded)", format)],
+        kept: Vec::new(),
+    };
+    Ok((out, report))
+}
+
+/// Drop the EXIF block (All) or rewrite it without GPS tags and the
+/// embedded thumbnail (Gps)
+fn scrub_exif<I: ImageEXIF>(image: &mut I, mode: ScrubMode, report: &mut ScrubReport) -> Result<()> {
+    let Some(raw) = image.exif() else {
+        return Ok(());
+    };
+    let exif = exif::Reader::new().read_raw(raw.to_vec());
+
+    if mode == ScrubMode::All {
+        let detail = match &exif {
+            Ok(exif) => match gps_position(exif) {
+                Some((lat, lon)) => format!("{} tags, incl. GPS {}", exif.fields().len(), format_position(lat, lon)),
+                None => format!("{} tags", exif.fields().len()),
+            },
+            Err(_) => "unreadable".to_string(),
+        };
+        report.removed.push(format!("EXIF ({})", detail));
+        image.set_exif(None);
+        return Ok(());
+    }
+
+    let exif = exif.context("EXIF data is unreadable; use --strip-all")?;
+    let gps_tags = exif
+        .fields()
+        .filter(|f| f.tag.context() == ExifContext::Gps)
+        .count();
+    if gps_tags == 0 {
+        report.kept.push(format!("EXIF ({} tags, no GPS)", exif.fields().len()));
+        return Ok(());
+    }
+
+    let kept: Vec<&Field> = exif
+        .fields()
+        .filter(|f| f.ifd_num == In::PRIMARY && f.tag.context() != ExifContext::Gps)
+        .collect();
+
+    let mut writer = Writer::new();
+    for field in &kept {
+        writer.push_field(field);
+    }
+    let mut buf = Cursor::new(Vec::new());
+    writer
+        .write(&mut buf, exif.little_endian())
+        .context("Failed to rewrite EXIF data")?;
+    image.set_exif(Some(Bytes::from(buf.into_inner())));
+
+    let position = gps_position(&exif)
+        .map(|(lat, lon)| format!(" {}", format_position(lat, lon)))
+        .unwrap_or_default();
+    report.removed.push(format!("GPS{} ({} tags)", position, gps_tags));
+    if exif.fields().any(|f| f.ifd_num == In::THUMBNAIL) {
+        report.removed.push("EXIF thumbnail".to_string());
+    }
+    report.kept.push(format!("EXIF ({} tags)", kept.len()));
+    Ok(())
+}
+
+fn is_xmp(contents: &[u8]) -> bool {
+    XMP_PREFIXES.iter().any(|prefix| contents.starts_with(prefix))
+}
+
+/// Label for an XMP packet that should be removed in `mode`
+fn xmp_label(contents: &[u8], mode: ScrubMode) -> Option<String> {
+    let has_gps = [b"GPSLatitude".as_slice(), b"GPSLongitude"]
+        .iter()
+        .any(|needle| contents.windows(needle.len()).any(|w| w == *needle));
+
+    match mode {
+        ScrubMode::All => Some("XMP".to_string()),
+        ScrubMode::Gps if has_gps => Some("XMP (contains GPS)".to_string()),
+        ScrubMode::Gps => None,
+    }
+}
+
+/// Keep the VP8X feature flags in sync with the remaining chunks
+fn clear_webp_flags(webp: &mut img_parts::webp::WebP) {
+    let has_exif = webp.has_chunk(*b"EXIF");
+    let has_xmp = webp.has_chunk(*b"XMP ");
+
+    for chunk in webp.chunks_mut().iter_mut().filter(|c| c.id() == *b"VP8X") {
+        if let img_parts::riff::RiffContent::Data(data) = chunk.content_mut() {
+            let mut bytes = data.to_vec();
+            if let Some(flags) = bytes.first_mut() {
+                if !has_exif {
+                    *flags &= !WEBP_FLAG_EXIF;
+                }
+                if !has_xmp {
+                    *flags &= !WEBP_FLAG_XMP;
+                }
+            }
+            *data = Bytes::from(bytes);
+        }
+    }
+}
+
+/// Decimal (latitude, longitude) from the GPS tags, if present
+pub fn gps_position(exif: &Exif) -> Option<(f64, f64)> {
+    let coordinate = |tag: Tag, ref_tag: Tag, negative: u8| -> Option<f64> {
+        let value = match &exif.get_field(tag, In::PRIMARY)?.value {
+            Value::Rational(parts) if parts.len() == 3 => {
+                parts[0].to_f64() + parts[1].to_f64() / 60.0 + parts[2].to_f64() / 3600.0
+            }
+            _ => return None,
+        };
+        let is_negative = match &exif.get_field(ref_tag, In::PRIMARY).map(|f| &f.value) {
+            Some(Value::Ascii(refs)) => refs.first().and_then(|r| r.first()) == Some(&negative),
+            _ => false,
+        };
+        Some(if is_negative { -value } else { value })
+    };
+
+    Some((
+        coordinate(Tag::GPSLatitude, Tag::GPSLatitudeRef, b'S')?,
+        coordinate(Tag::GPSLongitude, Tag::GPSLongitudeRef, b'W')?,
+    ))
+}
+
+pub fn format_position(lat: f64, lon: f64) -> String {
+    format!(
+        "{:.4}{} {:.4}{}",
+        lat.abs(),
+        if lat < 0.0 { 'S' } else { 'N' },
+        lon.abs(),
+        if lon < 0.0 { 'W' } else { 'E' }
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exif::Rational;
+
+    fn jpeg_with_exif() -> Vec<u8> {
+        let img = image::RgbImage::from_pixel(8, 8, image::Rgb([200, 100, 50]));
+        let mut jpeg = Vec::new();
+        image::DynamicImage::ImageRgb8(img)
+            .write_to(&mut Cursor::new(&mut jpeg), image::ImageFormat::Jpeg)
+            .unwrap();
+
+        let rational = |num| Rational { num, denom: 1 };
+        let fields = [
+            Field {
+                tag: Tag::Make,
+                ifd_num: In::PRIMARY,
+                value: Value::Ascii(vec![b"Camera".to_vec()]),
+            },
+            Field {
+                tag: Tag::GPSLatitudeRef,
+                ifd_num: In::PRIMARY,
+                value: Value::Ascii(vec![b"N".to_vec()]),
+            },
+            Field {
+                tag: Tag::GPSLatitude,
+                ifd_num: In::PRIMARY,
+                value: Value::Rational(vec![rational(37), rational(46), rational(30)]),
+            },
+            Field {
+                tag: Tag::GPSLongitudeRef,
+                ifd_num: In::PRIMARY,
+                value: Value::Ascii(vec![b"W".to_vec()]),
+            },
+            Field {
+                tag: Tag::GPSLongitude,
+                ifd_num: In::PRIMARY,
+                value: Value::Rational(vec![rational(122), rational(25), rational(0)]),
+            },
+        ];
+        let mut writer = Writer::new();
+        for field in &fields {
+            writer.push_field(field);
+        }
+        let mut exif = Cursor::new(Vec::new());
+        writer.write(&mut exif, false).unwrap();
+
+        let mut image = DynImage::from_bytes(Bytes::from(jpeg)).unwrap().unwrap();
+        image.set_exif(Some(Bytes::from(exif.into_inner())));
+        let mut out = Vec::new();
+        image.encoder().write_to(&mut out).unwrap();
+        out
+    }
+
+    fn read_exif(data: &[u8]) -> Option<Exif> {
+        let image = DynImage::from_bytes(Bytes::copy_from_slice(data)).unwrap().unwrap();
+        exif::Reader::new().read_raw(image.exif()?.to_vec()).ok()
+    }
+
+    #[test]
+    fn test_strip_gps_keeps_other_exif() {
+        let (data, report) = scrub_bytes(jpeg_with_exif(), ScrubMode::Gps).unwrap();
+
+        let exif = read_exif(&data).unwrap();
+        assert!(exif.get_field(Tag::Make, In::PRIMARY).is_some());
+        assert!(exif.fields().all(|f| f.tag.context() != ExifContext::Gps));
+        assert_eq!(report.removed, vec!["GPS 37.7750N 122.4167W (4 tags)"]);
+        assert!(image::load_from_memory(&data).is_ok());
+    }
+
+    #[test]
+    fn test_strip_all_removes_exif() {
+        let (data, report) = scrub_bytes(jpeg_with_exif(), ScrubMode::All).unwrap();
+
+        assert!(read_exif(&data).is_none());
+        assert!(report.removed[0].starts_with("EXIF (") && report.removed[0].contains("GPS"));
+    }
+
+    #[test]
+    fn test_xmp_label() {
+        let xmp = b"http://ns.adobe.com/xap/1.0/\0<x:xmpmeta><exif:GPSLatitude>1</exif:GPSLatitude>";
+        assert!(is_xmp(xmp));
+        assert_eq!(xmp_label(xmp, ScrubMode::Gps).as_deref(), Some("XMP (contains GPS)"));
+        assert_eq!(xmp_label(b"http://ns.adobe.com/xap/1.0/\0<x/>", ScrubMode::Gps), None);
+    }
+}
@@ -0,0 +1,301 @@
+// Pure-Rust SIXEL encoder: quantizes an RGBA image to an adaptive palette
+// (median cut) and emits a SIXEL stream without ImageMagick's `convert`.
+
+use image::{Rgb, RgbaImage};
+use std::collections::HashMap;
+use std::io::Write;
+
+/// Maximum number of color registers we ever emit
+pub const MAX_SIXEL_COLORS: usize = 256;
+
+/// Upper bound on pixels fed into median cut; larger images are sampled
+const MAX_QUANTIZE_SAMPLES: usize = 64 * 1024;
+
+/// An adaptive color palette with a nearest-color lookup
+#[derive(Debug, Clone)]
+pub struct Palette {
+    pub colors: Vec<Rgb<u8>>,
+    lookup: HashMap<[u8; 3], u8>,
+}
+
+impl Palette {
+    /// Build a palette of at most `max_colors` colors using median cut
+    pub fn median_cut(pixels: &[[u8; 3]], max_colors: usize) -> Palette {
+        let max_colors = max_colors.clamp(1, MAX_SIXEL_COLORS);
+
+        // Sample evenly so huge rows don't make quantization quadratic-ish
+        let step = (pixels.len() / MAX_QUANTIZE_SAMPLES).max(1);
+        let samples: Vec<[u8; 3]> = pixels.iter().step_by(step).copied().collect();
+
+        let mut boxes: Vec<Vec<[u8; 3]>> = if samples.is_empty() {
+            Vec::new()
+        } else {
+            vec![samples]
+        };
+
+        while boxes.len() < max_colors {
+            // Split the box with the widest channel range
+            let Some((idx, channel, range)) = boxes
+                .iter()
+                .enumerate()
+                .map(|(i, b)| {
+                    let (channel, range) = widest_channel(b);
+                    (i, channel, range)
+                })
+                .max_by_key(|&(_, _, range)| range)
+            else {
+                break;
+            };
+
+            if range == 0 {
+                // Every remaining box is a single color
+                break;
+            }
+
+            let mut colors = boxes.swap_remove(idx);
+            colors.sort_unstable_by_key(|c| c[channel]);
+            let upper = colors.split_off(colors.len() / 2);
+            boxes.push(colors);
+            boxes.push(upper);
+        }
+
+        let colors = boxes.iter().map(|b| average_color(b)).collect();
+        Palette {
+            colors,
+            lookup: HashMap::new(),
+        }
+    }
+
+    /// Index of the palette color closest to `color`
+    pub fn index_of(&mut self, color: [u8; 3]) -> u8 {
+        if let Some(&idx) = self.lookup.get(&color) {
+            return idx;
+        }
+
+        let idx = self
+            .colors
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, c)| {
+                let dr = c[0] as i32 - color[0] as i32;
+                let dg = c[1] as i32 - color[1] as i32;
+                let db = c[2] as i32 - color[2] as i32;
+                dr * dr + dg * dg + db * db
+            })
+            .map(|(i, _)| i as u8)
+            .unwrap_or(0);
+
+        self.lookup.insert(color, idx);
+        idx
+    }
+}
+
+/// Channel (0=R, 1=G, 2=B) with the largest spread, and that spread
+fn widest_channel(colors: &[[u8; 3]]) -> (usize, u8) {
+    let mut min = [u8::MAX; 3];
+    let mut max = [u8::MIN; 3];
+    for color in colors {
+        for c in 0..3 {
+            min[c] = min[c].min(color[c]);
+            max[c] = max[c].max(color[c]);
+        }
+    }
+
+    (0..3)
+        .map(|c| (c, max[c].saturating_sub(min[c])))
+        .max_by_key(|&(_, range)| range)
+        .unwrap_or((0, 0))
+}
+
+fn average_color(colors: &[[u8; 3]]) -> Rgb<u8> {
+    if colors.is_empty() {
+        return Rgb([0, 0, 0]);
+    }
+
+    let mut sum = [0u64; 3];
+    for color in colors {
+        for c in 0..3 {
+            sum[c] += color[c] as u64;
+        }
+    }
+    let n = colors.len() as u64;
+    Rgb([(sum[0] / n) as u8, (sum[1] / n) as u8, (sum[2] / n) as u8])
+}
+
+/// Flatten an RGBA image onto `background` and return its RGB pixels
+pub(crate) fn flatten(img: &RgbaImage, background: [u8; 3]) -> Vec<[u8; 3]> {
+    img.pixels()
+        .map(|p| {
+            let alpha = p[3] as u32;
+            let mut out = [0u8; 3];
+            for c in 0..3 {
+                out[c] = ((p[c] as u32 * alpha + background[c] as u32 * (255 - alpha)) / 255) as u8;
+            }
+            out
+        })
+        .collect()
+}
+
+/// Encode an image as SIXEL using at most `max_colors` color registers.
+/// Transparent pixels are blended onto `background`.
+pub fn encode(img: &RgbaImage, max_colors: u32, background: [u8; 3]) -> Vec<u8> {
+    let pixels = flatten(img, background);
+    let mut palette = Palette::median_cut(&pixels, max_colors as usize);
+    encode_with_palette(img.width(), img.height(), &pixels, &mut palette)
+}
+
+/// Encode an image as SIXEL with a palette built beforehand, such as one
+/// shared by every row of a listing
+pub fn encode_with(img: &RgbaImage, palette: &Palette, background: [u8; 3]) -> Vec<u8> {
+    let pixels = flatten(img, background);
+    encode_with_palette(img.width(), img.height(), &pixels, &mut palette.clone())
+}
+
+/// Encode already-flattened RGB pixels with the given palette
+fn encode_with_palette(width: u32, height: u32, pixels: &[[u8; 3]], palette: &mut Palette) -> Vec<u8> {
+    let width = width as usize;
+    let height = height as usize;
+    let mut out = Vec::with_capacity(width * height / 2);
+
+    // DCS with P2=1 (unset pixels keep the background), then raster attributes
+    write!(out, "\x1bP0;1;0q\"1;1;{};{}", width, height).ok();
+
+    // Color registers use percentages in SIXEL
+    for (i, color) in palette.colors.iter().enumerate() {
+        write!(
+            out,
+            "#{};2;{};{};{}",
+            i,
+            color[0] as u32 * 100 / 255,
+            color[1] as u32 * 100 / 255,
+            color[2] as u32 * 100 / 255
+        )
+        .ok();
+    }
+
+    let indices: Vec<u8> = pixels.iter().map(|&p| palette.index_of(p)).collect();
+    let num_colors = palette.colors.len();
+
+    // Each band covers 6 pixel rows; per-color bit rows are allocated on first use
+    let mut band_bits: Vec<Vec<u8>> = vec![Vec::new(); num_colors];
+    let mut in_band = vec![false; num_colors];
+    let mut used: Vec<usize> = Vec::new();
+
+    for band_top in (0..height).step_by(6) {
+        let band_height = (height - band_top).min(6);
+
+        for &color in &used {
+            band_bits[color].iter_mut().for_each(|b| *b = 0);
+            in_band[color] = false;
+        }
+        used.clear();
+
+        for row in 0..band_height {
+            let row_start = (band_top + row) * width;
+            for x in 0..width {
+                let color = indices[row_start + x] as usize;
+                if !in_band[color] {
+                    in_band[color] = true;
+                    used.push(color);
+                    if band_bits[color].is_empty() {
+                        band_bits[color] = vec![0; width];
+                    }
+                }
+                band_bits[color][x] |= 1 << row;
+            }
+        }
+
+        for (n, &color) in used.iter().enumerate() {
+            if n > 0 {
+                // Graphics carriage return: overprint the same band with the next color
+                out.push(b'$');
+            }
+            write!(out, "#{}", color).ok();
+            write_run_length(&mut out, &band_bits[color]);
+        }
+
+        // Graphics new line: advance to the next band
+        out.push(b'-');
+    }
+
+    // String terminator
+    out.extend_from_slice(b"\x1b\\");
+    out
+}
+
+/// Write one color's sixel characters for a band, run-length encoded
+fn write_run_length(out: &mut Vec<u8>, bits: &[u8]) {
+    let mut i = 0;
+    while i < bits.len() {
+        let value = bits[i];
+        let mut run = 1;
+        while i + run < bits.len() && bits[i + run] == value {
+            run += 1;
+        }
+
+        let ch = b'?' + value;
+        if run > 3 {
+            write!(out, "!{}", run).ok();
+            out.push(ch);
+        } else {
+            out.extend(std::iter::repeat_n(ch, run));
+        }
+        i += run;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn test_median_cut_respects_color_cap() {
+        let pixels: Vec<[u8; 3]> = (0..=255u8).map(|v| [v, 255 - v, v / 2]).collect();
+        let palette = Palette::median_cut(&pixels, 16);
+        assert!(palette.colors.len() <= 16);
+        assert!(!palette.colors.is_empty());
+    }
+
+    #[test]
+    fn test_median_cut_single_color() {
+        let pixels = vec![[10, 20, 30]; 100];
+        let palette = Palette::median_cut(&pixels, 256);
+        assert_eq!(palette.colors, vec![Rgb([10, 20, 30])]);
+    }
+
+    #[test]
+    fn test_encode_structure() {
+        let mut img = RgbaImage::from_pixel(8, 7, Rgba([255, 0, 0, 255]));
+        img.put_pixel(0, 6, Rgba([0, 0, 255, 255]));
+
+        let sixel = String::from_utf8(encode(&img, 256, [0, 0, 0])).unwrap();
+        assert!(sixel.starts_with("\x1bP0;1;0q\"1;1;8;7"));
+        assert!(sixel.ends_with("\x1b\\"));
+        // Two bands for seven rows
+        assert_eq!(sixel.matches('-').count(), 2);
+        // Red and blue registers
+        assert!(sixel.contains(";2;100;0;0"));
+        assert!(sixel.contains(";2;0;0;100"));
+    }
+
+    #[test]
+    fn test_encode_with_shared_palette() {
+        let pixels: Vec<[u8; 3]> = vec![[255, 0, 0], [0, 255, 0], [0, 0, 255]];
+        let palette = Palette::median_cut(&pixels, 4);
+
+        // Registers for colors the row doesn't use are still defined, so
+        // every row sharing the palette shows the same colors
+        let img = RgbaImage::from_pixel(4, 4, Rgba([250, 5, 5, 255]));
+        let sixel = String::from_utf8(encode_with(&img, &palette, [0, 0, 0])).unwrap();
+        assert_eq!(sixel.matches(";2;").count(), 3);
+        assert!(sixel.contains(";2;0;100;0"));
+    }
+
+    #[test]
+    fn test_run_length_encoding() {
+        let mut out = Vec::new();
+        write_run_length(&mut out, &[63, 63, 63, 63, 63, 1]);
+        assert_eq!(String::from_utf8(out).unwrap(), "!5~@");
+    }
+}
@@ -0,0 +1,428 @@
+// Display order for image lists (--sort, --reverse), shared by the grid and
+// the TUI. Names compare naturally: digit runs by value, so img2.jpg sorts
+// before img10.jpg.
+//
+// --sort takes a comma-separated chain of keys, each optionally followed by
+// asc or desc, e.g. "tag:rating desc, exif-date asc, name". Later keys only
+// break ties of earlier ones, and images missing a key's value go after
+// those that have it, whichever the direction.
+
+use crate::ai_tagging::load_cached_tags;
+use anyhow::Result;
+use rayon::prelude::*;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// What images are ordered by
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SortKey {
+    /// File name, A to Z (natural order)
+    Name,
+    /// Modification time, newest first
+    Mtime,
+    /// File size, largest first
+    Size,
+    /// Pixel count, largest first
+    Resolution,
+    /// Quality score, best first
+    Quality,
+    /// EXIF capture time (DateTimeOriginal), newest first
+    ExifDate,
+    /// Value of a `NAME:VALUE` tag in the tag cache, in natural order;
+    /// images tagged just `NAME` come before untagged ones
+    Tag(String),
+}
+
+impl SortKey {
+    /// Whether the key orders largest/newest first unless told otherwise
+    fn descending_by_default(&self) -> bool {
+        !matches!(self, SortKey::Name | SortKey::Tag(_))
+    }
+
+    /// The key as `parse_sort_key` reads it
+    pub fn name(&self) -> String {
+        match self {
+            SortKey::Name => "name".to_string(),
+            SortKey::Mtime => "mtime".to_string(),
+            SortKey::Size => "size".to_string(),
+            SortKey::Resolution => "resolution".to_string(),
+            SortKey::Quality => "quality".to_string(),
+            SortKey::ExifDate => "exif-date".to_string(),
+            SortKey::Tag(tag) => format!("tag:{}", tag),
+        }
+    }
+}
+
+/// One link of a sort chain: a key and its direction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortSpec {
+    pub key: SortKey,
+    pub descending: bool,
+}
+
+impl SortSpec {
+    /// `key` in its default direction
+    pub fn new(key: SortKey) -> SortSpec {
+        let descending = key.descending_by_default();
+        SortSpec { key, descending }
+    }
+
+    /// The spec as `parse_sort_order` reads it, e.g. `mtime desc`
+    pub fn label(&self) -> String {
+        format!("{} {}", self.key.name(), if self.descending { "desc" } else { "asc" })
+    }
+}
+
+/// Parse sort key from string
+pub fn parse_sort_key(s: &str) -> Result<SortKey> {
+    if let Some(tag) = s.strip_prefix("tag:") {
+        let tag = tag.trim().to_lowercase();
+        if tag.is_empty() {
+            anyhow::bail!("Invalid sort key: {}. Use tag:NAME", s);
+        }
+        return Ok(SortKey::Tag(tag));
+    }
+    match s.to_lowercase().as_str() {
+        "name" => Ok(SortKey::Name),
+        "mtime" | "time" => Ok(SortKey::Mtime),
+        "size" => Ok(SortKey::Size),
+        "resolution" => Ok(SortKey::Resolution),
+        "quality" => Ok(SortKey::Quality),
+        "exif-date" | "exif_date" => Ok(SortKey::ExifDate),
+        _ => anyhow::bail!(
+            "Invalid sort key: {}. Use: name, mtime, size, resolution, quality, exif-date, tag:NAME",
+            s
+        ),
+    }
+}
+
+/// Parse a sort chain such as `"tag:rating desc, exif-date asc, name"`
+pub fn parse_sort_order(s: &str) -> Result<Vec<SortSpec>> {
+    let mut order = Vec::new();
+    for part in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let mut words = part.split_whitespace();
+        let key = parse_sort_key(words.next().unwrap_or_default())?;
+        let descending = match words.next().map(str::to_lowercase).as_deref() {
+            None => key.descending_by_default(),
+            Some("asc") => false,
+            Some("desc") => true,
+            Some(other) => anyhow::bail!("Invalid sort direction: {}. Use asc or desc", other),
+        };
+        if let Some(extra) = words.next() {
+            anyhow::bail!("Unexpected '{}' in sort key: {}", extra, part);
+        }
+        order.push(SortSpec { key, descending });
+    }
+    if order.is_empty() {
+        anyhow::bail!("Empty sort order");
+    }
+    Ok(order)
+}
+
+/// Compare strings case-insensitively with digit runs compared by numeric
+/// value; strings equal under that order fall back to a plain comparison
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    natural_cmp_folded(a, b).then_with(|| a.cmp(b))
+}
+
+fn natural_cmp_folded(a: &str, b: &str) -> Ordering {
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+
+    loop {
+        match (a_chars.peek().copied(), b_chars.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(ca), Some(cb)) if ca.is_ascii_digit() && cb.is_ascii_digit() => {
+                let mut a_num = String::new();
+                while let Some(c) = a_chars.peek().filter(|c| c.is_ascii_digit()) {
+                    a_num.push(*c);
+                    a_chars.next();
+                }
+                let mut b_num = String::new();
+                while let Some(c) = b_chars.peek().filter(|c| c.is_ascii_digit()) {
+                    b_num.push(*c);
+                    b_chars.next();
+                }
+
+                // Compare by value without overflowing: strip leading zeros,
+                // then a longer run is larger
+                let a_trim = a_num.trim_start_matches('0');
+                let b_trim = b_num.trim_start_matches('0');
+                let ord = a_trim.len().cmp(&b_trim.len()).then_with(|| a_trim.cmp(b_trim));
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+            }
+            (Some(ca), Some(cb)) => {
+                let ord = ca.to_lowercase().cmp(cb.to_lowercase());
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+                a_chars.next();
+                b_chars.next();
+            }
+        }
+    }
+}
+
+/// Natural order of file names, then of full paths
+fn name_cmp(a: &str, b: &str) -> Ordering {
+    let file_name = |p: &str| {
+        Path::new(p)
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| p.to_string())
+    };
+    natural_cmp(&file_name(a), &file_name(b)).then_with(|| natural_cmp(a, b))
+}
+
+/// Sort value of one image under one key
+#[derive(Debug, Clone, PartialEq)]
+enum SortValue {
+    Number(f64),
+    Text(String),
+}
+
+impl SortValue {
+    /// Tag values that read as numbers compare by value
+    fn parse(value: &str) -> SortValue {
+        value
+            .parse::<f64>()
+            .map(SortValue::Number)
+            .unwrap_or_else(|_| SortValue::Text(value.to_string()))
+    }
+
+    fn compare(&self, other: &SortValue) -> Ordering {
+        match (self, other) {
+            (SortValue::Number(a), SortValue::Number(b)) => a.total_cmp(b),
+            (SortValue::Text(a), SortValue::Text(b)) => natural_cmp(a, b),
+            (SortValue::Number(_), SortValue::Text(_)) => Ordering::Less,
+            (SortValue::Text(_), SortValue::Number(_)) => Ordering::Greater,
+        }
+    }
+}
+
+/// EXIF DateTimeOriginal of `file` as seconds, ignoring the time zone
+fn exif_timestamp(file: &str) -> Option<f64> {
+    let mut reader = std::io::BufReader::new(std::fs::File::open(file).ok()?);
+    let exif = exif::Reader::new().read_from_container(&mut reader).ok()?;
+    let field = exif.get_field(exif::Tag::DateTimeOriginal, exif::In::PRIMARY)?;
+    let exif::Value::Ascii(ref values) = field.value else {
+        return None;
+    };
+    let date = exif::DateTime::from_ascii(values.first()?).ok()?;
+    let time =
+        chrono::NaiveDate::from_ymd_opt(date.year as i32, date.month as u32, date.day as u32)?
+            .and_hms_opt(date.hour as u32, date.minute as u32, date.second as u32)?;
+    Some(time.and_utc().timestamp() as f64)
+}
+
+/// Value of tag `name` among `tags`: the `VALUE` of a `name:VALUE` (or
+/// `name=VALUE`) tag, or an empty text for a bare `name` tag
+fn tag_value(tags: &[String], name: &str) -> Option<SortValue> {
+    let valued = tags.iter().find_map(|tag| {
+        let value = tag.strip_prefix(name)?;
+        value.strip_prefix(':').or_else(|| value.strip_prefix('='))
+    });
+    match valued {
+        Some(value) => Some(SortValue::parse(value.trim())),
+        None => tags
+            .iter()
+            .any(|tag| tag == name)
+            .then(|| SortValue::Text(String::new())),
+    }
+}
+
+/// Sort value of `path` under `key`; `None` if it can't be read. `tags` are
+/// the image's cached tags.
+fn sort_value(
+    path: &str,
+    key: &SortKey,
+    quality: Option<&HashMap<String, f32>>,
+    tags: &[String],
+) -> Option<SortValue> {
+    let file = path.trim_end_matches("[0]");
+    let number = match key {
+        SortKey::Mtime => std::fs::metadata(file)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|d| d.as_secs_f64()),
+        SortKey::Size => std::fs::metadata(file).ok().map(|m| m.len() as f64),
+        SortKey::Resolution => image::image_dimensions(file)
+            .ok()
+            .map(|(w, h)| w as f64 * h as f64),
+        SortKey::Quality => quality
+            .and_then(|scores| scores.get(path))
+            .map(|s| *s as f64),
+        SortKey::ExifDate => exif_timestamp(file),
+        SortKey::Tag(name) => return tag_value(tags, name),
+        SortKey::Name => None,
+    };
+    number.map(SortValue::Number)
+}
+
+/// Sort `paths` in place by `key`. Ties fall back to name order and files
+/// whose key can't be read go last; `reverse` flips the whole result.
+/// `quality` supplies scores for `SortKey::Quality`.
+pub fn sort_paths(
+    paths: &mut [String],
+    key: SortKey,
+    reverse: bool,
+    quality: Option<&HashMap<String, f32>>,
+) {
+    sort_paths_by(paths, &[SortSpec::new(key)], reverse, quality, None);
+}
+
+/// Sort `paths` in place by the chain `order`, each key breaking the ties
+/// of the one before and name order breaking the rest. Files missing a
+/// key's value go after the others for that key; `reverse` flips the whole
+/// result. `quality` supplies scores for `SortKey::Quality` and
+/// `tag_cache` the tags for `SortKey::Tag`.
+pub fn sort_paths_by(
+    paths: &mut [String],
+    order: &[SortSpec],
+    reverse: bool,
+    quality: Option<&HashMap<String, f32>>,
+    tag_cache: Option<&Path>,
+) {
+    let needs_tags = order.iter().any(|spec| matches!(spec.key, SortKey::Tag(_)));
+    let values: HashMap<String, Vec<Option<SortValue>>> = paths
+        .par_iter()
+        .map(|path| {
+            let tags = tag_cache
+                .filter(|_| needs_tags)
+                .and_then(|dir| load_cached_tags(dir, path).ok())
+                .map(|t| t.tags)
+                .unwrap_or_default();
+            let values = order
+                .iter()
+                .map(|spec| sort_value(path, &spec.key, quality, &tags))
+                .collect();
+            (path.clone(), values)
+        })
+        .collect();
+
+    paths.sort_by(|a, b| {
+        order
+            .iter()
+            .enumerate()
+            .map(|(i, spec)| {
+                let ord = match (&values[a][i], &values[b][i]) {
+                    _ if spec.key == SortKey::Name => name_cmp(a, b),
+                    (Some(x), Some(y)) => x.compare(y),
+                    (Some(_), None) => return Ordering::Less,
+                    (None, Some(_)) => return Ordering::Greater,
+                    (None, None) => Ordering::Equal,
+                };
+                if spec.descending {
+                    ord.reverse()
+                } else {
+                    ord
+                }
+            })
+            .find(|ord| *ord != Ordering::Equal)
+            .unwrap_or_else(|| name_cmp(a, b))
+    });
+
+    if reverse {
+        paths.reverse();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_natural_cmp() {
+        assert_eq!(natural_cmp("img2.jpg", "img10.jpg"), Ordering::Less);
+        assert_eq!(natural_cmp("IMG_b", "img_a"), Ordering::Greater);
+        // Case only breaks ties
+        assert_eq!(natural_cmp("IMG_a", "img_a"), Ordering::Less);
+        assert_eq!(natural_cmp("a1b", "a1"), Ordering::Greater);
+        assert_eq!(
+            natural_cmp("frame99999999999999999999", "frame100000000000000000000"),
+            Ordering::Less
+        );
+    }
+
+    #[test]
+    fn test_sort_by_name() {
+        let mut paths: Vec<String> = ["b/img10.png", "a/img2.png", "c/IMG1.png"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        sort_paths(&mut paths, SortKey::Name, false, None);
+        assert_eq!(paths, vec!["c/IMG1.png", "a/img2.png", "b/img10.png"]);
+
+        sort_paths(&mut paths, SortKey::Name, true, None);
+        assert_eq!(paths[0], "b/img10.png");
+    }
+
+    #[test]
+    fn test_parse_sort_order() {
+        let order = parse_sort_order("tag:Rating desc, exif-date asc, name").unwrap();
+        assert_eq!(
+            order,
+            vec![
+                SortSpec {
+                    key: SortKey::Tag("rating".to_string()),
+                    descending: true
+                },
+                SortSpec {
+                    key: SortKey::ExifDate,
+                    descending: false
+                },
+                SortSpec {
+                    key: SortKey::Name,
+                    descending: false
+                },
+            ]
+        );
+        // Keys keep their usual direction when none is given
+        assert!(parse_sort_order("size").unwrap()[0].descending);
+        assert_eq!(order[0].label(), "tag:rating desc");
+        assert_eq!(parse_sort_order(&order[1].label()).unwrap()[0], order[1]);
+        assert!(parse_sort_order("name sideways").is_err());
+        assert!(parse_sort_order("tag:").is_err());
+        assert!(parse_sort_order(" , ").is_err());
+    }
+
+    #[test]
+    fn test_sort_chain() {
+        let mut paths: Vec<String> = ["c", "a", "b", "d"].iter().map(|s| s.to_string()).collect();
+        let scores: HashMap<String, f32> = [("a", 50.0), ("b", 90.0), ("c", 50.0)]
+            .iter()
+            .map(|(p, s)| (p.to_string(), *s))
+            .collect();
+        let order = parse_sort_order("quality asc, name desc").unwrap();
+        sort_paths_by(&mut paths, &order, false, Some(&scores), None);
+        // Unscored d goes last even though the quality order is ascending
+        assert_eq!(paths, vec!["c", "a", "b", "d"]);
+
+        let tags = |list: &[&str]| list.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            tag_value(&tags(&["beach", "rating:4"]), "rating"),
+            Some(SortValue::Number(4.0))
+        );
+        assert_eq!(
+            tag_value(&tags(&["favorite"]), "favorite"),
+            Some(SortValue::Text(String::new()))
+        );
+        assert_eq!(tag_value(&tags(&["ratings"]), "rating"), None);
+    }
+
+    #[test]
+    fn test_sort_by_quality() {
+        let mut paths: Vec<String> = vec!["a".into(), "b".into(), "unscored".into()];
+        let scores: HashMap<String, f32> = [("a".to_string(), 10.0), ("b".to_string(), 90.0)]
+            .into_iter()
+            .collect();
+        sort_paths(&mut paths, SortKey::Quality, false, Some(&scores));
+        assert_eq!(paths, vec!["b", "a", "unscored"]);
+    }
+}
@@ -0,0 +1,266 @@
+// Sprite-sheet / texture-atlas splitting: cuts one image into a uniform
+// grid of frames so each sprite can be shown as its own tile.
+
+use crate::image_proc::{get_cache_dir, open_image};
+use anyhow::{Context, Result};
+use image::{DynamicImage, Rgba};
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+/// How to split a sprite sheet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GridSplit {
+    /// Fixed number of columns and rows
+    Fixed { cols: u32, rows: u32 },
+    /// Detect the grid from empty gutters between sprites
+    Auto,
+}
+
+/// Parse grid split from string ("4x2", "auto")
+pub fn parse_grid_split(s: &str) -> Result<GridSplit> {
+    let s = s.trim().to_lowercase();
+    if s == "auto" {
+        return Ok(GridSplit::Auto);
+    }
+
+    let parsed = s.split_once('x').and_then(|(c, r)| {
+        let cols: u32 = c.trim().parse().ok()?;
+        let rows: u32 = r.trim().parse().ok()?;
+        Some((cols, rows))
+    });
+
+    match parsed {
+        Some((cols, rows)) if cols > 0 && rows > 0 => Ok(GridSplit::Fixed { cols, rows }),
+        _ => anyhow::bail!("Invalid grid split: {}. Use: CxR (e.g. 8x4) or auto", s),
+    }
+}
+
+/// Cut an image into `cols` x `rows` equally sized frames, row by row.
+/// Leftover pixels at the right/bottom edge (if the size doesn't divide
+/// evenly) are dropped.
+pub fn split_image(img: &DynamicImage, cols: u32, rows: u32) -> Vec<DynamicImage> {
+    let cell_width = img.width() / cols.max(1);
+    let cell_height = img.height() / rows.max(1);
+    if cell_width == 0 || cell_height == 0 {
+        return Vec::new();
+    }
+
+    let mut frames = Vec::with_capacity((cols * rows) as usize);
+    for row in 0..rows {
+        for col in 0..cols {
+            frames.push(img.crop_imm(col * cell_width, row * cell_height, cell_width, cell_height));
+        }
+    }
+    frames
+}
+
+/// Detect a uniform sprite grid from fully empty rows and columns between
+/// sprites. Empty means transparent, or (for opaque sheets) the color of
+/// the top-left pixel. Returns `(cols, rows)`.
+pub fn detect_grid(img: &DynamicImage) -> Option<(u32, u32)> {
+    let rgba = img.to_rgba8();
+    let (width, height) = rgba.dimensions();
+    if width == 0 || height == 0 {
+        return None;
+    }
+
+    let has_alpha = rgba.pixels().any(|p| p[3] == 0);
+    let background = *rgba.get_pixel(0, 0);
+    let is_empty = |p: &Rgba<u8>| if has_alpha { p[3] == 0 } else { *p == background };
+
+    let col_empty: Vec<bool> = (0..width)
+        .map(|x| (0..height).all(|y| is_empty(rgba.get_pixel(x, y))))
+        .collect();
+    let row_empty: Vec<bool> = (0..height)
+        .map(|y| (0..width).all(|x| is_empty(rgba.get_pixel(x, y))))
+        .collect();
+
+    let cols = uniform_count(&col_empty)?;
+    let rows = uniform_count(&row_empty)?;
+
+    if cols * rows < 2 {
+        return None;
+    }
+    Some((cols, rows))
+}
+
+/// Largest number of equal cells along one axis such that every run of
+/// non-empty lines falls inside a single cell and no cell is left empty
+fn uniform_count(empty: &[bool]) -> Option<u32> {
+    let segments = segments(empty);
+    if segments.is_empty() {
+        return None;
+    }
+
+    let len = empty.len();
+    (1..=segments.len()).rev().find_map(|n| {
+        let pitch = len / n;
+        if pitch == 0 {
+            return None;
+        }
+
+        let mut used = vec![false; n];
+        for &(start, end) in &segments {
+            let cell = start / pitch;
+            if cell >= n || (end - 1) / pitch != cell {
+                return None;
+            }
+            used[cell] = true;
+        }
+
+        used.iter().all(|&u| u).then_some(n as u32)
+    })
+}
+
+/// Half-open ranges of consecutive non-empty lines
+fn segments(empty: &[bool]) -> Vec<(usize, usize)> {
+    let mut result = Vec::new();
+    let mut start = None;
+
+    for (i, &is_empty) in empty.iter().enumerate() {
+        match (is_empty, start) {
+            (false, None) => start = Some(i),
+            (true, Some(s)) => {
+                result.push((s, i));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        result.push((s, empty.len()));
+    }
+
+    result
+}
+
+/// Split a sprite sheet and write its frames as `<stem>_r<row>_c<col>.png`
+/// into `dir`. Returns the written paths in row-major order.
+pub fn write_frames(path: &str, split: GridSplit, dir: &Path) -> Result<Vec<String>> {
+    let img = open_image(path).with_context(|| format!("Failed to decode {}", path))?;
+
+    let (cols, rows) = match split {
+        GridSplit::Fixed { cols, rows } => (cols, rows),
+        GridSplit::Auto => detect_grid(&img).with_context(|| {
+            format!(
+                "Could not detect a sprite grid in {}; pass --grid-split CxR",
+                path
+            )
+        })?,
+    };
+
+    let stem = Path::new(path.trim_end_matches("[0]"))
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "sprite".to_string());
+
+    fs::create_dir_all(dir)?;
+
+    let mut written = Vec::new();
+    for (i, frame) in split_image(&img, cols, rows).iter().enumerate() {
+        let (row, col) = (i as u32 / cols, i as u32 % cols);
+        let frame_path = dir.join(format!("{}_r{}_c{}.png", stem, row, col));
+        frame
+            .save(&frame_path)
+            .with_context(|| format!("Failed to write {}", frame_path.display()))?;
+        written.push(frame_path.to_string_lossy().to_string());
+    }
+
+    Ok(written)
+}
+
+/// Split a sprite sheet into the cache so its frames can be displayed
+/// like ordinary image files. Frames are reused while the sheet is unchanged.
+pub fn cached_frames(path: &str, split: GridSplit) -> Result<Vec<String>> {
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    split.hash(&mut hasher);
+    if let Ok(modified) = fs::metadata(path).and_then(|m| m.modified()) {
+        modified.hash(&mut hasher);
+    }
+
+    let dir: PathBuf = get_cache_dir()?
+        .join("sprites")
+        .join(format!("{:x}", hasher.finish()));
+
+    if dir.is_dir() {
+        let mut frames: Vec<String> = fs::read_dir(&dir)?
+            .filter_map(|e| e.ok())
+            .map(|e| e.path().to_string_lossy().to_string())
+            .collect();
+        if !frames.is_empty() {
+            frames.sort_by_key(|f| frame_position(f));
+            return Ok(frames);
+        }
+    }
+
+    write_frames(path, split, &dir)
+}
+
+/// (row, col) parsed back from a frame file name, for row-major ordering
+fn frame_position(path: &str) -> (u32, u32) {
+    let stem = Path::new(path)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let mut parts = stem.rsplitn(3, '_');
+    let col = parts.next().and_then(|c| c.strip_prefix('c')?.parse().ok());
+    let row = parts.next().and_then(|r| r.strip_prefix('r')?.parse().ok());
+    (row.unwrap_or(0), col.unwrap_or(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GenericImageView, RgbaImage};
+
+    #[test]
+    fn test_parse_grid_split() {
+        assert_eq!(
+            parse_grid_split("8x4").unwrap(),
+            GridSplit::Fixed { cols: 8, rows: 4 }
+        );
+        assert_eq!(parse_grid_split("AUTO").unwrap(), GridSplit::Auto);
+        assert!(parse_grid_split("0x4").is_err());
+        assert!(parse_grid_split("8").is_err());
+    }
+
+    #[test]
+    fn test_split_image() {
+        let img = DynamicImage::ImageRgba8(RgbaImage::new(100, 40));
+        let frames = split_image(&img, 4, 2);
+        assert_eq!(frames.len(), 8);
+        assert!(frames.iter().all(|f| f.dimensions() == (25, 20)));
+    }
+
+    #[test]
+    fn test_detect_grid_with_transparent_gutters() {
+        // 3x2 sheet of 16x16 cells, each holding a 12x12 sprite
+        let mut sheet = RgbaImage::new(48, 32);
+        for row in 0..2 {
+            for col in 0..3 {
+                for y in 2..14 {
+                    for x in 2..14 {
+                        sheet.put_pixel(col * 16 + x, row * 16 + y, Rgba([255, 0, 0, 255]));
+                    }
+                }
+            }
+        }
+
+        assert_eq!(detect_grid(&DynamicImage::ImageRgba8(sheet)), Some((3, 2)));
+    }
+
+    #[test]
+    fn test_detect_grid_single_sprite() {
+        let img = RgbaImage::from_pixel(32, 32, Rgba([0, 255, 0, 255]));
+        assert_eq!(detect_grid(&DynamicImage::ImageRgba8(img)), None);
+    }
+
+    #[test]
+    fn test_frame_position() {
+        assert_eq!(frame_position("/tmp/hero_walk_r1_c12.png"), (1, 12));
+    }
+}
@@ -0,0 +1,184 @@
+// Inputs that aren't plain image files: process substitution (`lsix
+// <(curl -s URL)` passes /dev/fd/63), named pipes, and temporary files
+// without an image extension such as zsh's `=(cmd)`. They would fail the
+// extension check and be dropped, and a pipe can only be read once, so each
+// one is read here, recognized by its magic bytes and copied into a
+// per-process temp directory under a name with the right extension. The
+// directory is removed when the returned `BufferedInputs` is dropped.
+
+use crate::image_proc::IMAGE_EXTENSIONS;
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Bytes read from a regular file to recognize its format
+const SNIFF_LEN: u64 = 512;
+
+/// Command line inputs with streams replaced by temp copies
+#[derive(Debug, Default)]
+pub struct BufferedInputs {
+    pub inputs: Vec<String>,
+    dir: Option<PathBuf>,
+}
+
+impl Drop for BufferedInputs {
+    fn drop(&mut self) {
+        if let Some(dir) = &self.dir {
+            fs::remove_dir_all(dir).ok();
+        }
+    }
+}
+
+/// Whether `path` needs buffering: anything that exists but is neither a
+/// directory nor a regular file (pipes, sockets, devices), or a regular
+/// file whose extension isn't an image extension in any case
+fn needs_buffering(path: &str) -> bool {
+    let Ok(metadata) = fs::metadata(path) else {
+        return false;
+    };
+    if metadata.is_dir() {
+        return false;
+    }
+    if !metadata.is_file() {
+        return true;
+    }
+    let extension = Path::new(path).extension().map(|e| e.to_string_lossy().to_lowercase());
+    !extension.is_some_and(|e| IMAGE_EXTENSIONS.contains(&e.as_str()))
+}
+
+/// Extension for image data, from its first bytes
+pub fn sniff_extension(data: &[u8]) -> Option<&'static str> {
+    if let Ok(format) = image::guess_format(data) {
+        return format
+            .extensions_str()
+            .iter()
+            .copied()
+            .find(|e| IMAGE_EXTENSIONS.contains(e));
+    }
+    let head = String::from_utf8_lossy(&data[..data.len().min(SNIFF_LEN as usize)]);
+    let head = head.trim_start_matches('\u{feff}').trim_start();
+    if head.starts_with("%!PS") {
+        Some("eps")
+    } else if head.starts_with("<svg") || (head.starts_with("<?xml") && head.contains("<svg")) {
+        Some("svg")
+    } else {
+        None
+    }
+}
+
+/// File name for a buffered input: its own name with the sniffed
+/// extension; bare descriptor numbers (/dev/fd/63) become `stream63`
+fn buffer_name(input: &str, extension: &str) -> String {
+    let stem = Path::new(input)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| "stream".to_string());
+    if stem.chars().all(|c| c.is_ascii_digit()) {
+        format!("stream{}.{}", stem, extension)
+    } else {
+        format!("{}.{}", stem, extension)
+    }
+}
+
+/// Read `input`, if it's an image, into `dir`. Pipes are read to the end;
+/// regular files are only copied once their header looks like an image.
+fn buffer_input(input: &str, dir: &Path) -> Result<Option<PathBuf>> {
+    let mut file = fs::File::open(input).with_context(|| format!("Failed to open {}", input))?;
+    let is_file = file.metadata().map(|m| m.is_file()).unwrap_or(false);
+
+    let mut data = Vec::new();
+    if is_file {
+        file.by_ref().take(SNIFF_LEN).read_to_end(&mut data)?;
+    } else {
+        file.read_to_end(&mut data).with_context(|| format!("Failed to read {}", input))?;
+    }
+    let Some(extension) = sniff_extension(&data) else {
+        return Ok(None);
+    };
+
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let path = dir.join(buffer_name(input, extension));
+    if is_file {
+        fs::copy(input, &path).with_context(|| format!("Failed to copy {}", input))?;
+    } else {
+        fs::write(&path, &data).with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+    Ok(Some(path))
+}
+
+/// Replace stream inputs with temp copies. Streams that aren't images are
+/// dropped with a note (a pipe can't be handed on after it was read), and
+/// ones that can't be read are reported and dropped.
+pub fn buffer_stream_inputs(inputs: &[String]) -> BufferedInputs {
+    let root = std::env::temp_dir().join(format!("lsix-stream-{}", std::process::id()));
+    let mut buffered = BufferedInputs::default();
+
+    for (i, input) in inputs.iter().enumerate() {
+        if !needs_buffering(input) {
+            buffered.inputs.push(input.clone());
+            continue;
+        }
+        // One directory per input, so equal names from different pipes
+        // don't collide
+        match buffer_input(input, &root.join(i.to_string())) {
+            Ok(Some(path)) => {
+                buffered.dir = Some(root.clone());
+                buffered.inputs.push(path.to_string_lossy().to_string());
+            }
+            // Other files are dropped quietly, as before, so `lsix *` stays
+            // readable
+            Ok(None) if Path::new(input).is_file() => {}
+            Ok(None) => eprintln!("Skipping {}: not a recognized image format", input),
+            Err(e) => eprintln!("Warning: {:#}", e),
+        }
+    }
+    buffered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn test_sniff_extension() {
+        assert_eq!(sniff_extension(b"\x89PNG\r\n\x1a\n"), Some("png"));
+        assert_eq!(sniff_extension(b"\xff\xd8\xff\xe0"), Some("jpg"));
+        assert_eq!(sniff_extension(b"GIF89a"), Some("gif"));
+        assert_eq!(sniff_extension(b"<?xml version=\"1.0\"?>\n<svg>"), Some("svg"));
+        assert_eq!(sniff_extension(b"%!PS-Adobe-3.0 EPSF-3.0"), Some("eps"));
+        assert_eq!(sniff_extension(b"hello"), None);
+    }
+
+    #[test]
+    fn test_buffer_name() {
+        assert_eq!(buffer_name("/dev/fd/63", "png"), "stream63.png");
+        assert_eq!(buffer_name("/tmp/zshXa1b", "jpg"), "zshXa1b.jpg");
+        assert_eq!(buffer_name("/tmp/tmp.Ab3dE", "gif"), "tmp.gif");
+    }
+
+    #[test]
+    fn test_buffer_stream_inputs() {
+        let dir = TestDir::new("stream-test");
+        let photo = dir.join("photo.png").to_string_lossy().to_string();
+        let bare = dir.join("zshAb12").to_string_lossy().to_string();
+        let text = dir.join("notes.txt").to_string_lossy().to_string();
+        image::RgbImage::new(4, 4).save(&photo).unwrap();
+        fs::copy(&photo, &bare).unwrap();
+        fs::write(&text, "not an image").unwrap();
+
+        let inputs = vec![photo.clone(), bare, text, dir.to_string_lossy().to_string()];
+        let buffered = buffer_stream_inputs(&inputs);
+        assert_eq!(buffered.inputs.len(), 3);
+        assert_eq!(buffered.inputs[0], photo);
+        assert!(buffered.inputs[1].ends_with("/1/zshAb12.png"));
+        assert!(Path::new(&buffered.inputs[1]).is_file());
+        assert_eq!(buffered.inputs[2], inputs[3]);
+
+        let copy = buffered.inputs[1].clone();
+        drop(buffered);
+        assert!(!Path::new(&copy).exists());
+    }
+}
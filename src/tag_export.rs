@@ -0,0 +1,638 @@
+// Tag export (--export-tags xmp|iptc|exif): write the cached AI and manual
+// tags of each image into standard keyword metadata, so Lightroom, digiKam
+// and other photo tools see them. --import-tags reads the same places back
+// into the tag cache, so an existing library can be filtered with --tag.
+//
+//   xmp   sidecar next to the image (photo.jpg -> photo.jpg.xmp), dc:subject
+//   iptc  IPTC-IIM Keywords in the JPEG's Photoshop (APP13) segment
+//   exif  Windows XPKeywords in the EXIF block of a JPEG or PNG
+//
+// Keywords already present are kept and lsix's tags added to them. Embedded
+// formats edit the file in place without re-encoding the pixels.
+
+use crate::ai_tagging::{load_cached_tags, save_cached_tags};
+use anyhow::{Context, Result};
+use exif::{experimental::Writer, Field, In, Tag, Value};
+use img_parts::{jpeg::markers, jpeg::JpegSegment, Bytes, DynImage, ImageEXIF};
+use std::fs;
+use std::io::Cursor;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// Where tags are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagFormat {
+    Xmp,
+    Iptc,
+    Exif,
+}
+
+/// Parse tag export format from string
+pub fn parse_tag_format(s: &str) -> Result<TagFormat> {
+    match s.to_lowercase().as_str() {
+        "xmp" => Ok(TagFormat::Xmp),
+        "iptc" => Ok(TagFormat::Iptc),
+        "exif" => Ok(TagFormat::Exif),
+        _ => anyhow::bail!("Invalid tag export format: {}. Use: xmp, iptc, exif", s),
+    }
+}
+
+/// Write `tags` for the image at `path` in `format`. Returns the file that
+/// was written: the sidecar for XMP, the image itself otherwise.
+pub fn export_tags(path: &str, tags: &[String], format: TagFormat) -> Result<PathBuf> {
+    match format {
+        TagFormat::Xmp => {
+            let sidecar = sidecar_path(path);
+            let xmp = match fs::read_to_string(&sidecar) {
+                Ok(existing) => merge_xmp_subject(&existing, tags)
+                    .with_context(|| format!("Failed to update {}", sidecar.display()))?,
+                Err(_) => new_xmp(tags),
+            };
+            fs::write(&sidecar, xmp)
+                .with_context(|| format!("Failed to write {}", sidecar.display()))?;
+            Ok(sidecar)
+        }
+        TagFormat::Iptc | TagFormat::Exif => {
+            let data = fs::read(path).with_context(|| format!("Failed to read {}", path))?;
+            let data = if format == TagFormat::Iptc {
+                embed_iptc(data, tags)?
+            } else {
+                embed_exif(data, tags)?
+            };
+            replace_file(Path::new(path), &data)?;
+            Ok(PathBuf::from(path))
+        }
+    }
+}
+
+/// Export the cached tags of `path`; returns the file written, or `None`
+/// if the image has no tags. Embedded formats change the file's content
+/// hash, so its tags are stored again under the new key.
+pub fn export_cached_tags(
+    cache_dir: &Path,
+    path: &str,
+    format: TagFormat,
+) -> Result<Option<PathBuf>> {
+    let Ok(tags) = load_cached_tags(cache_dir, path) else {
+        return Ok(None);
+    };
+    if tags.tags.is_empty() {
+        return Ok(None);
+    }
+    let written = export_tags(path, &tags.tags, format)?;
+    if format != TagFormat::Xmp {
+        save_cached_tags(cache_dir, path, &tags)?;
+    }
+    Ok(Some(written))
+}
+
+/// Sidecar of `path`, named like digiKam and darktable do: photo.jpg.xmp
+fn sidecar_path(path: &str) -> PathBuf {
+    PathBuf::from(format!("{}.xmp", path))
+}
+
+/// Keywords other tools recorded for `path`: its XMP sidecar (photo.jpg.xmp
+/// or Lightroom's photo.xmp), embedded XMP, IPTC Keywords and EXIF
+/// XPKeywords. Sources that are absent or unreadable are skipped.
+pub fn read_keywords(path: &str) -> Vec<String> {
+    let mut keywords = Vec::new();
+    for sidecar in [sidecar_path(path), Path::new(path).with_extension("xmp")] {
+        if let Ok(xmp) = fs::read_to_string(&sidecar) {
+            keywords = merge_keywords(keywords, &xmp_subject(&xmp));
+        }
+    }
+
+    let Ok(data) = fs::read(path) else {
+        return keywords;
+    };
+    if let Ok(exif) = exif::Reader::new().read_from_container(&mut Cursor::new(&data)) {
+        if let Some(field) = exif.get_field(XP_KEYWORDS, In::PRIMARY) {
+            keywords = merge_keywords(keywords, &decode_xp_keywords(&field.value));
+        }
+    }
+    if let Ok(jpeg) = img_parts::jpeg::Jpeg::from_bytes(Bytes::from(data)) {
+        for segment in jpeg.segments() {
+            let contents = segment.contents();
+            let found = match segment.marker() {
+                markers::APP1 if contents.starts_with(XMP_JPEG_HEADER) => {
+                    let xmp = String::from_utf8_lossy(&contents[XMP_JPEG_HEADER.len()..]);
+                    xmp_subject(&xmp)
+                }
+                markers::APP13 if contents.starts_with(PHOTOSHOP_HEADER) => {
+                    iptc_keywords(&contents[PHOTOSHOP_HEADER.len()..]).unwrap_or_default()
+                }
+                _ => continue,
+            };
+            keywords = merge_keywords(keywords, &found);
+        }
+    }
+    keywords
+}
+
+/// Write `data` over `path` through a temporary file, keeping permissions
+fn replace_file(path: &Path, data: &[u8]) -> Result<()> {
+    let tmp = path.with_extension(format!("lsix-{}.tmp", std::process::id()));
+    fs::write(&tmp, data).with_context(|| format!("Failed to write {}", tmp.display()))?;
+    if let Ok(metadata) = fs::metadata(path) {
+        let _ = fs::set_permissions(&tmp, metadata.permissions());
+    }
+    fs::rename(&tmp, path).with_context(|| format!("Failed to replace {}", path.display()))
+}
+
+/// `existing` followed by the tags it doesn't have yet
+fn merge_keywords(existing: Vec<String>, tags: &[String]) -> Vec<String> {
+    let mut keywords = existing;
+    for tag in tags {
+        if !keywords.iter().any(|k| k.eq_ignore_ascii_case(tag)) {
+            keywords.push(tag.clone());
+        }
+    }
+    keywords
+}
+
+// ---- XMP ----
+
+const XMP_NS_DC: &str = "http://purl.org/dc/elements/1.1/";
+/// Signature starting a JPEG APP1 segment that holds XMP
+const XMP_JPEG_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn xml_unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// `dc:subject` element listing `keywords`
+fn subject_element(keywords: &[String]) -> String {
+    let mut element = String::from("<dc:subject>\n    <rdf:Bag>\n");
+    for keyword in keywords {
+        element.push_str(&format!("     <rdf:li>{}</rdf:li>\n", xml_escape(keyword)));
+    }
+    element.push_str("    </rdf:Bag>\n   </dc:subject>");
+    element
+}
+
+/// A complete XMP packet with `tags` as its keywords
+fn new_xmp(tags: &[String]) -> String {
+    format!(
+        "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n\
+         <x:xmpmeta xmlns:x=\"adobe:ns:meta/\" x:xmptk=\"lsix\">\n \
+         <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n  \
+         <rdf:Description rdf:about=\"\"\n    xmlns:dc=\"{}\">\n   \
+         {}\n  \
+         </rdf:Description>\n \
+         </rdf:RDF>\n\
+         </x:xmpmeta>\n\
+         <?xpacket end=\"w\"?>\n",
+        XMP_NS_DC,
+        subject_element(tags)
+    )
+}
+
+/// Text of each `rdf:li` in `xml`
+fn li_values(xml: &str) -> Vec<String> {
+    let mut values = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find("<rdf:li") {
+        let Some(open_end) = rest[start..].find('>').map(|i| start + i + 1) else {
+            break;
+        };
+        let Some(close) = rest[open_end..].find("</rdf:li>").map(|i| open_end + i) else {
+            break;
+        };
+        values.push(xml_unescape(rest[open_end..close].trim()));
+        rest = &rest[close..];
+    }
+    values
+}
+
+/// Span of the `dc:subject` element in `xmp`, if it has one
+fn subject_range(xmp: &str) -> Result<Option<Range<usize>>> {
+    let Some(start) = xmp.find("<dc:subject") else {
+        return Ok(None);
+    };
+    let end = if xmp[start..].starts_with("<dc:subject/>") {
+        start + "<dc:subject/>".len()
+    } else {
+        xmp[start..]
+            .find("</dc:subject>")
+            .map(|i| start + i + "</dc:subject>".len())
+            .context("Unclosed dc:subject")?
+    };
+    Ok(Some(start..end))
+}
+
+/// Keywords of an XMP packet. Only `dc:subject` is read; other bags, such
+/// as Lightroom's hierarchical keywords, hold different things.
+fn xmp_subject(xmp: &str) -> Vec<String> {
+    match subject_range(xmp) {
+        Ok(Some(range)) => li_values(&xmp[range]),
+        _ => Vec::new(),
+    }
+}
+
+/// Add `tags` to the keywords of an existing XMP packet: the `dc:subject`
+/// bag is extended, or created in the last `rdf:Description`
+fn merge_xmp_subject(xmp: &str, tags: &[String]) -> Result<String> {
+    if let Some(range) = subject_range(xmp)? {
+        let keywords = merge_keywords(li_values(&xmp[range.clone()]), tags);
+        return Ok(format!(
+            "{}{}{}",
+            &xmp[..range.start],
+            subject_element(&keywords),
+            &xmp[range.end..]
+        ));
+    }
+
+    let close = xmp
+        .rfind("</rdf:Description>")
+        .context("No rdf:Description element to add keywords to")?;
+    let mut merged = format!("{}{}\n  {}", &xmp[..close], subject_element(tags), &xmp[close..]);
+    if !merged.contains("xmlns:dc=") {
+        let open = merged[..close]
+            .rfind("<rdf:Description")
+            .context("No rdf:Description element to add keywords to")?;
+        merged.insert_str(open + "<rdf:Description".len(), &format!(" xmlns:dc=\"{}\"", XMP_NS_DC));
+    }
+    Ok(merged)
+}
+
+// ---- IPTC ----
+
+/// Signature starting the Photoshop APP13 segment
+const PHOTOSHOP_HEADER: &[u8] = b"Photoshop 3.0\0";
+/// Image resource holding the IPTC-IIM records
+const IPTC_RESOURCE: u16 = 0x0404;
+/// IIM Keywords are at most 64 bytes each
+const IPTC_KEYWORD_MAX: usize = 64;
+
+/// One IPTC-IIM dataset: record, dataset number and value
+type Dataset = (u8, u8, Vec<u8>);
+
+fn parse_iim(data: &[u8]) -> Result<Vec<Dataset>> {
+    let mut datasets = Vec::new();
+    let mut pos = 0;
+    while pos + 5 <= data.len() && data[pos] == 0x1c {
+        let length = u16::from_be_bytes([data[pos + 3], data[pos + 4]]) as usize;
+        if length & 0x8000 != 0 {
+            anyhow::bail!("Extended IPTC datasets are not supported");
+        }
+        let value = data
+            .get(pos + 5..pos + 5 + length)
+            .context("Truncated IPTC dataset")?;
+        datasets.push((data[pos + 1], data[pos + 2], value.to_vec()));
+        pos += 5 + length;
+    }
+    Ok(datasets)
+}
+
+/// Text of an IIM value: UTF-8 when valid, otherwise Latin-1 as older
+/// tools wrote it
+fn iim_text(value: Vec<u8>) -> String {
+    String::from_utf8(value)
+        .unwrap_or_else(|e| e.into_bytes().into_iter().map(char::from).collect())
+}
+
+/// Keywords (2:25) in the IPTC resource of Photoshop APP13 `resources`
+fn iptc_keywords(resources: &[u8]) -> Result<Vec<String>> {
+    let mut keywords = Vec::new();
+    for (_, _, iim) in parse_resources(resources)?
+        .into_iter()
+        .filter(|(id, _, _)| *id == IPTC_RESOURCE)
+    {
+        for (record, number, value) in parse_iim(&iim)? {
+            if (record, number) == (2, 25) {
+                keywords.push(iim_text(value));
+            }
+        }
+    }
+    Ok(keywords)
+}
+
+/// Keep `keyword` within the IIM limit without splitting a character
+fn truncate_keyword(keyword: &str) -> &str {
+    let mut end = keyword.len().min(IPTC_KEYWORD_MAX);
+    while !keyword.is_char_boundary(end) {
+        end -= 1;
+    }
+    &keyword[..end]
+}
+
+/// IIM records with Keywords (2:25) extended by `tags`. A UTF-8 marker
+/// (1:90) and a record version (2:00) are added when missing.
+fn update_iim(data: &[u8], tags: &[String]) -> Result<Vec<u8>> {
+    let datasets = parse_iim(data)?;
+    let existing: Vec<String> = datasets
+        .iter()
+        .filter(|(record, number, _)| (*record, *number) == (2, 25))
+        .map(|(_, _, value)| iim_text(value.clone()))
+        .collect();
+    let keywords = merge_keywords(existing, tags);
+
+    let mut records: Vec<Dataset> = datasets
+        .into_iter()
+        .filter(|(record, number, _)| (*record, *number) != (2, 25))
+        .collect();
+    if !records.iter().any(|(record, number, _)| (*record, *number) == (1, 90)) {
+        records.push((1, 90, b"\x1b%G".to_vec()));
+    }
+    if !records.iter().any(|(record, number, _)| (*record, *number) == (2, 0)) {
+        records.push((2, 0, vec![0, 4]));
+    }
+    for keyword in &keywords {
+        records.push((2, 25, truncate_keyword(keyword).as_bytes().to_vec()));
+    }
+    // Datasets must be in record order; within a record the order is kept,
+    // with the version first
+    records.sort_by_key(|(record, number, _)| (*record, *number != 0));
+
+    let mut out = Vec::new();
+    for (record, number, value) in records {
+        out.extend_from_slice(&[0x1c, record, number]);
+        out.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        out.extend_from_slice(&value);
+    }
+    Ok(out)
+}
+
+/// One Photoshop image resource: id, name bytes and data
+type Resource = (u16, Vec<u8>, Vec<u8>);
+
+fn parse_resources(data: &[u8]) -> Result<Vec<Resource>> {
+    let mut resources = Vec::new();
+    let mut pos = 0;
+    while pos + 4 <= data.len() && &data[pos..pos + 4] == b"8BIM" {
+        let id_bytes = data.get(pos + 4..pos + 6).context("Truncated Photoshop resource")?;
+        let id = u16::from_be_bytes(id_bytes.try_into()?);
+        let name_len = *data.get(pos + 6).context("Truncated Photoshop resource")? as usize;
+        // The Pascal name, with its length byte, is padded to an even size
+        let name_end = pos + 6 + ((name_len + 2) & !1);
+        let name = data.get(pos + 7..pos + 7 + name_len).context("Truncated Photoshop resource")?;
+        let size_bytes = data.get(name_end..name_end + 4).context("Truncated Photoshop resource")?;
+        let size = u32::from_be_bytes(size_bytes.try_into()?) as usize;
+        let value = data
+            .get(name_end + 4..name_end + 4 + size)
+            .context("Truncated Photoshop resource")?;
+        resources.push((id, name.to_vec(), value.to_vec()));
+        pos = name_end + 4 + size + (size & 1);
+    }
+    Ok(resources)
+}
+
+/// Photoshop APP13 contents with the IPTC resource updated (or added)
+fn update_photoshop_segment(contents: Option<&[u8]>, tags: &[String]) -> Result<Vec<u8>> {
+    let mut resources = match contents {
+        Some(contents) => parse_resources(&contents[PHOTOSHOP_HEADER.len()..])?,
+        None => Vec::new(),
+    };
+    match resources.iter_mut().find(|(id, _, _)| *id == IPTC_RESOURCE) {
+        Some((_, _, iim)) => *iim = update_iim(iim, tags)?,
+        None => resources.push((IPTC_RESOURCE, Vec::new(), update_iim(&[], tags)?)),
+    }
+
+    let mut out = PHOTOSHOP_HEADER.to_vec();
+    for (id, name, value) in resources {
+        out.extend_from_slice(b"8BIM");
+        out.extend_from_slice(&id.to_be_bytes());
+        out.push(name.len() as u8);
+        out.extend_from_slice(&name);
+        if name.len() % 2 == 0 {
+            out.push(0);
+        }
+        out.extend_from_slice(&(value.len() as u32).to_be_bytes());
+        out.extend_from_slice(&value);
+        if value.len() % 2 == 1 {
+            out.push(0);
+        }
+    }
+    Ok(out)
+}
+
+/// Add `tags` to the IPTC keywords of a JPEG
+fn embed_iptc(data: Vec<u8>, tags: &[String]) -> Result<Vec<u8>> {
+    let Ok(Some(DynImage::Jpeg(mut jpeg))) = DynImage::from_bytes(Bytes::from(data)) else {
+        anyhow::bail!("IPTC keywords can only be embedded in JPEG files; use xmp");
+    };
+
+    let segments = jpeg.segments_mut();
+    let existing = segments.iter().position(|segment| {
+        segment.marker() == markers::APP13 && segment.contents().starts_with(PHOTOSHOP_HEADER)
+    });
+    let contents = update_photoshop_segment(existing.map(|i| &segments[i].contents()[..]), tags)?;
+    let segment = JpegSegment::new_with_contents(markers::APP13, Bytes::from(contents));
+    match existing {
+        Some(i) => segments[i] = segment,
+        None => {
+            // After the JFIF/EXIF/XMP headers, as other tools place it
+            let after = segments
+                .iter()
+                .position(|s| !matches!(s.marker(), markers::APP0 | markers::APP1))
+                .unwrap_or(segments.len());
+            segments.insert(after, segment);
+        }
+    }
+
+    let mut out = Vec::new();
+    jpeg.encoder().write_to(&mut out)?;
+    Ok(out)
+}
+
+// ---- EXIF ----
+
+/// Windows keywords: UTF-16LE, semicolon separated, NUL terminated
+const XP_KEYWORDS: Tag = Tag(exif::Context::Tiff, 0x9c9e);
+
+fn decode_xp_keywords(value: &Value) -> Vec<String> {
+    let Value::Byte(bytes) = value else {
+        return Vec::new();
+    };
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .take_while(|&unit| unit != 0)
+        .collect();
+    String::from_utf16_lossy(&units)
+        .split(';')
+        .map(|k| k.trim().to_string())
+        .filter(|k| !k.is_empty())
+        .collect()
+}
+
+fn encode_xp_keywords(keywords: &[String]) -> Value {
+    let mut bytes: Vec<u8> = keywords
+        .join(";")
+        .encode_utf16()
+        .chain(std::iter::once(0))
+        .flat_map(|unit| unit.to_le_bytes())
+        .collect();
+    bytes.shrink_to_fit();
+    Value::Byte(bytes)
+}
+
+/// EXIF block `raw` (or a new one) with `tags` added to XPKeywords. Every
+/// other field and the EXIF thumbnail are kept.
+fn update_exif(raw: Option<&[u8]>, tags: &[String]) -> Result<Vec<u8>> {
+    let exif = raw
+        .map(|raw| exif::Reader::new().read_raw(raw.to_vec()))
+        .transpose()
+        .context("EXIF data is unreadable")?;
+    let fields: Vec<&Field> = exif.iter().flat_map(|exif| exif.fields()).collect();
+
+    let existing = fields
+        .iter()
+        .find(|f| f.tag == XP_KEYWORDS && f.ifd_num == In::PRIMARY)
+        .map(|f| decode_xp_keywords(&f.value))
+        .unwrap_or_default();
+    let keywords = Field {
+        tag: XP_KEYWORDS,
+        ifd_num: In::PRIMARY,
+        value: encode_xp_keywords(&merge_keywords(existing, tags)),
+    };
+
+    let mut writer = Writer::new();
+    for field in fields.iter().filter(|f| f.tag != XP_KEYWORDS || f.ifd_num != In::PRIMARY) {
+        if field.ifd_num == In::PRIMARY || field.ifd_num == In::THUMBNAIL {
+            writer.push_field(field);
+        }
+    }
+    writer.push_field(&keywords);
+
+    // The thumbnail is referenced by offset into the TIFF data
+    let thumbnail = exif.as_ref().and_then(|exif| {
+        let offset = exif
+            .get_field(Tag::JPEGInterchangeFormat, In::THUMBNAIL)?
+            .value
+            .get_uint(0)? as usize;
+        let length = exif
+            .get_field(Tag::JPEGInterchangeFormatLength, In::THUMBNAIL)?
+            .value
+            .get_uint(0)? as usize;
+        exif.buf().get(offset..offset + length)
+    });
+    if let Some(thumbnail) = thumbnail {
+        writer.set_jpeg(thumbnail, In::THUMBNAIL);
+    }
+
+    let little_endian = exif.as_ref().is_none_or(|exif| exif.little_endian());
+    let mut buf = Cursor::new(Vec::new());
+    writer
+        .write(&mut buf, little_endian)
+        .context("Failed to write EXIF data")?;
+    Ok(buf.into_inner())
+}
+
+/// Add `tags` to the EXIF XPKeywords of a JPEG or PNG
+fn embed_exif(data: Vec<u8>, tags: &[String]) -> Result<Vec<u8>> {
+    let image = match DynImage::from_bytes(Bytes::from(data)) {
+        Ok(Some(image @ (DynImage::Jpeg(_) | DynImage::Png(_)))) => image,
+        _ => anyhow::bail!("EXIF keywords can only be embedded in JPEG and PNG files; use xmp"),
+    };
+
+    let mut out = Vec::new();
+    match image {
+        DynImage::Jpeg(mut jpeg) => {
+            let exif = update_exif(jpeg.exif().as_deref(), tags)?;
+            jpeg.set_exif(Some(Bytes::from(exif)));
+            jpeg.encoder().write_to(&mut out)?;
+        }
+        DynImage::Png(mut png) => {
+            let exif = update_exif(png.exif().as_deref(), tags)?;
+            png.set_exif(Some(Bytes::from(exif)));
+            png.encoder().write_to(&mut out)?;
+        }
+        DynImage::WebP(_) => unreachable!("rejected above"),
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    fn tags(list: &[&str]) -> Vec<String> {
+        list.iter().map(|t| t.to_string()).collect()
+    }
+
+    fn jpeg() -> Vec<u8> {
+        let img = image::RgbImage::from_pixel(8, 8, image::Rgb([200, 100, 50]));
+        let mut data = Vec::new();
+        img.write_to(&mut Cursor::new(&mut data), image::ImageFormat::Jpeg)
+            .unwrap();
+        data
+    }
+
+    #[test]
+    fn test_xmp_sidecar_merge() {
+        let xmp = new_xmp(&tags(&["beach", "r&b"]));
+        assert_eq!(li_values(&xmp), ["beach", "r&b"]);
+
+        // Keywords from other tools are kept
+        let merged = merge_xmp_subject(&xmp, &tags(&["Beach", "sunset"])).unwrap();
+        assert_eq!(li_values(&merged), ["beach", "r&b", "sunset"]);
+
+        let bare = "<x:xmpmeta><rdf:RDF><rdf:Description rdf:about=\"\" \
+                    xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\"></rdf:Description>\
+                    </rdf:RDF></x:xmpmeta>";
+        let merged = merge_xmp_subject(bare, &tags(&["dog"])).unwrap();
+        assert_eq!(li_values(&merged), ["dog"]);
+        assert!(merged.contains("xmlns:dc="));
+        assert!(merge_xmp_subject("<x:xmpmeta/>", &tags(&["dog"])).is_err());
+    }
+
+    #[test]
+    fn test_embed_iptc_keywords() {
+        let once = embed_iptc(jpeg(), &tags(&["beach"])).unwrap();
+        let twice = embed_iptc(once, &tags(&["beach", "sunset"])).unwrap();
+        image::load_from_memory(&twice).unwrap();
+
+        let jpeg = img_parts::jpeg::Jpeg::from_bytes(Bytes::from(twice)).unwrap();
+        let app13: Vec<_> = jpeg.segments_by_marker(markers::APP13).collect();
+        assert_eq!(app13.len(), 1);
+        let keywords = iptc_keywords(&app13[0].contents()[PHOTOSHOP_HEADER.len()..]).unwrap();
+        assert_eq!(keywords, ["beach", "sunset"]);
+        assert_eq!(iim_text(b"caf\xe9".to_vec()), "café");
+
+        // A block cut off right after a signature is an error, not a panic
+        assert!(parse_resources(b"8BIM").is_err());
+        assert!(parse_resources(b"8BIM\x04").is_err());
+    }
+
+    #[test]
+    fn test_read_keywords() {
+        let dir = TestDir::new("tag-import");
+        let image = dir.join("photo.jpg").to_string_lossy().to_string();
+
+        let data = embed_iptc(jpeg(), &tags(&["beach"])).unwrap();
+        let data = embed_exif(data, &tags(&["Beach", "family"])).unwrap();
+        fs::write(&image, data).unwrap();
+        // Lightroom names the sidecar photo.xmp
+        fs::write(dir.join("photo.xmp"), new_xmp(&tags(&["sunset"]))).unwrap();
+
+        assert_eq!(read_keywords(&image), ["sunset", "Beach", "family"]);
+        assert!(read_keywords(&dir.join("none.jpg").to_string_lossy()).is_empty());
+    }
+
+    #[test]
+    fn test_embed_exif_keywords() {
+        let data = embed_exif(jpeg(), &tags(&["beach", "sunset"])).unwrap();
+        image::load_from_memory(&data).unwrap();
+
+        let exif = exif::Reader::new()
+            .read_from_container(&mut Cursor::new(&data))
+            .unwrap();
+        let field = exif.get_field(XP_KEYWORDS, In::PRIMARY).unwrap();
+        assert_eq!(decode_xp_keywords(&field.value), ["beach", "sunset"]);
+        assert_eq!(parse_tag_format("IPTC").unwrap(), TagFormat::Iptc);
+        assert!(parse_tag_format("json").is_err());
+    }
+}
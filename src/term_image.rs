@@ -4,29 +4,156 @@ use crossterm::{
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
-use image::ImageReader;
+use crate::image_proc::{open_image, ImageConfig};
+use crate::terminal::{detect_cell_size, detect_protocol, GraphicsProtocol, TerminalConfig};
+use image::RgbaImage;
 use ratatui::backend::CrosstermBackend;
-use ratatui_image::{picker::Picker, StatefulImage};
+use ratatui_image::picker::{Picker, ProtocolType};
+use ratatui_image::StatefulImage;
 use std::io::stdout;
+use std::sync::OnceLock;
 
-pub fn create_picker() -> Picker {
-    // Use from_query_stdio which should work fine when called after raw mode is enabled
-    match Picker::from_query_stdio() {
-        Ok(picker) => picker,
-        Err(_) => {
-            // Fallback to halfblocks if terminal query fails
-            Picker::halfblocks()
+/// Turns a composed grid row into the escape sequences of one inline
+/// graphics protocol
+pub trait GraphicsBackend: Sync {
+    fn encode_row(&self, row: &RgbaImage, config: &ImageConfig) -> Result<Vec<u8>>;
+}
+
+/// DEC SIXEL output via the built-in encoder
+pub struct SixelBackend;
+
+impl GraphicsBackend for SixelBackend {
+    fn encode_row(&self, row: &RgbaImage, config: &ImageConfig) -> Result<Vec<u8>> {
+        let background = crate::montage::parse_color(&config.background)
+            .unwrap_or(image::Rgba([0, 0, 0, 255]));
+        let background = [background[0], background[1], background[2]];
+        Ok(match &config.shared_palette {
+            Some(palette) => crate::sixel_encoder::encode_with(row, palette, background),
+            None => crate::sixel_encoder::encode(row, config.num_colors, background),
+        })
+    }
+}
+
+/// Kitty graphics protocol output (kitty, ghostty)
+pub struct KittyBackend;
+
+impl GraphicsBackend for KittyBackend {
+    fn encode_row(&self, row: &RgbaImage, _config: &ImageConfig) -> Result<Vec<u8>> {
+        crate::kitty_encoder::encode(row)
+    }
+}
+
+/// Unicode half-block output for terminals and multiplexers without inline
+/// graphics: every cell shows two vertically stacked pixels ("▀" with
+/// foreground = upper pixel, background = lower pixel)
+pub struct BlocksBackend;
+
+impl GraphicsBackend for BlocksBackend {
+    fn encode_row(&self, row: &RgbaImage, config: &ImageConfig) -> Result<Vec<u8>> {
+        use image::imageops::{self, FilterType};
+        use std::io::Write;
+
+        let columns = crate::test_mode::terminal_size()
+            .map(|(cols, _)| cols as u32)
+            .unwrap_or(80)
+            .max(1);
+        let width = row.width().min(columns).max(1);
+        let height = ((row.height() as u64 * width as u64 / row.width().max(1) as u64) as u32).max(2);
+        let small = imageops::resize(row, width, height, FilterType::Triangle);
+
+        let background = crate::montage::parse_color(&config.background)
+            .unwrap_or(image::Rgba([0, 0, 0, 255]));
+        let flatten = |p: &image::Rgba<u8>| -> [u8; 3] {
+            let alpha = p[3] as u32;
+            let mut out = [0u8; 3];
+            for c in 0..3 {
+                out[c] = ((p[c] as u32 * alpha + background[c] as u32 * (255 - alpha)) / 255) as u8;
+            }
+            out
+        };
+
+        let mut out = Vec::new();
+        for y in (0..height).step_by(2) {
+            let mut last: Option<([u8; 3], [u8; 3])> = None;
+            for x in 0..width {
+                let upper = flatten(small.get_pixel(x, y));
+                let lower = if y + 1 < height {
+                    flatten(small.get_pixel(x, y + 1))
+                } else {
+                    [background[0], background[1], background[2]]
+                };
+
+                // Only switch colors when they change along the line
+                if last != Some((upper, lower)) {
+                    write!(
+                        out,
+                        "\x1b[38;2;{};{};{};48;2;{};{};{}m",
+                        upper[0], upper[1], upper[2], lower[0], lower[1], lower[2]
+                    )?;
+                    last = Some((upper, lower));
+                }
+                out.extend_from_slice("▀".as_bytes());
+            }
+            out.extend_from_slice(b"\x1b[0m\n");
         }
+
+        Ok(out)
+    }
+}
+
+/// Backend implementing the given protocol
+pub fn backend_for(protocol: GraphicsProtocol) -> &'static dyn GraphicsBackend {
+    match protocol {
+        GraphicsProtocol::Sixel => &SixelBackend,
+        GraphicsProtocol::Kitty => &KittyBackend,
+        GraphicsProtocol::Blocks => &BlocksBackend,
     }
 }
 
+/// Picker shared by the TUI and the previews, built once per process
+static PICKER: OnceLock<Picker> = OnceLock::new();
+
+/// Build the shared picker from the settings found by terminal detection,
+/// so later `create_picker` calls need no terminal I/O. Does nothing once
+/// a picker exists.
+pub fn init_picker(config: &TerminalConfig) {
+    let _ = PICKER.set(build_picker(config.cell_size, config.protocol));
+}
+
+/// The shared picker; built on first use when `init_picker` wasn't called
+/// (subcommands that skip terminal detection)
+pub fn create_picker() -> Picker {
+    PICKER
+        .get_or_init(|| {
+            let protocol = detect_protocol().unwrap_or(GraphicsProtocol::Sixel);
+            build_picker(detect_cell_size(), protocol)
+        })
+        .clone()
+}
+
+/// Picker for a known cell size and protocol. Only when the cell size is
+/// unknown is the terminal queried, falling back to half blocks if that
+/// fails too.
+fn build_picker(cell_size: Option<(u16, u16)>, protocol: GraphicsProtocol) -> Picker {
+    let Some(font_size) = cell_size else {
+        return Picker::from_query_stdio().unwrap_or_else(|_| Picker::halfblocks());
+    };
+    // The replacements query the terminal, which a known cell size avoids
+    #[allow(deprecated)]
+    let mut picker = Picker::from_fontsize(font_size);
+    picker.set_protocol_type(match protocol {
+        GraphicsProtocol::Sixel => ProtocolType::Sixel,
+        GraphicsProtocol::Kitty => ProtocolType::Kitty,
+        GraphicsProtocol::Blocks => ProtocolType::Halfblocks,
+    });
+    picker
+}
+
 #[allow(dead_code)]
 pub fn render_single_image(image_path: &str) -> Result<()> {
     let picker = create_picker();
 
-    let dyn_img = ImageReader::open(image_path)?
-        .decode()
-        .context("Failed to decode image")?;
+    let dyn_img = open_image(image_path).context("Failed to decode image")?;
 
     let mut image_protocol = picker.new_resize_protocol(dyn_img);
 
@@ -64,11 +191,7 @@ pub fn render_image_grid(image_paths: &[String], num_columns: u32) -> Result<()>
 
     let images: Result<Vec<image::DynamicImage>> = image_paths
         .iter()
-        .map(|path| {
-            ImageReader::open(path)?
-                .decode()
-                .context(format!("Failed to decode image: {}", path))
-        })
+        .map(|path| open_image(path).context(format!("Failed to decode image: {}", path)))
         .collect();
 
     let images = images?;
@@ -146,9 +269,7 @@ pub fn display_single_image_interactive(image_path: &str) -> Result<()> {
 
     let picker = create_picker();
 
-    let dyn_img = ImageReader::open(image_path)?
-        .decode()
-        .context("Failed to decode image")?;
+    let dyn_img = open_image(image_path).context("Failed to decode image")?;
 
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = ratatui::Terminal::new(backend)?;
@@ -176,3 +297,49 @@ pub fn display_single_image_interactive(image_path: &str) -> Result<()> {
 
     Ok(())
 }
+
+/// Loop an animation in the alternate screen until q/Esc/Enter is pressed
+pub fn preview_animation(frames: &[image::DynamicImage], fps: u32) -> Result<()> {
+    if frames.is_empty() {
+        return Ok(());
+    }
+
+    enable_raw_mode()?;
+    let mut stdout = stdout();
+    execute!(stdout, EnterAlternateScreen)?;
+
+    let picker = create_picker();
+    let mut protocols: Vec<_> = frames
+        .iter()
+        .map(|frame| picker.new_resize_protocol(frame.clone()))
+        .collect();
+
+    let backend = CrosstermBackend::new(stdout);
+    let mut terminal = ratatui::Terminal::new(backend)?;
+
+    let frame_time = std::time::Duration::from_millis(1000 / fps.max(1) as u64);
+    let mut current = 0;
+
+    loop {
+        terminal.draw(|f| {
+            let widget = StatefulImage::default();
+            f.render_stateful_widget(widget, f.area(), &mut protocols[current]);
+        })?;
+
+        if event::poll(frame_time)? {
+            if let Event::Key(key) = event::read()? {
+                match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc | KeyCode::Enter => break,
+                    _ => {}
+                }
+            }
+        }
+
+        current = (current + 1) % protocols.len();
+    }
+
+    disable_raw_mode()?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+
+    Ok(())
+}
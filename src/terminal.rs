@@ -1,26 +1,54 @@
 use anyhow::Result;
 use std::io::{self, Write};
-use std::time::Duration;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How long replies still owed at the end of the run are waited for, so
+/// they don't turn up as typed input at the shell prompt
+const LATE_REPLY_WAIT_MS: u64 = 200;
+
+/// Inline graphics protocol used for grid output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GraphicsProtocol {
+    Sixel, // DEC SIXEL (xterm, mlterm, foot, wezterm, ...)
+    Kitty,  // Kitty graphics protocol (kitty, ghostty, ...)
+    Blocks, // Unicode half blocks with truecolor, works almost anywhere
+}
+
+/// Parse graphics protocol from string
+pub fn parse_protocol(s: &str) -> Result<GraphicsProtocol> {
+    match s.to_lowercase().as_str() {
+        "sixel" => Ok(GraphicsProtocol::Sixel),
+        "kitty" => Ok(GraphicsProtocol::Kitty),
+        "blocks" | "halfblocks" => Ok(GraphicsProtocol::Blocks),
+        _ => anyhow::bail!("Invalid graphics protocol: {}. Use: sixel, kitty or blocks", s),
+    }
+}
 
 /// Terminal configuration detected via escape sequences
 #[derive(Debug, Clone)]
 #[allow(dead_code)]
 pub struct TerminalConfig {
     pub has_sixel: bool,
+    pub protocol: GraphicsProtocol,
     pub num_colors: u32,
     pub width: u32,
     pub background: String,
     pub foreground: String,
+    /// Character cell size in pixels, if the terminal or tmux reports it
+    pub cell_size: Option<(u16, u16)>,
 }
 
 impl Default for TerminalConfig {
     fn default() -> Self {
         Self {
             has_sixel: false,
+            protocol: GraphicsProtocol::Sixel,
             num_colors: 16,
             width: 1024,
             background: "white".to_string(),
             foreground: "black".to_string(),
+            cell_size: None,
         }
     }
 }
@@ -75,6 +103,22 @@ fn query_terminal(sequence: &str, timeout_ms: u64) -> Result<Vec<u8>> {
     Ok(response)
 }
 
+/// Detect if terminal supports the Kitty graphics protocol
+pub fn detect_kitty() -> bool {
+    // kitty exports KITTY_WINDOW_ID to every child process
+    if std::env::var("KITTY_WINDOW_ID").is_ok() {
+        return true;
+    }
+
+    let term = std::env::var("TERM").unwrap_or_default().to_lowercase();
+    if term.contains("kitty") || term.contains("ghostty") {
+        return true;
+    }
+
+    let term_program = std::env::var("TERM_PROGRAM").unwrap_or_default().to_lowercase();
+    term_program.contains("ghostty")
+}
+
 /// Detect if terminal supports SIXEL graphics
 pub fn detect_sixel() -> Result<bool> {
     // Check for YAFT terminal (vt102 compatible but supports sixel)
@@ -101,7 +145,6 @@ pub fn detect_sixel() -> Result<bool> {
         "wezterm",
         "foot",
         "contour",
-        "kitty",
         "alacritty",
         "mintty",
         "cygwin",
@@ -181,20 +224,17 @@ pub fn detect_geometry() -> Result<u32> {
         }
     }
 
-    // Try to get pixel width via escape sequence CSI 14 t
-    // This returns something like \x1b[4;height;widtht
-    if let Ok(response) = query_terminal("\x1b[14t", 100) {
-        let response_str = String::from_utf8_lossy(&response);
-        if let Some(width_part) = response_str.split(';').nth(2) {
-            let width_str: String = width_part.chars().take_while(|c| c.is_ascii_digit()).collect();
-            if let Ok(width) = width_str.parse::<u32>() {
-                if width > 0 {
-                    return Ok(width);
-                }
-            }
+    // Inside tmux the terminal's size is the whole window's, not the pane's
+    if detect_multiplexer() == Some(Multiplexer::Tmux) {
+        if let Some(width) = tmux_pane_width() {
+            return Ok(width);
         }
     }
 
+    if let Some(width) = query_pixel_width() {
+        return Ok(width);
+    }
+
     // Fallback: Try to use character width * estimated font width
     if let Ok((cols, _)) = crossterm::terminal::size() {
         // Assume a typical font width of 10-12 pixels
@@ -205,30 +245,546 @@ pub fn detect_geometry() -> Result<u32> {
     Ok(1920)
 }
 
+/// Pixel width of the terminal window via the escape sequence CSI 14 t,
+/// which returns something like \x1b[4;height;widtht
+fn query_pixel_width() -> Option<u32> {
+    query_window_field(2)
+}
+
+/// Field `index` of the reply to CSI 14 t (1 = height, 2 = width)
+fn query_window_field(index: usize) -> Option<u32> {
+    let response = query_terminal("\x1b[14t", 100).ok()?;
+    parse_window_field(&String::from_utf8_lossy(&response), index)
+}
+
+fn parse_window_field(response: &str, index: usize) -> Option<u32> {
+    let part = response.split(';').nth(index)?;
+    let digits: String = part.chars().take_while(|c| c.is_ascii_digit()).collect();
+    digits.parse::<u32>().ok().filter(|&value| value > 0)
+}
+
+/// Pixel height of a terminal `lines` lines high: LSIX_HEIGHT, the cell
+/// height (from the tty's window size or tmux) times `lines`, or the window
+/// height via XTWINOPS (CSI 14 t). `None` when none of them know it.
+pub fn detect_pixel_height(lines: u32) -> Option<u32> {
+    if let Ok(height) = std::env::var("LSIX_HEIGHT") {
+        if let Ok(height) = height.parse::<u32>() {
+            return Some(height);
+        }
+    }
+    if let Some((_, cell_height)) = detect_cell_size() {
+        return Some(cell_height as u32 * lines);
+    }
+    // Inside tmux the window height is the whole terminal's, not the pane's
+    if detect_multiplexer() == Some(Multiplexer::Tmux) {
+        return None;
+    }
+    query_window_field(1)
+}
+
+/// Size of the tmux pane lsix runs in and of the client showing it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PaneGeometry {
+    /// Pane width in columns
+    pane_columns: u32,
+    /// Client (whole terminal) width in columns
+    client_columns: u32,
+    /// Cell width in pixels, reported by tmux >= 3.4
+    cell_width: Option<u32>,
+    /// Cell height in pixels, reported by tmux >= 3.4
+    cell_height: Option<u32>,
+}
+
+/// Format passed to `tmux display-message -p`
+const PANE_GEOMETRY_FORMAT: &str =
+    "#{pane_width} #{client_width} #{client_cell_width} #{client_cell_height}";
+
+/// Parse the output of `tmux display-message -p PANE_GEOMETRY_FORMAT`;
+/// older tmux versions leave the unknown cell size empty
+fn parse_pane_geometry(output: &str) -> Option<PaneGeometry> {
+    let mut fields = output.split_whitespace().map(|f| f.parse::<u32>().ok());
+    let pane_columns = fields.next()??;
+    let client_columns = fields.next()??;
+    let cell_width = fields.next().flatten().filter(|&w| w > 0);
+    let cell_height = fields.next().flatten().filter(|&h| h > 0);
+    (pane_columns > 0 && client_columns > 0).then_some(PaneGeometry {
+        pane_columns,
+        client_columns,
+        cell_width,
+        cell_height,
+    })
+}
+
+/// Ask tmux for the geometry of our own pane ($TMUX_PANE), which is not
+/// necessarily the active one
+fn tmux_pane_geometry() -> Option<PaneGeometry> {
+    let mut command = std::process::Command::new("tmux");
+    command.arg("display-message").arg("-p");
+    if let Ok(pane) = std::env::var("TMUX_PANE") {
+        command.args(["-t", &pane]);
+    }
+    let output = command
+        .arg(PANE_GEOMETRY_FORMAT)
+        .stderr(std::process::Stdio::null())
+        .output()
+        .ok()
+        .filter(|out| out.status.success())?;
+    parse_pane_geometry(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Pixel width of a pane, from the cell width when tmux knows it, otherwise
+/// by scaling the whole terminal's pixel width to the pane's share of columns
+fn pane_pixel_width(geometry: PaneGeometry, terminal_width: Option<u32>) -> Option<u32> {
+    match (geometry.cell_width, terminal_width) {
+        (Some(cell_width), _) => Some(geometry.pane_columns * cell_width),
+        (None, Some(width)) => {
+            let scaled = width as u64 * geometry.pane_columns as u64;
+            Some((scaled / geometry.client_columns as u64) as u32)
+        }
+        (None, None) => None,
+    }
+}
+
+/// Pixel width of the tmux pane lsix runs in
+fn tmux_pane_width() -> Option<u32> {
+    let geometry = tmux_pane_geometry()?;
+    // The terminal is only queried when tmux doesn't report the cell size
+    let terminal_width = geometry.cell_width.is_none().then(query_pixel_width).flatten();
+    pane_pixel_width(geometry, terminal_width).filter(|&width| width > 0)
+}
+
+/// Cell size from a window size in cells and pixels; `None` when the
+/// pixel size is unknown (reported as 0)
+fn cell_size_from_window(columns: u16, rows: u16, width: u16, height: u16) -> Option<(u16, u16)> {
+    if columns == 0 || rows == 0 {
+        return None;
+    }
+    let cell = (width / columns, height / rows);
+    (cell.0 > 0 && cell.1 > 0).then_some(cell)
+}
+
+/// Character cell size in pixels, without writing to the terminal: from the
+/// tty's window size, or from tmux, whose panes report no pixel size
+pub fn detect_cell_size() -> Option<(u16, u16)> {
+    let from_tty = crossterm::terminal::window_size()
+        .ok()
+        .and_then(|size| cell_size_from_window(size.columns, size.rows, size.width, size.height));
+    if from_tty.is_some() {
+        return from_tty;
+    }
+
+    if detect_multiplexer() != Some(Multiplexer::Tmux) {
+        return None;
+    }
+    let geometry = tmux_pane_geometry()?;
+    let width = u16::try_from(geometry.cell_width?).ok()?;
+    let height = u16::try_from(geometry.cell_height?).ok()?;
+    Some((width, height))
+}
+
+/// Pick the graphics protocol: LSIX_PROTOCOL wins, then Kitty if the
+/// terminal advertises it, otherwise SIXEL
+pub fn detect_protocol() -> Result<GraphicsProtocol> {
+    if let Ok(protocol) = std::env::var("LSIX_PROTOCOL") {
+        return parse_protocol(&protocol);
+    }
+
+    if detect_kitty() {
+        return Ok(GraphicsProtocol::Kitty);
+    }
+
+    Ok(GraphicsProtocol::Sixel)
+}
+
+/// Terminal multiplexer sitting between lsix and the real terminal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Multiplexer {
+    Tmux,
+    Screen,
+}
+
+/// Detect tmux/screen from the environment they export
+pub fn detect_multiplexer() -> Option<Multiplexer> {
+    if std::env::var("TMUX").is_ok() {
+        return Some(Multiplexer::Tmux);
+    }
+    if std::env::var("STY").is_ok() {
+        return Some(Multiplexer::Screen);
+    }
+
+    let term = std::env::var("TERM").unwrap_or_default();
+    if term.starts_with("tmux") {
+        Some(Multiplexer::Tmux)
+    } else if term.starts_with("screen") {
+        Some(Multiplexer::Screen)
+    } else {
+        None
+    }
+}
+
+/// Ask the tmux server whether the attached client can display SIXEL
+/// (tmux >= 3.4 built with --enable-sixel reports it in client_termfeatures)
+fn tmux_client_has_sixel() -> bool {
+    std::process::Command::new("tmux")
+        .args(["display-message", "-p", "#{client_termfeatures}"])
+        .stderr(std::process::Stdio::null())
+        .output()
+        .map(|out| String::from_utf8_lossy(&out.stdout).contains("sixel"))
+        .unwrap_or(false)
+}
+
+/// Check whether graphics written to stdout will actually reach a terminal
+/// that understands them; `has_sixel` is what detection found. Returns a
+/// warning describing the problem, if any.
+pub fn graphics_guard(protocol: GraphicsProtocol, has_sixel: bool) -> Option<String> {
+    use std::io::IsTerminal;
+
+    if protocol == GraphicsProtocol::Blocks {
+        return None;
+    }
+
+    if !io::stdout().is_terminal() {
+        return Some(
+            "stdout is not a terminal; graphics escape sequences will be written as raw data"
+                .to_string(),
+        );
+    }
+
+    // Behind tmux, what reaches the terminal is up to tmux
+    let multiplexer = detect_multiplexer();
+    let has_sixel = match multiplexer {
+        Some(Multiplexer::Tmux) => protocol == GraphicsProtocol::Sixel && tmux_client_has_sixel(),
+        _ => has_sixel,
+    };
+    graphics_problem(multiplexer, protocol, has_sixel).map(str::to_string)
+}
+
+/// Why graphics in `protocol` won't show behind `multiplexer`, when SIXEL
+/// support (through tmux, if that's the multiplexer) is `has_sixel`
+fn graphics_problem(
+    multiplexer: Option<Multiplexer>,
+    protocol: GraphicsProtocol,
+    has_sixel: bool,
+) -> Option<&'static str> {
+    match (multiplexer, protocol) {
+        (_, GraphicsProtocol::Blocks) => None,
+        (Some(Multiplexer::Screen), _) => {
+            Some("GNU screen does not pass inline graphics through to the terminal")
+        }
+        (Some(Multiplexer::Tmux), GraphicsProtocol::Sixel) if !has_sixel => Some(
+            "this tmux server/client does not report SIXEL support (needs tmux 3.4+ built with sixel)",
+        ),
+        (Some(Multiplexer::Tmux), GraphicsProtocol::Kitty) => {
+            Some("tmux does not forward the Kitty graphics protocol")
+        }
+        (_, GraphicsProtocol::Sixel) if !has_sixel => {
+            Some("the terminal does not report SIXEL support")
+        }
+        _ => None,
+    }
+}
+
+/// Primary Device Attributes queries sent after graphics, and the replies
+/// read back. There is one for the whole run: stdin is only read while a
+/// query is waited for, and a reply that arrives after its query timed out
+/// is matched to that query before the next one counts as answered.
+struct GraphicsProbe {
+    /// Queries whose reply hasn't been read yet
+    outstanding: usize,
+    /// How much of a reply (`ESC [ ? 6 2 ; 4 c`) the last read ended in
+    partial: ReplyState,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReplyState {
+    Idle,
+    Escape,
+    Csi,
+    Params,
+}
+
+static PROBE: Mutex<GraphicsProbe> =
+    Mutex::new(GraphicsProbe { outstanding: 0, partial: ReplyState::Idle });
+
+impl GraphicsProbe {
+    /// Send a query and wait up to `timeout` for every outstanding reply
+    fn query(&mut self, timeout: Duration) -> Result<bool> {
+        crossterm::terminal::enable_raw_mode()?;
+        print!("\x1b[c");
+        io::stdout().flush()?;
+        self.outstanding += 1;
+        let read = self.read_replies(Instant::now() + timeout);
+        crossterm::terminal::disable_raw_mode()?;
+        read?;
+        Ok(self.outstanding == 0)
+    }
+
+    /// Read stdin until no reply is owed or `deadline` passes. Anything
+    /// else typed meanwhile is dropped.
+    fn read_replies(&mut self, deadline: Instant) -> Result<()> {
+        let mut buf = [0u8; 256];
+        while self.outstanding > 0 {
+            let left = deadline.saturating_duration_since(Instant::now());
+            let n = read_stdin(&mut buf, left)?;
+            if n == 0 {
+                break;
+            }
+            for &byte in &buf[..n] {
+                self.feed(byte);
+            }
+        }
+        Ok(())
+    }
+
+    fn feed(&mut self, byte: u8) {
+        self.partial = match (self.partial, byte) {
+            (_, 0x1b) => ReplyState::Escape,
+            (ReplyState::Escape, b'[') => ReplyState::Csi,
+            (ReplyState::Csi, b'?') => ReplyState::Params,
+            (ReplyState::Params, b'0'..=b'9' | b';') => ReplyState::Params,
+            (ReplyState::Params, b'c') => {
+                self.outstanding = self.outstanding.saturating_sub(1);
+                ReplyState::Idle
+            }
+            _ => ReplyState::Idle,
+        };
+    }
+}
+
+/// Read what stdin has within `timeout`; 0 when nothing came
+#[cfg(unix)]
+fn read_stdin(buf: &mut [u8], timeout: Duration) -> Result<usize> {
+    let mut fd = libc::pollfd { fd: libc::STDIN_FILENO, events: libc::POLLIN, revents: 0 };
+    let millis = timeout.as_millis().min(i32::MAX as u128) as i32;
+    // SAFETY: `fd` is one valid pollfd
+    let ready = unsafe { libc::poll(&mut fd, 1, millis) };
+    if ready < 0 {
+        let error = io::Error::last_os_error();
+        return match error.kind() {
+            io::ErrorKind::Interrupted => Ok(0),
+            _ => Err(error.into()),
+        };
+    }
+    if ready == 0 {
+        return Ok(0);
+    }
+    // Straight from the descriptor: io::stdin() would buffer past the reply
+    // SAFETY: `buf` is valid for `buf.len()` bytes
+    let n = unsafe { libc::read(libc::STDIN_FILENO, buf.as_mut_ptr().cast(), buf.len()) };
+    Ok(n.max(0) as usize)
+}
+
+/// Without poll(2) stdin can't be read with a timeout, so nothing is read
+#[cfg(not(unix))]
+fn read_stdin(_buf: &mut [u8], _timeout: Duration) -> Result<usize> {
+    Ok(0)
+}
+
+/// After writing graphics, send a Primary Device Attributes query and wait
+/// for the answer. A terminal still stuck inside an unterminated or
+/// unsupported graphics sequence never answers, which is a good sign the
+/// image was mangled on the way. Returns `Ok(true)` if the terminal replied.
+pub fn verify_graphics_consumed(timeout_ms: u64) -> Result<bool> {
+    if cfg!(not(unix)) {
+        return Ok(true);
+    }
+    PROBE.lock().unwrap().query(Duration::from_millis(timeout_ms))
+}
+
+/// Read the replies still owed to `verify_graphics_consumed` at the end of
+/// the run, briefly, so the shell doesn't get them as input
+pub fn finish_graphics_checks() {
+    let mut probe = PROBE.lock().unwrap();
+    if probe.outstanding == 0 || crossterm::terminal::enable_raw_mode().is_err() {
+        return;
+    }
+    let _ = probe.read_replies(Instant::now() + Duration::from_millis(LATE_REPLY_WAIT_MS));
+    let _ = crossterm::terminal::disable_raw_mode();
+}
+
+/// The alternate screen (--alt-screen), entered for as long as the value
+/// lives. Dropping it restores the screen and scrollback from before, so
+/// graphics drawn meanwhile leave nothing behind, even after an error.
+pub struct AltScreen(());
+
+impl AltScreen {
+    /// Switch to a cleared alternate screen with the cursor at the top
+    pub fn enter() -> Result<AltScreen> {
+        use crossterm::{cursor, execute, terminal};
+
+        execute!(
+            io::stdout(),
+            terminal::EnterAlternateScreen,
+            terminal::Clear(terminal::ClearType::All),
+            cursor::MoveTo(0, 0)
+        )?;
+        Ok(AltScreen(()))
+    }
+
+    /// Say how to leave and wait for any key
+    pub fn wait_for_key(&self) -> Result<()> {
+        use crossterm::event::{self, Event, KeyEventKind};
+
+        io::stdout().flush()?;
+        eprint!("-- Press any key to return --");
+        io::stderr().flush()?;
+        crossterm::terminal::enable_raw_mode()?;
+        let result = loop {
+            match event::read() {
+                Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => break Ok(()),
+                Ok(_) => {}
+                Err(e) => break Err(e.into()),
+            }
+        };
+        crossterm::terminal::disable_raw_mode()?;
+        result
+    }
+}
+
+impl Drop for AltScreen {
+    fn drop(&mut self) {
+        crossterm::terminal::disable_raw_mode().ok();
+        crossterm::execute!(io::stdout(), crossterm::terminal::LeaveAlternateScreen).ok();
+    }
+}
+
 /// Auto-detect terminal capabilities and configuration
 /// Optimized for speed - uses smart defaults instead of slow queries
 pub fn autodetect() -> Result<TerminalConfig> {
-    // Fast detection based on TERM and environment variables
-    let has_sixel = detect_sixel()?;
+    autodetect_with_protocol(None, false)
+}
 
-    if !has_sixel {
-        anyhow::bail!(
-            "Your terminal does not report having sixel graphics support.\n\
-             Please use a sixel capable terminal, such as xterm -ti vt340.\n\
-             Or set LSIX_FORCE_SIXEL_SUPPORT=1 to force enable."
-        );
+/// Whether SIXEL reaches the terminal. Behind tmux the terminal can't be
+/// asked, so tmux is. Inside screen, or with `blocks_fallback`, a terminal
+/// without SIXEL isn't an error: `graphics_guard` then picks half blocks.
+fn sixel_reaches_terminal(blocks_fallback: bool) -> Result<bool> {
+    sixel_support(detect_sixel(), detect_multiplexer(), blocks_fallback, tmux_client_has_sixel)
+}
+
+/// `sixel_reaches_terminal` given what `detected` found behind
+/// `multiplexer`; `tmux_has_sixel` asks tmux
+fn sixel_support(
+    detected: Result<bool>,
+    multiplexer: Option<Multiplexer>,
+    blocks_fallback: bool,
+    tmux_has_sixel: impl FnOnce() -> bool,
+) -> Result<bool> {
+    match detected {
+        Ok(has_sixel) => Ok(has_sixel),
+        Err(_) if multiplexer == Some(Multiplexer::Tmux) => Ok(tmux_has_sixel()),
+        Err(_) if multiplexer.is_some() || blocks_fallback => Ok(false),
+        Err(e) => Err(e),
     }
+}
+
+/// Like `autodetect`, but use `protocol` (e.g. from the config file)
+/// instead of detecting one, and with `blocks_fallback` (--blocks-fallback)
+/// return `has_sixel: false` rather than fail when SIXEL isn't supported
+pub fn autodetect_with_protocol(
+    protocol: Option<GraphicsProtocol>,
+    blocks_fallback: bool,
+) -> Result<TerminalConfig> {
+    let protocol = match protocol {
+        Some(protocol) => protocol,
+        None => detect_protocol()?,
+    };
+
+    // Fast detection based on TERM and environment variables
+    // (Kitty graphics terminals don't need SIXEL)
+    let has_sixel =
+        protocol == GraphicsProtocol::Sixel && sixel_reaches_terminal(blocks_fallback)?;
 
     // Use smart defaults - no slow queries
     let num_colors = detect_colors()?;
     let (background, foreground) = detect_colorscheme()?;
     let width = detect_geometry()?;
+    let cell_size = detect_cell_size();
 
     Ok(TerminalConfig {
         has_sixel,
+        protocol,
         num_colors,
         width,
         background,
         foreground,
+        cell_size,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_window_field() {
+        assert_eq!(parse_window_field("\x1b[4;1080;1920t", 1), Some(1080));
+        assert_eq!(parse_window_field("\x1b[4;1080;1920t", 2), Some(1920));
+        assert_eq!(parse_window_field("\x1b[4;0;0t", 1), None);
+        assert_eq!(parse_window_field("", 1), None);
+    }
+
+    #[test]
+    fn test_pane_pixel_width() {
+        let geometry = parse_pane_geometry("80 200 10 21\n").unwrap();
+        assert_eq!(geometry.cell_width, Some(10));
+        assert_eq!(geometry.cell_height, Some(21));
+        assert_eq!(pane_pixel_width(geometry, Some(4000)), Some(800));
+
+        // tmux < 3.4: scale the terminal's width to the pane's columns
+        let geometry = parse_pane_geometry("80 200 \n").unwrap();
+        assert_eq!(geometry.cell_width, None);
+        assert_eq!(pane_pixel_width(geometry, Some(2400)), Some(960));
+        assert_eq!(pane_pixel_width(geometry, None), None);
+
+        assert_eq!(parse_pane_geometry(""), None);
+        assert_eq!(parse_pane_geometry("0 200 10"), None);
+    }
+
+    #[test]
+    fn test_graphics_probe_replies() {
+        let mut probe = GraphicsProbe { outstanding: 2, partial: ReplyState::Idle };
+        // Typed keys aren't replies; a reply may arrive split over reads
+        for &byte in b"abc\x1b[?62;4cq\x1b[?6" {
+            probe.feed(byte);
+        }
+        assert_eq!((probe.outstanding, probe.partial), (1, ReplyState::Params));
+        for &byte in b"2;4c" {
+            probe.feed(byte);
+        }
+        assert_eq!((probe.outstanding, probe.partial), (0, ReplyState::Idle));
+    }
+
+    #[test]
+    fn test_autodetect_in_screen() {
+        use GraphicsProtocol::{Blocks, Kitty, Sixel};
+        let screen = Some(Multiplexer::Screen);
+        let unanswered = || Err(anyhow::anyhow!("no answer"));
+        let never = || -> bool { unreachable!() };
+
+        // screen can't be queried: detection leaves the fallback to the guard
+        let has_sixel = sixel_support(unanswered(), screen, false, never).unwrap();
+        assert!(!has_sixel);
+        assert!(graphics_problem(screen, Sixel, has_sixel).is_some());
+        assert!(graphics_problem(screen, Blocks, has_sixel).is_none());
+
+        // Without a multiplexer only --blocks-fallback turns the error into
+        // a fallback; behind tmux, tmux is asked
+        assert!(sixel_support(unanswered(), None, false, never).is_err());
+        assert!(!sixel_support(unanswered(), None, true, never).unwrap());
+        assert!(sixel_support(unanswered(), Some(Multiplexer::Tmux), false, || true).unwrap());
+        assert!(sixel_support(Ok(true), screen, false, never).unwrap());
+
+        assert!(graphics_problem(None, Sixel, false).is_some());
+        assert!(graphics_problem(None, Sixel, true).is_none());
+        assert!(graphics_problem(Some(Multiplexer::Tmux), Sixel, true).is_none());
+        assert!(graphics_problem(Some(Multiplexer::Tmux), Kitty, true).is_some());
+    }
+
+    #[test]
+    fn test_cell_size_from_window() {
+        assert_eq!(cell_size_from_window(100, 40, 1000, 840), Some((10, 21)));
+        // Terminals that don't fill in the pixel size
+        assert_eq!(cell_size_from_window(100, 40, 0, 0), None);
+        assert_eq!(cell_size_from_window(0, 0, 1000, 840), None);
+    }
+}
@@ -0,0 +1,46 @@
+// Scratch directories for tests. Each is new and empty, and is removed when
+// the test ends, whether its assertions passed or not, so failing runs
+// don't leave lsix-* directories behind in the temp dir.
+
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// An empty directory under the system temp dir, deleted on drop
+pub struct TestDir(PathBuf);
+
+impl TestDir {
+    /// `lsix-<name>-<pid>-<n>`: tests running in parallel with the same
+    /// `name` each get their own
+    pub fn new(name: &str) -> TestDir {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+        let n = NEXT.fetch_add(1, Ordering::Relaxed);
+        let name = format!("lsix-{}-{}-{}", name, std::process::id(), n);
+        let path = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TestDir(path)
+    }
+}
+
+impl Deref for TestDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TestDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
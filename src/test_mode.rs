@@ -0,0 +1,115 @@
+// --test-mode (hidden): runs that produce the same output on any machine,
+// for integration tests and for reproducing bug reports. It
+//   - freezes the clock lsix stamps caches, history and exports with, and
+//     shows times in UTC instead of the local time zone,
+//   - replaces terminal detection with a fixed 80x24 terminal of 10x20
+//     pixel cells that takes 256-color SIXEL,
+//   - points the caches and the state directory at a fresh temp directory,
+//     so nothing left by earlier runs is reused (and nothing is left behind).
+//
+// The setting reaches the rest of the process through LSIX_TEST_MODE, like
+// LSIX_SKIP_QUERIES.
+
+use crate::terminal::{GraphicsProtocol, TerminalConfig};
+use chrono::{DateTime, FixedOffset, Utc};
+use std::path::PathBuf;
+
+const TEST_MODE_VAR: &str = "LSIX_TEST_MODE";
+
+/// The frozen clock: 2024-01-01 00:00:00 UTC
+pub const FROZEN_TIME: i64 = 1_704_067_200;
+
+/// Terminal size in cells, and the size of a cell in pixels
+pub const SCREEN_SIZE: (u16, u16) = (80, 24);
+pub const CELL_SIZE: (u16, u16) = (10, 20);
+
+/// Temp directory holding the caches and state of a test-mode run, removed
+/// when dropped
+#[derive(Debug)]
+pub struct TestDirs {
+    root: PathBuf,
+}
+
+impl Drop for TestDirs {
+    fn drop(&mut self) {
+        std::fs::remove_dir_all(&self.root).ok();
+    }
+}
+
+/// Switch the process to test mode; call before anything reads the clock,
+/// the terminal or a cache
+pub fn enable() -> TestDirs {
+    let root = std::env::temp_dir().join(format!("lsix-test-{}", std::process::id()));
+    std::env::set_var(TEST_MODE_VAR, "1");
+    std::env::set_var("LSIX_SKIP_QUERIES", "1");
+    std::env::set_var("LSIX_CACHE_DIR", root.join("cache"));
+    std::env::set_var("XDG_STATE_HOME", root.join("state"));
+    TestDirs { root }
+}
+
+pub fn enabled() -> bool {
+    std::env::var_os(TEST_MODE_VAR).is_some()
+}
+
+fn frozen_now() -> DateTime<Utc> {
+    DateTime::from_timestamp(FROZEN_TIME, 0).unwrap_or_default()
+}
+
+/// Current time, or the frozen time in test mode
+pub fn now() -> DateTime<Utc> {
+    if enabled() {
+        frozen_now()
+    } else {
+        Utc::now()
+    }
+}
+
+/// `time` in the local time zone, or in UTC in test mode
+pub fn local(time: DateTime<Utc>) -> DateTime<FixedOffset> {
+    in_zone(time, enabled())
+}
+
+fn in_zone(time: DateTime<Utc>, utc: bool) -> DateTime<FixedOffset> {
+    if utc {
+        time.fixed_offset()
+    } else {
+        time.with_timezone(&chrono::Local).fixed_offset()
+    }
+}
+
+/// Terminal size in cells, fixed in test mode
+pub fn terminal_size() -> std::io::Result<(u16, u16)> {
+    if enabled() {
+        Ok(SCREEN_SIZE)
+    } else {
+        crossterm::terminal::size()
+    }
+}
+
+/// The terminal test mode pretends to run in, drawing with `protocol` if
+/// one is configured
+pub fn stub_terminal(protocol: Option<GraphicsProtocol>) -> TerminalConfig {
+    let protocol = protocol.unwrap_or(GraphicsProtocol::Sixel);
+    TerminalConfig {
+        has_sixel: protocol == GraphicsProtocol::Sixel,
+        protocol,
+        num_colors: 256,
+        width: SCREEN_SIZE.0 as u32 * CELL_SIZE.0 as u32,
+        background: "black".to_string(),
+        foreground: "white".to_string(),
+        cell_size: Some(CELL_SIZE),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frozen_time() {
+        assert_eq!(frozen_now().to_rfc3339(), "2024-01-01T00:00:00+00:00");
+        assert_eq!(in_zone(frozen_now(), true).offset().local_minus_utc(), 0);
+        assert_eq!(stub_terminal(None).width, 800);
+        assert!(!stub_terminal(Some(GraphicsProtocol::Blocks)).has_sixel);
+    }
+}
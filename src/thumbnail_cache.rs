@@ -0,0 +1,60 @@
+// Downscaled thumbnails on disk (~/.cache/lsix/thumbs/<sha256>_<size>.png),
+// so the TUI doesn't decode full-resolution files again on every visit.
+// Keys are content hashes: renamed or copied files still hit the cache and
+// edited files miss it. A path index (thumbs/paths/) keeps the hash of each
+// file while its size and modification time are unchanged, so a revisit
+// doesn't read the originals again.
+
+use crate::ai_tagging::content_key;
+use crate::image_proc::{get_cache_dir, open_image};
+use crate::video_thumbs;
+use anyhow::Result;
+use image::DynamicImage;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Longest side of TUI grid thumbnails, in pixels
+pub const THUMBNAIL_SIZE: u32 = 256;
+
+fn thumbs_dir() -> Result<PathBuf> {
+    Ok(get_cache_dir()?.join("thumbs"))
+}
+
+/// Cache file for content with hash `hash` at `size`
+pub fn thumbnail_path(hash: &str, size: u32) -> Result<PathBuf> {
+    Ok(thumbs_dir()?.join(format!("{}_{}.png", hash, size)))
+}
+
+/// Load a thumbnail no larger than `size` x `size`, decoding the original
+/// and filling the cache on a miss. Cache write failures are ignored.
+pub fn load_thumbnail(path: &str, size: u32) -> Result<DynamicImage> {
+    // A video's frame is cached already; hashing the whole video would cost
+    // more than shrinking it
+    if video_thumbs::is_video(path) {
+        return Ok(video_thumbs::open_frame(path)?.thumbnail(size, size));
+    }
+    let cache_path = thumbnail_path(&content_key(&thumbs_dir()?, path)?, size)?;
+
+    if let Ok(thumb) = image::open(&cache_path) {
+        return Ok(thumb);
+    }
+
+    let thumb = open_image(path)?.thumbnail(size, size);
+    if let Some(parent) = cache_path.parent() {
+        if fs::create_dir_all(parent).is_ok() {
+            // Write then rename so a concurrent reader never sees a partial
+            // file; the decode pool may be writing the same one for a copy
+            static NEXT_TMP: AtomicUsize = AtomicUsize::new(0);
+            let tmp = cache_path.with_extension(format!(
+                "{}.{}.tmp",
+                std::process::id(),
+                NEXT_TMP.fetch_add(1, Ordering::Relaxed)
+            ));
+            if thumb.save_with_format(&tmp, image::ImageFormat::Png).is_ok() {
+                let _ = fs::rename(&tmp, &cache_path);
+            }
+        }
+    }
+    Ok(thumb)
+}
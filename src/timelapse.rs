@@ -0,0 +1,251 @@
+use crate::image_proc::{expand_directories, open_image};
+use crate::tools::{self, Tool};
+use crate::watermark::Watermark;
+use anyhow::{Context, Result};
+use image::{imageops::FilterType, DynamicImage, RgbaImage};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Options for assembling a time-lapse
+#[derive(Debug, Clone)]
+pub struct TimelapseConfig {
+    pub fps: u32,
+    /// Frames are fitted within this width (height follows the aspect ratio)
+    pub max_width: u32,
+    pub order: FrameOrder,
+    pub preview: bool,
+    /// Logo or text stamped onto every frame
+    pub watermark: Option<Watermark>,
+}
+
+/// How frames are ordered before assembly
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameOrder {
+    Name,  // By file name
+    Mtime, // By modification time, file name as tie-breaker
+}
+
+/// Parse frame order from string
+pub fn parse_frame_order(s: &str) -> Result<FrameOrder> {
+    match s.to_lowercase().as_str() {
+        "name" => Ok(FrameOrder::Name),
+        "time" | "mtime" => Ok(FrameOrder::Mtime),
+        _ => anyhow::bail!("Invalid frame order: {}. Use: name or time", s),
+    }
+}
+
+/// Output container, picked from the output file extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OutputFormat {
+    Gif, // Encoded in-process
+    Mp4, // Encoded by ffmpeg
+}
+
+fn output_format(out: &Path) -> Result<OutputFormat> {
+    match out
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .as_deref()
+    {
+        Some("gif") => Ok(OutputFormat::Gif),
+        Some("mp4") => Ok(OutputFormat::Mp4),
+        _ => anyhow::bail!(
+            "Unsupported output format: {}. Use a .gif or .mp4 file name",
+            out.display()
+        ),
+    }
+}
+
+/// Collect the frames of a sequence in display order
+pub fn collect_frames(dir: &str, order: FrameOrder) -> Vec<String> {
+    let mut frames = expand_directories(&[dir.to_string()]);
+
+    match order {
+        FrameOrder::Name => frames.sort(),
+        FrameOrder::Mtime => {
+            let mut timed: Vec<(std::time::SystemTime, String)> = frames
+                .into_iter()
+                .map(|path| {
+                    let modified = fs::metadata(&path)
+                        .and_then(|m| m.modified())
+                        .unwrap_or(std::time::UNIX_EPOCH);
+                    (modified, path)
+                })
+                .collect();
+            timed.sort();
+            frames = timed.into_iter().map(|(_, path)| path).collect();
+        }
+    }
+
+    frames
+}
+
+/// Assemble the images in `dir` into an animated GIF or MP4 at `out`
+pub fn run_timelapse(dir: &str, out: &str, config: &TimelapseConfig) -> Result<()> {
+    let out_path = Path::new(out);
+    let format = output_format(out_path)?;
+
+    if config.fps == 0 {
+        anyhow::bail!("--fps must be at least 1");
+    }
+
+    let frame_paths = collect_frames(dir, config.order);
+    if frame_paths.is_empty() {
+        anyhow::bail!("No image frames found in {}", dir);
+    }
+
+    eprintln!(
+        "Assembling {} frames at {} fps -> {}",
+        frame_paths.len(),
+        config.fps,
+        out_path.display()
+    );
+
+    let frames = load_frames(&frame_paths, config.max_width, config.watermark.as_ref())?;
+
+    match format {
+        OutputFormat::Gif => write_gif(&frames, config.fps, out_path)?,
+        OutputFormat::Mp4 => write_mp4(&frames, config.fps, out_path)?,
+    }
+
+    eprintln!("✓ Time-lapse written to {}", out_path.display());
+
+    if config.preview {
+        let preview: Vec<DynamicImage> = frames.into_iter().map(DynamicImage::ImageRgba8).collect();
+        crate::term_image::preview_animation(&preview, config.fps)?;
+    }
+
+    Ok(())
+}
+
+/// Decode all frames, scale them to a common size and stamp the watermark.
+/// The first frame decides the size; later frames are stretched to match.
+fn load_frames(
+    paths: &[String],
+    max_width: u32,
+    watermark: Option<&Watermark>,
+) -> Result<Vec<RgbaImage>> {
+    let progress = indicatif::ProgressBar::new(paths.len() as u64);
+    progress.set_style(
+        indicatif::ProgressStyle::default_bar()
+            .template("{spinner:.green} [{bar:40.cyan/blue}] {pos}/{len} {msg}")
+            .unwrap()
+            .progress_chars("##-"),
+    );
+
+    let mut frames = Vec::with_capacity(paths.len());
+    let mut size: Option<(u32, u32)> = None;
+
+    for path in paths {
+        progress.set_message(
+            Path::new(path)
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default(),
+        );
+
+        let img = match open_image(path) {
+            Ok(img) => img,
+            Err(e) => {
+                progress.println(format!("Warning: Skipping {}: {}", path, e));
+                progress.inc(1);
+                continue;
+            }
+        };
+
+        let (width, height) = *size.get_or_insert_with(|| {
+            let width = img.width().min(max_width);
+            let height = ((img.height() as u64 * width as u64) / img.width().max(1) as u64) as u32;
+            // Even dimensions keep yuv420p encoders happy
+            ((width & !1).max(2), (height & !1).max(2))
+        });
+
+        let mut frame = img.resize_exact(width, height, FilterType::Triangle).to_rgba8();
+        if let Some(watermark) = watermark {
+            watermark.apply(&mut frame);
+        }
+        frames.push(frame);
+        progress.inc(1);
+    }
+
+    progress.finish_and_clear();
+
+    if frames.is_empty() {
+        anyhow::bail!("None of the frames could be decoded");
+    }
+
+    Ok(frames)
+}
+
+fn write_gif(frames: &[RgbaImage], fps: u32, out: &Path) -> Result<()> {
+    use image::codecs::gif::{GifEncoder, Repeat};
+    use image::{Delay, Frame};
+
+    let file = fs::File::create(out).with_context(|| format!("Failed to create {}", out.display()))?;
+    let mut encoder = GifEncoder::new_with_speed(std::io::BufWriter::new(file), 10);
+    encoder.set_repeat(Repeat::Infinite)?;
+
+    let delay = Delay::from_numer_denom_ms(1000, fps);
+    for frame in frames {
+        encoder
+            .encode_frame(Frame::from_parts(frame.clone(), 0, 0, delay))
+            .context("Failed to encode GIF frame")?;
+    }
+
+    Ok(())
+}
+
+fn write_mp4(frames: &[RgbaImage], fps: u32, out: &Path) -> Result<()> {
+    let (width, height) = frames[0].dimensions();
+
+    // Raw RGBA frames are piped to ffmpeg, so frame files never need to exist on disk
+    let ffmpeg = tools::require(Tool::Ffmpeg, "MP4 output (.gif needs no external tools)")?;
+    let mut child = Command::new(ffmpeg)
+        .args(["-y", "-loglevel", "error", "-f", "rawvideo", "-pix_fmt", "rgba"])
+        .arg("-s")
+        .arg(format!("{}x{}", width, height))
+        .arg("-r")
+        .arg(fps.to_string())
+        .args(["-i", "-", "-c:v", "libx264", "-pix_fmt", "yuv420p"])
+        .arg(out)
+        .stdin(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .context("Failed to run ffmpeg")?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        for frame in frames {
+            stdin
+                .write_all(frame.as_raw())
+                .context("Failed to stream frames to ffmpeg")?;
+        }
+    }
+
+    let status = child.wait()?;
+    if !status.success() {
+        anyhow::bail!("ffmpeg failed with exit code: {:?}", status.code());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_format() {
+        assert_eq!(output_format(Path::new("out.gif")).unwrap(), OutputFormat::Gif);
+        assert_eq!(output_format(Path::new("out.MP4")).unwrap(), OutputFormat::Mp4);
+        assert!(output_format(Path::new("out.avi")).is_err());
+    }
+
+    #[test]
+    fn test_parse_frame_order() {
+        assert_eq!(parse_frame_order("name").unwrap(), FrameOrder::Name);
+        assert_eq!(parse_frame_order("time").unwrap(), FrameOrder::Mtime);
+        assert!(parse_frame_order("random").is_err());
+    }
+}
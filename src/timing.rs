@@ -0,0 +1,206 @@
+// Per-phase timing trace for --timing. Phases (scan, analyze, group,
+// render, output) and the items inside them (one image, one grid row)
+// record spans into a global list; the summary at the end shows where the
+// time went and which items were slowest. A phase named `parent/child` runs
+// inside its parent (possibly on several threads), so it is shown but not
+// added to the total.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Slowest items listed per phase
+const SLOWEST_ITEMS: usize = 3;
+
+/// Width of the bars in the summary
+const BAR_WIDTH: usize = 20;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static SPANS: Mutex<Vec<SpanRecord>> = Mutex::new(Vec::new());
+
+/// One finished span
+#[derive(Debug, Clone)]
+pub struct SpanRecord {
+    pub phase: &'static str,
+    /// Image path or row label; `None` for the phase as a whole
+    pub item: Option<String>,
+    pub duration: Duration,
+}
+
+/// Start recording spans
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Running span, recorded when dropped
+pub struct Span {
+    phase: &'static str,
+    item: Option<String>,
+    start: Option<Instant>,
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        if let Some(start) = self.start {
+            let record = SpanRecord {
+                phase: self.phase,
+                item: self.item.take(),
+                duration: start.elapsed(),
+            };
+            if let Ok(mut spans) = SPANS.lock() {
+                spans.push(record);
+            }
+        }
+    }
+}
+
+/// Time a whole phase
+pub fn phase(name: &'static str) -> Span {
+    Span {
+        phase: name,
+        item: None,
+        start: enabled().then(Instant::now),
+    }
+}
+
+/// Time one item of a phase
+pub fn item(phase: &'static str, item: &str) -> Span {
+    let start = enabled().then(Instant::now);
+    Span {
+        phase,
+        item: start.map(|_| item.to_string()),
+        start,
+    }
+}
+
+/// Summary of everything recorded so far
+pub fn summary() -> String {
+    let spans = SPANS.lock().map(|s| s.clone()).unwrap_or_default();
+    format_summary(&spans)
+}
+
+fn format_ms(duration: Duration) -> String {
+    format!("{:.1}ms", duration.as_secs_f64() * 1000.0)
+}
+
+/// Flame-style summary: one bar per phase in the order phases started,
+/// scaled to the sum of the phase times, plus the slowest items
+pub fn format_summary(spans: &[SpanRecord]) -> String {
+    let mut phases: Vec<&'static str> = Vec::new();
+    for span in spans {
+        if !phases.contains(&span.phase) {
+            phases.push(span.phase);
+        }
+    }
+
+    // A phase's time is its own span, or the sum of its items if it has none
+    let totals: Vec<(&str, Duration, Vec<&SpanRecord>)> = phases
+        .iter()
+        .map(|&phase| {
+            let whole = spans
+                .iter()
+                .filter(|s| s.phase == phase && s.item.is_none())
+                .map(|s| s.duration)
+                .sum::<Duration>();
+            let mut items: Vec<&SpanRecord> = spans
+                .iter()
+                .filter(|s| s.phase == phase && s.item.is_some())
+                .collect();
+            items.sort_by_key(|s| std::cmp::Reverse(s.duration));
+            let total = if whole.is_zero() {
+                items.iter().map(|s| s.duration).sum()
+            } else {
+                whole
+            };
+            (phase, total, items)
+        })
+        .collect();
+
+    let grand_total: Duration = totals
+        .iter()
+        .filter(|(phase, _, _)| !phase.contains('/'))
+        .map(|(_, total, _)| *total)
+        .sum();
+    let name_width = phases.iter().map(|p| p.len()).max().unwrap_or(0);
+
+    let mut out = format!("Timing (total {}):\n", format_ms(grand_total));
+    for (phase, total, items) in &totals {
+        let share = if grand_total.is_zero() {
+            0.0
+        } else {
+            total.as_secs_f64() / grand_total.as_secs_f64()
+        };
+        let filled = ((share * BAR_WIDTH as f64).round() as usize).min(BAR_WIDTH);
+        out.push_str(&format!(
+            "  {:<width$} {}{} {:>9} {:>5.1}%",
+            phase,
+            "█".repeat(filled),
+            "░".repeat(BAR_WIDTH - filled),
+            format_ms(*total),
+            share * 100.0,
+            width = name_width
+        ));
+
+        if !items.is_empty() {
+            let slowest: Vec<String> = items
+                .iter()
+                .take(SLOWEST_ITEMS)
+                .map(|s| {
+                    let item = s.item.as_deref().unwrap_or_default();
+                    let name = std::path::Path::new(item)
+                        .file_name()
+                        .map(|n| n.to_string_lossy().to_string())
+                        .unwrap_or_else(|| item.to_string());
+                    format!("{} {}", name, format_ms(s.duration))
+                })
+                .collect();
+            out.push_str(&format!("  ({} items; slowest: {})", items.len(), slowest.join(", ")));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(phase: &'static str, item: Option<&str>, ms: u64) -> SpanRecord {
+        SpanRecord {
+            phase,
+            item: item.map(String::from),
+            duration: Duration::from_millis(ms),
+        }
+    }
+
+    #[test]
+    fn test_format_summary() {
+        let summary = format_summary(&[
+            span("scan", None, 100),
+            span("render", Some("row 1"), 50),
+            span("render", Some("/photos/b.jpg"), 250),
+            span("render", Some("row 2"), 100),
+            span("render", Some("row 3"), 10),
+            span("render/image", Some("c.png"), 900),
+        ]);
+        let lines: Vec<&str> = summary.lines().collect();
+
+        assert_eq!(lines[0], "Timing (total 510.0ms):");
+        assert!(lines[1].starts_with("  scan  "));
+        // render has no phase span, so its items are summed
+        assert!(lines[2].contains("410.0ms"));
+        assert!(lines[2].contains("(4 items; slowest: b.jpg 250.0ms, row 2 100.0ms, row 1 50.0ms)"));
+        // Nested phases don't count towards the total
+        assert!(lines[3].starts_with("  render/image ████████████████████ "));
+    }
+
+    #[test]
+    fn test_disabled_spans_are_free() {
+        let span = item("analyze", "a.jpg");
+        assert!(span.start.is_none() && span.item.is_none());
+    }
+}
@@ -0,0 +1,167 @@
+// Optional external tools. Grids, tagging and exports need none of them, but
+// a few features hand their work to one: the ImageMagick encoder, filters
+// and size groups (ImageMagick), MP4 timelapses and video thumbnails
+// (ffmpeg), lossless JPEG rotation (jpegtran), text recognition
+// (tesseract), opening images in the desktop viewer (xdg-open) and copying
+// images to the clipboard (wl-copy on Wayland, xclip on X11).
+//
+// Each tool is looked for once, the first time a feature asks for it, and
+// the answer is kept for the rest of the run. A feature whose tool is
+// missing fails with what to install instead of the bare "No such file or
+// directory" that spawning it would give.
+
+use anyhow::Result;
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+
+/// An optional external tool
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tool {
+    ImageMagick,
+    Ffmpeg,
+    Jpegtran,
+    Tesseract,
+    XdgOpen,
+    WlCopy,
+    Xclip,
+}
+
+impl Tool {
+    pub const ALL: [Tool; 7] = [
+        Tool::ImageMagick,
+        Tool::Ffmpeg,
+        Tool::Jpegtran,
+        Tool::Tesseract,
+        Tool::XdgOpen,
+        Tool::WlCopy,
+        Tool::Xclip,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Tool::ImageMagick => "ImageMagick",
+            Tool::Ffmpeg => "ffmpeg",
+            Tool::Jpegtran => "jpegtran",
+            Tool::Tesseract => "tesseract",
+            Tool::XdgOpen => "xdg-open",
+            Tool::WlCopy => "wl-copy",
+            Tool::Xclip => "xclip",
+        }
+    }
+
+    /// Commands that provide the tool, tried in order (ImageMagick 7, then 6)
+    fn commands(self) -> &'static [&'static str] {
+        match self {
+            Tool::ImageMagick => &["magick", "convert"],
+            Tool::Ffmpeg => &["ffmpeg"],
+            Tool::Jpegtran => &["jpegtran"],
+            Tool::Tesseract => &["tesseract"],
+            Tool::XdgOpen => &["xdg-open"],
+            Tool::WlCopy => &["wl-copy"],
+            Tool::Xclip => &["xclip"],
+        }
+    }
+
+    fn version_flag(self) -> &'static str {
+        match self {
+            Tool::ImageMagick | Tool::Ffmpeg | Tool::Jpegtran | Tool::Xclip => "-version",
+            Tool::Tesseract | Tool::XdgOpen | Tool::WlCopy => "--version",
+        }
+    }
+
+    /// Package that installs the tool on most systems
+    pub fn package(self) -> &'static str {
+        match self {
+            Tool::ImageMagick => "imagemagick",
+            Tool::Ffmpeg => "ffmpeg",
+            Tool::Jpegtran => "libjpeg-turbo (libjpeg-turbo-progs on Debian)",
+            Tool::Tesseract => "tesseract (tesseract-ocr on Debian)",
+            Tool::XdgOpen => "xdg-utils",
+            Tool::WlCopy => "wl-clipboard",
+            Tool::Xclip => "xclip",
+        }
+    }
+
+    /// What works only with the tool
+    pub fn features(self) -> &'static str {
+        match self {
+            Tool::ImageMagick => "--encoder imagemagick, size/brightness filters, --group-by size",
+            Tool::Ffmpeg => "MP4 timelapses, video thumbnails",
+            Tool::Jpegtran => "lossless JPEG rotation in the TUI",
+            Tool::Tesseract => "text recognition",
+            Tool::XdgOpen => "opening images in the desktop viewer and maps in the browser",
+            Tool::WlCopy => "copying images to the clipboard on Wayland",
+            Tool::Xclip => "copying images to the clipboard on X11",
+        }
+    }
+}
+
+static FOUND: [OnceLock<Option<&'static str>>; Tool::ALL.len()] =
+    [const { OnceLock::new() }; Tool::ALL.len()];
+
+/// Whether `command` can be run at all; its exit status doesn't matter
+fn runs(command: &str, version_flag: &str) -> bool {
+    Command::new(command)
+        .arg(version_flag)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok()
+}
+
+/// The command `tool` runs as, or `None` if it isn't installed
+pub fn command(tool: Tool) -> Option<&'static str> {
+    *FOUND[tool as usize].get_or_init(|| {
+        tool.commands().iter().copied().find(|command| runs(command, tool.version_flag()))
+    })
+}
+
+pub fn available(tool: Tool) -> bool {
+    command(tool).is_some()
+}
+
+/// Error for `feature`, which can't run without `tool`
+fn missing(tool: Tool, feature: &str) -> anyhow::Error {
+    anyhow::anyhow!(
+        "{} needs {}, which was not found (looked for {} on PATH). Install the {} package.",
+        feature,
+        tool.name(),
+        tool.commands().join(" or "),
+        tool.package()
+    )
+}
+
+/// The command to run `tool` with, or an error naming what to install for
+/// `feature`
+pub fn require(tool: Tool, feature: &str) -> Result<&'static str> {
+    command(tool).ok_or_else(|| missing(tool, feature))
+}
+
+/// One line per tool saying which command was found, or what is missing
+/// without it
+pub fn report() -> Vec<String> {
+    Tool::ALL
+        .iter()
+        .map(|&tool| match command(tool) {
+            Some(command) => format!("{}: {}", tool.name(), command),
+            None => format!("{}: missing (needed for {})", tool.name(), tool.features()),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_message() {
+        let error = missing(Tool::ImageMagick, "--encoder imagemagick");
+        assert_eq!(
+            error.to_string(),
+            "--encoder imagemagick needs ImageMagick, which was not found (looked for magick or \
+             convert on PATH). Install the imagemagick package."
+        );
+        assert_eq!(report().len(), Tool::ALL.len());
+    }
+}
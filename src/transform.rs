@@ -0,0 +1,158 @@
+// Saving a rotation or mirroring made in the fullscreen view to the image
+// file. JPEGs go through jpegtran, which turns the compressed blocks without
+// decoding them, so no quality is lost and the metadata is kept. Other
+// formats, and JPEGs when jpegtran is missing or their size rules out a
+// perfect transform, are decoded, turned and encoded again. The new file
+// replaces the old one only once it is complete.
+
+use crate::tools::{self, Tool};
+use crate::view_state::ViewState;
+use anyhow::{Context, Result};
+use image::codecs::jpeg::JpegEncoder;
+use image::ImageFormat;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Quality of JPEGs that have to be encoded again
+const JPEG_QUALITY: u8 = 95;
+
+/// How the transformation was written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SaveMethod {
+    /// Without decoding (jpegtran)
+    Lossless,
+    /// Decoded and encoded again
+    Reencoded,
+}
+
+impl SaveMethod {
+    pub fn label(self) -> &'static str {
+        match self {
+            SaveMethod::Lossless => "lossless",
+            SaveMethod::Reencoded => "re-encoded",
+        }
+    }
+}
+
+/// The jpegtran transform for mirroring (first) and clockwise quarter
+/// turns; `None` when the image stays as it is
+fn jpegtran_args(flip: bool, rotation: u8) -> Option<&'static [&'static str]> {
+    match (flip, rotation % 4) {
+        (false, 0) => None,
+        (false, 1) => Some(&["-rotate", "90"]),
+        (false, 2) => Some(&["-rotate", "180"]),
+        (false, 3) => Some(&["-rotate", "270"]),
+        (true, 0) => Some(&["-flip", "horizontal"]),
+        (true, 1) => Some(&["-transverse"]),
+        (true, 2) => Some(&["-flip", "vertical"]),
+        _ => Some(&["-transpose"]),
+    }
+}
+
+fn is_jpeg(path: &str) -> bool {
+    ImageFormat::from_path(path).is_ok_and(|format| format == ImageFormat::Jpeg)
+}
+
+/// How saving `path` would go: lossless for JPEGs when jpegtran is
+/// installed
+pub fn planned_method(path: &str) -> SaveMethod {
+    if is_jpeg(path) && tools::available(Tool::Jpegtran) {
+        SaveMethod::Lossless
+    } else {
+        SaveMethod::Reencoded
+    }
+}
+
+/// Temporary file next to `path` for the transformed image
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    path.with_file_name(format!(".{}.lsix-tmp", name))
+}
+
+fn run_jpegtran(path: &str, args: &[&str], out: &Path) -> Result<()> {
+    let status = Command::new("jpegtran")
+        .args(args)
+        .args(["-perfect", "-copy", "all", "-outfile"])
+        .arg(out)
+        .arg(path)
+        .stderr(Stdio::null())
+        .status()
+        .context("Failed to run jpegtran")?;
+    if !status.success() {
+        anyhow::bail!("jpegtran can't transform {} perfectly", path);
+    }
+    Ok(())
+}
+
+fn reencode(path: &str, view: &ViewState, out: &Path) -> Result<()> {
+    let format = ImageFormat::from_path(path)
+        .with_context(|| format!("Unknown image format: {}", path))?;
+    let image = image::open(path).with_context(|| format!("Failed to open {}", path))?;
+    let turned = view.rotated(&image);
+    if format == ImageFormat::Jpeg {
+        let file = fs::File::create(out)?;
+        let encoder = JpegEncoder::new_with_quality(std::io::BufWriter::new(file), JPEG_QUALITY);
+        turned.to_rgb8().write_with_encoder(encoder)?;
+    } else {
+        turned
+            .save_with_format(out, format)
+            .with_context(|| format!("Can't save {} images", format.extensions_str()[0]))?;
+    }
+    Ok(())
+}
+
+/// Write the rotation and mirroring of `view` into the file at `path`
+pub fn save_transform(path: &str, view: &ViewState) -> Result<SaveMethod> {
+    let Some(args) = jpegtran_args(view.flip, view.rotation) else {
+        anyhow::bail!("The image is neither rotated nor mirrored");
+    };
+    let temp = temp_path(Path::new(path));
+    let lossless = is_jpeg(path) && tools::available(Tool::Jpegtran);
+    let result = if lossless && run_jpegtran(path, args, &temp).is_ok() {
+        Ok(SaveMethod::Lossless)
+    } else {
+        reencode(path, view, &temp).map(|()| SaveMethod::Reencoded)
+    };
+    let result = result.and_then(|method| {
+        fs::rename(&temp, path).with_context(|| format!("Failed to replace {}", path))?;
+        Ok(method)
+    });
+    if result.is_err() {
+        fs::remove_file(&temp).ok();
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn test_jpegtran_args() {
+        assert_eq!(jpegtran_args(false, 0), None);
+        assert_eq!(jpegtran_args(false, 3), Some(&["-rotate", "270"][..]));
+        assert_eq!(jpegtran_args(true, 1), Some(&["-transverse"][..]));
+        assert_eq!(jpegtran_args(true, 3), Some(&["-transpose"][..]));
+    }
+
+    #[test]
+    fn test_save_transform_png() {
+        let dir = TestDir::new("transform");
+        let path = dir.join("wide.png").to_string_lossy().to_string();
+        image::RgbImage::from_fn(4, 2, |x, _| image::Rgb([x as u8 * 60, 0, 0]))
+            .save(&path)
+            .unwrap();
+
+        let view = ViewState { rotation: 1, flip: true, ..ViewState::default() };
+        assert_eq!(save_transform(&path, &view).unwrap(), SaveMethod::Reencoded);
+        let saved = image::open(&path).unwrap().to_rgb8();
+        assert_eq!(saved.dimensions(), (2, 4));
+        // Mirrored, then turned clockwise: the right edge ends up on top
+        assert_eq!(saved.get_pixel(0, 0)[0], 180);
+        assert!(!temp_path(Path::new(&path)).exists());
+
+        assert!(save_transform(&path, &ViewState::default()).is_err());
+    }
+}
@@ -0,0 +1,258 @@
+// Custom actions: shell commands from the `[actions]` config table, bound
+// to TUI keys and run on the selected images (or the one under the cursor).
+//
+//   [actions]
+//   e = "gimp {}"
+//   u = "rsync -a {} backup:photos/"
+//
+// `{}` is replaced with the quoted image path, which is appended when the
+// command has no `{}`. Commands run one at a time on a worker thread with
+// stdin closed; their output is captured for the TUI's output pane instead
+// of being written over the screen.
+//
+// The `o` key is different: it opens the image under the cursor with a
+// viewer or editor (--open-with, `[tui] open_with`, else xdg-open) that
+// gets the terminal to itself until it exits.
+
+use crate::tools::{self, Tool};
+use anyhow::Result;
+use crossterm::event::KeyCode;
+use std::collections::HashMap;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender};
+
+/// A command bound to a key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CustomAction {
+    pub key: KeyCode,
+    pub command: String,
+}
+
+/// Parse the `[actions]` table, key => command, in key order
+pub fn parse_actions(actions: &HashMap<String, String>) -> Result<Vec<CustomAction>> {
+    let mut entries: Vec<(&String, &String)> = actions.iter().collect();
+    entries.sort();
+    entries
+        .into_iter()
+        .map(|(key, command)| {
+            if command.trim().is_empty() {
+                anyhow::bail!("Action for key {} has no command", key);
+            }
+            Ok(CustomAction {
+                key: crate::keymap::parse_key(key)?,
+                command: command.clone(),
+            })
+        })
+        .collect()
+}
+
+/// Quote `text` for `sh`
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+/// `template` with every `{}` replaced by the quoted `path`, or with the
+/// path appended if there is no `{}`
+pub fn expand_command(template: &str, path: &str) -> String {
+    let path = shell_quote(path.trim_end_matches("[0]"));
+    if template.contains("{}") {
+        template.replace("{}", &path)
+    } else {
+        format!("{} {}", template, path)
+    }
+}
+
+/// Command the `o` key opens images with: `open_with` (--open-with,
+/// LSIX_OPEN_WITH or `[tui] open_with`), or else xdg-open
+pub fn open_command(open_with: Option<&str>) -> Result<String> {
+    match open_with {
+        Some(command) if !command.trim().is_empty() => Ok(command.to_string()),
+        _ => Ok(tools::require(Tool::XdgOpen, "Opening images without --open-with")?.to_string()),
+    }
+}
+
+/// How an invocation went
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RunStatus {
+    /// Waiting for earlier commands, or running
+    Pending,
+    /// Exited with this code
+    Exited(i32),
+    /// Killed by a signal
+    Killed,
+    /// Couldn't be started
+    Failed(String),
+}
+
+impl RunStatus {
+    pub fn is_done(&self) -> bool {
+        *self != RunStatus::Pending
+    }
+
+    pub fn is_success(&self) -> bool {
+        *self == RunStatus::Exited(0)
+    }
+}
+
+/// One command run on one image
+#[derive(Debug, Clone)]
+pub struct Invocation {
+    pub path: String,
+    /// The command as run, with the path filled in
+    pub command: String,
+    pub status: RunStatus,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+type Job = (usize, String);
+type JobResult = (usize, RunStatus, String, String);
+
+/// Runs invocations in order on a worker thread and keeps all of them, so
+/// the output pane can show earlier runs and retry them
+pub struct ActionRunner {
+    jobs: Option<Sender<Job>>,
+    tx: Sender<JobResult>,
+    rx: Receiver<JobResult>,
+    pub invocations: Vec<Invocation>,
+}
+
+impl ActionRunner {
+    pub fn new() -> Self {
+        let (tx, rx) = mpsc::channel();
+        ActionRunner { jobs: None, tx, rx, invocations: Vec::new() }
+    }
+
+    /// Queue `template` for `path`. Returns the invocation's index.
+    pub fn run(&mut self, template: &str, path: &str) -> usize {
+        self.queue(path.to_string(), expand_command(template, path))
+    }
+
+    /// Queue invocation `idx` again, as a new invocation
+    pub fn retry(&mut self, idx: usize) -> Option<usize> {
+        let invocation = self.invocations.get(idx)?;
+        Some(self.queue(invocation.path.clone(), invocation.command.clone()))
+    }
+
+    fn queue(&mut self, path: String, command: String) -> usize {
+        let idx = self.invocations.len();
+        self.invocations.push(Invocation {
+            path,
+            command: command.clone(),
+            status: RunStatus::Pending,
+            stdout: String::new(),
+            stderr: String::new(),
+        });
+
+        let jobs = self.jobs.get_or_insert_with(|| {
+            let (jobs, queue) = mpsc::channel::<Job>();
+            let tx = self.tx.clone();
+            std::thread::spawn(move || {
+                for (idx, command) in queue {
+                    let (status, stdout, stderr) = run_shell(&command);
+                    if tx.send((idx, status, stdout, stderr)).is_err() {
+                        break;
+                    }
+                }
+            });
+            jobs
+        });
+        if jobs.send((idx, command)).is_err() {
+            self.invocations[idx].status = RunStatus::Failed("Action worker stopped".to_string());
+        }
+        idx
+    }
+
+    /// Whether any invocation hasn't finished
+    pub fn is_busy(&self) -> bool {
+        self.invocations.iter().any(|i| !i.status.is_done())
+    }
+
+    /// Record finished invocations without blocking. Returns their indices.
+    pub fn drain(&mut self) -> Vec<usize> {
+        let mut finished = Vec::new();
+        while let Ok((idx, status, stdout, stderr)) = self.rx.try_recv() {
+            let invocation = &mut self.invocations[idx];
+            invocation.status = status;
+            invocation.stdout = stdout;
+            invocation.stderr = stderr;
+            finished.push(idx);
+        }
+        finished
+    }
+}
+
+impl Default for ActionRunner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Run `command` with `sh -c`, capturing its output
+fn run_shell(command: &str) -> (RunStatus, String, String) {
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .output();
+    match output {
+        Ok(output) => (
+            match output.status.code() {
+                Some(code) => RunStatus::Exited(code),
+                None => RunStatus::Killed,
+            },
+            String::from_utf8_lossy(&output.stdout).to_string(),
+            String::from_utf8_lossy(&output.stderr).to_string(),
+        ),
+        Err(e) => (RunStatus::Failed(e.to_string()), String::new(), String::new()),
+    }
+}
+
+/// Run `template` on `path` with `sh -c`, attached to the terminal, and
+/// wait for it to exit
+pub fn run_attached(template: &str, path: &str) -> RunStatus {
+    match Command::new("sh").arg("-c").arg(expand_command(template, path)).status() {
+        Ok(status) => match status.code() {
+            Some(code) => RunStatus::Exited(code),
+            None => RunStatus::Killed,
+        },
+        Err(e) => RunStatus::Failed(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_command() {
+        assert_eq!(expand_command("gimp {}", "a b.jpg"), "gimp 'a b.jpg'");
+        assert_eq!(expand_command("cp {} {}.bak", "x.png"), "cp 'x.png' 'x.png'.bak");
+        assert_eq!(expand_command("open", "it's.gif[0]"), r"open 'it'\''s.gif'");
+        assert_eq!(run_attached("test -n", "a.jpg"), RunStatus::Exited(0));
+        assert_eq!(run_attached("exit 2 #", "a.jpg"), RunStatus::Exited(2));
+    }
+
+    #[test]
+    fn test_action_runner() {
+        let mut runner = ActionRunner::new();
+        let ok = runner.run("echo hello", "a.jpg");
+        let failed = runner.run("echo oops >&2; exit 3 #", "b.jpg");
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+        while runner.is_busy() && std::time::Instant::now() < deadline {
+            runner.drain();
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+
+        let ok = &runner.invocations[ok];
+        assert!(ok.status.is_success());
+        assert_eq!(ok.stdout, "hello a.jpg\n");
+        let failed = &runner.invocations[failed];
+        assert_eq!(failed.status, RunStatus::Exited(3));
+        assert_eq!(failed.stderr, "oops\n");
+
+        let retried = runner.retry(1).unwrap();
+        assert_eq!(runner.invocations[retried].command, runner.invocations[1].command);
+        assert_eq!(runner.invocations[retried].status, RunStatus::Pending);
+    }
+}
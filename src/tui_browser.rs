@@ -1,20 +1,52 @@
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyModifiers},
+    event::{
+        self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyModifiers,
+        MouseButton, MouseEvent, MouseEventKind,
+    },
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use ratatui::{
     backend::CrosstermBackend,
-    layout::{Constraint, Direction, Layout, Rect},
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
-    text::{Span, Text},
-    widgets::{Block, Borders, ListState, Paragraph},
+    text::{Line, Span, Text},
+    widgets::{Block, Borders, Clear, ListState, Paragraph, Wrap},
     Frame, Terminal,
 };
-use std::fs::OpenOptions;
+use std::fs;
 use std::io::{self, stdout, Write};
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+use crate::app_dirs;
+use crate::bulk_rename::{self, RenamePlan};
+use crate::clipboard;
+use crate::config;
+use crate::culling::{self, Flag};
+use crate::duplicates::DuplicateGroup;
+use crate::file_ops::{self, FileOp};
+use crate::geo_map::{self, MapLoader, MapState};
+use crate::diagnostics::{self, Verbosity};
+use crate::dir_tree::DirTree;
+use crate::group_edits::GroupEdits;
+use crate::group_names::GroupNames;
+use crate::grouping::{group_images, GroupBy, GroupMetadata, ImageGroup};
+use crate::ai_tagging::{load_cached_tags, AITaggingConfig, AITags, DetectedObject};
+use crate::image_cache::{ImageCache, DEFAULT_CACHE_MB};
+use crate::image_info::ImageDetails;
+use crate::image_proc::{expand_directories, open_image};
+use crate::keymap::{self, KeyBindings};
+use crate::paths::{canonicalize_path, locations_badge, Locations};
+use crate::sorting::{self, SortKey, SortSpec};
+use crate::thumbnail_cache;
+use crate::transform::{self, SaveMethod};
+use crate::tui_actions::{self, ActionRunner, CustomAction, Invocation, RunStatus};
+use crate::tui_command::{self, Command, FilterTerm, ItemInfo};
+use crate::video_thumbs;
+use crate::positions::{self, Position, Positions};
+use crate::view_state::{FitMode, ViewState, ViewStates};
+use crate::watch::{Changes, DirWatcher};
 
 fn is_logging_enabled() -> bool {
     std::env::var("LSIX_ENABLE_LOG").is_ok()
@@ -25,19 +57,354 @@ fn trace_log(msg: &str) {
         return;
     }
     
-    if let Ok(mut file) = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open("/tmp/lsix_tui.log")
-    {
+    if let Ok(mut file) = app_dirs::open_log(false) {
         let timestamp = chrono::Local::now().format("%H:%M:%S%.3f");
         writeln!(file, "[{}] {}", timestamp, msg).ok();
     }
 }
 
-use image::{imageops::FilterType, ImageReader};
+use image::imageops::FilterType;
 use ratatui_image::{picker::Picker, Resize, StatefulImage};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::mpsc::{self, Receiver, Sender};
+
+/// Part of an image shown in fullscreen: a source rectangle and the pixel
+/// size it is scaled to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Viewport {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    out_width: u32,
+    out_height: u32,
+}
+
+impl Viewport {
+    /// Fit `image` (pixels) into `display` (pixels) with `mode`, magnified
+    /// by `zoom`. When the scaled image overflows the display, `pan` (0.0
+    /// to 1.0 on each axis, 0.5 centered) picks the visible part.
+    fn new(
+        mode: FitMode,
+        zoom: f32,
+        image: (u32, u32),
+        display: (u32, u32),
+        pan: (f32, f32),
+    ) -> Viewport {
+        let (width, height) = (image.0.max(1) as f32, image.1.max(1) as f32);
+        let (display_width, display_height) = (display.0.max(1) as f32, display.1.max(1) as f32);
+        let fitted = match mode {
+            FitMode::Fit => (display_width / width).min(display_height / height),
+            FitMode::Fill => (display_width / width).max(display_height / height),
+            FitMode::ActualSize => 1.0,
+            FitMode::FitWidth => display_width / width,
+            FitMode::FitHeight => display_height / height,
+        };
+        let scale = fitted * zoom;
+
+        // Visible part of the image in source pixels
+        let visible_width = (display_width / scale).min(width);
+        let visible_height = (display_height / scale).min(height);
+        let x = ((width - visible_width) * pan.0.clamp(0.0, 1.0)) as u32;
+        let y = ((height - visible_height) * pan.1.clamp(0.0, 1.0)) as u32;
+
+        Viewport {
+            x,
+            y,
+            width: (visible_width.round() as u32).clamp(1, image.0.max(1) - x),
+            height: (visible_height.round() as u32).clamp(1, image.1.max(1) - y),
+            out_width: ((visible_width * scale).round() as u32).max(1),
+            out_height: ((visible_height * scale).round() as u32).max(1),
+        }
+    }
+
+    /// Whether part of an `image` sized image is cut off, so it can be panned
+    fn is_cropped(&self, image: (u32, u32)) -> bool {
+        self.width < image.0 || self.height < image.1
+    }
+}
+
+/// Captions under the grid thumbnails
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GridLabels {
+    /// File names
+    Names,
+    /// File names after the image's number in the list
+    Numbered,
+    Off,
+}
+
+impl GridLabels {
+    /// Captions the labels key switches to
+    pub fn next(self) -> GridLabels {
+        match self {
+            GridLabels::Names => GridLabels::Numbered,
+            GridLabels::Numbered => GridLabels::Off,
+            GridLabels::Off => GridLabels::Names,
+        }
+    }
+
+    /// Caption of the image at `index` for a cell `width` columns wide
+    fn caption(self, index: usize, path: &str, width: usize) -> Option<String> {
+        let name = Path::new(path).file_name().map_or(path.into(), |n| n.to_string_lossy());
+        let caption = match self {
+            GridLabels::Names => name.to_string(),
+            GridLabels::Numbered => format!("{}. {}", index + 1, name),
+            GridLabels::Off => return None,
+        };
+        Some(truncate_middle(&caption, width))
+    }
+}
+
+/// `text` cut to `width` characters in the middle, so the start and the
+/// extension of a file name both stay visible
+fn truncate_middle(text: &str, width: usize) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    if chars.len() <= width {
+        return text.to_string();
+    }
+    if width == 0 {
+        return String::new();
+    }
+    let tail = (width - 1) / 2;
+    let head = width - 1 - tail;
+    let mut cut: String = chars[..head].iter().collect();
+    cut.push('…');
+    cut.extend(&chars[chars.len() - tail..]);
+    cut
+}
+
+/// Overlays on a grid thumbnail from the image's cached tags
+#[derive(Debug, Default, PartialEq)]
+struct Badges {
+    /// Stars of a `rating:N` tag, 0 to 5
+    rating: Option<u8>,
+    /// Pick or reject
+    flag: Option<Flag>,
+    nsfw: bool,
+    /// The first tags, without the rating, flag and content rating
+    tags: Vec<String>,
+}
+
+impl Badges {
+    fn new(tags: &AITags, max_tags: usize) -> Badges {
+        Badges {
+            rating: culling::stars(&tags.tags),
+            flag: culling::flag(&tags.tags),
+            nsfw: tags.content_rating.as_deref() == Some("nsfw"),
+            tags: tags
+                .tags
+                .iter()
+                .filter(|tag| !culling::is_culling_tag(tag) && *tag != "sfw" && *tag != "nsfw")
+                .take(max_tags)
+                .cloned()
+                .collect(),
+        }
+    }
+
+    fn stars(rating: u8) -> String {
+        "★".repeat(rating as usize) + &"☆".repeat(5 - rating as usize)
+    }
+
+    /// The pick/reject and NSFW flags and the tags, fitted into `width`
+    /// columns
+    fn bottom_line(&self, width: usize) -> Line<'static> {
+        let mut spans = Vec::new();
+        let mut width = width;
+        if let Some(flag) = self.flag {
+            let (text, color) = match flag {
+                Flag::Pick => (" ✓ PICK ", Color::Green),
+                Flag::Reject => (" ✗ REJECT ", Color::DarkGray),
+            };
+            width = width.saturating_sub(text.chars().count());
+            spans.push(Span::styled(
+                text,
+                Style::default().fg(Color::Black).bg(color).add_modifier(Modifier::BOLD),
+            ));
+        }
+        if self.nsfw {
+            let flag = " NSFW ";
+            width = width.saturating_sub(flag.len());
+            spans.push(Span::styled(
+                flag,
+                Style::default().fg(Color::White).bg(Color::Red).add_modifier(Modifier::BOLD),
+            ));
+        }
+        if !self.tags.is_empty() && width > 2 {
+            let tags = truncate_end(&self.tags.join(" · "), width - 2);
+            spans.push(Span::styled(format!(" {} ", tags), Style::default().fg(Color::Cyan)));
+        }
+        Line::from(spans)
+    }
+}
+
+/// `text` cut to `width` characters, ending in "…" when cut
+fn truncate_end(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+    let mut cut: String = text.chars().take(width.saturating_sub(1)).collect();
+    cut.push('…');
+    cut
+}
+
+/// Operations applied to the selection (or the image under the cursor)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatchAction {
+    File(FileOp),
+    /// Rename several images from a pattern such as `trip_{n:3}`; with
+    /// `suffix`, names that collide are numbered
+    RenameSequence { suffix: bool },
+    /// View, add and remove tags in the tag cache
+    Tag,
+    /// Write the paths to a list file
+    Export,
+    /// Name the group of the image under the cursor
+    RenameGroup,
+    /// A `:filter`, `:sort` or `:group` command typed into the status bar
+    Command,
+    /// A `/` search typed into the status bar, applied as it is typed
+    Search,
+    /// Write the fullscreen rotation and mirroring into the file, the way
+    /// the prompt announces
+    SaveView(SaveMethod),
+}
+
+/// A batch action waiting for confirmation (trash/delete) or for text
+/// input (new name, directory, tags, list file, group name)
+pub struct Prompt {
+    pub action: BatchAction,
+    pub targets: Vec<String>,
+    pub input: String,
+    /// Tags of the targets, with how many targets have each (tag editor only)
+    pub tags: Vec<(String, usize)>,
+}
+
+/// Position in the output pane of custom actions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputPane {
+    /// Invocation whose output is shown
+    pub selected: usize,
+    /// Output lines scrolled past
+    pub scroll: u16,
+}
+
+/// Outline colors for detected objects, cycled in detection order
+const BOX_COLORS: [(Color, [u8; 3]); 6] = [
+    (Color::Red, [230, 40, 40]),
+    (Color::Green, [40, 210, 60]),
+    (Color::Blue, [50, 110, 240]),
+    (Color::Yellow, [240, 210, 30]),
+    (Color::Magenta, [220, 50, 220]),
+    (Color::Cyan, [40, 210, 220]),
+];
+
+/// Renames listed in the preview of a sequence rename
+const RENAME_PREVIEW_LINES: usize = 8;
+
+/// Spinner frames shown while a thumbnail is loading
+const SPINNER: [&str; 4] = ["|", "/", "-", "\\"];
+
+/// Longest gap between the clicks of a double click
+const DOUBLE_CLICK: std::time::Duration = std::time::Duration::from_millis(400);
+
+/// Thumbnails per grid row unless `[tui] grid_columns` says otherwise
+const DEFAULT_GRID_COLUMNS: u16 = 5;
+
+/// Most thumbnails per grid row the - key goes to
+const MAX_GRID_COLUMNS: u16 = 12;
+
+/// Tags shown on each thumbnail unless `[tui] badge_tags` says otherwise
+const DEFAULT_BADGE_TAGS: usize = 3;
+
+/// Width of the folder sidebar in columns (at most half the screen)
+const SIDEBAR_WIDTH: u16 = 28;
+
+/// Height of the map in the info panel in rows (at most half the panel)
+const MAP_ROWS: u16 = 14;
+
+/// Id of the group that images added under --watch join when browsing groups
+const NEW_IMAGES_GROUP: &str = "watch-new";
+
+type ThumbnailResult = (String, Result<image::DynamicImage, String>);
+
+/// Decodes grid thumbnails on a worker pool so the draw loop never blocks
+pub struct ThumbnailLoader {
+    pool: Option<rayon::ThreadPool>,
+    tx: Sender<ThumbnailResult>,
+    rx: Receiver<ThumbnailResult>,
+    pending: HashSet<String>,
+    failed: HashSet<String>,
+}
+
+impl ThumbnailLoader {
+    pub fn new() -> Self {
+        let (tx, rx) = mpsc::channel();
+        let pool = rayon::ThreadPoolBuilder::new()
+            .thread_name(|i| format!("lsix-thumb-{}", i))
+            .build()
+            .ok();
+        ThumbnailLoader { pool, tx, rx, pending: HashSet::new(), failed: HashSet::new() }
+    }
+
+    /// Queue `path` for decoding unless it is already queued or failed before
+    pub fn request(&mut self, path: &str) {
+        if self.failed.contains(path) || !self.pending.insert(path.to_string()) {
+            return;
+        }
+        let tx = self.tx.clone();
+        let path = path.to_string();
+        let job = move || {
+            let result = thumbnail_cache::load_thumbnail(&path, thumbnail_cache::THUMBNAIL_SIZE)
+                .map_err(|e| format!("{:#}", e));
+            let _ = tx.send((path, result));
+        };
+        match &self.pool {
+            Some(pool) => pool.spawn(job),
+            None => job(),
+        }
+    }
+
+    /// Let `path` be decoded again, e.g. after its file was rewritten
+    pub fn forget(&mut self, path: &str) {
+        self.failed.remove(path);
+    }
+
+    /// Whether `path` failed to decode
+    pub fn has_failed(&self, path: &str) -> bool {
+        self.failed.contains(path)
+    }
+
+    /// Whether any thumbnails are still being decoded
+    pub fn is_busy(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Collect finished thumbnails without blocking, along with the paths
+    /// that failed to decode since the last call
+    pub fn drain(&mut self) -> (Vec<(String, image::DynamicImage)>, Vec<String>) {
+        let mut loaded = Vec::new();
+        let mut failed = Vec::new();
+        while let Ok((path, result)) = self.rx.try_recv() {
+            self.pending.remove(&path);
+            match result {
+                Ok(thumb) => loaded.push((path, thumb)),
+                Err(e) => {
+                    trace_log(&format!("Thumbnail failed for {}: {}", path, e));
+                    self.failed.insert(path.clone());
+                    failed.push(path);
+                }
+            }
+        }
+        (loaded, failed)
+    }
+}
+
+impl Default for ThumbnailLoader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 pub struct TuiBrowser {
     pub items: Vec<String>,
@@ -46,10 +413,56 @@ pub struct TuiBrowser {
     pub selected_image: Option<String>,
     pub grid_cols: u16,
     pub grid_rows: u16,
+    pub grid_columns: u16, // Thumbnails per row wanted (+/- keys); fewer when they don't fit
+    pub grid_labels: GridLabels, // Captions under the thumbnails
+    pub badges: bool,            // Rating, NSFW and tag overlays on the thumbnails
+    pub badge_tags: usize,       // Tags shown in the overlays
     pub scroll_offset: usize,
-    pub image_cache: HashMap<String, image::DynamicImage>,
+    pub image_cache: ImageCache, // Full-resolution images for fullscreen/wipe views, LRU within a memory budget
+    pub thumbnails: HashMap<String, image::DynamicImage>,  // Downscaled grid thumbnails
+    pub loader: ThumbnailLoader,                           // Background thumbnail decoding
+    pub spinner_frame: usize,                              // Animation step for loading placeholders
     pub picker: Option<Picker>,
     pub fullscreen_mode: bool, // Whether we're in fullscreen image view mode
+    pub compare_marks: Vec<String>, // Images marked for before/after comparison (at most two)
+    pub wipe_mode: bool,            // Whether we're in the before/after wipe view
+    pub wipe_split: f32,            // Wipe divider position (0.0 = all "after", 1.0 = all "before")
+    pub keys: KeyBindings,
+    pub prompt: Option<Prompt>,      // Pending file operation
+    pub message: Option<String>,     // Result of the last file operation, shown in the status bar
+    pub selection: HashSet<String>,  // Images selected for batch actions
+    pub visual_anchor: Option<usize>, // Start of the visual range being extended, if any
+    pub show_boxes: bool,             // Draw detected object boxes in fullscreen view
+    pub view: ViewState,              // Fit mode, pan and rotation of the selected image
+    pub view_states: ViewStates,      // Views of images seen before, restored on return
+    pub resume_dir: Option<String>,   // Directory whose cursor position is restored and saved
+    pub can_pan: bool,                // Whether the fullscreen image is cropped and arrows scroll it
+    pub ai_tags: HashMap<String, Option<AITags>>, // Objects and captions loaded from the tag cache
+    pub groups: Vec<ImageGroup>,      // Groups being browsed, in item order (empty when ungrouped)
+    pub group_names: GroupNames,      // Saved custom group names
+    pub group_edits: GroupEdits,      // Saved moves, merges and splits of groups
+    pub all_items: Vec<String>,       // Every image being browsed, before `:filter`
+    pub base_groups: Vec<ImageGroup>, // Groups the browser started with, kept until `:group`
+    pub filters: Vec<FilterTerm>,     // Terms of the last `:filter`
+    pub search: String,               // Text of the `/` search (empty when none)
+    pub sort_order: Vec<SortSpec>,    // Keys of the last `:sort` (empty for scan order)
+    pub group_by: Option<GroupBy>,    // Strategy of the last `:group`, if any
+    pub item_info: HashMap<String, ItemInfo>, // Details `:filter` and `/` read, once
+    pub actions: Vec<CustomAction>,   // Shell commands from the `[actions]` config table
+    pub runner: ActionRunner,         // Runs actions and keeps their output
+    pub action_batch: usize,          // First invocation of the actions still running
+    pub output_pane: Option<OutputPane>, // Output of the actions, when open
+    pub watcher: Option<DirWatcher>,  // Directories followed with --watch
+    pub locations: Locations,         // Every path of images reached through hard links
+    pub show_info: bool,              // Info panel for the selected image
+    pub details: HashMap<String, ImageDetails>, // What the info panel shows, read once
+    pub maps: MapLoader,              // Maps of where geotagged images were taken
+    pub map_url: Option<String>,      // Page the map key opens (OpenStreetMap when unset)
+    pub open_with: Option<String>,    // Command the open key runs (xdg-open when unset)
+    pub sidebar: Option<DirTree>,     // Folder sidebar, when open; it takes the keys
+    pub help_scroll: Option<usize>,   // First line of the help popup, when open
+    pub grid_area: Rect,              // Where the grid was last drawn, for mouse clicks
+    pub last_click: Option<(usize, std::time::Instant)>, // Item and time of the last click
 }
 
 impl TuiBrowser {
@@ -59,20 +472,959 @@ impl TuiBrowser {
 
         // Don't initialize the picker here - do it after raw mode is enabled
         TuiBrowser {
+            all_items: items.clone(),
             items,
             state,
             current_dir,
             selected_image: None,
             grid_cols: 5,
             grid_rows: 0,
+            grid_columns: DEFAULT_GRID_COLUMNS,
+            grid_labels: GridLabels::Names,
+            badges: true,
+            badge_tags: DEFAULT_BADGE_TAGS,
             scroll_offset: 0,
-            image_cache: HashMap::new(),
+            image_cache: ImageCache::new(DEFAULT_CACHE_MB),
+            thumbnails: HashMap::new(),
+            loader: ThumbnailLoader::new(),
+            spinner_frame: 0,
             picker: None, // Will be initialized later
             fullscreen_mode: false,
+            compare_marks: Vec::new(),
+            wipe_mode: false,
+            wipe_split: 0.5,
+            keys: KeyBindings::default(),
+            prompt: None,
+            message: None,
+            selection: HashSet::new(),
+            visual_anchor: None,
+            show_boxes: false,
+            view: ViewState::default(),
+            view_states: ViewStates::default(),
+            resume_dir: None,
+            can_pan: false,
+            ai_tags: HashMap::new(),
+            groups: Vec::new(),
+            group_names: GroupNames::default(),
+            group_edits: GroupEdits::default(),
+            base_groups: Vec::new(),
+            filters: Vec::new(),
+            search: String::new(),
+            sort_order: Vec::new(),
+            group_by: None,
+            item_info: HashMap::new(),
+            actions: Vec::new(),
+            runner: ActionRunner::new(),
+            action_batch: 0,
+            output_pane: None,
+            watcher: None,
+            locations: Locations::new(),
+            show_info: false,
+            details: HashMap::new(),
+            maps: MapLoader::new(None),
+            map_url: None,
+            open_with: None,
+            sidebar: None,
+            help_scroll: None,
+            grid_area: Rect::default(),
+            last_click: None,
+        }
+    }
+
+    /// Index of the group containing the image under the cursor
+    pub fn current_group(&self) -> Option<usize> {
+        let path = self.items.get(self.state.selected()?)?;
+        self.groups.iter().position(|g| g.images.contains(path))
+    }
+
+    /// Toggle the compare mark on the selected image.
+    /// Only two images can be compared, so marking a third drops the oldest mark.
+    pub fn toggle_compare_mark(&mut self) {
+        let Some(path) = self
+            .state
+            .selected()
+            .and_then(|idx| self.items.get(idx))
+            .cloned()
+        else {
+            return;
+        };
+
+        if let Some(pos) = self.compare_marks.iter().position(|p| *p == path) {
+            self.compare_marks.remove(pos);
+        } else {
+            if self.compare_marks.len() >= 2 {
+                self.compare_marks.remove(0);
+            }
+            self.compare_marks.push(path);
+        }
+    }
+
+    /// Toggle the selected image in the batch selection
+    pub fn toggle_selection(&mut self) {
+        let Some(path) = self
+            .state
+            .selected()
+            .and_then(|idx| self.items.get(idx))
+            .cloned()
+        else {
+            return;
+        };
+
+        if !self.selection.remove(&path) {
+            self.selection.insert(path);
+        }
+    }
+
+    /// Start a visual range at the cursor, or add the current range to
+    /// the selection and end it
+    pub fn toggle_visual(&mut self) {
+        match self.visual_range() {
+            Some(range) => {
+                self.selection.extend(self.items[range].iter().cloned());
+                self.visual_anchor = None;
+            }
+            None => self.visual_anchor = self.state.selected(),
+        }
+    }
+
+    /// Select every image, or clear the selection if all are selected
+    pub fn select_all(&mut self) {
+        self.visual_anchor = None;
+        if self.selection.len() == self.items.len() {
+            self.selection.clear();
+        } else {
+            self.selection = self.items.iter().cloned().collect();
+        }
+    }
+
+    /// Clear the selection and any visual range. Returns false if there
+    /// was nothing to clear.
+    pub fn clear_selection(&mut self) -> bool {
+        let had_selection = !self.selection.is_empty() || self.visual_anchor.is_some();
+        self.selection.clear();
+        self.visual_anchor = None;
+        had_selection
+    }
+
+    /// Item indices covered by the visual range (anchor to cursor)
+    fn visual_range(&self) -> Option<std::ops::RangeInclusive<usize>> {
+        let anchor = self.visual_anchor?;
+        let cursor = self.state.selected()?;
+        let end = anchor.max(cursor).min(self.items.len().checked_sub(1)?);
+        Some(anchor.min(cursor)..=end)
+    }
+
+    /// Whether the item at `idx` is selected or inside the visual range
+    pub fn is_marked(&self, idx: usize) -> bool {
+        self.visual_range().is_some_and(|r| r.contains(&idx))
+            || self.items.get(idx).is_some_and(|p| self.selection.contains(p))
+    }
+
+    /// Images a batch action applies to: the selection (plus the visual
+    /// range) in display order, or the image under the cursor
+    pub fn action_targets(&self) -> Vec<String> {
+        let marked: Vec<String> = (0..self.items.len())
+            .filter(|&idx| self.is_marked(idx))
+            .map(|idx| self.items[idx].clone())
+            .collect();
+        if !marked.is_empty() {
+            return marked;
+        }
+
+        self.state
+            .selected()
+            .and_then(|idx| self.items.get(idx))
+            .cloned()
+            .into_iter()
+            .collect()
+    }
+
+    /// Open the prompt for `action` on the current targets
+    pub fn start_batch_action(&mut self, action: BatchAction) {
+        if action == BatchAction::RenameGroup {
+            self.start_group_rename();
+            return;
+        }
+        if matches!(action, BatchAction::Command | BatchAction::Search) {
+            // A search is opened again with its text, to be edited
+            let input = match action {
+                BatchAction::Search => self.search.clone(),
+                _ => String::new(),
+            };
+            self.prompt = Some(Prompt { action, targets: Vec::new(), input, tags: Vec::new() });
+            return;
+        }
+
+        let targets = self.action_targets();
+        if targets.is_empty() {
+            return;
+        }
+
+        let action = match action {
+            BatchAction::File(FileOp::Rename) if targets.len() > 1 => {
+                BatchAction::RenameSequence { suffix: false }
+            }
+            action => action,
+        };
+        let input = match action {
+            BatchAction::RenameSequence { .. } => "{name}".to_string(),
+            BatchAction::File(FileOp::Rename) => Path::new(&targets[0])
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            _ => String::new(),
+        };
+        let tags = if action == BatchAction::Tag {
+            self.tag_summary(&targets)
+        } else {
+            Vec::new()
+        };
+
+        self.prompt = Some(Prompt {
+            action,
+            targets,
+            input,
+            tags,
+        });
+    }
+
+    /// Tags of `paths` from the tag cache, with how many of them have each,
+    /// most common first
+    fn tag_summary(&mut self, paths: &[String]) -> Vec<(String, usize)> {
+        let mut counts: Vec<(String, usize)> = Vec::new();
+        for path in paths {
+            let tags = self.ai_tags.entry(path.clone()).or_insert_with(|| cached_tags(path));
+            for tag in tags.iter().flat_map(|t| &t.tags) {
+                match counts.iter_mut().find(|(t, _)| t == tag) {
+                    Some((_, count)) => *count += 1,
+                    None => counts.push((tag.clone(), 1)),
+                }
+            }
+        }
+        // Stable, so tags shared by as many images keep their cached order
+        counts.sort_by_key(|c| std::cmp::Reverse(c.1));
+        counts
+    }
+
+    /// Apply a tag edit such as "beach, -blurry" to `prompt.targets` and
+    /// refresh the tags shown in the prompt. Returns a message for the
+    /// status bar.
+    pub fn edit_tags(&mut self, prompt: &mut Prompt) -> anyhow::Result<String> {
+        let (added, removed) = parse_tag_edits(&prompt.input);
+        if added.is_empty() && removed.is_empty() {
+            anyhow::bail!("No tags given");
+        }
+        let cache_dir = crate::ai_tagging::AITaggingConfig::default()
+            .cache_dir
+            .ok_or_else(|| anyhow::anyhow!("Tag cache is disabled"))?;
+
+        for path in &prompt.targets {
+            if !removed.is_empty() {
+                crate::ai_tagging::remove_manual_tags(&cache_dir, path, &removed)?;
+            }
+            if !added.is_empty() {
+                crate::ai_tagging::add_manual_tags(&cache_dir, path, &added)?;
+            }
+            self.ai_tags.remove(path);
+        }
+        prompt.input.clear();
+        prompt.tags = self.tag_summary(&prompt.targets);
+
+        let mut changes = Vec::new();
+        if !added.is_empty() {
+            changes.push(format!("added {}", added.join(", ")));
+        }
+        if !removed.is_empty() {
+            changes.push(format!("removed {}", removed.join(", ")));
+        }
+        Ok(format!("Tags of {} file(s): {}", prompt.targets.len(), changes.join("; ")))
+    }
+
+    /// Images a rating or flag applies to: the one shown in fullscreen,
+    /// the action targets in the grid
+    fn cull_targets(&self) -> Vec<String> {
+        if self.fullscreen_mode {
+            return self.selected_image.clone().into_iter().collect();
+        }
+        self.action_targets()
+    }
+
+    /// Give the targets `stars` stars, or clear the rating when they all
+    /// have it already. Returns a message for the status bar.
+    pub fn rate(&mut self, stars: u8) -> anyhow::Result<String> {
+        let targets = self.cull_targets();
+        let cache_dir = AITaggingConfig::default()
+            .cache_dir
+            .ok_or_else(|| anyhow::anyhow!("Tag cache is disabled"))?;
+        let clear =
+            targets.iter().all(|path| culling::cached_stars(&cache_dir, path) == Some(stars));
+        let rating = (!clear).then_some(stars);
+        for path in &targets {
+            culling::set_stars(&cache_dir, path, rating)?;
+            self.ai_tags.remove(path);
+        }
+        Ok(match rating {
+            Some(stars) => format!("Rated {} file(s) {}", targets.len(), Badges::stars(stars)),
+            None => format!("Cleared the rating of {} file(s)", targets.len()),
+        })
+    }
+
+    /// Flag the targets, or unflag them when they all have the flag already.
+    /// Returns a message for the status bar.
+    pub fn toggle_flag(&mut self, flag: Flag) -> anyhow::Result<String> {
+        let targets = self.cull_targets();
+        let cache_dir = AITaggingConfig::default()
+            .cache_dir
+            .ok_or_else(|| anyhow::anyhow!("Tag cache is disabled"))?;
+        let clear = targets.iter().all(|path| culling::cached_flag(&cache_dir, path) == Some(flag));
+        let new_flag = (!clear).then_some(flag);
+        for path in &targets {
+            culling::set_flag(&cache_dir, path, new_flag)?;
+            self.ai_tags.remove(path);
+        }
+        Ok(match new_flag {
+            Some(flag) => format!("Flagged {} file(s) as {}", targets.len(), flag.tag()),
+            None => format!("Unflagged {} file(s)", targets.len()),
+        })
+    }
+
+    /// Open the prompt for writing the rotation and mirroring of the
+    /// fullscreen image into its file
+    fn start_save_view(&mut self) {
+        let Some(path) = self.selected_image.clone() else {
+            return;
+        };
+        if !self.view.is_turned() {
+            self.message = Some("Rotate (r/R) or mirror (m) the image first".to_string());
+            return;
+        }
+        self.prompt = Some(Prompt {
+            action: BatchAction::SaveView(transform::planned_method(&path)),
+            targets: vec![path],
+            input: String::new(),
+            tags: Vec::new(),
+        });
+    }
+
+    /// Open the prompt for naming the group under the cursor, filled in with
+    /// its current name
+    fn start_group_rename(&mut self) {
+        let Some(group) = self.current_group().map(|idx| &self.groups[idx]) else {
+            self.message = Some("Images are not grouped; use --group-by".to_string());
+            return;
+        };
+
+        self.prompt = Some(Prompt {
+            action: BatchAction::RenameGroup,
+            targets: vec![group.key()],
+            input: group.name.clone(),
+            tags: Vec::new(),
+        });
+    }
+
+    /// Move the selected images into the group under the cursor
+    pub fn move_to_group(&mut self) -> anyhow::Result<String> {
+        let Some(target) = self.current_group().map(|idx| self.groups[idx].clone()) else {
+            anyhow::bail!("Images are not grouped; use --group-by");
+        };
+        let images: Vec<String> = self
+            .action_targets()
+            .into_iter()
+            .filter(|path| !target.images.contains(path))
+            .collect();
+        if images.is_empty() {
+            anyhow::bail!("Select images, then move the cursor to the group they belong in");
+        }
+        self.group_edits.move_images(&self.groups, &images, &target)?;
+        self.save_group_edits(format!("Moved {} image(s) to {}", images.len(), target.name))
+    }
+
+    /// Merge the group after the one under the cursor into it
+    pub fn merge_next_group(&mut self) -> anyhow::Result<String> {
+        let Some(idx) = self.current_group() else {
+            anyhow::bail!("Images are not grouped; use --group-by");
+        };
+        let Some(next) = self.groups.get(idx + 1) else {
+            anyhow::bail!("{} is the last group", self.groups[idx].name);
+        };
+        let message = format!("Merged {} into {}", next.name, self.groups[idx].name);
+        self.group_edits.merge(&self.groups, next, &self.groups[idx])?;
+        self.save_group_edits(message)
+    }
+
+    /// Split the group under the cursor: the image under the cursor and
+    /// the ones after it form a new group
+    pub fn split_group(&mut self) -> anyhow::Result<String> {
+        let Some(group) = self.current_group().map(|idx| &self.groups[idx]) else {
+            anyhow::bail!("Images are not grouped; use --group-by");
+        };
+        let cursor = self.state.selected().and_then(|idx| self.items.get(idx));
+        let at = group.images.iter().position(|path| Some(path) == cursor).unwrap_or(0);
+        if at == 0 {
+            anyhow::bail!("Move the cursor to the first image of the new group");
         }
+        let images = group.images[at..].to_vec();
+        let name = self.group_edits.split(&self.groups, &images)?;
+        self.save_group_edits(format!("Split {} image(s) off into {}", images.len(), name))
+    }
+
+    /// Save the group edits and regroup with them
+    fn save_group_edits(&mut self, message: String) -> anyhow::Result<String> {
+        self.group_edits.save()?;
+        self.refresh_items()?;
+        self.clear_selection();
+        Ok(message)
+    }
+
+    /// Run a confirmed batch action and update the item list.
+    /// Returns a message for the status bar.
+    pub fn apply_batch_action(&mut self, mut prompt: Prompt) -> anyhow::Result<String> {
+        let count = prompt.targets.len();
+        let message = match prompt.action {
+            BatchAction::File(FileOp::Trash) => {
+                file_ops::trash_files(&prompt.targets)?;
+                self.remove_items(&prompt.targets);
+                format!("Moved {} file(s) to trash", count)
+            }
+            BatchAction::File(FileOp::Delete) => {
+                file_ops::delete_files(&prompt.targets)?;
+                self.remove_items(&prompt.targets);
+                format!("Deleted {} file(s)", count)
+            }
+            BatchAction::File(FileOp::Rename) => {
+                let old = &prompt.targets[0];
+                let new = file_ops::rename_file(old, &prompt.input)?;
+                self.replace_item(old, &new);
+                format!("Renamed to {}", new)
+            }
+            BatchAction::RenameSequence { suffix } => {
+                let plan = rename_plan(&prompt.targets, &prompt.input, suffix)?;
+                let renamed = bulk_rename::apply(&plan)?;
+                self.replace_items(&renamed);
+                format!("Renamed {} file(s)", renamed.len())
+            }
+            BatchAction::File(FileOp::Move) => {
+                file_ops::move_files(&prompt.targets, &prompt.input)?;
+                // Moved files leave the current view, like in a file manager
+                self.remove_items(&prompt.targets);
+                format!("Moved {} file(s) to {}", count, prompt.input.trim())
+            }
+            BatchAction::File(FileOp::Copy) => {
+                file_ops::copy_files(&prompt.targets, &prompt.input)?;
+                format!("Copied {} file(s) to {}", count, prompt.input.trim())
+            }
+            BatchAction::Tag => {
+                // Edits are saved as they are entered; this closes the editor
+                if prompt.input.trim().is_empty() {
+                    format!("Done editing tags of {} file(s)", count)
+                } else {
+                    self.edit_tags(&mut prompt)?
+                }
+            }
+            BatchAction::Export => {
+                let list_path = prompt.input.trim();
+                if list_path.is_empty() {
+                    anyhow::bail!("No file name given");
+                }
+                let mut list = prompt.targets.join("\n");
+                list.push('\n');
+                std::fs::write(list_path, list)?;
+                format!("Exported {} path(s) to {}", count, list_path)
+            }
+            BatchAction::RenameGroup => {
+                let name = prompt.input.trim();
+                if name.is_empty() {
+                    anyhow::bail!("No group name given");
+                }
+                let key = &prompt.targets[0];
+                for group in self.groups.iter_mut().filter(|g| &g.key() == key) {
+                    group.name = name.to_string();
+                }
+                self.group_names.set(key, name);
+                self.group_names.save()?;
+                // Renaming a group leaves the selection alone
+                return Ok(format!("Renamed group to {}", name));
+            }
+            BatchAction::Command => return self.run_command(&prompt.input),
+            BatchAction::Search => return self.set_search(&prompt.input),
+            BatchAction::SaveView(_) => {
+                let path = &prompt.targets[0];
+                let method = transform::save_transform(path, &self.view)?;
+                // The file is turned now, so it is shown as it is
+                self.view.rotation = 0;
+                self.view.flip = false;
+                self.forget_image(path);
+                return Ok(format!("Saved {} ({})", path, method.label()));
+            }
+        };
+
+        self.clear_selection();
+        Ok(message)
+    }
+
+    /// Run a `:` command and rebuild the view from it. A command that
+    /// would leave nothing to show is undone. Returns a message for the
+    /// status bar.
+    pub fn run_command(&mut self, input: &str) -> anyhow::Result<String> {
+        let previous = (self.filters.clone(), self.sort_order.clone(), self.group_by);
+        match tui_command::parse_command(input)? {
+            Command::Filter(terms) => self.filters = terms,
+            Command::Sort(order) => self.sort_order = order,
+            Command::Group(strategy) => self.group_by = Some(strategy),
+        }
+        let result = self.refresh_items();
+        if result.is_err() {
+            (self.filters, self.sort_order, self.group_by) = previous;
+        }
+        result
+    }
+
+    /// Search for `query` as typed so far, or show everything again for an
+    /// empty one. A search nothing matches is undone.
+    pub fn set_search(&mut self, query: &str) -> anyhow::Result<String> {
+        let previous = std::mem::replace(&mut self.search, query.to_string());
+        let result = self.refresh_items();
+        if result.is_err() {
+            self.search = previous;
+        }
+        result
+    }
+
+    /// Filter, search, sort and group `all_items` by the current commands,
+    /// keeping the cursor on the same image when it is still shown
+    fn refresh_items(&mut self) -> anyhow::Result<String> {
+        let tag_cache = AITaggingConfig::default().cache_dir;
+        let items = tui_command::filter_paths(
+            &self.all_items,
+            &self.filters,
+            tag_cache.as_deref(),
+            &mut self.item_info,
+        );
+        let mut items = tui_command::search_paths(
+            &items,
+            &self.search,
+            tag_cache.as_deref(),
+            &mut self.item_info,
+        );
+        if items.is_empty() {
+            anyhow::bail!("No images match");
+        }
+        if !self.sort_order.is_empty() {
+            let quality = self
+                .sort_order
+                .iter()
+                .any(|spec| spec.key == SortKey::Quality)
+                .then(|| tui_command::quality_scores(&items));
+            sorting::sort_paths_by(
+                &mut items,
+                &self.sort_order,
+                false,
+                quality.as_ref(),
+                tag_cache.as_deref(),
+            );
+        }
+
+        let mut groups = match self.group_by {
+            None => self.base_groups.clone(),
+            Some(GroupBy::None) => Vec::new(),
+            Some(strategy) => {
+                // Progress bars and warnings would draw over the TUI
+                let verbosity = diagnostics::verbosity();
+                diagnostics::set_verbosity(Verbosity::Quiet);
+                // --similarity-threshold's default
+                let groups = group_images(&items, strategy, 0.85);
+                diagnostics::set_verbosity(verbosity);
+                groups?
+            }
+        };
+        self.group_edits.apply(&mut groups);
+        self.group_names.apply(&mut groups);
+        if !groups.is_empty() {
+            // Groups keep the sorted order inside them, and an image that
+            // falls into several is shown in the first
+            let rank: HashMap<&String, usize> =
+                items.iter().enumerate().map(|(i, path)| (path, i)).collect();
+            let mut seen = HashSet::new();
+            for group in &mut groups {
+                group.images.retain(|image| rank.contains_key(image) && seen.insert(image.clone()));
+                group.images.sort_by_key(|image| rank[image]);
+            }
+            groups.retain(|group| !group.images.is_empty());
+            items = groups.iter().flat_map(|g| g.images.clone()).collect();
+        }
+
+        let current = self.selected_image.clone();
+        self.items = items;
+        self.groups = groups;
+        let shown: HashSet<&String> = self.items.iter().collect();
+        self.selection.retain(|path| shown.contains(path));
+        self.compare_marks.retain(|path| shown.contains(path));
+        self.visual_anchor = None;
+        let selected = current
+            .and_then(|path| self.items.iter().position(|item| *item == path))
+            .unwrap_or(0);
+        self.state.select(Some(selected));
+        self.update_selected_image();
+        self.scroll_offset = 0;
+        self.ensure_selection_visible();
+
+        Ok(format!("Showing {} of {} images", self.items.len(), self.all_items.len()))
+    }
+
+    /// Open the folder sidebar on the directory of the image under the
+    /// cursor, or on the working directory
+    pub fn open_sidebar(&mut self) {
+        let dir = self
+            .items
+            .get(self.state.selected().unwrap_or(0))
+            .and_then(|path| Path::new(path).parent())
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| PathBuf::from(&self.current_dir));
+        match DirTree::open(&dir) {
+            Ok(tree) => self.sidebar = Some(tree),
+            Err(e) => self.message = Some(format!("Error: {:#}", e)),
+        }
+    }
+
+    /// Browse the images of `dir` instead of the current ones. `:filter`
+    /// and `:sort` stay; the search and the starting groups are dropped.
+    /// A folder without images leaves the grid as it is.
+    pub fn open_dir(&mut self, dir: &Path) -> anyhow::Result<String> {
+        // "Scanning directory" notes would draw over the TUI
+        let verbosity = diagnostics::verbosity();
+        diagnostics::set_verbosity(Verbosity::Quiet);
+        let images = expand_directories(&[dir.to_string_lossy().to_string()]);
+        diagnostics::set_verbosity(verbosity);
+        if images.is_empty() {
+            return Ok(format!("No images in {}", dir.display()));
+        }
+
+        let previous = (
+            std::mem::replace(&mut self.all_items, images),
+            std::mem::take(&mut self.base_groups),
+            std::mem::take(&mut self.search),
+        );
+        if let Err(e) = self.refresh_items() {
+            (self.all_items, self.base_groups, self.search) = previous;
+            return Err(e);
+        }
+        let shown: HashSet<&String> = self.all_items.iter().collect();
+        self.thumbnails.retain(|path, _| shown.contains(path));
+        self.current_dir = dir.to_string_lossy().to_string();
+        if self.watcher.is_some() {
+            // Follow the folder being shown instead
+            self.watcher = DirWatcher::new(std::slice::from_ref(&self.current_dir), false).ok();
+        }
+        Ok(format!("{}: {} images", dir.display(), self.items.len()))
+    }
+
+    /// Run custom action `idx` on the current targets
+    pub fn run_action(&mut self, idx: usize) {
+        let Some(action) = self.actions.get(idx) else {
+            return;
+        };
+        let targets = self.action_targets();
+        if targets.is_empty() {
+            return;
+        }
+        if !self.runner.is_busy() {
+            self.action_batch = self.runner.invocations.len();
+        }
+        for path in &targets {
+            self.runner.run(&action.command, path);
+        }
+        trace_log(&format!("Action {:?} on {:?}", action.command, targets));
+        self.message = Some(format!(
+            "Running {} on {} image(s) | O: Output",
+            action.command,
+            targets.len()
+        ));
+    }
+
+    /// Run the invocation selected in the output pane again
+    pub fn retry_action(&mut self) {
+        let Some(pane) = &mut self.output_pane else {
+            return;
+        };
+        if !self.runner.is_busy() {
+            self.action_batch = self.runner.invocations.len();
+        }
+        if let Some(idx) = self.runner.retry(pane.selected) {
+            pane.selected = idx;
+            pane.scroll = 0;
+        }
+    }
+
+    /// Record finished actions and, once none are left running, sum them
+    /// up in the status bar. Returns whether anything finished.
+    pub fn receive_action_results(&mut self) -> bool {
+        if self.runner.drain().is_empty() {
+            return false;
+        }
+        if !self.runner.is_busy() {
+            let batch = &self.runner.invocations[self.action_batch..];
+            let failed = batch.iter().filter(|i| !i.status.is_success()).count();
+            self.message = Some(match failed {
+                0 => format!("Action finished on {} image(s) | O: Output", batch.len()),
+                n => format!("Action failed on {} of {} image(s) | O: Output", n, batch.len()),
+            });
+        }
+        true
+    }
+
+    /// Apply changes in the watched directories: new images join the list
+    /// (in a "New images" group when browsing groups) and go through the
+    /// current `:filter`, `:sort` and `:group`, changed ones are decoded
+    /// again and removed ones leave. Returns whether anything changed.
+    pub fn receive_changes(&mut self) -> bool {
+        let Some(watcher) = &mut self.watcher else {
+            return false;
+        };
+        let touched = watcher.poll();
+        if touched.is_empty() {
+            return false;
+        }
+        let known: HashSet<&str> = self.all_items.iter().map(String::as_str).collect();
+        let changes = Changes::classify(&touched, |path| known.contains(path));
+        if changes.is_empty() {
+            return false;
+        }
+        trace_log(&format!("Watched directories changed: {:?}", changes));
+
+        self.remove_items(&changes.removed);
+        for path in &changes.modified {
+            self.forget_image(path);
+        }
+        if !changes.added.is_empty() {
+            self.all_items.extend(changes.added.iter().cloned());
+            if !self.base_groups.is_empty() {
+                let position = self.base_groups.iter().position(|g| g.id == NEW_IMAGES_GROUP);
+                let group = match position {
+                    Some(idx) => &mut self.base_groups[idx],
+                    None => {
+                        self.base_groups.push(ImageGroup {
+                            id: NEW_IMAGES_GROUP.to_string(),
+                            name: "New images".to_string(),
+                            images: Vec::new(),
+                            representative: changes.added[0].clone(),
+                            metadata: GroupMetadata {
+                                group_type: "watch".to_string(),
+                                count: 0,
+                                common_features: HashMap::new(),
+                            },
+                        });
+                        self.base_groups.last_mut().unwrap()
+                    }
+                };
+                group.images.extend(changes.added.iter().cloned());
+                group.metadata.count = group.images.len();
+            }
+        }
+        if !changes.added.is_empty() || !changes.modified.is_empty() {
+            // A changed image may now sort or filter differently
+            if let Err(e) = self.refresh_items() {
+                trace_log(&format!("Refresh after changes failed: {:#}", e));
+            }
+        }
+        self.message = Some(format!("Directory changed: {}", changes.summary()));
+        true
+    }
+
+    /// Copy the absolute paths of the selected images (or the one under the
+    /// cursor), one per line, or with `image` the image under the cursor
+    /// itself, to the clipboard. Returns a message for the status bar.
+    pub fn yank(&mut self, image: bool) -> anyhow::Result<String> {
+        if image {
+            let Some(path) = self.selected_image.clone() else {
+                anyhow::bail!("No image to copy");
+            };
+            let via = clipboard::copy_image(&path)?;
+            return Ok(format!("Copied image {} ({})", path, via.label()));
+        }
+        let paths: Vec<String> = self
+            .action_targets()
+            .iter()
+            .map(|path| canonicalize_path(path.trim_end_matches("[0]"), false))
+            .collect();
+        if paths.is_empty() {
+            anyhow::bail!("No image to copy");
+        }
+        let via = clipboard::copy_text(&paths.join("\n"))?;
+        Ok(match paths.as_slice() {
+            [path] => format!("Copied {} ({})", path, via.label()),
+            _ => format!("Copied {} paths ({})", paths.len(), via.label()),
+        })
+    }
+
+    /// Show the output of the latest action
+    pub fn open_output_pane(&mut self) {
+        match self.runner.invocations.len() {
+            0 => self.message = Some("No actions have been run".to_string()),
+            n => self.output_pane = Some(OutputPane { selected: n - 1, scroll: 0 }),
+        }
+    }
+
+    /// Store thumbnails finished by the loader, dropping images whose file
+    /// turned out to be gone. Returns whether anything changed.
+    pub fn receive_thumbnails(&mut self) -> bool {
+        let (loaded, failed) = self.loader.drain();
+        let any = !loaded.is_empty() || !failed.is_empty();
+        for (path, thumb) in loaded {
+            // Ignore results for images removed or renamed while loading
+            if self.items.contains(&path) {
+                self.thumbnails.insert(path, thumb);
+            }
+        }
+        self.drop_missing(&failed);
+        any
+    }
+
+    /// Remove those of `paths` whose file no longer exists (deleted, moved,
+    /// or on a share that went away) and say so in the status bar. Other
+    /// errors, such as a flaky mount timing out, leave the image in place.
+    /// Returns whether any were removed.
+    pub fn drop_missing(&mut self, paths: &[String]) -> bool {
+        let missing: Vec<String> = paths
+            .iter()
+            .filter(|path| self.items.contains(path) && is_missing(path))
+            .cloned()
+            .collect();
+        if missing.is_empty() {
+            return false;
+        }
+
+        trace_log(&format!("Dropping missing images: {:?}", missing));
+        self.remove_items(&missing);
+        self.message = Some(match missing.as_slice() {
+            [path] => format!(
+                "{} no longer exists; removed from the list",
+                Path::new(path).file_name().unwrap_or_default().to_string_lossy()
+            ),
+            _ => format!("{} images no longer exist; removed from the list", missing.len()),
+        });
+        true
+    }
+
+    /// Images the current view shows in full that failed to load
+    fn unloaded_in_view(&self) -> Vec<String> {
+        let shown: Vec<String> = if self.wipe_mode {
+            self.compare_marks.clone()
+        } else if self.fullscreen_mode {
+            self.selected_image.iter().cloned().collect()
+        } else {
+            Vec::new()
+        };
+        shown
+            .into_iter()
+            .filter(|path| !self.image_cache.contains_key(path))
+            .collect()
+    }
+
+    /// Drop paths from the browser, keeping the selection in range
+    fn remove_items(&mut self, paths: &[String]) {
+        self.items.retain(|item| !paths.contains(item));
+        self.all_items.retain(|item| !paths.contains(item));
+        self.compare_marks.retain(|mark| !paths.contains(mark));
+        for path in paths {
+            self.image_cache.remove(path);
+            self.thumbnails.remove(path);
+            self.ai_tags.remove(path);
+            self.selection.remove(path);
+            self.item_info.remove(path);
+            self.details.remove(path);
+        }
+        for group in self.groups.iter_mut().chain(self.base_groups.iter_mut()) {
+            group.images.retain(|image| !paths.contains(image));
+        }
+        self.groups.retain(|group| !group.images.is_empty());
+        self.base_groups.retain(|group| !group.images.is_empty());
+        if let Some(prompt) = &mut self.prompt {
+            prompt.targets.retain(|target| !paths.contains(target));
+            let typed = matches!(prompt.action, BatchAction::Command | BatchAction::Search);
+            if prompt.targets.is_empty() && !typed {
+                self.prompt = None;
+            }
+        }
+        self.visual_anchor = None;
+        if self.compare_marks.len() < 2 {
+            self.wipe_mode = false;
+        }
+        if self.selected_image.as_ref().is_some_and(|p| paths.contains(p)) {
+            self.selected_image = None;
+            self.view = ViewState::default();
+        }
+
+        if self.items.is_empty() {
+            self.state.select(None);
+            self.selected_image = None;
+            self.scroll_offset = 0;
+            self.fullscreen_mode = false;
+            return;
+        }
+
+        let selected = self.state.selected().unwrap_or(0).min(self.items.len() - 1);
+        self.state.select(Some(selected));
+        self.update_selected_image();
+        self.scroll_offset = self.scroll_offset.min(selected);
+        self.ensure_selection_visible();
+    }
+
+    /// Point every reference to `old` at `new` after a rename
+    fn replace_item(&mut self, old: &str, new: &str) {
+        let group_images = self
+            .groups
+            .iter_mut()
+            .chain(self.base_groups.iter_mut())
+            .flat_map(|g| g.images.iter_mut());
+        for item in self
+            .items
+            .iter_mut()
+            .chain(self.all_items.iter_mut())
+            .chain(self.compare_marks.iter_mut())
+            .chain(group_images)
+        {
+            if item == old {
+                *item = new.to_string();
+            }
+        }
+        if let Some(img) = self.image_cache.remove(old) {
+            self.image_cache.insert(new.to_string(), img);
+        }
+        if let Some(thumb) = self.thumbnails.remove(old) {
+            self.thumbnails.insert(new.to_string(), thumb);
+        }
+        if let Some(tags) = self.ai_tags.remove(old) {
+            self.ai_tags.insert(new.to_string(), tags);
+        }
+        // The name changed, so `:filter name:` must look again
+        self.item_info.remove(old);
+        self.details.remove(old);
+        if self.selected_image.as_deref() == Some(old) {
+            self.selected_image = Some(new.to_string());
+        }
+        self.view_states.rename(old, new);
+        self.update_selected_image();
+    }
+
+    /// Replace several paths at once. They go through placeholders first,
+    /// so names swapped among the images aren't replaced twice.
+    fn replace_items(&mut self, renamed: &[(String, String)]) {
+        let placeholders: Vec<String> =
+            (0..renamed.len()).map(|i| format!("\0renamed {}", i)).collect();
+        for ((old, _), placeholder) in renamed.iter().zip(&placeholders) {
+            self.replace_item(old, placeholder);
+        }
+        for ((_, new), placeholder) in renamed.iter().zip(&placeholders) {
+            self.replace_item(placeholder, new);
+        }
+    }
+
+    /// Move the wipe divider by `delta` (fraction of the image width)
+    pub fn adjust_wipe_split(&mut self, delta: f32) {
+        self.wipe_split = (self.wipe_split + delta).clamp(0.0, 1.0);
     }
 
-    #[allow(dead_code)]
     pub fn next(&mut self) {
         let i = match self.state.selected() {
             Some(i) => {
@@ -89,7 +1441,6 @@ impl TuiBrowser {
         self.ensure_selection_visible();
     }
 
-    #[allow(dead_code)]
     pub fn previous(&mut self) {
         let i = match self.state.selected() {
             Some(i) => {
@@ -106,43 +1457,285 @@ impl TuiBrowser {
         self.ensure_selection_visible();
     }
 
-    /// Ensure the selected item is visible in the current view
-    fn ensure_selection_visible(&mut self) {
-        if let Some(selected_idx) = self.state.selected() {
-            let items_per_page = self.grid_cols as usize * self.grid_rows as usize;
-
-            // Calculate which page the selected item is on
-            let selected_page = selected_idx / items_per_page;
-            let current_start_page = self.scroll_offset / items_per_page;
-            let current_end_page = (self.scroll_offset + items_per_page - 1) / items_per_page;
+    /// Ensure the selected item is visible in the current view
+    fn ensure_selection_visible(&mut self) {
+        if let Some(selected_idx) = self.state.selected() {
+            let items_per_page = self.grid_cols as usize * self.grid_rows as usize;
+            if items_per_page == 0 {
+                // Not laid out yet; the first draw sizes the grid
+                return;
+            }
+
+            // Calculate which page the selected item is on
+            let selected_page = selected_idx / items_per_page;
+            let current_start_page = self.scroll_offset / items_per_page;
+            let current_end_page = (self.scroll_offset + items_per_page - 1) / items_per_page;
+
+            // If the selected item is not on the current page, adjust the scroll
+            if selected_page < current_start_page || selected_page > current_end_page {
+                // Center the selected item's page
+                self.scroll_offset = (selected_idx / items_per_page) * items_per_page;
+            }
+        }
+    }
+
+    /// Show `delta` more thumbnails per grid row (fewer when negative) and
+    /// save the count as `[tui] grid_columns` in the user config. Returns
+    /// a message for the status bar.
+    pub fn resize_grid(&mut self, delta: i16) -> String {
+        let columns = self.grid_columns.saturating_add_signed(delta).clamp(1, MAX_GRID_COLUMNS);
+        if columns == self.grid_columns {
+            return format!("The grid shows 1 to {} thumbnails per row", MAX_GRID_COLUMNS);
+        }
+        self.grid_columns = columns;
+        // The next draw lays the grid out again and finds the cursor's page
+        self.scroll_offset = 0;
+        match config::save_user_value("tui", "grid_columns", &columns.to_string()) {
+            Ok(()) => format!("{} thumbnails per row (saved)", columns),
+            Err(e) => format!("{} thumbnails per row (not saved: {:#})", columns, e),
+        }
+    }
+
+    /// Put the cursor back where it was left the last time `resume_dir`
+    /// was browsed
+    fn restore_position(&mut self) {
+        let Some(dir) = &self.resume_dir else {
+            return;
+        };
+        let positions = Positions::load();
+        let Some(index) = positions.get(dir).and_then(|p| p.index_in(&self.items)) else {
+            return;
+        };
+        let scroll_offset = positions.get(dir).map_or(0, |p| p.scroll_offset);
+        self.state.select(Some(index));
+        // The first draw moves to the cursor's page if the grid has changed
+        self.scroll_offset = scroll_offset.min(index);
+        self.update_selected_image();
+    }
+
+    /// Remember the cursor and scroll position for `resume_dir`
+    fn save_position(&self) -> anyhow::Result<()> {
+        let Some(dir) = &self.resume_dir else {
+            return Ok(());
+        };
+        let Some(index) = self.state.selected().filter(|&i| i < self.items.len()) else {
+            return Ok(());
+        };
+        let mut positions = Positions::load();
+        positions.set(dir, Position::new(&self.items[index], index, self.scroll_offset));
+        positions.save()
+    }
+
+    /// Move the cursor one page up
+    fn page_up(&mut self) {
+        let items_per_page = (self.grid_cols * self.grid_rows) as usize;
+        let current = self.state.selected().unwrap_or(0);
+        self.state.select(Some(current.saturating_sub(items_per_page)));
+        self.update_selected_image();
+        self.ensure_selection_visible();
+    }
+
+    /// Move the cursor one page down, stopping at the last item
+    fn page_down(&mut self) {
+        let items_per_page = (self.grid_cols * self.grid_rows) as usize;
+        let current = self.state.selected().unwrap_or(0);
+        let new_index = std::cmp::min(current + items_per_page, self.items.len().saturating_sub(1));
+        self.state.select(Some(new_index));
+        self.update_selected_image();
+        self.ensure_selection_visible();
+    }
 
-            // If the selected item is not on the current page, adjust the scroll
-            if selected_page < current_start_page || selected_page > current_end_page {
-                // Center the selected item's page
-                self.scroll_offset = (selected_idx / items_per_page) * items_per_page;
-            }
+    /// Index of the item drawn over terminal cell (`column`, `row`), if any
+    fn item_at(&self, column: u16, row: u16) -> Option<usize> {
+        let area = self.grid_area;
+        if self.grid_cols == 0 || self.grid_rows == 0 || column < area.x || row < area.y {
+            return None;
         }
+        let cell_width = (area.width / self.grid_cols).max(1);
+        let cell_height = (area.height / self.grid_rows).max(1);
+        let col = (column - area.x) / cell_width;
+        let grid_row = (row - area.y) / cell_height;
+        if col >= self.grid_cols || grid_row >= self.grid_rows {
+            return None;
+        }
+        let idx = self.scroll_offset
+            + grid_row as usize * self.grid_cols as usize
+            + col as usize;
+        (idx < self.items.len()).then_some(idx)
     }
 
     fn update_selected_image(&mut self) {
-        if let Some(idx) = self.state.selected() {
-            if idx < self.items.len() {
-                self.selected_image = Some(self.items[idx].clone());
-            }
+        let Some(path) = self.state.selected().and_then(|idx| self.items.get(idx)) else {
+            return;
+        };
+        if self.selected_image.as_ref() == Some(path) {
+            return;
+        }
+        // Leave the current image's view behind and pick up the new one's
+        let path = path.clone();
+        self.store_view();
+        self.view = self.view_states.get(&path);
+        self.selected_image = Some(path);
+    }
+
+    /// Drop everything read from `path`, which changed on disk, so it is
+    /// loaded again
+    fn forget_image(&mut self, path: &str) {
+        self.thumbnails.remove(path);
+        self.image_cache.remove(path);
+        self.item_info.remove(path);
+        self.details.remove(path);
+        self.maps.forget(path);
+        self.loader.forget(path);
+    }
+
+    /// Remember the view of the selected image
+    fn store_view(&mut self) {
+        if let Some(path) = &self.selected_image {
+            self.view_states.set(path, self.view);
+        }
+    }
+
+    /// Read what the info panel shows for the image under the cursor; read
+    /// again once its thumbnail is ready, which brightness and color need.
+    /// A geotagged image's map is started in the background.
+    fn load_details(&mut self) {
+        let Some(path) = self.items.get(self.state.selected().unwrap_or(0)).cloned() else {
+            return;
+        };
+        let thumbnail = self.thumbnails.get(&path);
+        let stale = match self.details.get(&path) {
+            Some(details) => details.brightness.is_none() && thumbnail.is_some(),
+            None => true,
+        };
+        if stale {
+            self.details.insert(path.clone(), ImageDetails::load(&path, thumbnail));
+        }
+        if let Some(position) = self.details.get(&path).and_then(|d| d.position) {
+            self.maps.request(&path, position);
         }
+        self.ai_tags.entry(path.clone()).or_insert_with(|| cached_tags(&path));
+    }
+
+    /// Open where the image under the cursor was taken in a browser or
+    /// maps app. Returns a message for the status bar.
+    pub fn open_map(&self) -> anyhow::Result<String> {
+        let Some(path) = self.items.get(self.state.selected().unwrap_or(0)) else {
+            anyhow::bail!("No image");
+        };
+        let Some((lat, lon)) = self.details.get(path).and_then(|d| d.position) else {
+            anyhow::bail!("No location in {}", path);
+        };
+        Ok(format!("Opened {}", geo_map::open_map(self.map_url.as_deref(), lat, lon)?))
     }
 }
 
+/// What the TUI takes from the command line, the environment and the config
+/// files, resolved before it starts
+pub struct TuiSettings {
+    pub keys: KeyBindings,
+    /// Shell commands from the `[actions]` config table
+    pub actions: Vec<CustomAction>,
+    /// Memory budget of the full-resolution image cache in MB
+    pub cache_mb: usize,
+    /// Command the open key runs; xdg-open when unset
+    pub open_with: Option<String>,
+    /// Tile server the info panel's map downloads missing tiles from
+    pub map_tiles: Option<String>,
+    /// Page the map key opens; OpenStreetMap when unset
+    pub map_url: Option<String>,
+    /// Thumbnails per grid row wanted
+    pub grid_columns: Option<u16>,
+    /// Tags shown on each thumbnail
+    pub badge_tags: Option<usize>,
+}
+
 // Main function to run the TUI browser
-pub fn run_tui_browser(image_paths: Vec<String>) -> Result<(), Box<dyn std::error::Error>> {
+/// With `remember_view`, fullscreen views are restored from and saved to
+/// disk instead of only lasting for the session; with a `watcher`, images
+/// added to or removed from the watched directories come and go. The info
+/// panel lists the other paths `locations` holds for an image.
+pub fn run_tui_browser(
+    image_paths: Vec<String>,
+    settings: TuiSettings,
+    remember_view: bool,
+    watcher: Option<DirWatcher>,
+    locations: Locations,
+) -> Result<(), Box<dyn std::error::Error>> {
+    run_tui(image_paths, settings, |app| {
+        if remember_view {
+            app.view_states = ViewStates::load();
+        }
+        app.resume_dir = positions::browse_dir(&app.items);
+        app.watcher = watcher;
+        app.locations = locations;
+    })
+}
+
+/// Browse `groups` one after another, with the current group's name in the
+/// header; names given with the rename_group key are saved for later runs
+pub fn run_tui_grouped(
+    groups: Vec<ImageGroup>,
+    settings: TuiSettings,
+    remember_view: bool,
+    watcher: Option<DirWatcher>,
+    locations: Locations,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // An image can fall into several groups (e.g. tags); show it once, in
+    // the first of them
+    let mut seen = HashSet::new();
+    let groups: Vec<ImageGroup> = groups
+        .into_iter()
+        .map(|mut group| {
+            group.images.retain(|image| seen.insert(image.clone()));
+            group
+        })
+        .filter(|group| !group.images.is_empty())
+        .collect();
+    let image_paths: Vec<String> = groups.iter().flat_map(|g| g.images.clone()).collect();
+
+    run_tui(image_paths, settings, move |app| {
+        app.groups = groups;
+        app.group_names = GroupNames::load();
+        app.group_edits = GroupEdits::load();
+        if remember_view {
+            app.view_states = ViewStates::load();
+        }
+        app.resume_dir = positions::browse_dir(&app.items);
+        app.watcher = watcher;
+        app.locations = locations;
+    })
+}
+
+/// Browse duplicate groups with every copy but the kept one selected and
+/// the trash prompt open: y keeps the first of each group and trashes the
+/// rest, n leaves everything to review and adjust with the selection keys
+pub fn run_tui_dedup(
+    groups: &[DuplicateGroup],
+    settings: TuiSettings,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let image_paths: Vec<String> = groups.iter().flat_map(|g| g.images.clone()).collect();
+    let extras: Vec<String> = groups.iter().flat_map(|g| g.extras().to_vec()).collect();
+
+    run_tui(image_paths, settings, move |app| {
+        app.selection = extras.iter().cloned().collect();
+        app.prompt = Some(Prompt {
+            action: BatchAction::File(FileOp::Trash),
+            targets: extras,
+            input: String::new(),
+            tags: Vec::new(),
+        });
+    })
+}
+
+fn run_tui(
+    image_paths: Vec<String>,
+    settings: TuiSettings,
+    setup: impl FnOnce(&mut TuiBrowser),
+) -> Result<(), Box<dyn std::error::Error>> {
     // Initialize log file if logging is enabled
     if is_logging_enabled() {
-        if let Ok(mut file) = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open("/tmp/lsix_tui.log")
-        {
+        if let Ok(mut file) = app_dirs::open_log(true) {
             writeln!(file, "=== LSIX TUI Browser Log ===").ok();
             writeln!(file, "Start time: {}", chrono::Local::now().format("%Y-%m-%d %H:%M:%S")).ok();
             writeln!(file, "Total images: {}\n", image_paths.len()).ok();
@@ -150,6 +1743,11 @@ pub fn run_tui_browser(image_paths: Vec<String>) -> Result<(), Box<dyn std::erro
     }
     
     trace_log("Starting TUI browser initialization");
+
+    // Files can vanish between scanning and startup; don't open an empty browser
+    if !image_paths.is_empty() && image_paths.iter().all(|path| is_missing(path)) {
+        return Err(format!("None of the {} images exist any more", image_paths.len()).into());
+    }
     
     // Clear any pending input events before starting TUI
     // This prevents issues from terminal queries done before TUI initialization
@@ -175,6 +1773,21 @@ pub fn run_tui_browser(image_paths: Vec<String>) -> Result<(), Box<dyn std::erro
         .to_string();
 
     let mut app = TuiBrowser::new(image_paths, current_dir);
+    app.keys = settings.keys;
+    app.actions = settings.actions;
+    app.image_cache = ImageCache::new(settings.cache_mb);
+    app.grid_columns = settings
+        .grid_columns
+        .map_or(DEFAULT_GRID_COLUMNS, |columns| columns.clamp(1, MAX_GRID_COLUMNS));
+    app.badge_tags = settings.badge_tags.unwrap_or(DEFAULT_BADGE_TAGS);
+    app.maps = MapLoader::new(settings.map_tiles);
+    app.map_url = settings.map_url;
+    app.open_with = settings.open_with;
+    setup(&mut app);
+    app.base_groups = app.groups.clone();
+    let items = app.items.clone();
+    app.drop_missing(&items);
+    app.restore_position();
     
     trace_log("Initializing image picker");
     
@@ -187,6 +1800,14 @@ pub fn run_tui_browser(image_paths: Vec<String>) -> Result<(), Box<dyn std::erro
     // Run the main loop
     let res = run_app(&mut terminal, &mut app);
 
+    app.store_view();
+    if let Err(e) = app.view_states.save() {
+        trace_log(&format!("Failed to save view state: {:#}", e));
+    }
+    if let Err(e) = app.save_position() {
+        trace_log(&format!("Failed to save the position: {:#}", e));
+    }
+
     trace_log("Exiting TUI browser, restoring terminal");
 
     // Restore terminal
@@ -218,10 +1839,49 @@ fn run_app(
         // Use poll to check if there's an event available with a timeout
         // This allows the UI to update even if no key is pressed
         if event::poll(std::time::Duration::from_millis(100))? {
-            if let Event::Key(key) = event::read()? {
-                match key.code {
-                    KeyCode::Char('q') => {
-                        if app.fullscreen_mode {
+            let event = event::read()?;
+            if let Event::Mouse(mouse) = event {
+                if handle_mouse(app, mouse) {
+                    app.message = None;
+                    terminal.draw(|f| ui(f, app))?;
+                }
+                continue;
+            }
+            if let Event::Key(key) = event {
+                app.message = None;
+                if app.prompt.is_some() {
+                    handle_prompt_key(app, key);
+                    terminal.draw(|f| ui(f, app))?;
+                    continue;
+                }
+                if app.output_pane.is_some() {
+                    handle_output_key(app, key);
+                    terminal.draw(|f| ui(f, app))?;
+                    continue;
+                }
+                if app.sidebar.is_some() {
+                    handle_sidebar_key(app, key);
+                    terminal.draw(|f| ui(f, app))?;
+                    continue;
+                }
+                if app.help_scroll.is_some() {
+                    handle_help_key(app, key);
+                    terminal.draw(|f| ui(f, app))?;
+                    continue;
+                }
+                let code = if app.fullscreen_mode && !app.wipe_mode {
+                    vim_arrow(key.code)
+                } else {
+                    key.code
+                };
+
+                match code {
+                    code if code == app.keys.quit => {
+                        if app.wipe_mode {
+                            // Exit wipe comparison
+                            app.wipe_mode = false;
+                            terminal.draw(|f| ui(f, app))?;
+                        } else if app.fullscreen_mode {
                             // Exit fullscreen mode
                             app.fullscreen_mode = false;
                             terminal.draw(|f| ui(f, app))?;
@@ -230,8 +1890,28 @@ fn run_app(
                             return Ok(());
                         }
                     }
+                    KeyCode::Esc if !app.fullscreen_mode && !app.wipe_mode && app.clear_selection() => {
+                        terminal.draw(|f| ui(f, app))?;
+                    }
+                    KeyCode::Esc if !app.fullscreen_mode && !app.wipe_mode && !app.search.is_empty() => {
+                        app.message = app.set_search("").ok();
+                        terminal.draw(|f| ui(f, app))?;
+                    }
+                    // Only matches are shown, so the next match is the next item
+                    code if code == app.keys.next_match && !app.wipe_mode && !app.search.is_empty() => {
+                        app.next();
+                        terminal.draw(|f| ui(f, app))?;
+                    }
+                    code if code == app.keys.prev_match && !app.wipe_mode && !app.search.is_empty() => {
+                        app.previous();
+                        terminal.draw(|f| ui(f, app))?;
+                    }
                     KeyCode::Esc => {
-                        if app.fullscreen_mode {
+                        if app.wipe_mode {
+                            // Exit wipe comparison
+                            app.wipe_mode = false;
+                            terminal.draw(|f| ui(f, app))?;
+                        } else if app.fullscreen_mode {
                             // Exit fullscreen mode
                             app.fullscreen_mode = false;
                             terminal.draw(|f| ui(f, app))?;
@@ -240,6 +1920,19 @@ fn run_app(
                             return Ok(());
                         }
                     }
+                    KeyCode::Up | KeyCode::Down | KeyCode::Left | KeyCode::Right
+                        if app.fullscreen_mode && app.can_pan =>
+                    {
+                        // Scroll the cropped image instead of changing images
+                        let (dx, dy) = match code {
+                            KeyCode::Left => (-1.0, 0.0),
+                            KeyCode::Right => (1.0, 0.0),
+                            KeyCode::Up => (0.0, -1.0),
+                            _ => (0.0, 1.0),
+                        };
+                        app.view.pan_by(dx, dy);
+                        terminal.draw(|f| ui(f, app))?;
+                    }
                     KeyCode::Down => {
                         if app.fullscreen_mode {
                             // In fullscreen mode, ignore navigation
@@ -300,6 +1993,16 @@ fn run_app(
                         }
                         terminal.draw(|f| ui(f, app))?;
                     }
+                    KeyCode::Left if app.wipe_mode => {
+                        // Reveal more of the "after" image
+                        app.adjust_wipe_split(-0.05);
+                        terminal.draw(|f| ui(f, app))?;
+                    }
+                    KeyCode::Right if app.wipe_mode => {
+                        // Reveal more of the "before" image
+                        app.adjust_wipe_split(0.05);
+                        terminal.draw(|f| ui(f, app))?;
+                    }
                     KeyCode::Left => {
                         // Move left in grid
                         if let Some(selected) = app.state.selected() {
@@ -323,6 +2026,116 @@ fn run_app(
                         }
                         terminal.draw(|f| ui(f, app))?;
                     }
+                    code if code == app.keys.boxes && app.fullscreen_mode => {
+                        app.show_boxes = !app.show_boxes;
+                        terminal.draw(|f| ui(f, app))?;
+                    }
+                    code if code == app.keys.fit && app.fullscreen_mode => {
+                        app.view.cycle_fit();
+                        terminal.draw(|f| ui(f, app))?;
+                    }
+                    code if code == app.keys.rotate && app.fullscreen_mode => {
+                        app.view.rotate();
+                        terminal.draw(|f| ui(f, app))?;
+                    }
+                    code if code == app.keys.rotate_back && app.fullscreen_mode => {
+                        app.view.rotate_back();
+                        terminal.draw(|f| ui(f, app))?;
+                    }
+                    code if code == app.keys.mirror && app.fullscreen_mode => {
+                        app.view.mirror();
+                        terminal.draw(|f| ui(f, app))?;
+                    }
+                    code if code == app.keys.save_view && app.fullscreen_mode => {
+                        app.start_save_view();
+                        terminal.draw(|f| ui(f, app))?;
+                    }
+                    code if code == app.keys.zoom_in && !app.fullscreen_mode && !app.wipe_mode => {
+                        // Bigger thumbnails, fewer per row
+                        app.message = Some(app.resize_grid(-1));
+                        terminal.draw(|f| ui(f, app))?;
+                    }
+                    code if code == app.keys.zoom_out && !app.fullscreen_mode && !app.wipe_mode => {
+                        app.message = Some(app.resize_grid(1));
+                        terminal.draw(|f| ui(f, app))?;
+                    }
+                    code if code == app.keys.labels && !app.fullscreen_mode && !app.wipe_mode => {
+                        app.grid_labels = app.grid_labels.next();
+                        app.message = Some(match app.grid_labels {
+                            GridLabels::Names => "Captions: file names",
+                            GridLabels::Numbered => "Captions: numbers and file names",
+                            GridLabels::Off => "Captions off",
+                        }
+                        .to_string());
+                        terminal.draw(|f| ui(f, app))?;
+                    }
+                    code if code == app.keys.badges && !app.fullscreen_mode && !app.wipe_mode => {
+                        app.badges = !app.badges;
+                        let state = if app.badges { "on" } else { "off" };
+                        app.message = Some(format!("Badges {}", state));
+                        terminal.draw(|f| ui(f, app))?;
+                    }
+                    code if app.keys.rate.position(code).is_some() && !app.wipe_mode => {
+                        let stars = app.keys.rate.position(code).unwrap_or(0) as u8 + 1;
+                        app.message = Some(match app.rate(stars.min(culling::MAX_STARS)) {
+                            Ok(message) => message,
+                            Err(e) => format!("Error: {:#}", e),
+                        });
+                        terminal.draw(|f| ui(f, app))?;
+                    }
+                    code if (code == app.keys.pick || code == app.keys.reject) && !app.wipe_mode => {
+                        let flag = if code == app.keys.pick { Flag::Pick } else { Flag::Reject };
+                        app.message = Some(match app.toggle_flag(flag) {
+                            Ok(message) => message,
+                            Err(e) => format!("Error: {:#}", e),
+                        });
+                        terminal.draw(|f| ui(f, app))?;
+                    }
+                    code if code == app.keys.zoom_in && app.fullscreen_mode => {
+                        app.view.zoom_by(1);
+                        terminal.draw(|f| ui(f, app))?;
+                    }
+                    code if code == app.keys.zoom_out && app.fullscreen_mode => {
+                        app.view.zoom_by(-1);
+                        terminal.draw(|f| ui(f, app))?;
+                    }
+                    code if code == app.keys.reset_view && app.fullscreen_mode => {
+                        app.view.reset();
+                        terminal.draw(|f| ui(f, app))?;
+                    }
+                    code if code == app.keys.compare && !app.fullscreen_mode && !app.wipe_mode => {
+                        // Mark/unmark the selected image for before/after comparison
+                        app.toggle_compare_mark();
+                        terminal.draw(|f| ui(f, app))?;
+                    }
+                    code if !app.fullscreen_mode && !app.wipe_mode && batch_action_for(&app.keys, code).is_some() => {
+                        if let Some(action) = batch_action_for(&app.keys, code) {
+                            app.start_batch_action(action);
+                        }
+                        terminal.draw(|f| ui(f, app))?;
+                    }
+                    code if code == app.keys.select && !app.fullscreen_mode && !app.wipe_mode => {
+                        app.toggle_selection();
+                        terminal.draw(|f| ui(f, app))?;
+                    }
+                    code if code == app.keys.visual && !app.fullscreen_mode && !app.wipe_mode => {
+                        app.toggle_visual();
+                        terminal.draw(|f| ui(f, app))?;
+                    }
+                    code if code == app.keys.select_all && !app.fullscreen_mode && !app.wipe_mode => {
+                        app.select_all();
+                        terminal.draw(|f| ui(f, app))?;
+                    }
+                    code if code == app.keys.wipe && app.compare_marks.len() == 2 => {
+                        // Toggle the wipe view once two images are marked
+                        app.wipe_mode = !app.wipe_mode;
+                        trace_log(&format!(
+                            "Wipe comparison {}: {:?}",
+                            if app.wipe_mode { "started" } else { "closed" },
+                            app.compare_marks
+                        ));
+                        terminal.draw(|f| ui(f, app))?;
+                    }
                     KeyCode::Char('g') if key.modifiers.contains(KeyModifiers::CONTROL) => {
                         app.state.select(Some(0));
                         app.update_selected_image();
@@ -334,24 +2147,11 @@ fn run_app(
                         terminal.draw(|f| ui(f, app))?;
                     }
                     KeyCode::PageUp => {
-                        // Move up by one page (grid size)
-                        let items_per_page = (app.grid_cols * app.grid_rows) as usize;
-                        let current = app.state.selected().unwrap_or(0);
-                        let new_index = current.saturating_sub(items_per_page);
-                        app.state.select(Some(new_index));
-                        app.update_selected_image();
-                        app.ensure_selection_visible();
+                        app.page_up();
                         terminal.draw(|f| ui(f, app))?;
                     }
                     KeyCode::PageDown => {
-                        // Move down by one page (grid size)
-                        let items_per_page = (app.grid_cols * app.grid_rows) as usize;
-                        let current = app.state.selected().unwrap_or(0);
-                        let new_index =
-                            std::cmp::min(current + items_per_page, app.items.len().saturating_sub(1));
-                        app.state.select(Some(new_index));
-                        app.update_selected_image();
-                        app.ensure_selection_visible();
+                        app.page_down();
                         terminal.draw(|f| ui(f, app))?;
                     }
                     KeyCode::Home => {
@@ -366,7 +2166,7 @@ fn run_app(
                         }
                         terminal.draw(|f| ui(f, app))?;
                     }
-                    KeyCode::Enter => {
+                    code if code == app.keys.view => {
                         trace_log(&format!(
                             "=== ENTER KEY PRESSED ===\n\
                             Current state:\n\
@@ -406,17 +2206,855 @@ fn run_app(
                         
                         trace_log("=== ENTER KEY HANDLED ===\n");
                     }
+                    code if code == app.keys.help && !app.wipe_mode => {
+                        app.help_scroll = Some(0);
+                        terminal.draw(|f| ui(f, app))?;
+                    }
+                    code if code == app.keys.info && !app.wipe_mode => {
+                        app.show_info = !app.show_info;
+                        terminal.draw(|f| ui(f, app))?;
+                    }
+                    code if code == app.keys.map && app.show_info && !app.wipe_mode => {
+                        app.message = Some(match app.open_map() {
+                            Ok(message) => message,
+                            Err(e) => format!("Error: {:#}", e),
+                        });
+                        terminal.draw(|f| ui(f, app))?;
+                    }
+                    code if (code == app.keys.move_to_group
+                        || code == app.keys.merge_group
+                        || code == app.keys.split_group)
+                        && !app.fullscreen_mode
+                        && !app.wipe_mode =>
+                    {
+                        let result = if code == app.keys.move_to_group {
+                            app.move_to_group()
+                        } else if code == app.keys.merge_group {
+                            app.merge_next_group()
+                        } else {
+                            app.split_group()
+                        };
+                        app.message = Some(match result {
+                            Ok(message) => message,
+                            Err(e) => format!("Error: {:#}", e),
+                        });
+                        terminal.draw(|f| ui(f, app))?;
+                    }
+                    code if code == app.keys.folders && !app.fullscreen_mode && !app.wipe_mode => {
+                        app.open_sidebar();
+                        terminal.draw(|f| ui(f, app))?;
+                    }
+                    code if code == app.keys.output && !app.fullscreen_mode && !app.wipe_mode => {
+                        app.open_output_pane();
+                        terminal.draw(|f| ui(f, app))?;
+                    }
+                    code if code == app.keys.open && !app.wipe_mode => {
+                        app.message = Some(match open_externally(terminal, app) {
+                            Ok(message) => message,
+                            Err(e) => format!("Error: {:#}", e),
+                        });
+                        terminal.draw(|f| ui(f, app))?;
+                    }
+                    code if (code == app.keys.yank || code == app.keys.yank_image)
+                        && !app.wipe_mode =>
+                    {
+                        app.message = Some(match app.yank(code == app.keys.yank_image) {
+                            Ok(message) => message,
+                            Err(e) => format!("Error: {:#}", e),
+                        });
+                        terminal.draw(|f| ui(f, app))?;
+                    }
+                    code if !app.wipe_mode && app.actions.iter().any(|a| a.key == code) => {
+                        // Built-in keys win over actions bound to the same key
+                        if let Some(idx) = app.actions.iter().position(|a| a.key == code) {
+                            app.run_action(idx);
+                        }
+                        terminal.draw(|f| ui(f, app))?;
+                    }
                     _ => {}
                 }
             }
+        } else {
+            // Idle: pick up decoded thumbnails and keep the spinner moving;
+            // a fullscreen or wipe image that failed to load may have been
+            // deleted, in which case the view moves on without it
+            let busy = app.loader.is_busy();
+            let unloaded = app.unloaded_in_view();
+            let dropped = !unloaded.is_empty() && app.drop_missing(&unloaded);
+            let finished = app.receive_action_results();
+            let changed = app.receive_changes();
+            let mapped = app.maps.receive();
+            if app.receive_thumbnails() || busy || dropped || finished || changed || mapped {
+                app.spinner_frame = app.spinner_frame.wrapping_add(1);
+                terminal.draw(|f| ui(f, app))?;
+            }
+        }
+    }
+}
+
+/// Hand the terminal to the program that opens images until it exits, with
+/// the image under the cursor, then take it back. Returns a message for the
+/// status bar.
+fn open_externally(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    app: &mut TuiBrowser,
+) -> anyhow::Result<String> {
+    let Some(path) = app.selected_image.clone() else {
+        anyhow::bail!("No image to open");
+    };
+    let command = tui_actions::open_command(app.open_with.as_deref())?;
+    trace_log(&format!("Opening {} with {:?}", path, command));
+
+    disable_raw_mode()?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen, DisableMouseCapture)?;
+    terminal.show_cursor()?;
+    let status = tui_actions::run_attached(&command, &path);
+    enable_raw_mode()?;
+    execute!(terminal.backend_mut(), EnterAlternateScreen, EnableMouseCapture)?;
+    terminal.clear()?;
+    // Keys meant for the program that it left unread don't drive the browser
+    while event::poll(std::time::Duration::from_millis(0))? {
+        event::read()?;
+    }
+
+    // An editor may have changed the image
+    app.forget_image(&path);
+    match status {
+        status if status.is_success() => Ok(format!("Opened {} with {}", path, command)),
+        RunStatus::Exited(code) => anyhow::bail!("{} exited with {}", command, code),
+        RunStatus::Failed(e) => anyhow::bail!("Failed to run {}: {}", command, e),
+        _ => anyhow::bail!("{} was killed", command),
+    }
+}
+
+/// hjkl as the arrow keys they stand for in vi, other keys unchanged
+fn vim_arrow(code: KeyCode) -> KeyCode {
+    match code {
+        KeyCode::Char('h') => KeyCode::Left,
+        KeyCode::Char('j') => KeyCode::Down,
+        KeyCode::Char('k') => KeyCode::Up,
+        KeyCode::Char('l') => KeyCode::Right,
+        code => code,
+    }
+}
+
+/// Batch action bound to `code`, if any
+fn batch_action_for(keys: &KeyBindings, code: KeyCode) -> Option<BatchAction> {
+    if code == keys.delete {
+        Some(BatchAction::File(FileOp::Trash))
+    } else if code == keys.rename {
+        Some(BatchAction::File(FileOp::Rename))
+    } else if code == keys.move_to {
+        Some(BatchAction::File(FileOp::Move))
+    } else if code == keys.copy_to {
+        Some(BatchAction::File(FileOp::Copy))
+    } else if code == keys.tag {
+        Some(BatchAction::Tag)
+    } else if code == keys.export {
+        Some(BatchAction::Export)
+    } else if code == keys.rename_group {
+        Some(BatchAction::RenameGroup)
+    } else if code == keys.command {
+        Some(BatchAction::Command)
+    } else if code == keys.search {
+        Some(BatchAction::Search)
+    } else {
+        None
+    }
+}
+
+/// Keys while the output pane is open
+fn handle_output_key(app: &mut TuiBrowser, key: KeyEvent) {
+    let last = app.runner.invocations.len().saturating_sub(1);
+    let Some(pane) = app.output_pane.as_mut() else {
+        return;
+    };
+    match key.code {
+        code if code == app.keys.retry => app.retry_action(),
+        code if code == app.keys.quit || code == app.keys.output || code == KeyCode::Esc => {
+            app.output_pane = None;
+        }
+        KeyCode::Up if pane.selected > 0 => {
+            *pane = OutputPane { selected: pane.selected - 1, scroll: 0 };
+        }
+        KeyCode::Down if pane.selected < last => {
+            *pane = OutputPane { selected: pane.selected + 1, scroll: 0 };
+        }
+        KeyCode::PageUp => pane.scroll = pane.scroll.saturating_sub(10),
+        KeyCode::PageDown => pane.scroll = pane.scroll.saturating_add(10),
+        KeyCode::Home => pane.scroll = 0,
+        _ => {}
+    }
+}
+
+/// Keys while the help popup is open: scrolling, anything else closes it
+fn handle_help_key(app: &mut TuiBrowser, key: KeyEvent) {
+    let last = help_lines(app).len().saturating_sub(1);
+    let Some(scroll) = app.help_scroll.as_mut() else {
+        return;
+    };
+    match vim_arrow(key.code) {
+        KeyCode::Up => *scroll = scroll.saturating_sub(1),
+        KeyCode::Down => *scroll = (*scroll + 1).min(last),
+        KeyCode::PageUp => *scroll = scroll.saturating_sub(10),
+        KeyCode::PageDown => *scroll = (*scroll + 10).min(last),
+        KeyCode::Home => *scroll = 0,
+        _ => app.help_scroll = None,
+    }
+}
+
+/// Columns and rows of a grid in `area` for `columns` thumbnails per row:
+/// as many columns as fit, and rows in the proportion of the default 5x3
+fn grid_size(area: Rect, columns: u16) -> (u16, u16) {
+    let min_cell_width = 12;
+    let min_cell_height = 8;
+
+    let max_cols = std::cmp::max(1, area.width / min_cell_width);
+    let max_rows = std::cmp::max(1, area.height / min_cell_height);
+    let rows = (columns * 3 + 2) / 5;
+    (columns.min(max_cols), rows.clamp(1, max_rows))
+}
+
+/// Mouse in the grid: a click selects an image, a double click opens it
+/// fullscreen and the wheel pages. Nothing else takes the mouse, so it is
+/// ignored while a popup, the sidebar or a fullscreen view is open.
+/// Returns whether the screen needs drawing again.
+fn handle_mouse(app: &mut TuiBrowser, mouse: MouseEvent) -> bool {
+    let popup = app.prompt.is_some() || app.output_pane.is_some() || app.help_scroll.is_some();
+    if popup || app.sidebar.is_some() || app.fullscreen_mode || app.wipe_mode {
+        return false;
+    }
+    match mouse.kind {
+        MouseEventKind::ScrollUp => app.page_up(),
+        MouseEventKind::ScrollDown => app.page_down(),
+        MouseEventKind::Down(MouseButton::Left) => {
+            let Some(idx) = app.item_at(mouse.column, mouse.row) else {
+                return false;
+            };
+            let now = std::time::Instant::now();
+            let double = app
+                .last_click
+                .is_some_and(|(last, at)| last == idx && now.duration_since(at) < DOUBLE_CLICK);
+            // A third click starts a new double click
+            app.last_click = (!double).then_some((idx, now));
+            app.state.select(Some(idx));
+            app.update_selected_image();
+            app.fullscreen_mode = double;
+        }
+        _ => return false,
+    }
+    true
+}
+
+/// Keys while the folder sidebar is open: opening a folder shows its images
+fn handle_sidebar_key(app: &mut TuiBrowser, key: KeyEvent) {
+    let Some(tree) = app.sidebar.as_mut() else {
+        return;
+    };
+    let opened = match vim_arrow(key.code) {
+        KeyCode::Up => {
+            tree.move_selection(-1);
+            return;
+        }
+        KeyCode::Down => {
+            tree.move_selection(1);
+            return;
+        }
+        KeyCode::Enter | KeyCode::Right => tree.enter(),
+        KeyCode::Backspace | KeyCode::Left => tree.up(),
+        code if code == app.keys.folders || code == app.keys.quit || code == KeyCode::Esc => {
+            app.sidebar = None;
+            return;
+        }
+        _ => return,
+    };
+    app.message = Some(match opened.and_then(|dir| app.open_dir(&dir)) {
+        Ok(message) => message,
+        Err(e) => format!("Error: {:#}", e),
+    });
+}
+
+/// Keys while a batch action prompt is open
+fn handle_prompt_key(app: &mut TuiBrowser, key: KeyEvent) {
+    let Some(prompt) = app.prompt.as_mut() else {
+        return;
+    };
+
+    // The tag editor stays open after each edit, so several can be made
+    if prompt.action == BatchAction::Tag
+        && key.code == KeyCode::Enter
+        && !prompt.input.trim().is_empty()
+    {
+        let Some(mut prompt) = app.prompt.take() else {
+            return;
+        };
+        app.message = Some(match app.edit_tags(&mut prompt) {
+            Ok(message) => message,
+            Err(e) => format!("Error: {:#}", e),
+        });
+        app.prompt = Some(prompt);
+        return;
+    }
+
+    // A search is applied as it is typed; Enter keeps it and Esc clears it
+    if prompt.action == BatchAction::Search {
+        match key.code {
+            KeyCode::Enter => app.prompt = None,
+            KeyCode::Esc => {
+                app.prompt = None;
+                app.message = app.set_search("").ok();
+            }
+            KeyCode::Backspace | KeyCode::Char(_) => {
+                if let KeyCode::Char(c) = key.code {
+                    prompt.input.push(c);
+                } else {
+                    prompt.input.pop();
+                }
+                let input = prompt.input.clone();
+                app.message = Some(match app.set_search(&input) {
+                    Ok(message) => message,
+                    Err(e) => format!("Error: {:#}", e),
+                });
+            }
+            _ => {}
+        }
+        return;
+    }
+
+    let confirmed = match prompt.action {
+        BatchAction::SaveView(_) => matches!(key.code, KeyCode::Char('y') | KeyCode::Enter),
+        BatchAction::File(FileOp::Trash | FileOp::Delete) => match key.code {
+            KeyCode::Char('y') | KeyCode::Enter => true,
+            KeyCode::Char('D') => {
+                prompt.action = BatchAction::File(FileOp::Delete);
+                true
+            }
+            _ => false,
+        },
+        BatchAction::RenameSequence { suffix } if key.code == KeyCode::Tab => {
+            prompt.action = BatchAction::RenameSequence { suffix: !suffix };
+            return;
+        }
+        BatchAction::RenameSequence { suffix } if key.code == KeyCode::Enter => {
+            // Stay open on names that collide, for another pattern or Tab
+            match rename_plan(&prompt.targets, &prompt.input, suffix) {
+                Ok(plan) if plan.conflicts() > 0 => {
+                    app.message = Some(format!("{} of the new names collide", plan.conflicts()));
+                    return;
+                }
+                Err(e) => {
+                    app.message = Some(format!("Error: {:#}", e));
+                    return;
+                }
+                Ok(_) => true,
+            }
+        }
+        _ => match key.code {
+            KeyCode::Enter => true,
+            KeyCode::Esc => false,
+            KeyCode::Backspace => {
+                prompt.input.pop();
+                return;
+            }
+            KeyCode::Char(c) => {
+                prompt.input.push(c);
+                return;
+            }
+            _ => return,
+        },
+    };
+
+    let Some(prompt) = app.prompt.take() else {
+        return;
+    };
+    if !confirmed {
+        return;
+    }
+
+    trace_log(&format!("Batch action {:?} on {:?}", prompt.action, prompt.targets));
+    app.message = Some(match app.apply_batch_action(prompt) {
+        Ok(message) => message,
+        Err(e) => format!("Error: {:#}", e),
+    });
+}
+
+/// Centered popup for the pending batch action
+/// Plan of a sequence rename, with colliding names numbered if `suffix`
+fn rename_plan(targets: &[String], pattern: &str, suffix: bool) -> anyhow::Result<RenamePlan> {
+    let plan = bulk_rename::plan(targets, pattern)?;
+    Ok(if suffix { plan.with_suffixes() } else { plan })
+}
+
+/// Old and new names of a sequence rename, collisions first
+fn rename_preview(plan: &RenamePlan) -> String {
+    let name = |path: &Path| path.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let mut renames: Vec<_> = plan.renames.iter().collect();
+    renames.sort_by_key(|r| r.conflict.is_none());
+    let mut lines: Vec<String> = renames
+        .iter()
+        .take(RENAME_PREVIEW_LINES)
+        .map(|r| match r.conflict {
+            Some(conflict) => {
+                format!("{} → {}  ✗ {}", name(&r.from), name(&r.to), conflict.label())
+            }
+            None => format!("{} → {}", name(&r.from), name(&r.to)),
+        })
+        .collect();
+    if renames.len() > RENAME_PREVIEW_LINES {
+        lines.push(format!("... and {} more", renames.len() - RENAME_PREVIEW_LINES));
+    }
+    if plan.conflicts() > 0 {
+        lines.insert(0, format!("{} of the new names collide:", plan.conflicts()));
+    }
+    lines.join("\n")
+}
+
+fn render_prompt(f: &mut Frame, prompt: &Prompt) {
+    let names: Vec<String> = prompt
+        .targets
+        .iter()
+        .map(|p| {
+            Path::new(p)
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_else(|| p.clone())
+        })
+        .collect();
+    let subject = if names.len() == 1 {
+        names[0].clone()
+    } else {
+        format!("{} files", names.len())
+    };
+
+    let (title, body) = match prompt.action {
+        BatchAction::File(FileOp::Trash | FileOp::Delete) => (
+            "Delete".to_string(),
+            format!(
+                "Move {} to trash?\n\ny: Trash | D: Delete permanently | n/Esc: Cancel",
+                subject
+            ),
+        ),
+        BatchAction::File(FileOp::Rename) => (
+            format!("Rename {}", subject),
+            format!("New name: {}_\n\nEnter: Rename | Esc: Cancel", prompt.input),
+        ),
+        BatchAction::RenameSequence { suffix } => {
+            let preview = match rename_plan(&prompt.targets, &prompt.input, suffix) {
+                Ok(plan) => rename_preview(&plan),
+                Err(e) => format!("{:#}", e),
+            };
+            (
+                format!("Rename {}", subject),
+                format!(
+                    "Pattern: {}_\n\
+                     {{n}}, {{n:3}}: Number | {{name}}: Old name | {{ext}}: Extension\n\n\
+                     {}\n\nEnter: Rename | Tab: {} | Esc: Cancel",
+                    prompt.input,
+                    preview,
+                    if suffix { "Don't number collisions" } else { "Number collisions (_2, _3)" }
+                ),
+            )
+        }
+        BatchAction::File(FileOp::Move) => (
+            format!("Move {}", subject),
+            format!("To directory: {}_\n\nEnter: Move | Esc: Cancel", prompt.input),
+        ),
+        BatchAction::File(FileOp::Copy) => (
+            format!("Copy {}", subject),
+            format!("To directory: {}_\n\nEnter: Copy | Esc: Cancel", prompt.input),
+        ),
+        BatchAction::Tag => {
+            let tags: Vec<String> = prompt
+                .tags
+                .iter()
+                .map(|(tag, count)| match count {
+                    n if *n == prompt.targets.len() => tag.clone(),
+                    n => format!("{} ({}/{})", tag, n, prompt.targets.len()),
+                })
+                .collect();
+            let current = if tags.is_empty() { "(none)".to_string() } else { tags.join(", ") };
+            (
+                format!("Tags of {}", subject),
+                format!(
+                    "Tags: {}\nEdit: {}_\n\n\
+                     Enter: Apply, empty to finish | -tag: Remove | Esc: Close",
+                    current, prompt.input
+                ),
+            )
+        }
+        BatchAction::Export => (
+            format!("Export {}", subject),
+            format!("List file: {}_\n\nEnter: Write paths | Esc: Cancel", prompt.input),
+        ),
+        BatchAction::RenameGroup => (
+            "Rename group".to_string(),
+            format!("Group name: {}_\n\nEnter: Rename | Esc: Cancel", prompt.input),
+        ),
+        BatchAction::SaveView(method) => (
+            format!("Save {}", subject),
+            format!(
+                "Write the rotation into the file? {}\n\ny/Enter: Save | n/Esc: Cancel",
+                match method {
+                    SaveMethod::Lossless => "JPEG blocks are turned losslessly (jpegtran).",
+                    SaveMethod::Reencoded => "The image will be re-encoded.",
+                }
+            ),
+        ),
+        // Typed into the status bar instead
+        BatchAction::Command | BatchAction::Search => return,
+    };
+
+    let area = f.area();
+    let width = (area.width * 3 / 5).max(40).min(area.width);
+    // One row per wrapped line, plus the borders
+    let inner_width = width.saturating_sub(2).max(1) as usize;
+    let rows: usize = body
+        .lines()
+        .map(|line| line.chars().count().div_ceil(inner_width).max(1))
+        .sum();
+    let height = (rows as u16 + 2).min(area.height);
+    let popup = Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    );
+
+    f.render_widget(Clear, popup);
+    let paragraph = Paragraph::new(body)
+        .style(Style::default().fg(Color::White).bg(Color::Black))
+        .wrap(Wrap { trim: false })
+        .block(Block::default().borders(Borders::ALL).title(title));
+    f.render_widget(paragraph, popup);
+}
+
+/// Marker and color for an invocation's status
+fn status_marker(status: &RunStatus, spinner_frame: usize) -> (String, Color) {
+    match status {
+        RunStatus::Pending => (SPINNER[spinner_frame % SPINNER.len()].to_string(), Color::Yellow),
+        RunStatus::Exited(0) => ("✓".to_string(), Color::Green),
+        RunStatus::Exited(code) => (format!("✗ {}", code), Color::Red),
+        RunStatus::Killed => ("✗ killed".to_string(), Color::Red),
+        RunStatus::Failed(_) => ("✗ not run".to_string(), Color::Red),
+    }
+}
+
+/// Output of one invocation: the command, stdout, stderr in red, and how
+/// it ended
+fn invocation_text(invocation: &Invocation) -> Text<'_> {
+    let mut lines = vec![Line::from(Span::styled(
+        format!("$ {}", invocation.command),
+        Style::default().add_modifier(Modifier::BOLD),
+    ))];
+    lines.extend(invocation.stdout.lines().map(Line::raw));
+    lines.extend(
+        invocation
+            .stderr
+            .lines()
+            .map(|line| Line::from(Span::styled(line, Style::default().fg(Color::Red)))),
+    );
+    let end = match &invocation.status {
+        RunStatus::Pending => "Running...".to_string(),
+        RunStatus::Exited(code) => format!("Exit status {}", code),
+        RunStatus::Killed => "Killed by a signal".to_string(),
+        RunStatus::Failed(e) => format!("Could not run: {}", e),
+    };
+    lines.push(Line::from(Span::styled(end, Style::default().add_modifier(Modifier::DIM))));
+    Text::from(lines)
+}
+
+/// Panel on the right describing the selected image: file details, EXIF
+/// capture data, cached AI tags and, for an image reached through hard
+/// links or bind mounts, all of its paths. A geotagged image gets a map of
+/// where it was taken at the bottom.
+fn render_info_panel(f: &mut Frame, app: &TuiBrowser) {
+    let Some(path) = app.items.get(app.state.selected().unwrap_or(0)) else {
+        return;
+    };
+    let area = f.area();
+    let width = (area.width / 2).max(30.min(area.width));
+    let height = area.height.saturating_sub(1);
+    let panel = Rect::new(area.x + area.width - width, area.y, width, height);
+    f.render_widget(Clear, panel);
+    let panel = match app.details.get(path).and_then(|d| d.position) {
+        Some(_) => {
+            let map_rows = MAP_ROWS.min(panel.height / 2);
+            let map_area = Rect { y: panel.bottom() - map_rows, height: map_rows, ..panel };
+            render_map(f, app, path, map_area);
+            Rect { height: panel.height - map_rows, ..panel }
+        }
+        None => panel,
+    };
+
+    let heading = Style::default().add_modifier(Modifier::BOLD);
+    let row = |label: &str, value: &str| {
+        Line::from(vec![
+            Span::styled(format!("{:<15}", label), Style::default().fg(Color::Gray)),
+            Span::raw(value.to_string()),
+        ])
+    };
+    let mut lines = Vec::new();
+    if let Some(details) = app.details.get(path) {
+        lines.extend(details.rows().iter().map(|(label, value)| row(label, value)));
+        if !details.capture.is_empty() {
+            lines.push(Line::default());
+            lines.push(Line::from(Span::styled("Capture", heading)));
+            lines.extend(details.capture.iter().map(|(label, value)| row(label, value)));
+        }
+    }
+    lines.push(Line::default());
+    lines.push(Line::from(Span::styled("AI tags", heading)));
+    match app.ai_tags.get(path).and_then(Option::as_ref) {
+        Some(tags) => {
+            lines.push(Line::from(tags.tags.join(", ")));
+            if let Some(caption) = &tags.caption {
+                lines.push(Line::from(Span::styled(
+                    caption.as_str(),
+                    Style::default().add_modifier(Modifier::ITALIC),
+                )));
+            }
+        }
+        None => lines.push(Line::from(Span::styled(
+            "Not tagged",
+            Style::default().fg(Color::DarkGray),
+        ))),
+    }
+    lines.push(Line::default());
+    lines.push(Line::from(Span::styled("Path", heading)));
+    lines.push(Line::from(path.as_str()));
+    if let Some(all) = app.locations.get(path) {
+        lines.push(Line::default());
+        lines.push(Line::from(Span::styled(locations_badge(all.len()), heading)));
+        lines.extend(all.iter().map(|location| Line::from(format!("  {}", location))));
+    }
+    let info = Paragraph::new(lines)
+        .style(Style::default().fg(Color::White).bg(Color::Black))
+        .wrap(Wrap { trim: false })
+        .block(Block::default().borders(Borders::ALL).title("Info - i: Close"));
+    f.render_widget(info, panel);
+}
+
+/// Map of where the image at `path` was taken, once it is made
+fn render_map(f: &mut Frame, app: &TuiBrowser, path: &str, area: Rect) {
+    let title = format!("Location - {}: Open map", app.keys.map.label());
+    let block = Block::default().borders(Borders::ALL).title(title);
+    let inner = block.inner(area);
+    f.render_widget(block.style(Style::default().fg(Color::White).bg(Color::Black)), area);
+
+    let note = |text: &str| {
+        Paragraph::new(text.to_string())
+            .style(Style::default().fg(Color::DarkGray))
+            .wrap(Wrap { trim: true })
+    };
+    match app.maps.get(path) {
+        MapState::Ready(map) => {
+            if let Some(ref picker) = app.picker {
+                let mut image_protocol = picker.new_resize_protocol(map.clone());
+                let image_widget = StatefulImage::new().resize(Resize::Fit(None));
+                f.render_stateful_widget(image_widget, inner, &mut image_protocol);
+            }
+        }
+        MapState::Loading => f.render_widget(note("Loading map..."), inner),
+        MapState::Failed(e) => f.render_widget(note(&format!("No map: {}", e)), inner),
+    }
+}
+
+/// Folder sidebar on the left: `..` and the subdirectories, keeping the
+/// highlighted one in view
+fn render_sidebar(f: &mut Frame, tree: &DirTree, area: Rect) {
+    let rows = area.height.saturating_sub(2) as usize;
+    let first = (tree.selected + 1).saturating_sub(rows);
+    let lines: Vec<Line> = tree
+        .entries()
+        .into_iter()
+        .enumerate()
+        .skip(first)
+        .take(rows)
+        .map(|(idx, name)| {
+            let style = if idx == tree.selected {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default().fg(Color::Cyan)
+            };
+            Line::from(Span::styled(name, style))
+        })
+        .collect();
+    let name = tree
+        .dir
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| tree.dir.to_string_lossy().to_string());
+    let list = Paragraph::new(lines)
+        .style(Style::default().bg(Color::Black))
+        .block(Block::default().borders(Borders::ALL).title(format!("Folders - {}", name)));
+    f.render_widget(Clear, area);
+    f.render_widget(list, area);
+}
+
+/// Popup listing the actions run this session, with the selected one's
+/// output beside the list
+/// Lines of the help popup: the bindings in effect, the fixed keys and the
+/// custom actions
+fn help_lines(app: &TuiBrowser) -> Vec<Line<'static>> {
+    let key_style = Style::default().fg(Color::Yellow);
+    let heading = Style::default().add_modifier(Modifier::BOLD);
+    let line = |keys: String, what: String| {
+        Line::from(vec![Span::styled(format!("{:>12}  ", keys), key_style), Span::raw(what)])
+    };
+    let joined = |labels: Vec<String>, none: &str| {
+        if labels.is_empty() {
+            none.to_string()
+        } else {
+            labels.join(", ")
+        }
+    };
+    let mut lines = vec![Line::styled("Settings", heading)];
+    lines.push(line("grid".to_string(), format!("{} x {}", app.grid_cols, app.grid_rows)));
+    lines.push(line(
+        "images".to_string(),
+        format!("{} of {}", app.items.len(), app.all_items.len()),
+    ));
+    let sort = app.sort_order.iter().map(SortSpec::label).collect();
+    lines.push(line("sort".to_string(), joined(sort, "scan order")));
+    let filters = app.filters.iter().map(FilterTerm::label).collect();
+    lines.push(line("filter".to_string(), joined(filters, "none")));
+    let group = app.group_by.map_or("as started", GroupBy::name);
+    lines.push(line("group".to_string(), group.to_string()));
+    if !app.search.is_empty() {
+        lines.push(line("search".to_string(), app.search.clone()));
+    }
+    lines.push(Line::default());
+    lines.push(Line::styled("Keys ([keybindings])", heading));
+    lines.extend(
+        app.keys
+            .bindings()
+            .into_iter()
+            .map(|(name, what, keys)| line(keys.label(), format!("{} ({})", what, name))),
+    );
+    lines.push(Line::default());
+    lines.push(line("arrows".to_string(), "Move; pan a zoomed image in fullscreen".to_string()));
+    lines.push(line("hjkl".to_string(), "Arrows, in fullscreen and the sidebar".to_string()));
+    lines.push(line("pgup/pgdn".to_string(), "Previous or next page".to_string()));
+    lines.push(line(
+        "mouse".to_string(),
+        "Click: select; double click: fullscreen; wheel: page".to_string(),
+    ));
+    lines.push(line("esc".to_string(), "Back; clear the selection or search".to_string()));
+    if !app.actions.is_empty() {
+        lines.push(Line::default());
+        for action in &app.actions {
+            lines.push(line(keymap::key_name(action.key), action.command.clone()));
         }
     }
+    lines
+}
+
+/// The help popup, scrolled to line `scroll`
+fn render_help(f: &mut Frame, app: &TuiBrowser, scroll: usize) {
+    let lines = help_lines(app);
+    let area = f.area();
+    let width = 64.min(area.width);
+    let height = (lines.len() as u16 + 2).min(area.height);
+    let popup = Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    );
+    f.render_widget(Clear, popup);
+    // Don't scroll past the point where the last line is at the bottom
+    let rows = height.saturating_sub(2) as usize;
+    let scroll = scroll.min(lines.len().saturating_sub(rows));
+    let help = Paragraph::new(lines)
+        .style(Style::default().fg(Color::White).bg(Color::Black))
+        .scroll((scroll as u16, 0))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Help | Up/Down: Scroll | other keys: Close"),
+        );
+    f.render_widget(help, popup);
+}
+
+fn render_output_pane(f: &mut Frame, app: &TuiBrowser, pane: OutputPane) {
+    let area = f.area();
+    let popup = Rect::new(
+        area.x + area.width / 20,
+        area.y + area.height / 10,
+        area.width - area.width / 10,
+        area.height - area.height / 5,
+    );
+    f.render_widget(Clear, popup);
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(30), Constraint::Percentage(70)])
+        .split(popup);
+
+    // Keep the selected invocation in view in a long list
+    let rows = columns[0].height.saturating_sub(2) as usize;
+    let first = (pane.selected + 1).saturating_sub(rows);
+    let entries: Vec<Line> = app
+        .runner
+        .invocations
+        .iter()
+        .enumerate()
+        .skip(first)
+        .take(rows)
+        .map(|(idx, invocation)| {
+            let (marker, color) = status_marker(&invocation.status, app.spinner_frame);
+            let name = Path::new(&invocation.path)
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_else(|| invocation.path.clone());
+            let style = if idx == pane.selected {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            };
+            Line::from(vec![
+                Span::styled(format!("{} ", marker), style.fg(color)),
+                Span::styled(name, style),
+            ])
+        })
+        .collect();
+    let list = Paragraph::new(entries)
+        .style(Style::default().bg(Color::Black))
+        .block(Block::default().borders(Borders::ALL).title("Actions"));
+    f.render_widget(list, columns[0]);
+
+    let output = Paragraph::new(invocation_text(&app.runner.invocations[pane.selected]))
+        .style(Style::default().fg(Color::White).bg(Color::Black))
+        .wrap(Wrap { trim: false })
+        .scroll((pane.scroll, 0))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Output - Up/Down: Select | PgUp/PgDn: Scroll | R: Retry | Esc: Close"),
+        );
+    f.render_widget(output, columns[1]);
 }
 
 fn ui(f: &mut Frame, app: &mut TuiBrowser) {
+    // The wipe comparison takes over the whole screen like fullscreen mode
+    if app.wipe_mode {
+        render_wipe_compare(f, app);
+        return;
+    }
+
     // Check if we're in fullscreen mode
     if app.fullscreen_mode {
         render_fullscreen_image(f, app);
+        if app.show_info {
+            app.load_details();
+            render_info_panel(f, app);
+        }
+        // Saving a rotation is asked about over the image
+        if let Some(prompt) = &app.prompt {
+            render_prompt(f, prompt);
+        }
+        if let Some(scroll) = app.help_scroll {
+            render_help(f, app, scroll);
+        }
         return;
     }
     
@@ -430,13 +3068,47 @@ fn ui(f: &mut Frame, app: &mut TuiBrowser) {
         .split(f.area());
 
     // Header
-    let header_block = Block::default()
-        .borders(Borders::ALL)
-        .title(format!("TUI Image Browser - {}", app.current_dir));
+    let title = match app.current_group() {
+        Some(idx) => format!(
+            "TUI Image Browser - Group {}/{}: {} ({} images)",
+            idx + 1,
+            app.groups.len(),
+            app.groups[idx].name,
+            app.groups[idx].images.len()
+        ),
+        None => format!("TUI Image Browser - {}", app.current_dir),
+    };
+    let title = if app.items.len() < app.all_items.len() {
+        format!("{} - {} of {} shown", title, app.items.len(), app.all_items.len())
+    } else {
+        title
+    };
+    let title = if app.search.is_empty() {
+        title
+    } else {
+        format!("{} - /{}", title, app.search)
+    };
+    let header_block = Block::default().borders(Borders::ALL).title(title);
     f.render_widget(header_block, chunks[0]);
 
-    // Main content - grid of thumbnails
-    render_thumbnail_grid(f, app, chunks[1]);
+    // Main content - folder sidebar, when open, and grid of thumbnails
+    let grid_area = match &app.sidebar {
+        Some(tree) => {
+            let width = SIDEBAR_WIDTH.min(chunks[1].width / 2);
+            let columns = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Length(width), Constraint::Min(0)])
+                .split(chunks[1]);
+            render_sidebar(f, tree, columns[0]);
+            columns[1]
+        }
+        None => chunks[1],
+    };
+    render_thumbnail_grid(f, app, grid_area);
+    if app.show_info {
+        app.load_details();
+        render_info_panel(f, app);
+    }
 
     // Status bar
     let _selected_filename = if let Some(ref path) = app.selected_image {
@@ -453,16 +3125,53 @@ fn ui(f: &mut Frame, app: &mut TuiBrowser) {
     let page = (app.scroll_offset / items_per_page) + 1;
     let total_pages = (app.items.len() + items_per_page - 1) / items_per_page;
 
-    let status_text = format!(
-        "q: Quit | Arrows: Nav | Enter: View | PgUp/PgDn: Page | {}/{} | Page {}/{}",
-        current_pos,
-        app.items.len(),
-        page,
-        total_pages
-    );
+    let command = app.prompt.as_ref().filter(|p| p.action == BatchAction::Command);
+    let search = app.prompt.as_ref().filter(|p| p.action == BatchAction::Search);
+    let status_text = if let Some(prompt) = command {
+        format!(":{}_", prompt.input)
+    } else if let Some(prompt) = search {
+        match &app.message {
+            Some(message) => format!("/{}_ | {}", prompt.input, message),
+            None => format!("/{}_", prompt.input),
+        }
+    } else if let Some(message) = &app.message {
+        message.clone()
+    } else if app.sidebar.is_some() {
+        "Up/Down: Folder | Enter: Open | Backspace: Up | Tab/Esc: Close".to_string()
+    } else {
+        format!(
+            "{}: Help | q: Quit | Arrows: Nav | Enter: View | i: Info | c/w: Compare{} | Space/v/a: Select{} | d/r/m/C/t/x: Batch | :: Filter/sort/group | /: Search | Tab: Folders | o: Open | y/Y: Copy | +/-: Size | PgUp/PgDn: Page | {}/{} | Page {}/{}",
+            app.keys.help.label(),
+            if app.compare_marks.is_empty() {
+                String::new()
+            } else {
+                format!(" ({}/2)", app.compare_marks.len())
+            },
+            match app.action_targets().len() {
+                n if n > 1 || !app.selection.is_empty() || app.visual_anchor.is_some() => {
+                    format!(" ({})", n)
+                }
+                _ => String::new(),
+            },
+            current_pos,
+            app.items.len(),
+            page,
+            total_pages
+        )
+    };
     let status_bar = Paragraph::new(Text::from(Span::raw(status_text)))
         .block(Block::default().borders(Borders::ALL));
     f.render_widget(status_bar, chunks[2]);
+
+    if let Some(prompt) = &app.prompt {
+        render_prompt(f, prompt);
+    }
+    if let Some(pane) = app.output_pane {
+        render_output_pane(f, app, pane);
+    }
+    if let Some(scroll) = app.help_scroll {
+        render_help(f, app, scroll);
+    }
 }
 
 fn render_fullscreen_image(f: &mut Frame, app: &mut TuiBrowser) {
@@ -488,37 +3197,37 @@ fn render_fullscreen_image(f: &mut Frame, app: &mut TuiBrowser) {
         
         // Use the entire screen for image, overlay status text
         let full_area = f.area();
+        let ai_tags = app
+            .ai_tags
+            .entry(image_path.clone())
+            .or_insert_with(|| cached_tags(image_path))
+            .clone();
+        let objects = ai_tags.as_ref().and_then(|t| t.objects.clone()).unwrap_or_default();
+        let caption = ai_tags.and_then(|t| t.caption);
         
         // Try to load and display the image
         if !app.image_cache.contains_key(image_path) {
             trace_log(&format!("Image not in cache, loading: {}", image_path));
             
-            match ImageReader::open(image_path) {
-                Ok(reader) => match reader.decode() {
-                    Ok(img) => {
-                        trace_log(&format!(
-                            "Image loaded successfully:\n\
-                            - dimensions: {}x{}\n\
-                            - color_type: {:?}",
-                            img.width(), img.height(), img.color()
-                        ));
-                        app.image_cache.insert(image_path.to_string(), img);
-                    }
-                    Err(e) => {
-                        trace_log(&format!("Failed to decode image: {}", e));
-                        let error_text = Paragraph::new("Error: Failed to decode image")
-                            .block(Block::default().borders(Borders::ALL));
-                        f.render_widget(error_text, full_area);
-                        trace_log("=== RENDER_FULLSCREEN_IMAGE END (decode error) ===\n");
-                        return;
-                    }
-                },
+            // Videos show their representative frame, HEIF photos are
+            // decoded through libheif
+            match open_image(image_path) {
+                Ok(img) => {
+                    trace_log(&format!(
+                        "Image loaded successfully:\n\
+                        - dimensions: {}x{}\n\
+                        - color_type: {:?}",
+                        img.width(), img.height(), img.color()
+                    ));
+                    app.image_cache.insert(image_path.to_string(), img);
+                }
                 Err(e) => {
-                    trace_log(&format!("Failed to open image: {}", e));
-                    let error_text = Paragraph::new("Error: Failed to open image")
+                    trace_log(&format!("Failed to decode image: {:#}", e));
+                    let error_text = Paragraph::new(format!("Error: {:#}", e))
+                        .wrap(Wrap { trim: true })
                         .block(Block::default().borders(Borders::ALL));
                     f.render_widget(error_text, full_area);
-                    trace_log("=== RENDER_FULLSCREEN_IMAGE END (open error) ===\n");
+                    trace_log("=== RENDER_FULLSCREEN_IMAGE END (decode error) ===\n");
                     return;
                 }
             }
@@ -548,25 +3257,42 @@ fn render_fullscreen_image(f: &mut Frame, app: &mut TuiBrowser) {
                     target_pixel_width, target_pixel_height
                 ));
                 
-                // Resize image to fit within 1920x1920 while maintaining aspect ratio
-                let max_dimension = 1920;
-                let (img_width, img_height) = (image_data.width(), image_data.height());
-                
-                let resized_image = {
-                    // Calculate the scaling factor to fit within max_dimension
-                    let scale = (max_dimension as f32) / img_width.max(img_height) as f32;
-                    let new_width = (img_width as f32 * scale) as u32;
-                    let new_height = (img_height as f32 * scale) as u32;
-                    
-                    trace_log(&format!(
-                        "Resizing image: {}x{} -> {}x{} (scale: {:.2})",
-                        img_width, img_height, new_width, new_height, scale
-                    ));
-                    
-                    // Use Lanczos3 filter for high-quality downscaling
-                    image_data.resize(new_width, new_height, FilterType::Lanczos3)
+                // Boxes are drawn before rotating so they turn with the image
+                let source = if app.show_boxes {
+                    draw_object_boxes(image_data, &objects)
+                } else {
+                    image_data.clone()
                 };
-                
+                let source = app.view.rotated(&source);
+
+                // Scale and crop for the fit mode, straight to screen pixels
+                let image_size = (source.width(), source.height());
+                let viewport = Viewport::new(
+                    app.view.fit_mode,
+                    app.view.zoom,
+                    image_size,
+                    (target_pixel_width, target_pixel_height),
+                    app.view.pan,
+                );
+                app.can_pan = viewport.is_cropped(image_size);
+
+                trace_log(&format!(
+                    "Fullscreen viewport ({}, {} quarter turns): {:?}",
+                    app.view.scale_label(),
+                    app.view.rotation,
+                    viewport
+                ));
+
+                let visible =
+                    source.crop_imm(viewport.x, viewport.y, viewport.width, viewport.height);
+                let out_size = (viewport.out_width, viewport.out_height);
+                let resized_image = if out_size == (viewport.width, viewport.height) {
+                    visible
+                } else {
+                    // Use Lanczos3 filter for high-quality scaling
+                    visible.resize_exact(out_size.0, out_size.1, FilterType::Lanczos3)
+                };
+
                 trace_log(&format!("Final image size: {}x{}", resized_image.width(), resized_image.height()));
                 
                 // Use new_resize_protocol which handles resizing automatically
@@ -575,43 +3301,19 @@ fn render_fullscreen_image(f: &mut Frame, app: &mut TuiBrowser) {
                 // Use Resize::Fit to maintain aspect ratio and Center it
                 let image_widget = StatefulImage::new().resize(Resize::Fit(None));
                 
-                // Calculate centering for the image_area
-                // Get the potential pixel size of the display area
-                let display_width_pixels = (full_area.width as u32) * (font_size.0 as u32);
-                let display_height_pixels = (display_height as u32) * (font_size.1 as u32);
-                
-                // Calculate how many pixels the image will actually take while fitting
-                let img_aspect = (resized_image.width() as f32) / (resized_image.height() as f32);
-                let area_aspect = (display_width_pixels as f32) / (display_height_pixels as f32);
-                
-                let (final_cell_width, final_cell_height) = if img_aspect > area_aspect {
-                    // Limited by width
-                    let w = full_area.width;
-                    let h = ((w as f32 * (font_size.0 as f32) / img_aspect) / (font_size.1 as f32)) as u16;
-                    (w, h.min(display_height))
-                } else {
-                    // Limited by height
-                    let h = display_height;
-                    let w = ((h as f32 * (font_size.1 as f32) * img_aspect) / (font_size.0 as f32)) as u16;
-                    (w.min(full_area.width), h)
-                };
-
-                let x_offset = full_area.width.saturating_sub(final_cell_width) / 2;
-                let y_offset = display_height.saturating_sub(final_cell_height) / 2;
-
-                let image_area = Rect {
-                    x: full_area.x + x_offset,
-                    y: full_area.y + y_offset,
-                    width: final_cell_width,
-                    height: final_cell_height,
-                };
+                // The image is already scaled, so center it at its own size
+                let image_area = centered_pixel_area(
+                    full_area,
+                    display_height,
+                    resized_image.width(),
+                    resized_image.height(),
+                    font_size,
+                );
                 
                 trace_log(&format!(
                     "Rendering centered image:\n\
-                    - final_cells: {}x{}\n\
-                    - offsets: {},{}\n\
                     - image_area: {:?}", 
-                    final_cell_width, final_cell_height, x_offset, y_offset, image_area
+                    image_area
                 ));
                 
                 f.render_stateful_widget(image_widget, image_area, &mut image_protocol);
@@ -630,16 +3332,57 @@ fn render_fullscreen_image(f: &mut Frame, app: &mut TuiBrowser) {
             height: 1,
         };
         
+        let pan_hint = if app.can_pan { " | arrows/hjkl: Scroll" } else { "" };
+        let rotation_hint = if app.view.is_turned() {
+            format!(" | r/R/m: {} | s: Save", app.view.turn_label())
+        } else {
+            " | r/R/m: Rotate".to_string()
+        };
         let status_text = format!(
-            "{} | q/ESC: Back | {}/{}",
+            "{}{} | {}: Help | q/ESC: Back | i: Info | o: Open | y/Y: Copy | f: {} \
+             | +/-: Zoom{}{} | {}/{}",
             filename,
+            match app.locations.get(image_path) {
+                Some(all) => format!(" ({})", locations_badge(all.len())),
+                None => String::new(),
+            },
+            app.keys.help.label(),
+            app.view.scale_label(),
+            rotation_hint,
+            pan_hint,
             current_pos,
             app.items.len()
         );
         
         trace_log(&format!("Rendering status bar: '{}' at {:?}", status_text, status_area));
         
-        let status_bar = Paragraph::new(Text::from(Span::raw(status_text)))
+        let mut status_spans = vec![Span::raw(status_text)];
+        if let Some(message) = &app.message {
+            status_spans.insert(
+                0,
+                Span::styled(format!("{} | ", message), Style::default().fg(Color::Yellow)),
+            );
+        }
+        if let Some(caption) = caption {
+            status_spans.push(Span::styled(
+                format!(" | {}", caption),
+                Style::default().add_modifier(Modifier::ITALIC),
+            ));
+        }
+        if !objects.is_empty() {
+            status_spans.push(Span::raw(format!(" | b: Boxes ({})", objects.len())));
+            if app.show_boxes {
+                for (i, object) in objects.iter().enumerate() {
+                    let (color, _) = BOX_COLORS[i % BOX_COLORS.len()];
+                    status_spans.push(Span::styled(
+                        format!(" {}", object.label),
+                        Style::default().fg(color).add_modifier(Modifier::BOLD),
+                    ));
+                }
+            }
+        }
+
+        let status_bar = Paragraph::new(Text::from(Line::from(status_spans)))
             .style(Style::default().bg(Color::Black).fg(Color::White));
         f.render_widget(status_bar, status_area);
     } else {
@@ -649,15 +3392,213 @@ fn render_fullscreen_image(f: &mut Frame, app: &mut TuiBrowser) {
     trace_log("=== RENDER_FULLSCREEN_IMAGE END ===\n");
 }
 
-fn render_thumbnail_grid(f: &mut Frame, app: &mut TuiBrowser, area: Rect) {
-    let min_cell_width = 12;
-    let min_cell_height = 8;
+/// Tags to add and to remove from a tag editor input: comma-separated,
+/// with a leading `-` marking a tag to remove (and an optional `+` one to add)
+fn parse_tag_edits(input: &str) -> (Vec<String>, Vec<String>) {
+    let mut added = Vec::new();
+    let mut removed = Vec::new();
+    for item in input.split(',').map(str::trim) {
+        let (list, tag) = match item.strip_prefix('-') {
+            Some(tag) => (&mut removed, tag),
+            None => (&mut added, item.strip_prefix('+').unwrap_or(item)),
+        };
+        let tag = tag.trim().to_lowercase();
+        if !tag.is_empty() && !list.contains(&tag) {
+            list.push(tag);
+        }
+    }
+    (added, removed)
+}
 
-    let max_cols = std::cmp::max(1, area.width / min_cell_width);
-    let max_rows = std::cmp::max(1, area.height / min_cell_height);
+/// AI tags of `path` from the tag cache, if it was tagged
+fn cached_tags(path: &str) -> Option<AITags> {
+    AITaggingConfig::default()
+        .cache_dir
+        .and_then(|dir| load_cached_tags(&dir, path).ok())
+}
+
+/// Copy of `image` with each object's box outlined in its legend color
+fn draw_object_boxes(image: &image::DynamicImage, objects: &[DetectedObject]) -> image::DynamicImage {
+    let mut canvas = image.to_rgba8();
+    let (width, height) = canvas.dimensions();
+    if width == 0 || height == 0 {
+        return image.clone();
+    }
+    let thickness = (width.max(height) / 400).max(2);
+
+    for (i, object) in objects.iter().enumerate() {
+        let [r, g, b] = BOX_COLORS[i % BOX_COLORS.len()].1;
+        let color = image::Rgba([r, g, b, 255]);
+
+        let x0 = ((object.bbox[0] * width as f32) as u32).min(width - 1);
+        let y0 = ((object.bbox[1] * height as f32) as u32).min(height - 1);
+        let x1 = (((object.bbox[0] + object.bbox[2]) * width as f32) as u32).clamp(x0 + 1, width);
+        let y1 = (((object.bbox[1] + object.bbox[3]) * height as f32) as u32).clamp(y0 + 1, height);
+
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let on_edge = x < x0 + thickness
+                    || x + thickness >= x1
+                    || y < y0 + thickness
+                    || y + thickness >= y1;
+                if on_edge {
+                    canvas.put_pixel(x, y, color);
+                }
+            }
+        }
+    }
+
+    image::DynamicImage::ImageRgba8(canvas)
+}
+
+/// Compute the cell area an already scaled image of the given pixel size
+/// occupies when centered in `full_area` (minus the status line)
+fn centered_pixel_area(
+    full_area: Rect,
+    display_height: u16,
+    img_width: u32,
+    img_height: u32,
+    font_size: (u16, u16),
+) -> Rect {
+    let cells = |pixels: u32, cell: u16| pixels.div_ceil(cell.max(1) as u32).min(u16::MAX as u32);
+    let width = cells(img_width, font_size.0).min(full_area.width as u32) as u16;
+    let height = cells(img_height, font_size.1).min(display_height as u32) as u16;
+
+    Rect {
+        x: full_area.x + full_area.width.saturating_sub(width) / 2,
+        y: full_area.y + display_height.saturating_sub(height) / 2,
+        width,
+        height,
+    }
+}
+
+/// Compute the cell area an image of the given pixel size occupies when fitted
+/// into `full_area` (minus the status line) and centered
+fn centered_image_area(
+    full_area: Rect,
+    display_height: u16,
+    img_width: u32,
+    img_height: u32,
+    font_size: (u16, u16),
+) -> Rect {
+    // Get the potential pixel size of the display area
+    let display_width_pixels = (full_area.width as u32) * (font_size.0 as u32);
+    let display_height_pixels = (display_height as u32) * (font_size.1 as u32);
+
+    // Calculate how many pixels the image will actually take while fitting
+    let img_aspect = (img_width as f32) / (img_height as f32);
+    let area_aspect = (display_width_pixels as f32) / (display_height_pixels as f32);
+
+    let (final_cell_width, final_cell_height) = if img_aspect > area_aspect {
+        // Limited by width
+        let w = full_area.width;
+        let h = ((w as f32 * (font_size.0 as f32) / img_aspect) / (font_size.1 as f32)) as u16;
+        (w, h.min(display_height))
+    } else {
+        // Limited by height
+        let h = display_height;
+        let w = ((h as f32 * (font_size.1 as f32) * img_aspect) / (font_size.0 as f32)) as u16;
+        (w.min(full_area.width), h)
+    };
+
+    let x_offset = full_area.width.saturating_sub(final_cell_width) / 2;
+    let y_offset = display_height.saturating_sub(final_cell_height) / 2;
+
+    Rect {
+        x: full_area.x + x_offset,
+        y: full_area.y + y_offset,
+        width: final_cell_width,
+        height: final_cell_height,
+    }
+}
+
+/// Whether the file at `path` is gone, as opposed to merely unreadable
+fn is_missing(path: &str) -> bool {
+    matches!(fs::metadata(path), Err(e) if e.kind() == io::ErrorKind::NotFound)
+}
+
+/// Load an image into the cache if it isn't there yet
+fn ensure_image_cached(app: &mut TuiBrowser, image_path: &str) -> Result<(), String> {
+    if app.image_cache.contains_key(image_path) {
+        return Ok(());
+    }
+
+    let img =
+        open_image(image_path).map_err(|e| format!("Failed to decode {}: {:#}", image_path, e))?;
+    app.image_cache.insert(image_path.to_string(), img);
+    Ok(())
+}
+
+fn render_wipe_compare(f: &mut Frame, app: &mut TuiBrowser) {
+    let full_area = f.area();
+    let display_height = full_area.height.saturating_sub(1);
+
+    let (before_path, after_path) = match app.compare_marks.as_slice() {
+        [before, after] => (before.clone(), after.clone()),
+        _ => {
+            app.wipe_mode = false;
+            return;
+        }
+    };
+
+    for path in [&before_path, &after_path] {
+        if let Err(e) = ensure_image_cached(app, path) {
+            trace_log(&format!("Wipe compare: {}", e));
+            let error_text = Paragraph::new(format!("Error: {}", e))
+                .block(Block::default().borders(Borders::ALL));
+            f.render_widget(error_text, full_area);
+            return;
+        }
+    }
+
+    if let (Some(before), Some(after), Some(picker)) = (
+        app.image_cache.get(&before_path),
+        app.image_cache.get(&after_path),
+        app.picker.as_ref(),
+    ) {
+        let composite = crate::compare::wipe_composite(before, after, app.wipe_split);
+        let image_area = centered_image_area(
+            full_area,
+            display_height,
+            composite.width(),
+            composite.height(),
+            picker.font_size(),
+        );
+
+        let mut image_protocol = picker.new_resize_protocol(composite);
+        let image_widget = StatefulImage::new().resize(Resize::Fit(None));
+        f.render_stateful_widget(image_widget, image_area, &mut image_protocol);
+    }
+
+    let file_name = |path: &str| {
+        Path::new(path)
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| path.to_string())
+    };
+
+    let status_area = Rect {
+        x: 0,
+        y: full_area.height.saturating_sub(1),
+        width: full_area.width,
+        height: 1,
+    };
+    let status_text = format!(
+        "Before: {} | After: {} | Split {:.0}% | Left/Right: Move split | w/q/ESC: Back",
+        file_name(&before_path),
+        file_name(&after_path),
+        app.wipe_split * 100.0
+    );
+    let status_bar = Paragraph::new(Text::from(Span::raw(status_text)))
+        .style(Style::default().bg(Color::Black).fg(Color::White));
+    f.render_widget(status_bar, status_area);
+}
 
-    app.grid_cols = std::cmp::min(max_cols, 5);
-    app.grid_rows = std::cmp::min(max_rows, 3);
+fn render_thumbnail_grid(f: &mut Frame, app: &mut TuiBrowser, area: Rect) {
+    (app.grid_cols, app.grid_rows) = grid_size(area, app.grid_columns);
+    app.grid_area = area;
+    // After a resize, bring the cursor's page back into view
+    app.ensure_selection_visible();
 
     let cell_width = area.width / app.grid_cols;
     let cell_height = area.height / app.grid_rows;
@@ -718,29 +3659,123 @@ fn render_thumbnail_grid(f: &mut Frame, app: &mut TuiBrowser, area: Rect) {
                 f.render_widget(selection_block, cell_area);
             }
         }
+        // Mark images in the batch selection
+        if app.is_marked(start_idx + i) && cell_area.width > 2 && cell_area.height > 1 {
+            let mut mark_block = Block::default().title_top(
+                Line::from(Span::styled(
+                    " ✓ ",
+                    Style::default().fg(Color::Green).add_modifier(Modifier::BOLD),
+                ))
+                .right_aligned(),
+            );
+            if app.state.selected() != Some(start_idx + i) {
+                mark_block = mark_block
+                    .borders(Borders::ALL)
+                    .border_style(Style::default().fg(Color::Green));
+            }
+            f.render_widget(mark_block, cell_area);
+        }
+        // Label images marked for before/after comparison
+        if let Some(mark) = app.compare_marks.iter().position(|p| p == item_path) {
+            if cell_area.width > 2 && cell_area.height > 1 {
+                let label = if mark == 0 { " Before " } else { " After " };
+                let mark_block = Block::default().title(Span::styled(
+                    label,
+                    Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD),
+                ));
+                f.render_widget(mark_block, cell_area);
+            }
+        }
+        // Count the paths of images reached through hard links
+        if let Some(all) = app.locations.get(item_path) {
+            if cell_area.width > 2 && cell_area.height > 1 {
+                let badge = Block::default().title_bottom(
+                    Line::from(Span::styled(
+                        format!(" ×{} ", all.len()),
+                        Style::default().fg(Color::Magenta).add_modifier(Modifier::BOLD),
+                    ))
+                    .right_aligned(),
+                );
+                f.render_widget(badge, cell_area);
+            }
+        }
+        // Mark videos, whose thumbnail is one of their frames
+        if video_thumbs::is_video(item_path) && cell_area.width > 4 && cell_area.height > 1 {
+            let badge = Block::default().title_top(Line::from(Span::styled(
+                format!(" {} ", video_thumbs::BADGE),
+                Style::default().fg(Color::Black).bg(Color::White).add_modifier(Modifier::BOLD),
+            )));
+            f.render_widget(badge, cell_area);
+        }
+        // Rating stars on the top edge, flags and tags on the bottom one
+        if app.badges && cell_area.width > 2 && cell_area.height > 1 {
+            let tags =
+                app.ai_tags.entry(item_path.clone()).or_insert_with(|| cached_tags(item_path));
+            if let Some(badges) = tags.as_ref().map(|tags| Badges::new(tags, app.badge_tags)) {
+                let mut block = Block::default();
+                if let Some(rating) = badges.rating {
+                    block = block.title_top(
+                        Line::from(Span::styled(
+                            format!(" {} ", Badges::stars(rating)),
+                            Style::default().fg(Color::Yellow),
+                        ))
+                        .centered(),
+                    );
+                }
+                // Room is left for the hard link count on the right
+                let width = (cell_area.width as usize).saturating_sub(8);
+                block = block.title_bottom(badges.bottom_line(width));
+                f.render_widget(block, cell_area);
+            }
+        }
         if cell_area.height > 2 {
             cell_area.y += 1;
             cell_area.height -= 1;
         }
 
-        // Try to load the image if not already cached
-        if !app.image_cache.contains_key(item_path) {
-            match ImageReader::open(item_path) {
-                Ok(reader) => match reader.decode() {
-                    Ok(img) => {
-                        app.image_cache.insert(item_path.to_string(), img);
-                    }
-                    Err(_) => {
-                        continue;
-                    }
-                },
-                Err(_) => {
-                    continue;
-                }
-            }
+        // Caption on the last row above the cell's bottom edge, taken from the
+        // thumbnail when the cell has room for both
+        let caption_width = cell_area.width.saturating_sub(4) as usize;
+        let caption = app
+            .grid_labels
+            .caption(start_idx + i, item_path, caption_width)
+            .filter(|_| cell_area.height > 3 && caption_width > 0);
+        if let Some(caption) = &caption {
+            cell_area.height -= 1;
+            let caption_area = Rect {
+                x: cell_area.x + 2,
+                y: cell_area.y + cell_area.height - 1,
+                width: caption_width as u16,
+                height: 1,
+            };
+            let text = Paragraph::new(caption.clone())
+                .style(Style::default().fg(Color::DarkGray))
+                .alignment(Alignment::Center);
+            f.render_widget(text, caption_area);
         }
 
-        if let Some(image_data) = app.image_cache.get(item_path) {
+        // Thumbnails are decoded in the background; show a placeholder until ready
+        if !app.thumbnails.contains_key(item_path) {
+            let (label, color) = if app.loader.has_failed(item_path) {
+                ("✗ unreadable".to_string(), Color::Red)
+            } else {
+                app.loader.request(item_path);
+                let spinner = SPINNER[app.spinner_frame % SPINNER.len()];
+                (format!("{} loading", spinner), Color::DarkGray)
+            };
+            let placeholder = Paragraph::new(label)
+                .style(Style::default().fg(color))
+                .alignment(Alignment::Center);
+            let placeholder_area = Rect {
+                y: cell_area.y + cell_area.height / 2,
+                height: 1.min(cell_area.height),
+                ..cell_area
+            };
+            f.render_widget(placeholder, placeholder_area);
+            continue;
+        }
+
+        if let Some(image_data) = app.thumbnails.get(item_path) {
             if let Some(ref picker) = app.picker {
                 let mut image_protocol = picker.new_resize_protocol(image_data.clone());
 
@@ -781,3 +3816,227 @@ fn render_thumbnail_grid(f: &mut Frame, app: &mut TuiBrowser, area: Rect) {
     f.render_widget(grid_block, area);
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn test_parse_tag_edits() {
+        let (added, removed) = parse_tag_edits("Beach, -blurry, +sunset, -, beach");
+        assert_eq!(added, ["beach", "sunset"]);
+        assert_eq!(removed, ["blurry"]);
+        let (added, removed) = parse_tag_edits(" , ");
+        assert!(added.is_empty() && removed.is_empty());
+    }
+
+    #[test]
+    fn test_grid_size() {
+        let area = Rect::new(0, 3, 200, 45);
+        assert_eq!(grid_size(area, DEFAULT_GRID_COLUMNS), (5, 3));
+        assert_eq!(grid_size(area, 1), (1, 1));
+        assert_eq!(grid_size(area, 8), (8, 5));
+        // Narrow and short screens fit what they can
+        assert_eq!(grid_size(Rect::new(0, 0, 60, 20), 8), (5, 2));
+    }
+
+    #[test]
+    fn test_grid_labels() {
+        let path = "photos/IMG_20240512_0042.jpg";
+        assert_eq!(GridLabels::Names.caption(0, path, 30).unwrap(), "IMG_20240512_0042.jpg");
+        assert_eq!(GridLabels::Numbered.caption(6, path, 30).unwrap(), "7. IMG_20240512_0042.jpg");
+        assert_eq!(GridLabels::Names.caption(0, path, 11).unwrap(), "IMG_2…2.jpg");
+        assert_eq!(GridLabels::Off.caption(0, path, 30), None);
+        assert_eq!(truncate_middle("日本の写真.png", 5), "日本…ng");
+    }
+
+    #[test]
+    fn test_badges() {
+        let tags = AITags {
+            tags: ["beach", "rating:4", "sea", "nsfw", "sunset", "people"]
+                .map(String::from)
+                .to_vec(),
+            content_rating: Some("nsfw".to_string()),
+            confidence: 0.9,
+            model: "test".to_string(),
+            timestamp: 0,
+            cache_hit: false,
+            objects: None,
+            caption: None,
+            model_version: None,
+            tag_confidence: HashMap::new(),
+            manual_tags: Vec::new(),
+            removed_tags: Vec::new(),
+        };
+        let badges = Badges::new(&tags, 3);
+        assert_eq!(badges.rating, Some(4));
+        assert!(badges.nsfw);
+        assert_eq!(badges.tags, ["beach", "sea", "sunset"]);
+        assert_eq!(Badges::stars(4), "★★★★☆");
+        let line = badges.bottom_line(16);
+        let text: String = line.spans.iter().map(|span| span.content.as_ref()).collect();
+        assert_eq!(text, " NSFW  beach ·… ");
+        assert_eq!(Badges::new(&tags, 0).tags, Vec::<String>::new());
+
+        let flagged = AITags { tags: ["pick", "sea"].map(String::from).to_vec(), ..tags };
+        let badges = Badges::new(&flagged, 3);
+        assert_eq!((badges.flag, badges.rating), (Some(Flag::Pick), None));
+        let text: String =
+            badges.bottom_line(20).spans.iter().map(|span| span.content.as_ref()).collect();
+        assert_eq!(text, " ✓ PICK  NSFW  sea ");
+    }
+
+    #[test]
+    fn test_viewport_modes() {
+        let image = (4000, 2000);
+        let display = (1000, 1000);
+        let center = (0.5, 0.5);
+
+        let fit = Viewport::new(FitMode::Fit, 1.0, image, display, center);
+        assert_eq!((fit.width, fit.height), image);
+        assert_eq!((fit.out_width, fit.out_height), (1000, 500));
+        assert!(!fit.is_cropped(image));
+
+        // Fill covers the display and crops the sides, centered
+        let fill = Viewport::new(FitMode::Fill, 1.0, image, display, center);
+        assert_eq!((fill.x, fill.y, fill.width, fill.height), (1000, 0, 2000, 2000));
+        assert_eq!((fill.out_width, fill.out_height), display);
+        assert!(fill.is_cropped(image));
+
+        let actual = Viewport::new(FitMode::ActualSize, 1.0, image, display, (0.0, 1.0));
+        assert_eq!((actual.x, actual.y, actual.width, actual.height), (0, 1000, 1000, 1000));
+        assert_eq!((actual.out_width, actual.out_height), display);
+
+        let width = Viewport::new(FitMode::FitWidth, 1.0, image, display, center);
+        assert_eq!((width.out_width, width.out_height), (1000, 500));
+        let height = Viewport::new(FitMode::FitHeight, 1.0, image, display, (1.0, 0.5));
+        assert_eq!((height.x, height.width, height.out_height), (2000, 2000, 1000));
+
+        // 4x the fitted scale is 1:1 here: a display-sized crop, centered
+        let zoomed = Viewport::new(FitMode::Fit, 4.0, image, display, center);
+        assert_eq!((zoomed.x, zoomed.y, zoomed.width, zoomed.height), (1500, 500, 1000, 1000));
+        assert_eq!((zoomed.out_width, zoomed.out_height), display);
+        assert!(zoomed.is_cropped(image));
+        let out = Viewport::new(FitMode::Fit, 0.5, image, display, center);
+        assert_eq!((out.out_width, out.out_height), (500, 250));
+    }
+
+    #[test]
+    fn test_vim_arrow() {
+        assert_eq!(vim_arrow(KeyCode::Char('h')), KeyCode::Left);
+        assert_eq!(vim_arrow(KeyCode::Char('k')), KeyCode::Up);
+        assert_eq!(vim_arrow(KeyCode::Char('q')), KeyCode::Char('q'));
+    }
+
+    #[test]
+    fn test_drop_missing() {
+        let dir = TestDir::new("tui-missing");
+        let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+        let (a, gone, b) = (path("a.png"), path("gone.png"), path("b.png"));
+        fs::write(&a, b"").unwrap();
+        fs::write(&b, b"").unwrap();
+
+        // Before the first draw the grid has no size yet
+        let mut app = TuiBrowser::new(vec![a.clone(), gone.clone(), b.clone()], String::new());
+        app.state.select(Some(2));
+        app.update_selected_image();
+        app.selection = [gone.clone(), b.clone()].into_iter().collect();
+        app.compare_marks = vec![a.clone(), gone.clone()];
+        app.wipe_mode = true;
+
+        assert!(app.drop_missing(&app.items.clone()));
+        assert_eq!(app.items, [a.clone(), b.clone()]);
+        assert_eq!(app.state.selected(), Some(1));
+        assert_eq!(app.selected_image.as_deref(), Some(b.as_str()));
+        assert_eq!(app.selection.len(), 1);
+        assert!(!app.wipe_mode);
+        assert!(app.message.as_deref().unwrap().starts_with("gone.png no longer exists"));
+        assert!(!app.drop_missing(&app.items.clone()));
+    }
+
+    #[test]
+    fn test_mouse() {
+        let items: Vec<String> = (0..20).map(|i| format!("{}.png", i)).collect();
+        let mut app = TuiBrowser::new(items, String::new());
+        (app.grid_cols, app.grid_rows) = (5, 3);
+        app.grid_area = Rect::new(0, 3, 100, 30);
+        let mouse = |kind, column, row| MouseEvent {
+            kind,
+            column,
+            row,
+            modifiers: KeyModifiers::NONE,
+        };
+        // Cells are 20x10: this is the third image of the second row
+        let click = mouse(MouseEventKind::Down(MouseButton::Left), 45, 15);
+        assert!(handle_mouse(&mut app, click));
+        assert_eq!(app.state.selected(), Some(7));
+        assert!(!app.fullscreen_mode);
+        assert!(handle_mouse(&mut app, click));
+        assert!(app.fullscreen_mode);
+
+        let wheel = mouse(MouseEventKind::ScrollDown, 0, 0);
+        assert!(!handle_mouse(&mut app, wheel));
+        app.fullscreen_mode = false;
+        assert!(handle_mouse(&mut app, wheel));
+        assert_eq!((app.state.selected(), app.scroll_offset), (Some(19), 15));
+        // The last page has a single row
+        assert!(!handle_mouse(&mut app, click));
+        assert_eq!(app.state.selected(), Some(19));
+    }
+
+    #[test]
+    fn test_run_command() {
+        let dir = TestDir::new("tui-command");
+        let image = |name: &str, width: u32| {
+            let path = dir.join(name);
+            image::RgbImage::new(width, 10).save(&path).unwrap();
+            path.to_string_lossy().to_string()
+        };
+        let (a, b, c) = (image("a.png", 50), image("b.png", 200), image("c.png", 300));
+
+        let mut app = TuiBrowser::new(vec![a.clone(), b.clone(), c.clone()], String::new());
+        app.state.select(Some(1));
+        app.update_selected_image();
+        app.selection = [a.clone(), b.clone()].into_iter().collect();
+
+        assert_eq!(app.run_command("filter width>100").unwrap(), "Showing 2 of 3 images");
+        assert_eq!(app.items, [b.clone(), c.clone()]);
+        // The cursor stays on the same image; hidden images are deselected
+        assert_eq!(app.selected_image.as_deref(), Some(b.as_str()));
+        assert_eq!(app.selection.len(), 1);
+
+        app.run_command("sort name desc").unwrap();
+        assert_eq!(app.items, [c.clone(), b.clone()]);
+        assert_eq!(app.state.selected(), Some(1));
+
+        // A filter matching nothing leaves the view as it was
+        assert!(app.run_command("filter width>1000").is_err());
+        assert_eq!(app.items, [c.clone(), b.clone()]);
+        assert!(app.run_command("zoom").is_err());
+
+        app.run_command("filter").unwrap();
+        assert_eq!(app.items, [c, b, a]);
+    }
+
+    #[test]
+    fn test_receive_changes() {
+        let dir = TestDir::new("tui-watch");
+        let root = crate::paths::canonicalize_path(&dir.to_string_lossy(), true);
+        let path = |name: &str| Path::new(&root).join(name).to_string_lossy().to_string();
+        let (old, new) = (path("old.png"), path("new.png"));
+        image::RgbImage::new(10, 10).save(&old).unwrap();
+
+        let mut app = TuiBrowser::new(vec![old.clone()], String::new());
+        app.watcher = Some(DirWatcher::new(std::slice::from_ref(&root), false).unwrap());
+        image::RgbImage::new(10, 10).save(&new).unwrap();
+        fs::remove_file(&old).unwrap();
+
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+        while !app.receive_changes() && std::time::Instant::now() < deadline {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        }
+        assert_eq!(app.items, std::slice::from_ref(&new));
+        assert_eq!(app.all_items, [new]);
+        assert_eq!(app.message.as_deref(), Some("Directory changed: 1 added, 1 removed"));
+    }
+}
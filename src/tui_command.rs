@@ -0,0 +1,347 @@
+// Commands typed after `:` in the TUI, to narrow, reorder or regroup the
+// images being browsed without restarting lsix:
+//
+//   :filter width>1000 tag:cat    keep images matching every term
+//   :filter                       show every image again
+//   :sort mtime desc, name        the keys --sort takes; empty for scan order
+//   :group color                  the strategies --group-by takes
+//
+// A `/` search narrows the result further, as it is typed, to the images
+// whose file name or one of whose tags contains the text.
+//
+// Filters only read what is cheap to read again: image headers, file
+// metadata and the tag cache, remembered per image for the session.
+
+use crate::ai_tagging::load_cached_tags;
+use crate::filter::{
+    format_file_size, orientation_name, orientation_of, parse_file_size, parse_orientation,
+    ImageOrientation,
+};
+use crate::grouping::{parse_group_by, GroupBy};
+use crate::sorting::{parse_sort_order, SortSpec};
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// A parsed `:` command
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Keep images matching every term; no terms shows all of them
+    Filter(Vec<FilterTerm>),
+    /// Sort by these keys; no keys restores the scan order
+    Sort(Vec<SortSpec>),
+    /// Regroup the images
+    Group(GroupBy),
+}
+
+/// Parse a command line such as `filter width>1000 tag:cat` (without the
+/// leading `:`). Command names may be shortened to their first letter.
+pub fn parse_command(input: &str) -> Result<Command> {
+    let input = input.trim().trim_start_matches(':').trim_start();
+    let (name, rest) = input.split_once(char::is_whitespace).unwrap_or((input, ""));
+    let rest = rest.trim();
+    match name.to_lowercase().as_str() {
+        "filter" | "f" => Ok(Command::Filter(
+            rest.split_whitespace().map(parse_filter_term).collect::<Result<_>>()?,
+        )),
+        "sort" | "s" => Ok(Command::Sort(parse_sort_order(rest)?)),
+        "group" | "g" if rest.is_empty() => Ok(Command::Group(GroupBy::None)),
+        "group" | "g" => Ok(Command::Group(parse_group_by(rest)?)),
+        "" => anyhow::bail!("Empty command. Use: filter, sort, group"),
+        _ => anyhow::bail!("Unknown command: {}. Use: filter, sort, group", name),
+    }
+}
+
+/// How a number is compared with a term's value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    GreaterOrEqual,
+    Greater,
+}
+
+impl Comparison {
+    fn holds<T: PartialOrd>(self, actual: T, expected: T) -> bool {
+        match self {
+            Comparison::Less => actual < expected,
+            Comparison::LessOrEqual => actual <= expected,
+            Comparison::Equal => actual == expected,
+            Comparison::GreaterOrEqual => actual >= expected,
+            Comparison::Greater => actual > expected,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Equal => "=",
+            Comparison::GreaterOrEqual => ">=",
+            Comparison::Greater => ">",
+        }
+    }
+}
+
+/// What a filter term checks
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    Width(Comparison, u32),
+    Height(Comparison, u32),
+    /// File size in bytes
+    Size(Comparison, u64),
+    Orientation(ImageOrientation),
+    /// Has this cached tag
+    Tag(String),
+    /// File name contains this text, ignoring case
+    Name(String),
+}
+
+/// One `:filter` term; a leading `-` negates it (`-tag:blurry`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilterTerm {
+    pub condition: Condition,
+    pub negated: bool,
+}
+
+/// Parse a term such as `width>1000`, `size<=2M`, `tag:cat`, `name:IMG_`,
+/// `orientation:portrait` or just `portrait`
+pub fn parse_filter_term(term: &str) -> Result<FilterTerm> {
+    let (negated, body) = match term.strip_prefix('-') {
+        Some(body) => (true, body),
+        None => (false, term),
+    };
+    let Some(split) = body.find(['<', '>', '=', ':']) else {
+        let orientation = parse_orientation(body)
+            .with_context(|| format!("Invalid filter term: {}", term))?;
+        return Ok(FilterTerm { condition: Condition::Orientation(orientation), negated });
+    };
+
+    let (key, rest) = body.split_at(split);
+    let (comparison, value) = match rest.as_bytes() {
+        [b'<', b'=', ..] => (Comparison::LessOrEqual, &rest[2..]),
+        [b'>', b'=', ..] => (Comparison::GreaterOrEqual, &rest[2..]),
+        [b'<', ..] => (Comparison::Less, &rest[1..]),
+        [b'>', ..] => (Comparison::Greater, &rest[1..]),
+        _ => (Comparison::Equal, &rest[1..]),
+    };
+    if value.is_empty() {
+        anyhow::bail!("Missing value in filter term: {}", term);
+    }
+
+    let is_match = comparison == Comparison::Equal;
+    let number = || value.parse::<u32>().with_context(|| format!("Invalid number: {}", value));
+    let condition = match key.to_lowercase().as_str() {
+        "width" | "w" => Condition::Width(comparison, number()?),
+        "height" | "h" => Condition::Height(comparison, number()?),
+        "size" => Condition::Size(comparison, parse_file_size(value)?),
+        "orientation" | "orient" if is_match => Condition::Orientation(parse_orientation(value)?),
+        "tag" if is_match => Condition::Tag(value.trim().to_lowercase()),
+        "name" if is_match => Condition::Name(value.to_lowercase()),
+        _ => anyhow::bail!(
+            "Invalid filter term: {}. Use width/height/size with <, <=, =, >=, >, \
+             or tag:, name:, orientation:",
+            term
+        ),
+    };
+    Ok(FilterTerm { condition, negated })
+}
+
+/// What filters look at for one image
+#[derive(Debug, Clone, Default)]
+pub struct ItemInfo {
+    /// Pixel size, if the image header could be read
+    pub dimensions: Option<(u32, u32)>,
+    pub file_size: Option<u64>,
+    /// Cached tags, lowercase
+    pub tags: Vec<String>,
+    /// Lowercase file name
+    pub name: String,
+}
+
+impl ItemInfo {
+    /// Read the header, file size and cached tags of `path`
+    pub fn load(path: &str, tag_cache: Option<&Path>) -> ItemInfo {
+        let file = path.trim_end_matches("[0]");
+        ItemInfo {
+            dimensions: image::image_dimensions(file).ok(),
+            file_size: std::fs::metadata(file).ok().map(|m| m.len()),
+            tags: tag_cache
+                .and_then(|dir| load_cached_tags(dir, file).ok())
+                .map(|t| t.tags)
+                .unwrap_or_default(),
+            name: Path::new(file)
+                .file_name()
+                .map(|n| n.to_string_lossy().to_lowercase())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl FilterTerm {
+    /// Whether an image described by `info` passes this term. Images whose
+    /// size couldn't be read fail every size comparison.
+    pub fn matches(&self, info: &ItemInfo) -> bool {
+        let dimensions = info.dimensions;
+        let holds = match &self.condition {
+            Condition::Width(cmp, value) => dimensions.is_some_and(|(w, _)| cmp.holds(w, *value)),
+            Condition::Height(cmp, value) => {
+                dimensions.is_some_and(|(_, h)| cmp.holds(h, *value))
+            }
+            Condition::Size(cmp, value) => info.file_size.is_some_and(|s| cmp.holds(s, *value)),
+            Condition::Orientation(orientation) => {
+                dimensions.is_some_and(|(w, h)| orientation_of(w, h) == *orientation)
+            }
+            Condition::Tag(tag) => info.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)),
+            Condition::Name(text) => info.name.contains(text.as_str()),
+        };
+        holds != self.negated
+    }
+
+    /// The term as `parse_filter_term` reads it, e.g. `-tag:blurry`
+    pub fn label(&self) -> String {
+        let body = match &self.condition {
+            Condition::Width(cmp, value) => format!("width{}{}", cmp.symbol(), value),
+            Condition::Height(cmp, value) => format!("height{}{}", cmp.symbol(), value),
+            Condition::Size(cmp, value) => {
+                format!("size{}{}", cmp.symbol(), format_file_size(*value))
+            }
+            Condition::Orientation(orientation) => orientation_name(*orientation).to_string(),
+            Condition::Tag(tag) => format!("tag:{}", tag),
+            Condition::Name(text) => format!("name:{}", text),
+        };
+        if self.negated {
+            format!("-{}", body)
+        } else {
+            body
+        }
+    }
+}
+
+/// `paths` that pass every term, in order. Image details are read once and
+/// kept in `infos`; entries for files that changed are not refreshed.
+pub fn filter_paths(
+    paths: &[String],
+    terms: &[FilterTerm],
+    tag_cache: Option<&Path>,
+    infos: &mut HashMap<String, ItemInfo>,
+) -> Vec<String> {
+    if terms.is_empty() {
+        return paths.to_vec();
+    }
+    load_infos(paths, tag_cache, infos);
+
+    paths
+        .iter()
+        .filter(|path| {
+            let info = &infos[*path];
+            terms.iter().all(|term| term.matches(info))
+        })
+        .cloned()
+        .collect()
+}
+
+/// Read the details of `paths` that aren't in `infos` yet
+fn load_infos(paths: &[String], tag_cache: Option<&Path>, infos: &mut HashMap<String, ItemInfo>) {
+    let missing: Vec<(String, ItemInfo)> = paths
+        .par_iter()
+        .filter(|path| !infos.contains_key(*path))
+        .map(|path| (path.clone(), ItemInfo::load(path, tag_cache)))
+        .collect();
+    infos.extend(missing);
+}
+
+/// Whether `query` (lowercase) is part of the file name or of a tag
+pub fn search_matches(info: &ItemInfo, query: &str) -> bool {
+    info.name.contains(query) || info.tags.iter().any(|tag| tag.contains(query))
+}
+
+/// `paths` matching a `/` search for `query`, in order; an empty query
+/// matches everything
+pub fn search_paths(
+    paths: &[String],
+    query: &str,
+    tag_cache: Option<&Path>,
+    infos: &mut HashMap<String, ItemInfo>,
+) -> Vec<String> {
+    let query = query.trim().to_lowercase();
+    if query.is_empty() {
+        return paths.to_vec();
+    }
+    load_infos(paths, tag_cache, infos);
+    paths.iter().filter(|path| search_matches(&infos[*path], &query)).cloned().collect()
+}
+
+/// Scores for `:sort quality`, cached or computed. Unlike
+/// `quality::score_all` nothing is printed, as the TUI owns the screen;
+/// images that can't be scored sort last.
+pub fn quality_scores(paths: &[String]) -> HashMap<String, f32> {
+    paths
+        .par_iter()
+        .filter_map(|path| Some((path.clone(), crate::quality::score_file(path).ok()?.score)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sorting::SortKey;
+
+    #[test]
+    fn test_parse_command() {
+        let Command::Filter(terms) = parse_command(":filter width>1000 -tag:Cat").unwrap() else {
+            panic!("not a filter");
+        };
+        assert_eq!(terms[0].condition, Condition::Width(Comparison::Greater, 1000));
+        assert_eq!(terms[1].condition, Condition::Tag("cat".to_string()));
+        assert!(terms[1].negated);
+        let labels: Vec<String> = terms.iter().map(FilterTerm::label).collect();
+        assert_eq!(labels, ["width>1000", "-tag:cat"]);
+        assert_eq!(parse_command("f").unwrap(), Command::Filter(Vec::new()));
+
+        let Command::Sort(order) = parse_command("sort mtime desc, name").unwrap() else {
+            panic!("not a sort");
+        };
+        assert_eq!(order.len(), 2);
+        assert_eq!(order[0].key, SortKey::Mtime);
+        assert_eq!(parse_command("g color").unwrap(), Command::Group(GroupBy::Color));
+        assert_eq!(parse_command("group").unwrap(), Command::Group(GroupBy::None));
+
+        assert!(parse_command("zoom 2").is_err());
+        assert!(parse_command("filter width>wide").is_err());
+        assert!(parse_command("filter tag>cat").is_err());
+        assert!(parse_command("filter size>").is_err());
+    }
+
+    #[test]
+    fn test_filter_terms() {
+        let info = ItemInfo {
+            dimensions: Some((1920, 1080)),
+            file_size: Some(3 * 1024 * 1024),
+            tags: vec!["cat".to_string(), "sofa".to_string()],
+            name: "img_0042.jpg".to_string(),
+        };
+        let matches = |term: &str| parse_filter_term(term).unwrap().matches(&info);
+
+        assert!(matches("width>1000"));
+        assert!(matches("h<=1080"));
+        assert!(!matches("height<1080"));
+        assert!(matches("size>2M"));
+        assert!(matches("landscape"));
+        assert!(!matches("orientation:portrait"));
+        assert!(matches("tag:CAT"));
+        assert!(matches("-tag:dog"));
+        assert!(matches("name:IMG_00"));
+
+        // Images of unknown size fail size checks, so negated checks pass
+        let unknown = ItemInfo::default();
+        assert!(!parse_filter_term("width>0").unwrap().matches(&unknown));
+        assert!(parse_filter_term("-width>0").unwrap().matches(&unknown));
+
+        assert!(search_matches(&info, "0042"));
+        assert!(search_matches(&info, "so"));
+        assert!(!search_matches(&info, "dog"));
+    }
+}
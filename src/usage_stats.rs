@@ -0,0 +1,242 @@
+// Opt-in usage statistics for `lsix stats --self`, kept as JSON in the
+// state directory and never sent anywhere: how many runs there were, how
+// many grid rows were rendered and how long that took, how often the SIXEL
+// cache had a row ready, and which flags are used most. They help tune the
+// cache and answer "how long does a row take for you?" in bug reports.
+// Only flag names are recorded, never their values or any paths.
+
+use crate::app_dirs::state_dir;
+use crate::test_mode;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Flags listed by `lsix stats --self`
+const TOP_FLAGS: usize = 10;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static RENDERS: AtomicU64 = AtomicU64::new(0);
+static RENDER_MICROS: AtomicU64 = AtomicU64::new(0);
+static CACHE_HITS: AtomicU64 = AtomicU64::new(0);
+static CACHE_MISSES: AtomicU64 = AtomicU64::new(0);
+
+/// Totals over every recorded run
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UsageStats {
+    /// Unix time of the first recorded run
+    pub since: i64,
+    pub runs: u64,
+    /// Grid rows rendered (cache misses)
+    pub renders: u64,
+    /// Time spent rendering them
+    pub render_micros: u64,
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+    /// Runs each flag was given in
+    pub flags: BTreeMap<String, u64>,
+}
+
+impl UsageStats {
+    /// What this process recorded, as one run made with `flags` at `now`
+    fn this_run(flags: &[String], now: i64) -> UsageStats {
+        UsageStats {
+            since: now,
+            runs: 1,
+            renders: RENDERS.load(Ordering::Relaxed),
+            render_micros: RENDER_MICROS.load(Ordering::Relaxed),
+            cache_hits: CACHE_HITS.load(Ordering::Relaxed),
+            cache_misses: CACHE_MISSES.load(Ordering::Relaxed),
+            flags: flags.iter().map(|flag| (flag.clone(), 1)).collect(),
+        }
+    }
+
+    /// Add the counts of `other`, recorded later
+    fn add(&mut self, other: &UsageStats) {
+        if self.runs == 0 {
+            self.since = other.since;
+        }
+        self.runs += other.runs;
+        self.renders += other.renders;
+        self.render_micros += other.render_micros;
+        self.cache_hits += other.cache_hits;
+        self.cache_misses += other.cache_misses;
+        for (flag, runs) in &other.flags {
+            *self.flags.entry(flag.clone()).or_default() += runs;
+        }
+    }
+
+    /// The statistics as `lsix stats --self` prints them
+    pub fn report(&self) -> String {
+        let since = chrono::DateTime::from_timestamp(self.since, 0)
+            .map(|t| test_mode::local(t).format("%Y-%m-%d").to_string())
+            .unwrap_or_default();
+        let mut report = format!("Runs:             {} since {}\n", self.runs, since);
+        report += &format!("Rows rendered:    {}\n", self.renders);
+        if self.renders > 0 {
+            let average = self.render_micros as f64 / self.renders as f64 / 1000.0;
+            report += &format!("Average render:   {:.1} ms per row\n", average);
+        }
+        let lookups = self.cache_hits + self.cache_misses;
+        if lookups > 0 {
+            report += &format!(
+                "SIXEL cache hits: {} of {} rows ({:.0}%)\n",
+                self.cache_hits,
+                lookups,
+                self.cache_hits as f64 * 100.0 / lookups as f64
+            );
+        }
+
+        let mut flags: Vec<(&String, &u64)> = self.flags.iter().collect();
+        flags.sort_by_key(|&(_, runs)| std::cmp::Reverse(*runs));
+        if !flags.is_empty() {
+            report += "Most used flags:\n";
+            for (flag, runs) in flags.into_iter().take(TOP_FLAGS) {
+                report += &format!("  {:<24} {}\n", flag, runs);
+            }
+        }
+        report
+    }
+}
+
+/// Record statistics for this run
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// A grid row rendered in `duration`
+pub fn record_render(duration: Duration) {
+    RENDERS.fetch_add(1, Ordering::Relaxed);
+    RENDER_MICROS.fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+}
+
+/// A grid row looked up in the SIXEL cache
+pub fn record_cache(hit: bool) {
+    let counter = if hit { &CACHE_HITS } else { &CACHE_MISSES };
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Names of the flags in `args` (`--tile-size=240` gives `--tile-size`,
+/// `-rv` gives `-r` and `-v`), each once; values and paths are left out
+pub fn flag_names(args: &[String]) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for arg in args {
+        if arg == "--" {
+            break;
+        }
+        let found: Vec<String> = if let Some(long) = arg.strip_prefix("--") {
+            vec![format!("--{}", long.split('=').next().unwrap_or_default())]
+        } else if let Some(short) = arg.strip_prefix('-') {
+            // Negative numbers are values
+            if short.starts_with(|c: char| c.is_ascii_digit()) {
+                continue;
+            }
+            short.chars().filter(char::is_ascii_alphabetic).map(|c| format!("-{}", c)).collect()
+        } else {
+            continue;
+        };
+        for name in found {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    names
+}
+
+fn stats_path() -> PathBuf {
+    state_dir().join("stats.json")
+}
+
+/// Load the statistics; a missing or unreadable file yields none
+pub fn load() -> UsageStats {
+    fs::read_to_string(stats_path())
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+/// Add this run, made with `args`, to the statistics file, if enabled
+pub fn save(args: &[String]) -> Result<()> {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+    let mut stats = load();
+    stats.add(&UsageStats::this_run(&flag_names(args), test_mode::now().timestamp()));
+
+    let path = stats_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).context("Failed to create state directory")?;
+    }
+    // Written whole and renamed, so concurrent runs never leave half a file
+    let tmp = path.with_extension(format!("json.{}", std::process::id()));
+    fs::write(&tmp, serde_json::to_string_pretty(&stats)?)
+        .with_context(|| format!("Failed to write {}", tmp.display()))?;
+    fs::rename(&tmp, &path).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(())
+}
+
+/// `lsix stats --self`: print the statistics, or with `reset` delete them
+pub fn run_stats(reset: bool) -> Result<()> {
+    let path = stats_path();
+    if reset {
+        match fs::remove_file(&path) {
+            Ok(()) => eprintln!("Removed {}", path.display()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e).with_context(|| format!("Failed to remove {}", path.display())),
+        }
+        return Ok(());
+    }
+
+    let stats = load();
+    if stats.runs == 0 {
+        eprintln!(
+            "No usage statistics recorded yet. They are kept only when enabled, \
+             with `[stats] enabled = true` in the config file or LSIX_STATS=1."
+        );
+        return Ok(());
+    }
+    print!("{}", stats.report());
+    println!("Kept in {}", path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usage_stats() {
+        let args: Vec<String> = ["photos/", "--tile-size=240", "-rv", "--colors", "64", "-r"]
+            .iter()
+            .map(|a| a.to_string())
+            .collect();
+        assert_eq!(flag_names(&args), ["--tile-size", "-r", "-v", "--colors"]);
+        assert_eq!(flag_names(&["--".to_string(), "-x".to_string()]), Vec::<String>::new());
+
+        let run = |renders, micros, hits, flags: &[&str]| UsageStats {
+            since: 1_700_000_000,
+            runs: 1,
+            renders,
+            render_micros: micros,
+            cache_hits: hits,
+            cache_misses: renders,
+            flags: flags.iter().map(|flag| (flag.to_string(), 1)).collect(),
+        };
+        let mut stats = UsageStats::default();
+        stats.add(&run(1, 4_000, 0, &["--tui"]));
+        stats.add(&UsageStats { since: 1_800_000_000, ..run(3, 6_000, 3, &["--tui", "-r"]) });
+        assert_eq!((stats.runs, stats.since), (2, 1_700_000_000));
+        assert_eq!(stats.flags["--tui"], 2);
+
+        let report = stats.report();
+        assert!(report.contains("2.5 ms per row"), "{}", report);
+        assert!(report.contains("3 of 7 rows (43%)"), "{}", report);
+        // Most used first
+        assert!(report.find("--tui").unwrap() < report.find("-r ").unwrap());
+    }
+}
@@ -0,0 +1,145 @@
+// Thumbnails for video files (mp4, mkv, webm, mov), so folders that mix
+// photos and clips can be previewed. ffmpeg extracts one representative
+// frame, picked by its thumbnail filter from the first couple of seconds
+// after a short seek past fade-ins, and the frame is kept in the cache
+// directory under a hash of the video's path, size and modification time.
+// Everything that decodes images through `open_image` then sees the frame;
+// the grid and the TUI mark the cell with a ▶ badge.
+//
+// Directory listings include videos only when ffmpeg is installed, so
+// folders don't fill with cells that can't be drawn; videos named on the
+// command line are always listed and fail with what to install.
+
+use crate::image_proc::get_cache_dir;
+use crate::tools::{self, Tool};
+use anyhow::{Context, Result};
+use image::DynamicImage;
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Extensions of the video files listed with the images
+pub const VIDEO_EXTENSIONS: [&str; 4] = ["mp4", "mkv", "webm", "mov"];
+
+/// Badge on the cells of videos
+pub const BADGE: &str = "▶";
+
+/// Longest width of an extracted frame, in pixels
+const FRAME_WIDTH: u32 = 960;
+
+/// Frames the thumbnail filter chooses among
+const SAMPLE_FRAMES: u32 = 48;
+
+/// Seconds skipped before sampling; clips shorter than this are sampled
+/// from the start
+const SEEK_SECONDS: &str = "1";
+
+/// Whether `ext` (without the dot) is a video extension
+pub fn is_video_extension(ext: &str) -> bool {
+    VIDEO_EXTENSIONS.contains(&ext.to_lowercase().as_str())
+}
+
+/// Whether the file at `path` is a video, by its extension
+pub fn is_video(path: &str) -> bool {
+    Path::new(path.trim_end_matches("[0]"))
+        .extension()
+        .is_some_and(|ext| is_video_extension(&ext.to_string_lossy()))
+}
+
+/// Whether directory listings include files with extension `ext` as
+/// videos: only when ffmpeg can draw them
+pub fn listed_in_directories(ext: &str) -> bool {
+    is_video_extension(ext) && tools::available(Tool::Ffmpeg)
+}
+
+/// Cache file for the frame of the video at `path`
+fn frame_cache_path(path: &str) -> Result<PathBuf> {
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    if let Ok(metadata) = fs::metadata(path) {
+        metadata.len().hash(&mut hasher);
+        if let Ok(modified) = metadata.modified() {
+            modified.hash(&mut hasher);
+        }
+    }
+    Ok(get_cache_dir()?.join("video_frames").join(format!("{:x}.png", hasher.finish())))
+}
+
+/// Write the representative frame of the video at `path` to the cache,
+/// unless it is there already, and return its path
+pub fn frame_path(path: &str) -> Result<PathBuf> {
+    let path = path.trim_end_matches("[0]");
+    let frame_path = frame_cache_path(path)?;
+    if frame_path.exists() {
+        return Ok(frame_path);
+    }
+
+    let png = extract_frame(path)?;
+    if let Some(parent) = frame_path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    // Write then rename so a concurrent reader never sees a partial file
+    let tmp = frame_path.with_extension(format!("{}.tmp", std::process::id()));
+    fs::write(&tmp, png).with_context(|| format!("Failed to write {}", tmp.display()))?;
+    fs::rename(&tmp, &frame_path)
+        .with_context(|| format!("Failed to write {}", frame_path.display()))?;
+    Ok(frame_path)
+}
+
+/// The representative frame of the video at `path`
+pub fn open_frame(path: &str) -> Result<DynamicImage> {
+    let frame_path = frame_path(path)?;
+    image::open(&frame_path).with_context(|| format!("Failed to decode the frame of {}", path))
+}
+
+/// ffmpeg's video filter: shrink first, so the sampled frames are small,
+/// then keep the most representative of them
+fn frame_filter() -> String {
+    format!("scale='min({},iw)':-2,thumbnail={}", FRAME_WIDTH, SAMPLE_FRAMES)
+}
+
+/// One frame of the video at `path` as PNG
+fn extract_frame(path: &str) -> Result<Vec<u8>> {
+    let ffmpeg = tools::require(Tool::Ffmpeg, "Video thumbnails")?;
+    for seek in [Some(SEEK_SECONDS), None] {
+        let mut command = Command::new(ffmpeg);
+        command.args(["-v", "error", "-nostdin"]);
+        if let Some(seek) = seek {
+            command.args(["-ss", seek]);
+        }
+        // The file: prefix keeps names with a colon from being read as a protocol
+        let output = command
+            .arg("-i")
+            .arg(format!("file:{}", path))
+            .args(["-an", "-vf", &frame_filter(), "-frames:v", "1"])
+            .args(["-f", "image2pipe", "-c:v", "png", "-"])
+            .stdin(Stdio::null())
+            .output()
+            .context("Failed to run ffmpeg")?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            anyhow::bail!("ffmpeg could not read {}: {}", path, stderr.trim());
+        }
+        // Seeking past the end of a short clip leaves no frame
+        if !output.stdout.is_empty() {
+            return Ok(output.stdout);
+        }
+    }
+    anyhow::bail!("{} has no video frames", path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_video() {
+        assert!(is_video("clips/beach.mp4") && is_video("IMG_0001.MOV"));
+        assert!(is_video("a.webm[0]"));
+        assert!(!is_video("beach.jpg") && !is_video("mp4"));
+        assert_eq!(frame_filter(), "scale='min(960,iw)':-2,thumbnail=48");
+    }
+}
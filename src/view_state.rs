@@ -0,0 +1,341 @@
+// Per-image fullscreen view state: fit mode, zoom, pan position, rotation
+// and mirroring.
+// States are kept for the whole TUI session so stepping back to an image
+// shows it the way it was left; with --remember-view they are also saved to
+// view_state.json in the state directory and restored next time.
+
+use crate::app_dirs::state_dir;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+/// Factor one zoom key press scales by
+const ZOOM_STEP: f32 = 1.25;
+/// Zoom limits, relative to the fit mode's scale
+const MIN_ZOOM: f32 = 0.25;
+const MAX_ZOOM: f32 = 16.0;
+
+/// How the fullscreen view scales the image to the screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FitMode {
+    /// Whole image visible
+    Fit,
+    /// Screen covered, overflow scrollable
+    Fill,
+    /// One image pixel per screen pixel
+    ActualSize,
+    /// Image width matches the screen
+    FitWidth,
+    /// Image height matches the screen
+    FitHeight,
+}
+
+impl FitMode {
+    /// Mode the fit key switches to
+    pub fn next(self) -> FitMode {
+        match self {
+            FitMode::Fit => FitMode::Fill,
+            FitMode::Fill => FitMode::ActualSize,
+            FitMode::ActualSize => FitMode::FitWidth,
+            FitMode::FitWidth => FitMode::FitHeight,
+            FitMode::FitHeight => FitMode::Fit,
+        }
+    }
+
+    /// Name shown in the status bar
+    pub fn label(self) -> &'static str {
+        match self {
+            FitMode::Fit => "fit",
+            FitMode::Fill => "fill",
+            FitMode::ActualSize => "1:1",
+            FitMode::FitWidth => "fit width",
+            FitMode::FitHeight => "fit height",
+        }
+    }
+}
+
+/// How one image is shown in fullscreen
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ViewState {
+    pub fit_mode: FitMode,
+    /// Magnification on top of the fit mode, 1.0 = none
+    #[serde(default = "no_zoom")]
+    pub zoom: f32,
+    /// Visible part of a cropped image on each axis, 0.5 = centered
+    pub pan: (f32, f32),
+    /// Clockwise quarter turns, 0 to 3
+    pub rotation: u8,
+    /// Mirrored left to right before the rotation is applied
+    #[serde(default)]
+    pub flip: bool,
+}
+
+/// Zoom of states saved before there was zoom
+fn no_zoom() -> f32 {
+    1.0
+}
+
+impl Default for ViewState {
+    fn default() -> Self {
+        ViewState {
+            fit_mode: FitMode::Fit,
+            zoom: 1.0,
+            pan: (0.5, 0.5),
+            rotation: 0,
+            flip: false,
+        }
+    }
+}
+
+impl ViewState {
+    /// Switch to the next fit mode, starting centered and unzoomed
+    pub fn cycle_fit(&mut self) {
+        self.fit_mode = self.fit_mode.next();
+        self.zoom = 1.0;
+        self.pan = (0.5, 0.5);
+    }
+
+    /// Zoom in (`steps` > 0) or out by whole steps, keeping the pan
+    /// position so the same part of the image stays in view
+    pub fn zoom_by(&mut self, steps: i32) {
+        self.zoom = (self.zoom * ZOOM_STEP.powi(steps)).clamp(MIN_ZOOM, MAX_ZOOM);
+        // Snap back to exactly 1.0 after zooming in and out again
+        if (self.zoom - 1.0).abs() < 1e-3 {
+            self.zoom = 1.0;
+        }
+    }
+
+    /// Back to the whole image, fitted and centered; rotation and
+    /// mirroring stay
+    pub fn reset(&mut self) {
+        *self = ViewState { rotation: self.rotation, flip: self.flip, ..ViewState::default() };
+    }
+
+    /// Fit mode and zoom for the status bar, e.g. "fit" or "fill 156%"
+    pub fn scale_label(&self) -> String {
+        if self.zoom == 1.0 {
+            self.fit_mode.label().to_string()
+        } else {
+            format!("{} {:.0}%", self.fit_mode.label(), self.zoom * 100.0)
+        }
+    }
+
+    /// Turn the image a quarter clockwise
+    pub fn rotate(&mut self) {
+        self.rotation = (self.rotation + 1) % 4;
+        self.pan = (0.5, 0.5);
+    }
+
+    /// Turn the image a quarter counterclockwise
+    pub fn rotate_back(&mut self) {
+        self.rotation = (self.rotation + 3) % 4;
+        self.pan = (0.5, 0.5);
+    }
+
+    /// Mirror the image as shown left to right. Mirroring a turned image
+    /// equals mirroring first and turning the other way.
+    pub fn mirror(&mut self) {
+        self.flip = !self.flip;
+        self.rotation = (4 - self.rotation) % 4;
+        self.pan = (0.5, 0.5);
+    }
+
+    /// Whether the image is shown rotated or mirrored
+    pub fn is_turned(&self) -> bool {
+        self.rotation != 0 || self.flip
+    }
+
+    /// Status bar hint for the rotation and mirroring, e.g. "90° mirrored"
+    pub fn turn_label(&self) -> String {
+        match (self.rotation, self.flip) {
+            (0, false) => String::new(),
+            (0, true) => "mirrored".to_string(),
+            (turns, flip) => {
+                format!("{}°{}", turns as u32 * 90, if flip { " mirrored" } else { "" })
+            }
+        }
+    }
+
+    /// Scroll a cropped image by a tenth of its hidden part
+    pub fn pan_by(&mut self, dx: f32, dy: f32) {
+        self.pan.0 = (self.pan.0 + dx * 0.1).clamp(0.0, 1.0);
+        self.pan.1 = (self.pan.1 + dy * 0.1).clamp(0.0, 1.0);
+    }
+
+    /// Apply the mirroring and rotation to `image`
+    pub fn rotated(&self, image: &image::DynamicImage) -> image::DynamicImage {
+        let flipped;
+        let image = if self.flip {
+            flipped = image.fliph();
+            &flipped
+        } else {
+            image
+        };
+        match self.rotation {
+            1 => image.rotate90(),
+            2 => image.rotate180(),
+            3 => image.rotate270(),
+            _ => image.clone(),
+        }
+    }
+}
+
+/// View states by image path
+#[derive(Debug, Default)]
+pub struct ViewStates {
+    states: HashMap<String, ViewState>,
+    /// Save to disk on exit (--remember-view)
+    persistent: bool,
+}
+
+fn view_state_path() -> PathBuf {
+    state_dir().join("view_state.json")
+}
+
+impl ViewStates {
+    /// States saved by earlier sessions; saved again by `save`. A missing or
+    /// unreadable file starts empty.
+    pub fn load() -> ViewStates {
+        let states = fs::read_to_string(view_state_path())
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        ViewStates {
+            states,
+            persistent: true,
+        }
+    }
+
+    /// State of `path`, the default view if it was never changed
+    pub fn get(&self, path: &str) -> ViewState {
+        self.states.get(path).copied().unwrap_or_default()
+    }
+
+    /// Remember the view of `path`; default views aren't stored
+    pub fn set(&mut self, path: &str, state: ViewState) {
+        if state == ViewState::default() {
+            self.states.remove(path);
+        } else {
+            self.states.insert(path.to_string(), state);
+        }
+    }
+
+    /// Carry a state over to a renamed file
+    pub fn rename(&mut self, old: &str, new: &str) {
+        if let Some(state) = self.states.remove(old) {
+            self.states.insert(new.to_string(), state);
+        }
+    }
+
+    /// Write the states to disk if they were loaded from there
+    pub fn save(&self) -> Result<()> {
+        if !self.persistent {
+            return Ok(());
+        }
+        let path = view_state_path();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let json = serde_json::to_string(&self.states)?;
+        fs::write(&path, json).with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fit_mode_cycle() {
+        let mut mode = FitMode::Fit;
+        let mut labels = Vec::new();
+        for _ in 0..5 {
+            labels.push(mode.label());
+            mode = mode.next();
+        }
+        assert_eq!(mode, FitMode::Fit);
+        assert_eq!(labels, ["fit", "fill", "1:1", "fit width", "fit height"]);
+    }
+
+    #[test]
+    fn test_view_states() {
+        let mut states = ViewStates::default();
+        let mut view = ViewState::default();
+        view.cycle_fit();
+        view.pan_by(-1.0, 0.0);
+        view.rotate();
+        states.set("a.jpg", view);
+
+        assert_eq!(states.get("a.jpg"), view);
+        assert_eq!(states.get("b.jpg"), ViewState::default());
+
+        states.rename("a.jpg", "c.jpg");
+        assert_eq!(states.get("c.jpg"), view);
+
+        // Going back to the default view forgets the image
+        states.set("c.jpg", ViewState::default());
+        assert!(states.states.is_empty());
+    }
+
+    #[test]
+    fn test_zoom() {
+        let mut view = ViewState::default();
+        view.zoom_by(2);
+        assert_eq!(view.scale_label(), "fit 156%");
+        view.zoom_by(-2);
+        assert_eq!(view.zoom, 1.0);
+        view.zoom_by(-100);
+        assert_eq!(view.zoom, MIN_ZOOM);
+
+        view.zoom_by(3);
+        view.pan_by(1.0, 0.0);
+        view.rotate();
+        view.reset();
+        assert_eq!(view, ViewState { rotation: 1, ..ViewState::default() });
+
+        // States saved before zooming existed load unzoomed
+        let old: ViewState =
+            serde_json::from_str(r#"{"fit_mode":"fill","pan":[0.5,0.5],"rotation":0}"#).unwrap();
+        assert_eq!(old.zoom, 1.0);
+    }
+
+    #[test]
+    fn test_rotate_and_mirror() {
+        let image = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(3, 2, |x, y| {
+            image::Rgb([x as u8, y as u8, 0])
+        }));
+        let mut view = ViewState::default();
+        view.rotate_back();
+        assert_eq!(view.rotation, 3);
+        assert_eq!(view.turn_label(), "270°");
+
+        // Mirroring what is shown, whatever the rotation
+        let shown = view.rotated(&image).fliph();
+        view.mirror();
+        assert_eq!(view.rotated(&image), shown);
+        assert_eq!((view.rotation, view.flip), (1, true));
+        assert_eq!(view.turn_label(), "90° mirrored");
+
+        view.mirror();
+        view.rotate();
+        assert!(!view.is_turned());
+    }
+
+    #[test]
+    fn test_view_state_json() {
+        let view = ViewState {
+            fit_mode: FitMode::ActualSize,
+            zoom: 2.5,
+            pan: (0.25, 1.0),
+            rotation: 3,
+            flip: true,
+        };
+        let json = serde_json::to_string(&view).unwrap();
+        assert!(json.contains("\"actual-size\""));
+        assert_eq!(serde_json::from_str::<ViewState>(&json).unwrap(), view);
+    }
+}
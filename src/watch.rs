@@ -0,0 +1,317 @@
+// --watch: follow the directories being shown and report which images were
+// added, changed or removed. Events are collected until the directory has
+// been quiet for a moment, so a file that is still being copied or written
+// by a camera import comes through once, after its last write.
+//
+// The grid and --output json are produced again from scratch on every
+// change; the TUI applies the changes to the images it is browsing. The
+// grid is also drawn again when the terminal is resized (SIGWINCH), once
+// the resizing stops, so it fits the new width.
+
+use crate::image_proc::IMAGE_EXTENSIONS;
+use crate::paths::canonicalize_path;
+use crate::video_thumbs;
+// The notify crate, not crate::notify (completion notices)
+use ::notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use anyhow::{Context, Result};
+use std::collections::{BTreeSet, HashSet};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant};
+
+/// How long a directory must be quiet before its changes are reported
+const SETTLE_TIME: Duration = Duration::from_millis(500);
+
+/// How long the terminal must keep its size before a resize is reported
+const RESIZE_SETTLE_TIME: Duration = Duration::from_millis(200);
+
+/// Directories to watch for `inputs`: the directories among them, or the
+/// current directory when there are none. Single files and URLs are not
+/// watched.
+pub fn watch_roots(inputs: &[String], resolve_symlinks: bool) -> Vec<String> {
+    if inputs.is_empty() {
+        return vec![canonicalize_path(".", resolve_symlinks)];
+    }
+    let mut seen = HashSet::new();
+    inputs
+        .iter()
+        .filter(|input| Path::new(input).is_dir())
+        .map(|dir| canonicalize_path(dir, resolve_symlinks))
+        .filter(|dir| seen.insert(dir.clone()))
+        .collect()
+}
+
+/// Whether an event for `path` concerns an image (or video) lsix would list
+fn is_image_path(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| {
+        let ext = ext.to_string_lossy().to_lowercase();
+        IMAGE_EXTENSIONS.contains(&ext.as_str()) || video_thumbs::listed_in_directories(&ext)
+    })
+}
+
+/// Images changed since the last report, split by what happened to them
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Changes {
+    pub added: Vec<String>,
+    pub modified: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl Changes {
+    /// Sort `touched` paths by whether they exist now and whether they
+    /// were `known` (shown) before
+    pub fn classify(touched: &BTreeSet<String>, known: impl Fn(&str) -> bool) -> Changes {
+        let mut changes = Changes::default();
+        for path in touched {
+            match (known(path), Path::new(path).exists()) {
+                (true, true) => changes.modified.push(path.clone()),
+                (true, false) => changes.removed.push(path.clone()),
+                (false, true) => changes.added.push(path.clone()),
+                // Created and removed again before it settled
+                (false, false) => {}
+            }
+        }
+        changes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.modified.is_empty() && self.removed.is_empty()
+    }
+
+    /// One-line summary, e.g. "2 added, 1 removed"
+    pub fn summary(&self) -> String {
+        let parts: Vec<String> = [
+            (self.added.len(), "added"),
+            (self.modified.len(), "changed"),
+            (self.removed.len(), "removed"),
+        ]
+        .iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, what)| format!("{} {}", count, what))
+        .collect();
+        parts.join(", ")
+    }
+}
+
+/// What the watcher hears about
+enum Message {
+    Files(::notify::Result<Event>),
+    /// The terminal was resized
+    Resized,
+}
+
+/// Why `DirWatcher::wait` returned
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Wake {
+    /// These image paths were touched
+    Changed(BTreeSet<String>),
+    /// The terminal has a new size
+    Resized,
+}
+
+/// Watches directories for changes to image files
+pub struct DirWatcher {
+    // Events stop when the watcher is dropped
+    _watcher: RecommendedWatcher,
+    tx: Sender<Message>,
+    rx: Receiver<Message>,
+    /// Paths touched since the last report
+    pending: BTreeSet<String>,
+    last_event: Option<Instant>,
+    /// Terminal size when the resize was last reported, once resizes are
+    /// followed
+    size: Option<(u16, u16)>,
+    last_resize: Option<Instant>,
+}
+
+impl DirWatcher {
+    /// Start watching `roots`, and their subdirectories with `recursive`
+    pub fn new(roots: &[String], recursive: bool) -> Result<DirWatcher> {
+        if roots.is_empty() {
+            anyhow::bail!("--watch needs a directory to watch");
+        }
+        let (tx, rx) = mpsc::channel();
+        let events = tx.clone();
+        let mut watcher = ::notify::recommended_watcher(move |event| {
+            events.send(Message::Files(event)).ok();
+        })
+        .context("Failed to start the file watcher")?;
+        let mode = if recursive { RecursiveMode::Recursive } else { RecursiveMode::NonRecursive };
+        for root in roots {
+            watcher
+                .watch(Path::new(root), mode)
+                .with_context(|| format!("Failed to watch {}", root))?;
+        }
+        Ok(DirWatcher {
+            _watcher: watcher,
+            tx,
+            rx,
+            pending: BTreeSet::new(),
+            last_event: None,
+            size: None,
+            last_resize: None,
+        })
+    }
+
+    /// Also wake `wait` when the terminal is resized. Only Unix terminals
+    /// announce it.
+    pub fn follow_resizes(&mut self) {
+        self.size = crossterm::terminal::size().ok();
+        #[cfg(unix)]
+        {
+            let tx = self.tx.clone();
+            std::thread::spawn(move || forward_resizes(tx));
+        }
+    }
+
+    /// Note one message
+    fn record(&mut self, message: Message) {
+        let event = match message {
+            Message::Files(event) => event,
+            Message::Resized => {
+                self.last_resize = Some(Instant::now());
+                return;
+            }
+        };
+        let Ok(event) = event else {
+            return;
+        };
+        if matches!(event.kind, EventKind::Access(_)) {
+            return;
+        }
+        for path in event.paths.iter().filter(|p| is_image_path(p)) {
+            self.pending.insert(path.to_string_lossy().to_string());
+            self.last_event = Some(Instant::now());
+        }
+    }
+
+    /// Image paths touched since the last call, once the directories have
+    /// settled; empty while nothing changed or changes are still coming in
+    pub fn poll(&mut self) -> BTreeSet<String> {
+        while let Ok(event) = self.rx.try_recv() {
+            self.record(event);
+        }
+        match self.last_event {
+            Some(last) if last.elapsed() >= SETTLE_TIME => {
+                self.last_event = None;
+                std::mem::take(&mut self.pending)
+            }
+            _ => BTreeSet::new(),
+        }
+    }
+
+    /// Whether the terminal was resized and has kept its new size for a
+    /// moment
+    fn resize_settled(&mut self) -> bool {
+        match self.last_resize {
+            Some(last) if last.elapsed() >= RESIZE_SETTLE_TIME => {
+                self.last_resize = None;
+                let size = crossterm::terminal::size().ok();
+                // Resized back, or a signal without a change
+                if size == self.size {
+                    return false;
+                }
+                self.size = size;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Block until image files change and the directories settle, or the
+    /// terminal is resized (with `follow_resizes`)
+    pub fn wait(&mut self) -> Result<Wake> {
+        loop {
+            let timeout = [
+                self.last_event.map(|last| SETTLE_TIME.saturating_sub(last.elapsed())),
+                self.last_resize.map(|last| RESIZE_SETTLE_TIME.saturating_sub(last.elapsed())),
+            ]
+            .into_iter()
+            .flatten()
+            .min()
+            .unwrap_or(Duration::from_secs(3600));
+            match self.rx.recv_timeout(timeout) {
+                Ok(message) => self.record(message),
+                Err(RecvTimeoutError::Timeout) => {
+                    let touched = self.poll();
+                    if !touched.is_empty() {
+                        return Ok(Wake::Changed(touched));
+                    }
+                    if self.resize_settled() {
+                        return Ok(Wake::Resized);
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => anyhow::bail!("The file watcher stopped"),
+            }
+        }
+    }
+}
+
+/// Send a message for every SIGWINCH, for as long as the watcher lives
+#[cfg(unix)]
+fn forward_resizes(tx: Sender<Message>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let Ok(runtime) = tokio::runtime::Builder::new_current_thread().enable_all().build() else {
+        return;
+    };
+    runtime.block_on(async {
+        let Ok(mut resizes) = signal(SignalKind::window_change()) else {
+            return;
+        };
+        while resizes.recv().await.is_some() {
+            if tx.send(Message::Resized).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn test_classify_changes() {
+        let dir = TestDir::new("watch");
+        let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+        std::fs::write(path("new.jpg"), b"").unwrap();
+        std::fs::write(path("edited.jpg"), b"").unwrap();
+
+        let touched: BTreeSet<String> =
+            ["new.jpg", "edited.jpg", "gone.jpg", "flash.jpg"].iter().map(|n| path(n)).collect();
+        let known = [path("edited.jpg"), path("gone.jpg")];
+        let changes = Changes::classify(&touched, |p| known.iter().any(|k| k == p));
+        assert_eq!(changes.added, [path("new.jpg")]);
+        assert_eq!(changes.modified, [path("edited.jpg")]);
+        assert_eq!(changes.removed, [path("gone.jpg")]);
+        assert_eq!(changes.summary(), "1 added, 1 changed, 1 removed");
+    }
+
+    #[test]
+    fn test_watcher_reports_new_images() {
+        let dir = TestDir::new("watch-new");
+        let root = canonicalize_path(&dir.to_string_lossy(), true);
+        let mut watcher = DirWatcher::new(std::slice::from_ref(&root), false).unwrap();
+
+        std::fs::write(Path::new(&root).join("notes.txt"), b"ignored").unwrap();
+        std::fs::write(Path::new(&root).join("shot.png"), b"").unwrap();
+        // Cameras name their files in upper case
+        std::fs::write(Path::new(&root).join("IMG_0001.JPG"), b"").unwrap();
+        let touched = BTreeSet::from([
+            format!("{}/IMG_0001.JPG", root),
+            format!("{}/shot.png", root),
+        ]);
+        assert_eq!(watcher.wait().unwrap(), Wake::Changed(touched));
+
+        // A resize is reported once the size differs from the last one
+        watcher.size = Some((0, 0));
+        watcher.tx.send(Message::Resized).unwrap();
+        assert_eq!(watcher.wait().unwrap(), Wake::Resized);
+        watcher.tx.send(Message::Resized).unwrap();
+        std::fs::write(Path::new(&root).join("late.png"), b"").unwrap();
+        let touched = BTreeSet::from([format!("{}/late.png", root)]);
+        assert_eq!(watcher.wait().unwrap(), Wake::Changed(touched));
+    }
+}
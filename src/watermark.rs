@@ -0,0 +1,196 @@
+// Watermark stamping (--watermark): a logo image or a short text such as
+// "(DRAFT)" composited into a corner of every thumbnail and of exported
+// time-lapse frames. The mark is sized relative to the image it lands on,
+// so it reads the same on small tiles and large frames.
+
+use crate::image_proc::open_image;
+use crate::montage::draw_text;
+use anyhow::{Context, Result};
+use embedded_graphics::mono_font::ascii::FONT_10X20;
+use image::{imageops, Rgba, RgbaImage};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+
+/// Opacity used when none is given
+pub const DEFAULT_OPACITY: f32 = 0.5;
+
+/// Largest share of the image width or height the mark may cover
+const MAX_FRACTION: f32 = 0.25;
+
+/// Where the mark is placed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WatermarkPosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    Center,
+}
+
+/// Parse watermark position from string
+pub fn parse_watermark_position(s: &str) -> Result<WatermarkPosition> {
+    match s.to_lowercase().as_str() {
+        "tl" | "top-left" => Ok(WatermarkPosition::TopLeft),
+        "tr" | "top-right" => Ok(WatermarkPosition::TopRight),
+        "bl" | "bottom-left" => Ok(WatermarkPosition::BottomLeft),
+        "br" | "bottom-right" => Ok(WatermarkPosition::BottomRight),
+        "c" | "center" => Ok(WatermarkPosition::Center),
+        _ => anyhow::bail!("Invalid watermark position: {}. Use: tl, tr, bl, br or center", s),
+    }
+}
+
+/// A decoded logo or rendered text, ready to be stamped onto images
+#[derive(Debug, Clone)]
+pub struct Watermark {
+    /// Image path or text the mark was made from
+    spec: String,
+    /// The mark at its natural size
+    mark: RgbaImage,
+    /// Text marks are bitmap glyphs and may be enlarged; logos only shrink
+    is_text: bool,
+    pub position: WatermarkPosition,
+    /// 0.0 (invisible) to 1.0 (opaque)
+    pub opacity: f32,
+}
+
+impl Watermark {
+    /// Load the mark from `spec`: an existing image file is used as a logo,
+    /// anything else is drawn as text. A missing file with an image
+    /// extension is an error rather than a text mark.
+    pub fn load(spec: &str, position: WatermarkPosition, opacity: f32) -> Result<Watermark> {
+        if !(0.0..=1.0).contains(&opacity) {
+            anyhow::bail!("Watermark opacity must be between 0.0 and 1.0");
+        }
+
+        let path = Path::new(spec);
+        let (mark, is_text) = if path.is_file() {
+            let logo = open_image(spec)
+                .with_context(|| format!("Failed to decode watermark {}", spec))?;
+            (logo.to_rgba8(), false)
+        } else if image::ImageFormat::from_path(path).is_ok() {
+            anyhow::bail!("Watermark image not found: {}", spec);
+        } else if spec.trim().is_empty() {
+            anyhow::bail!("Watermark text is empty");
+        } else {
+            (render_text(spec.trim()), true)
+        };
+
+        Ok(Watermark {
+            spec: spec.to_string(),
+            mark,
+            is_text,
+            position,
+            opacity,
+        })
+    }
+
+    /// Identifies the mark's look, for caches of stamped images
+    pub fn cache_key(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.spec.hash(&mut hasher);
+        self.position.hash(&mut hasher);
+        self.opacity.to_bits().hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Stamp the mark onto `image`
+    pub fn apply(&self, image: &mut RgbaImage) {
+        let (width, height) = image.dimensions();
+        let (mark_width, mark_height) = self.mark.dimensions();
+        if width == 0 || height == 0 || mark_width == 0 || mark_height == 0 {
+            return;
+        }
+
+        let scale = (width as f32 * MAX_FRACTION / mark_width as f32)
+            .min(height as f32 * MAX_FRACTION / mark_height as f32);
+        let scale = if self.is_text { scale } else { scale.min(1.0) };
+        let scaled_width = ((mark_width as f32 * scale).round() as u32).max(1);
+        let scaled_height = ((mark_height as f32 * scale).round() as u32).max(1);
+        let filter = if self.is_text {
+            imageops::FilterType::Nearest
+        } else {
+            imageops::FilterType::Triangle
+        };
+        let mut mark = imageops::resize(&self.mark, scaled_width, scaled_height, filter);
+        for pixel in mark.pixels_mut() {
+            pixel[3] = (pixel[3] as f32 * self.opacity).round() as u8;
+        }
+
+        let (x, y) = self.origin((width, height), (scaled_width, scaled_height));
+        imageops::overlay(image, &mark, x as i64, y as i64);
+    }
+
+    /// Top left corner of a `mark` sized mark on an `image` sized image
+    fn origin(&self, image: (u32, u32), mark: (u32, u32)) -> (u32, u32) {
+        let margin = (image.0.min(image.1) / 32).max(2);
+        let left = margin;
+        let top = margin;
+        let right = image.0.saturating_sub(mark.0 + margin);
+        let bottom = image.1.saturating_sub(mark.1 + margin);
+        match self.position {
+            WatermarkPosition::TopLeft => (left, top),
+            WatermarkPosition::TopRight => (right, top),
+            WatermarkPosition::BottomLeft => (left, bottom),
+            WatermarkPosition::BottomRight => (right, bottom),
+            WatermarkPosition::Center => (
+                image.0.saturating_sub(mark.0) / 2,
+                image.1.saturating_sub(mark.1) / 2,
+            ),
+        }
+    }
+}
+
+/// Draw `text` in white with a dark outline on a transparent canvas, so it
+/// stays readable on both light and dark images
+fn render_text(text: &str) -> RgbaImage {
+    let font = &FONT_10X20;
+    let lines: Vec<&str> = text.lines().collect();
+    let columns = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0) as u32;
+    let mut canvas = RgbaImage::new(
+        columns * font.character_size.width + 2,
+        lines.len() as u32 * font.character_size.height + 2,
+    );
+
+    for (i, line) in lines.iter().enumerate() {
+        let y = 1 + i as u32 * font.character_size.height;
+        for (dx, dy) in [(0, 0), (2, 0), (0, 2), (2, 2), (1, 0), (0, 1), (2, 1), (1, 2)] {
+            draw_text(&mut canvas, line, dx, y - 1 + dy, font, Rgba([0, 0, 0, 255]));
+        }
+        draw_text(&mut canvas, line, 1, y, font, Rgba([255, 255, 255, 255]));
+    }
+
+    canvas
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_watermark_position() {
+        assert_eq!(parse_watermark_position("br").unwrap(), WatermarkPosition::BottomRight);
+        assert_eq!(parse_watermark_position("Top-Left").unwrap(), WatermarkPosition::TopLeft);
+        assert_eq!(parse_watermark_position("center").unwrap(), WatermarkPosition::Center);
+        assert!(parse_watermark_position("middle").is_err());
+    }
+
+    #[test]
+    fn test_text_watermark_placement() {
+        let mark = Watermark::load("(DRAFT)", WatermarkPosition::BottomRight, 1.0).unwrap();
+        assert!(Watermark::load("missing-logo.png", WatermarkPosition::Center, 0.5).is_err());
+        assert!(Watermark::load("(DRAFT)", WatermarkPosition::Center, 1.5).is_err());
+
+        let mut image = RgbaImage::from_pixel(400, 300, Rgba([128, 128, 128, 255]));
+        mark.apply(&mut image);
+
+        // Only the bottom right quadrant changes
+        let changed: Vec<(u32, u32)> = image
+            .enumerate_pixels()
+            .filter(|(_, _, p)| p.0 != [128, 128, 128, 255])
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        assert!(!changed.is_empty());
+        assert!(changed.iter().all(|&(x, y)| x >= 200 && y >= 150));
+    }
+}